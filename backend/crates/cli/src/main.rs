@@ -0,0 +1,772 @@
+use chrono::{DateTime, Utc};
+use dissipate_core::{
+    config::Config,
+    db,
+    export_format::ExportFormat,
+    ids::UserId,
+    models::{self, AuditEvent, IntegrityResponse, SuccessResponse, User},
+    utils::hash_password,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+    process::ExitCode,
+};
+
+/// Rows written per transaction by `purge`, `trash-empty` and `seed`, so a
+/// running server only waits briefly for the write lock
+const BATCH_SIZE: i64 = 500;
+/// Default `--older-than` for `trash-empty`
+const TRASH_RETENTION_DAYS: i64 = 90;
+/// Actor recorded in the audit log for changes made here
+const AUDIT_ACTOR: &str = "manage_users";
+
+/// Where results go: text for people, or with `--json` one JSON document
+/// on stdout for provisioning scripts
+#[derive(Clone, Copy)]
+struct Output {
+    json: bool,
+}
+
+impl Output {
+    /// Progress notes, left out of JSON output
+    fn note(self, message: &str) {
+        if !self.json {
+            println!("{}", message);
+        }
+    }
+
+    fn success<T: Serialize>(self, value: &T, message: &str) -> ExitCode {
+        if self.json {
+            match serde_json::to_string_pretty(value) {
+                Ok(json) => println!("{}", json),
+                Err(e) => return self.failure(&format!("Error encoding output: {}", e)),
+            }
+        } else {
+            println!("{}", message);
+        }
+        ExitCode::SUCCESS
+    }
+
+    fn failure(self, message: &str) -> ExitCode {
+        if self.json {
+            println!("{}", serde_json::json!({ "error": message }));
+        } else {
+            println!("{}", message);
+        }
+        ExitCode::FAILURE
+    }
+
+    /// Wrong arguments; exits 2 so scripts can tell it from a failed command
+    fn usage(self, usage: &str) -> ExitCode {
+        self.failure(&format!("Usage: manage_users {}", usage));
+        ExitCode::from(2)
+    }
+
+    fn aborted(self) -> ExitCode {
+        self.failure("Aborted; nothing was changed. Pass --yes to skip the prompt.")
+    }
+}
+
+/// Flags for destructive commands, accepted anywhere like `--json`
+#[derive(Clone, Copy)]
+struct Flags {
+    /// `--yes`/`-y`: don't ask for confirmation
+    yes: bool,
+    /// `--dry-run`: report what would be deleted and stop
+    dry_run: bool,
+}
+
+impl Flags {
+    /// Ask on the terminal before deleting anything, unless `--yes` was given
+    ///
+    /// Without a terminal, as in scripts, only `--yes` confirms. The prompt
+    /// goes to stderr so it never mixes with `--json` output.
+    fn confirm(self, question: &str) -> io::Result<bool> {
+        if self.yes {
+            return Ok(true);
+        }
+        if !io::stdin().is_terminal() {
+            return Ok(false);
+        }
+        eprint!("{} [y/N] ", question);
+        io::stderr().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    // Load .env if available
+    dotenvy::dotenv().ok();
+
+    let mut args: Vec<String> = env::args().collect();
+    // Accepted anywhere, so `--json list` and `list --json` both work
+    let has_flag = |names: &[&str]| args.iter().any(|arg| names.contains(&arg.as_str()));
+    let out = Output {
+        json: has_flag(&["--json"]),
+    };
+    let flags = Flags {
+        yes: has_flag(&["--yes", "-y"]),
+        dry_run: has_flag(&["--dry-run"]),
+    };
+    args.retain(|arg| !["--json", "--yes", "-y", "--dry-run"].contains(&arg.as_str()));
+    if args.len() < 2 {
+        print_usage();
+        return ExitCode::from(2);
+    }
+
+    match run(out, flags, &args).await {
+        Ok(code) => code,
+        Err(e) => out.failure(&format!("Error: {:#}", e)),
+    }
+}
+
+async fn run(out: Output, flags: Flags, args: &[String]) -> anyhow::Result<ExitCode> {
+    let config = Config::load(None, |name| env::var(name).ok()).map_err(anyhow::Error::msg)?;
+
+    // Connect to DB
+    let db_options = db::DbOptions::from_config(&config.database).map_err(anyhow::Error::msg)?;
+    let pool = db::init_pool_with(&config.database.url, &db_options).await?;
+
+    let command = &args[1];
+
+    let code = match command.as_str() {
+        "list" => {
+            let users = db::list_users(&pool).await?;
+            if out.json {
+                let users: Vec<_> = users.iter().map(User::to_admin).collect();
+                return Ok(out.success(&users, ""));
+            }
+            if users.is_empty() {
+                println!("No users found.");
+            } else {
+                println!(
+                    "{:<36} | {:<30} | {:<20} | {:<5} | {:<8}",
+                    "ID", "Email", "Username", "Role", "Status"
+                );
+                println!("{}", "-".repeat(109));
+                for user in users {
+                    let status = if user.disabled { "disabled" } else { "active" };
+                    println!(
+                        "{:<36} | {:<30} | {:<20} | {:<5} | {:<8}",
+                        user.id, user.email, user.username, user.role, status
+                    );
+                }
+            }
+            ExitCode::SUCCESS
+        }
+        "add" => {
+            if args.len() != 5 {
+                return Ok(out.usage("add <email> <username> <password>"));
+            }
+            let email = &args[2];
+            let username = &args[3];
+            let password = &args[4];
+
+            out.note(&format!("Adding user: {}", email));
+
+            let (hash, salt) =
+                hash_password(password).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+            let user = User::new(email.clone(), username.clone(), hash, salt);
+
+            match db::create_user(&pool, &user).await {
+                Ok(_) => out.success(&user.to_admin(), "User added successfully."),
+                Err(e) => out.failure(&format!("Error adding user: {}", e)),
+            }
+        }
+        "check" => {
+            let report = db::check_database(&pool).await?;
+            let code = if report.is_ok() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            };
+            if out.json {
+                out.success(
+                    &IntegrityResponse {
+                        ok: report.is_ok(),
+                        integrity_errors: report.integrity_errors,
+                        orphaned_rows: report.orphaned_rows,
+                        row_counts: report.row_counts,
+                    },
+                    "",
+                );
+                return Ok(code);
+            }
+
+            if report.integrity_errors.is_empty() {
+                println!("Integrity check: ok");
+            } else {
+                println!(
+                    "Integrity check found {} problem(s):",
+                    report.integrity_errors.len()
+                );
+                for error in &report.integrity_errors {
+                    println!("  {}", error);
+                }
+            }
+
+            if report.orphaned_rows.is_empty() {
+                println!("Orphaned rows: none");
+            } else {
+                println!("Orphaned rows:");
+                for (table, count) in &report.orphaned_rows {
+                    println!("  {:<20} {}", table, count);
+                }
+            }
+
+            println!("Row counts:");
+            for (table, count) in &report.row_counts {
+                println!("  {:<20} {}", table, count);
+            }
+
+            code
+        }
+        "seed" => seed(out, &pool, &args[2..]).await?,
+        "stats" => {
+            let instance = db::instance_stats(&pool, 1).await?;
+            let storage = db::storage_stats(&pool).await?;
+            let wal_bytes = db::wal_size_bytes(&pool).await?;
+            let message_count: i64 = storage
+                .messages_per_user
+                .iter()
+                .map(|(_, count)| count)
+                .sum();
+            let healthy = storage
+                .indexes
+                .iter()
+                .all(|index| index.problems.is_empty());
+            let code = if healthy {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            };
+
+            if out.json {
+                let report = json!({
+                    "user_count": instance.user_count,
+                    "message_count": message_count,
+                    "messages_per_user": storage
+                        .messages_per_user
+                        .iter()
+                        .map(|(email, count)| json!({ "email": email, "messages": count }))
+                        .collect::<Vec<_>>(),
+                    "database_bytes": instance.database_bytes,
+                    "free_bytes": storage.free_bytes,
+                    "wal_bytes": wal_bytes,
+                    "indexes": storage
+                        .indexes
+                        .iter()
+                        .map(|index| json!({
+                            "table": index.table,
+                            "name": index.name,
+                            "problems": index.problems,
+                        }))
+                        .collect::<Vec<_>>(),
+                });
+                out.success(&report, "");
+                return Ok(code);
+            }
+
+            println!("Users:     {}", instance.user_count);
+            println!("Messages:  {}", message_count);
+            println!(
+                "Database:  {} ({} free, reclaimed by VACUUM)",
+                format_bytes(instance.database_bytes as u64),
+                format_bytes(storage.free_bytes as u64)
+            );
+            match wal_bytes {
+                Some(bytes) => println!("WAL:       {}", format_bytes(bytes)),
+                None => println!("WAL:       none (in-memory database)"),
+            }
+
+            println!("Messages per user:");
+            for (email, count) in &storage.messages_per_user {
+                println!("  {:<40} {}", email, count);
+            }
+
+            println!("Indexes:");
+            for index in &storage.indexes {
+                let status = if index.problems.is_empty() {
+                    "ok"
+                } else {
+                    "CORRUPT"
+                };
+                println!("  {:<40} {:<20} {}", index.name, index.table, status);
+                for problem in &index.problems {
+                    println!("    {}", problem);
+                }
+            }
+            if !healthy {
+                println!("Rebuild damaged indexes with REINDEX after taking a backup.");
+            }
+
+            code
+        }
+        "passwd" => {
+            if args.len() != 3 {
+                return Ok(out.usage("passwd <email>"));
+            }
+            let email = &args[2];
+            let Some(user) = db::find_user_by_email(&pool, email).await? else {
+                return Ok(out.failure(&format!("User not found: {}", email)));
+            };
+
+            // Prompted for rather than taken from argv, which other users
+            // can see and shells keep in history
+            let password = rpassword::prompt_password("New password: ")?;
+            if password.len() < 8 {
+                return Ok(out.failure("Password must be at least 8 characters."));
+            }
+            if rpassword::prompt_password("Repeat new password: ")? != password {
+                return Ok(out.failure("Passwords do not match."));
+            }
+
+            let (hash, salt) =
+                hash_password(&password).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+            match db::update_user_password(&pool, &user.id, &hash, &salt).await {
+                Ok(_) => out.success(&SuccessResponse::new(), "Password changed successfully."),
+                Err(e) => out.failure(&format!("Error changing password: {}", e)),
+            }
+        }
+        "force-reset" => {
+            if args.len() != 3 {
+                return Ok(out.usage("force-reset <email>"));
+            }
+            let email = &args[2];
+            let Some(user) = db::find_user_by_email(&pool, email).await? else {
+                return Ok(out.failure(&format!("User not found: {}", email)));
+            };
+            match db::require_password_reset(&pool, &user.id).await {
+                Ok(_) => {
+                    let message = format!("{} must choose a new password at next use.", email);
+                    updated(out, &pool, &user.id, &message).await?
+                }
+                Err(e) => out.failure(&format!("Error requiring password reset: {}", e)),
+            }
+        }
+        "remove" => {
+            if args.len() != 3 {
+                return Ok(out.usage("remove <email>"));
+            }
+            let email = &args[2];
+            let Some(user) = db::find_user_by_email(&pool, email).await? else {
+                return Ok(out.failure(&format!("User not found: {}", email)));
+            };
+            let messages = db::count_messages_for_user(&pool, &user.id).await?;
+            if flags.dry_run {
+                let message = format!("Would remove {} and their {} message(s).", email, messages);
+                return Ok(out.success(&user.to_admin(), &message));
+            }
+            let question = format!("Remove {} and their {} message(s)?", email, messages);
+            if !flags.confirm(&question)? {
+                return Ok(out.aborted());
+            }
+
+            out.note(&format!("Removing user: {}", email));
+            match db::delete_user_by_email(&pool, email).await {
+                Ok(_) => out.success(&SuccessResponse::new(), "User removed successfully."),
+                Err(e) => out.failure(&format!("Error removing user: {}", e)),
+            }
+        }
+        "role" => {
+            if args.len() != 4 {
+                return Ok(out.usage("role <email> <user|admin>"));
+            }
+            let email = &args[2];
+            let role = &args[3];
+            if !models::is_valid_role(role) {
+                return Ok(out.failure(&format!("Unknown role: {}", role)));
+            }
+            let Some(user) = db::find_user_by_email(&pool, email).await? else {
+                return Ok(out.failure(&format!("User not found: {}", email)));
+            };
+            match db::update_user_role(&pool, &user.id, role).await {
+                Ok(_) => {
+                    let message = format!("{} is now {}.", email, role);
+                    updated(out, &pool, &user.id, &message).await?
+                }
+                Err(e) => out.failure(&format!("Error changing role: {}", e)),
+            }
+        }
+        "purge" => {
+            let usage = "purge --older-than <days>d [--user <email>] [--dry-run]";
+            let Some(retention) = RetentionArgs::parse(&args[2..]) else {
+                return Ok(out.usage(usage));
+            };
+            let Some(days) = retention.older_than_days else {
+                return Ok(out.usage(usage));
+            };
+            let before = Utc::now() - chrono::Duration::days(days);
+            let user = match &retention.user {
+                Some(email) => match db::find_user_by_email(&pool, email).await? {
+                    Some(user) => Some(user),
+                    None => return Ok(out.failure(&format!("User not found: {}", email))),
+                },
+                None => None,
+            };
+            let user_id = user.as_ref().map(|user| &user.id);
+
+            let matching = db::count_messages_created_before(&pool, before, user_id).await? as u64;
+            let count = if flags.dry_run || matching == 0 {
+                matching
+            } else {
+                let question = format!(
+                    "Delete {} message(s) created before {}?",
+                    matching,
+                    before.to_rfc3339()
+                );
+                if !flags.confirm(&question)? {
+                    return Ok(out.aborted());
+                }
+                let mut deleted = 0;
+                loop {
+                    let batch =
+                        db::purge_messages_created_before(&pool, before, user_id, BATCH_SIZE)
+                            .await?;
+                    if batch == 0 {
+                        break;
+                    }
+                    deleted += batch;
+                }
+                if deleted > 0 {
+                    let mut event = AuditEvent::new("messages.purge", AUDIT_ACTOR).with_metadata(
+                        json!({ "before": before.to_rfc3339(), "deleted": deleted }),
+                    );
+                    if let Some(id) = user_id {
+                        event = event.with_target("user", id.as_str());
+                    }
+                    record_audit_event(&pool, &event).await;
+                }
+                deleted
+            };
+
+            let verb = if flags.dry_run {
+                "Would delete"
+            } else {
+                "Deleted"
+            };
+            let message = format!(
+                "{} {} message(s) created before {}.",
+                verb,
+                count,
+                before.to_rfc3339()
+            );
+            out.success(&PurgeReport::new(before, flags.dry_run, count), &message)
+        }
+        "trash-empty" => {
+            let usage = "trash-empty [--older-than <days>d] [--dry-run]";
+            let Some(retention) = RetentionArgs::parse(&args[2..]) else {
+                return Ok(out.usage(usage));
+            };
+            if retention.user.is_some() {
+                return Ok(out.usage(usage));
+            }
+            let days = retention.older_than_days.unwrap_or(TRASH_RETENTION_DAYS);
+            let before = Utc::now() - chrono::Duration::days(days);
+
+            let matching = db::count_tombstones_before(&pool, before).await? as u64;
+            let count = if flags.dry_run || matching == 0 {
+                matching
+            } else {
+                let question = format!(
+                    "Forget {} tombstone(s) of entries deleted before {}?",
+                    matching,
+                    before.to_rfc3339()
+                );
+                if !flags.confirm(&question)? {
+                    return Ok(out.aborted());
+                }
+                let mut deleted = 0;
+                loop {
+                    let batch = db::purge_tombstones_before(&pool, before, BATCH_SIZE).await?;
+                    if batch == 0 {
+                        break;
+                    }
+                    deleted += batch;
+                }
+                if deleted > 0 {
+                    let event = AuditEvent::new("tombstones.purge", AUDIT_ACTOR).with_metadata(
+                        json!({ "before": before.to_rfc3339(), "deleted": deleted }),
+                    );
+                    record_audit_event(&pool, &event).await;
+                }
+                deleted
+            };
+
+            let verb = if flags.dry_run {
+                "Would remove"
+            } else {
+                "Removed"
+            };
+            let message = format!(
+                "{} {} tombstone(s) of entries deleted before {}.",
+                verb,
+                count,
+                before.to_rfc3339()
+            );
+            out.success(&PurgeReport::new(before, flags.dry_run, count), &message)
+        }
+        "export" => {
+            let usage = "export <email> --format json|markdown|html --out <file>";
+            if args.len() < 3 {
+                return Ok(out.usage(usage));
+            }
+            let email = &args[2];
+            let mut format = None;
+            let mut path = None;
+            let mut options = args[3..].iter();
+            while let Some(option) = options.next() {
+                match option.as_str() {
+                    "--format" => format = options.next(),
+                    "--out" => path = options.next(),
+                    _ => return Ok(out.usage(usage)),
+                }
+            }
+            let (Some(format), Some(path)) = (format, path) else {
+                return Ok(out.usage(usage));
+            };
+            let format: ExportFormat = match format.parse() {
+                Ok(format) => format,
+                Err(e) => return Ok(out.failure(&e)),
+            };
+            let Some(user) = db::find_user_by_email(&pool, email).await? else {
+                return Ok(out.failure(&format!("User not found: {}", email)));
+            };
+
+            // The same rendering as the HTTP exports, so this works while
+            // the server is down
+            let messages = db::get_messages_for_user(&pool, &user.id, None).await?;
+            let contents = format.render(&messages).map_err(anyhow::Error::msg)?;
+            tokio::fs::write(path, contents)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path, e))?;
+
+            let event = AuditEvent::new("admin.user.export", AUDIT_ACTOR)
+                .with_target("user", user.id.as_str())
+                .with_metadata(json!({ "format": format.as_str(), "messages": messages.len() }));
+            record_audit_event(&pool, &event).await;
+
+            let message = format!("Exported {} message(s) to {}.", messages.len(), path);
+            out.success(
+                &json!({ "path": path, "format": format.as_str(), "messages": messages.len() }),
+                &message,
+            )
+        }
+        "disable" | "enable" => {
+            if args.len() != 3 {
+                return Ok(out.usage(&format!("{} <email>", command)));
+            }
+            let email = &args[2];
+            let disabled = command == "disable";
+            let Some(user) = db::find_user_by_email(&pool, email).await? else {
+                return Ok(out.failure(&format!("User not found: {}", email)));
+            };
+            match db::set_user_disabled(&pool, &user.id, disabled).await {
+                Ok(_) => {
+                    let message = format!("User {}d successfully.", command);
+                    updated(out, &pool, &user.id, &message).await?
+                }
+                Err(e) => out.failure(&format!("Error updating user: {}", e)),
+            }
+        }
+        _ => {
+            print_usage();
+            ExitCode::from(2)
+        }
+    };
+
+    Ok(code)
+}
+
+/// `seed [--users N] [--messages M] [--seed S]`: create `test1@example.com`
+/// to `testN@example.com`, each with M fake messages, skipping users that
+/// already exist
+#[cfg(feature = "seed")]
+async fn seed(out: Output, pool: &db::DbPool, args: &[String]) -> anyhow::Result<ExitCode> {
+    use dissipate_core::seed::{Seeder, SEED_PASSWORD};
+
+    let mut users = 2;
+    let mut messages = 5;
+    let mut seed = 1;
+    let mut options = args.iter();
+    while let Some(option) = options.next() {
+        let value = options.next().and_then(|value| value.parse::<u64>().ok());
+        match (option.as_str(), value) {
+            ("--users", Some(value)) => users = value,
+            ("--messages", Some(value)) => messages = value,
+            ("--seed", Some(value)) => seed = value,
+            _ => return Ok(out.usage("seed [--users N] [--messages M] [--seed S]")),
+        }
+    }
+
+    let (hash, salt) = hash_password(SEED_PASSWORD).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    let mut seeder = Seeder::new(seed, Utc::now());
+    let mut created = 0;
+    let mut skipped = 0;
+    let mut messages_created = 0;
+    for index in 1..=users {
+        // Generated even for existing users, so the rest come out the same
+        let user = seeder.user(index, &hash, &salt);
+        let user_messages: Vec<_> = (0..messages).map(|_| seeder.message(&user.id)).collect();
+        match db::create_user(pool, &user).await {
+            Ok(()) => created += 1,
+            Err(db::DbError::EmailAlreadyExists) => {
+                skipped += 1;
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        for batch in user_messages.chunks(BATCH_SIZE as usize) {
+            let mut tx = pool.begin().await?;
+            for message in batch {
+                db::create_message_in(&mut tx, message).await?;
+            }
+            tx.commit().await?;
+            messages_created += batch.len();
+        }
+        out.note(&format!("Seeded {} ({} messages)", user.email, messages));
+    }
+
+    let message = format!(
+        "Created {} user(s) and {} message(s), skipped {} existing user(s). Password: {}",
+        created, messages_created, skipped, SEED_PASSWORD
+    );
+    let report = json!({
+        "users_created": created,
+        "users_skipped": skipped,
+        "messages_created": messages_created,
+        "password": SEED_PASSWORD,
+    });
+    Ok(out.success(&report, &message))
+}
+
+#[cfg(not(feature = "seed"))]
+async fn seed(out: Output, _pool: &db::DbPool, _args: &[String]) -> anyhow::Result<ExitCode> {
+    Ok(out.failure(
+        "Seeding requires building with the `seed` feature: \
+         cargo run --features seed --bin manage_users -- seed",
+    ))
+}
+
+/// Size in bytes as B, KiB, MiB or GiB
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Options shared by `purge` and `trash-empty`
+#[derive(Default)]
+struct RetentionArgs {
+    older_than_days: Option<i64>,
+    user: Option<String>,
+}
+
+impl RetentionArgs {
+    /// `None` on an unknown flag, a missing value or an age that isn't a
+    /// positive number of days such as `90d`
+    fn parse(args: &[String]) -> Option<Self> {
+        let mut parsed = Self::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--older-than" => {
+                    let age = args.next()?;
+                    let days: i64 = age.strip_suffix('d').unwrap_or(age).parse().ok()?;
+                    if days <= 0 {
+                        return None;
+                    }
+                    parsed.older_than_days = Some(days);
+                }
+                "--user" => parsed.user = Some(args.next()?.clone()),
+                _ => return None,
+            }
+        }
+        Some(parsed)
+    }
+}
+
+/// Result of `purge` and `trash-empty`
+#[derive(Serialize)]
+struct PurgeReport {
+    /// Cutoff; only rows older than this were counted or deleted
+    before: String,
+    dry_run: bool,
+    /// Rows deleted, or that would be with `--dry-run`
+    count: u64,
+}
+
+impl PurgeReport {
+    fn new(before: DateTime<Utc>, dry_run: bool, count: u64) -> Self {
+        Self {
+            before: before.to_rfc3339(),
+            dry_run,
+            count,
+        }
+    }
+}
+
+/// Append `event` to the audit log; the change is already made, so a
+/// failure is only reported
+async fn record_audit_event(pool: &db::DbPool, event: &AuditEvent) {
+    if let Err(e) = db::insert_audit_event(pool, event).await {
+        eprintln!("Failed to record audit event {}: {}", event.action, e);
+    }
+}
+
+/// Report a changed user, re-read so JSON output shows the new state
+async fn updated(
+    out: Output,
+    pool: &db::DbPool,
+    user_id: &UserId,
+    message: &str,
+) -> anyhow::Result<ExitCode> {
+    Ok(match db::find_user_by_id(pool, user_id).await? {
+        Some(user) => out.success(&user.to_admin(), message),
+        None => out.failure(&format!("User not found: {}", user_id)),
+    })
+}
+
+fn print_usage() {
+    println!("Usage: manage_users [--json] [--yes] [--dry-run] <command> [args]");
+    println!("Commands:");
+    println!("  list                            List all users");
+    println!("  add <email> <username> <password> Add a new user");
+    println!("  remove <email> [--dry-run]      Remove a user and their entries");
+    println!("  passwd <email>                  Set a user's password, prompting for it");
+    println!("  force-reset <email>             Make a user choose a new password");
+    println!("  role <email> <user|admin>       Change a user's role");
+    println!("  disable <email>                 Stop a user logging in, keeping their data");
+    println!("  enable <email>                  Let a disabled user log in again");
+    println!("  check                           Check database integrity and row counts");
+    println!("  seed [--users N] [--messages M] [--seed S]");
+    println!("                                  Create fake users and messages (`seed` feature)");
+    println!("  stats                           Show entry counts, file sizes and index health");
+    println!("  export <email> --format json|markdown|html --out <file>");
+    println!("                                  Write a user's entries to a file");
+    println!("  purge --older-than <days>d [--user <email>] [--dry-run]");
+    println!("                                  Delete entries created before then");
+    println!("  trash-empty [--older-than <days>d] [--dry-run]");
+    println!("                                  Forget deletions older than 90 days (default)");
+    println!();
+    println!("With --json, results and errors are printed as JSON. Failures exit 1,");
+    println!("wrong arguments exit 2. remove, purge and trash-empty ask before deleting");
+    println!("unless given --yes, which scripts without a terminal must pass.");
+}
@@ -0,0 +1,384 @@
+//! Typed client for the Dissipate HTTP API
+//!
+//! Requests and responses are the DTOs from `dissipate_core::models`, the same
+//! types the server uses, so tools built on this crate stay in step with the
+//! API without hand-written JSON.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), dissipate_client::ClientError> {
+//! use dissipate_client::{models::CreateMessageRequest, DissipateClient};
+//!
+//! let mut client = DissipateClient::new("http://localhost:8080");
+//! client.login("me@example.com", "hunter22").await?;
+//! let message = client
+//!     .create_message(&CreateMessageRequest {
+//!         content: "Hello".into(),
+//!         id: None,
+//!         template_id: None,
+//!         created_at: None,
+//!         publish_at: None,
+//!     })
+//!     .await?;
+//! println!("{}", message.id);
+//! # Ok(())
+//! # }
+//! ```
+
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize};
+
+pub use dissipate_core::{export_format::ExportFormat, models};
+
+use models::{
+    BatchSyncRequest, CreateExportRequest, CreateMessageRequest, ExportJobResponse,
+    ExportProgressResponse, LoginRequest, LoginResponse, MessageResponse, MessagesQuery,
+    MessagesResponse, SuccessResponse, SyncResponse, UpdateMessageRequest,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("Request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    /// The server answered with an error status
+    #[error("{status}: {message}")]
+    Api {
+        status: StatusCode,
+        /// The `error` field of the response body
+        message: String,
+        /// Stable identifier for errors clients handle specially, such as
+        /// `account_disabled`
+        code: Option<String>,
+    },
+    #[error("Not logged in")]
+    NotLoggedIn,
+}
+
+/// Body the server sends with error statuses
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: String,
+    #[serde(default)]
+    code: Option<String>,
+}
+
+/// Client for one Dissipate server, holding the token of the signed-in user
+#[derive(Debug, Clone)]
+pub struct DissipateClient {
+    http: reqwest::Client,
+    base_url: String,
+    token: Option<String>,
+}
+
+impl DissipateClient {
+    /// Client for the server at `base_url`, such as `https://journal.example.com`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self::with_http_client(reqwest::Client::new(), base_url)
+    }
+
+    /// Client sending requests through `http`, for custom timeouts or proxies
+    pub fn with_http_client(http: reqwest::Client, base_url: impl Into<String>) -> Self {
+        let base_url = base_url.into().trim_end_matches('/').to_string();
+        Self {
+            http,
+            base_url,
+            token: None,
+        }
+    }
+
+    /// Use an existing token instead of logging in
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Token sent with requests, once logged in
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    /// POST /api/login, keeping the returned token for later requests
+    pub async fn login(
+        &mut self,
+        email: &str,
+        password: &str,
+    ) -> Result<LoginResponse, ClientError> {
+        let request = LoginRequest {
+            email: email.to_string(),
+            password: password.to_string(),
+        };
+        let response = self
+            .http
+            .post(self.url("/api/login"))
+            .json(&request)
+            .send()
+            .await?;
+        let login: LoginResponse = json(response).await?;
+        self.token = Some(login.token.clone());
+        Ok(login)
+    }
+
+    /// GET /api/messages, optionally only those changed after an RFC 3339 `since`
+    pub async fn messages(&self, since: Option<&str>) -> Result<Vec<MessageResponse>, ClientError> {
+        let query = MessagesQuery {
+            since: since.map(str::to_string),
+            since_seq: None,
+        };
+        let response: MessagesResponse = json(
+            self.authed(Method::GET, "/api/messages")?
+                .query(&query)
+                .send()
+                .await?,
+        )
+        .await?;
+        Ok(response.messages)
+    }
+
+    /// POST /api/messages
+    pub async fn create_message(
+        &self,
+        request: &CreateMessageRequest,
+    ) -> Result<MessageResponse, ClientError> {
+        let builder = self.authed(Method::POST, "/api/messages")?;
+        json(builder.json(request).send().await?).await
+    }
+
+    /// PUT /api/messages/:id
+    pub async fn update_message(
+        &self,
+        id: &str,
+        request: &UpdateMessageRequest,
+    ) -> Result<MessageResponse, ClientError> {
+        let builder = self.authed(Method::PUT, &format!("/api/messages/{}", id))?;
+        json(builder.json(request).send().await?).await
+    }
+
+    /// DELETE /api/messages/:id
+    pub async fn delete_message(&self, id: &str) -> Result<(), ClientError> {
+        let builder = self.authed(Method::DELETE, &format!("/api/messages/{}", id))?;
+        let _: SuccessResponse = json(builder.send().await?).await?;
+        Ok(())
+    }
+
+    /// GET /api/sync, with the `seq` of the previous sync or `None` for
+    /// everything
+    pub async fn sync(&self, since_seq: Option<i64>) -> Result<SyncResponse, ClientError> {
+        let query = MessagesQuery {
+            since: None,
+            since_seq,
+        };
+        let builder = self.authed(Method::GET, "/api/sync")?.query(&query);
+        json(builder.send().await?).await
+    }
+
+    /// POST /api/sync, pushing queued changes and pulling those after
+    /// `request.since_seq`
+    pub async fn batch_sync(
+        &self,
+        request: &BatchSyncRequest,
+    ) -> Result<SyncResponse, ClientError> {
+        let builder = self.authed(Method::POST, "/api/sync")?;
+        json(builder.json(request).send().await?).await
+    }
+
+    /// GET /api/export/:format, returning the file
+    pub async fn export(&self, format: ExportFormat) -> Result<Vec<u8>, ClientError> {
+        let path = format!("/api/export/{}", format.as_str());
+        bytes(self.authed(Method::GET, &path)?.send().await?).await
+    }
+
+    /// POST /api/exports, starting a background export job
+    pub async fn create_export(
+        &self,
+        request: &CreateExportRequest,
+    ) -> Result<ExportJobResponse, ClientError> {
+        let builder = self.authed(Method::POST, "/api/exports")?;
+        json(builder.json(request).send().await?).await
+    }
+
+    /// GET /api/exports/:id
+    pub async fn export_job(&self, id: &str) -> Result<ExportJobResponse, ClientError> {
+        let builder = self.authed(Method::GET, &format!("/api/exports/{}", id))?;
+        json(builder.send().await?).await
+    }
+
+    /// GET /api/exports/:id/progress
+    pub async fn export_progress(&self, id: &str) -> Result<ExportProgressResponse, ClientError> {
+        let builder = self.authed(Method::GET, &format!("/api/exports/{}/progress", id))?;
+        json(builder.send().await?).await
+    }
+
+    /// GET /api/exports/:id/download, returning the finished file
+    pub async fn download_export(&self, id: &str) -> Result<Vec<u8>, ClientError> {
+        let builder = self.authed(Method::GET, &format!("/api/exports/{}/download", id))?;
+        bytes(builder.send().await?).await
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    fn authed(&self, method: Method, path: &str) -> Result<RequestBuilder, ClientError> {
+        let token = self.token.as_deref().ok_or(ClientError::NotLoggedIn)?;
+        Ok(self.http.request(method, self.url(path)).bearer_auth(token))
+    }
+}
+
+/// Turn error statuses into [`ClientError::Api`]
+async fn check(response: Response) -> Result<Response, ClientError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.text().await?;
+    let (message, code) = match serde_json::from_str::<ErrorBody>(&body) {
+        Ok(error) => (error.error, error.code),
+        Err(_) => (body, None),
+    };
+    Err(ClientError::Api {
+        status,
+        message,
+        code,
+    })
+}
+
+async fn json<T: DeserializeOwned>(response: Response) -> Result<T, ClientError> {
+    Ok(check(response).await?.json().await?)
+}
+
+async fn bytes(response: Response) -> Result<Vec<u8>, ClientError> {
+    Ok(check(response).await?.bytes().await?.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dissipate_server::testing::{create_test_user, test_app, TEST_PASSWORD};
+
+    /// Serve the test app on a local port and return a client for it
+    async fn spawn_server() -> DissipateClient {
+        let (app, state) = test_app().await;
+        create_test_user(&state, "test@example.com", "testuser").await;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        DissipateClient::new(format!("http://{}", addr))
+    }
+
+    fn new_message(content: &str) -> CreateMessageRequest {
+        CreateMessageRequest {
+            content: content.to_string(),
+            id: None,
+            template_id: None,
+            created_at: None,
+            publish_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_login_stores_token() {
+        let mut client = spawn_server().await;
+        assert!(client.token().is_none());
+
+        let login = client
+            .login("test@example.com", TEST_PASSWORD)
+            .await
+            .unwrap();
+        assert_eq!(login.user.username, "testuser");
+        assert_eq!(client.token(), Some(login.token.as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_wrong_password_is_api_error() {
+        let mut client = spawn_server().await;
+        let err = client.login("test@example.com", "wrong").await.unwrap_err();
+        assert!(matches!(
+            err,
+            ClientError::Api {
+                status: StatusCode::UNAUTHORIZED,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_requests_need_login() {
+        let client = spawn_server().await;
+        let err = client.messages(None).await.unwrap_err();
+        assert!(matches!(err, ClientError::NotLoggedIn));
+    }
+
+    #[tokio::test]
+    async fn test_message_crud() {
+        let mut client = spawn_server().await;
+        client
+            .login("test@example.com", TEST_PASSWORD)
+            .await
+            .unwrap();
+
+        let created = client.create_message(&new_message("First")).await.unwrap();
+        assert_eq!(created.content, "First");
+
+        let updated = client
+            .update_message(
+                &created.id,
+                &UpdateMessageRequest {
+                    content: "Edited".to_string(),
+                    updated_at: None,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(updated.content, "Edited");
+
+        let messages = client.messages(None).await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, "Edited");
+
+        client.delete_message(&created.id).await.unwrap();
+        assert!(client.messages(None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sync_round_trip() {
+        let mut client = spawn_server().await;
+        client
+            .login("test@example.com", TEST_PASSWORD)
+            .await
+            .unwrap();
+
+        let first = client.sync(None).await.unwrap();
+        assert!(first.changed.is_empty());
+
+        client.create_message(&new_message("Hello")).await.unwrap();
+        let delta = client.sync(Some(first.seq)).await.unwrap();
+        assert_eq!(delta.changed.len(), 1);
+
+        let pushed = client
+            .batch_sync(&BatchSyncRequest {
+                since_seq: Some(delta.seq),
+                deletes: vec![delta.changed[0].id.parse().unwrap()],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(pushed.deleted, vec![delta.changed[0].id.clone()]);
+    }
+
+    #[tokio::test]
+    async fn test_export() {
+        let mut client = spawn_server().await;
+        client
+            .login("test@example.com", TEST_PASSWORD)
+            .await
+            .unwrap();
+        client
+            .create_message(&new_message("Exported"))
+            .await
+            .unwrap();
+
+        let file = client.export(ExportFormat::Markdown).await.unwrap();
+        assert!(String::from_utf8(file).unwrap().contains("Exported"));
+    }
+}
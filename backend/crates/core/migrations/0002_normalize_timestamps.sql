@@ -0,0 +1,46 @@
+-- Timestamps are decoded as chrono::DateTime<Utc> and compared in SQL, so
+-- every stored value must use the encoding sqlx writes: RFC 3339 in UTC with
+-- a +00:00 offset. In that single format string order matches time order.
+-- SQLite cannot change a column's declared type in place (and rebuilding
+-- these tables would cascade-delete their children), so the columns stay
+-- TEXT and only values written with another offset or layout are rewritten.
+
+UPDATE users
+SET created_at = replace(strftime('%Y-%m-%dT%H:%M:%f', created_at), '.000', '') || '+00:00'
+WHERE created_at NOT LIKE '____-__-__T__:__:__%+00:00'
+  AND strftime('%s', created_at) IS NOT NULL;
+
+UPDATE users
+SET updated_at = replace(strftime('%Y-%m-%dT%H:%M:%f', updated_at), '.000', '') || '+00:00'
+WHERE updated_at NOT LIKE '____-__-__T__:__:__%+00:00'
+  AND strftime('%s', updated_at) IS NOT NULL;
+
+UPDATE messages
+SET created_at = replace(strftime('%Y-%m-%dT%H:%M:%f', created_at), '.000', '') || '+00:00'
+WHERE created_at NOT LIKE '____-__-__T__:__:__%+00:00'
+  AND strftime('%s', created_at) IS NOT NULL;
+
+UPDATE messages
+SET updated_at = replace(strftime('%Y-%m-%dT%H:%M:%f', updated_at), '.000', '') || '+00:00'
+WHERE updated_at NOT LIKE '____-__-__T__:__:__%+00:00'
+  AND strftime('%s', updated_at) IS NOT NULL;
+
+UPDATE messages
+SET publish_at = replace(strftime('%Y-%m-%dT%H:%M:%f', publish_at), '.000', '') || '+00:00'
+WHERE publish_at NOT LIKE '____-__-__T__:__:__%+00:00'
+  AND strftime('%s', publish_at) IS NOT NULL;
+
+UPDATE templates
+SET created_at = replace(strftime('%Y-%m-%dT%H:%M:%f', created_at), '.000', '') || '+00:00'
+WHERE created_at NOT LIKE '____-__-__T__:__:__%+00:00'
+  AND strftime('%s', created_at) IS NOT NULL;
+
+UPDATE templates
+SET updated_at = replace(strftime('%Y-%m-%dT%H:%M:%f', updated_at), '.000', '') || '+00:00'
+WHERE updated_at NOT LIKE '____-__-__T__:__:__%+00:00'
+  AND strftime('%s', updated_at) IS NOT NULL;
+
+UPDATE tombstones
+SET deleted_at = replace(strftime('%Y-%m-%dT%H:%M:%f', deleted_at), '.000', '') || '+00:00'
+WHERE deleted_at NOT LIKE '____-__-__T__:__:__%+00:00'
+  AND strftime('%s', deleted_at) IS NOT NULL;
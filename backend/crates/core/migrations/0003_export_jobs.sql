@@ -0,0 +1,14 @@
+-- Exports produced in the background and downloaded once finished
+CREATE TABLE IF NOT EXISTS export_jobs (
+    id TEXT PRIMARY KEY,  -- UUID
+    user_id TEXT NOT NULL,
+    format TEXT NOT NULL,  -- 'json', 'markdown' or 'html'
+    status TEXT NOT NULL,  -- 'pending', 'running', 'completed' or 'failed'
+    error TEXT,  -- Set when the job failed
+    created_at TEXT NOT NULL,
+    completed_at TEXT,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_export_jobs_status ON export_jobs(status, created_at);
+CREATE INDEX IF NOT EXISTS idx_export_jobs_user_id ON export_jobs(user_id);
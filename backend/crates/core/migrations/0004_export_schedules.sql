@@ -0,0 +1,21 @@
+-- Recurring exports the export worker queues and emails to the user
+CREATE TABLE IF NOT EXISTS export_schedules (
+    id TEXT PRIMARY KEY,  -- UUID
+    user_id TEXT NOT NULL,
+    format TEXT NOT NULL,  -- 'json', 'markdown' or 'html'
+    frequency TEXT NOT NULL,  -- 'weekly' or 'monthly'
+    email TEXT NOT NULL,  -- Where the export is delivered
+    delivery TEXT NOT NULL,  -- 'attachment' or 'link'
+    next_run_at TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_export_schedules_next_run_at ON export_schedules(next_run_at);
+CREATE INDEX IF NOT EXISTS idx_export_schedules_user_id ON export_schedules(user_id);
+
+-- Set on jobs queued by a schedule, which are delivered by email when done
+ALTER TABLE export_jobs ADD COLUMN schedule_id TEXT
+    REFERENCES export_schedules(id) ON DELETE SET NULL;
+-- Secret for downloading without logging in, used by emailed links
+ALTER TABLE export_jobs ADD COLUMN download_token TEXT;
@@ -0,0 +1,9 @@
+-- WebDAV folder (e.g. Nextcloud) each user's finished exports are copied to
+CREATE TABLE IF NOT EXISTS webdav_destinations (
+    user_id TEXT PRIMARY KEY,
+    url TEXT NOT NULL,  -- Folder URL, e.g. https://cloud.example.com/remote.php/dav/files/alice/Journal
+    username TEXT NOT NULL,
+    password TEXT NOT NULL,  -- Ideally an app password
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,6 @@
+-- age recipient or passphrase, cleared once the job has run
+ALTER TABLE export_jobs ADD COLUMN encrypt_to TEXT;
+-- Whether the job's file is encrypted, which adds `.age` to its name
+ALTER TABLE export_jobs ADD COLUMN encrypted INTEGER NOT NULL DEFAULT 0;
+-- age recipient scheduled exports are encrypted to
+ALTER TABLE export_schedules ADD COLUMN encrypt_to TEXT;
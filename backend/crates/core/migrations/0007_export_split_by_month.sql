@@ -0,0 +1,2 @@
+-- Whether the job's file is a ZIP with one file per month
+ALTER TABLE export_jobs ADD COLUMN split_by_month INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- Messages read so far by a running job, out of `rows_total`
+ALTER TABLE export_jobs ADD COLUMN rows_processed INTEGER NOT NULL DEFAULT 0;
+-- Number of messages being exported, set when the job starts
+ALTER TABLE export_jobs ADD COLUMN rows_total INTEGER;
@@ -0,0 +1,2 @@
+-- `user`, or `admin` for users allowed to use the /api/admin routes
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'user';
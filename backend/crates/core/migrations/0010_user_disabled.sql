@@ -0,0 +1,3 @@
+-- Set by admins to freeze an account: it can't log in or use its tokens,
+-- but its data is kept
+ALTER TABLE users ADD COLUMN disabled INTEGER NOT NULL DEFAULT 0;
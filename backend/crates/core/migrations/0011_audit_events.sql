@@ -0,0 +1,15 @@
+-- Admin actions and destructive operations, kept for operators to review
+-- (no foreign keys, so events outlive the users they mention)
+CREATE TABLE IF NOT EXISTS audit_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,  -- Also the pagination cursor
+    created_at TEXT NOT NULL,
+    action TEXT NOT NULL,  -- e.g. 'admin.user.delete' or 'messages.import'
+    actor TEXT NOT NULL,  -- User ID, 'admin_token' or 'system'
+    target_type TEXT,  -- e.g. 'user'
+    target_id TEXT,
+    metadata TEXT NOT NULL DEFAULT '{}'  -- JSON object with details
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_events_action ON audit_events(action, id);
+CREATE INDEX IF NOT EXISTS idx_audit_events_actor ON audit_events(actor, id);
+CREATE INDEX IF NOT EXISTS idx_audit_events_target ON audit_events(target_id, id);
@@ -0,0 +1,3 @@
+-- Set to make a user choose a new password before using the API again;
+-- cleared when they change it
+ALTER TABLE users ADD COLUMN must_reset_password INTEGER NOT NULL DEFAULT 0;
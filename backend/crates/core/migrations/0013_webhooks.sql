@@ -0,0 +1,31 @@
+-- URLs each user's message changes are POSTed to, e.g. a Zapier or n8n hook
+CREATE TABLE IF NOT EXISTS webhooks (
+    id TEXT PRIMARY KEY,  -- UUID
+    user_id TEXT NOT NULL,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,  -- Key the payloads are signed with (HMAC-SHA256)
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhooks_user_id ON webhooks(user_id);
+
+-- One event sent to one webhook, retried until it succeeds or gives up
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id TEXT PRIMARY KEY,  -- UUID, sent in the X-Dissipate-Delivery header
+    webhook_id TEXT NOT NULL,
+    event TEXT NOT NULL,  -- 'message.created', 'message.updated' or 'message.deleted'
+    payload TEXT NOT NULL,  -- JSON body, fixed when the event happened
+    status TEXT NOT NULL,  -- 'pending', 'delivered' or 'failed'
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TEXT,  -- Set while pending
+    response_status INTEGER,  -- HTTP status of the last attempt, if there was a response
+    error TEXT,  -- Why the last attempt failed
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (webhook_id) REFERENCES webhooks(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due ON webhook_deliveries(status, next_attempt_at);
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook_id ON webhook_deliveries(webhook_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_created_at ON webhook_deliveries(created_at);
@@ -0,0 +1,7 @@
+-- Instance features switched by operators at /api/admin/features, overriding
+-- the [features] defaults from the config
+CREATE TABLE IF NOT EXISTS feature_flags (
+    name TEXT PRIMARY KEY,  -- 'registration', 'sharing' or 'attachments'
+    enabled INTEGER NOT NULL,
+    updated_at TEXT NOT NULL
+);
@@ -0,0 +1,9 @@
+-- Messages each user created per UTC day, for USER_MAX_MESSAGES_PER_DAY.
+-- Counted as they are written, since clients may backdate created_at.
+CREATE TABLE IF NOT EXISTS message_creations (
+    user_id TEXT NOT NULL,
+    day TEXT NOT NULL,  -- UTC date, YYYY-MM-DD
+    count INTEGER NOT NULL,
+    PRIMARY KEY (user_id, day),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
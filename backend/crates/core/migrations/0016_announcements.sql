@@ -0,0 +1,22 @@
+-- Notices operators show in every client, such as maintenance windows
+CREATE TABLE IF NOT EXISTS announcements (
+    id TEXT PRIMARY KEY,  -- UUID
+    message TEXT NOT NULL,
+    level TEXT NOT NULL,  -- 'info' or 'warning'
+    starts_at TEXT,  -- Shown from this time; straight away when unset
+    ends_at TEXT,  -- Hidden from this time; kept up when unset
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+-- Announcements each user has closed, so clients stop showing them
+CREATE TABLE IF NOT EXISTS announcement_dismissals (
+    announcement_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    dismissed_at TEXT NOT NULL,
+    PRIMARY KEY (announcement_id, user_id),
+    FOREIGN KEY (announcement_id) REFERENCES announcements(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_announcement_dismissals_user_id ON announcement_dismissals(user_id);
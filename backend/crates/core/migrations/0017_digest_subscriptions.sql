@@ -0,0 +1,13 @@
+-- Users who get a daily or weekly email summarising what they wrote
+CREATE TABLE IF NOT EXISTS digest_subscriptions (
+    user_id TEXT PRIMARY KEY,
+    frequency TEXT NOT NULL,  -- 'daily' or 'weekly'
+    hour INTEGER NOT NULL,  -- Local hour the digest is sent at, 0-23
+    weekday TEXT,  -- Day weekly digests are sent on, e.g. 'monday'
+    timezone TEXT NOT NULL,  -- IANA name, e.g. 'Europe/Berlin'
+    next_run_at TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_digest_subscriptions_next_run_at ON digest_subscriptions(next_run_at);
@@ -0,0 +1,15 @@
+-- Users who want a nudge to write on days they haven't yet
+CREATE TABLE IF NOT EXISTS reminders (
+    user_id TEXT PRIMARY KEY,
+    time TEXT NOT NULL,  -- Local time it is sent at, 'HH:MM'
+    days TEXT NOT NULL,  -- Comma-separated days, e.g. 'monday,friday'
+    timezone TEXT NOT NULL,  -- IANA name, e.g. 'Europe/Berlin'
+    email INTEGER NOT NULL DEFAULT 0,
+    push INTEGER NOT NULL DEFAULT 0,
+    ntfy_url TEXT,  -- ntfy topic URL, e.g. https://ntfy.sh/my-journal
+    next_run_at TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_reminders_next_run_at ON reminders(next_run_at);
@@ -0,0 +1,28 @@
+-- Self-hosted services, such as ntfy or Gotify, that show a user notifications
+CREATE TABLE IF NOT EXISTS notification_channels (
+    id TEXT PRIMARY KEY,  -- UUID
+    user_id TEXT NOT NULL,
+    kind TEXT NOT NULL,  -- 'ntfy' or 'gotify'
+    url TEXT NOT NULL,  -- ntfy topic URL, or Gotify server URL
+    token TEXT,  -- ntfy access token, or Gotify application token
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_notification_channels_user_id ON notification_channels(user_id);
+
+-- Reminders go to the user's channels rather than an ntfy URL of their own,
+-- so existing URLs become channels
+ALTER TABLE reminders ADD COLUMN channels INTEGER NOT NULL DEFAULT 0;
+
+INSERT INTO notification_channels (id, user_id, kind, url, created_at)
+SELECT
+    lower(hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4' || substr(hex(randomblob(2)), 2)
+        || '-a' || substr(hex(randomblob(2)), 2) || '-' || hex(randomblob(6))),
+    user_id, 'ntfy', ntfy_url, created_at
+FROM reminders
+WHERE ntfy_url IS NOT NULL;
+
+UPDATE reminders SET channels = 1 WHERE ntfy_url IS NOT NULL;
+
+ALTER TABLE reminders DROP COLUMN ntfy_url;
@@ -0,0 +1,8 @@
+-- Secret addresses whose mail becomes entries in a user's journal
+CREATE TABLE IF NOT EXISTS inbound_email_addresses (
+    user_id TEXT PRIMARY KEY,
+    token TEXT NOT NULL UNIQUE,  -- Local part of the address
+    allowed_senders TEXT NOT NULL DEFAULT '',  -- Comma-separated, besides the account email
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
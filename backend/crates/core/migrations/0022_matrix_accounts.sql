@@ -0,0 +1,11 @@
+-- Matrix bot accounts that post to, and save entries from, a user's DM room
+CREATE TABLE IF NOT EXISTS matrix_accounts (
+    user_id TEXT PRIMARY KEY,
+    homeserver_url TEXT NOT NULL,
+    access_token TEXT NOT NULL,
+    bot_user_id TEXT NOT NULL,  -- The bot's Matrix ID, e.g. '@journal-bot:example.org'
+    room_id TEXT NOT NULL,  -- e.g. '!abc123:example.org'
+    sync_token TEXT,  -- Where the next sync continues from; unset until the first sync
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
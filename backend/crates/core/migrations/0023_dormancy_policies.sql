@@ -0,0 +1,14 @@
+-- Users' dead-man switches: what happens to their account once they have
+-- been inactive for a while
+CREATE TABLE IF NOT EXISTS dormancy_policies (
+    user_id TEXT PRIMARY KEY,
+    inactive_days INTEGER NOT NULL,
+    export_email TEXT,  -- Address the account export is emailed to
+    purge INTEGER NOT NULL DEFAULT 0,  -- Delete the account after any export
+    last_active_at TEXT NOT NULL,
+    warnings_sent INTEGER NOT NULL DEFAULT 0,  -- Since the last activity
+    last_warning_at TEXT,
+    triggered_at TEXT,  -- Set once the export was sent, until the next activity
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,2 @@
+-- Lets housekeeping find expired idempotency keys without a full scan
+CREATE INDEX IF NOT EXISTS idx_idempotency_keys_created_at ON idempotency_keys(created_at);
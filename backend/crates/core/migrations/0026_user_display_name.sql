@@ -0,0 +1,3 @@
+-- Name shown to other users in shared journals and exports, so the username
+-- can stay fixed while this changes; NULL shows the username
+ALTER TABLE users ADD COLUMN display_name TEXT;
@@ -0,0 +1,5358 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    str::FromStr,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use sqlx::{
+    migrate::{MigrateDatabase, Migrator},
+    sqlite::{SqliteConnectOptions, SqliteConnection, SqlitePoolOptions, SqliteSynchronous},
+    Executor, Pool, Sqlite,
+};
+use thiserror::Error;
+
+use crate::config::DatabaseConfig;
+use crate::ids::{MessageId, UserId};
+use crate::models::{
+    Announcement, AuditEvent, Avatar, DigestSubscription, DormancyPolicy, DueWebhookDelivery,
+    ExportJob, ExportSchedule, IdempotentResponse, InboundEmailAddress, JournalMemberResponse,
+    MatrixAccount, Message, NotificationChannel, PushSubscription, ReactionCount, Reminder,
+    SharedJournalResponse, TelegramLink, TelegramLinkCode, Template, User, UserReaction,
+    WebDavDestination, Webhook, WebhookDelivery, EXPORT_STATUS_COMPLETED, EXPORT_STATUS_FAILED,
+    EXPORT_STATUS_PENDING, EXPORT_STATUS_RUNNING, JOURNAL_ROLE_WRITE, WEBHOOK_DELIVERY_PENDING,
+};
+
+#[derive(Debug, Error)]
+pub enum DbError {
+    #[error("Database error: {0}")]
+    SqlxError(#[from] sqlx::Error),
+    #[error("User not found")]
+    UserNotFound,
+    #[error("Message not found")]
+    MessageNotFound,
+    #[error("Email already exists")]
+    EmailAlreadyExists,
+    #[error("Template not found")]
+    TemplateNotFound,
+    #[error("Journal member not found")]
+    MemberNotFound,
+    #[error("Journal access denied")]
+    JournalAccessDenied,
+    #[error("Export schedule not found")]
+    ExportScheduleNotFound,
+    #[error("Webhook not found")]
+    WebhookNotFound,
+    #[error("Notification channel not found")]
+    NotificationChannelNotFound,
+    #[error("Telegram is not linked")]
+    TelegramNotLinked,
+    #[error("Inbound email address not found")]
+    InboundEmailAddressNotFound,
+    #[error("Matrix account not found")]
+    MatrixAccountNotFound,
+    #[error("Dormancy policy not found")]
+    DormancyPolicyNotFound,
+    #[error("Announcement not found")]
+    AnnouncementNotFound,
+    #[error("Message ID already in use")]
+    MessageIdConflict,
+    #[error("Migration error: {0}")]
+    MigrateError(#[from] sqlx::migrate::MigrateError),
+    #[error("Database encryption requires building with the `sqlcipher` feature")]
+    EncryptionUnavailable,
+    #[error("Database is busy")]
+    DatabaseBusy,
+    /// A duplicate key or a reference to a missing row, from storage that
+    /// has no SQL error to report it with
+    #[error("Constraint violated: {0}")]
+    ConstraintViolation(&'static str),
+    #[error("{0} is not supported by this storage backend")]
+    Unsupported(&'static str),
+}
+
+impl DbError {
+    /// Whether SQLite rejected the operation because another connection
+    /// holds a conflicting lock (SQLITE_BUSY or SQLITE_LOCKED)
+    pub fn is_busy(&self) -> bool {
+        match self {
+            DbError::DatabaseBusy => true,
+            DbError::SqlxError(sqlx::Error::Database(e)) => e
+                .code()
+                .and_then(|code| code.parse::<i32>().ok())
+                // Extended result codes keep the primary code in the low byte
+                .is_some_and(|code| matches!(code & 0xff, SQLITE_BUSY | SQLITE_LOCKED)),
+            _ => false,
+        }
+    }
+}
+
+const SQLITE_BUSY: i32 = 5;
+const SQLITE_LOCKED: i32 = 6;
+
+/// Attempts made at a write transaction before reporting the database as busy
+const BUSY_RETRY_ATTEMPTS: u32 = 4;
+
+/// Delay before the first retry; doubled after each further attempt
+const BUSY_RETRY_BASE_DELAY: Duration = Duration::from_millis(25);
+
+/// Run a write operation, retrying it while the database is locked
+///
+/// The busy timeout already makes single statements wait for the lock, but
+/// a deferred transaction that read before writing fails immediately with
+/// SQLITE_BUSY when another writer got in first; only rerunning the whole
+/// transaction helps. Returns `DbError::DatabaseBusy` once attempts run out.
+pub async fn retry_busy<T, F, Fut>(mut operation: F) -> Result<T, DbError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, DbError>>,
+{
+    let mut delay = BUSY_RETRY_BASE_DELAY;
+    for attempt in 1..=BUSY_RETRY_ATTEMPTS {
+        match operation().await {
+            Err(e) if e.is_busy() => {
+                tracing::debug!("Database busy (attempt {}): {}", attempt, e);
+                if attempt < BUSY_RETRY_ATTEMPTS {
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+            }
+            result => return result,
+        }
+    }
+
+    Err(DbError::DatabaseBusy)
+}
+
+pub type DbPool = Pool<Sqlite>;
+
+/// Separate pools for reads and writes against the same database
+#[derive(Debug, Clone)]
+pub struct DbPools {
+    pub read: DbPool,
+    pub write: DbPool,
+}
+
+impl DbPools {
+    /// Use one pool for both reads and writes
+    pub fn single(pool: DbPool) -> Self {
+        Self {
+            read: pool.clone(),
+            write: pool,
+        }
+    }
+}
+
+/// How `update_message` resolves an edit made against a stale version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// The most recently received edit overwrites the stored content
+    #[default]
+    LastWriterWins,
+    /// The stored content is kept and the stale edit is saved as a new
+    /// message referencing the original via `conflict_of`
+    ConflictCopy,
+}
+
+impl std::str::FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "last-writer-wins" | "lww" => Ok(ConflictPolicy::LastWriterWins),
+            "conflict-copy" => Ok(ConflictPolicy::ConflictCopy),
+            other => Err(format!("Unknown conflict policy: {}", other)),
+        }
+    }
+}
+
+/// Mode passed to `PRAGMA wal_checkpoint`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CheckpointMode {
+    /// Copy as many frames as possible without waiting on readers or writers
+    #[default]
+    Passive,
+    /// Wait for writers, then copy every frame
+    Full,
+    /// Like `Full`, then wait for readers so the next writer restarts the WAL
+    Restart,
+    /// Like `Restart`, then truncate the WAL file to zero bytes
+    Truncate,
+}
+
+impl CheckpointMode {
+    fn as_sql(self) -> &'static str {
+        match self {
+            CheckpointMode::Passive => "PASSIVE",
+            CheckpointMode::Full => "FULL",
+            CheckpointMode::Restart => "RESTART",
+            CheckpointMode::Truncate => "TRUNCATE",
+        }
+    }
+}
+
+impl std::str::FromStr for CheckpointMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "passive" => Ok(CheckpointMode::Passive),
+            "full" => Ok(CheckpointMode::Full),
+            "restart" => Ok(CheckpointMode::Restart),
+            "truncate" => Ok(CheckpointMode::Truncate),
+            other => Err(format!("Unknown checkpoint mode: {}", other)),
+        }
+    }
+}
+
+/// Outcome of `PRAGMA wal_checkpoint`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckpointResult {
+    /// The checkpoint could not finish because of other connections
+    pub busy: bool,
+    /// Frames in the WAL
+    pub log_frames: i64,
+    /// Frames copied back into the database file
+    pub checkpointed_frames: i64,
+}
+
+/// Outcome of `optimize`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptimizeResult {
+    /// Bytes of free pages returned to the filesystem, or `None` when the
+    /// database doesn't use incremental auto-vacuum
+    pub vacuumed_bytes: Option<i64>,
+    /// The truncating checkpoint run last
+    pub checkpoint: CheckpointResult,
+}
+
+/// Outcome of `check_database`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct IntegrityReport {
+    /// Problems found by `PRAGMA integrity_check`; empty when the file is sound
+    pub integrity_errors: Vec<String>,
+    /// Rows referencing a missing parent row, by table
+    pub orphaned_rows: BTreeMap<String, i64>,
+    /// Rows in each application table
+    pub row_counts: BTreeMap<String, i64>,
+}
+
+impl IntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.integrity_errors.is_empty() && self.orphaned_rows.is_empty()
+    }
+}
+
+/// Aggregates reported by `instance_stats`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct InstanceStats {
+    pub user_count: i64,
+    /// Messages created on each UTC day (`YYYY-MM-DD`) of the period,
+    /// including days without any
+    pub messages_per_day: BTreeMap<String, i64>,
+    /// Size of the database, excluding the WAL
+    pub database_bytes: i64,
+}
+
+/// Storage details reported by `storage_stats`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StorageStats {
+    /// Message count of each user's email, most first
+    pub messages_per_user: Vec<(String, i64)>,
+    /// Space in the database file left by deleted rows, which `VACUUM`
+    /// gives back
+    pub free_bytes: i64,
+    pub indexes: Vec<IndexHealth>,
+}
+
+/// An index and any corruption `PRAGMA integrity_check` found in it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexHealth {
+    pub table: String,
+    pub name: String,
+    pub problems: Vec<String>,
+}
+
+/// Which audit events `list_audit_events` returns; unset fields match all
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    /// Exact action, or a prefix before a `.`
+    pub action: Option<String>,
+    pub actor: Option<String>,
+    pub target_id: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Only events older than this ID, for the next page
+    pub before_id: Option<i64>,
+}
+
+/// One page of a list sorted by one of its columns
+#[derive(Debug, Clone, Copy)]
+pub struct SortedPage<'a> {
+    /// Column to sort by; a column the list doesn't offer sorts by
+    /// `created_at`
+    pub sort: &'a str,
+    pub descending: bool,
+    /// Most rows to return; `None` for all of them
+    pub limit: Option<i64>,
+    /// Rows to skip
+    pub offset: i64,
+}
+
+impl SortedPage<'_> {
+    /// `ORDER BY` clause for `column`, with the ID breaking ties so pages
+    /// don't overlap
+    fn order_by(&self, column: &str) -> String {
+        let direction = if self.descending { "DESC" } else { "ASC" };
+        format!("ORDER BY {0} {1}, id {1}", column, direction)
+    }
+
+    /// `LIMIT` for SQLite, where a negative one means no limit
+    fn sql_limit(&self) -> i64 {
+        self.limit.unwrap_or(-1)
+    }
+}
+
+/// Tables counted by `check_database`
+const APP_TABLES: &[&str] = &[
+    "users",
+    "messages",
+    "templates",
+    "journal_members",
+    "reactions",
+    "tombstones",
+    "push_subscriptions",
+    "idempotency_keys",
+    "audit_events",
+];
+
+/// Versioned schema migrations embedded from `migrations/`
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Connection settings beyond the database URL
+#[derive(Debug, Clone)]
+pub struct DbOptions {
+    /// SQLCipher key; the database file is encrypted when set
+    pub encryption_key: Option<String>,
+    pub max_connections: u32,
+    /// How long a connection waits on a locked database before failing
+    pub busy_timeout: Duration,
+    pub synchronous: SqliteSynchronous,
+    /// Page cache per connection, in KiB
+    pub cache_size_kib: u32,
+}
+
+impl Default for DbOptions {
+    fn default() -> Self {
+        Self::small()
+    }
+}
+
+impl DbOptions {
+    /// Preset for personal instances with a handful of users (the default)
+    pub fn small() -> Self {
+        Self {
+            encryption_key: None,
+            max_connections: 5,
+            busy_timeout: Duration::from_secs(5),
+            synchronous: SqliteSynchronous::Full,
+            cache_size_kib: 2 * 1024,
+        }
+    }
+
+    /// Preset for shared instances with many concurrent clients
+    ///
+    /// `NORMAL` synchronous is safe in WAL mode: a power loss can roll back
+    /// the last commits but cannot corrupt the database.
+    pub fn busy() -> Self {
+        Self {
+            encryption_key: None,
+            max_connections: 16,
+            busy_timeout: Duration::from_secs(15),
+            synchronous: SqliteSynchronous::Normal,
+            cache_size_kib: 64 * 1024,
+        }
+    }
+
+    /// Options from the `[database]` settings
+    ///
+    /// `preset` (`small` or `busy`) picks the starting values, which
+    /// `max_connections`, `busy_timeout_ms`, `synchronous` and
+    /// `cache_size_kib` override. The encryption key is `key`, or read from
+    /// `key_file` so it can be kept out of the config and environment.
+    pub fn from_config(config: &DatabaseConfig) -> Result<Self, String> {
+        let mut options = match config.preset.as_deref() {
+            None | Some("small") => Self::small(),
+            Some("busy") => Self::busy(),
+            Some(other) => return Err(format!("Unknown DATABASE_PRESET: {}", other)),
+        };
+
+        if let Some(count) = config.max_connections {
+            options.max_connections = count;
+        }
+        if let Some(ms) = config.busy_timeout_ms {
+            options.busy_timeout = Duration::from_millis(ms);
+        }
+        if let Some(value) = &config.synchronous {
+            options.synchronous = value
+                .parse()
+                .map_err(|_| format!("Invalid DATABASE_SYNCHRONOUS: {}", value))?;
+        }
+        if let Some(kib) = config.cache_size_kib {
+            options.cache_size_kib = kib;
+        }
+
+        options.encryption_key = match (&config.key, &config.key_file) {
+            (Some(key), _) => Some(key.clone()),
+            (None, Some(path)) => Some(read_key_file(path)?),
+            _ => None,
+        };
+
+        if options.max_connections == 0 {
+            return Err("DATABASE_MAX_CONNECTIONS must be at least 1".to_string());
+        }
+
+        Ok(options)
+    }
+}
+
+fn read_key_file(path: &str) -> Result<String, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read DATABASE_KEY_FILE {}: {}", path, e))?;
+    let key = contents.trim_end_matches(['\r', '\n']);
+    if key.is_empty() {
+        return Err(format!("DATABASE_KEY_FILE {} is empty", path));
+    }
+    Ok(key.to_string())
+}
+
+/// Initialize the database connection pool
+pub async fn init_pool(database_url: &str) -> Result<DbPool, DbError> {
+    init_pool_with(database_url, &DbOptions::default()).await
+}
+
+/// Initialize the database connection pool with explicit options
+pub async fn init_pool_with(database_url: &str, options: &DbOptions) -> Result<DbPool, DbError> {
+    // Create database if it doesn't exist
+    let created = !Sqlite::database_exists(database_url).await.unwrap_or(false);
+    if created {
+        Sqlite::create_database(database_url).await?;
+    }
+
+    let pool = SqlitePoolOptions::new()
+        .max_connections(options.max_connections)
+        .connect_with(connect_options(database_url, options)?)
+        .await?;
+
+    if options.encryption_key.is_some() {
+        ensure_sqlcipher(&pool).await?;
+    }
+
+    // Lets `optimize` reclaim free pages. Creating the database already
+    // switched it to WAL, after which the setting only takes effect through a
+    // VACUUM; older databases need the same full VACUUM to switch
+    if created {
+        sqlx::query("PRAGMA auto_vacuum = INCREMENTAL")
+            .execute(&pool)
+            .await?;
+        sqlx::query("VACUUM").execute(&pool).await?;
+    }
+
+    run_migrations(&pool).await?;
+
+    // Enable WAL mode (not allowed inside the migration transaction)
+    sqlx::query("PRAGMA journal_mode = WAL")
+        .execute(&pool)
+        .await?;
+
+    Ok(pool)
+}
+
+/// Initialize a single-writer pool plus a pool of read-only connections
+///
+/// SQLite serializes writers anyway, so funnelling every write through one
+/// connection avoids lock contention, while WAL lets the readers proceed
+/// alongside it. `max_connections` sizes the read pool. In-memory databases
+/// are private to each connection, so they get one shared pool instead.
+pub async fn init_pools_with(database_url: &str, options: &DbOptions) -> Result<DbPools, DbError> {
+    let write_options = DbOptions {
+        max_connections: 1,
+        ..options.clone()
+    };
+    let write = init_pool_with(database_url, &write_options).await?;
+
+    if is_memory_url(database_url) {
+        return Ok(DbPools::single(write));
+    }
+
+    let read = SqlitePoolOptions::new()
+        .max_connections(options.max_connections)
+        .connect_with(connect_options(database_url, options)?.read_only(true))
+        .await?;
+
+    Ok(DbPools { read, write })
+}
+
+fn connect_options(
+    database_url: &str,
+    options: &DbOptions,
+) -> Result<SqliteConnectOptions, DbError> {
+    let mut connect_options = SqliteConnectOptions::from_str(database_url)?
+        .busy_timeout(options.busy_timeout)
+        .synchronous(options.synchronous)
+        // A negative cache size is in KiB rather than pages
+        .pragma("cache_size", format!("-{}", options.cache_size_kib));
+    if let Some(key) = options.encryption_key.as_deref() {
+        // sqlx always issues the key pragma first, as SQLCipher requires
+        connect_options = connect_options.pragma("key", format!("'{}'", key.replace('\'', "''")));
+    }
+    Ok(connect_options)
+}
+
+fn is_memory_url(database_url: &str) -> bool {
+    database_url.contains(":memory:") || database_url.contains("mode=memory")
+}
+
+/// Plain SQLite silently ignores `PRAGMA key`, which would leave the
+/// database unencrypted
+async fn ensure_sqlcipher(pool: &DbPool) -> Result<(), DbError> {
+    let version: Option<String> = sqlx::query_scalar("PRAGMA cipher_version")
+        .fetch_optional(pool)
+        .await?;
+
+    match version {
+        Some(_) => Ok(()),
+        None => Err(DbError::EncryptionUnavailable),
+    }
+}
+
+/// Apply any pending migrations
+pub async fn run_migrations(pool: &DbPool) -> Result<(), DbError> {
+    upgrade_legacy_schema(pool).await?;
+    MIGRATOR.run(pool).await?;
+    Ok(())
+}
+
+/// Run a trivial query to check the database answers
+pub async fn ping(pool: &DbPool) -> Result<(), DbError> {
+    sqlx::query("SELECT 1").execute(pool).await?;
+    Ok(())
+}
+
+/// Number of embedded migrations not yet applied to the database
+pub async fn pending_migrations(pool: &DbPool) -> Result<usize, DbError> {
+    let applied: Vec<i64> = if table_exists(pool, "_sqlx_migrations").await? {
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success = 1")
+            .fetch_all(pool)
+            .await?
+    } else {
+        Vec::new()
+    };
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .filter(|migration| !applied.contains(&migration.version))
+        .count())
+}
+
+/// Bring databases created before migrations existed up to the baseline
+///
+/// Those databases have tables but no `_sqlx_migrations`, and may predate
+/// columns that the baseline migration's `CREATE TABLE IF NOT EXISTS` would
+/// not add.
+async fn upgrade_legacy_schema(pool: &DbPool) -> Result<(), DbError> {
+    if table_exists(pool, "_sqlx_migrations").await? || !table_exists(pool, "users").await? {
+        return Ok(());
+    }
+
+    add_column_if_missing(pool, "users", "change_seq", "INTEGER NOT NULL DEFAULT 0").await?;
+    add_column_if_missing(pool, "messages", "publish_at", "TEXT").await?;
+    add_column_if_missing(pool, "messages", "author_id", "TEXT").await?;
+    add_column_if_missing(pool, "messages", "conflict_of", "TEXT").await?;
+    add_column_if_missing(pool, "messages", "seq", "INTEGER NOT NULL DEFAULT 0").await?;
+    if table_exists(pool, "tombstones").await? {
+        add_column_if_missing(pool, "tombstones", "seq", "INTEGER NOT NULL DEFAULT 0").await?;
+    }
+
+    Ok(())
+}
+
+async fn table_exists(pool: &DbPool, table: &str) -> Result<bool, DbError> {
+    let existing = sqlx::query("SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?")
+        .bind(table)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(existing.is_some())
+}
+
+/// Add a column to an existing table if it is not already present
+async fn add_column_if_missing(
+    pool: &DbPool,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), DbError> {
+    let existing = sqlx::query("SELECT name FROM pragma_table_info(?) WHERE name = ?")
+        .bind(table)
+        .bind(column)
+        .fetch_optional(pool)
+        .await?;
+
+    if existing.is_none() {
+        sqlx::query(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            table, column, definition
+        ))
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Write a consistent snapshot of the database to `path`, which must not exist
+pub async fn backup_database(pool: &DbPool, path: &str) -> Result<(), DbError> {
+    sqlx::query("VACUUM INTO ?")
+        .bind(path)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Checkpoint the WAL into the main database file
+///
+/// Must run on a connection that can write; read-only connections can't
+/// checkpoint.
+pub async fn wal_checkpoint(
+    pool: &DbPool,
+    mode: CheckpointMode,
+) -> Result<CheckpointResult, DbError> {
+    let (busy, log_frames, checkpointed_frames): (i64, i64, i64) =
+        sqlx::query_as(&format!("PRAGMA wal_checkpoint({})", mode.as_sql()))
+            .fetch_one(pool)
+            .await?;
+
+    Ok(CheckpointResult {
+        busy: busy != 0,
+        log_frames,
+        checkpointed_frames,
+    })
+}
+
+/// Refresh the query planner's statistics, reclaim free pages and
+/// truncate the WAL, for a quiet hour
+///
+/// Must run on a connection that can write.
+pub async fn optimize(pool: &DbPool) -> Result<OptimizeResult, DbError> {
+    sqlx::query("PRAGMA optimize").execute(pool).await?;
+
+    // 2 is INCREMENTAL
+    let auto_vacuum: i64 = sqlx::query_scalar("PRAGMA auto_vacuum")
+        .fetch_one(pool)
+        .await?;
+    let vacuumed_bytes = if auto_vacuum == 2 {
+        let before = free_bytes(pool).await?;
+        sqlx::query("PRAGMA incremental_vacuum")
+            .execute(pool)
+            .await?;
+        Some(before - free_bytes(pool).await?)
+    } else {
+        None
+    };
+
+    let checkpoint = wal_checkpoint(pool, CheckpointMode::Truncate).await?;
+
+    Ok(OptimizeResult {
+        vacuumed_bytes,
+        checkpoint,
+    })
+}
+
+/// Space in the database file left by deleted rows
+async fn free_bytes(pool: &DbPool) -> Result<i64, DbError> {
+    let bytes = sqlx::query_scalar(
+        "SELECT freelist_count * page_size FROM pragma_freelist_count, pragma_page_size",
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(bytes)
+}
+
+/// Size in bytes of the database's `-wal` file, or `None` for in-memory
+/// databases, which have no file
+pub async fn wal_size_bytes(pool: &DbPool) -> Result<Option<u64>, DbError> {
+    let file: String =
+        sqlx::query_scalar("SELECT file FROM pragma_database_list WHERE name = 'main'")
+            .fetch_one(pool)
+            .await?;
+    if file.is_empty() {
+        return Ok(None);
+    }
+
+    // The WAL is removed when the last connection closes cleanly
+    match tokio::fs::metadata(format!("{}-wal", file)).await {
+        Ok(metadata) => Ok(Some(metadata.len())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Some(0)),
+        Err(e) => Err(DbError::SqlxError(sqlx::Error::Io(e))),
+    }
+}
+
+/// Validate the database file and its references, e.g. after a crash
+///
+/// Orphans are found with `PRAGMA foreign_key_check`, which reports rows
+/// left behind when foreign keys were not enforced, such as messages whose
+/// user no longer exists.
+pub async fn check_database(pool: &DbPool) -> Result<IntegrityReport, DbError> {
+    let integrity_errors: Vec<String> = sqlx::query_scalar("SELECT * FROM pragma_integrity_check")
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .filter(|line: &String| line != "ok")
+        .collect();
+
+    let orphaned_rows: Vec<(String, i64)> = sqlx::query_as(
+        r#"SELECT "table", COUNT(*) FROM pragma_foreign_key_check GROUP BY "table""#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut row_counts = BTreeMap::new();
+    for table in APP_TABLES {
+        let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(pool)
+            .await?;
+        row_counts.insert(table.to_string(), count);
+    }
+
+    Ok(IntegrityReport {
+        integrity_errors,
+        orphaned_rows: orphaned_rows.into_iter().collect(),
+        row_counts,
+    })
+}
+
+/// Count users, and messages created on each of the last `days` days
+/// including today
+pub async fn instance_stats(pool: &DbPool, days: u32) -> Result<InstanceStats, DbError> {
+    let user_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        .fetch_one(pool)
+        .await?;
+
+    let today = chrono::Utc::now().date_naive();
+    let first_day = today - chrono::Duration::days(i64::from(days.max(1)) - 1);
+    let mut messages_per_day: BTreeMap<String, i64> = first_day
+        .iter_days()
+        .take_while(|day| *day <= today)
+        .map(|day| (day.to_string(), 0))
+        .collect();
+    // Timestamps are stored as RFC 3339 in UTC, so the date is the prefix
+    let counts: Vec<(String, i64)> = sqlx::query_as(
+        r#"
+        SELECT substr(created_at, 1, 10) AS day, COUNT(*)
+        FROM messages
+        WHERE created_at >= ?
+        GROUP BY day
+        "#,
+    )
+    .bind(first_day.to_string())
+    .fetch_all(pool)
+    .await?;
+    for (day, count) in counts {
+        if let Some(total) = messages_per_day.get_mut(&day) {
+            *total = count;
+        }
+    }
+
+    let database_bytes: i64 = sqlx::query_scalar(
+        "SELECT page_count * page_size FROM pragma_page_count, pragma_page_size",
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(InstanceStats {
+        user_count,
+        messages_per_day,
+        database_bytes,
+    })
+}
+
+/// Per-user message counts, free space and index health, for triage
+///
+/// Runs `PRAGMA integrity_check`, which reads the whole database.
+pub async fn storage_stats(pool: &DbPool) -> Result<StorageStats, DbError> {
+    let messages_per_user: Vec<(String, i64)> = sqlx::query_as(
+        r#"
+        SELECT users.email, COUNT(messages.id) AS count
+        FROM users LEFT JOIN messages ON messages.user_id = users.id
+        GROUP BY users.id
+        ORDER BY count DESC, users.email ASC
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let free_bytes = free_bytes(pool).await?;
+
+    let integrity_errors: Vec<String> = sqlx::query_scalar("SELECT * FROM pragma_integrity_check")
+        .fetch_all(pool)
+        .await?;
+    let indexes: Vec<(String, String)> = sqlx::query_as(
+        r#"
+        SELECT tbl_name, name FROM sqlite_master
+        WHERE type = 'index' AND tbl_name NOT LIKE 'sqlite_%' AND tbl_name != '_sqlx_migrations'
+        ORDER BY tbl_name, name
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+    // Index problems are reported as e.g. "wrong # of entries in index idx_x"
+    let indexes = indexes
+        .into_iter()
+        .map(|(table, name)| IndexHealth {
+            problems: integrity_errors
+                .iter()
+                .filter(|error| error.split_whitespace().any(|word| word == name))
+                .cloned()
+                .collect(),
+            table,
+            name,
+        })
+        .collect();
+
+    Ok(StorageStats {
+        messages_per_user,
+        free_bytes,
+        indexes,
+    })
+}
+
+// ============ User Operations ============
+
+/// Find a user by email
+pub async fn find_user_by_email(pool: &DbPool, email: &str) -> Result<Option<User>, DbError> {
+    let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE email = ?")
+        .bind(email)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(user)
+}
+
+/// Find a user by ID
+pub async fn find_user_by_id(pool: &DbPool, id: &UserId) -> Result<Option<User>, DbError> {
+    let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(user)
+}
+
+/// Create a new user
+#[allow(dead_code)]
+pub async fn create_user(pool: &DbPool, user: &User) -> Result<(), DbError> {
+    // The UNIQUE constraint rejects duplicates atomically, unlike a prior lookup
+    sqlx::query(
+        r#"
+        INSERT INTO users (
+            id, email, username, display_name, password_hash, salt, role, disabled,
+            must_reset_password, created_at, updated_at
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&user.id)
+    .bind(&user.email)
+    .bind(&user.username)
+    .bind(&user.display_name)
+    .bind(&user.password_hash)
+    .bind(&user.salt)
+    .bind(&user.role)
+    .bind(user.disabled)
+    .bind(user.must_reset_password)
+    .bind(user.created_at)
+    .bind(user.updated_at)
+    .execute(pool)
+    .await
+    .map_err(email_conflict)?;
+
+    Ok(())
+}
+
+/// Map a UNIQUE violation (only `users.email` has one) to `EmailAlreadyExists`
+fn email_conflict(e: sqlx::Error) -> DbError {
+    match e.as_database_error() {
+        Some(db_err) if db_err.is_unique_violation() => DbError::EmailAlreadyExists,
+        _ => DbError::SqlxError(e),
+    }
+}
+
+/// List all users, oldest first
+pub async fn list_users(pool: &DbPool) -> Result<Vec<User>, DbError> {
+    let users = sqlx::query_as::<_, User>("SELECT * FROM users ORDER BY created_at")
+        .fetch_all(pool)
+        .await?;
+    Ok(users)
+}
+
+/// List a page of users, sorted by `created_at`, `email` or `username`
+pub async fn list_users_page(pool: &DbPool, page: &SortedPage<'_>) -> Result<Vec<User>, DbError> {
+    let column = match page.sort {
+        "email" => "email",
+        "username" => "username",
+        _ => "created_at",
+    };
+    let users = sqlx::query_as::<_, User>(&format!(
+        "SELECT * FROM users {} LIMIT ? OFFSET ?",
+        page.order_by(column)
+    ))
+    .bind(page.sql_limit())
+    .bind(page.offset)
+    .fetch_all(pool)
+    .await?;
+    Ok(users)
+}
+
+/// Delete a user by email
+#[allow(dead_code)]
+pub async fn delete_user_by_email(pool: &DbPool, email: &str) -> Result<(), DbError> {
+    let result = sqlx::query("DELETE FROM users WHERE email = ?")
+        .bind(email)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::UserNotFound);
+    }
+    Ok(())
+}
+
+/// Delete a user and, through the foreign keys, everything they own
+pub async fn delete_user(pool: &DbPool, user_id: &UserId) -> Result<(), DbError> {
+    let result = sqlx::query("DELETE FROM users WHERE id = ?")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::UserNotFound);
+    }
+    Ok(())
+}
+
+/// Update user role
+pub async fn update_user_role(pool: &DbPool, user_id: &UserId, role: &str) -> Result<(), DbError> {
+    let updated_at = chrono::Utc::now();
+
+    let result = sqlx::query(
+        r#"
+        UPDATE users SET role = ?, updated_at = ? WHERE id = ?
+        "#,
+    )
+    .bind(role)
+    .bind(updated_at)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::UserNotFound);
+    }
+
+    Ok(())
+}
+
+/// Disable or re-enable a user
+pub async fn set_user_disabled(
+    pool: &DbPool,
+    user_id: &UserId,
+    disabled: bool,
+) -> Result<(), DbError> {
+    let updated_at = chrono::Utc::now();
+
+    let result = sqlx::query(
+        r#"
+        UPDATE users SET disabled = ?, updated_at = ? WHERE id = ?
+        "#,
+    )
+    .bind(disabled)
+    .bind(updated_at)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::UserNotFound);
+    }
+
+    Ok(())
+}
+
+/// Make a user change their password before doing anything else
+#[allow(dead_code)]
+pub async fn require_password_reset(pool: &DbPool, user_id: &UserId) -> Result<(), DbError> {
+    let updated_at = chrono::Utc::now();
+
+    let result = sqlx::query(
+        r#"
+        UPDATE users SET must_reset_password = 1, updated_at = ? WHERE id = ?
+        "#,
+    )
+    .bind(updated_at)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::UserNotFound);
+    }
+
+    Ok(())
+}
+
+/// Update user email
+pub async fn update_user_email(
+    pool: &DbPool,
+    user_id: &UserId,
+    email: &str,
+) -> Result<(), DbError> {
+    let updated_at = chrono::Utc::now();
+
+    let result = sqlx::query(
+        r#"
+        UPDATE users SET email = ?, updated_at = ? WHERE id = ?
+        "#,
+    )
+    .bind(email)
+    .bind(updated_at)
+    .bind(user_id)
+    .execute(pool)
+    .await
+    .map_err(email_conflict)?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::UserNotFound);
+    }
+
+    Ok(())
+}
+
+/// Update user username
+pub async fn update_user_username(
+    pool: &DbPool,
+    user_id: &UserId,
+    username: &str,
+) -> Result<(), DbError> {
+    let updated_at = chrono::Utc::now();
+
+    let result = sqlx::query(
+        r#"
+        UPDATE users SET username = ?, updated_at = ? WHERE id = ?
+        "#,
+    )
+    .bind(username)
+    .bind(updated_at)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::UserNotFound);
+    }
+
+    Ok(())
+}
+
+/// Set or clear (with `None`) the name shown to other users
+pub async fn update_user_display_name(
+    pool: &DbPool,
+    user_id: &UserId,
+    display_name: Option<&str>,
+) -> Result<(), DbError> {
+    let updated_at = chrono::Utc::now();
+
+    let result = sqlx::query(
+        r#"
+        UPDATE users SET display_name = ?, updated_at = ? WHERE id = ?
+        "#,
+    )
+    .bind(display_name)
+    .bind(updated_at)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::UserNotFound);
+    }
+
+    Ok(())
+}
+
+/// Update user password, which also satisfies a required reset
+pub async fn update_user_password(
+    pool: &DbPool,
+    user_id: &UserId,
+    password_hash: &str,
+    salt: &str,
+) -> Result<(), DbError> {
+    let updated_at = chrono::Utc::now();
+
+    let result = sqlx::query(
+        r#"
+        UPDATE users
+        SET password_hash = ?, salt = ?, must_reset_password = 0, updated_at = ?
+        WHERE id = ?
+        "#,
+    )
+    .bind(password_hash)
+    .bind(salt)
+    .bind(updated_at)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::UserNotFound);
+    }
+
+    Ok(())
+}
+
+// ============ Message Operations ============
+//
+// Operations that issue several statements come in two forms: one taking the
+// pool, and an `_in` variant taking a connection so callers can compose them
+// inside a transaction. Single-statement reads accept any executor.
+
+/// Bump and return the user's change sequence number
+///
+/// Every create, update and delete of a user's messages takes the next value,
+/// giving clients an integer sync cursor that is immune to clock skew.
+async fn next_seq(conn: &mut SqliteConnection, user_id: &UserId) -> Result<i64, DbError> {
+    let row: Option<(i64,)> = sqlx::query_as(
+        "UPDATE users SET change_seq = change_seq + 1 WHERE id = ? RETURNING change_seq",
+    )
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    row.map(|(seq,)| seq).ok_or(DbError::UserNotFound)
+}
+
+/// Get the user's current change sequence number
+pub async fn get_change_seq<'e, E>(executor: E, user_id: &UserId) -> Result<i64, DbError>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let row: Option<(i64,)> = sqlx::query_as("SELECT change_seq FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(executor)
+        .await?;
+
+    row.map(|(seq,)| seq).ok_or(DbError::UserNotFound)
+}
+
+/// Get messages changed after the given sequence number
+pub async fn get_messages_since_seq<'e, E>(
+    executor: E,
+    user_id: &UserId,
+    since_seq: i64,
+) -> Result<Vec<Message>, DbError>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let now = chrono::Utc::now();
+
+    let messages = sqlx::query_as::<_, Message>(
+        r#"
+        SELECT * FROM messages
+        WHERE user_id = ? AND seq > ? AND (publish_at IS NULL OR publish_at <= ?)
+        ORDER BY seq ASC
+        "#,
+    )
+    .bind(user_id)
+    .bind(since_seq)
+    .bind(now)
+    .fetch_all(executor)
+    .await?;
+
+    Ok(messages)
+}
+
+/// Get a cheap fingerprint of a user's visible messages for HTTP caching
+///
+/// Combines the change sequence (bumped on every create, update and delete,
+/// and whenever a reaction is added or removed) with the number of visible
+/// messages, which changes as scheduled entries publish.
+pub async fn get_messages_fingerprint(pool: &DbPool, user_id: &UserId) -> Result<String, DbError> {
+    let now = chrono::Utc::now();
+
+    let (change_seq, visible): (i64, i64) = sqlx::query_as(
+        r#"
+        SELECT
+            u.change_seq,
+            (SELECT COUNT(*) FROM messages m
+             WHERE m.user_id = u.id AND (m.publish_at IS NULL OR m.publish_at <= ?))
+        FROM users u
+        WHERE u.id = ?
+        "#,
+    )
+    .bind(now)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or(DbError::UserNotFound)?;
+
+    Ok(format!("{}-{}", change_seq, visible))
+}
+
+/// Total size in bytes of the content of a user's messages
+pub async fn get_content_bytes(pool: &DbPool, user_id: &UserId) -> Result<i64, DbError> {
+    let bytes: i64 = sqlx::query_scalar(
+        "SELECT COALESCE(SUM(LENGTH(CAST(content AS BLOB))), 0) FROM messages WHERE user_id = ?",
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(bytes)
+}
+
+/// How many messages a user has, and how many they created today (UTC)
+pub async fn get_message_counts(pool: &DbPool, user_id: &UserId) -> Result<(i64, i64), DbError> {
+    let counts = sqlx::query_as::<_, (i64, i64)>(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM messages WHERE user_id = ?),
+            COALESCE((SELECT count FROM message_creations WHERE user_id = ? AND day = ?), 0)
+        "#,
+    )
+    .bind(user_id)
+    .bind(user_id)
+    .bind(Utc::now().date_naive())
+    .fetch_one(pool)
+    .await?;
+
+    Ok(counts)
+}
+
+/// Get all messages for a user, optionally filtered by timestamp
+///
+/// Messages scheduled with a `publish_at` in the future are excluded until
+/// that time has passed. When `since` is given, messages whose scheduled
+/// publish time falls after it are included so syncing clients pick them up.
+pub async fn get_messages_for_user(
+    pool: &DbPool,
+    user_id: &UserId,
+    since: Option<DateTime<Utc>>,
+) -> Result<Vec<Message>, DbError> {
+    let now = chrono::Utc::now();
+
+    let messages = if let Some(since_timestamp) = since {
+        sqlx::query_as::<_, Message>(
+            r#"
+            SELECT * FROM messages 
+            WHERE user_id = ?
+              AND (publish_at IS NULL OR publish_at <= ?)
+              AND (created_at > ? OR updated_at > ? OR publish_at > ?)
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .bind(now)
+        .bind(since_timestamp)
+        .bind(since_timestamp)
+        .bind(since_timestamp)
+        .fetch_all(pool)
+        .await?
+    } else {
+        sqlx::query_as::<_, Message>(
+            r#"
+            SELECT * FROM messages 
+            WHERE user_id = ? AND (publish_at IS NULL OR publish_at <= ?)
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .bind(now)
+        .fetch_all(pool)
+        .await?
+    };
+
+    Ok(messages)
+}
+
+/// Get a page of a user's visible messages, sorted by `created_at` or
+/// `updated_at`, with `since` filtering them like in `get_messages_for_user`
+pub async fn get_messages_for_user_page(
+    pool: &DbPool,
+    user_id: &UserId,
+    since: Option<DateTime<Utc>>,
+    page: &SortedPage<'_>,
+) -> Result<Vec<Message>, DbError> {
+    let column = match page.sort {
+        "updated_at" => "updated_at",
+        _ => "created_at",
+    };
+    let messages = sqlx::query_as::<_, Message>(&format!(
+        r#"
+        SELECT * FROM messages
+        WHERE user_id = ? AND (publish_at IS NULL OR publish_at <= ?)
+          AND (? IS NULL OR created_at > ? OR updated_at > ? OR publish_at > ?)
+        {}
+        LIMIT ? OFFSET ?
+        "#,
+        page.order_by(column)
+    ))
+    .bind(user_id)
+    .bind(chrono::Utc::now())
+    .bind(since)
+    .bind(since)
+    .bind(since)
+    .bind(since)
+    .bind(page.sql_limit())
+    .bind(page.offset)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(messages)
+}
+
+/// Count a user's visible messages
+pub async fn count_messages_for_user(pool: &DbPool, user_id: &UserId) -> Result<i64, DbError> {
+    let (count,): (i64,) = sqlx::query_as(
+        r#"
+        SELECT COUNT(*) FROM messages
+        WHERE user_id = ? AND (publish_at IS NULL OR publish_at <= ?)
+        "#,
+    )
+    .bind(user_id)
+    .bind(chrono::Utc::now())
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count)
+}
+
+/// Get a user's visible messages created from `since` up to `until`,
+/// newest first
+pub async fn get_messages_created_between(
+    pool: &DbPool,
+    user_id: &UserId,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> Result<Vec<Message>, DbError> {
+    let messages = sqlx::query_as::<_, Message>(
+        r#"
+        SELECT * FROM messages
+        WHERE user_id = ? AND (publish_at IS NULL OR publish_at <= ?)
+          AND created_at >= ? AND created_at < ?
+        ORDER BY created_at DESC, id DESC
+        "#,
+    )
+    .bind(user_id)
+    .bind(chrono::Utc::now())
+    .bind(since)
+    .bind(until)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(messages)
+}
+
+/// Get when each of a user's messages since `since` was created, newest
+/// first
+pub async fn get_message_times(
+    pool: &DbPool,
+    user_id: &UserId,
+    since: DateTime<Utc>,
+) -> Result<Vec<DateTime<Utc>>, DbError> {
+    let times = sqlx::query_scalar::<_, DateTime<Utc>>(
+        r#"
+        SELECT created_at FROM messages
+        WHERE user_id = ? AND created_at >= ?
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(times)
+}
+
+/// Get up to `limit` of a user's visible messages, newest first, starting
+/// after the message at `after` (its `created_at` and ID)
+pub async fn get_messages_page(
+    pool: &DbPool,
+    user_id: &UserId,
+    after: Option<(DateTime<Utc>, &MessageId)>,
+    limit: i64,
+) -> Result<Vec<Message>, DbError> {
+    let (after_created_at, after_id) = after.unzip();
+
+    let messages = sqlx::query_as::<_, Message>(
+        r#"
+        SELECT * FROM messages
+        WHERE user_id = ? AND (publish_at IS NULL OR publish_at <= ?)
+          AND (? IS NULL OR created_at < ? OR (created_at = ? AND id < ?))
+        ORDER BY created_at DESC, id DESC
+        LIMIT ?
+        "#,
+    )
+    .bind(user_id)
+    .bind(chrono::Utc::now())
+    .bind(after_created_at)
+    .bind(after_created_at)
+    .bind(after_created_at)
+    .bind(after_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(messages)
+}
+
+/// Create a new message
+pub async fn create_message(pool: &DbPool, message: &Message) -> Result<Message, DbError> {
+    retry_busy(|| async move {
+        let mut tx = pool.begin().await?;
+        let created = create_message_in(&mut tx, message).await?;
+        tx.commit().await?;
+        Ok(created)
+    })
+    .await
+}
+
+/// Create a new message on the given connection
+pub async fn create_message_in(
+    conn: &mut SqliteConnection,
+    message: &Message,
+) -> Result<Message, DbError> {
+    let seq = next_seq(conn, &message.user_id).await?;
+
+    let created = sqlx::query_as::<_, Message>(
+        r#"
+        INSERT INTO messages
+            (id, user_id, content, created_at, updated_at, publish_at, author_id, conflict_of, seq)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        RETURNING *
+        "#,
+    )
+    .bind(&message.id)
+    .bind(&message.user_id)
+    .bind(&message.content)
+    .bind(message.created_at)
+    .bind(message.updated_at)
+    .bind(message.publish_at)
+    .bind(&message.author_id)
+    .bind(&message.conflict_of)
+    .bind(seq)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    // A re-created ID is no longer deleted
+    sqlx::query("DELETE FROM tombstones WHERE message_id = ?")
+        .bind(&message.id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(created)
+}
+
+/// Create a message, or return the existing one if the user already created it
+///
+/// Makes client-generated IDs idempotent for offline retry queues. If the ID
+/// exists for the same user, its content is updated when it differs. Returns
+/// the stored message and whether it was newly created.
+pub async fn upsert_message(pool: &DbPool, message: &Message) -> Result<(Message, bool), DbError> {
+    retry_busy(|| async move {
+        let mut tx = pool.begin().await?;
+        let result = upsert_message_in(&mut tx, message).await?;
+        tx.commit().await?;
+        Ok(result)
+    })
+    .await
+}
+
+/// Create or return an existing message on the given connection
+pub async fn upsert_message_in(
+    conn: &mut SqliteConnection,
+    message: &Message,
+) -> Result<(Message, bool), DbError> {
+    match get_message_by_id(&mut *conn, &message.id).await? {
+        None => {
+            let created = create_message_in(&mut *conn, message).await?;
+            record_message_created(conn, &message.user_id).await?;
+            Ok((created, true))
+        }
+        Some(existing) if existing.user_id != message.user_id => Err(DbError::MessageIdConflict),
+        Some(existing) if existing.content == message.content => Ok((existing, false)),
+        Some(existing) => {
+            let updated = update_message_in(
+                conn,
+                &existing.id,
+                &existing.user_id,
+                &message.content,
+                None,
+                ConflictPolicy::LastWriterWins,
+            )
+            .await?;
+            Ok((updated, false))
+        }
+    }
+}
+
+/// Count a message written by a client towards its owner's messages today
+///
+/// Imports and conflict copies aren't counted. Earlier days are dropped as
+/// the count moves on, since only today's is checked.
+async fn record_message_created(
+    conn: &mut SqliteConnection,
+    user_id: &UserId,
+) -> Result<(), DbError> {
+    let today = Utc::now().date_naive();
+
+    sqlx::query("DELETE FROM message_creations WHERE user_id = ? AND day < ?")
+        .bind(user_id)
+        .bind(today)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query(
+        r#"
+        INSERT INTO message_creations (user_id, day, count) VALUES (?, ?, 1)
+        ON CONFLICT(user_id, day) DO UPDATE SET count = count + 1
+        "#,
+    )
+    .bind(user_id)
+    .bind(today)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Get a message by ID
+pub async fn get_message_by_id<'e, E>(
+    executor: E,
+    id: &MessageId,
+) -> Result<Option<Message>, DbError>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let message = sqlx::query_as::<_, Message>("SELECT * FROM messages WHERE id = ?")
+        .bind(id)
+        .fetch_optional(executor)
+        .await?;
+
+    Ok(message)
+}
+
+/// Update a message
+///
+/// `base_updated_at` is the `updated_at` the client last saw. If it no longer
+/// matches the stored row, another device edited the message in the meantime
+/// and `policy` decides the outcome. With `ConflictCopy`, the stored message is
+/// left untouched and the returned message is a new copy holding `content`.
+pub async fn update_message(
+    pool: &DbPool,
+    id: &MessageId,
+    user_id: &UserId,
+    content: &str,
+    base_updated_at: Option<DateTime<Utc>>,
+    policy: ConflictPolicy,
+) -> Result<Message, DbError> {
+    retry_busy(|| async move {
+        let mut tx = pool.begin().await?;
+        let updated =
+            update_message_in(&mut tx, id, user_id, content, base_updated_at, policy).await?;
+        tx.commit().await?;
+        Ok(updated)
+    })
+    .await
+}
+
+/// Update a message on the given connection
+pub async fn update_message_in(
+    conn: &mut SqliteConnection,
+    id: &MessageId,
+    user_id: &UserId,
+    content: &str,
+    base_updated_at: Option<DateTime<Utc>>,
+    policy: ConflictPolicy,
+) -> Result<Message, DbError> {
+    let updated_at = chrono::Utc::now();
+
+    let updated = match (policy, base_updated_at) {
+        (ConflictPolicy::ConflictCopy, Some(base)) => {
+            sqlx::query_as::<_, Message>(
+                r#"
+                UPDATE messages
+                SET content = ?, updated_at = ?,
+                    seq = (SELECT change_seq + 1 FROM users WHERE id = messages.user_id)
+                WHERE id = ? AND user_id = ? AND updated_at = ?
+                RETURNING *
+                "#,
+            )
+            .bind(content)
+            .bind(updated_at)
+            .bind(id)
+            .bind(user_id)
+            .bind(base)
+            .fetch_optional(&mut *conn)
+            .await?
+        }
+        _ => {
+            sqlx::query_as::<_, Message>(
+                r#"
+                UPDATE messages
+                SET content = ?, updated_at = ?,
+                    seq = (SELECT change_seq + 1 FROM users WHERE id = messages.user_id)
+                WHERE id = ? AND user_id = ?
+                RETURNING *
+                "#,
+            )
+            .bind(content)
+            .bind(updated_at)
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(&mut *conn)
+            .await?
+        }
+    };
+
+    match updated {
+        Some(updated) => {
+            // Claim the sequence number the row was just stamped with
+            next_seq(conn, user_id).await?;
+            Ok(updated)
+        }
+        None => match get_message_by_id(&mut *conn, id).await? {
+            Some(existing) if existing.user_id == *user_id => {
+                // Stale edit: keep the stored version and save the edit alongside it
+                let mut copy = Message::new(user_id.clone(), content.to_string());
+                copy.conflict_of = Some(existing.id);
+                create_message_in(conn, &copy).await
+            }
+            _ => Err(DbError::MessageNotFound),
+        },
+    }
+}
+
+/// Delete a message
+pub async fn delete_message(
+    pool: &DbPool,
+    id: &MessageId,
+    user_id: &UserId,
+) -> Result<(), DbError> {
+    retry_busy(|| async move {
+        let mut tx = pool.begin().await?;
+        delete_message_in(&mut tx, id, user_id).await?;
+        tx.commit().await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Delete a message on the given connection
+pub async fn delete_message_in(
+    conn: &mut SqliteConnection,
+    id: &MessageId,
+    user_id: &UserId,
+) -> Result<(), DbError> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM messages WHERE id = ? AND user_id = ?
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .execute(&mut *conn)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::MessageNotFound);
+    }
+
+    // Record a tombstone so syncing clients learn about the deletion
+    let deleted_at = chrono::Utc::now();
+    let seq = next_seq(conn, user_id).await?;
+    sqlx::query(
+        r#"
+        INSERT INTO tombstones (message_id, user_id, deleted_at, seq) VALUES (?, ?, ?, ?)
+        ON CONFLICT(message_id) DO UPDATE SET deleted_at = excluded.deleted_at, seq = excluded.seq
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(deleted_at)
+    .bind(seq)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Get IDs of messages deleted after the given timestamp
+pub async fn get_deleted_message_ids(
+    pool: &DbPool,
+    user_id: &UserId,
+    since: DateTime<Utc>,
+) -> Result<Vec<MessageId>, DbError> {
+    let rows: Vec<(MessageId,)> = sqlx::query_as(
+        r#"
+        SELECT message_id FROM tombstones
+        WHERE user_id = ? AND deleted_at > ?
+        ORDER BY deleted_at ASC
+        "#,
+    )
+    .bind(user_id)
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|(id,)| id).collect())
+}
+
+/// Get IDs of messages deleted after the given sequence number
+pub async fn get_deleted_message_ids_since_seq<'e, E>(
+    executor: E,
+    user_id: &UserId,
+    since_seq: i64,
+) -> Result<Vec<MessageId>, DbError>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let rows: Vec<(MessageId,)> = sqlx::query_as(
+        r#"
+        SELECT message_id FROM tombstones
+        WHERE user_id = ? AND seq > ?
+        ORDER BY seq ASC
+        "#,
+    )
+    .bind(user_id)
+    .bind(since_seq)
+    .fetch_all(executor)
+    .await?;
+
+    Ok(rows.into_iter().map(|(id,)| id).collect())
+}
+
+/// Count messages created before `before`, only `user_id`'s if given
+#[allow(dead_code)]
+pub async fn count_messages_created_before(
+    pool: &DbPool,
+    before: DateTime<Utc>,
+    user_id: Option<&UserId>,
+) -> Result<i64, DbError> {
+    let (count,): (i64,) = sqlx::query_as(
+        r#"
+        SELECT COUNT(*) FROM messages
+        WHERE created_at < ? AND (? IS NULL OR user_id = ?)
+        "#,
+    )
+    .bind(before)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count)
+}
+
+/// Delete up to `limit` of the oldest messages created before `before`,
+/// only `user_id`'s if given, and return how many were deleted
+///
+/// Each deletion leaves a tombstone, as `delete_message` does, so syncing
+/// clients drop the messages too. Call repeatedly until it returns 0; small
+/// batches keep the write lock short for a running server.
+#[allow(dead_code)]
+pub async fn purge_messages_created_before(
+    pool: &DbPool,
+    before: DateTime<Utc>,
+    user_id: Option<&UserId>,
+    limit: i64,
+) -> Result<u64, DbError> {
+    retry_busy(|| async move {
+        let mut tx = pool.begin().await?;
+        let rows: Vec<(MessageId, UserId)> = sqlx::query_as(
+            r#"
+            SELECT id, user_id FROM messages
+            WHERE created_at < ? AND (? IS NULL OR user_id = ?)
+            ORDER BY created_at ASC
+            LIMIT ?
+            "#,
+        )
+        .bind(before)
+        .bind(user_id)
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for (id, owner) in &rows {
+            delete_message_in(&mut tx, id, owner).await?;
+        }
+        tx.commit().await?;
+        Ok(rows.len() as u64)
+    })
+    .await
+}
+
+/// Count tombstones of messages deleted before `before`
+#[allow(dead_code)]
+pub async fn count_tombstones_before(pool: &DbPool, before: DateTime<Utc>) -> Result<i64, DbError> {
+    let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM tombstones WHERE deleted_at < ?")
+        .bind(before)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(count)
+}
+
+/// Forget up to `limit` tombstones of messages deleted before `before` and
+/// return how many were removed
+///
+/// Clients that last synced before then won't learn about those deletions.
+#[allow(dead_code)]
+pub async fn purge_tombstones_before(
+    pool: &DbPool,
+    before: DateTime<Utc>,
+    limit: i64,
+) -> Result<u64, DbError> {
+    retry_busy(|| async move {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM tombstones WHERE message_id IN (
+                SELECT message_id FROM tombstones WHERE deleted_at < ? LIMIT ?
+            )
+            "#,
+        )
+        .bind(before)
+        .bind(limit)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    })
+    .await
+}
+
+// ============ Template Operations ============
+
+/// Get all templates for a user, ordered by name
+pub async fn get_templates_for_user(
+    pool: &DbPool,
+    user_id: &UserId,
+) -> Result<Vec<Template>, DbError> {
+    let templates = sqlx::query_as::<_, Template>(
+        r#"
+        SELECT * FROM templates
+        WHERE user_id = ?
+        ORDER BY name ASC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(templates)
+}
+
+/// Get a template by ID, scoped to its owner
+pub async fn get_template_for_user(
+    pool: &DbPool,
+    id: &str,
+    user_id: &UserId,
+) -> Result<Option<Template>, DbError> {
+    let template =
+        sqlx::query_as::<_, Template>("SELECT * FROM templates WHERE id = ? AND user_id = ?")
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(template)
+}
+
+/// Create a new template
+pub async fn create_template(pool: &DbPool, template: &Template) -> Result<Template, DbError> {
+    let created = sqlx::query_as::<_, Template>(
+        r#"
+        INSERT INTO templates (id, user_id, name, content, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?)
+        RETURNING *
+        "#,
+    )
+    .bind(&template.id)
+    .bind(&template.user_id)
+    .bind(&template.name)
+    .bind(&template.content)
+    .bind(template.created_at)
+    .bind(template.updated_at)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(created)
+}
+
+/// Update a template's name and content
+pub async fn update_template(
+    pool: &DbPool,
+    id: &str,
+    user_id: &UserId,
+    name: &str,
+    content: &str,
+) -> Result<Template, DbError> {
+    let updated_at = chrono::Utc::now();
+
+    sqlx::query_as::<_, Template>(
+        r#"
+        UPDATE templates SET name = ?, content = ?, updated_at = ?
+        WHERE id = ? AND user_id = ?
+        RETURNING *
+        "#,
+    )
+    .bind(name)
+    .bind(content)
+    .bind(updated_at)
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or(DbError::TemplateNotFound)
+}
+
+/// Delete a template
+pub async fn delete_template(pool: &DbPool, id: &str, user_id: &UserId) -> Result<(), DbError> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM templates WHERE id = ? AND user_id = ?
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::TemplateNotFound);
+    }
+
+    Ok(())
+}
+
+// ============ Shared Journal Operations ============
+//
+// A journal is the set of messages owned by a user. Owners may grant other
+// accounts read or write access; every query below checks that membership.
+
+/// Grant (or change) a member's access to an owner's journal
+pub async fn add_journal_member(
+    pool: &DbPool,
+    owner_id: &UserId,
+    member_id: &UserId,
+    role: &str,
+) -> Result<(), DbError> {
+    let created_at = chrono::Utc::now().to_rfc3339();
+
+    sqlx::query(
+        r#"
+        INSERT INTO journal_members (owner_id, member_id, role, created_at)
+        VALUES (?, ?, ?, ?)
+        ON CONFLICT(owner_id, member_id) DO UPDATE SET role = excluded.role
+        "#,
+    )
+    .bind(owner_id)
+    .bind(member_id)
+    .bind(role)
+    .bind(&created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Revoke a member's access to an owner's journal
+pub async fn remove_journal_member(
+    pool: &DbPool,
+    owner_id: &UserId,
+    member_id: &UserId,
+) -> Result<(), DbError> {
+    let result = sqlx::query("DELETE FROM journal_members WHERE owner_id = ? AND member_id = ?")
+        .bind(owner_id)
+        .bind(member_id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::MemberNotFound);
+    }
+
+    Ok(())
+}
+
+/// List the members of an owner's journal
+pub async fn list_journal_members(
+    pool: &DbPool,
+    owner_id: &UserId,
+) -> Result<Vec<JournalMemberResponse>, DbError> {
+    let members = sqlx::query_as::<_, JournalMemberResponse>(
+        r#"
+        SELECT u.id AS user_id, u.email, u.username,
+            COALESCE(u.display_name, u.username) AS display_name, jm.role, jm.created_at
+        FROM journal_members jm
+        JOIN users u ON u.id = jm.member_id
+        WHERE jm.owner_id = ?
+        ORDER BY jm.created_at ASC
+        "#,
+    )
+    .bind(owner_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(members)
+}
+
+/// List the journals that have been shared with a member
+pub async fn list_shared_journals(
+    pool: &DbPool,
+    member_id: &UserId,
+) -> Result<Vec<SharedJournalResponse>, DbError> {
+    let journals = sqlx::query_as::<_, SharedJournalResponse>(
+        r#"
+        SELECT u.id AS owner_id, u.username,
+            COALESCE(u.display_name, u.username) AS display_name, jm.role
+        FROM journal_members jm
+        JOIN users u ON u.id = jm.owner_id
+        WHERE jm.member_id = ?
+        ORDER BY u.username ASC
+        "#,
+    )
+    .bind(member_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(journals)
+}
+
+/// Get a member's role on an owner's journal, if any
+pub async fn get_journal_role<'e, E>(
+    executor: E,
+    owner_id: &UserId,
+    member_id: &UserId,
+) -> Result<Option<String>, DbError>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let role: Option<(String,)> =
+        sqlx::query_as("SELECT role FROM journal_members WHERE owner_id = ? AND member_id = ?")
+            .bind(owner_id)
+            .bind(member_id)
+            .fetch_optional(executor)
+            .await?;
+
+    Ok(role.map(|(role,)| role))
+}
+
+/// Get messages from a shared journal on behalf of a member
+pub async fn get_journal_messages(
+    pool: &DbPool,
+    owner_id: &UserId,
+    member_id: &UserId,
+    since: Option<DateTime<Utc>>,
+) -> Result<Vec<Message>, DbError> {
+    if get_journal_role(pool, owner_id, member_id).await?.is_none() {
+        return Err(DbError::JournalAccessDenied);
+    }
+
+    get_messages_for_user(pool, owner_id, since).await
+}
+
+/// Create a message in a shared journal on behalf of a member with write access
+pub async fn create_journal_message(
+    pool: &DbPool,
+    member_id: &UserId,
+    message: &Message,
+) -> Result<Message, DbError> {
+    retry_busy(|| async move {
+        let mut tx = pool.begin().await?;
+        let created = create_journal_message_in(&mut tx, member_id, message).await?;
+        tx.commit().await?;
+        Ok(created)
+    })
+    .await
+}
+
+/// Create a journal message on the given connection, so access cannot be
+/// revoked between the role check and the insert
+pub async fn create_journal_message_in(
+    conn: &mut SqliteConnection,
+    member_id: &UserId,
+    message: &Message,
+) -> Result<Message, DbError> {
+    match get_journal_role(&mut *conn, &message.user_id, member_id).await? {
+        Some(role) if role == JOURNAL_ROLE_WRITE => {
+            let created = create_message_in(&mut *conn, message).await?;
+            record_message_created(conn, &message.user_id).await?;
+            Ok(created)
+        }
+        _ => Err(DbError::JournalAccessDenied),
+    }
+}
+
+// ============ Reaction Operations ============
+
+/// Check that a user may react to a message: they must own it or be a member
+/// of the owner's journal. Returns the owner.
+async fn ensure_can_react(
+    conn: &mut SqliteConnection,
+    message_id: &MessageId,
+    user_id: &UserId,
+) -> Result<UserId, DbError> {
+    let message = get_message_by_id(&mut *conn, message_id)
+        .await?
+        .ok_or(DbError::MessageNotFound)?;
+
+    let is_member = get_journal_role(&mut *conn, &message.user_id, user_id)
+        .await?
+        .is_some();
+    if message.user_id != *user_id && !is_member {
+        return Err(DbError::MessageNotFound);
+    }
+
+    Ok(message.user_id)
+}
+
+/// Add an emoji reaction to a message (no-op if already present)
+pub async fn add_reaction(
+    pool: &DbPool,
+    message_id: &MessageId,
+    user_id: &UserId,
+    emoji: &str,
+) -> Result<(), DbError> {
+    retry_busy(|| async move {
+        let mut tx = pool.begin().await?;
+        let owner_id = ensure_can_react(&mut tx, message_id, user_id).await?;
+
+        let created_at = chrono::Utc::now().to_rfc3339();
+
+        let result = sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO reactions (message_id, user_id, emoji, created_at)
+            VALUES (?, ?, ?, ?)
+            "#,
+        )
+        .bind(message_id)
+        .bind(user_id)
+        .bind(emoji)
+        .bind(&created_at)
+        .execute(&mut *tx)
+        .await?;
+        // Reactions are part of the owner's message list, so its ETag changes
+        if result.rows_affected() > 0 {
+            next_seq(&mut tx, &owner_id).await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    })
+    .await
+}
+
+/// Remove the user's emoji reaction from a message
+pub async fn remove_reaction(
+    pool: &DbPool,
+    message_id: &MessageId,
+    user_id: &UserId,
+    emoji: &str,
+) -> Result<(), DbError> {
+    retry_busy(|| async move {
+        let mut tx = pool.begin().await?;
+        let owner_id = ensure_can_react(&mut tx, message_id, user_id).await?;
+
+        let result =
+            sqlx::query("DELETE FROM reactions WHERE message_id = ? AND user_id = ? AND emoji = ?")
+                .bind(message_id)
+                .bind(user_id)
+                .bind(emoji)
+                .execute(&mut *tx)
+                .await?;
+        if result.rows_affected() > 0 {
+            next_seq(&mut tx, &owner_id).await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    })
+    .await
+}
+
+/// Aggregate reaction counts for every message in an owner's journal, keyed by message ID
+pub async fn get_reaction_counts(
+    pool: &DbPool,
+    owner_id: &UserId,
+) -> Result<HashMap<MessageId, Vec<ReactionCount>>, DbError> {
+    let rows: Vec<(MessageId, String, i64)> = sqlx::query_as(
+        r#"
+        SELECT r.message_id, r.emoji, COUNT(*)
+        FROM reactions r
+        JOIN messages m ON m.id = r.message_id
+        WHERE m.user_id = ?
+        GROUP BY r.message_id, r.emoji
+        ORDER BY MIN(r.created_at) ASC
+        "#,
+    )
+    .bind(owner_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut counts: HashMap<MessageId, Vec<ReactionCount>> = HashMap::new();
+    for (message_id, emoji, count) in rows {
+        counts
+            .entry(message_id)
+            .or_default()
+            .push(ReactionCount { emoji, count });
+    }
+
+    Ok(counts)
+}
+
+/// Get every reaction added by a user, oldest first
+pub async fn get_reactions_by_user(
+    pool: &DbPool,
+    user_id: &UserId,
+) -> Result<Vec<UserReaction>, DbError> {
+    let reactions = sqlx::query_as::<_, UserReaction>(
+        r#"
+        SELECT message_id, emoji, created_at FROM reactions
+        WHERE user_id = ?
+        ORDER BY created_at
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(reactions)
+}
+
+// ============ Push Subscription Operations ============
+
+/// Save a browser push subscription, moving the endpoint to `user_id` if it
+/// was registered by someone else on the same browser
+pub async fn save_push_subscription(
+    pool: &DbPool,
+    user_id: &UserId,
+    endpoint: &str,
+    p256dh: &str,
+    auth: &str,
+) -> Result<(), DbError> {
+    let created_at = chrono::Utc::now().to_rfc3339();
+
+    sqlx::query(
+        r#"
+        INSERT INTO push_subscriptions (endpoint, user_id, p256dh, auth, created_at)
+        VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT(endpoint) DO UPDATE SET
+            user_id = excluded.user_id,
+            p256dh = excluded.p256dh,
+            auth = excluded.auth
+        "#,
+    )
+    .bind(endpoint)
+    .bind(user_id)
+    .bind(p256dh)
+    .bind(auth)
+    .bind(&created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get all push subscriptions for a user
+pub async fn get_push_subscriptions(
+    pool: &DbPool,
+    user_id: &UserId,
+) -> Result<Vec<PushSubscription>, DbError> {
+    let subscriptions = sqlx::query_as::<_, PushSubscription>(
+        "SELECT * FROM push_subscriptions WHERE user_id = ? ORDER BY created_at ASC",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(subscriptions)
+}
+
+/// Remove a push subscription; a no-op if it does not exist
+pub async fn delete_push_subscription(
+    pool: &DbPool,
+    user_id: &UserId,
+    endpoint: &str,
+) -> Result<(), DbError> {
+    sqlx::query("DELETE FROM push_subscriptions WHERE endpoint = ? AND user_id = ?")
+        .bind(endpoint)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+// ============ Export Job Operations ============
+
+/// Queue an export job
+pub async fn create_export_job(pool: &DbPool, job: &ExportJob) -> Result<(), DbError> {
+    let mut conn = pool.acquire().await?;
+    create_export_job_in(&mut conn, job).await
+}
+
+/// Queue an export job on the given connection
+pub async fn create_export_job_in(
+    conn: &mut SqliteConnection,
+    job: &ExportJob,
+) -> Result<(), DbError> {
+    sqlx::query(
+        r#"
+        INSERT INTO export_jobs
+            (id, user_id, format, status, error, created_at, completed_at, schedule_id,
+             download_token, encrypt_to, encrypted, split_by_month, rows_processed, rows_total)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&job.id)
+    .bind(&job.user_id)
+    .bind(&job.format)
+    .bind(&job.status)
+    .bind(&job.error)
+    .bind(job.created_at)
+    .bind(job.completed_at)
+    .bind(&job.schedule_id)
+    .bind(&job.download_token)
+    .bind(&job.encrypt_to)
+    .bind(job.encrypted)
+    .bind(job.split_by_month)
+    .bind(job.rows_processed)
+    .bind(job.rows_total)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Get an export job by the token from an emailed download link
+pub async fn get_export_job_by_token(
+    pool: &DbPool,
+    id: &str,
+    token: &str,
+) -> Result<Option<ExportJob>, DbError> {
+    let job = sqlx::query_as::<_, ExportJob>(
+        "SELECT * FROM export_jobs WHERE id = ? AND download_token = ?",
+    )
+    .bind(id)
+    .bind(token)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(job)
+}
+
+/// Get an export job, only if owned by `user_id`
+pub async fn get_export_job(
+    pool: &DbPool,
+    id: &str,
+    user_id: &UserId,
+) -> Result<Option<ExportJob>, DbError> {
+    let job =
+        sqlx::query_as::<_, ExportJob>("SELECT * FROM export_jobs WHERE id = ? AND user_id = ?")
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(job)
+}
+
+/// Mark the oldest pending job as running and return it
+pub async fn claim_next_export_job(pool: &DbPool) -> Result<Option<ExportJob>, DbError> {
+    let job = sqlx::query_as::<_, ExportJob>(
+        r#"
+        UPDATE export_jobs SET status = ?
+        WHERE id = (
+            SELECT id FROM export_jobs WHERE status = ? ORDER BY created_at LIMIT 1
+        )
+        RETURNING *
+        "#,
+    )
+    .bind(EXPORT_STATUS_RUNNING)
+    .bind(EXPORT_STATUS_PENDING)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(job)
+}
+
+/// Record how many of a running job's messages have been read
+pub async fn set_export_job_progress(
+    pool: &DbPool,
+    id: &str,
+    processed: i64,
+    total: i64,
+) -> Result<(), DbError> {
+    sqlx::query("UPDATE export_jobs SET rows_processed = ?, rows_total = ? WHERE id = ?")
+        .bind(processed)
+        .bind(total)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Record that a running job completed, or failed with `error`
+pub async fn finish_export_job(
+    pool: &DbPool,
+    id: &str,
+    error: Option<&str>,
+) -> Result<(), DbError> {
+    let status = match error {
+        None => EXPORT_STATUS_COMPLETED,
+        Some(_) => EXPORT_STATUS_FAILED,
+    };
+
+    // The passphrase, if any, is no longer needed
+    sqlx::query(
+        r#"
+        UPDATE export_jobs SET status = ?, error = ?, completed_at = ?, encrypt_to = NULL
+        WHERE id = ?
+        "#,
+    )
+    .bind(status)
+    .bind(error)
+    .bind(chrono::Utc::now())
+    .bind(id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Put jobs left running by a previous process back in the queue
+pub async fn requeue_running_export_jobs(pool: &DbPool) -> Result<u64, DbError> {
+    let result = sqlx::query("UPDATE export_jobs SET status = ? WHERE status = ?")
+        .bind(EXPORT_STATUS_PENDING)
+        .bind(EXPORT_STATUS_RUNNING)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Delete jobs that finished before `before`, returning them so their files
+/// can be removed
+pub async fn delete_export_jobs_finished_before(
+    pool: &DbPool,
+    before: DateTime<Utc>,
+) -> Result<Vec<ExportJob>, DbError> {
+    let jobs = sqlx::query_as::<_, ExportJob>(
+        "DELETE FROM export_jobs WHERE completed_at < ? RETURNING *",
+    )
+    .bind(before)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(jobs)
+}
+
+// ============ Export Schedule Operations ============
+
+/// Create a recurring export
+pub async fn create_export_schedule(
+    pool: &DbPool,
+    schedule: &ExportSchedule,
+) -> Result<(), DbError> {
+    sqlx::query(
+        r#"
+        INSERT INTO export_schedules
+            (id, user_id, format, frequency, email, delivery, next_run_at, created_at, encrypt_to)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&schedule.id)
+    .bind(&schedule.user_id)
+    .bind(&schedule.format)
+    .bind(&schedule.frequency)
+    .bind(&schedule.email)
+    .bind(&schedule.delivery)
+    .bind(schedule.next_run_at)
+    .bind(schedule.created_at)
+    .bind(&schedule.encrypt_to)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get a recurring export, only if owned by `user_id`
+pub async fn get_export_schedule(
+    pool: &DbPool,
+    id: &str,
+    user_id: &UserId,
+) -> Result<Option<ExportSchedule>, DbError> {
+    let schedule = sqlx::query_as::<_, ExportSchedule>(
+        "SELECT * FROM export_schedules WHERE id = ? AND user_id = ?",
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(schedule)
+}
+
+/// List a user's recurring exports, oldest first
+pub async fn list_export_schedules(
+    pool: &DbPool,
+    user_id: &UserId,
+) -> Result<Vec<ExportSchedule>, DbError> {
+    let schedules = sqlx::query_as::<_, ExportSchedule>(
+        "SELECT * FROM export_schedules WHERE user_id = ? ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(schedules)
+}
+
+/// Delete a recurring export
+pub async fn delete_export_schedule(
+    pool: &DbPool,
+    id: &str,
+    user_id: &UserId,
+) -> Result<(), DbError> {
+    let result = sqlx::query("DELETE FROM export_schedules WHERE id = ? AND user_id = ?")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::ExportScheduleNotFound);
+    }
+
+    Ok(())
+}
+
+/// Get the recurring exports due to run at `now`
+pub async fn get_due_export_schedules(
+    pool: &DbPool,
+    now: DateTime<Utc>,
+) -> Result<Vec<ExportSchedule>, DbError> {
+    let schedules = sqlx::query_as::<_, ExportSchedule>(
+        "SELECT * FROM export_schedules WHERE next_run_at <= ? ORDER BY next_run_at",
+    )
+    .bind(now)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(schedules)
+}
+
+/// Queue a scheduled export job and move its schedule on to `next_run_at`,
+/// so a job is queued exactly once per run
+pub async fn queue_scheduled_export(
+    pool: &DbPool,
+    job: &ExportJob,
+    next_run_at: DateTime<Utc>,
+) -> Result<(), DbError> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("UPDATE export_schedules SET next_run_at = ? WHERE id = ?")
+        .bind(next_run_at)
+        .bind(&job.schedule_id)
+        .execute(&mut *tx)
+        .await?;
+    create_export_job_in(&mut tx, job).await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+// ============ WebDAV Destination Operations ============
+
+/// Set the WebDAV folder a user's exports are copied to, replacing any
+/// existing one
+pub async fn set_webdav_destination(
+    pool: &DbPool,
+    destination: &WebDavDestination,
+) -> Result<(), DbError> {
+    sqlx::query(
+        r#"
+        INSERT INTO webdav_destinations (user_id, url, username, password, created_at)
+        VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT(user_id) DO UPDATE SET
+            url = excluded.url,
+            username = excluded.username,
+            password = excluded.password,
+            created_at = excluded.created_at
+        "#,
+    )
+    .bind(&destination.user_id)
+    .bind(&destination.url)
+    .bind(&destination.username)
+    .bind(&destination.password)
+    .bind(destination.created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get a user's WebDAV export folder
+pub async fn get_webdav_destination(
+    pool: &DbPool,
+    user_id: &UserId,
+) -> Result<Option<WebDavDestination>, DbError> {
+    let destination = sqlx::query_as::<_, WebDavDestination>(
+        "SELECT * FROM webdav_destinations WHERE user_id = ?",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(destination)
+}
+
+/// Stop copying a user's exports to WebDAV
+pub async fn delete_webdav_destination(pool: &DbPool, user_id: &UserId) -> Result<(), DbError> {
+    sqlx::query("DELETE FROM webdav_destinations WHERE user_id = ?")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+// ============ Digest Subscription Operations ============
+
+/// Set how often a user's digest is emailed, replacing any existing setting
+pub async fn set_digest_subscription(
+    pool: &DbPool,
+    subscription: &DigestSubscription,
+) -> Result<(), DbError> {
+    sqlx::query(
+        r#"
+        INSERT INTO digest_subscriptions
+            (user_id, frequency, hour, weekday, timezone, next_run_at, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(user_id) DO UPDATE SET
+            frequency = excluded.frequency,
+            hour = excluded.hour,
+            weekday = excluded.weekday,
+            timezone = excluded.timezone,
+            next_run_at = excluded.next_run_at,
+            created_at = excluded.created_at
+        "#,
+    )
+    .bind(&subscription.user_id)
+    .bind(&subscription.frequency)
+    .bind(subscription.hour)
+    .bind(&subscription.weekday)
+    .bind(&subscription.timezone)
+    .bind(subscription.next_run_at)
+    .bind(subscription.created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get a user's digest setting
+pub async fn get_digest_subscription(
+    pool: &DbPool,
+    user_id: &UserId,
+) -> Result<Option<DigestSubscription>, DbError> {
+    let subscription = sqlx::query_as::<_, DigestSubscription>(
+        "SELECT * FROM digest_subscriptions WHERE user_id = ?",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(subscription)
+}
+
+/// Stop emailing a user's digest
+pub async fn delete_digest_subscription(pool: &DbPool, user_id: &UserId) -> Result<(), DbError> {
+    sqlx::query("DELETE FROM digest_subscriptions WHERE user_id = ?")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Digests whose next run is at or before `now`
+pub async fn get_due_digest_subscriptions(
+    pool: &DbPool,
+    now: DateTime<Utc>,
+) -> Result<Vec<DigestSubscription>, DbError> {
+    let subscriptions = sqlx::query_as::<_, DigestSubscription>(
+        "SELECT * FROM digest_subscriptions WHERE next_run_at <= ? ORDER BY next_run_at",
+    )
+    .bind(now)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(subscriptions)
+}
+
+/// Move a user's digest on to `next_run_at`
+pub async fn set_digest_next_run(
+    pool: &DbPool,
+    user_id: &UserId,
+    next_run_at: DateTime<Utc>,
+) -> Result<(), DbError> {
+    sqlx::query("UPDATE digest_subscriptions SET next_run_at = ? WHERE user_id = ?")
+        .bind(next_run_at)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+// ============ Reminder Operations ============
+
+/// Set when a user is reminded to write, replacing any existing reminder
+pub async fn set_reminder(pool: &DbPool, reminder: &Reminder) -> Result<(), DbError> {
+    sqlx::query(
+        r#"
+        INSERT INTO reminders
+            (user_id, time, days, timezone, email, push, channels, next_run_at, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(user_id) DO UPDATE SET
+            time = excluded.time,
+            days = excluded.days,
+            timezone = excluded.timezone,
+            email = excluded.email,
+            push = excluded.push,
+            channels = excluded.channels,
+            next_run_at = excluded.next_run_at,
+            created_at = excluded.created_at
+        "#,
+    )
+    .bind(&reminder.user_id)
+    .bind(&reminder.time)
+    .bind(&reminder.days)
+    .bind(&reminder.timezone)
+    .bind(reminder.email)
+    .bind(reminder.push)
+    .bind(reminder.channels)
+    .bind(reminder.next_run_at)
+    .bind(reminder.created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get a user's reminder
+pub async fn get_reminder(pool: &DbPool, user_id: &UserId) -> Result<Option<Reminder>, DbError> {
+    let reminder = sqlx::query_as::<_, Reminder>("SELECT * FROM reminders WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(reminder)
+}
+
+/// Stop reminding a user to write
+pub async fn delete_reminder(pool: &DbPool, user_id: &UserId) -> Result<(), DbError> {
+    sqlx::query("DELETE FROM reminders WHERE user_id = ?")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Reminders whose next run is at or before `now`
+pub async fn get_due_reminders(
+    pool: &DbPool,
+    now: DateTime<Utc>,
+) -> Result<Vec<Reminder>, DbError> {
+    let reminders = sqlx::query_as::<_, Reminder>(
+        "SELECT * FROM reminders WHERE next_run_at <= ? ORDER BY next_run_at",
+    )
+    .bind(now)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(reminders)
+}
+
+/// Move a user's reminder on to `next_run_at`
+pub async fn set_reminder_next_run(
+    pool: &DbPool,
+    user_id: &UserId,
+    next_run_at: DateTime<Utc>,
+) -> Result<(), DbError> {
+    sqlx::query("UPDATE reminders SET next_run_at = ? WHERE user_id = ?")
+        .bind(next_run_at)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+// ============ Notification Channel Operations ============
+
+/// Add a service a user's notifications are sent to
+pub async fn create_notification_channel(
+    pool: &DbPool,
+    channel: &NotificationChannel,
+) -> Result<(), DbError> {
+    sqlx::query(
+        r#"
+        INSERT INTO notification_channels (id, user_id, kind, url, token, created_at)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&channel.id)
+    .bind(&channel.user_id)
+    .bind(&channel.kind)
+    .bind(&channel.url)
+    .bind(&channel.token)
+    .bind(channel.created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get a notification channel, only if owned by `user_id`
+pub async fn get_notification_channel(
+    pool: &DbPool,
+    id: &str,
+    user_id: &UserId,
+) -> Result<Option<NotificationChannel>, DbError> {
+    let channel = sqlx::query_as::<_, NotificationChannel>(
+        "SELECT * FROM notification_channels WHERE id = ? AND user_id = ?",
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(channel)
+}
+
+/// List a user's notification channels, oldest first
+pub async fn list_notification_channels(
+    pool: &DbPool,
+    user_id: &UserId,
+) -> Result<Vec<NotificationChannel>, DbError> {
+    let channels = sqlx::query_as::<_, NotificationChannel>(
+        "SELECT * FROM notification_channels WHERE user_id = ? ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(channels)
+}
+
+/// Stop sending a user's notifications to a channel
+pub async fn delete_notification_channel(
+    pool: &DbPool,
+    id: &str,
+    user_id: &UserId,
+) -> Result<(), DbError> {
+    let result = sqlx::query("DELETE FROM notification_channels WHERE id = ? AND user_id = ?")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::NotificationChannelNotFound);
+    }
+
+    Ok(())
+}
+
+// ============ Telegram Operations ============
+
+/// Store a code for linking a user's Telegram chat, replacing any earlier one
+pub async fn create_telegram_link_code(
+    pool: &DbPool,
+    link_code: &TelegramLinkCode,
+) -> Result<(), DbError> {
+    sqlx::query(
+        r#"
+        INSERT INTO telegram_link_codes (code, user_id, expires_at)
+        VALUES (?, ?, ?)
+        ON CONFLICT(user_id) DO UPDATE SET
+            code = excluded.code,
+            expires_at = excluded.expires_at
+        "#,
+    )
+    .bind(&link_code.code)
+    .bind(&link_code.user_id)
+    .bind(link_code.expires_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Delete link codes that expired before `now`, returning how many
+pub async fn delete_expired_telegram_link_codes(
+    pool: &DbPool,
+    now: DateTime<Utc>,
+) -> Result<u64, DbError> {
+    let result = sqlx::query("DELETE FROM telegram_link_codes WHERE expires_at <= ?")
+        .bind(now)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Link a chat to the user a code was issued to, using up the code
+///
+/// Returns the user, or `None` if the code is unknown or has expired. A chat
+/// linked to another user before is moved over, and a user's earlier chat is
+/// replaced.
+pub async fn link_telegram_chat(
+    pool: &DbPool,
+    code: &str,
+    chat_id: i64,
+    now: DateTime<Utc>,
+) -> Result<Option<UserId>, DbError> {
+    retry_busy(|| async move {
+        let mut tx = pool.begin().await?;
+
+        let user_id: Option<UserId> = sqlx::query_scalar(
+            "DELETE FROM telegram_link_codes WHERE code = ? AND expires_at > ? RETURNING user_id",
+        )
+        .bind(code)
+        .bind(now)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(user_id) = user_id else {
+            return Ok(None);
+        };
+
+        sqlx::query("DELETE FROM telegram_links WHERE chat_id = ? OR user_id = ?")
+            .bind(chat_id)
+            .bind(&user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO telegram_links (user_id, chat_id, created_at) VALUES (?, ?, ?)")
+            .bind(&user_id)
+            .bind(chat_id)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(Some(user_id))
+    })
+    .await
+}
+
+/// Get the Telegram chat linked to a user
+pub async fn get_telegram_link(
+    pool: &DbPool,
+    user_id: &UserId,
+) -> Result<Option<TelegramLink>, DbError> {
+    let link = sqlx::query_as::<_, TelegramLink>("SELECT * FROM telegram_links WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(link)
+}
+
+/// Get the link of the user a Telegram chat belongs to
+pub async fn get_telegram_link_by_chat(
+    pool: &DbPool,
+    chat_id: i64,
+) -> Result<Option<TelegramLink>, DbError> {
+    let link = sqlx::query_as::<_, TelegramLink>("SELECT * FROM telegram_links WHERE chat_id = ?")
+        .bind(chat_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(link)
+}
+
+/// Stop saving messages from a user's Telegram chat
+pub async fn delete_telegram_link(pool: &DbPool, user_id: &UserId) -> Result<(), DbError> {
+    let result = sqlx::query("DELETE FROM telegram_links WHERE user_id = ?")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::TelegramNotLinked);
+    }
+
+    Ok(())
+}
+
+// ============ Inbound Email Operations ============
+
+/// Create or replace a user's inbound email address
+pub async fn save_inbound_email_address(
+    pool: &DbPool,
+    address: &InboundEmailAddress,
+) -> Result<(), DbError> {
+    sqlx::query(
+        r#"
+        INSERT INTO inbound_email_addresses (user_id, token, allowed_senders, created_at)
+        VALUES (?, ?, ?, ?)
+        ON CONFLICT(user_id) DO UPDATE SET
+            token = excluded.token,
+            allowed_senders = excluded.allowed_senders
+        "#,
+    )
+    .bind(&address.user_id)
+    .bind(&address.token)
+    .bind(&address.allowed_senders)
+    .bind(address.created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get a user's inbound email address
+pub async fn get_inbound_email_address(
+    pool: &DbPool,
+    user_id: &UserId,
+) -> Result<Option<InboundEmailAddress>, DbError> {
+    let address = sqlx::query_as::<_, InboundEmailAddress>(
+        "SELECT * FROM inbound_email_addresses WHERE user_id = ?",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(address)
+}
+
+/// Get the inbound email address with the given local part
+pub async fn get_inbound_email_address_by_token(
+    pool: &DbPool,
+    token: &str,
+) -> Result<Option<InboundEmailAddress>, DbError> {
+    let address = sqlx::query_as::<_, InboundEmailAddress>(
+        "SELECT * FROM inbound_email_addresses WHERE token = ?",
+    )
+    .bind(token)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(address)
+}
+
+/// Stop accepting mail for a user
+pub async fn delete_inbound_email_address(pool: &DbPool, user_id: &UserId) -> Result<(), DbError> {
+    let result = sqlx::query("DELETE FROM inbound_email_addresses WHERE user_id = ?")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::InboundEmailAddressNotFound);
+    }
+
+    Ok(())
+}
+
+// ============ Matrix Operations ============
+
+/// Connect a user's Matrix bot account, replacing any earlier one
+pub async fn set_matrix_account(pool: &DbPool, account: &MatrixAccount) -> Result<(), DbError> {
+    sqlx::query(
+        r#"
+        INSERT INTO matrix_accounts
+            (user_id, homeserver_url, access_token, bot_user_id, room_id, sync_token, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(user_id) DO UPDATE SET
+            homeserver_url = excluded.homeserver_url,
+            access_token = excluded.access_token,
+            bot_user_id = excluded.bot_user_id,
+            room_id = excluded.room_id,
+            sync_token = excluded.sync_token,
+            created_at = excluded.created_at
+        "#,
+    )
+    .bind(&account.user_id)
+    .bind(&account.homeserver_url)
+    .bind(&account.access_token)
+    .bind(&account.bot_user_id)
+    .bind(&account.room_id)
+    .bind(&account.sync_token)
+    .bind(account.created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get a user's Matrix bot account
+pub async fn get_matrix_account(
+    pool: &DbPool,
+    user_id: &UserId,
+) -> Result<Option<MatrixAccount>, DbError> {
+    let account =
+        sqlx::query_as::<_, MatrixAccount>("SELECT * FROM matrix_accounts WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(account)
+}
+
+/// List every connected Matrix account, for the sync worker
+pub async fn list_matrix_accounts(pool: &DbPool) -> Result<Vec<MatrixAccount>, DbError> {
+    let accounts = sqlx::query_as::<_, MatrixAccount>("SELECT * FROM matrix_accounts")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(accounts)
+}
+
+/// Record where a user's next Matrix sync continues from
+pub async fn set_matrix_sync_token(
+    pool: &DbPool,
+    user_id: &UserId,
+    sync_token: &str,
+) -> Result<(), DbError> {
+    sqlx::query("UPDATE matrix_accounts SET sync_token = ? WHERE user_id = ?")
+        .bind(sync_token)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Disconnect a user's Matrix bot account
+pub async fn delete_matrix_account(pool: &DbPool, user_id: &UserId) -> Result<(), DbError> {
+    let result = sqlx::query("DELETE FROM matrix_accounts WHERE user_id = ?")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::MatrixAccountNotFound);
+    }
+
+    Ok(())
+}
+
+// ============ Dormancy Operations ============
+
+/// Set a user's dormancy policy, replacing any earlier one and counting
+/// inactivity afresh
+pub async fn set_dormancy_policy(pool: &DbPool, policy: &DormancyPolicy) -> Result<(), DbError> {
+    sqlx::query(
+        r#"
+        INSERT INTO dormancy_policies
+            (user_id, inactive_days, export_email, purge, last_active_at, warnings_sent,
+             last_warning_at, triggered_at, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(user_id) DO UPDATE SET
+            inactive_days = excluded.inactive_days,
+            export_email = excluded.export_email,
+            purge = excluded.purge,
+            last_active_at = excluded.last_active_at,
+            warnings_sent = excluded.warnings_sent,
+            last_warning_at = excluded.last_warning_at,
+            triggered_at = excluded.triggered_at,
+            created_at = excluded.created_at
+        "#,
+    )
+    .bind(&policy.user_id)
+    .bind(policy.inactive_days)
+    .bind(&policy.export_email)
+    .bind(policy.purge)
+    .bind(policy.last_active_at)
+    .bind(policy.warnings_sent)
+    .bind(policy.last_warning_at)
+    .bind(policy.triggered_at)
+    .bind(policy.created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get a user's dormancy policy
+pub async fn get_dormancy_policy(
+    pool: &DbPool,
+    user_id: &UserId,
+) -> Result<Option<DormancyPolicy>, DbError> {
+    let policy =
+        sqlx::query_as::<_, DormancyPolicy>("SELECT * FROM dormancy_policies WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(policy)
+}
+
+/// List the policies not carried out since their user's last activity
+pub async fn list_pending_dormancy_policies(pool: &DbPool) -> Result<Vec<DormancyPolicy>, DbError> {
+    let policies = sqlx::query_as::<_, DormancyPolicy>(
+        "SELECT * FROM dormancy_policies WHERE triggered_at IS NULL",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(policies)
+}
+
+/// Note that a user was active at `at`, which restarts their dormancy
+/// policy, if they have one
+pub async fn record_user_activity(
+    pool: &DbPool,
+    user_id: &UserId,
+    at: DateTime<Utc>,
+) -> Result<(), DbError> {
+    sqlx::query(
+        r#"
+        UPDATE dormancy_policies
+        SET last_active_at = ?, warnings_sent = 0, last_warning_at = NULL, triggered_at = NULL
+        WHERE user_id = ?
+        "#,
+    )
+    .bind(at)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Record that `warnings_sent` warnings have been emailed, the last at `at`
+pub async fn record_dormancy_warning(
+    pool: &DbPool,
+    user_id: &UserId,
+    warnings_sent: i64,
+    at: DateTime<Utc>,
+) -> Result<(), DbError> {
+    sqlx::query(
+        "UPDATE dormancy_policies SET warnings_sent = ?, last_warning_at = ? WHERE user_id = ?",
+    )
+    .bind(warnings_sent)
+    .bind(at)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Mark a policy carried out, unless the user has been active since
+/// `last_active_at` or it already was; returns whether it was marked
+pub async fn mark_dormancy_triggered(
+    pool: &DbPool,
+    user_id: &UserId,
+    last_active_at: DateTime<Utc>,
+    at: DateTime<Utc>,
+) -> Result<bool, DbError> {
+    let result = sqlx::query(
+        r#"
+        UPDATE dormancy_policies SET triggered_at = ?
+        WHERE user_id = ? AND last_active_at = ? AND triggered_at IS NULL
+        "#,
+    )
+    .bind(at)
+    .bind(user_id)
+    .bind(last_active_at)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Remove a user's dormancy policy
+pub async fn delete_dormancy_policy(pool: &DbPool, user_id: &UserId) -> Result<(), DbError> {
+    let result = sqlx::query("DELETE FROM dormancy_policies WHERE user_id = ?")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::DormancyPolicyNotFound);
+    }
+
+    Ok(())
+}
+
+// ============ Avatar Operations ============
+
+/// Set a user's avatar, replacing any earlier one
+pub async fn set_user_avatar(pool: &DbPool, avatar: &Avatar) -> Result<(), DbError> {
+    sqlx::query(
+        r#"
+        INSERT INTO user_avatars (user_id, content_type, data, updated_at)
+        VALUES (?, ?, ?, ?)
+        ON CONFLICT(user_id) DO UPDATE SET
+            content_type = excluded.content_type,
+            data = excluded.data,
+            updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(&avatar.user_id)
+    .bind(&avatar.content_type)
+    .bind(&avatar.data)
+    .bind(avatar.updated_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get a user's avatar
+pub async fn get_user_avatar(pool: &DbPool, user_id: &UserId) -> Result<Option<Avatar>, DbError> {
+    let avatar = sqlx::query_as::<_, Avatar>("SELECT * FROM user_avatars WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(avatar)
+}
+
+// ============ Webhook Operations ============
+
+/// Register a URL a user's message changes are sent to
+pub async fn create_webhook(pool: &DbPool, webhook: &Webhook) -> Result<(), DbError> {
+    sqlx::query(
+        "INSERT INTO webhooks (id, user_id, url, secret, created_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(&webhook.id)
+    .bind(&webhook.user_id)
+    .bind(&webhook.url)
+    .bind(&webhook.secret)
+    .bind(webhook.created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get a webhook, only if owned by `user_id`
+pub async fn get_webhook(
+    pool: &DbPool,
+    id: &str,
+    user_id: &UserId,
+) -> Result<Option<Webhook>, DbError> {
+    let webhook =
+        sqlx::query_as::<_, Webhook>("SELECT * FROM webhooks WHERE id = ? AND user_id = ?")
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(webhook)
+}
+
+/// List a user's webhooks, oldest first
+pub async fn list_webhooks(pool: &DbPool, user_id: &UserId) -> Result<Vec<Webhook>, DbError> {
+    let webhooks = sqlx::query_as::<_, Webhook>(
+        "SELECT * FROM webhooks WHERE user_id = ? ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(webhooks)
+}
+
+/// Delete a webhook along with its delivery log
+pub async fn delete_webhook(pool: &DbPool, id: &str, user_id: &UserId) -> Result<(), DbError> {
+    let result = sqlx::query("DELETE FROM webhooks WHERE id = ? AND user_id = ?")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::WebhookNotFound);
+    }
+
+    Ok(())
+}
+
+/// Queue an event for each of a user's webhooks, due straight away
+///
+/// Returns how many deliveries were queued.
+pub async fn queue_webhook_deliveries(
+    pool: &DbPool,
+    user_id: &UserId,
+    event: &str,
+    payload: &str,
+) -> Result<u64, DbError> {
+    let now = Utc::now();
+    let mut tx = pool.begin().await?;
+
+    let webhook_ids: Vec<(String,)> = sqlx::query_as("SELECT id FROM webhooks WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_all(&mut *tx)
+        .await?;
+    for (webhook_id,) in &webhook_ids {
+        sqlx::query(
+            r#"
+            INSERT INTO webhook_deliveries
+                (id, webhook_id, event, payload, status, attempts, next_attempt_at,
+                 created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, 0, ?, ?, ?)
+            "#,
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(webhook_id)
+        .bind(event)
+        .bind(payload)
+        .bind(WEBHOOK_DELIVERY_PENDING)
+        .bind(now)
+        .bind(now)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(webhook_ids.len() as u64)
+}
+
+/// Get up to `limit` pending deliveries due at `now`, longest waiting first
+pub async fn get_due_webhook_deliveries(
+    pool: &DbPool,
+    now: DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<DueWebhookDelivery>, DbError> {
+    let deliveries = sqlx::query_as::<_, DueWebhookDelivery>(
+        r#"
+        SELECT d.id, d.event, d.payload, d.attempts, w.url, w.secret
+        FROM webhook_deliveries d
+        JOIN webhooks w ON w.id = d.webhook_id
+        WHERE d.status = ? AND d.next_attempt_at <= ?
+        ORDER BY d.next_attempt_at
+        LIMIT ?
+        "#,
+    )
+    .bind(WEBHOOK_DELIVERY_PENDING)
+    .bind(now)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(deliveries)
+}
+
+/// Record the outcome of an attempt at a delivery
+///
+/// `next_attempt_at` is set when the delivery stays pending for a retry.
+pub async fn record_webhook_attempt(
+    pool: &DbPool,
+    id: &str,
+    status: &str,
+    response_status: Option<i64>,
+    error: Option<&str>,
+    next_attempt_at: Option<DateTime<Utc>>,
+) -> Result<(), DbError> {
+    sqlx::query(
+        r#"
+        UPDATE webhook_deliveries
+        SET status = ?, attempts = attempts + 1, response_status = ?, error = ?,
+            next_attempt_at = ?, updated_at = ?
+        WHERE id = ?
+        "#,
+    )
+    .bind(status)
+    .bind(response_status)
+    .bind(error)
+    .bind(next_attempt_at)
+    .bind(Utc::now())
+    .bind(id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// List up to `limit` of a webhook's deliveries, newest first
+pub async fn list_webhook_deliveries(
+    pool: &DbPool,
+    webhook_id: &str,
+    limit: i64,
+) -> Result<Vec<WebhookDelivery>, DbError> {
+    let deliveries = sqlx::query_as::<_, WebhookDelivery>(
+        r#"
+        SELECT * FROM webhook_deliveries
+        WHERE webhook_id = ?
+        ORDER BY created_at DESC, id
+        LIMIT ?
+        "#,
+    )
+    .bind(webhook_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(deliveries)
+}
+
+/// Forget deliveries made before `before`, whatever their outcome
+pub async fn delete_webhook_deliveries_before(
+    pool: &DbPool,
+    before: DateTime<Utc>,
+) -> Result<u64, DbError> {
+    let result = sqlx::query("DELETE FROM webhook_deliveries WHERE created_at < ?")
+        .bind(before)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+// ============ Idempotency Operations ============
+
+/// Get the stored response for an idempotency key, ignoring entries created
+/// before `not_before`
+pub async fn get_idempotent_response(
+    pool: &DbPool,
+    user_id: &UserId,
+    key: &str,
+    not_before: &str,
+) -> Result<Option<IdempotentResponse>, DbError> {
+    let response = sqlx::query_as::<_, IdempotentResponse>(
+        r#"
+        SELECT method, path, status, content_type, body
+        FROM idempotency_keys
+        WHERE user_id = ? AND key = ? AND created_at >= ?
+        "#,
+    )
+    .bind(user_id)
+    .bind(key)
+    .bind(not_before)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(response)
+}
+
+/// Store the response for an idempotency key, replacing an expired entry
+pub async fn save_idempotent_response(
+    pool: &DbPool,
+    user_id: &UserId,
+    key: &str,
+    response: &IdempotentResponse,
+) -> Result<(), DbError> {
+    let created_at = chrono::Utc::now().to_rfc3339();
+
+    sqlx::query(
+        r#"
+        INSERT OR REPLACE INTO idempotency_keys
+            (user_id, key, method, path, status, content_type, body, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(user_id)
+    .bind(key)
+    .bind(&response.method)
+    .bind(&response.path)
+    .bind(response.status)
+    .bind(&response.content_type)
+    .bind(&response.body)
+    .bind(&created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Forget responses stored before `before`, which are no longer replayed,
+/// returning how many
+pub async fn delete_idempotent_responses_before(
+    pool: &DbPool,
+    before: DateTime<Utc>,
+) -> Result<u64, DbError> {
+    // Stored as RFC 3339 strings, like the cutoff in get_idempotent_response
+    let result = sqlx::query("DELETE FROM idempotency_keys WHERE created_at < ?")
+        .bind(before.to_rfc3339())
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+// ============ Feature Flag Operations ============
+
+/// Every feature operators have switched, with whether it is on
+pub async fn list_feature_flags(pool: &DbPool) -> Result<Vec<(String, bool)>, DbError> {
+    let flags = sqlx::query_as::<_, (String, bool)>(
+        "SELECT name, enabled FROM feature_flags ORDER BY name",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(flags)
+}
+
+/// Switch a feature on or off, overriding the config
+pub async fn set_feature_flag(pool: &DbPool, name: &str, enabled: bool) -> Result<(), DbError> {
+    sqlx::query(
+        r#"
+        INSERT INTO feature_flags (name, enabled, updated_at)
+        VALUES (?, ?, ?)
+        ON CONFLICT(name) DO UPDATE SET
+            enabled = excluded.enabled,
+            updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(name)
+    .bind(enabled)
+    .bind(Utc::now())
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Drop an operator's switch, so the config decides again
+pub async fn delete_feature_flag(pool: &DbPool, name: &str) -> Result<(), DbError> {
+    sqlx::query("DELETE FROM feature_flags WHERE name = ?")
+        .bind(name)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+// ============ Announcement Operations ============
+
+/// Save a new announcement
+pub async fn create_announcement(
+    pool: &DbPool,
+    announcement: &Announcement,
+) -> Result<(), DbError> {
+    sqlx::query(
+        r#"
+        INSERT INTO announcements
+            (id, message, level, starts_at, ends_at, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&announcement.id)
+    .bind(&announcement.message)
+    .bind(&announcement.level)
+    .bind(announcement.starts_at)
+    .bind(announcement.ends_at)
+    .bind(announcement.created_at)
+    .bind(announcement.updated_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Replace an announcement's text, level and window
+///
+/// Users who dismissed it don't see it again.
+pub async fn update_announcement(
+    pool: &DbPool,
+    id: &str,
+    message: &str,
+    level: &str,
+    starts_at: Option<DateTime<Utc>>,
+    ends_at: Option<DateTime<Utc>>,
+) -> Result<Announcement, DbError> {
+    sqlx::query_as::<_, Announcement>(
+        r#"
+        UPDATE announcements
+        SET message = ?, level = ?, starts_at = ?, ends_at = ?, updated_at = ?
+        WHERE id = ?
+        RETURNING *
+        "#,
+    )
+    .bind(message)
+    .bind(level)
+    .bind(starts_at)
+    .bind(ends_at)
+    .bind(Utc::now())
+    .bind(id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or(DbError::AnnouncementNotFound)
+}
+
+/// Delete an announcement and who dismissed it
+pub async fn delete_announcement(pool: &DbPool, id: &str) -> Result<(), DbError> {
+    let result = sqlx::query("DELETE FROM announcements WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DbError::AnnouncementNotFound);
+    }
+
+    Ok(())
+}
+
+/// List every announcement, past and scheduled ones included, newest first
+pub async fn list_announcements(pool: &DbPool) -> Result<Vec<Announcement>, DbError> {
+    let announcements =
+        sqlx::query_as::<_, Announcement>("SELECT * FROM announcements ORDER BY created_at DESC")
+            .fetch_all(pool)
+            .await?;
+
+    Ok(announcements)
+}
+
+/// List the announcements showing at `now`, newest first, leaving out those
+/// `user_id` dismissed if given
+pub async fn list_active_announcements(
+    pool: &DbPool,
+    now: DateTime<Utc>,
+    user_id: Option<&UserId>,
+) -> Result<Vec<Announcement>, DbError> {
+    let announcements = sqlx::query_as::<_, Announcement>(
+        r#"
+        SELECT * FROM announcements
+        WHERE (starts_at IS NULL OR starts_at <= ?)
+          AND (ends_at IS NULL OR ends_at > ?)
+          AND NOT EXISTS (
+              SELECT 1 FROM announcement_dismissals
+              WHERE announcement_id = announcements.id AND user_id = ?
+          )
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(now)
+    .bind(now)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(announcements)
+}
+
+/// Stop showing an announcement to a user; dismissing it again does nothing
+pub async fn dismiss_announcement(
+    pool: &DbPool,
+    id: &str,
+    user_id: &UserId,
+) -> Result<(), DbError> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO announcement_dismissals (announcement_id, user_id, dismissed_at)
+        SELECT id, ?, ? FROM announcements WHERE id = ?
+        ON CONFLICT(announcement_id, user_id) DO NOTHING
+        "#,
+    )
+    .bind(user_id)
+    .bind(Utc::now())
+    .bind(id)
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 && get_announcement(pool, id).await?.is_none() {
+        return Err(DbError::AnnouncementNotFound);
+    }
+
+    Ok(())
+}
+
+async fn get_announcement(pool: &DbPool, id: &str) -> Result<Option<Announcement>, DbError> {
+    let announcement =
+        sqlx::query_as::<_, Announcement>("SELECT * FROM announcements WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(announcement)
+}
+
+// ============ Audit Operations ============
+
+/// Append an event to the audit log
+pub async fn insert_audit_event(pool: &DbPool, event: &AuditEvent) -> Result<(), DbError> {
+    sqlx::query(
+        r#"
+        INSERT INTO audit_events
+            (created_at, action, actor, target_type, target_id, metadata)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(event.created_at)
+    .bind(&event.action)
+    .bind(&event.actor)
+    .bind(&event.target_type)
+    .bind(&event.target_id)
+    .bind(&event.metadata)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Up to `limit` audit events matching `filter`, newest first
+pub async fn list_audit_events(
+    pool: &DbPool,
+    filter: &AuditFilter,
+    limit: i64,
+) -> Result<Vec<AuditEvent>, DbError> {
+    let action_prefix = filter.action.as_ref().map(|action| format!("{}.*", action));
+
+    let events = sqlx::query_as::<_, AuditEvent>(
+        r#"
+        SELECT * FROM audit_events
+        WHERE (? IS NULL OR action = ? OR action GLOB ?)
+          AND (? IS NULL OR actor = ?)
+          AND (? IS NULL OR target_id = ?)
+          AND (? IS NULL OR created_at >= ?)
+          AND (? IS NULL OR created_at < ?)
+          AND (? IS NULL OR id < ?)
+        ORDER BY id DESC
+        LIMIT ?
+        "#,
+    )
+    .bind(&filter.action)
+    .bind(&filter.action)
+    .bind(&action_prefix)
+    .bind(&filter.actor)
+    .bind(&filter.actor)
+    .bind(&filter.target_id)
+    .bind(&filter.target_id)
+    .bind(filter.since)
+    .bind(filter.since)
+    .bind(filter.until)
+    .bind(filter.until)
+    .bind(filter.before_id)
+    .bind(filter.before_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::hash_password;
+
+    async fn setup_test_db() -> DbPool {
+        // Use in-memory SQLite database for tests
+        init_pool("sqlite::memory:").await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_retry_busy_gives_up_after_bounded_attempts() {
+        let mut attempts = 0;
+        let result: Result<(), DbError> = retry_busy(|| {
+            attempts += 1;
+            async { Err(DbError::DatabaseBusy) }
+        })
+        .await;
+
+        assert!(matches!(result, Err(DbError::DatabaseBusy)));
+        assert_eq!(attempts, BUSY_RETRY_ATTEMPTS);
+
+        // Other errors are returned without retrying
+        let mut attempts = 0;
+        let result: Result<(), DbError> = retry_busy(|| {
+            attempts += 1;
+            async { Err(DbError::MessageNotFound) }
+        })
+        .await;
+
+        assert!(matches!(result, Err(DbError::MessageNotFound)));
+        assert_eq!(attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_locked_database_reports_busy() {
+        let path = std::env::temp_dir().join(format!("dissipate-busy-{}.db", uuid::Uuid::new_v4()));
+        let options = DbOptions {
+            busy_timeout: Duration::ZERO,
+            ..DbOptions::default()
+        };
+        let pool = init_pool_with(&format!("sqlite://{}", path.display()), &options)
+            .await
+            .unwrap();
+        let user = create_test_user("busy@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        // Another connection holds the write lock
+        let mut holder = pool.acquire().await.unwrap();
+        sqlx::query("BEGIN IMMEDIATE")
+            .execute(&mut *holder)
+            .await
+            .unwrap();
+
+        let result = update_user_username(&pool, &user.id, "blocked").await;
+        assert!(result.unwrap_err().is_busy());
+
+        let message = Message::new(user.id.clone(), "Blocked".to_string());
+        let result = create_message(&pool, &message).await;
+        assert!(matches!(result, Err(DbError::DatabaseBusy)));
+
+        sqlx::query("ROLLBACK").execute(&mut *holder).await.unwrap();
+        drop(holder);
+        create_message(&pool, &message).await.unwrap();
+
+        pool.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_read_pool_sees_writes_but_cannot_write() {
+        let path =
+            std::env::temp_dir().join(format!("dissipate-pools-{}.db", uuid::Uuid::new_v4()));
+        let pools = init_pools_with(
+            &format!("sqlite://{}", path.display()),
+            &DbOptions::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(pools.write.options().get_max_connections(), 1);
+
+        let user = create_test_user("pools@example.com");
+        create_user(&pools.write, &user).await.unwrap();
+        let found = find_user_by_id(&pools.read, &user.id).await.unwrap();
+        assert!(found.is_some());
+
+        let result = update_user_username(&pools.read, &user.id, "reader").await;
+        assert!(result.is_err());
+
+        pools.read.close().await;
+        pools.write.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_truncate_checkpoint_empties_wal() {
+        let path = std::env::temp_dir().join(format!("dissipate-wal-{}.db", uuid::Uuid::new_v4()));
+        let pools = init_pools_with(
+            &format!("sqlite://{}", path.display()),
+            &DbOptions::default(),
+        )
+        .await
+        .unwrap();
+        let user = create_test_user("wal@example.com");
+        create_user(&pools.write, &user).await.unwrap();
+        assert!(wal_size_bytes(&pools.read).await.unwrap().unwrap() > 0);
+
+        let result = wal_checkpoint(&pools.write, CheckpointMode::Truncate)
+            .await
+            .unwrap();
+
+        assert!(!result.busy);
+        assert_eq!(wal_size_bytes(&pools.read).await.unwrap(), Some(0));
+        assert!(find_user_by_id(&pools.read, &user.id)
+            .await
+            .unwrap()
+            .is_some());
+
+        pools.read.close().await;
+        pools.write.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_optimize_reclaims_free_pages() {
+        let path =
+            std::env::temp_dir().join(format!("dissipate-optimize-{}.db", uuid::Uuid::new_v4()));
+        let pools = init_pools_with(
+            &format!("sqlite://{}", path.display()),
+            &DbOptions::default(),
+        )
+        .await
+        .unwrap();
+        let user = create_test_user("optimize@example.com");
+        create_user(&pools.write, &user).await.unwrap();
+        for i in 0..200 {
+            let message = Message::new(user.id.clone(), format!("{} {}", i, "x".repeat(2000)));
+            create_message(&pools.write, &message).await.unwrap();
+        }
+        delete_user(&pools.write, &user.id).await.unwrap();
+
+        let result = optimize(&pools.write).await.unwrap();
+
+        // New databases use incremental auto-vacuum
+        assert!(result.vacuumed_bytes.unwrap() > 0);
+        assert_eq!(free_bytes(&pools.read).await.unwrap(), 0);
+        assert!(!result.checkpoint.busy);
+        assert_eq!(wal_size_bytes(&pools.read).await.unwrap(), Some(0));
+
+        pools.read.close().await;
+        pools.write.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_wal_size_unknown_for_memory_database() {
+        let pool = init_pool("sqlite::memory:").await.unwrap();
+        assert_eq!(wal_size_bytes(&pool).await.unwrap(), None);
+        assert!("sometimes".parse::<CheckpointMode>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_database_reports_orphans_and_counts() {
+        let pool = init_pool("sqlite::memory:").await.unwrap();
+        let user = create_test_user("check@example.com");
+        create_user(&pool, &user).await.unwrap();
+        create_message(&pool, &Message::new(user.id.clone(), "Kept".to_string()))
+            .await
+            .unwrap();
+
+        let report = check_database(&pool).await.unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.row_counts["users"], 1);
+        assert_eq!(report.row_counts["messages"], 1);
+
+        // Rows written while foreign keys were off can outlive their user
+        let mut conn = pool.acquire().await.unwrap();
+        sqlx::query("PRAGMA foreign_keys = OFF")
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        let orphan = Message::new(UserId::generate(), "Orphan".to_string());
+        sqlx::query(
+            "INSERT INTO messages (id, user_id, content, created_at, updated_at) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&orphan.id)
+        .bind(&orphan.user_id)
+        .bind(&orphan.content)
+        .bind(orphan.created_at)
+        .bind(orphan.updated_at)
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+        drop(conn);
+
+        let report = check_database(&pool).await.unwrap();
+        assert!(!report.is_ok());
+        assert!(report.integrity_errors.is_empty());
+        assert_eq!(report.orphaned_rows.get("messages"), Some(&1));
+        assert_eq!(report.row_counts["messages"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_instance_stats_counts_messages_per_day() {
+        let pool = init_pool("sqlite::memory:").await.unwrap();
+        let user = create_test_user("stats@example.com");
+        create_user(&pool, &user).await.unwrap();
+        let mut old = Message::new(user.id.clone(), "Last year".to_string());
+        old.created_at = Utc::now() - chrono::Duration::days(365);
+        create_message(&pool, &old).await.unwrap();
+        for content in ["One", "Two"] {
+            create_message(&pool, &Message::new(user.id.clone(), content.to_string()))
+                .await
+                .unwrap();
+        }
+
+        let stats = instance_stats(&pool, 7).await.unwrap();
+        assert_eq!(stats.user_count, 1);
+        assert_eq!(stats.messages_per_day.len(), 7);
+        let today = Utc::now().date_naive().to_string();
+        assert_eq!(stats.messages_per_day[&today], 2);
+        assert_eq!(stats.messages_per_day.values().sum::<i64>(), 2);
+        assert!(stats.database_bytes > 0);
+    }
+
+    #[tokio::test]
+    async fn test_storage_stats_counts_messages_per_user() {
+        let pool = init_pool("sqlite::memory:").await.unwrap();
+        let writer = create_test_user("writer@example.com");
+        create_user(&pool, &writer).await.unwrap();
+        let idle = create_test_user("idle@example.com");
+        create_user(&pool, &idle).await.unwrap();
+        for content in ["One", "Two"] {
+            create_message(&pool, &Message::new(writer.id.clone(), content.to_string()))
+                .await
+                .unwrap();
+        }
+
+        let stats = storage_stats(&pool).await.unwrap();
+        assert_eq!(
+            stats.messages_per_user,
+            vec![
+                ("writer@example.com".to_string(), 2),
+                ("idle@example.com".to_string(), 0),
+            ]
+        );
+        assert!(stats.free_bytes >= 0);
+        let index = stats
+            .indexes
+            .iter()
+            .find(|index| index.name == "idx_messages_user_id")
+            .unwrap();
+        assert_eq!(index.table, "messages");
+        assert!(index.problems.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_audit_events_filters_and_pages() {
+        let pool = init_pool("sqlite::memory:").await.unwrap();
+        for (action, target) in [
+            ("admin.user.create", "user-1"),
+            ("admin.user.delete", "user-1"),
+            ("admin.maintenance.enable", "api"),
+            ("messages.import", "user-2"),
+        ] {
+            let event = AuditEvent::new(action, "admin_token").with_target("user", target);
+            insert_audit_event(&pool, &event).await.unwrap();
+        }
+
+        let all = list_audit_events(&pool, &AuditFilter::default(), 10)
+            .await
+            .unwrap();
+        assert_eq!(all.len(), 4);
+        assert_eq!(all[0].action, "messages.import");
+
+        let filter = AuditFilter {
+            action: Some("admin.user".to_string()),
+            ..Default::default()
+        };
+        let users = list_audit_events(&pool, &filter, 10).await.unwrap();
+        assert_eq!(users.len(), 2);
+
+        let filter = AuditFilter {
+            target_id: Some("user-1".to_string()),
+            ..Default::default()
+        };
+        let first_page = list_audit_events(&pool, &filter, 1).await.unwrap();
+        assert_eq!(first_page[0].action, "admin.user.delete");
+        let filter = AuditFilter {
+            before_id: Some(first_page[0].id),
+            ..filter
+        };
+        let second_page = list_audit_events(&pool, &filter, 1).await.unwrap();
+        assert_eq!(second_page[0].action, "admin.user.create");
+    }
+
+    #[tokio::test]
+    async fn test_memory_database_shares_one_pool() {
+        let pools = init_pools_with("sqlite::memory:", &DbOptions::default())
+            .await
+            .unwrap();
+        let user = create_test_user("memory@example.com");
+        create_user(&pools.write, &user).await.unwrap();
+
+        assert!(find_user_by_id(&pools.read, &user.id)
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_db_options_preset_with_overrides() {
+        let config = DatabaseConfig {
+            preset: Some("busy".to_string()),
+            max_connections: Some(32),
+            synchronous: Some("full".to_string()),
+            ..Default::default()
+        };
+
+        let options = DbOptions::from_config(&config).unwrap();
+
+        assert_eq!(options.max_connections, 32);
+        assert!(matches!(options.synchronous, SqliteSynchronous::Full));
+        assert_eq!(options.busy_timeout, DbOptions::busy().busy_timeout);
+        assert_eq!(options.cache_size_kib, DbOptions::busy().cache_size_kib);
+    }
+
+    #[test]
+    fn test_db_options_rejects_invalid_values() {
+        let preset = DatabaseConfig {
+            preset: Some("huge".to_string()),
+            ..Default::default()
+        };
+        let connections = DatabaseConfig {
+            max_connections: Some(0),
+            ..Default::default()
+        };
+        let synchronous = DatabaseConfig {
+            synchronous: Some("sometimes".to_string()),
+            ..Default::default()
+        };
+
+        assert!(DbOptions::from_config(&preset).is_err());
+        assert!(DbOptions::from_config(&connections).is_err());
+        assert!(DbOptions::from_config(&synchronous).is_err());
+        assert!(DbOptions::from_config(&DatabaseConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_read_key_file_trims_newline() {
+        let path = std::env::temp_dir().join(format!("dissipate-key-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "s3cret key\n").unwrap();
+
+        let key = read_key_file(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(key, "s3cret key");
+    }
+
+    #[cfg(not(feature = "sqlcipher"))]
+    #[tokio::test]
+    async fn test_encryption_key_requires_sqlcipher() {
+        let options = DbOptions {
+            encryption_key: Some("secret".to_string()),
+            ..Default::default()
+        };
+
+        let result = init_pool_with("sqlite::memory:", &options).await;
+
+        assert!(matches!(result, Err(DbError::EncryptionUnavailable)));
+    }
+
+    #[tokio::test]
+    async fn test_migrations_are_recorded() {
+        let pool = setup_test_db().await;
+
+        let applied: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM _sqlx_migrations")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(applied as usize, MIGRATOR.iter().count());
+
+        // Running again is a no-op
+        run_migrations(&pool).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pending_migrations() {
+        let pool = setup_test_db().await;
+        assert_eq!(pending_migrations(&pool).await.unwrap(), 0);
+
+        // As if the binary were newer than the schema
+        sqlx::query("DELETE FROM _sqlx_migrations WHERE version = (SELECT MAX(version) FROM _sqlx_migrations)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(pending_migrations(&pool).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_migrations_upgrade_legacy_database() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+
+        // Schema as created by releases before migrations existed
+        sqlx::query(
+            "CREATE TABLE users (id TEXT PRIMARY KEY, email TEXT UNIQUE NOT NULL, \
+             username TEXT NOT NULL, password_hash TEXT NOT NULL, salt TEXT NOT NULL, \
+             created_at TEXT NOT NULL, updated_at TEXT NOT NULL)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "CREATE TABLE messages (id TEXT PRIMARY KEY, user_id TEXT NOT NULL, \
+             content TEXT NOT NULL, created_at TEXT NOT NULL, updated_at TEXT NOT NULL)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let old_user_id = UserId::generate();
+        sqlx::query(
+            "INSERT INTO users VALUES (?, 'old@example.com', 'old', 'hash', 'salt', \
+             '2023-06-01 12:00:00', '2023-06-01T14:00:00+02:00')",
+        )
+        .bind(&old_user_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        run_migrations(&pool).await.unwrap();
+
+        // Timestamps in other layouts are rewritten in UTC
+        let old_user = find_user_by_id(&pool, &old_user_id).await.unwrap().unwrap();
+        assert_eq!(
+            old_user.created_at.to_rfc3339(),
+            "2023-06-01T12:00:00+00:00"
+        );
+        assert_eq!(old_user.updated_at, old_user.created_at);
+        let (stored,): (String,) = sqlx::query_as("SELECT updated_at FROM users WHERE id = ?")
+            .bind(&old_user_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(stored, "2023-06-01T12:00:00+00:00");
+
+        let user = create_test_user("legacy@example.com");
+        create_user(&pool, &user).await.unwrap();
+        let message = Message::new(user.id.clone(), "After upgrade".to_string());
+        let created = create_message(&pool, &message).await.unwrap();
+        assert_eq!(created.seq, 1);
+    }
+
+    fn create_test_user(email: &str) -> User {
+        let (hash, salt) = hash_password("password123").unwrap();
+        User::new(
+            email.to_string(),
+            "testuser".to_string(),
+            hash,
+            salt,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_init_pool_creates_tables() {
+        let pool = setup_test_db().await;
+
+        // Tables should exist
+        let result = sqlx::query("SELECT name FROM sqlite_master WHERE type='table' AND name='users'")
+            .fetch_optional(&pool)
+            .await
+            .unwrap();
+        assert!(result.is_some());
+
+        let result = sqlx::query("SELECT name FROM sqlite_master WHERE type='table' AND name='messages'")
+            .fetch_optional(&pool)
+            .await
+            .unwrap();
+        assert!(result.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_create_user_success() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("test@example.com");
+
+        let result = create_user(&pool, &user).await;
+
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_create_user_duplicate_email_fails() {
+        let pool = setup_test_db().await;
+        let user1 = create_test_user("duplicate@example.com");
+        let user2 = create_test_user("duplicate@example.com");
+
+        create_user(&pool, &user1).await.unwrap();
+        let result = create_user(&pool, &user2).await;
+
+        assert!(matches!(result, Err(DbError::EmailAlreadyExists)));
+    }
+
+    #[tokio::test]
+    async fn test_find_user_by_email_exists() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("find@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let found = find_user_by_email(&pool, "find@example.com").await.unwrap();
+
+        assert!(found.is_some());
+        assert_eq!(found.unwrap().email, "find@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_find_user_by_email_not_exists() {
+        let pool = setup_test_db().await;
+
+        let found = find_user_by_email(&pool, "nonexistent@example.com").await.unwrap();
+
+        assert!(found.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_find_user_by_id() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("byid@example.com");
+        let user_id = user.id.clone();
+        create_user(&pool, &user).await.unwrap();
+
+        let found = find_user_by_id(&pool, &user_id).await.unwrap();
+
+        assert!(found.is_some());
+        assert_eq!(found.unwrap().id, user_id);
+    }
+
+    #[tokio::test]
+    async fn test_update_user_email() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("old@example.com");
+        let user_id = user.id.clone();
+        create_user(&pool, &user).await.unwrap();
+
+        update_user_email(&pool, &user_id, "new@example.com").await.unwrap();
+
+        let found = find_user_by_id(&pool, &user_id).await.unwrap().unwrap();
+        assert_eq!(found.email, "new@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_update_user_email_to_taken_email_fails() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("mine@example.com");
+        let other = create_test_user("taken@example.com");
+        create_user(&pool, &user).await.unwrap();
+        create_user(&pool, &other).await.unwrap();
+
+        let result = update_user_email(&pool, &user.id, "taken@example.com").await;
+
+        assert!(matches!(result, Err(DbError::EmailAlreadyExists)));
+        let found = find_user_by_id(&pool, &user.id).await.unwrap().unwrap();
+        assert_eq!(found.email, "mine@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_signups_with_same_email() {
+        let pool = setup_test_db().await;
+        let first = create_test_user("race@example.com");
+        let second = create_test_user("race@example.com");
+
+        let (a, b) = tokio::join!(create_user(&pool, &first), create_user(&pool, &second));
+
+        assert_eq!(a.is_ok() as u8 + b.is_ok() as u8, 1);
+        assert!(matches!(a.and(b), Err(DbError::EmailAlreadyExists)));
+    }
+
+    #[tokio::test]
+    async fn test_update_user_username() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("username@example.com");
+        let user_id = user.id.clone();
+        create_user(&pool, &user).await.unwrap();
+
+        update_user_username(&pool, &user_id, "newusername").await.unwrap();
+
+        let found = find_user_by_id(&pool, &user_id).await.unwrap().unwrap();
+        assert_eq!(found.username, "newusername");
+    }
+
+    #[tokio::test]
+    async fn test_update_user_display_name() {
+        let pool = setup_test_db().await;
+        let owner = create_test_user("shown-owner@example.com");
+        let member = create_test_user("shown-member@example.com");
+        create_user(&pool, &owner).await.unwrap();
+        create_user(&pool, &member).await.unwrap();
+        add_journal_member(&pool, &owner.id, &member.id, "read")
+            .await
+            .unwrap();
+
+        // Without a display name the username is shown
+        let journals = list_shared_journals(&pool, &member.id).await.unwrap();
+        assert_eq!(journals[0].display_name, "testuser");
+
+        update_user_display_name(&pool, &owner.id, Some("Ada"))
+            .await
+            .unwrap();
+        let found = find_user_by_id(&pool, &owner.id).await.unwrap().unwrap();
+        assert_eq!(found.display_name.as_deref(), Some("Ada"));
+        assert_eq!(found.username, "testuser");
+        let journals = list_shared_journals(&pool, &member.id).await.unwrap();
+        assert_eq!(journals[0].display_name, "Ada");
+
+        update_user_display_name(&pool, &owner.id, None)
+            .await
+            .unwrap();
+        let found = find_user_by_id(&pool, &owner.id).await.unwrap().unwrap();
+        assert_eq!(found.display_name, None);
+    }
+
+    #[tokio::test]
+    async fn test_sorted_pages() {
+        let pool = setup_test_db().await;
+        for email in ["c@example.com", "a@example.com", "b@example.com"] {
+            create_user(&pool, &create_test_user(email)).await.unwrap();
+        }
+        let page = SortedPage {
+            sort: "email",
+            descending: false,
+            limit: Some(2),
+            offset: 1,
+        };
+        let users = list_users_page(&pool, &page).await.unwrap();
+        let emails: Vec<&str> = users.iter().map(|u| u.email.as_str()).collect();
+        assert_eq!(emails, ["b@example.com", "c@example.com"]);
+
+        // Created in order, but updated in reverse
+        let user = users[0].clone();
+        let start = chrono::Utc::now() - chrono::Duration::hours(1);
+        for i in 0..3 {
+            let mut message = Message::new(user.id.clone(), format!("Entry {}", i));
+            message.created_at = start + chrono::Duration::minutes(i);
+            message.updated_at = start + chrono::Duration::minutes(10 - i);
+            create_message(&pool, &message).await.unwrap();
+        }
+        let contents = |messages: Vec<Message>| -> Vec<String> {
+            messages.into_iter().map(|m| m.content).collect()
+        };
+
+        let page = SortedPage {
+            sort: "created_at",
+            descending: true,
+            limit: None,
+            offset: 1,
+        };
+        let messages = get_messages_for_user_page(&pool, &user.id, None, &page)
+            .await
+            .unwrap();
+        assert_eq!(contents(messages), ["Entry 1", "Entry 0"]);
+
+        let page = SortedPage {
+            sort: "updated_at",
+            descending: true,
+            limit: Some(1),
+            offset: 0,
+        };
+        let messages = get_messages_for_user_page(&pool, &user.id, None, &page)
+            .await
+            .unwrap();
+        assert_eq!(contents(messages), ["Entry 0"]);
+
+        let since = Some(start + chrono::Duration::minutes(9) + chrono::Duration::seconds(30));
+        let messages = get_messages_for_user_page(&pool, &user.id, since, &page)
+            .await
+            .unwrap();
+        assert_eq!(contents(messages), ["Entry 0"]);
+    }
+
+    #[tokio::test]
+    async fn test_update_user_password() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("password@example.com");
+        let user_id = user.id.clone();
+        let old_hash = user.password_hash.clone();
+        create_user(&pool, &user).await.unwrap();
+
+        let (new_hash, new_salt) = hash_password("newpassword").unwrap();
+        update_user_password(&pool, &user_id, &new_hash, &new_salt).await.unwrap();
+
+        let found = find_user_by_id(&pool, &user_id).await.unwrap().unwrap();
+        assert_ne!(found.password_hash, old_hash);
+    }
+
+    #[tokio::test]
+    async fn test_create_message() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("msg@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let message = Message::new(user.id.clone(), "Hello, world!".to_string());
+        let created = create_message(&pool, &message).await.unwrap();
+
+        assert_eq!(created.content, "Hello, world!");
+        assert_eq!(created.user_id, user.id);
+    }
+
+    #[tokio::test]
+    async fn test_get_messages_for_user() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("getmsgs@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let msg1 = Message::new(user.id.clone(), "Message 1".to_string());
+        let msg2 = Message::new(user.id.clone(), "Message 2".to_string());
+        create_message(&pool, &msg1).await.unwrap();
+        create_message(&pool, &msg2).await.unwrap();
+
+        let messages = get_messages_for_user(&pool, &user.id, None).await.unwrap();
+
+        assert_eq!(messages.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_get_messages_for_user_filters_by_since() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("since@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let msg1 = Message::new(user.id.clone(), "Old message".to_string());
+        create_message(&pool, &msg1).await.unwrap();
+
+        // Wait a moment and create another message
+        let future_timestamp = chrono::Utc::now();
+
+        let messages = get_messages_for_user(&pool, &user.id, Some(future_timestamp))
+            .await
+            .unwrap();
+
+        // No messages should be newer than the future timestamp
+        assert_eq!(messages.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_get_messages_for_user_since_compares_instants() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("offset@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let mut message = Message::new(user.id.clone(), "Written at ten UTC".to_string());
+        message.created_at = "2024-01-01T10:00:00Z".parse().unwrap();
+        message.updated_at = message.created_at;
+        create_message(&pool, &message).await.unwrap();
+
+        // 11:00 at +02:00 is 09:00 UTC, an hour before the message; as
+        // strings it would sort after it
+        let since = crate::utils::parse_timestamp("2024-01-01T11:00:00+02:00").unwrap();
+        let messages = get_messages_for_user(&pool, &user.id, Some(since))
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+
+        let since = crate::utils::parse_timestamp("2024-01-01T11:30:00+01:00").unwrap();
+        let messages = get_messages_for_user(&pool, &user.id, Some(since))
+            .await
+            .unwrap();
+        assert!(messages.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_messages_for_user_hides_scheduled() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("scheduled@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let visible = Message::new(user.id.clone(), "Visible now".to_string());
+        let mut future = Message::new(user.id.clone(), "Dear future me".to_string());
+        future.publish_at = Some(chrono::Utc::now() + chrono::Duration::days(365));
+        let mut past = Message::new(user.id.clone(), "Already published".to_string());
+        past.publish_at = Some(chrono::Utc::now() - chrono::Duration::days(1));
+        create_message(&pool, &visible).await.unwrap();
+        create_message(&pool, &future).await.unwrap();
+        create_message(&pool, &past).await.unwrap();
+
+        let messages = get_messages_for_user(&pool, &user.id, None).await.unwrap();
+
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().all(|m| m.content != "Dear future me"));
+    }
+
+    #[tokio::test]
+    async fn test_get_message_by_id() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("getbyid@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let message = Message::new(user.id.clone(), "Find me!".to_string());
+        let msg_id = message.id.clone();
+        create_message(&pool, &message).await.unwrap();
+
+        let found = get_message_by_id(&pool, &msg_id).await.unwrap();
+
+        assert!(found.is_some());
+        assert_eq!(found.unwrap().content, "Find me!");
+    }
+
+    #[tokio::test]
+    async fn test_update_message() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("update@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let message = Message::new(user.id.clone(), "Original content".to_string());
+        let msg_id = message.id.clone();
+        create_message(&pool, &message).await.unwrap();
+
+        let updated = update_message(
+            &pool,
+            &msg_id,
+            &user.id,
+            "Updated content",
+            None,
+            ConflictPolicy::LastWriterWins,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(updated.content, "Updated content");
+    }
+
+    #[tokio::test]
+    async fn test_writes_return_stored_rows() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("returning@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let created = create_message(&pool, &Message::new(user.id.clone(), "First".to_string()))
+            .await
+            .unwrap();
+        let stored = get_message_by_id(&pool, &created.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(created.seq, stored.seq);
+        assert_eq!(created.updated_at, stored.updated_at);
+
+        let updated = update_message(
+            &pool,
+            &created.id,
+            &user.id,
+            "Second",
+            None,
+            ConflictPolicy::LastWriterWins,
+        )
+        .await
+        .unwrap();
+        let stored = get_message_by_id(&pool, &created.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.content, stored.content);
+        assert_eq!(updated.updated_at, stored.updated_at);
+        assert_eq!(updated.seq, stored.seq);
+        assert_eq!(updated.seq, get_change_seq(&pool, &user.id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_update_message_wrong_user_fails() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("owner@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let message = Message::new(user.id.clone(), "My message".to_string());
+        let msg_id = message.id.clone();
+        create_message(&pool, &message).await.unwrap();
+
+        let result = update_message(
+            &pool,
+            &msg_id,
+            &UserId::generate(),
+            "Hacked!",
+            None,
+            ConflictPolicy::LastWriterWins,
+        )
+        .await;
+
+        assert!(matches!(result, Err(DbError::MessageNotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_update_message_stale_base_creates_conflict_copy() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("conflict@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let message = Message::new(user.id.clone(), "Original".to_string());
+        create_message(&pool, &message).await.unwrap();
+        let base = message.updated_at;
+
+        // Device A edits against the current version
+        let first = update_message(
+            &pool,
+            &message.id,
+            &user.id,
+            "Edit from A",
+            Some(base),
+            ConflictPolicy::ConflictCopy,
+        )
+        .await
+        .unwrap();
+        assert_eq!(first.id, message.id);
+
+        // Device B edits against the now-stale version
+        let second = update_message(
+            &pool,
+            &message.id,
+            &user.id,
+            "Edit from B",
+            Some(base),
+            ConflictPolicy::ConflictCopy,
+        )
+        .await
+        .unwrap();
+        assert_ne!(second.id, message.id);
+        assert_eq!(second.conflict_of, Some(message.id.clone()));
+
+        let stored = get_message_by_id(&pool, &message.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.content, "Edit from A");
+    }
+
+    #[tokio::test]
+    async fn test_update_message_stale_base_last_writer_wins() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("lww@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let message = Message::new(user.id.clone(), "Original".to_string());
+        create_message(&pool, &message).await.unwrap();
+
+        let updated = update_message(
+            &pool,
+            &message.id,
+            &user.id,
+            "Overwritten",
+            "1970-01-01T00:00:00Z".parse().ok(),
+            ConflictPolicy::LastWriterWins,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(updated.id, message.id);
+        assert_eq!(updated.content, "Overwritten");
+    }
+
+    #[tokio::test]
+    async fn test_delete_message() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("delete@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let message = Message::new(user.id.clone(), "Delete me".to_string());
+        let msg_id = message.id.clone();
+        create_message(&pool, &message).await.unwrap();
+
+        delete_message(&pool, &msg_id, &user.id).await.unwrap();
+
+        let found = get_message_by_id(&pool, &msg_id).await.unwrap();
+        assert!(found.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_delete_message_wrong_user_fails() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("nodelete@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let message = Message::new(user.id.clone(), "Protected".to_string());
+        let msg_id = message.id.clone();
+        create_message(&pool, &message).await.unwrap();
+
+        let result = delete_message(&pool, &msg_id, &UserId::generate()).await;
+
+        assert!(matches!(result, Err(DbError::MessageNotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_upsert_message_is_idempotent() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("upsert@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let message_id = MessageId::generate();
+        let message =
+            Message::with_id(message_id.clone(), user.id.clone(), "First try".to_string());
+        let (_, created) = upsert_message(&pool, &message).await.unwrap();
+        assert!(created);
+
+        let (stored, created) = upsert_message(&pool, &message).await.unwrap();
+        assert!(!created);
+        assert_eq!(stored.content, "First try");
+
+        let edited = Message::with_id(message_id, user.id.clone(), "Edited offline".to_string());
+        let (stored, created) = upsert_message(&pool, &edited).await.unwrap();
+        assert!(!created);
+        assert_eq!(stored.content, "Edited offline");
+
+        let messages = get_messages_for_user(&pool, &user.id, None).await.unwrap();
+        assert_eq!(messages.len(), 1);
+        // Retries don't count as more messages created today
+        assert_eq!(get_message_counts(&pool, &user.id).await.unwrap(), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_upsert_message_other_users_id_conflicts() {
+        let pool = setup_test_db().await;
+        let user1 = create_test_user("upsert1@example.com");
+        let user2 = create_test_user("upsert2@example.com");
+        create_user(&pool, &user1).await.unwrap();
+        create_user(&pool, &user2).await.unwrap();
+
+        let shared_id = MessageId::generate();
+        let mine = Message::with_id(shared_id.clone(), user1.id.clone(), "Mine".to_string());
+        upsert_message(&pool, &mine).await.unwrap();
+
+        let theirs = Message::with_id(shared_id, user2.id.clone(), "Theirs".to_string());
+        let result = upsert_message(&pool, &theirs).await;
+
+        assert!(matches!(result, Err(DbError::MessageIdConflict)));
+    }
+
+    #[tokio::test]
+    async fn test_delete_message_records_tombstone() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("tombstone@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let before = chrono::Utc::now();
+        let message = Message::new(user.id.clone(), "Short-lived".to_string());
+        create_message(&pool, &message).await.unwrap();
+        delete_message(&pool, &message.id, &user.id).await.unwrap();
+
+        let deleted = get_deleted_message_ids(&pool, &user.id, before)
+            .await
+            .unwrap();
+        assert_eq!(deleted, vec![message.id.clone()]);
+
+        // Re-creating the same ID clears the tombstone
+        create_message(&pool, &message).await.unwrap();
+        let deleted = get_deleted_message_ids(&pool, &user.id, before)
+            .await
+            .unwrap();
+        assert!(deleted.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_expired_tokens_are_pruned() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("tokens@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let response = IdempotentResponse {
+            method: "POST".to_string(),
+            path: "/api/messages".to_string(),
+            status: 201,
+            content_type: None,
+            body: Vec::new(),
+        };
+        save_idempotent_response(&pool, &user.id, "key-1", &response)
+            .await
+            .unwrap();
+        create_telegram_link_code(&pool, &TelegramLinkCode::new(user.id.clone()))
+            .await
+            .unwrap();
+
+        let now = Utc::now();
+        let hour = chrono::Duration::hours(1);
+        assert_eq!(
+            delete_idempotent_responses_before(&pool, now - hour)
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            delete_expired_telegram_link_codes(&pool, now)
+                .await
+                .unwrap(),
+            0
+        );
+
+        assert_eq!(
+            delete_idempotent_responses_before(&pool, now + hour)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            delete_expired_telegram_link_codes(&pool, now + hour)
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_purge_messages_and_tombstones() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("purge@example.com");
+        create_user(&pool, &user).await.unwrap();
+        let other = create_test_user("kept@example.com");
+        create_user(&pool, &other).await.unwrap();
+
+        let old = chrono::Utc::now() - chrono::Duration::days(100);
+        for owner in [&user, &other] {
+            for content in ["Old", "Older", "Oldest"] {
+                let mut message = Message::new(owner.id.clone(), content.to_string());
+                message.created_at = old;
+                create_message(&pool, &message).await.unwrap();
+            }
+        }
+        let recent = Message::new(user.id.clone(), "Recent".to_string());
+        create_message(&pool, &recent).await.unwrap();
+
+        let cutoff = chrono::Utc::now() - chrono::Duration::days(90);
+        assert_eq!(
+            count_messages_created_before(&pool, cutoff, None)
+                .await
+                .unwrap(),
+            6
+        );
+        assert_eq!(
+            count_messages_created_before(&pool, cutoff, Some(&user.id))
+                .await
+                .unwrap(),
+            3
+        );
+
+        // Batches of two until nothing is left
+        let mut deleted = Vec::new();
+        loop {
+            match purge_messages_created_before(&pool, cutoff, Some(&user.id), 2)
+                .await
+                .unwrap()
+            {
+                0 => break,
+                n => deleted.push(n),
+            }
+        }
+        assert_eq!(deleted, vec![2, 1]);
+        assert_eq!(count_messages_for_user(&pool, &user.id).await.unwrap(), 1);
+        assert_eq!(count_messages_for_user(&pool, &other.id).await.unwrap(), 3);
+        assert_eq!(
+            get_deleted_message_ids_since_seq(&pool, &user.id, 0)
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+
+        // Only tombstones older than the cutoff are forgotten
+        assert_eq!(count_tombstones_before(&pool, cutoff).await.unwrap(), 0);
+        let now = chrono::Utc::now() + chrono::Duration::seconds(1);
+        assert_eq!(count_tombstones_before(&pool, now).await.unwrap(), 3);
+        assert_eq!(purge_tombstones_before(&pool, now, 500).await.unwrap(), 3);
+        assert_eq!(count_tombstones_before(&pool, now).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_messages_fingerprint_changes_with_content() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("fingerprint@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let empty = get_messages_fingerprint(&pool, &user.id).await.unwrap();
+        assert_eq!(
+            empty,
+            get_messages_fingerprint(&pool, &user.id).await.unwrap()
+        );
+
+        let message = Message::new(user.id.clone(), "Hello".to_string());
+        create_message(&pool, &message).await.unwrap();
+        let after_create = get_messages_fingerprint(&pool, &user.id).await.unwrap();
+        assert_ne!(empty, after_create);
+
+        add_reaction(&pool, &message.id, &user.id, "👍")
+            .await
+            .unwrap();
+        let after_reaction = get_messages_fingerprint(&pool, &user.id).await.unwrap();
+        assert_ne!(after_create, after_reaction);
+
+        // Swapping one reaction for another leaves the count as it was
+        remove_reaction(&pool, &message.id, &user.id, "👍")
+            .await
+            .unwrap();
+        add_reaction(&pool, &message.id, &user.id, "🎉")
+            .await
+            .unwrap();
+        let after_swap = get_messages_fingerprint(&pool, &user.id).await.unwrap();
+        assert_ne!(after_reaction, after_swap);
+
+        assert!(matches!(
+            get_messages_fingerprint(&pool, &UserId::generate()).await,
+            Err(DbError::UserNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_change_seq_increases_on_every_change() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("seq@example.com");
+        create_user(&pool, &user).await.unwrap();
+        assert_eq!(get_change_seq(&pool, &user.id).await.unwrap(), 0);
+
+        let message = Message::new(user.id.clone(), "v1".to_string());
+        let created = create_message(&pool, &message).await.unwrap();
+        assert_eq!(created.seq, 1);
+
+        let updated = update_message(
+            &pool,
+            &message.id,
+            &user.id,
+            "v2",
+            None,
+            ConflictPolicy::LastWriterWins,
+        )
+        .await
+        .unwrap();
+        assert_eq!(updated.seq, 2);
+
+        let changed = get_messages_since_seq(&pool, &user.id, 1).await.unwrap();
+        assert_eq!(changed.len(), 1);
+
+        delete_message(&pool, &message.id, &user.id).await.unwrap();
+        assert_eq!(get_change_seq(&pool, &user.id).await.unwrap(), 3);
+
+        let deleted = get_deleted_message_ids_since_seq(&pool, &user.id, 2)
+            .await
+            .unwrap();
+        assert_eq!(deleted, vec![message.id]);
+        assert!(get_messages_since_seq(&pool, &user.id, 2)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_user_isolation_messages() {
+        let pool = setup_test_db().await;
+        let user1 = create_test_user("user1@example.com");
+        let user2 = create_test_user("user2@example.com");
+        create_user(&pool, &user1).await.unwrap();
+        create_user(&pool, &user2).await.unwrap();
+
+        let msg1 = Message::new(user1.id.clone(), "User 1's message".to_string());
+        let msg2 = Message::new(user2.id.clone(), "User 2's message".to_string());
+        create_message(&pool, &msg1).await.unwrap();
+        create_message(&pool, &msg2).await.unwrap();
+
+        let user1_messages = get_messages_for_user(&pool, &user1.id, None).await.unwrap();
+        let user2_messages = get_messages_for_user(&pool, &user2.id, None).await.unwrap();
+
+        assert_eq!(user1_messages.len(), 1);
+        assert_eq!(user2_messages.len(), 1);
+        assert_eq!(user1_messages[0].content, "User 1's message");
+        assert_eq!(user2_messages[0].content, "User 2's message");
+    }
+
+    #[tokio::test]
+    async fn test_create_and_list_templates() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("templates@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let morning = Template::new(
+            user.id.clone(),
+            "Morning".to_string(),
+            "Grateful for:".to_string(),
+        );
+        let evening = Template::new(
+            user.id.clone(),
+            "Evening".to_string(),
+            "Today I learned:".to_string(),
+        );
+        create_template(&pool, &morning).await.unwrap();
+        create_template(&pool, &evening).await.unwrap();
+
+        let templates = get_templates_for_user(&pool, &user.id).await.unwrap();
+
+        assert_eq!(templates.len(), 2);
+        assert_eq!(templates[0].name, "Evening");
+        assert_eq!(templates[1].name, "Morning");
+    }
+
+    #[tokio::test]
+    async fn test_update_template() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("updatetemplate@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let template = Template::new(user.id.clone(), "Daily".to_string(), "Old".to_string());
+        create_template(&pool, &template).await.unwrap();
+
+        let updated = update_template(&pool, &template.id, &user.id, "Daily", "New")
+            .await
+            .unwrap();
+
+        assert_eq!(updated.content, "New");
+    }
+
+    #[tokio::test]
+    async fn test_template_wrong_user_not_found() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("templateowner@example.com");
+        create_user(&pool, &user).await.unwrap();
+
+        let template = Template::new(user.id.clone(), "Private".to_string(), "Mine".to_string());
+        create_template(&pool, &template).await.unwrap();
+
+        let found = get_template_for_user(&pool, &template.id, &UserId::generate())
+            .await
+            .unwrap();
+        assert!(found.is_none());
+
+        let result = delete_template(&pool, &template.id, &UserId::generate()).await;
+        assert!(matches!(result, Err(DbError::TemplateNotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_journal_messages_require_membership() {
+        let pool = setup_test_db().await;
+        let owner = create_test_user("owner-journal@example.com");
+        let reader = create_test_user("reader@example.com");
+        create_user(&pool, &owner).await.unwrap();
+        create_user(&pool, &reader).await.unwrap();
+
+        let message = Message::new(owner.id.clone(), "Family news".to_string());
+        create_message(&pool, &message).await.unwrap();
+
+        let result = get_journal_messages(&pool, &owner.id, &reader.id, None).await;
+        assert!(matches!(result, Err(DbError::JournalAccessDenied)));
+
+        add_journal_member(&pool, &owner.id, &reader.id, "read")
+            .await
+            .unwrap();
+        let messages = get_journal_messages(&pool, &owner.id, &reader.id, None)
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+
+        remove_journal_member(&pool, &owner.id, &reader.id)
+            .await
+            .unwrap();
+        let result = get_journal_messages(&pool, &owner.id, &reader.id, None).await;
+        assert!(matches!(result, Err(DbError::JournalAccessDenied)));
+    }
+
+    #[tokio::test]
+    async fn test_create_journal_message_requires_write_role() {
+        let pool = setup_test_db().await;
+        let owner = create_test_user("owner-write@example.com");
+        let member = create_test_user("member-write@example.com");
+        create_user(&pool, &owner).await.unwrap();
+        create_user(&pool, &member).await.unwrap();
+
+        let mut message = Message::new(owner.id.clone(), "Shared entry".to_string());
+        message.author_id = Some(member.id.clone());
+
+        add_journal_member(&pool, &owner.id, &member.id, "read")
+            .await
+            .unwrap();
+        let result = create_journal_message(&pool, &member.id, &message).await;
+        assert!(matches!(result, Err(DbError::JournalAccessDenied)));
+
+        add_journal_member(&pool, &owner.id, &member.id, "write")
+            .await
+            .unwrap();
+        let created = create_journal_message(&pool, &member.id, &message)
+            .await
+            .unwrap();
+        assert_eq!(created.author_id, Some(member.id.clone()));
+
+        let members = list_journal_members(&pool, &owner.id).await.unwrap();
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].role, "write");
+    }
+
+    #[tokio::test]
+    async fn test_reactions_aggregate_and_require_access() {
+        let pool = setup_test_db().await;
+        let owner = create_test_user("react-owner@example.com");
+        let member = create_test_user("react-member@example.com");
+        let stranger = create_test_user("react-stranger@example.com");
+        create_user(&pool, &owner).await.unwrap();
+        create_user(&pool, &member).await.unwrap();
+        create_user(&pool, &stranger).await.unwrap();
+        add_journal_member(&pool, &owner.id, &member.id, "read")
+            .await
+            .unwrap();
+
+        let message = Message::new(owner.id.clone(), "Big news".to_string());
+        create_message(&pool, &message).await.unwrap();
+
+        add_reaction(&pool, &message.id, &owner.id, "🎉")
+            .await
+            .unwrap();
+        add_reaction(&pool, &message.id, &member.id, "🎉")
+            .await
+            .unwrap();
+        add_reaction(&pool, &message.id, &member.id, "🎉")
+            .await
+            .unwrap();
+        add_reaction(&pool, &message.id, &member.id, "❤️")
+            .await
+            .unwrap();
+
+        let result = add_reaction(&pool, &message.id, &stranger.id, "👀").await;
+        assert!(matches!(result, Err(DbError::MessageNotFound)));
+
+        let counts = get_reaction_counts(&pool, &owner.id).await.unwrap();
+        let reactions = &counts[&message.id];
+        assert_eq!(reactions.len(), 2);
+        assert_eq!(reactions[0].emoji, "🎉");
+        assert_eq!(reactions[0].count, 2);
+
+        remove_reaction(&pool, &message.id, &member.id, "❤️")
+            .await
+            .unwrap();
+        let counts = get_reaction_counts(&pool, &owner.id).await.unwrap();
+        assert_eq!(counts[&message.id].len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_push_subscriptions() {
+        let pool = setup_test_db().await;
+        let first = create_test_user("push1@example.com");
+        let second = create_test_user("push2@example.com");
+        create_user(&pool, &first).await.unwrap();
+        create_user(&pool, &second).await.unwrap();
+
+        save_push_subscription(&pool, &first.id, "https://push.example/1", "key", "auth")
+            .await
+            .unwrap();
+        assert_eq!(
+            get_push_subscriptions(&pool, &first.id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // Re-registering the same endpoint moves it to the new user
+        save_push_subscription(&pool, &second.id, "https://push.example/1", "key2", "auth2")
+            .await
+            .unwrap();
+        assert!(get_push_subscriptions(&pool, &first.id)
+            .await
+            .unwrap()
+            .is_empty());
+        let subscriptions = get_push_subscriptions(&pool, &second.id).await.unwrap();
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(subscriptions[0].p256dh, "key2");
+
+        delete_push_subscription(&pool, &second.id, "https://push.example/1")
+            .await
+            .unwrap();
+        assert!(get_push_subscriptions(&pool, &second.id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_user_avatar_is_replaced_and_deleted_with_user() {
+        let pool = setup_test_db().await;
+        let user = create_test_user("avatar@example.com");
+        create_user(&pool, &user).await.unwrap();
+        assert!(get_user_avatar(&pool, &user.id).await.unwrap().is_none());
+
+        let mut avatar = Avatar {
+            user_id: user.id.clone(),
+            content_type: "image/png".to_string(),
+            data: vec![1, 2, 3],
+            updated_at: chrono::Utc::now(),
+        };
+        set_user_avatar(&pool, &avatar).await.unwrap();
+        avatar.data = vec![4, 5];
+        set_user_avatar(&pool, &avatar).await.unwrap();
+        let stored = get_user_avatar(&pool, &user.id).await.unwrap().unwrap();
+        assert_eq!(stored.data, [4, 5]);
+
+        delete_user(&pool, &user.id).await.unwrap();
+        assert!(get_user_avatar(&pool, &user.id).await.unwrap().is_none());
+    }
+}
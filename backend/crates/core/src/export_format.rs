@@ -0,0 +1,586 @@
+use std::{collections::BTreeMap, io::Write};
+
+use age::secrecy::Secret;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use pulldown_cmark::{html, Event, Options, Parser};
+
+use crate::models::{MarkdownExportQuery, Message, MessageResponse};
+
+/// Stylesheet embedded in HTML exports so the page works offline
+const HTML_EXPORT_STYLE: &str = r#"
+body { font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", sans-serif; line-height: 1.6; color: #222; background: #fafafa; margin: 0; }
+main { max-width: 42rem; margin: 0 auto; padding: 2rem 1rem; }
+header p { color: #666; }
+nav ol { columns: 2; padding-left: 1.2rem; }
+nav a, h2 a { color: #3a6ea5; text-decoration: none; }
+h2 { margin-top: 3rem; border-bottom: 1px solid #ddd; padding-bottom: 0.3rem; }
+article { background: #fff; border: 1px solid #e4e4e4; border-radius: 8px; padding: 1rem 1.25rem; margin: 1rem 0; }
+article time { display: block; color: #888; font-size: 0.85rem; margin-bottom: 0.5rem; }
+article img { max-width: 100%; }
+pre { background: #f3f3f3; padding: 0.75rem; overflow-x: auto; border-radius: 4px; }
+blockquote { border-left: 3px solid #ccc; margin-left: 0; padding-left: 1rem; color: #555; }
+@media print { body { background: #fff; } article { border: none; padding: 0; } nav { page-break-after: always; } }
+"#;
+
+/// File formats offered by the export endpoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json,
+    Markdown,
+    Html,
+}
+
+impl ExportFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Markdown => "markdown",
+            ExportFormat::Html => "html",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            ExportFormat::Markdown => "text/markdown; charset=utf-8",
+            ExportFormat::Html => "text/html; charset=utf-8",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+        }
+    }
+
+    /// Render messages, newest first, in this format
+    pub fn render(self, messages: &[Message]) -> Result<String, String> {
+        match self {
+            ExportFormat::Json => {
+                render_json(messages).map_err(|e| format!("Failed to serialize messages: {}", e))
+            }
+            ExportFormat::Markdown => Ok(render_markdown(
+                messages,
+                Utc::now(),
+                MarkdownLayout::default(),
+            )),
+            ExportFormat::Html => Ok(render_html(messages, Utc::now())),
+        }
+    }
+
+    /// Render a ZIP archive with one file per month, named like `2024-05.md`
+    pub fn render_by_month(self, messages: &[Message]) -> Result<Vec<u8>, String> {
+        let mut months: BTreeMap<String, Vec<Message>> = BTreeMap::new();
+        for message in messages {
+            months
+                .entry(message.created_at.format("%Y-%m").to_string())
+                .or_default()
+                .push(message.clone());
+        }
+
+        let files = months
+            .into_iter()
+            .map(|(month, messages)| {
+                let name = format!("{}.{}", month, self.extension());
+                Ok((name, self.render(&messages)?.into_bytes()))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        write_zip(&files)
+    }
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ExportFormat::Json),
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            "html" => Ok(ExportFormat::Html),
+            other => Err(format!("Unknown export format: {}", other)),
+        }
+    }
+}
+
+/// Shortest passphrase accepted for encrypting an export
+const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// Who can decrypt an export encrypted with age
+#[derive(Clone)]
+pub enum EncryptTo {
+    /// Holder of the matching `AGE-SECRET-KEY-1...` identity
+    Recipient(age::x25519::Recipient),
+    Passphrase(String),
+}
+
+impl EncryptTo {
+    pub fn is_passphrase(&self) -> bool {
+        matches!(self, EncryptTo::Passphrase(_))
+    }
+
+    /// Encrypt `plaintext` into the binary age format
+    ///
+    /// Passphrases are stretched with scrypt, which takes about a second, so
+    /// call this off the async runtime.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+        let encryptor = match self {
+            EncryptTo::Recipient(recipient) => {
+                age::Encryptor::with_recipients(vec![Box::new(recipient.clone())])
+                    .ok_or("No age recipient")?
+            }
+            EncryptTo::Passphrase(passphrase) => {
+                age::Encryptor::with_user_passphrase(Secret::new(passphrase.clone()))
+            }
+        };
+
+        let mut encrypted = Vec::new();
+        let mut writer = encryptor
+            .wrap_output(&mut encrypted)
+            .map_err(|e| format!("Failed to encrypt export: {}", e))?;
+        writer
+            .write_all(plaintext)
+            .and_then(|_| writer.finish())
+            .map_err(|e| format!("Failed to encrypt export: {}", e))?;
+
+        Ok(encrypted)
+    }
+}
+
+impl std::str::FromStr for EncryptTo {
+    type Err = String;
+
+    /// `age1...` is read as a recipient; anything else is a passphrase
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("age1") {
+            return s
+                .parse()
+                .map(EncryptTo::Recipient)
+                .map_err(|e| format!("Invalid age recipient: {}", e));
+        }
+        if s.chars().count() < MIN_PASSPHRASE_LEN {
+            return Err(format!(
+                "Passphrase must be at least {} characters",
+                MIN_PASSPHRASE_LEN
+            ));
+        }
+        Ok(EncryptTo::Passphrase(s.to_string()))
+    }
+}
+
+/// Date headings Markdown exports can nest entries under
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkdownGrouping {
+    Year,
+    /// Months nested inside years
+    Month,
+}
+
+/// Order and grouping of a Markdown export; the default is newest first
+/// with no grouping
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MarkdownLayout {
+    pub oldest_first: bool,
+    pub group_by: Option<MarkdownGrouping>,
+}
+
+impl MarkdownLayout {
+    pub fn from_query(query: &MarkdownExportQuery) -> Result<Self, String> {
+        let oldest_first = match query.order.as_deref() {
+            None | Some("desc") => false,
+            Some("asc") => true,
+            Some(other) => return Err(format!("Unknown order: {}", other)),
+        };
+        let group_by = match query.group_by.as_deref() {
+            None => None,
+            Some("year") => Some(MarkdownGrouping::Year),
+            Some("month") => Some(MarkdownGrouping::Month),
+            Some(other) => return Err(format!("Unknown group_by: {}", other)),
+        };
+
+        Ok(Self {
+            oldest_first,
+            group_by,
+        })
+    }
+}
+
+/// Write named files into a ZIP archive
+pub fn write_zip(files: &[(String, Vec<u8>)]) -> Result<Vec<u8>, String> {
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for (name, contents) in files {
+        writer
+            .start_file(name.as_str(), zip::write::SimpleFileOptions::default())
+            .map_err(|e| format!("Failed to write {}: {}", name, e))?;
+        writer
+            .write_all(contents)
+            .map_err(|e| format!("Failed to write {}: {}", name, e))?;
+    }
+
+    let archive = writer
+        .finish()
+        .map_err(|e| format!("Failed to finish archive: {}", e))?;
+    Ok(archive.into_inner())
+}
+
+/// Render messages as a pretty-printed JSON array
+pub fn render_json(messages: &[Message]) -> Result<String, serde_json::Error> {
+    let message_responses: Vec<MessageResponse> =
+        messages.iter().map(|m| m.to_response()).collect();
+    serde_json::to_string_pretty(&message_responses)
+}
+
+/// Render messages (given newest first) as a Markdown document with a
+/// heading per message, nested under year and month headings if grouped
+pub fn render_markdown(
+    messages: &[Message],
+    exported: DateTime<Utc>,
+    layout: MarkdownLayout,
+) -> String {
+    let export_date = exported.format("%B %d, %Y").to_string();
+
+    let mut markdown = format!("# Messages Export\n\nExported: {}\n\n---\n\n", export_date);
+    push_markdown_entries(&mut markdown, messages, layout, Tz::UTC);
+    markdown
+}
+
+/// Append a heading per message (given newest first) and its content,
+/// dated in `tz`
+pub fn push_markdown_entries(
+    markdown: &mut String,
+    messages: &[Message],
+    layout: MarkdownLayout,
+    tz: Tz,
+) {
+    let mut ordered: Vec<&Message> = messages.iter().collect();
+    if layout.oldest_first {
+        ordered.reverse();
+    }
+
+    // Entries of a year or month are contiguous in either order, so a
+    // heading is written whenever the period changes
+    let mut year = String::new();
+    let mut month = String::new();
+    for message in ordered {
+        let created_at = message.created_at.with_timezone(&tz);
+        let heading = match layout.group_by {
+            None => "##",
+            Some(grouping) => {
+                let message_year = created_at.format("%Y").to_string();
+                if message_year != year {
+                    markdown.push_str(&format!("## {}\n\n", message_year));
+                    year = message_year;
+                    month.clear();
+                }
+
+                if grouping == MarkdownGrouping::Year {
+                    "###"
+                } else {
+                    let message_month = created_at.format("%B").to_string();
+                    if message_month != month {
+                        markdown.push_str(&format!("### {}\n\n", message_month));
+                        month = message_month;
+                    }
+                    "####"
+                }
+            }
+        };
+
+        let formatted_date = created_at.format("%B %d, %Y at %I:%M %p").to_string();
+
+        markdown.push_str(&format!(
+            "{} {}\n\n{}\n\n---\n\n",
+            heading, formatted_date, message.content
+        ));
+    }
+}
+
+/// Render messages (newest first) as a self-contained page with a table of
+/// contents by month
+pub fn render_html(messages: &[Message], exported: DateTime<Utc>) -> String {
+    // Messages arrive newest first, so each month's entries are contiguous
+    let mut months: Vec<(String, String, Vec<&Message>)> = Vec::new();
+    for message in messages {
+        let anchor = message.created_at.format("month-%Y-%m").to_string();
+        match months.last_mut() {
+            Some((last, _, entries)) if *last == anchor => entries.push(message),
+            _ => {
+                let label = message.created_at.format("%B %Y").to_string();
+                months.push((anchor, label, vec![message]));
+            }
+        }
+    }
+
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>Messages Export</title>\n<style>{}</style>\n</head>\n<body>\n<main>\n\
+         <header>\n<h1>Messages Export</h1>\n<p>Exported: {}</p>\n</header>\n",
+        HTML_EXPORT_STYLE,
+        exported.format("%B %d, %Y")
+    );
+
+    page.push_str("<nav>\n<ol>\n");
+    for (anchor, label, entries) in &months {
+        page.push_str(&format!(
+            "<li><a href=\"#{}\">{}</a> ({})</li>\n",
+            anchor,
+            label,
+            entries.len()
+        ));
+    }
+    page.push_str("</ol>\n</nav>\n");
+
+    for (anchor, label, entries) in &months {
+        page.push_str(&format!(
+            "<section>\n<h2 id=\"{}\"><a href=\"#{}\">{}</a></h2>\n",
+            anchor, anchor, label
+        ));
+        for message in entries {
+            page.push_str(&format!(
+                "<article>\n<time datetime=\"{}\">{}</time>\n{}</article>\n",
+                message.created_at.to_rfc3339(),
+                message.created_at.format("%B %d, %Y at %I:%M %p"),
+                markdown_to_html(&message.content)
+            ));
+        }
+        page.push_str("</section>\n");
+    }
+
+    page.push_str("</main>\n</body>\n</html>\n");
+    page
+}
+
+/// Render messages as an iCalendar file with an all-day event per message,
+/// summarised by its first line
+///
+/// Days are taken in UTC, as the server doesn't know users' time zones.
+pub fn render_ics(messages: &[Message], exported: DateTime<Utc>) -> String {
+    let stamp = exported.format("%Y%m%dT%H%M%SZ");
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//Dissipate//Messages Export//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+
+    for message in messages {
+        let day = message.created_at.date_naive();
+        let summary = message
+            .content
+            .lines()
+            .map(|line| line.trim_start_matches('#').trim())
+            .find(|line| !line.is_empty())
+            .unwrap_or("Journal entry");
+
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}@dissipate", message.id),
+            format!("DTSTAMP:{}", stamp),
+            format!("DTSTART;VALUE=DATE:{}", day.format("%Y%m%d")),
+            format!(
+                "DTEND;VALUE=DATE:{}",
+                day.succ_opt().unwrap_or(day).format("%Y%m%d")
+            ),
+            format!("SUMMARY:{}", escape_ics_text(summary)),
+            "TRANSP:TRANSPARENT".to_string(),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold_ics_line(line)).collect()
+}
+
+/// Escape the characters iCalendar treats specially in text values
+fn escape_ics_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+}
+
+/// Fold a content line at 75 octets, never splitting a character, and end it
+/// with CRLF
+fn fold_ics_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 2);
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+/// Render Markdown to HTML, escaping any raw HTML in the entry so the
+/// exported page can't run scripts
+pub fn markdown_to_html(content: &str) -> String {
+    let options =
+        Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES | Options::ENABLE_TASKLISTS;
+    let events = Parser::new_ext(content, options).map(|event| match event {
+        Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+        other => other,
+    });
+
+    let mut rendered = String::new();
+    html::push_html(&mut rendered, events);
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids::UserId;
+
+    #[test]
+    fn test_render_markdown_oldest_first_grouped_by_month() {
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        let mut jan = Message::new(UserId::generate(), "New year".to_string());
+        jan.created_at = at("2024-01-01T09:00:00Z");
+        let mut late_dec = Message::new(UserId::generate(), "Party".to_string());
+        late_dec.created_at = at("2023-12-31T21:00:00Z");
+        let mut early_dec = Message::new(UserId::generate(), "Snow".to_string());
+        early_dec.created_at = at("2023-12-02T09:00:00Z");
+        let layout = MarkdownLayout::from_query(&MarkdownExportQuery {
+            order: Some("asc".to_string()),
+            group_by: Some("month".to_string()),
+        })
+        .unwrap();
+
+        let markdown = render_markdown(&[jan, late_dec, early_dec], Utc::now(), layout);
+
+        let headings: Vec<&str> = markdown.lines().filter(|l| l.starts_with("##")).collect();
+        assert_eq!(
+            headings,
+            vec![
+                "## 2023",
+                "### December",
+                "#### December 02, 2023 at 09:00 AM",
+                "#### December 31, 2023 at 09:00 PM",
+                "## 2024",
+                "### January",
+                "#### January 01, 2024 at 09:00 AM",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_by_month_writes_a_file_per_month() {
+        use std::io::Read;
+
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        let mut june = Message::new(UserId::generate(), "Summer".to_string());
+        june.created_at = at("2024-06-01T09:00:00Z");
+        let mut may = Message::new(UserId::generate(), "Spring".to_string());
+        may.created_at = at("2024-05-20T09:00:00Z");
+
+        let zip = ExportFormat::Markdown
+            .render_by_month(&[june, may])
+            .unwrap();
+
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(zip)).unwrap();
+        let names: Vec<&str> = archive.file_names().collect();
+        assert_eq!(names, vec!["2024-05.md", "2024-06.md"]);
+        let mut may_file = String::new();
+        archive
+            .by_name("2024-05.md")
+            .unwrap()
+            .read_to_string(&mut may_file)
+            .unwrap();
+        assert!(may_file.contains("Spring"));
+        assert!(!may_file.contains("Summer"));
+    }
+
+    #[test]
+    fn test_markdown_layout_rejects_unknown_values() {
+        let query = MarkdownExportQuery {
+            order: None,
+            group_by: Some("week".to_string()),
+        };
+        assert!(MarkdownLayout::from_query(&query).is_err());
+        assert_eq!(
+            MarkdownLayout::from_query(&MarkdownExportQuery::default()).unwrap(),
+            MarkdownLayout::default()
+        );
+    }
+
+    #[test]
+    fn test_fold_ics_line_keeps_characters_whole() {
+        let line = format!("SUMMARY:{}", "é".repeat(40));
+        let folded = fold_ics_line(&line);
+
+        assert!(folded.split("\r\n").all(|part| part.len() <= 75));
+        assert_eq!(folded.replace("\r\n ", ""), format!("{}\r\n", line));
+    }
+
+    #[test]
+    fn test_encrypt_to_recipient_round_trips() {
+        use std::io::Read;
+
+        let identity = age::x25519::Identity::generate();
+        let encrypt_to: EncryptTo = identity.to_public().to_string().parse().unwrap();
+        assert!(!encrypt_to.is_passphrase());
+
+        let encrypted = encrypt_to.encrypt(b"Secret journal").unwrap();
+        assert!(!encrypted.windows(6).any(|w| w == b"Secret"));
+
+        let age::Decryptor::Recipients(decryptor) = age::Decryptor::new(&encrypted[..]).unwrap()
+        else {
+            panic!("expected a recipient-encrypted file");
+        };
+        let mut reader = decryptor
+            .decrypt(std::iter::once(&identity as &dyn age::Identity))
+            .unwrap();
+        let mut decrypted = Vec::new();
+        reader.read_to_end(&mut decrypted).unwrap();
+        assert_eq!(decrypted, b"Secret journal");
+    }
+
+    #[test]
+    fn test_encrypt_to_rejects_bad_values() {
+        assert!("age1notarecipient".parse::<EncryptTo>().is_err());
+        assert!("short".parse::<EncryptTo>().is_err());
+        assert!("correct horse battery"
+            .parse::<EncryptTo>()
+            .unwrap()
+            .is_passphrase());
+    }
+
+    #[test]
+    fn test_render_html_groups_by_month() {
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        let mut march = Message::new(UserId::generate(), "Spring".to_string());
+        march.created_at = at("2024-03-10T09:00:00Z");
+        let mut late_jan = Message::new(UserId::generate(), "Snow".to_string());
+        late_jan.created_at = at("2024-01-30T09:00:00Z");
+        let mut early_jan = Message::new(UserId::generate(), "New year".to_string());
+        early_jan.created_at = at("2024-01-01T09:00:00Z");
+
+        let page = render_html(&[march, late_jan, early_jan], at("2024-04-01T00:00:00Z"));
+
+        assert!(page.contains("<a href=\"#month-2024-03\">March 2024</a> (1)"));
+        assert!(page.contains("<a href=\"#month-2024-01\">January 2024</a> (2)"));
+        assert_eq!(page.matches("<section>").count(), 2);
+        assert!(page.find("Spring").unwrap() < page.find("Snow").unwrap());
+    }
+
+    #[test]
+    fn test_render_html_escapes_raw_html() {
+        let message = Message::new(
+            UserId::generate(),
+            "<script>alert(1)</script>\n\nHi <b onclick=\"x\">there</b>".to_string(),
+        );
+
+        let page = render_html(&[message], Utc::now());
+
+        assert!(!page.contains("<script>"));
+        assert!(!page.contains("<b onclick"));
+        assert!(page.contains("&lt;script&gt;"));
+    }
+}
@@ -0,0 +1,14 @@
+//! Dissipate's data model and database, without the HTTP server
+//!
+//! Shared by the `dissipate-backend` server, the `manage_users` CLI and the
+//! `dissipate-client` API client, and usable from any other binary that needs
+//! to read or write a Dissipate database directly.
+
+pub mod config;
+pub mod db;
+pub mod export_format;
+pub mod ids;
+pub mod models;
+#[cfg(feature = "seed")]
+pub mod seed;
+pub mod utils;
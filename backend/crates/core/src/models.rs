@@ -0,0 +1,2200 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Datelike, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::ids::{MessageId, UserId};
+
+/// User database model
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct User {
+    pub id: UserId,
+    pub email: String,
+    pub username: String,
+    /// Name shown to other users, falling back to `username` when unset
+    pub display_name: Option<String>,
+    pub password_hash: String,
+    pub salt: String,
+    /// `user`, or `admin` for access to the `/api/admin` routes
+    pub role: String,
+    /// Frozen by an admin: can't log in or use existing tokens
+    pub disabled: bool,
+    /// Must change their password before using anything else
+    pub must_reset_password: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Role of ordinary users
+pub const ROLE_USER: &str = "user";
+/// Role of users allowed to use the `/api/admin` routes
+pub const ROLE_ADMIN: &str = "admin";
+
+/// Whether `role` is one of the roles above
+pub fn is_valid_role(role: &str) -> bool {
+    role == ROLE_USER || role == ROLE_ADMIN
+}
+
+impl User {
+    /// Create a new user with generated UUID and timestamps
+    #[allow(dead_code)]
+    pub fn new(email: String, username: String, password_hash: String, salt: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id: UserId::generate(),
+            email,
+            username,
+            display_name: None,
+            password_hash,
+            salt,
+            role: ROLE_USER.to_string(),
+            disabled: false,
+            must_reset_password: false,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.role == ROLE_ADMIN
+    }
+
+    /// Name to show other users: the display name if set, else the username
+    pub fn shown_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.username)
+    }
+
+    /// Convert to public user response (without sensitive fields)
+    pub fn to_public(&self) -> UserResponse {
+        UserResponse {
+            id: self.id.to_string(),
+            email: self.email.clone(),
+            username: self.username.clone(),
+            display_name: self.display_name.clone(),
+        }
+    }
+
+    /// Convert to the listing shown to admins
+    pub fn to_admin(&self) -> AdminUserResponse {
+        AdminUserResponse {
+            id: self.id.to_string(),
+            email: self.email.clone(),
+            username: self.username.clone(),
+            display_name: self.display_name.clone(),
+            role: self.role.clone(),
+            disabled: self.disabled,
+            created_at: self.created_at.to_rfc3339(),
+        }
+    }
+}
+
+/// Public user response (excludes sensitive fields)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct UserResponse {
+    pub id: String,
+    pub email: String,
+    pub username: String,
+    pub display_name: Option<String>,
+}
+
+/// A user's profile picture, as resized on upload
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct Avatar {
+    pub user_id: UserId,
+    /// MIME type of `data`
+    pub content_type: String,
+    pub data: Vec<u8>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Message database model
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct Message {
+    pub id: MessageId,
+    pub user_id: UserId,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub publish_at: Option<DateTime<Utc>>,
+    pub author_id: Option<UserId>,
+    pub conflict_of: Option<MessageId>,
+    pub seq: i64,
+}
+
+impl Message {
+    /// Create a new message with generated UUID and timestamps
+    pub fn new(user_id: UserId, content: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id: MessageId::generate(),
+            user_id,
+            content,
+            created_at: now,
+            updated_at: now,
+            publish_at: None,
+            author_id: None,
+            conflict_of: None,
+            seq: 0,
+        }
+    }
+
+    /// Create a new message with a client-provided ID (for offline sync)
+    pub fn with_id(id: MessageId, user_id: UserId, content: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id,
+            user_id,
+            content,
+            created_at: now,
+            updated_at: now,
+            publish_at: None,
+            author_id: None,
+            conflict_of: None,
+            seq: 0,
+        }
+    }
+
+    /// Convert to API response format
+    pub fn to_response(&self) -> MessageResponse {
+        MessageResponse {
+            id: self.id.to_string(),
+            content: self.content.clone(),
+            created_at: self.created_at.to_rfc3339(),
+            updated_at: self.updated_at.to_rfc3339(),
+            publish_at: self.publish_at.map(|at| at.to_rfc3339()),
+            author_id: self.author_id.as_ref().map(UserId::to_string),
+            conflict_of: self.conflict_of.as_ref().map(MessageId::to_string),
+            seq: self.seq,
+            reactions: Vec::new(),
+        }
+    }
+}
+
+/// Message response for API
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct MessageResponse {
+    pub id: String,
+    pub content: String,
+    pub created_at: String,
+    pub updated_at: String,
+    #[serde(default)]
+    pub seq: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author_id: Option<String>, // Set when written by a journal collaborator
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conflict_of: Option<String>, // Set on copies saved from a conflicting edit
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reactions: Vec<ReactionCount>,
+}
+
+/// Aggregate count of one emoji reaction on a message
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ReactionCount {
+    pub emoji: String,
+    pub count: i64,
+}
+
+/// Template database model
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct Template {
+    pub id: String,
+    pub user_id: UserId,
+    pub name: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Template {
+    /// Create a new template with generated UUID and timestamps
+    pub fn new(user_id: UserId, name: String, content: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            user_id,
+            name,
+            content,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Convert to API response format
+    pub fn to_response(&self) -> TemplateResponse {
+        TemplateResponse {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            content: self.content.clone(),
+            created_at: self.created_at.to_rfc3339(),
+            updated_at: self.updated_at.to_rfc3339(),
+        }
+    }
+}
+
+/// Template response for API
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct TemplateResponse {
+    pub id: String,
+    pub name: String,
+    pub content: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Journal role allowing a member to read the owner's messages
+pub const JOURNAL_ROLE_READ: &str = "read";
+/// Journal role additionally allowing a member to post to the owner's journal
+pub const JOURNAL_ROLE_WRITE: &str = "write";
+
+/// Member of a shared journal, as seen by the owner
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct JournalMemberResponse {
+    pub user_id: String,
+    pub email: String,
+    pub username: String,
+    /// Display name, or the username when none is set
+    pub display_name: String,
+    pub role: String,
+    pub created_at: String,
+}
+
+/// Journal shared with the current user
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct SharedJournalResponse {
+    pub owner_id: String,
+    pub username: String,
+    /// Owner's display name, or their username when none is set
+    pub display_name: String,
+    pub role: String,
+}
+
+/// Reaction the user added, as included in account exports
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct UserReaction {
+    pub message_id: MessageId,
+    pub emoji: String,
+    pub created_at: String,
+}
+
+/// Browser Web Push subscription database model
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct PushSubscription {
+    pub endpoint: String,
+    pub user_id: UserId,
+    pub p256dh: String,
+    pub auth: String,
+    pub created_at: String,
+}
+
+/// Response recorded for an `Idempotency-Key`, replayed on retries
+#[derive(Debug, Clone, FromRow)]
+pub struct IdempotentResponse {
+    pub method: String,
+    pub path: String,
+    pub status: i64,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+/// Entry in the audit log of admin actions and destructive operations
+#[derive(Debug, Clone, FromRow)]
+pub struct AuditEvent {
+    /// Assigned by the database; increases with every event
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    /// Dotted name such as `admin.user.delete` or `messages.import`
+    pub action: String,
+    /// User ID, or `audit::ACTOR_ADMIN_TOKEN` / `audit::ACTOR_SYSTEM`
+    pub actor: String,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    /// JSON object with details of the action
+    pub metadata: String,
+}
+
+impl AuditEvent {
+    pub fn new(action: &str, actor: &str) -> Self {
+        Self {
+            id: 0,
+            created_at: Utc::now(),
+            action: action.to_string(),
+            actor: actor.to_string(),
+            target_type: None,
+            target_id: None,
+            metadata: "{}".to_string(),
+        }
+    }
+
+    /// Name what the action was done to, such as `("user", id)`
+    pub fn with_target(mut self, target_type: &str, target_id: &str) -> Self {
+        self.target_type = Some(target_type.to_string());
+        self.target_id = Some(target_id.to_string());
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: serde_json::Value) -> Self {
+        self.metadata = metadata.to_string();
+        self
+    }
+
+    pub fn to_response(&self) -> AuditEventResponse {
+        AuditEventResponse {
+            id: self.id,
+            created_at: self.created_at.to_rfc3339(),
+            action: self.action.clone(),
+            actor: self.actor.clone(),
+            target_type: self.target_type.clone(),
+            target_id: self.target_id.clone(),
+            metadata: serde_json::from_str(&self.metadata).unwrap_or_default(),
+        }
+    }
+}
+
+/// Export job waiting for the worker
+pub const EXPORT_STATUS_PENDING: &str = "pending";
+/// Export job being written by the worker
+pub const EXPORT_STATUS_RUNNING: &str = "running";
+/// Export job whose file is ready to download
+pub const EXPORT_STATUS_COMPLETED: &str = "completed";
+/// Export job that could not be written; see `error`
+pub const EXPORT_STATUS_FAILED: &str = "failed";
+
+/// Background export job database model
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct ExportJob {
+    pub id: String,
+    pub user_id: UserId,
+    pub format: String,
+    pub status: String,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Schedule that queued the job, for delivery by email
+    pub schedule_id: Option<String>,
+    /// Secret allowing download without logging in, for emailed links
+    pub download_token: Option<String>,
+    /// age recipient or passphrase, cleared once the job has run
+    #[serde(skip_serializing)]
+    pub encrypt_to: Option<String>,
+    /// Whether the file is encrypted with age
+    pub encrypted: bool,
+    /// Whether the file is a ZIP archive with one file per month
+    pub split_by_month: bool,
+    /// Messages read so far, out of `rows_total`
+    pub rows_processed: i64,
+    /// Number of messages being exported, known once the job starts
+    pub rows_total: Option<i64>,
+}
+
+impl ExportJob {
+    /// Create a pending job with generated UUID
+    pub fn new(user_id: UserId, format: String) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            user_id,
+            format,
+            status: EXPORT_STATUS_PENDING.to_string(),
+            error: None,
+            created_at: Utc::now(),
+            completed_at: None,
+            schedule_id: None,
+            download_token: None,
+            encrypt_to: None,
+            encrypted: false,
+            split_by_month: false,
+            rows_processed: 0,
+            rows_total: None,
+        }
+    }
+
+    /// Encrypt the job's file to an age recipient or passphrase
+    pub fn encrypted_to(mut self, encrypt_to: Option<String>) -> Self {
+        self.encrypted = encrypt_to.is_some();
+        self.encrypt_to = encrypt_to;
+        self
+    }
+
+    /// Create a pending job for a schedule, downloadable by emailed link
+    pub fn for_schedule(schedule: &ExportSchedule) -> Self {
+        let token: String = (0..32)
+            .map(|_| format!("{:02x}", rand::random::<u8>()))
+            .collect();
+
+        Self {
+            schedule_id: Some(schedule.id.clone()),
+            download_token: Some(token),
+            ..Self::new(schedule.user_id.clone(), schedule.format.clone())
+                .encrypted_to(schedule.encrypt_to.clone())
+        }
+    }
+
+    /// Convert to API response format
+    pub fn to_response(&self) -> ExportJobResponse {
+        let download_url = (self.status == EXPORT_STATUS_COMPLETED)
+            .then(|| format!("/api/exports/{}/download", self.id));
+
+        ExportJobResponse {
+            id: self.id.clone(),
+            format: self.format.clone(),
+            status: self.status.clone(),
+            error: self.error.clone(),
+            created_at: self.created_at.to_rfc3339(),
+            completed_at: self.completed_at.map(|at| at.to_rfc3339()),
+            encrypted: self.encrypted,
+            split_by_month: self.split_by_month,
+            download_url,
+        }
+    }
+
+    /// Convert to the progress API response format
+    pub fn to_progress_response(&self) -> ExportProgressResponse {
+        ExportProgressResponse {
+            id: self.id.clone(),
+            status: self.status.clone(),
+            processed: self.rows_processed,
+            total: self.rows_total,
+        }
+    }
+}
+
+/// Schedule delivering exports once a week
+pub const EXPORT_FREQUENCY_WEEKLY: &str = "weekly";
+/// Schedule delivering exports once a month
+pub const EXPORT_FREQUENCY_MONTHLY: &str = "monthly";
+/// Scheduled export emailed as an attachment
+pub const EXPORT_DELIVERY_ATTACHMENT: &str = "attachment";
+/// Scheduled export emailed as a download link
+pub const EXPORT_DELIVERY_LINK: &str = "link";
+
+/// Next run of a schedule with `frequency` after `after`, or `None` for an
+/// unknown frequency
+pub fn next_export_run(frequency: &str, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    match frequency {
+        EXPORT_FREQUENCY_WEEKLY => Some(after + chrono::Duration::weeks(1)),
+        EXPORT_FREQUENCY_MONTHLY => after.checked_add_months(chrono::Months::new(1)),
+        _ => None,
+    }
+}
+
+/// Recurring export database model
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct ExportSchedule {
+    pub id: String,
+    pub user_id: UserId,
+    pub format: String,
+    pub frequency: String,
+    pub email: String,
+    pub delivery: String,
+    pub next_run_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    /// age recipient the exports are encrypted to
+    pub encrypt_to: Option<String>,
+}
+
+impl ExportSchedule {
+    /// Create a schedule with generated UUID, first run straight away
+    pub fn new(
+        user_id: UserId,
+        format: String,
+        frequency: String,
+        email: String,
+        delivery: String,
+        encrypt_to: Option<String>,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            user_id,
+            format,
+            frequency,
+            email,
+            delivery,
+            next_run_at: now,
+            created_at: now,
+            encrypt_to,
+        }
+    }
+
+    /// Convert to API response format
+    pub fn to_response(&self) -> ExportScheduleResponse {
+        ExportScheduleResponse {
+            id: self.id.clone(),
+            format: self.format.clone(),
+            frequency: self.frequency.clone(),
+            email: self.email.clone(),
+            delivery: self.delivery.clone(),
+            next_run_at: self.next_run_at.to_rfc3339(),
+            created_at: self.created_at.to_rfc3339(),
+            encrypt_to: self.encrypt_to.clone(),
+        }
+    }
+}
+
+/// WebDAV folder a user's finished exports are copied to
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct WebDavDestination {
+    pub user_id: UserId,
+    pub url: String,
+    pub username: String,
+    pub password: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl WebDavDestination {
+    /// Convert to API response format, leaving out the password
+    pub fn to_response(&self) -> WebDavDestinationResponse {
+        WebDavDestinationResponse {
+            url: self.url.clone(),
+            username: self.username.clone(),
+            created_at: self.created_at.to_rfc3339(),
+        }
+    }
+}
+
+/// Lowercase English name of a weekday, as stored on digests and reminders
+pub fn weekday_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "monday",
+        Weekday::Tue => "tuesday",
+        Weekday::Wed => "wednesday",
+        Weekday::Thu => "thursday",
+        Weekday::Fri => "friday",
+        Weekday::Sat => "saturday",
+        Weekday::Sun => "sunday",
+    }
+}
+
+/// First instant after `after` that is `time` in `tz`, on a day `on_day`
+/// accepts
+///
+/// A time skipped by a daylight saving change is taken an hour later.
+fn next_local_run(
+    tz: Tz,
+    time: NaiveTime,
+    on_day: impl Fn(Weekday) -> bool,
+    after: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    // Eight days always reach an accepted day, even when today's run is over
+    let mut date = after.with_timezone(&tz).date_naive();
+    for _ in 0..8 {
+        if on_day(date.weekday()) {
+            let local = date.and_time(time);
+            let run = tz
+                .from_local_datetime(&local)
+                .earliest()
+                .or_else(|| {
+                    tz.from_local_datetime(&(local + chrono::Duration::hours(1)))
+                        .earliest()
+                })?
+                .with_timezone(&Utc);
+            if run > after {
+                return Some(run);
+            }
+        }
+        date = date.succ_opt()?;
+    }
+    None
+}
+
+/// Digest emailed every day
+pub const DIGEST_FREQUENCY_DAILY: &str = "daily";
+/// Digest emailed once a week
+pub const DIGEST_FREQUENCY_WEEKLY: &str = "weekly";
+
+/// A user's daily or weekly email summarising what they wrote
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct DigestSubscription {
+    pub user_id: UserId,
+    pub frequency: String,
+    /// Local hour the digest is sent at, 0-23
+    pub hour: i64,
+    /// Day weekly digests are sent on, e.g. `monday`
+    pub weekday: Option<String>,
+    /// IANA time zone `hour` is in
+    pub timezone: String,
+    pub next_run_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl DigestSubscription {
+    /// Create a subscription whose first digest goes out at the next
+    /// matching hour, or `None` if a setting is invalid
+    pub fn new(
+        user_id: UserId,
+        frequency: String,
+        hour: i64,
+        weekday: Option<String>,
+        timezone: String,
+    ) -> Option<Self> {
+        let now = Utc::now();
+        let mut subscription = Self {
+            user_id,
+            frequency,
+            hour,
+            weekday,
+            timezone,
+            next_run_at: now,
+            created_at: now,
+        };
+        subscription.next_run_at = subscription.run_after(now)?;
+        Some(subscription)
+    }
+
+    /// First run after `after`, or `None` if a setting is invalid
+    pub fn run_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let tz: Tz = self.timezone.parse().ok()?;
+        let time = NaiveTime::from_hms_opt(u32::try_from(self.hour).ok()?, 0, 0)?;
+        let weekday = match self.frequency.as_str() {
+            DIGEST_FREQUENCY_DAILY => None,
+            DIGEST_FREQUENCY_WEEKLY => Some(
+                self.weekday
+                    .as_deref()
+                    .unwrap_or("monday")
+                    .parse::<Weekday>()
+                    .ok()?,
+            ),
+            _ => return None,
+        };
+
+        next_local_run(tz, time, |day| weekday.is_none_or(|w| w == day), after)
+    }
+
+    /// Start of the period a digest sent at `run_at` covers: a day or a
+    /// week before it
+    pub fn period_start(&self, run_at: DateTime<Utc>) -> DateTime<Utc> {
+        if self.frequency == DIGEST_FREQUENCY_WEEKLY {
+            run_at - chrono::Duration::weeks(1)
+        } else {
+            run_at - chrono::Duration::days(1)
+        }
+    }
+
+    /// Convert to API response format
+    pub fn to_response(&self) -> DigestSubscriptionResponse {
+        DigestSubscriptionResponse {
+            frequency: self.frequency.clone(),
+            hour: self.hour,
+            weekday: self.weekday.clone(),
+            timezone: self.timezone.clone(),
+            next_run_at: self.next_run_at.to_rfc3339(),
+            created_at: self.created_at.to_rfc3339(),
+        }
+    }
+}
+
+/// A user's nudge to write, sent at a local time on the days they chose
+/// unless they have already written that day
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct Reminder {
+    pub user_id: UserId,
+    /// Local time the reminder is sent at, `HH:MM`
+    pub time: String,
+    /// Comma-separated days it is sent on, e.g. `monday,friday`
+    pub days: String,
+    /// IANA time zone `time` is in
+    pub timezone: String,
+    /// Sent by email to the account's address
+    pub email: bool,
+    /// Sent to the user's Web Push subscriptions
+    pub push: bool,
+    /// Sent to the user's notification channels, such as ntfy or Gotify
+    pub channels: bool,
+    pub next_run_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Reminder {
+    /// Create a reminder first sent at the next matching time, or `None` if
+    /// a setting is invalid
+    pub fn new(
+        user_id: UserId,
+        time: String,
+        days: String,
+        timezone: String,
+        email: bool,
+        push: bool,
+        channels: bool,
+    ) -> Option<Self> {
+        let now = Utc::now();
+        let mut reminder = Self {
+            user_id,
+            time,
+            days,
+            timezone,
+            email,
+            push,
+            channels,
+            next_run_at: now,
+            created_at: now,
+        };
+        reminder.next_run_at = reminder.run_after(now)?;
+        Some(reminder)
+    }
+
+    /// Days the reminder is sent on
+    pub fn weekdays(&self) -> Vec<Weekday> {
+        self.days
+            .split(',')
+            .filter_map(|day| day.parse().ok())
+            .collect()
+    }
+
+    /// First run after `after`, or `None` if a setting is invalid
+    pub fn run_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let tz: Tz = self.timezone.parse().ok()?;
+        let time = NaiveTime::parse_from_str(&self.time, "%H:%M").ok()?;
+        let weekdays = self.weekdays();
+
+        next_local_run(tz, time, |day| weekdays.contains(&day), after)
+    }
+
+    /// Convert to API response format
+    pub fn to_response(&self) -> ReminderResponse {
+        ReminderResponse {
+            time: self.time.clone(),
+            days: self.days.split(',').map(str::to_string).collect(),
+            timezone: self.timezone.clone(),
+            email: self.email,
+            push: self.push,
+            channels: self.channels,
+            next_run_at: self.next_run_at.to_rfc3339(),
+            created_at: self.created_at.to_rfc3339(),
+        }
+    }
+}
+
+/// Channel posting to an ntfy topic
+pub const NOTIFICATION_CHANNEL_NTFY: &str = "ntfy";
+/// Channel posting to a Gotify server
+pub const NOTIFICATION_CHANNEL_GOTIFY: &str = "gotify";
+
+/// Self-hosted service, such as ntfy or Gotify, that shows a user
+/// notifications
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct NotificationChannel {
+    pub id: String,
+    pub user_id: UserId,
+    /// `ntfy` or `gotify`
+    pub kind: String,
+    /// ntfy topic URL, or Gotify server URL
+    pub url: String,
+    /// ntfy access token, or Gotify application token
+    #[serde(skip_serializing)]
+    pub token: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl NotificationChannel {
+    /// Create a channel with generated UUID
+    pub fn new(user_id: UserId, kind: String, url: String, token: Option<String>) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            user_id,
+            kind,
+            url,
+            token,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Convert to API response format, leaving out the token
+    pub fn to_response(&self) -> NotificationChannelResponse {
+        NotificationChannelResponse {
+            id: self.id.clone(),
+            kind: self.kind.clone(),
+            url: self.url.clone(),
+            has_token: self.token.is_some(),
+            created_at: self.created_at.to_rfc3339(),
+        }
+    }
+}
+
+/// How long a Telegram link code can be sent to the bot
+pub const TELEGRAM_LINK_CODE_MINUTES: i64 = 15;
+
+/// Characters of Telegram link codes, leaving out ones easily mistyped
+const TELEGRAM_LINK_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+/// Telegram chat whose messages to the bot are saved to a user's journal
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct TelegramLink {
+    pub user_id: UserId,
+    pub chat_id: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One-time code a user sends the bot to link their Telegram chat
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct TelegramLinkCode {
+    pub code: String,
+    pub user_id: UserId,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl TelegramLinkCode {
+    /// Create a random code that expires after `TELEGRAM_LINK_CODE_MINUTES`
+    pub fn new(user_id: UserId) -> Self {
+        let code = (0..8)
+            .map(|_| {
+                let i = rand::random::<usize>() % TELEGRAM_LINK_CODE_ALPHABET.len();
+                TELEGRAM_LINK_CODE_ALPHABET[i] as char
+            })
+            .collect();
+
+        Self {
+            code,
+            user_id,
+            expires_at: Utc::now() + chrono::Duration::minutes(TELEGRAM_LINK_CODE_MINUTES),
+        }
+    }
+}
+
+/// Secret address whose mail is saved to a user's journal
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct InboundEmailAddress {
+    pub user_id: UserId,
+    /// Local part of the address, e.g. `3f9c...@in.example.com`
+    pub token: String,
+    /// Comma-separated addresses mail is accepted from, besides the
+    /// account's own
+    pub allowed_senders: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl InboundEmailAddress {
+    /// Create an address with a random token
+    pub fn new(user_id: UserId, allowed_senders: &[String]) -> Self {
+        let mut address = Self {
+            user_id,
+            token: String::new(),
+            allowed_senders: allowed_senders.join(","),
+            created_at: Utc::now(),
+        };
+        address.rotate();
+        address
+    }
+
+    /// Replace the token, so mail to the old address is no longer accepted
+    pub fn rotate(&mut self) {
+        self.token = (0..12)
+            .map(|_| format!("{:02x}", rand::random::<u8>()))
+            .collect();
+    }
+
+    pub fn allowed_senders(&self) -> Vec<String> {
+        self.allowed_senders
+            .split(',')
+            .filter(|sender| !sender.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Convert to API response format, with the address at `domain`
+    pub fn to_response(&self, domain: &str) -> InboundEmailResponse {
+        InboundEmailResponse {
+            address: format!("{}@{}", self.token, domain),
+            allowed_senders: self.allowed_senders(),
+            created_at: self.created_at.to_rfc3339(),
+        }
+    }
+}
+
+/// Matrix bot account that posts a user's notifications to a DM room, and
+/// saves what the user writes there as entries
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct MatrixAccount {
+    pub user_id: UserId,
+    pub homeserver_url: String,
+    #[serde(skip_serializing)]
+    pub access_token: String,
+    /// The bot's own Matrix ID, whose messages aren't saved
+    pub bot_user_id: String,
+    pub room_id: String,
+    /// `next_batch` of the last sync; `None` until the first one, which only
+    /// notes where the room's history ends
+    pub sync_token: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl MatrixAccount {
+    /// Convert to API response format, leaving out the access token
+    pub fn to_response(&self) -> MatrixAccountResponse {
+        MatrixAccountResponse {
+            homeserver_url: self.homeserver_url.clone(),
+            bot_user_id: self.bot_user_id.clone(),
+            room_id: self.room_id.clone(),
+            created_at: self.created_at.to_rfc3339(),
+        }
+    }
+}
+
+/// Fewest days of inactivity a dormancy policy can wait for
+pub const DORMANCY_MIN_DAYS: i64 = 30;
+/// Most days of inactivity a dormancy policy can wait for
+pub const DORMANCY_MAX_DAYS: i64 = 3650;
+/// Days before a dormancy policy's deadline its warnings are emailed
+pub const DORMANCY_WARNING_DAYS: [i64; 3] = [14, 7, 1];
+
+/// A user's dead-man switch: once they have been inactive for
+/// `inactive_days`, their account export is emailed to `export_email`
+/// and/or the account is deleted, after warning them by email
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct DormancyPolicy {
+    pub user_id: UserId,
+    pub inactive_days: i64,
+    /// Address the account export is emailed to
+    pub export_email: Option<String>,
+    /// Delete the account, after emailing any export
+    pub purge: bool,
+    /// Last login or authenticated request, to the hour
+    pub last_active_at: DateTime<Utc>,
+    /// Warnings emailed since the last activity
+    pub warnings_sent: i64,
+    pub last_warning_at: Option<DateTime<Utc>>,
+    /// When the export was sent, until the user is active again
+    pub triggered_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl DormancyPolicy {
+    /// Create a policy counting inactivity from now
+    pub fn new(
+        user_id: UserId,
+        inactive_days: i64,
+        export_email: Option<String>,
+        purge: bool,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            user_id,
+            inactive_days,
+            export_email,
+            purge,
+            last_active_at: now,
+            warnings_sent: 0,
+            last_warning_at: None,
+            triggered_at: None,
+            created_at: now,
+        }
+    }
+
+    /// When the policy is carried out unless the user is active before then
+    pub fn deadline(&self) -> DateTime<Utc> {
+        self.last_active_at + chrono::Duration::days(self.inactive_days)
+    }
+
+    /// How many warnings should have been sent by `now`, if more than have
+    ///
+    /// Warnings missed while the server was down are skipped, so only the
+    /// latest one due is sent.
+    pub fn warnings_due(&self, now: DateTime<Utc>) -> Option<i64> {
+        let deadline = self.deadline();
+        let due = DORMANCY_WARNING_DAYS
+            .iter()
+            .filter(|days| now >= deadline - chrono::Duration::days(**days))
+            .count() as i64;
+        (due > self.warnings_sent).then_some(due)
+    }
+
+    /// Whether to carry the policy out at `now`: past the deadline, with
+    /// every warning sent and the last one at least a day ago
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.triggered_at.is_none()
+            && now >= self.deadline()
+            && self.warnings_sent >= DORMANCY_WARNING_DAYS.len() as i64
+            && self
+                .last_warning_at
+                .is_none_or(|warned_at| now - warned_at >= chrono::Duration::days(1))
+    }
+
+    /// Convert to API response format
+    pub fn to_response(&self) -> DormancyPolicyResponse {
+        DormancyPolicyResponse {
+            inactive_days: self.inactive_days,
+            export_email: self.export_email.clone(),
+            purge: self.purge,
+            last_active_at: self.last_active_at.to_rfc3339(),
+            deadline: self.deadline().to_rfc3339(),
+            warnings_sent: self.warnings_sent,
+            triggered_at: self.triggered_at.map(|t| t.to_rfc3339()),
+        }
+    }
+}
+
+/// Webhook delivery waiting for its first attempt or a retry
+pub const WEBHOOK_DELIVERY_PENDING: &str = "pending";
+/// Webhook delivery the receiver accepted with a 2xx status
+pub const WEBHOOK_DELIVERY_DELIVERED: &str = "delivered";
+/// Webhook delivery given up on after its last retry
+pub const WEBHOOK_DELIVERY_FAILED: &str = "failed";
+
+/// URL a user's message changes are POSTed to
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: String,
+    pub user_id: UserId,
+    pub url: String,
+    /// Key the payloads are signed with
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Webhook {
+    /// Create a webhook with generated UUID, and a random secret unless one
+    /// is given
+    pub fn new(user_id: UserId, url: String, secret: Option<String>) -> Self {
+        let secret = secret.unwrap_or_else(|| {
+            (0..32)
+                .map(|_| format!("{:02x}", rand::random::<u8>()))
+                .collect()
+        });
+
+        Self {
+            id: Uuid::new_v4().to_string(),
+            user_id,
+            url,
+            secret,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Convert to API response format, leaving out the secret
+    pub fn to_response(&self) -> WebhookResponse {
+        WebhookResponse {
+            id: self.id.clone(),
+            url: self.url.clone(),
+            created_at: self.created_at.to_rfc3339(),
+            secret: None,
+        }
+    }
+}
+
+/// One event sent to one webhook
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i64,
+    /// Set while the delivery is pending
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    /// HTTP status of the last attempt, if the receiver answered
+    pub response_status: Option<i64>,
+    /// Why the last attempt failed
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl WebhookDelivery {
+    /// Convert to API response format
+    pub fn to_response(&self) -> WebhookDeliveryResponse {
+        WebhookDeliveryResponse {
+            id: self.id.clone(),
+            event: self.event.clone(),
+            status: self.status.clone(),
+            attempts: self.attempts,
+            response_status: self.response_status,
+            error: self.error.clone(),
+            next_attempt_at: self.next_attempt_at.map(|at| at.to_rfc3339()),
+            created_at: self.created_at.to_rfc3339(),
+            updated_at: self.updated_at.to_rfc3339(),
+        }
+    }
+}
+
+/// A delivery due for an attempt, with where to send it and how to sign it
+#[derive(Debug, Clone, FromRow)]
+pub struct DueWebhookDelivery {
+    pub id: String,
+    pub event: String,
+    pub payload: String,
+    pub attempts: i64,
+    pub url: String,
+    pub secret: String,
+}
+
+/// Announcement level for news, such as a new feature
+pub const ANNOUNCEMENT_LEVEL_INFO: &str = "info";
+/// Announcement level for something users should act on or plan around,
+/// such as a maintenance window
+pub const ANNOUNCEMENT_LEVEL_WARNING: &str = "warning";
+
+/// Whether `level` is one of the announcement levels above
+pub fn is_valid_announcement_level(level: &str) -> bool {
+    level == ANNOUNCEMENT_LEVEL_INFO || level == ANNOUNCEMENT_LEVEL_WARNING
+}
+
+/// Notice operators show in every client
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct Announcement {
+    pub id: String,
+    pub message: String,
+    pub level: String,
+    /// Shown from this time; straight away when unset
+    pub starts_at: Option<DateTime<Utc>>,
+    /// Hidden from this time; kept up when unset
+    pub ends_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Announcement {
+    /// Create an announcement with generated UUID and timestamps
+    pub fn new(
+        message: String,
+        level: String,
+        starts_at: Option<DateTime<Utc>>,
+        ends_at: Option<DateTime<Utc>>,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            message,
+            level,
+            starts_at,
+            ends_at,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Convert to API response format
+    pub fn to_response(&self) -> AnnouncementResponse {
+        AnnouncementResponse {
+            id: self.id.clone(),
+            message: self.message.clone(),
+            level: self.level.clone(),
+            starts_at: self.starts_at.map(|at| at.to_rfc3339()),
+            ends_at: self.ends_at.map(|at| at.to_rfc3339()),
+            created_at: self.created_at.to_rfc3339(),
+            updated_at: self.updated_at.to_rfc3339(),
+        }
+    }
+}
+
+/// JWT Claims
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub user_id: String,
+    pub exp: usize,
+}
+
+// ============ Request DTOs ============
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct LoginRequest {
+    pub email: String,
+    pub password: String,
+}
+
+/// Body of `POST /api/register`
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RegisterRequest {
+    pub email: String,
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreateMessageRequest {
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub id: Option<MessageId>, // Optional client-generated UUID for offline sync
+    #[serde(default)]
+    pub template_id: Option<String>, // Pre-fills content when content is empty
+    #[serde(default)]
+    pub created_at: Option<String>, // Backdate the entry (RFC 3339)
+    #[serde(default)]
+    pub publish_at: Option<String>, // Hide from listings until this time (RFC 3339)
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UpdateMessageRequest {
+    pub content: String,
+    #[serde(default)]
+    pub updated_at: Option<String>, // Version the edit was based on, for conflict detection
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateEmailRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateUsernameRequest {
+    pub username: String,
+}
+
+/// Body of `PUT /api/user/display-name`; `null` or blank clears the name
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateDisplayNameRequest {
+    pub display_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdatePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateTemplateRequest {
+    pub name: String,
+    pub content: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateTemplateRequest {
+    pub name: String,
+    pub content: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct InviteMemberRequest {
+    pub email: String,
+    pub role: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ReactionRequest {
+    pub emoji: String,
+}
+
+/// Subscription as produced by the browser's `PushSubscription.toJSON()`
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PushSubscriptionRequest {
+    pub endpoint: String,
+    pub keys: PushSubscriptionKeys,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PushSubscriptionKeys {
+    pub p256dh: String,
+    pub auth: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PushUnsubscribeRequest {
+    pub endpoint: String,
+}
+
+/// Queued client changes pushed to `POST /api/sync`
+#[derive(Debug, Serialize, Deserialize, Default, ToSchema)]
+pub struct BatchSyncRequest {
+    /// Cursor from the previous sync; the response carries changes after it
+    #[serde(default)]
+    pub since_seq: Option<i64>,
+    #[serde(default)]
+    pub creates: Vec<SyncCreate>,
+    #[serde(default)]
+    pub updates: Vec<SyncUpdate>,
+    #[serde(default)]
+    #[schema(value_type = Vec<String>)]
+    pub deletes: Vec<MessageId>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SyncCreate {
+    #[schema(value_type = String)]
+    pub id: MessageId,
+    pub content: String,
+    #[serde(default)]
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SyncUpdate {
+    #[schema(value_type = String)]
+    pub id: MessageId,
+    pub content: String,
+    #[serde(default)]
+    pub updated_at: Option<String>, // Version the edit was based on
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreateExportRequest {
+    /// `json`, `markdown` or `html`
+    pub format: String,
+    /// age recipient (`age1...`) or passphrase to encrypt the file to
+    #[serde(default)]
+    pub encrypt_to: Option<String>,
+    /// `month` to write a ZIP archive with one file per month
+    #[serde(default)]
+    pub split_by: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateExportScheduleRequest {
+    /// `json`, `markdown` or `html`
+    pub format: String,
+    /// `weekly` or `monthly`
+    pub frequency: String,
+    /// Defaults to the account's email
+    #[serde(default)]
+    pub email: Option<String>,
+    /// `attachment` (default) or `link`
+    #[serde(default)]
+    pub delivery: Option<String>,
+    /// age recipient (`age1...`) to encrypt the exports to
+    #[serde(default)]
+    pub encrypt_to: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetWebDavDestinationRequest {
+    /// Folder URL, e.g. `https://cloud.example.com/remote.php/dav/files/alice/Journal`
+    pub url: String,
+    pub username: String,
+    /// Ideally an app password rather than the account password
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetDigestSubscriptionRequest {
+    /// `daily` or `weekly`
+    pub frequency: String,
+    /// Local hour to send the digest at, 0-23
+    pub hour: i64,
+    /// Day to send weekly digests on, e.g. `friday`; Monday when left out
+    pub weekday: Option<String>,
+    /// IANA time zone the hour is in, e.g. `Europe/Berlin`; UTC when left out
+    pub timezone: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetReminderRequest {
+    /// Local time to send the reminder at, `HH:MM`
+    pub time: String,
+    /// Days to send it on, e.g. `["monday", "friday"]`; every day when left
+    /// out
+    pub days: Option<Vec<String>>,
+    /// IANA time zone the time is in, e.g. `Europe/Berlin`; UTC when left out
+    pub timezone: Option<String>,
+    /// Send it by email
+    #[serde(default)]
+    pub email: bool,
+    /// Send it to the user's Web Push subscriptions
+    #[serde(default)]
+    pub push: bool,
+    /// Send it to the user's notification channels, such as ntfy or Gotify
+    #[serde(default)]
+    pub channels: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateNotificationChannelRequest {
+    /// `ntfy` or `gotify`
+    pub kind: String,
+    /// ntfy topic URL, e.g. `https://ntfy.sh/my-journal`, or Gotify server
+    /// URL, e.g. `https://gotify.example.com`
+    pub url: String,
+    /// ntfy access token for protected topics, or the Gotify application
+    /// token, which is required
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetInboundEmailRequest {
+    /// Other addresses mail is accepted from; the account email always is
+    #[serde(default)]
+    pub allowed_senders: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetDormancyPolicyRequest {
+    /// Days without a login or any use of the API, 30 to 3650
+    pub inactive_days: i64,
+    /// Address to email an export of the account to
+    pub export_email: Option<String>,
+    /// Delete the account, after emailing any export
+    #[serde(default)]
+    pub purge: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetMatrixAccountRequest {
+    /// e.g. `https://matrix.example.org`
+    pub homeserver_url: String,
+    /// Access token of the bot account, not the user's own
+    pub access_token: String,
+    /// DM room between the user and the bot, e.g. `!abc123:example.org`
+    pub room_id: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateWebhookRequest {
+    /// http or https URL the events are POSTed to
+    pub url: String,
+    /// Key the payloads are signed with; generated when left out
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+/// Body of `POST /api/admin/announcements` and `PUT /api/admin/announcements/{id}`
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AnnouncementRequest {
+    pub message: String,
+    /// `info` (default) or `warning`
+    #[serde(default)]
+    pub level: Option<String>,
+    /// When to start showing it (RFC 3339); straight away when left out
+    #[serde(default)]
+    pub starts_at: Option<String>,
+    /// When to stop showing it (RFC 3339); kept up until deleted when left out
+    #[serde(default)]
+    pub ends_at: Option<String>,
+}
+
+// ============ Response DTOs ============
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct HealthResponse {
+    pub status: String,
+    /// Time of the last successful scheduled backup, if backups are enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_backup_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReadinessResponse {
+    pub status: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct LoginResponse {
+    pub token: String,
+    pub user: UserResponse,
+    /// The token only works for `PUT /api/user/password` until the password
+    /// is changed
+    pub must_reset_password: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MessagesResponse {
+    pub messages: Vec<MessageResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TemplatesResponse {
+    pub templates: Vec<TemplateResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct JournalMembersResponse {
+    pub members: Vec<JournalMemberResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SharedJournalsResponse {
+    pub journals: Vec<SharedJournalResponse>,
+}
+
+/// Delta returned by `GET /api/sync`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SyncResponse {
+    pub changed: Vec<MessageResponse>,
+    pub deleted: Vec<String>,
+    /// Pass back as `since` on the next sync
+    pub server_time: String,
+    /// Pass back as `since_seq` on the next sync
+    pub seq: i64,
+}
+
+/// Storage used by a user, returned by `GET /api/user/usage` and with the
+/// 413s and 429s of writes past a limit
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UsageResponse {
+    /// Bytes of message content stored
+    pub content_bytes: i64,
+    /// Configured per-user limit; absent when unlimited
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota_bytes: Option<i64>,
+    /// Messages stored, including scheduled ones
+    pub message_count: i64,
+    /// Configured per-user limit on messages; absent when unlimited
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_messages: Option<i64>,
+    /// Messages created since midnight UTC; imports don't count
+    pub messages_today: i64,
+    /// Configured per-user limit on messages created per day; absent when
+    /// unlimited
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_messages_per_day: Option<i64>,
+    /// When `messages_today` goes back to zero (next midnight UTC)
+    pub day_resets_at: String,
+}
+
+/// Result of `POST /api/admin/checkpoint`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CheckpointResponse {
+    /// The checkpoint could not finish because of other connections
+    pub busy: bool,
+    pub log_frames: i64,
+    pub checkpointed_frames: i64,
+    /// WAL file size after the checkpoint; absent for in-memory databases
+    pub wal_bytes: Option<u64>,
+}
+
+/// WAL metrics returned by `GET /api/admin/wal`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WalStatusResponse {
+    /// Current WAL file size; absent for in-memory databases
+    pub wal_bytes: Option<u64>,
+    /// Time of the last checkpoint that copied every frame
+    pub last_checkpoint_at: Option<String>,
+}
+
+/// Result of `GET /api/admin/integrity`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct IntegrityResponse {
+    /// No corruption or orphaned rows were found
+    pub ok: bool,
+    pub integrity_errors: Vec<String>,
+    pub orphaned_rows: BTreeMap<String, i64>,
+    pub row_counts: BTreeMap<String, i64>,
+}
+
+/// Result of `GET /api/admin/stats`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct StatsResponse {
+    pub user_count: i64,
+    /// Messages created on each UTC day, keyed `YYYY-MM-DD`
+    pub messages_per_day: BTreeMap<String, i64>,
+    /// Database file size, excluding the WAL
+    pub database_bytes: i64,
+    /// Open WebSocket connections; logins are stateless JWTs, so these are
+    /// the clients currently connected
+    pub active_sessions: usize,
+    /// Time of the last successful backup since the server started
+    pub last_backup_at: Option<String>,
+    /// Last scheduled optimization since the server started
+    pub last_optimize: Option<OptimizeResponse>,
+}
+
+/// Outcome of a scheduled database optimization, in `GET /api/admin/stats`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OptimizeResponse {
+    pub finished_at: String,
+    pub duration_ms: u64,
+    /// Free space returned to the filesystem; absent when the database
+    /// doesn't use incremental auto-vacuum
+    pub vacuumed_bytes: Option<i64>,
+    /// The WAL checkpoint couldn't finish because of other connections
+    pub checkpoint_busy: bool,
+}
+
+/// Audit log entry returned by `GET /api/admin/audit`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AuditEventResponse {
+    pub id: i64,
+    pub created_at: String,
+    pub action: String,
+    pub actor: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_id: Option<String>,
+    #[schema(value_type = Object)]
+    pub metadata: serde_json::Value,
+}
+
+/// A page of `GET /api/admin/audit`, newest first
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AuditEventsResponse {
+    pub events: Vec<AuditEventResponse>,
+    /// Pass as `before` to get the next page; absent on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_before: Option<i64>,
+}
+
+/// Body of `PUT /api/admin/maintenance`
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct MaintenanceRequest {
+    /// Shown to clients; a generic message when absent
+    pub message: Option<String>,
+    /// Sent as `Retry-After`; 60 when absent
+    pub retry_after_secs: Option<u32>,
+}
+
+/// Maintenance mode state returned by `/api/admin/maintenance`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MaintenanceResponse {
+    pub enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u32>,
+}
+
+/// A user as listed by `/api/admin/users`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AdminUserResponse {
+    pub id: String,
+    pub email: String,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub role: String,
+    pub disabled: bool,
+    pub created_at: String,
+}
+
+/// Users returned by `GET /api/admin/users`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AdminUsersResponse {
+    pub users: Vec<AdminUserResponse>,
+}
+
+/// Body of `POST /api/admin/users`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AdminCreateUserRequest {
+    pub email: String,
+    pub username: String,
+    pub password: String,
+    /// `user` (default) or `admin`
+    pub role: Option<String>,
+}
+
+/// Body of `PUT /api/admin/users/{id}/password`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AdminResetPasswordRequest {
+    pub password: String,
+}
+
+/// Body of `PUT /api/admin/users/{id}/role`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AdminSetRoleRequest {
+    /// `user` or `admin`
+    pub role: String,
+}
+
+/// Body of `PUT /api/admin/users/{id}/disabled`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AdminSetDisabledRequest {
+    pub disabled: bool,
+}
+
+/// Settings clients adapt to, returned by `/api/config`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ClientConfigResponse {
+    pub features: FeaturesResponse,
+}
+
+/// Which instance features are on
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct FeaturesResponse {
+    /// Anyone can create an account at `/api/register`
+    pub registration: bool,
+    /// Users can share their journal with other accounts
+    pub sharing: bool,
+    /// Clients may offer attachments
+    pub attachments: bool,
+}
+
+/// A feature as listed by `/api/admin/features`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct FeatureFlagResponse {
+    pub name: String,
+    pub enabled: bool,
+    /// Whether an operator switched it, rather than it following the config
+    pub overridden: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct FeatureFlagsResponse {
+    pub features: Vec<FeatureFlagResponse>,
+}
+
+/// Body of `PUT /api/admin/features/{name}`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SetFeatureFlagRequest {
+    pub enabled: bool,
+}
+
+/// Export job status returned by `/api/exports`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ExportJobResponse {
+    pub id: String,
+    pub format: String,
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub created_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<String>,
+    pub encrypted: bool,
+    pub split_by_month: bool,
+    /// Set once the file is ready
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_url: Option<String>,
+}
+
+/// Export job progress returned by `/api/exports/:id/progress`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ExportProgressResponse {
+    pub id: String,
+    pub status: String,
+    /// Messages read so far
+    pub processed: i64,
+    /// Messages being exported; unknown until the job starts
+    pub total: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ExportScheduleResponse {
+    pub id: String,
+    pub format: String,
+    pub frequency: String,
+    pub email: String,
+    pub delivery: String,
+    pub next_run_at: String,
+    pub created_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encrypt_to: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ExportSchedulesResponse {
+    pub schedules: Vec<ExportScheduleResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WebDavDestinationResponse {
+    pub url: String,
+    pub username: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DigestSubscriptionResponse {
+    pub frequency: String,
+    pub hour: i64,
+    pub weekday: Option<String>,
+    pub timezone: String,
+    pub next_run_at: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReminderResponse {
+    pub time: String,
+    pub days: Vec<String>,
+    pub timezone: String,
+    pub email: bool,
+    pub push: bool,
+    pub channels: bool,
+    pub next_run_at: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct NotificationChannelResponse {
+    pub id: String,
+    pub kind: String,
+    pub url: String,
+    /// Whether a token is stored; the token itself is never returned
+    pub has_token: bool,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct NotificationChannelsResponse {
+    pub channels: Vec<NotificationChannelResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct InboundEmailResponse {
+    /// Mail sent here from an allowed sender becomes an entry
+    pub address: String,
+    pub allowed_senders: Vec<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DormancyPolicyResponse {
+    pub inactive_days: i64,
+    pub export_email: Option<String>,
+    pub purge: bool,
+    pub last_active_at: String,
+    /// When the policy is carried out unless the user is active before then
+    pub deadline: String,
+    pub warnings_sent: i64,
+    /// When the export was sent; cleared by the next activity
+    pub triggered_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MatrixAccountResponse {
+    pub homeserver_url: String,
+    pub bot_user_id: String,
+    pub room_id: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TelegramLinkResponse {
+    pub linked: bool,
+    pub linked_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TelegramLinkCodeResponse {
+    /// Sent to the bot as `/start <code>` to link the chat
+    pub code: String,
+    pub expires_at: String,
+    /// Opens the bot in Telegram with the code filled in
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WebhookResponse {
+    pub id: String,
+    pub url: String,
+    pub created_at: String,
+    /// Key the payloads are signed with, only returned when the webhook is
+    /// created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WebhooksResponse {
+    pub webhooks: Vec<WebhookResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WebhookDeliveryResponse {
+    pub id: String,
+    /// `message.created`, `message.updated` or `message.deleted`
+    pub event: String,
+    /// `pending`, `delivered` or `failed`
+    pub status: String,
+    pub attempts: i64,
+    /// HTTP status of the last attempt, if the receiver answered
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_status: Option<i64>,
+    /// Why the last attempt failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// When the delivery is next tried, while pending
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_attempt_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WebhookDeliveriesResponse {
+    pub deliveries: Vec<WebhookDeliveryResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AnnouncementResponse {
+    pub id: String,
+    pub message: String,
+    /// `info` or `warning`
+    pub level: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starts_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ends_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AnnouncementsResponse {
+    pub announcements: Vec<AnnouncementResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ImportResponse {
+    pub imported: usize,
+    /// Files left out because no date could be found for them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<String>,
+}
+
+/// Application server key browsers need to create a push subscription
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct VapidKeyResponse {
+    pub public_key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SuccessResponse {
+    pub success: bool,
+}
+
+impl SuccessResponse {
+    pub fn new() -> Self {
+        Self { success: true }
+    }
+}
+
+impl Default for SuccessResponse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ============ Query Parameters ============
+
+#[derive(Debug, Serialize, Deserialize, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MessagesQuery {
+    pub since: Option<String>,
+    /// Integer change cursor; takes precedence over `since` in `/api/sync`
+    pub since_seq: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    /// Action, or a prefix such as `admin.user` for all actions under it
+    pub action: Option<String>,
+    /// User ID, `admin_token` or `system`
+    pub actor: Option<String>,
+    pub target_id: Option<String>,
+    /// Only events at or after this RFC 3339 timestamp
+    pub since: Option<String>,
+    /// Only events before this RFC 3339 timestamp
+    pub until: Option<String>,
+    /// `next_before` from the previous page; the same as `cursor`, kept for
+    /// older clients
+    pub before: Option<i64>,
+}
+
+/// Paging and sorting of a list, shared by listing endpoints
+///
+/// Each endpoint documents its defaults, the fields it sorts by, and whether
+/// it pages by `offset` or `cursor`.
+#[derive(Debug, Serialize, Deserialize, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageQuery {
+    /// Most items to return
+    pub limit: Option<u32>,
+    /// Items to skip, on lists paged by offset
+    pub offset: Option<u32>,
+    /// Where the previous page ended, on lists paged by cursor
+    pub cursor: Option<String>,
+    /// Field to sort by
+    pub sort: Option<String>,
+    /// `asc` or `desc`
+    pub order: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MarkdownExportQuery {
+    /// `desc` (newest first, the default) or `asc`
+    pub order: Option<String>,
+    /// `month` or `year` to nest entries under date headings
+    pub group_by: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_new_creates_valid_user() {
+        let user = User::new(
+            "test@example.com".to_string(),
+            "testuser".to_string(),
+            "hash123".to_string(),
+            "salt123".to_string(),
+        );
+
+        assert!(!user.id.as_str().is_empty());
+        assert_eq!(user.email, "test@example.com");
+        assert_eq!(user.username, "testuser");
+        assert_eq!(user.password_hash, "hash123");
+        assert_eq!(user.salt, "salt123");
+        assert!(user.created_at <= Utc::now());
+        assert_eq!(user.created_at, user.updated_at);
+        
+        // Verify UUID format
+        Uuid::parse_str(user.id.as_str()).expect("User ID should be valid UUID");
+    }
+
+    #[test]
+    fn test_user_to_public_excludes_sensitive_data() {
+        let user = User::new(
+            "test@example.com".to_string(),
+            "testuser".to_string(),
+            "hash123".to_string(),
+            "salt123".to_string(),
+        );
+
+        let public = user.to_public();
+
+        assert_eq!(public.id, user.id);
+        assert_eq!(public.email, user.email);
+        assert_eq!(public.username, user.username);
+    }
+
+    #[test]
+    fn test_message_new_creates_valid_message() {
+        let user_id = UserId::generate();
+        let message = Message::new(user_id.clone(), "Hello, world!".to_string());
+
+        assert!(!message.id.as_str().is_empty());
+        assert_eq!(message.user_id, user_id);
+        assert_eq!(message.content, "Hello, world!");
+        assert!(message.created_at <= Utc::now());
+        assert_eq!(message.created_at, message.updated_at);
+        
+        // Verify UUID format
+        Uuid::parse_str(message.id.as_str()).expect("Message ID should be valid UUID");
+    }
+
+    #[test]
+    fn test_message_with_id_uses_provided_id() {
+        let custom_id = MessageId::generate();
+        let user_id = UserId::generate();
+        let message = Message::with_id(
+            custom_id.clone(),
+            user_id.clone(),
+            "Test content".to_string(),
+        );
+
+        assert_eq!(message.id, custom_id);
+        assert_eq!(message.user_id, user_id);
+        assert_eq!(message.content, "Test content");
+    }
+
+    #[test]
+    fn test_message_to_response() {
+        let message = Message::new(UserId::generate(), "Test message".to_string());
+
+        let response = message.to_response();
+
+        assert_eq!(response.id, message.id);
+        assert_eq!(response.content, message.content);
+        assert_eq!(response.created_at, message.created_at.to_rfc3339());
+        assert_eq!(response.updated_at, message.updated_at.to_rfc3339());
+    }
+
+    #[test]
+    fn test_claims_serialization() {
+        let claims = Claims {
+            user_id: "user-123".to_string(),
+            exp: 1704067200,
+        };
+
+        let json = serde_json::to_string(&claims).unwrap();
+        let deserialized: Claims = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(deserialized.user_id, claims.user_id);
+        assert_eq!(deserialized.exp, claims.exp);
+    }
+
+    #[test]
+    fn test_login_request_deserialization() {
+        let json = r#"{"email": "test@example.com", "password": "secret123"}"#;
+        let request: LoginRequest = serde_json::from_str(json).unwrap();
+
+        assert_eq!(request.email, "test@example.com");
+        assert_eq!(request.password, "secret123");
+    }
+
+    #[test]
+    fn test_create_message_request_with_optional_id() {
+        // Without id
+        let json1 = r#"{"content": "Hello"}"#;
+        let request1: CreateMessageRequest = serde_json::from_str(json1).unwrap();
+        assert_eq!(request1.content, "Hello");
+        assert!(request1.id.is_none());
+
+        // With id
+        let json2 = r#"{"content": "Hello", "id": "67e55044-10b1-426f-9247-bb680e5fe0c8"}"#;
+        let request2: CreateMessageRequest = serde_json::from_str(json2).unwrap();
+        assert_eq!(request2.content, "Hello");
+        assert_eq!(request2.id.unwrap(), "67e55044-10b1-426f-9247-bb680e5fe0c8");
+
+        // IDs must be UUIDs
+        let json3 = r#"{"content": "Hello", "id": "custom-id"}"#;
+        assert!(serde_json::from_str::<CreateMessageRequest>(json3).is_err());
+    }
+
+    #[test]
+    fn test_create_message_request_with_template_only() {
+        let json = r#"{"template_id": "template-1"}"#;
+        let request: CreateMessageRequest = serde_json::from_str(json).unwrap();
+
+        assert!(request.content.is_empty());
+        assert_eq!(request.template_id, Some("template-1".to_string()));
+    }
+
+    #[test]
+    fn test_template_to_response() {
+        let template = Template::new(
+            UserId::generate(),
+            "Daily".to_string(),
+            "Three good things:".to_string(),
+        );
+
+        let response = template.to_response();
+
+        assert_eq!(response.id, template.id);
+        assert_eq!(response.name, "Daily");
+        assert_eq!(response.content, "Three good things:");
+    }
+
+    #[test]
+    fn test_success_response_default() {
+        let response = SuccessResponse::default();
+        assert!(response.success);
+    }
+
+    #[test]
+    fn test_digest_runs_at_local_hour() {
+        let utc = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+        let mut subscription = DigestSubscription::new(
+            UserId::generate(),
+            DIGEST_FREQUENCY_WEEKLY.to_string(),
+            8,
+            Some("friday".to_string()),
+            "Europe/Berlin".to_string(),
+        )
+        .unwrap();
+
+        // Thursday in Berlin; 08:00 CEST is 06:00 UTC
+        let after = utc("2026-10-15T12:00:00Z");
+        assert_eq!(
+            subscription.run_after(after),
+            Some(utc("2026-10-16T06:00:00Z"))
+        );
+        // Just after Friday's run, the next one is a week later, in CET
+        let after = utc("2026-10-30T07:00:00Z");
+        assert_eq!(
+            subscription.run_after(after),
+            Some(utc("2026-11-06T07:00:00Z"))
+        );
+
+        // 02:00 doesn't exist in New York when clocks go forward
+        subscription.frequency = DIGEST_FREQUENCY_DAILY.to_string();
+        subscription.hour = 2;
+        subscription.timezone = "America/New_York".to_string();
+        let after = utc("2026-03-08T00:00:00Z");
+        assert_eq!(
+            subscription.run_after(after),
+            Some(utc("2026-03-08T07:00:00Z"))
+        );
+
+        subscription.timezone = "Mars/Olympus_Mons".to_string();
+        assert!(subscription.run_after(after).is_none());
+    }
+}
@@ -0,0 +1,121 @@
+use chrono::{DateTime, Duration, Utc};
+use fake::{
+    faker::{lorem::en::Sentences, name::en::Name},
+    Fake,
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use uuid::Builder;
+
+use crate::models::{Message, User};
+
+/// Password of every seeded user
+pub const SEED_PASSWORD: &str = "password123";
+
+/// How far back seeded messages are spread
+const SEED_HISTORY_DAYS: i64 = 365;
+
+/// Tags mixed into some messages, so tag views have something to show
+const SEED_HASHTAGS: &[&str] = &["#ideas", "#work", "#reading", "#travel", "#health"];
+
+/// Fake users and messages for demo and load-test instances
+///
+/// The same seed always gives the same IDs, names and content, in the same
+/// order; dates are spread over the year before `now`.
+pub struct Seeder {
+    rng: StdRng,
+    now: DateTime<Utc>,
+}
+
+impl Seeder {
+    pub fn new(seed: u64, now: DateTime<Utc>) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            now,
+        }
+    }
+
+    /// The `index`th user, `test{index}@example.com`, with a fake name
+    ///
+    /// Hashing is slow, so every user gets the same hash of `SEED_PASSWORD`.
+    pub fn user(&mut self, index: u64, password_hash: &str, salt: &str) -> User {
+        let username: String = Name().fake_with_rng(&mut self.rng);
+        let mut user = User::new(
+            format!("test{}@example.com", index),
+            username,
+            password_hash.to_string(),
+            salt.to_string(),
+        );
+        user.id = self.uuid();
+        user.created_at = self.now - Duration::days(SEED_HISTORY_DAYS);
+        user.updated_at = user.created_at;
+        user
+    }
+
+    /// A message of a few sentences, sometimes with a hashtag
+    pub fn message(&mut self, user_id: &str) -> Message {
+        let sentences: Vec<String> = Sentences(1..4).fake_with_rng(&mut self.rng);
+        let mut content = sentences.join(" ");
+        if self.rng.gen_bool(0.3) {
+            let tag = SEED_HASHTAGS
+                .choose(&mut self.rng)
+                .copied()
+                .unwrap_or("#ideas");
+            content.push(' ');
+            content.push_str(tag);
+        }
+
+        let mut message = Message::new(user_id.to_string(), content);
+        message.id = self.uuid();
+        let age = self.rng.gen_range(0..SEED_HISTORY_DAYS * 24 * 60 * 60);
+        message.created_at = self.now - Duration::seconds(age);
+        message.updated_at = message.created_at;
+        message
+    }
+
+    fn uuid(&mut self) -> String {
+        Builder::from_random_bytes(self.rng.gen())
+            .into_uuid()
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeder_is_deterministic() {
+        let now = Utc::now();
+        let generate = |seed| {
+            let mut seeder = Seeder::new(seed, now);
+            let user = seeder.user(1, "hash", "salt");
+            let message = seeder.message(&user.id);
+            (
+                user.id,
+                user.username,
+                message.id,
+                message.content,
+                message.created_at,
+            )
+        };
+
+        assert_eq!(generate(7), generate(7));
+        assert_ne!(generate(7), generate(8));
+    }
+
+    #[test]
+    fn test_seeded_messages_fall_within_the_last_year() {
+        let now = Utc::now();
+        let mut seeder = Seeder::new(1, now);
+        let user = seeder.user(3, "hash", "salt");
+        assert_eq!(user.email, "test3@example.com");
+
+        for _ in 0..100 {
+            let message = seeder.message(&user.id);
+            assert_eq!(message.user_id, user.id);
+            assert!(!message.content.is_empty());
+            assert!(message.created_at <= now);
+            assert!(message.created_at > now - Duration::days(SEED_HISTORY_DAYS));
+        }
+    }
+}
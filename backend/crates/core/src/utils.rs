@@ -35,6 +35,24 @@ pub fn verify_password(password: &str, hash: &str) -> Result<bool, PasswordError
         .is_ok())
 }
 
+/// Parse an RFC 3339 timestamp with any offset into UTC
+pub fn parse_timestamp(value: &str) -> Result<chrono::DateTime<chrono::Utc>, chrono::ParseError> {
+    let parsed = chrono::DateTime::parse_from_rfc3339(value)?;
+    Ok(parsed.with_timezone(&chrono::Utc))
+}
+
+/// Lowercased `#hashtags` in a message, in order, without trailing
+/// punctuation
+pub fn hashtags(content: &str) -> impl Iterator<Item = String> + '_ {
+    content.split_whitespace().filter_map(|word| {
+        let tag = word
+            .strip_prefix('#')?
+            .trim_end_matches(|c: char| !c.is_alphanumeric() && c != '_');
+        tag.starts_with(|c: char| c.is_alphanumeric() || c == '_')
+            .then(|| tag.to_lowercase())
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -133,4 +151,16 @@ mod tests {
 
         assert!(result);
     }
+
+    #[test]
+    fn test_parse_timestamp_converts_to_utc() {
+        let parsed = parse_timestamp("2024-01-01T10:00:00+02:00").unwrap();
+
+        assert_eq!(parsed.to_rfc3339(), "2024-01-01T08:00:00+00:00");
+    }
+
+    #[test]
+    fn test_parse_timestamp_rejects_garbage() {
+        assert!(parse_timestamp("next tuesday").is_err());
+    }
 }
@@ -0,0 +1,5 @@
+fn main() {
+    // Stubs for the gRPC service; needs `protoc` on the PATH
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/dissipate.proto").expect("Failed to compile protos");
+}
@@ -0,0 +1,87 @@
+use std::{
+    fs::OpenOptions,
+    io::{LineWriter, Write},
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::config::LoggingConfig;
+
+/// The user a request was made as, set on the response by `auth_middleware`
+/// so the access log, which runs outside it, can record it
+#[derive(Debug, Clone)]
+pub struct LoggedUser(pub String);
+
+/// JSON lines access log, one line per request, written to stdout or a file
+/// for log aggregation stacks to ingest
+#[derive(Clone)]
+pub struct AccessLog {
+    out: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+/// One access log line
+#[derive(Debug, Serialize)]
+pub struct AccessLogEntry<'a> {
+    pub timestamp: DateTime<Utc>,
+    pub request_id: &'a str,
+    pub method: &'a str,
+    /// Without the query string, which can carry tokens from emailed links
+    pub path: &'a str,
+    pub status: u16,
+    pub latency_ms: f64,
+    pub user_id: Option<&'a str>,
+    pub client_ip: Option<IpAddr>,
+}
+
+impl AccessLog {
+    pub fn new(out: Box<dyn Write + Send>) -> Self {
+        Self {
+            out: Arc::new(Mutex::new(out)),
+        }
+    }
+
+    /// The log named by `[logging] access_log`: `stdout`, or a file to append
+    /// to. `None` when unset
+    pub fn from_config(config: &LoggingConfig) -> Result<Option<Self>, String> {
+        let Some(target) = config.access_log.as_deref() else {
+            return Ok(None);
+        };
+        if target == "stdout" {
+            return Ok(Some(Self::new(Box::new(std::io::stdout()))));
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(target)
+            .map_err(|e| format!("Failed to open access log {}: {}", target, e))?;
+        Ok(Some(Self::new(Box::new(LineWriter::new(file)))))
+    }
+
+    pub fn write(&self, entry: &AccessLogEntry) {
+        let mut line = match serde_json::to_vec(entry) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::error!("Failed to serialize access log entry: {}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        // Lines are short, so writing them in place is cheaper than handing
+        // them to another thread
+        let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = out.write_all(&line) {
+            tracing::error!("Failed to write access log: {}", e);
+        }
+    }
+}
+
+/// Milliseconds with microsecond precision, which is all aggregators show
+pub fn latency_ms(latency: Duration) -> f64 {
+    (latency.as_micros() as f64) / 1000.0
+}
@@ -0,0 +1,311 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde_json::json;
+
+use crate::{
+    audit::{self, AdminActor},
+    db::DbError,
+    error::AppError,
+    handlers::{storage_error, SharedState},
+    ids::UserId,
+    models::{
+        is_valid_role, AdminCreateUserRequest, AdminResetPasswordRequest, AdminSetDisabledRequest,
+        AdminSetRoleRequest, AdminUserResponse, AdminUsersResponse, AuditEvent, PageQuery, User,
+        ROLE_USER,
+    },
+    pagination::{PageSpec, Pagination, SortOrder},
+    utils::hash_password,
+    validation::Validate,
+};
+
+// ============ User Management Handlers ============
+//
+// The HTTP counterpart of the `manage_users` tool, for hosted instances
+// where operators have no shell on the box.
+
+/// Users come oldest first and all at once unless `limit` is given, and can
+/// be sorted by `created_at`, `email` or `username`
+pub struct UserPages;
+
+impl PageSpec for UserPages {
+    const DEFAULT_LIMIT: Option<u32> = None;
+    const MAX_LIMIT: u32 = 1000;
+    const SORTS: &'static [&'static str] = &["created_at", "email", "username"];
+    const DEFAULT_ORDER: SortOrder = SortOrder::Asc;
+    const CURSOR: bool = false;
+}
+
+/// GET /api/admin/users
+/// List users with their roles
+#[utoipa::path(
+    get,
+    path = "/api/admin/users",
+    tag = "admin",
+    params(PageQuery),
+    responses(
+        (status = 200, description = "Users, oldest first by default", body = AdminUsersResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+    ),
+    security(("admin_token" = []), ("bearer_auth" = []))
+)]
+pub async fn list_users(
+    State(state): State<SharedState>,
+    pagination: Pagination<UserPages>,
+) -> Result<Json<AdminUsersResponse>, AppError> {
+    let users = state
+        .storage
+        .list_users_page(&pagination.sorted_page())
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    Ok(Json(AdminUsersResponse {
+        users: users.iter().map(User::to_admin).collect(),
+    }))
+}
+
+/// POST /api/admin/users
+/// Create a user
+#[utoipa::path(
+    post,
+    path = "/api/admin/users",
+    tag = "admin",
+    request_body = AdminCreateUserRequest,
+    responses(
+        (status = 201, description = "User created", body = AdminUserResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 409, description = "Email already exists", body = ErrorResponse),
+    ),
+    security(("admin_token" = []), ("bearer_auth" = []))
+)]
+pub async fn create_user(
+    State(state): State<SharedState>,
+    Extension(AdminActor(actor)): Extension<AdminActor>,
+    Json(payload): Json<AdminCreateUserRequest>,
+) -> Result<(StatusCode, Json<AdminUserResponse>), AppError> {
+    payload.validate()?;
+    let role = payload.role.unwrap_or_else(|| ROLE_USER.to_string());
+    validate_role(&role)?;
+
+    let (hash, salt) = hash_password(&payload.password)?;
+    let mut user = User::new(payload.email, payload.username, hash, salt);
+    user.role = role;
+
+    state
+        .storage
+        .create_user(&user)
+        .await
+        .map_err(|e| match e {
+            DbError::EmailAlreadyExists => {
+                AppError::new(StatusCode::CONFLICT, "Email already exists")
+            }
+            _ => storage_error(e, "Failed to create user"),
+        })?;
+    tracing::info!("Admin created user {} ({})", user.id, user.role);
+    audit::record(
+        &*state.storage,
+        AuditEvent::new("admin.user.create", &actor)
+            .with_target("user", user.id.as_str())
+            .with_metadata(json!({ "email": user.email, "role": user.role })),
+    )
+    .await;
+
+    Ok((StatusCode::CREATED, Json(user.to_admin())))
+}
+
+/// DELETE /api/admin/users/{id}
+/// Delete a user and everything they own
+#[utoipa::path(
+    delete,
+    path = "/api/admin/users/{id}",
+    tag = "admin",
+    params(("id" = String, Path, description = "User ID")),
+    responses(
+        (status = 204, description = "User deleted"),
+        (status = 404, description = "User not found", body = ErrorResponse),
+    ),
+    security(("admin_token" = []), ("bearer_auth" = []))
+)]
+pub async fn delete_user(
+    State(state): State<SharedState>,
+    Extension(AdminActor(actor)): Extension<AdminActor>,
+    Path(user_id): Path<UserId>,
+) -> Result<StatusCode, AppError> {
+    // Looked up first so the audit log keeps the email
+    let user = state
+        .storage
+        .find_user_by_id(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "User not found"))?;
+    state
+        .storage
+        .delete_user(&user_id)
+        .await
+        .map_err(|e| user_error(e, "Failed to delete user"))?;
+    state.users.invalidate(&user_id).await;
+    tracing::info!("Admin deleted user {}", user_id);
+    audit::record(
+        &*state.storage,
+        AuditEvent::new("admin.user.delete", &actor)
+            .with_target("user", user_id.as_str())
+            .with_metadata(json!({ "email": user.email })),
+    )
+    .await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// PUT /api/admin/users/{id}/password
+/// Set a new password for a user who can't log in
+#[utoipa::path(
+    put,
+    path = "/api/admin/users/{id}/password",
+    tag = "admin",
+    params(("id" = String, Path, description = "User ID")),
+    request_body = AdminResetPasswordRequest,
+    responses(
+        (status = 204, description = "Password changed"),
+        (status = 400, description = "Invalid password", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+    ),
+    security(("admin_token" = []), ("bearer_auth" = []))
+)]
+pub async fn reset_password(
+    State(state): State<SharedState>,
+    Extension(AdminActor(actor)): Extension<AdminActor>,
+    Path(user_id): Path<UserId>,
+    Json(payload): Json<AdminResetPasswordRequest>,
+) -> Result<StatusCode, AppError> {
+    payload.validate()?;
+    let (hash, salt) = hash_password(&payload.password)?;
+
+    state
+        .storage
+        .update_user_password(&user_id, &hash, &salt)
+        .await
+        .map_err(|e| user_error(e, "Failed to update password"))?;
+    state.users.invalidate(&user_id).await;
+    tracing::info!("Admin reset the password of user {}", user_id);
+    audit::record(
+        &*state.storage,
+        AuditEvent::new("admin.user.reset_password", &actor).with_target("user", user_id.as_str()),
+    )
+    .await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// PUT /api/admin/users/{id}/role
+/// Make a user an admin, or an ordinary user again
+#[utoipa::path(
+    put,
+    path = "/api/admin/users/{id}/role",
+    tag = "admin",
+    params(("id" = String, Path, description = "User ID")),
+    request_body = AdminSetRoleRequest,
+    responses(
+        (status = 200, description = "Updated user", body = AdminUserResponse),
+        (status = 400, description = "Unknown role", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+    ),
+    security(("admin_token" = []), ("bearer_auth" = []))
+)]
+pub async fn set_role(
+    State(state): State<SharedState>,
+    Extension(AdminActor(actor)): Extension<AdminActor>,
+    Path(user_id): Path<UserId>,
+    Json(payload): Json<AdminSetRoleRequest>,
+) -> Result<Json<AdminUserResponse>, AppError> {
+    validate_role(&payload.role)?;
+
+    state
+        .storage
+        .update_user_role(&user_id, &payload.role)
+        .await
+        .map_err(|e| user_error(e, "Failed to update role"))?;
+    state.users.invalidate(&user_id).await;
+    tracing::info!("Admin set the role of user {} to {}", user_id, payload.role);
+    audit::record(
+        &*state.storage,
+        AuditEvent::new("admin.user.set_role", &actor)
+            .with_target("user", user_id.as_str())
+            .with_metadata(json!({ "role": payload.role })),
+    )
+    .await;
+
+    let user = state
+        .storage
+        .find_user_by_id(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "User not found"))?;
+    Ok(Json(user.to_admin()))
+}
+
+/// PUT /api/admin/users/{id}/disabled
+/// Freeze an account without deleting its data, or unfreeze it
+#[utoipa::path(
+    put,
+    path = "/api/admin/users/{id}/disabled",
+    tag = "admin",
+    params(("id" = String, Path, description = "User ID")),
+    request_body = AdminSetDisabledRequest,
+    responses(
+        (status = 200, description = "Updated user", body = AdminUserResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+    ),
+    security(("admin_token" = []), ("bearer_auth" = []))
+)]
+pub async fn set_disabled(
+    State(state): State<SharedState>,
+    Extension(AdminActor(actor)): Extension<AdminActor>,
+    Path(user_id): Path<UserId>,
+    Json(payload): Json<AdminSetDisabledRequest>,
+) -> Result<Json<AdminUserResponse>, AppError> {
+    state
+        .storage
+        .set_user_disabled(&user_id, payload.disabled)
+        .await
+        .map_err(|e| user_error(e, "Failed to update user"))?;
+    state.users.invalidate(&user_id).await;
+    let action = if payload.disabled {
+        tracing::warn!("Admin disabled user {}", user_id);
+        "admin.user.disable"
+    } else {
+        tracing::info!("Admin re-enabled user {}", user_id);
+        "admin.user.enable"
+    };
+    audit::record(
+        &*state.storage,
+        AuditEvent::new(action, &actor).with_target("user", user_id.as_str()),
+    )
+    .await;
+
+    let user = state
+        .storage
+        .find_user_by_id(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "User not found"))?;
+    Ok(Json(user.to_admin()))
+}
+
+fn validate_role(role: &str) -> Result<(), AppError> {
+    if !is_valid_role(role) {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Unknown role: {}", role),
+        ));
+    }
+    Ok(())
+}
+
+fn user_error(e: DbError, message: &str) -> AppError {
+    match e {
+        DbError::UserNotFound => AppError::new(StatusCode::NOT_FOUND, "User not found"),
+        _ => storage_error(e, message),
+    }
+}
@@ -0,0 +1,371 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use chrono::Utc;
+use serde_json::json;
+
+use crate::{
+    audit::{self, AdminActor},
+    db::DbError,
+    error::AppError,
+    handlers::{parse_optional_timestamp, storage_error, SharedState},
+    ids::UserId,
+    models::{
+        is_valid_announcement_level, Announcement, AnnouncementRequest, AnnouncementResponse,
+        AnnouncementsResponse, AuditEvent, SuccessResponse, ANNOUNCEMENT_LEVEL_INFO,
+    },
+    storage::Storage,
+    AuthUser,
+};
+
+/// Longest announcement accepted, so clients can show it in a banner
+pub const MAX_ANNOUNCEMENT_CHARS: usize = 1000;
+
+/// GET /api/announcements
+/// Announcements showing now, for clients to display even before login
+#[utoipa::path(
+    get,
+    path = "/api/announcements",
+    tag = "announcements",
+    responses(
+        (status = 200, description = "Current announcements, newest first", body = AnnouncementsResponse),
+    )
+)]
+pub async fn list_active(
+    State(state): State<SharedState>,
+) -> Result<Json<AnnouncementsResponse>, AppError> {
+    active_announcements(&*state.storage, None).await
+}
+
+/// GET /api/user/announcements
+/// Announcements showing now that the user hasn't dismissed
+#[utoipa::path(
+    get,
+    path = "/api/user/announcements",
+    tag = "announcements",
+    responses(
+        (status = 200, description = "Current announcements the user hasn't dismissed, newest first", body = AnnouncementsResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn list_for_user(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<AnnouncementsResponse>, AppError> {
+    active_announcements(&*state.storage, Some(&user_id)).await
+}
+
+/// POST /api/user/announcements/:id/dismiss
+/// Stop showing an announcement to the user, on all their devices
+#[utoipa::path(
+    post,
+    path = "/api/user/announcements/{id}/dismiss",
+    tag = "announcements",
+    params(("id" = String, Path, description = "Announcement ID")),
+    responses(
+        (status = 200, description = "Announcement dismissed", body = SuccessResponse),
+        (status = 404, description = "Announcement not found", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn dismiss(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Path(announcement_id): Path<String>,
+) -> Result<Json<SuccessResponse>, AppError> {
+    state
+        .storage
+        .dismiss_announcement(&announcement_id, &user_id)
+        .await
+        .map_err(announcement_error)?;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+async fn active_announcements(
+    storage: &dyn Storage,
+    user_id: Option<&UserId>,
+) -> Result<Json<AnnouncementsResponse>, AppError> {
+    let announcements = storage
+        .list_active_announcements(Utc::now(), user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    Ok(Json(AnnouncementsResponse {
+        announcements: announcements
+            .iter()
+            .map(Announcement::to_response)
+            .collect(),
+    }))
+}
+
+// ============ Admin Handlers ============
+
+/// GET /api/admin/announcements
+/// List every announcement, including past and scheduled ones
+#[utoipa::path(
+    get,
+    path = "/api/admin/announcements",
+    tag = "admin",
+    responses(
+        (status = 200, description = "All announcements, newest first", body = AnnouncementsResponse),
+    ),
+    security(("admin_token" = []), ("bearer_auth" = []))
+)]
+pub async fn list_all(
+    State(state): State<SharedState>,
+) -> Result<Json<AnnouncementsResponse>, AppError> {
+    let announcements = state
+        .storage
+        .list_announcements()
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    Ok(Json(AnnouncementsResponse {
+        announcements: announcements
+            .iter()
+            .map(Announcement::to_response)
+            .collect(),
+    }))
+}
+
+/// POST /api/admin/announcements
+/// Announce something to every user, now or within a window
+#[utoipa::path(
+    post,
+    path = "/api/admin/announcements",
+    tag = "admin",
+    request_body = AnnouncementRequest,
+    responses(
+        (status = 201, description = "Announcement created", body = AnnouncementResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+    ),
+    security(("admin_token" = []), ("bearer_auth" = []))
+)]
+pub async fn create(
+    State(state): State<SharedState>,
+    Extension(AdminActor(actor)): Extension<AdminActor>,
+    Json(payload): Json<AnnouncementRequest>,
+) -> Result<(StatusCode, Json<AnnouncementResponse>), AppError> {
+    let announcement = validate(payload)?;
+
+    state
+        .storage
+        .create_announcement(&announcement)
+        .await
+        .map_err(|e| storage_error(e, "Failed to create announcement"))?;
+    tracing::info!("Admin created announcement {}", announcement.id);
+    audit::record(
+        &*state.storage,
+        AuditEvent::new("admin.announcement.create", &actor)
+            .with_target("announcement", &announcement.id)
+            .with_metadata(json!({ "level": announcement.level })),
+    )
+    .await;
+
+    Ok((StatusCode::CREATED, Json(announcement.to_response())))
+}
+
+/// PUT /api/admin/announcements/{id}
+/// Replace an announcement's message, level and window
+#[utoipa::path(
+    put,
+    path = "/api/admin/announcements/{id}",
+    tag = "admin",
+    params(("id" = String, Path, description = "Announcement ID")),
+    request_body = AnnouncementRequest,
+    responses(
+        (status = 200, description = "Updated announcement", body = AnnouncementResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 404, description = "Announcement not found", body = ErrorResponse),
+    ),
+    security(("admin_token" = []), ("bearer_auth" = []))
+)]
+pub async fn update(
+    State(state): State<SharedState>,
+    Extension(AdminActor(actor)): Extension<AdminActor>,
+    Path(announcement_id): Path<String>,
+    Json(payload): Json<AnnouncementRequest>,
+) -> Result<Json<AnnouncementResponse>, AppError> {
+    let changes = validate(payload)?;
+
+    let announcement = state
+        .storage
+        .update_announcement(
+            &announcement_id,
+            &changes.message,
+            &changes.level,
+            changes.starts_at,
+            changes.ends_at,
+        )
+        .await
+        .map_err(announcement_error)?;
+    tracing::info!("Admin updated announcement {}", announcement.id);
+    audit::record(
+        &*state.storage,
+        AuditEvent::new("admin.announcement.update", &actor)
+            .with_target("announcement", &announcement.id)
+            .with_metadata(json!({ "level": announcement.level })),
+    )
+    .await;
+
+    Ok(Json(announcement.to_response()))
+}
+
+/// DELETE /api/admin/announcements/{id}
+/// Take an announcement down
+#[utoipa::path(
+    delete,
+    path = "/api/admin/announcements/{id}",
+    tag = "admin",
+    params(("id" = String, Path, description = "Announcement ID")),
+    responses(
+        (status = 204, description = "Announcement deleted"),
+        (status = 404, description = "Announcement not found", body = ErrorResponse),
+    ),
+    security(("admin_token" = []), ("bearer_auth" = []))
+)]
+pub async fn delete(
+    State(state): State<SharedState>,
+    Extension(AdminActor(actor)): Extension<AdminActor>,
+    Path(announcement_id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    state
+        .storage
+        .delete_announcement(&announcement_id)
+        .await
+        .map_err(announcement_error)?;
+    tracing::info!("Admin deleted announcement {}", announcement_id);
+    audit::record(
+        &*state.storage,
+        AuditEvent::new("admin.announcement.delete", &actor)
+            .with_target("announcement", &announcement_id),
+    )
+    .await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Check an announcement's message and level, and parse its window
+fn validate(payload: AnnouncementRequest) -> Result<Announcement, AppError> {
+    if payload.message.trim().is_empty() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Message cannot be empty",
+        ));
+    }
+    if payload.message.chars().count() > MAX_ANNOUNCEMENT_CHARS {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Message must be at most {} characters",
+                MAX_ANNOUNCEMENT_CHARS
+            ),
+        ));
+    }
+
+    let level = payload
+        .level
+        .unwrap_or_else(|| ANNOUNCEMENT_LEVEL_INFO.to_string());
+    if !is_valid_announcement_level(&level) {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Level must be info or warning",
+        ));
+    }
+
+    let starts_at = parse_optional_timestamp(payload.starts_at.as_deref(), "starts_at")?;
+    let ends_at = parse_optional_timestamp(payload.ends_at.as_deref(), "ends_at")?;
+    if let (Some(starts_at), Some(ends_at)) = (starts_at, ends_at) {
+        if ends_at <= starts_at {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "ends_at must be after starts_at",
+            ));
+        }
+    }
+
+    Ok(Announcement::new(
+        payload.message,
+        level,
+        starts_at,
+        ends_at,
+    ))
+}
+
+fn announcement_error(e: DbError) -> AppError {
+    match e {
+        DbError::AnnouncementNotFound => {
+            AppError::new(StatusCode::NOT_FOUND, "Announcement not found")
+        }
+        _ => storage_error(e, "Failed to save announcement"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{storage::MemoryStorage, utils::hash_password};
+    use chrono::Duration;
+
+    #[tokio::test]
+    async fn test_active_announcements_follow_window_and_dismissals() {
+        let storage = MemoryStorage::new();
+        let (hash, salt) = hash_password("password123").unwrap();
+        let user = crate::models::User::new(
+            "reader@example.com".to_string(),
+            "reader".to_string(),
+            hash,
+            salt,
+        );
+        storage.create_user(&user).await.unwrap();
+
+        let now = Utc::now();
+        let current = Announcement::new("Now".to_string(), "info".to_string(), None, None);
+        let scheduled = Announcement::new(
+            "Maintenance tonight".to_string(),
+            "warning".to_string(),
+            Some(now + Duration::hours(1)),
+            Some(now + Duration::hours(2)),
+        );
+        let expired = Announcement::new(
+            "Yesterday".to_string(),
+            "info".to_string(),
+            None,
+            Some(now - Duration::hours(1)),
+        );
+        for announcement in [&current, &scheduled, &expired] {
+            storage.create_announcement(announcement).await.unwrap();
+        }
+
+        let ids = |announcements: Vec<Announcement>| -> Vec<String> {
+            announcements.into_iter().map(|a| a.id).collect()
+        };
+        let active = storage.list_active_announcements(now, None).await;
+        assert_eq!(ids(active.unwrap()), vec![current.id.clone()]);
+        let later = now + Duration::minutes(90);
+        let active = storage.list_active_announcements(later, None).await;
+        assert_eq!(ids(active.unwrap()).len(), 2);
+
+        storage
+            .dismiss_announcement(&current.id, &user.id)
+            .await
+            .unwrap();
+        // Dismissing twice is fine
+        storage
+            .dismiss_announcement(&current.id, &user.id)
+            .await
+            .unwrap();
+        let active = storage.list_active_announcements(now, Some(&user.id)).await;
+        assert!(active.unwrap().is_empty());
+        let active = storage.list_active_announcements(now, None).await;
+        assert_eq!(active.unwrap().len(), 1);
+
+        assert!(matches!(
+            storage.dismiss_announcement("missing", &user.id).await,
+            Err(DbError::AnnouncementNotFound)
+        ));
+    }
+}
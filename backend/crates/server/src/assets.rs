@@ -0,0 +1,104 @@
+use axum::{
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use rust_embed::RustEmbed;
+
+/// The frontend build (`npm run build`, copied to `backend/dist`), compiled
+/// into the binary so it can be deployed as a single file
+#[derive(RustEmbed)]
+#[folder = "../../dist/"]
+struct Frontend;
+
+const INDEX: &str = "index.html";
+
+/// Serve an embedded frontend file, or the app itself for client-side routes
+///
+/// Vite fingerprints everything under `assets/`, so those are cached for a
+/// year; the rest, above all `index.html`, is revalidated with its ETag.
+pub async fn serve(method: Method, uri: Uri, headers: HeaderMap) -> Response {
+    if method != Method::GET && method != Method::HEAD {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    }
+
+    let path = match uri.path().trim_start_matches('/') {
+        "" => INDEX,
+        path => path,
+    };
+    let (path, file) = match Frontend::get(path) {
+        Some(file) => (path, file),
+        None if is_app_route(path) => match Frontend::get(INDEX) {
+            Some(file) => (INDEX, file),
+            None => return StatusCode::NOT_FOUND.into_response(),
+        },
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    let hash: String = file
+        .metadata
+        .sha256_hash()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let etag = format!("\"{}\"", hash);
+    let cache_control = HeaderValue::from_static(cache_control(path));
+    let etag = HeaderValue::from_str(&etag).expect("hex is a valid header value");
+
+    if headers
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|value| value == etag)
+    {
+        return (
+            StatusCode::NOT_MODIFIED,
+            [(header::ETAG, etag), (header::CACHE_CONTROL, cache_control)],
+        )
+            .into_response();
+    }
+
+    let content_type = HeaderValue::from_str(file.metadata.mimetype())
+        .unwrap_or(HeaderValue::from_static("application/octet-stream"));
+    let headers = [
+        (header::CONTENT_TYPE, content_type),
+        (header::ETAG, etag),
+        (header::CACHE_CONTROL, cache_control),
+    ];
+    (headers, file.data.into_owned()).into_response()
+}
+
+fn cache_control(path: &str) -> &'static str {
+    if path.starts_with("assets/") {
+        "public, max-age=31536000, immutable"
+    } else {
+        "no-cache"
+    }
+}
+
+/// Paths the frontend router handles, such as `/settings`; missing files and
+/// unknown API routes still get a 404
+fn is_app_route(path: &str) -> bool {
+    let last = path.rsplit('/').next().unwrap_or_default();
+    !path.starts_with("api/") && !last.contains('.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_control() {
+        assert_eq!(
+            cache_control("assets/index-4f3a9c.js"),
+            "public, max-age=31536000, immutable"
+        );
+        assert_eq!(cache_control("index.html"), "no-cache");
+        assert_eq!(cache_control("favicon.ico"), "no-cache");
+    }
+
+    #[test]
+    fn test_app_routes_fall_back_to_index() {
+        assert!(is_app_route("settings"));
+        assert!(is_app_route("messages/42"));
+        assert!(!is_app_route("assets/missing.js"));
+        assert!(!is_app_route("api/unknown"));
+    }
+}
@@ -0,0 +1,99 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+
+use crate::{
+    db::AuditFilter,
+    error::AppError,
+    handlers::{parse_optional_timestamp, storage_error, SharedState},
+    models::{AuditEvent, AuditEventsResponse, AuditQuery, PageQuery},
+    pagination::{PageSpec, Pagination, SortOrder},
+    storage::Storage,
+    validation::ValidationErrors,
+};
+
+/// Actor of actions taken with `ADMIN_TOKEN` rather than as a user
+pub const ACTOR_ADMIN_TOKEN: &str = "admin_token";
+/// Actor of actions the server takes on its own, such as pruning old exports
+pub const ACTOR_SYSTEM: &str = "system";
+
+/// Who is calling an admin route, set by `admin_middleware`: a user ID or
+/// `ACTOR_ADMIN_TOKEN`
+#[derive(Debug, Clone)]
+pub struct AdminActor(pub String);
+
+/// Append `event` to the audit log
+///
+/// Called once the audited change has been made, so a failure is logged
+/// rather than failing the request.
+pub async fn record(storage: &dyn Storage, event: AuditEvent) {
+    if let Err(e) = storage.record_audit_event(&event).await {
+        tracing::error!("Failed to record audit event {}: {}", event.action, e);
+    }
+}
+
+/// Audit events come newest first, 100 at a time, each page following on
+/// from the last event ID of the one before
+pub struct AuditPages;
+
+impl PageSpec for AuditPages {
+    const DEFAULT_LIMIT: Option<u32> = Some(100);
+    const MAX_LIMIT: u32 = 500;
+    const SORTS: &'static [&'static str] = &[];
+    const DEFAULT_ORDER: SortOrder = SortOrder::Desc;
+    const CURSOR: bool = true;
+}
+
+/// GET /api/admin/audit
+/// List audit events, newest first, a page at a time
+#[utoipa::path(
+    get,
+    path = "/api/admin/audit",
+    tag = "admin",
+    params(AuditQuery, PageQuery),
+    responses(
+        (status = 200, description = "A page of audit events", body = AuditEventsResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+    ),
+    security(("admin_token" = []), ("bearer_auth" = []))
+)]
+pub async fn list_audit_events(
+    State(state): State<SharedState>,
+    Query(query): Query<AuditQuery>,
+    pagination: Pagination<AuditPages>,
+) -> Result<Json<AuditEventsResponse>, AppError> {
+    let limit = pagination.limit.unwrap_or(AuditPages::MAX_LIMIT);
+    let cursor = match pagination.cursor.as_deref() {
+        Some(cursor) => Some(cursor.parse::<i64>().map_err(|_| {
+            let mut errors = ValidationErrors::default();
+            errors.reject("cursor", "invalid", "cursor must be an event ID");
+            errors
+        })?),
+        None => None,
+    };
+    let filter = AuditFilter {
+        action: query.action,
+        actor: query.actor,
+        target_id: query.target_id,
+        since: parse_optional_timestamp(query.since.as_deref(), "since")?,
+        until: parse_optional_timestamp(query.until.as_deref(), "until")?,
+        before_id: cursor.or(query.before),
+    };
+
+    let events = state
+        .storage
+        .list_audit_events(&filter, i64::from(limit))
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    // A full page may have more after it
+    let next_before = match events.last() {
+        Some(last) if events.len() == limit as usize => Some(last.id),
+        _ => None,
+    };
+    Ok(Json(AuditEventsResponse {
+        events: events.iter().map(AuditEvent::to_response).collect(),
+        next_before,
+    }))
+}
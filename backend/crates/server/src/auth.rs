@@ -1,8 +1,14 @@
+use std::sync::Arc;
+
 use chrono::{Duration, Utc};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use thiserror::Error;
 
-use crate::models::Claims;
+use crate::{
+    ids::UserId,
+    models::{Claims, User},
+    utils::verify_password,
+};
 
 #[derive(Debug, Error)]
 pub enum AuthError {
@@ -17,13 +23,65 @@ pub enum AuthError {
     MissingAuthHeader,
     #[error("Invalid authorization header format")]
     InvalidAuthHeader,
+    #[error("Failed to check credentials: {0}")]
+    CredentialCheckError(String),
+}
+
+/// How users prove who they are: their password at login, then the bearer
+/// token they were given with every later request
+///
+/// [`JwtAuth`] is the default. Applications embedding the server can set
+/// their own as `AppState::auth`, for instance to accept tokens from their
+/// single sign-on.
+#[axum::async_trait]
+pub trait AuthProvider: Send + Sync {
+    /// Whether `password` is `user`'s password
+    async fn verify_credentials(&self, user: &User, password: &str) -> Result<bool, AuthError>;
+
+    /// A token for `user_id` to send as `Authorization: Bearer`
+    async fn issue_token(&self, user_id: &UserId) -> Result<String, AuthError>;
+
+    /// The claims of a token, if it is genuine and unexpired
+    async fn validate_token(&self, token: &str) -> Result<Claims, AuthError>;
+}
+
+/// Shared handle to the configured authentication provider
+pub type SharedAuthProvider = Arc<dyn AuthProvider>;
+
+/// Argon2 password hashes, and JWTs signed with the configured secret
+pub struct JwtAuth {
+    secret: String,
+}
+
+impl JwtAuth {
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self {
+            secret: secret.into(),
+        }
+    }
+}
+
+#[axum::async_trait]
+impl AuthProvider for JwtAuth {
+    async fn verify_credentials(&self, user: &User, password: &str) -> Result<bool, AuthError> {
+        verify_password(password, &user.password_hash)
+            .map_err(|e| AuthError::CredentialCheckError(e.to_string()))
+    }
+
+    async fn issue_token(&self, user_id: &UserId) -> Result<String, AuthError> {
+        create_token(user_id, &self.secret)
+    }
+
+    async fn validate_token(&self, token: &str) -> Result<Claims, AuthError> {
+        validate_token(token, &self.secret)
+    }
 }
 
 /// Token expiration in days
 const TOKEN_EXPIRATION_DAYS: i64 = 15;
 
 /// Create a JWT token for the given user ID
-pub fn create_token(user_id: &str, secret: &str) -> Result<String, AuthError> {
+pub fn create_token(user_id: &UserId, secret: &str) -> Result<String, AuthError> {
     let expiration = Utc::now()
         .checked_add_signed(Duration::days(TOKEN_EXPIRATION_DAYS))
         .expect("Valid timestamp")
@@ -82,9 +140,9 @@ mod tests {
 
     #[test]
     fn test_create_token_returns_valid_jwt() {
-        let user_id = "user-123";
+        let user_id = UserId::generate();
 
-        let token = create_token(user_id, TEST_SECRET).unwrap();
+        let token = create_token(&user_id, TEST_SECRET).unwrap();
 
         // JWT tokens have 3 parts separated by dots
         let parts: Vec<&str> = token.split('.').collect();
@@ -98,9 +156,9 @@ mod tests {
 
     #[test]
     fn test_validate_token_returns_correct_claims() {
-        let user_id = "user-456";
+        let user_id = UserId::generate();
 
-        let token = create_token(user_id, TEST_SECRET).unwrap();
+        let token = create_token(&user_id, TEST_SECRET).unwrap();
         let claims = validate_token(&token, TEST_SECRET).unwrap();
 
         assert_eq!(claims.user_id, user_id);
@@ -109,9 +167,9 @@ mod tests {
 
     #[test]
     fn test_validate_token_fails_with_wrong_secret() {
-        let user_id = "user-789";
+        let user_id = UserId::generate();
 
-        let token = create_token(user_id, TEST_SECRET).unwrap();
+        let token = create_token(&user_id, TEST_SECRET).unwrap();
         let result = validate_token(&token, "wrong-secret");
 
         assert!(result.is_err());
@@ -172,15 +230,37 @@ mod tests {
 
     #[test]
     fn test_create_token_different_users_get_different_tokens() {
-        let token1 = create_token("user-1", TEST_SECRET).unwrap();
-        let token2 = create_token("user-2", TEST_SECRET).unwrap();
+        let token1 = create_token(&UserId::generate(), TEST_SECRET).unwrap();
+        let token2 = create_token(&UserId::generate(), TEST_SECRET).unwrap();
 
         assert_ne!(token1, token2);
     }
 
+    #[tokio::test]
+    async fn test_jwt_auth_round_trip() {
+        let auth = JwtAuth::new(TEST_SECRET);
+        let (hash, salt) = crate::utils::hash_password("password123").unwrap();
+        let user = User::new(
+            "auth@example.com".to_string(),
+            "authuser".to_string(),
+            hash,
+            salt,
+        );
+
+        assert!(auth.verify_credentials(&user, "password123").await.unwrap());
+        assert!(!auth.verify_credentials(&user, "wrong").await.unwrap());
+
+        let token = auth.issue_token(&user.id).await.unwrap();
+        assert_eq!(auth.validate_token(&token).await.unwrap().user_id, user.id);
+        assert!(JwtAuth::new("other-secret")
+            .validate_token(&token)
+            .await
+            .is_err());
+    }
+
     #[test]
     fn test_token_expiration_is_in_future() {
-        let token = create_token("user-123", TEST_SECRET).unwrap();
+        let token = create_token(&UserId::generate(), TEST_SECRET).unwrap();
         let claims = validate_token(&token, TEST_SECRET).unwrap();
 
         let now = Utc::now().timestamp() as usize;
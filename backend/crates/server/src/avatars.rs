@@ -0,0 +1,189 @@
+//! Profile pictures, shown next to users' entries in shared journals
+//!
+//! Uploads are decoded, shrunk to fit [`AVATAR_SIZE`] and stored as PNG, so
+//! what is served is small and in one format whatever was sent.
+
+use std::io::Cursor;
+
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use image::{imageops::FilterType, ImageFormat, ImageReader, Limits};
+
+use crate::{
+    error::AppError,
+    handlers::{storage_error, SharedState},
+    ids::UserId,
+    models::{Avatar, SuccessResponse},
+    AuthUser,
+};
+
+/// Maximum request body size accepted by `PUT /api/user/avatar` (5 MB)
+pub const AVATAR_BODY_LIMIT: usize = 5 * 1024 * 1024;
+
+/// Width and height stored avatars fit in, in pixels
+pub const AVATAR_SIZE: u32 = 256;
+
+/// Content type avatars are stored and served as
+const AVATAR_CONTENT_TYPE: &str = "image/png";
+
+/// Largest width or height decoded, guarding against decompression bombs
+const MAX_SOURCE_DIMENSION: u32 = 8192;
+
+/// Upload content types accepted, with the format each is decoded as
+const ACCEPTED_TYPES: &[(&str, ImageFormat)] = &[
+    ("image/png", ImageFormat::Png),
+    ("image/jpeg", ImageFormat::Jpeg),
+    ("image/webp", ImageFormat::WebP),
+    ("image/gif", ImageFormat::Gif),
+];
+
+/// Decode an uploaded image and re-encode it as PNG, shrunk to fit
+/// [`AVATAR_SIZE`] if it is bigger
+pub fn resize_avatar(data: &[u8], format: ImageFormat) -> Result<Vec<u8>, String> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SOURCE_DIMENSION);
+    limits.max_image_height = Some(MAX_SOURCE_DIMENSION);
+    let mut reader = ImageReader::with_format(Cursor::new(data), format);
+    reader.limits(limits);
+    let image = reader
+        .decode()
+        .map_err(|e| format!("Invalid image: {}", e))?;
+
+    let image = if image.width() > AVATAR_SIZE || image.height() > AVATAR_SIZE {
+        image.resize(AVATAR_SIZE, AVATAR_SIZE, FilterType::Lanczos3)
+    } else {
+        image
+    };
+
+    let mut png = Vec::new();
+    image
+        .into_rgba8()
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode avatar: {}", e))?;
+    Ok(png)
+}
+
+/// PUT /api/user/avatar
+/// Replace the user's avatar with the image in the request body
+#[utoipa::path(
+    put,
+    path = "/api/user/avatar",
+    tag = "user",
+    request_body(content = String, description = "PNG, JPEG, WebP or GIF image, up to 5 MB", content_type = "image/png"),
+    responses(
+        (status = 200, description = "Avatar replaced", body = SuccessResponse),
+        (status = 400, description = "Not a readable image", body = ErrorResponse),
+        (status = 413, description = "Image too large", body = ErrorResponse),
+        (status = 415, description = "Unsupported image type", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn upload_avatar(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<SuccessResponse>, AppError> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let format = ACCEPTED_TYPES
+        .iter()
+        .find(|(accepted, _)| content_type.starts_with(accepted))
+        .map(|(_, format)| *format)
+        .ok_or_else(|| {
+            AppError::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "Avatars must be PNG, JPEG, WebP or GIF images",
+            )
+        })?;
+
+    let data = tokio::task::spawn_blocking(move || resize_avatar(&body, format))
+        .await
+        .map_err(|_| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read image"))?
+        .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, e))?;
+
+    let avatar = Avatar {
+        user_id,
+        content_type: AVATAR_CONTENT_TYPE.to_string(),
+        data,
+        updated_at: Utc::now(),
+    };
+    state
+        .storage
+        .set_user_avatar(&avatar)
+        .await
+        .map_err(|e| storage_error(e, "Failed to save avatar"))?;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+/// GET /api/user/:id/avatar
+/// Any user's avatar, for showing next to their entries
+#[utoipa::path(
+    get,
+    path = "/api/user/{id}/avatar",
+    tag = "user",
+    params(("id" = String, Path, description = "User ID")),
+    responses(
+        (status = 200, description = "The avatar", content_type = "image/png"),
+        (status = 404, description = "No avatar set", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_avatar(
+    State(state): State<SharedState>,
+    Path(user_id): Path<UserId>,
+) -> Result<Response, AppError> {
+    let avatar = state
+        .storage
+        .get_user_avatar(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "No avatar set"))?;
+
+    Ok(([(header::CONTENT_TYPE, avatar.content_type)], avatar.data).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgba};
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = ImageBuffer::from_pixel(width, height, Rgba([200u8, 80, 40, 255]));
+        let mut data = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_resize_avatar_shrinks_to_fit() {
+        let resized = resize_avatar(&png(1024, 512), ImageFormat::Png).unwrap();
+        let image = image::load_from_memory(&resized).unwrap();
+        assert_eq!(
+            (image.width(), image.height()),
+            (AVATAR_SIZE, AVATAR_SIZE / 2)
+        );
+
+        // Small images are kept at their size
+        let resized = resize_avatar(&png(32, 32), ImageFormat::Png).unwrap();
+        let image = image::load_from_memory(&resized).unwrap();
+        assert_eq!((image.width(), image.height()), (32, 32));
+    }
+
+    #[test]
+    fn test_resize_avatar_rejects_other_data() {
+        assert!(resize_avatar(b"not an image", ImageFormat::Png).is_err());
+        assert!(resize_avatar(&png(8, 8), ImageFormat::Jpeg).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::RwLock,
+    time::Duration,
+};
+
+use chrono::Utc;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::BackupSettings, destinations::S3Destination, handlers::SharedState, storage::Storage,
+};
+
+const BACKUP_PREFIX: &str = "dissipate-";
+const BACKUP_SUFFIX: &str = ".db";
+
+/// Scheduled backup settings
+#[derive(Debug, Clone)]
+pub struct BackupConfig {
+    pub dir: PathBuf,
+    pub interval: Duration,
+    /// Number of most recent backups to keep
+    pub keep: usize,
+}
+
+impl BackupConfig {
+    /// Backups are disabled unless `dir` is set
+    ///
+    /// `interval_hours` defaults to 24 and `keep` to 7.
+    pub fn from_config(settings: &BackupSettings) -> Result<Option<Self>, String> {
+        let Some(dir) = &settings.dir else {
+            return Ok(None);
+        };
+
+        let interval_hours = settings.interval_hours.unwrap_or(24);
+        if interval_hours == 0 {
+            return Err(
+                "[backup] interval_hours (BACKUP_INTERVAL_HOURS) must be positive".to_string(),
+            );
+        }
+        let keep = settings.keep.unwrap_or(7);
+        if keep == 0 {
+            return Err("[backup] keep (BACKUP_KEEP) must be at least 1".to_string());
+        }
+
+        Ok(Some(Self {
+            dir: PathBuf::from(dir),
+            interval: Duration::from_secs(interval_hours * 60 * 60),
+            keep,
+        }))
+    }
+}
+
+/// Outcome of scheduled backups, reported by the health endpoint
+#[derive(Debug, Default)]
+pub struct BackupStatus {
+    last_success: RwLock<Option<String>>,
+}
+
+impl BackupStatus {
+    pub fn last_success(&self) -> Option<String> {
+        self.last_success.read().unwrap().clone()
+    }
+
+    fn record_success(&self, at: String) {
+        *self.last_success.write().unwrap() = Some(at);
+    }
+}
+
+/// Run backups every `config.interval` in the background
+pub fn spawn(
+    state: SharedState,
+    config: BackupConfig,
+    shutdown: CancellationToken,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let start = tokio::time::Instant::now() + config.interval;
+        let mut interval = tokio::time::interval_at(start, config.interval);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = shutdown.cancelled() => break,
+            }
+            match run_backup(state.storage.as_ref(), &config).await {
+                Ok(path) => {
+                    tracing::info!("Wrote backup {}", path.display());
+                    state.backups.record_success(Utc::now().to_rfc3339());
+                    if let Some(s3) = &state.s3 {
+                        upload_backup(s3, &path).await;
+                    }
+                }
+                Err(e) => tracing::error!("Scheduled backup failed: {}", e),
+            }
+        }
+    })
+}
+
+/// Write one backup into the configured directory and prune old ones
+pub async fn run_backup(storage: &dyn Storage, config: &BackupConfig) -> Result<PathBuf, String> {
+    tokio::fs::create_dir_all(&config.dir)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", config.dir.display(), e))?;
+
+    // Timestamped names sort chronologically
+    let name = format!(
+        "{}{}{}",
+        BACKUP_PREFIX,
+        Utc::now().format("%Y%m%dT%H%M%S%.3fZ"),
+        BACKUP_SUFFIX
+    );
+    let path = config.dir.join(name);
+    let path_str = path
+        .to_str()
+        .ok_or_else(|| format!("Backup path is not valid UTF-8: {}", path.display()))?;
+
+    storage
+        .backup_to(path_str)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    prune_backups(&config.dir, config.keep)
+        .await
+        .map_err(|e| format!("Failed to prune old backups: {}", e))?;
+
+    Ok(path)
+}
+
+/// Copy a backup to the S3 bucket; the local copy is kept either way
+async fn upload_backup(s3: &S3Destination, path: &Path) {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return;
+    };
+    let key = s3.backup_key(name, Utc::now());
+    match s3.upload_file(path, &key).await {
+        Ok(()) => tracing::info!("Uploaded backup to {}", key),
+        Err(e) => tracing::error!("Backup upload failed: {}", e),
+    }
+}
+
+async fn prune_backups(dir: &Path, keep: usize) -> std::io::Result<()> {
+    let mut backups = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(BACKUP_PREFIX) && name.ends_with(BACKUP_SUFFIX) {
+            backups.push(name);
+        }
+    }
+
+    backups.sort();
+    let excess = backups.len().saturating_sub(keep);
+    for name in &backups[..excess] {
+        tokio::fs::remove_file(dir.join(name)).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db, storage::SqliteStorage};
+
+    fn temp_backup_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("dissipate-backups-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn test_run_backup_rotates_old_files() {
+        // VACUUM INTO from an in-memory database writes to memory too
+        let dir = temp_backup_dir();
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let url = format!("sqlite://{}", dir.join("source.sqlite").display());
+        let pool = db::init_pool(&url).await.unwrap();
+        let storage = SqliteStorage::new(pool);
+        let config = BackupConfig {
+            dir: dir.join("backups"),
+            interval: Duration::from_secs(60),
+            keep: 2,
+        };
+
+        // Unrelated files in the directory are left alone
+        tokio::fs::create_dir_all(&config.dir).await.unwrap();
+        tokio::fs::write(config.dir.join("notes.txt"), "keep me")
+            .await
+            .unwrap();
+
+        let mut written = Vec::new();
+        for _ in 0..3 {
+            written.push(run_backup(&storage, &config).await.unwrap());
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        assert!(!written[0].exists());
+        assert!(written[1].exists());
+        assert!(written[2].exists());
+        assert!(config.dir.join("notes.txt").exists());
+
+        storage.close().await;
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[test]
+    fn test_backup_status_records_success() {
+        let status = BackupStatus::default();
+        assert_eq!(status.last_success(), None);
+
+        status.record_success("2024-01-01T00:00:00+00:00".to_string());
+
+        assert_eq!(
+            status.last_success().as_deref(),
+            Some("2024-01-01T00:00:00+00:00")
+        );
+    }
+}
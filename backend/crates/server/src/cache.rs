@@ -0,0 +1,170 @@
+use std::time::Duration;
+
+use moka::future::Cache;
+
+use crate::{config::CacheConfig, db::DbError, ids::UserId, models::User, storage::Storage};
+
+const DEFAULT_TTL: Duration = Duration::from_secs(60);
+const DEFAULT_CAPACITY: u64 = 10_000;
+
+/// Short-lived cache in front of the user lookups on hot paths
+///
+/// Only found users are cached. Handlers that change a profile must call
+/// `invalidate` so later lookups see the change before the TTL expires.
+#[derive(Clone)]
+pub struct UserCache {
+    by_id: Cache<UserId, User>,
+    /// Email to user ID; resolved through `by_id` and checked against the
+    /// user's current email, so an entry left over from an email change
+    /// is never trusted
+    email_ids: Cache<String, UserId>,
+}
+
+impl UserCache {
+    pub fn new(ttl: Duration, capacity: u64) -> Self {
+        Self {
+            by_id: Cache::builder()
+                .time_to_live(ttl)
+                .max_capacity(capacity)
+                .build(),
+            email_ids: Cache::builder()
+                .time_to_live(ttl)
+                .max_capacity(capacity)
+                .build(),
+        }
+    }
+
+    /// Use `user_ttl_secs` (default 60); 0 disables caching
+    pub fn from_config(config: &CacheConfig) -> Self {
+        let ttl = config
+            .user_ttl_secs
+            .map_or(DEFAULT_TTL, Duration::from_secs);
+        let capacity = if ttl.is_zero() { 0 } else { DEFAULT_CAPACITY };
+        Self::new(ttl, capacity)
+    }
+
+    pub async fn find_by_id(
+        &self,
+        storage: &dyn Storage,
+        id: &UserId,
+    ) -> Result<Option<User>, DbError> {
+        if let Some(user) = self.by_id.get(id).await {
+            return Ok(Some(user));
+        }
+
+        let user = storage.find_user_by_id(id).await?;
+        if let Some(user) = &user {
+            self.insert(user).await;
+        }
+        Ok(user)
+    }
+
+    pub async fn find_by_email(
+        &self,
+        storage: &dyn Storage,
+        email: &str,
+    ) -> Result<Option<User>, DbError> {
+        if let Some(id) = self.email_ids.get(email).await {
+            if let Some(user) = self.find_by_id(storage, &id).await? {
+                if user.email == email {
+                    return Ok(Some(user));
+                }
+            }
+            self.email_ids.invalidate(email).await;
+        }
+
+        let user = storage.find_user_by_email(email).await?;
+        if let Some(user) = &user {
+            self.insert(user).await;
+        }
+        Ok(user)
+    }
+
+    /// Drop a user whose row has changed
+    pub async fn invalidate(&self, id: &UserId) {
+        if let Some(user) = self.by_id.remove(id).await {
+            self.email_ids.invalidate(&user.email).await;
+        }
+    }
+
+    async fn insert(&self, user: &User) {
+        self.by_id.insert(user.id.clone(), user.clone()).await;
+        self.email_ids
+            .insert(user.email.clone(), user.id.clone())
+            .await;
+    }
+}
+
+impl Default for UserCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_TTL, DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    async fn setup() -> (MemoryStorage, User) {
+        let storage = MemoryStorage::new();
+        let user = User::new(
+            "cached@example.com".to_string(),
+            "cached".to_string(),
+            "hash".to_string(),
+            "salt".to_string(),
+        );
+        storage.create_user(&user).await.unwrap();
+        (storage, user)
+    }
+
+    #[tokio::test]
+    async fn test_cached_user_served_until_invalidated() {
+        let (storage, user) = setup().await;
+        let cache = UserCache::default();
+
+        let found = cache.find_by_id(&storage, &user.id).await.unwrap().unwrap();
+        assert_eq!(found.username, "cached");
+
+        storage
+            .update_user_username(&user.id, "renamed")
+            .await
+            .unwrap();
+        let found = cache.find_by_id(&storage, &user.id).await.unwrap().unwrap();
+        assert_eq!(found.username, "cached");
+
+        cache.invalidate(&user.id).await;
+        let found = cache.find_by_id(&storage, &user.id).await.unwrap().unwrap();
+        assert_eq!(found.username, "renamed");
+    }
+
+    #[tokio::test]
+    async fn test_email_change_invalidates_old_email() {
+        let (storage, user) = setup().await;
+        let cache = UserCache::default();
+
+        assert!(cache
+            .find_by_email(&storage, "cached@example.com")
+            .await
+            .unwrap()
+            .is_some());
+
+        storage
+            .update_user_email(&user.id, "moved@example.com")
+            .await
+            .unwrap();
+        cache.invalidate(&user.id).await;
+
+        assert!(cache
+            .find_by_email(&storage, "cached@example.com")
+            .await
+            .unwrap()
+            .is_none());
+        let found = cache
+            .find_by_email(&storage, "moved@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.id, user.id);
+    }
+}
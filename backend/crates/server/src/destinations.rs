@@ -0,0 +1,289 @@
+use std::path::Path;
+#[cfg(feature = "s3")]
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+#[cfg(feature = "s3")]
+use object_store::{
+    aws::{AmazonS3Builder, AmazonS3ConfigKey},
+    buffered::BufWriter,
+    path::Path as ObjectPath,
+    ObjectStore,
+};
+use reqwest::{header, redirect, Method, StatusCode};
+#[cfg(feature = "s3")]
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    config::S3Config,
+    models::{ExportJob, WebDavDestination},
+};
+
+/// S3-compatible bucket that exports and backups are copied to
+#[cfg(feature = "s3")]
+pub struct S3Destination {
+    store: Arc<dyn ObjectStore>,
+    /// Prepended to every key, e.g. `dissipate/`
+    prefix: String,
+}
+
+#[cfg(feature = "s3")]
+impl S3Destination {
+    /// Connect to the `[s3]` bucket; uploads are disabled unless `bucket` is
+    /// set
+    ///
+    /// Credentials, region and endpoint are left to the object store, which
+    /// reads the standard `AWS_*` variables (`AWS_ENDPOINT` for MinIO and
+    /// other S3-compatible stores).
+    pub fn from_config(config: &S3Config) -> Result<Option<Self>, String> {
+        let Some(bucket) = &config.bucket else {
+            return Ok(None);
+        };
+
+        let mut builder = AmazonS3Builder::from_env().with_bucket_name(bucket);
+        for (key, value) in [
+            ("aws_server_side_encryption", &config.sse),
+            ("aws_sse_kms_key_id", &config.sse_kms_key_id),
+        ] {
+            if let Some(value) = value {
+                // By name, since object_store doesn't export the encryption keys
+                let key: AmazonS3ConfigKey = key
+                    .parse()
+                    .map_err(|e| format!("Invalid S3 configuration: {}", e))?;
+                builder = builder.with_config(key, value);
+            }
+        }
+        let store = builder
+            .build()
+            .map_err(|e| format!("Invalid S3 configuration: {}", e))?;
+
+        let prefix = match &config.prefix {
+            Some(prefix) => format!("{}/", prefix.trim_end_matches('/')),
+            None => String::new(),
+        };
+
+        Ok(Some(Self {
+            store: Arc::new(store),
+            prefix,
+        }))
+    }
+
+    /// Key for an export's file
+    ///
+    /// Exports and backups live under separate top-level prefixes, dated by
+    /// day, so bucket lifecycle rules can expire each on its own schedule.
+    pub fn export_key(&self, job: &ExportJob, extension: &str) -> String {
+        format!(
+            "{}exports/{}/{}/{}.{}",
+            self.prefix,
+            day(job.created_at),
+            job.user_id,
+            job.id,
+            extension
+        )
+    }
+
+    /// Key for a backup file, keeping its timestamped name
+    pub fn backup_key(&self, file_name: &str, at: DateTime<Utc>) -> String {
+        format!("{}backups/{}/{}", self.prefix, day(at), file_name)
+    }
+
+    /// Stream a local file to `key`, using a multipart upload for large files
+    pub async fn upload_file(&self, path: &Path, key: &str) -> Result<(), String> {
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+
+        let mut writer = BufWriter::new(self.store.clone(), ObjectPath::from(key));
+        tokio::io::copy(&mut file, &mut writer)
+            .await
+            .map_err(|e| format!("Failed to upload {}: {}", key, e))?;
+        writer
+            .shutdown()
+            .await
+            .map_err(|e| format!("Failed to upload {}: {}", key, e))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "s3")]
+fn day(at: DateTime<Utc>) -> String {
+    at.format("%Y/%m/%d").to_string()
+}
+
+/// Stands in for the bucket in builds without the `s3` feature, where
+/// uploads can never be configured
+#[cfg(not(feature = "s3"))]
+pub enum S3Destination {}
+
+#[cfg(not(feature = "s3"))]
+impl S3Destination {
+    pub fn from_config(config: &S3Config) -> Result<Option<Self>, String> {
+        if config.bucket.is_some() {
+            return Err(
+                "S3 uploads (S3_BUCKET) require building with the `s3` feature".to_string(),
+            );
+        }
+        Ok(None)
+    }
+
+    pub fn export_key(&self, _job: &ExportJob, _extension: &str) -> String {
+        match *self {}
+    }
+
+    pub fn backup_key(&self, _file_name: &str, _at: DateTime<Utc>) -> String {
+        match *self {}
+    }
+
+    pub async fn upload_file(&self, _path: &Path, _key: &str) -> Result<(), String> {
+        match *self {}
+    }
+}
+
+/// Copies exports into users' WebDAV folders, such as Nextcloud's
+#[derive(Debug, Clone)]
+pub struct WebDavClient {
+    client: reqwest::Client,
+}
+
+impl Default for WebDavClient {
+    fn default() -> Self {
+        // A redirect could lead to an address the URL check would refuse
+        let client = reqwest::Client::builder()
+            .redirect(redirect::Policy::none())
+            .build()
+            .expect("valid HTTP client configuration");
+
+        Self { client }
+    }
+}
+
+impl WebDavClient {
+    /// Check that the folder exists and the credentials are accepted
+    pub async fn check(&self, destination: &WebDavDestination) -> Result<(), String> {
+        let propfind = Method::from_bytes(b"PROPFIND").expect("valid method");
+        let response = self
+            .client
+            .request(propfind, folder_url(&destination.url))
+            .basic_auth(&destination.username, Some(&destination.password))
+            .header("Depth", "0")
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach WebDAV server: {}", e))?;
+
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                Err("WebDAV server rejected the credentials".to_string())
+            }
+            StatusCode::NOT_FOUND => Err("WebDAV folder not found".to_string()),
+            status => Err(format!("WebDAV server returned {}", status)),
+        }
+    }
+
+    /// Upload a local file into the folder as `name`, replacing any file
+    /// with that name
+    pub async fn upload_file(
+        &self,
+        destination: &WebDavDestination,
+        path: &Path,
+        name: &str,
+    ) -> Result<(), String> {
+        let file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let len = file
+            .metadata()
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+            .len();
+
+        // Sent with a length, since some servers reject chunked uploads
+        let response = self
+            .client
+            .put(format!("{}{}", folder_url(&destination.url), name))
+            .basic_auth(&destination.username, Some(&destination.password))
+            .header(header::CONTENT_LENGTH, len)
+            .body(reqwest::Body::wrap_stream(ReaderStream::new(file)))
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach WebDAV server: {}", e))?;
+
+        if !response.status().is_success() {
+            return Err(format!("WebDAV server returned {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+/// Name of an export's file in a WebDAV folder, sorting chronologically
+pub fn webdav_file_name(job: &ExportJob, extension: &str) -> String {
+    format!(
+        "dissipate-export-{}.{}",
+        job.created_at.format("%Y%m%dT%H%M%SZ"),
+        extension
+    )
+}
+
+fn folder_url(url: &str) -> String {
+    format!("{}/", url.trim_end_matches('/'))
+}
+
+#[cfg(all(test, feature = "s3"))]
+mod tests {
+    use super::*;
+    use crate::ids::UserId;
+    use object_store::memory::InMemory;
+
+    fn destination(prefix: &str) -> S3Destination {
+        S3Destination {
+            store: Arc::new(InMemory::new()),
+            prefix: prefix.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_keys_are_grouped_by_kind_and_day() {
+        let destination = destination("dissipate/");
+        let mut job = ExportJob::new(UserId::generate(), "json".to_string());
+        job.created_at = "2024-03-05T10:00:00Z".parse().unwrap();
+
+        assert_eq!(
+            destination.export_key(&job, "json"),
+            format!(
+                "dissipate/exports/2024/03/05/{}/{}.json",
+                job.user_id, job.id
+            )
+        );
+        assert_eq!(
+            destination.backup_key("dissipate-20240305T100000.000Z.db", job.created_at),
+            "dissipate/backups/2024/03/05/dissipate-20240305T100000.000Z.db"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upload_file() {
+        let destination = destination("");
+        let path = std::env::temp_dir().join(format!("dissipate-upload-{}", uuid::Uuid::new_v4()));
+        tokio::fs::write(&path, "exported").await.unwrap();
+
+        destination
+            .upload_file(&path, "exports/test.json")
+            .await
+            .unwrap();
+
+        let stored = destination
+            .store
+            .get(&ObjectPath::from("exports/test.json"))
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(&stored[..], b"exported");
+
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+}
@@ -0,0 +1,410 @@
+use std::time::Duration;
+
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::{Utc, Weekday};
+use chrono_tz::Tz;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    error::AppError,
+    export_format::{markdown_to_html, push_markdown_entries, MarkdownLayout},
+    handlers::{storage_error, AppState, SharedState},
+    mailer::Mailer,
+    models::{
+        weekday_name, DigestSubscription, DigestSubscriptionResponse, Message,
+        SetDigestSubscriptionRequest, SuccessResponse, DIGEST_FREQUENCY_DAILY,
+        DIGEST_FREQUENCY_WEEKLY,
+    },
+    AuthUser,
+};
+
+/// How often the worker looks for digests that are due
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Email digests as they fall due, until shutdown
+pub fn spawn_worker(state: SharedState, shutdown: CancellationToken) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            send_due_digests(&state).await;
+
+            tokio::select! {
+                _ = tokio::time::sleep(CHECK_INTERVAL) => {}
+                _ = shutdown.cancelled() => break,
+            }
+        }
+    })
+}
+
+/// Send every digest that is due and move each on to its next run
+async fn send_due_digests(state: &AppState) {
+    let Some(mailer) = &state.mailer else {
+        return;
+    };
+
+    let now = Utc::now();
+    let subscriptions = match state.storage.get_due_digest_subscriptions(now).await {
+        Ok(subscriptions) => subscriptions,
+        Err(e) => {
+            tracing::error!("Failed to fetch digest subscriptions: {}", e);
+            return;
+        }
+    };
+
+    for subscription in subscriptions {
+        // Runs missed while the server was down are skipped, not all sent
+        let Some(next_run_at) = subscription.run_after(now) else {
+            tracing::warn!(
+                "Digest for user {} has invalid settings",
+                subscription.user_id
+            );
+            continue;
+        };
+        // Moved on before sending, so a failing email isn't retried every
+        // minute
+        if let Err(e) = state
+            .storage
+            .set_digest_next_run(&subscription.user_id, next_run_at)
+            .await
+        {
+            tracing::error!(
+                "Failed to schedule digest for user {}: {}",
+                subscription.user_id,
+                e
+            );
+            continue;
+        }
+
+        if let Err(e) = send_digest(state, mailer, &subscription).await {
+            tracing::error!(
+                "Failed to email digest to user {}: {}",
+                subscription.user_id,
+                e
+            );
+        }
+    }
+}
+
+/// Email a user the entries they wrote in the period ending at the
+/// subscription's due run
+async fn send_digest(
+    state: &AppState,
+    mailer: &Mailer,
+    subscription: &DigestSubscription,
+) -> Result<(), String> {
+    let Some(user) = state
+        .storage
+        .find_user_by_id(&subscription.user_id)
+        .await
+        .map_err(|e| format!("Failed to fetch user: {}", e))?
+    else {
+        return Ok(());
+    };
+    if user.disabled {
+        return Ok(());
+    }
+
+    let run_at = subscription.next_run_at;
+    let messages = state
+        .storage
+        .get_messages_created_between(&user.id, subscription.period_start(run_at), run_at)
+        .await
+        .map_err(|e| format!("Failed to fetch messages: {}", e))?;
+
+    let (subject, markdown) = render_digest(subscription, &messages, mailer.public_url());
+    let html = markdown_to_html(&markdown);
+    mailer
+        .send_html(&user.email, &subject, markdown, html)
+        .await
+}
+
+/// Subject and Markdown body of a digest of `messages` (newest first), or
+/// of a nudge to write when there are none
+fn render_digest(
+    subscription: &DigestSubscription,
+    messages: &[Message],
+    public_url: Option<&str>,
+) -> (String, String) {
+    let tz: Tz = subscription.timezone.parse().unwrap_or(Tz::UTC);
+    let period = if subscription.frequency == DIGEST_FREQUENCY_WEEKLY {
+        "week"
+    } else {
+        "day"
+    };
+    let mut markdown = format!("# Your {} digest\n\n", subscription.frequency);
+
+    if messages.is_empty() {
+        markdown.push_str(&format!(
+            "You didn't write anything in the past {}. A few lines about how it went are enough to get going again.\n",
+            period
+        ));
+        if let Some(url) = public_url {
+            markdown.push_str(&format!("\n[Write an entry]({})\n", url));
+        }
+        return (format!("Nothing written in the past {}", period), markdown);
+    }
+
+    let entries = match messages.len() {
+        1 => "1 entry".to_string(),
+        count => format!("{} entries", count),
+    };
+    markdown.push_str(&format!(
+        "You wrote {} in the past {}.\n\n---\n\n",
+        entries, period
+    ));
+    let layout = MarkdownLayout {
+        oldest_first: true,
+        group_by: None,
+    };
+    push_markdown_entries(&mut markdown, messages, layout, tz);
+
+    (
+        format!("Your {} digest: {}", subscription.frequency, entries),
+        markdown,
+    )
+}
+
+/// GET /api/user/digest
+/// Get when the authenticated user's digest is emailed
+#[utoipa::path(
+    get,
+    path = "/api/user/digest",
+    tag = "user",
+    responses(
+        (status = 200, description = "When the digest is emailed", body = DigestSubscriptionResponse),
+        (status = 404, description = "No digest set up", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_subscription(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<DigestSubscriptionResponse>, AppError> {
+    let subscription = state
+        .storage
+        .get_digest_subscription(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "No digest set up"))?;
+
+    Ok(Json(subscription.to_response()))
+}
+
+/// PUT /api/user/digest
+/// Email the user a daily or weekly digest of what they wrote, at a local
+/// hour, replacing any existing setting
+#[utoipa::path(
+    put,
+    path = "/api/user/digest",
+    tag = "user",
+    request_body = SetDigestSubscriptionRequest,
+    responses(
+        (status = 200, description = "Digest saved", body = DigestSubscriptionResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 404, description = "Email is not configured", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn set_subscription(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Json(payload): Json<SetDigestSubscriptionRequest>,
+) -> Result<Json<DigestSubscriptionResponse>, AppError> {
+    if state.mailer.is_none() {
+        return Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            "Email is not configured",
+        ));
+    }
+
+    let weekday = match payload.frequency.as_str() {
+        DIGEST_FREQUENCY_DAILY => None,
+        DIGEST_FREQUENCY_WEEKLY => {
+            let weekday = match payload.weekday.as_deref() {
+                None => Weekday::Mon,
+                Some(weekday) => weekday.parse().map_err(|_| {
+                    AppError::new(
+                        StatusCode::BAD_REQUEST,
+                        format!("Unknown weekday: {}", weekday),
+                    )
+                })?,
+            };
+            Some(weekday_name(weekday).to_string())
+        }
+        _ => {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "Frequency must be daily or weekly",
+            ))
+        }
+    };
+    if !(0..24).contains(&payload.hour) {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Hour must be between 0 and 23",
+        ));
+    }
+    let timezone = match payload.timezone.as_deref() {
+        None => Tz::UTC,
+        Some(timezone) => timezone.parse().map_err(|_| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                format!("Unknown time zone: {}", timezone),
+            )
+        })?,
+    };
+
+    let subscription = DigestSubscription::new(
+        user_id,
+        payload.frequency,
+        payload.hour,
+        weekday,
+        timezone.name().to_string(),
+    )
+    .ok_or_else(|| AppError::new(StatusCode::BAD_REQUEST, "Invalid digest settings"))?;
+    state
+        .storage
+        .set_digest_subscription(&subscription)
+        .await
+        .map_err(|e| storage_error(e, "Failed to save digest"))?;
+
+    Ok(Json(subscription.to_response()))
+}
+
+/// DELETE /api/user/digest
+/// Stop emailing the user's digest
+#[utoipa::path(
+    delete,
+    path = "/api/user/digest",
+    tag = "user",
+    responses(
+        (status = 200, description = "Digest stopped", body = SuccessResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn delete_subscription(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<SuccessResponse>, AppError> {
+    state
+        .storage
+        .delete_digest_subscription(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Failed to remove digest"))?;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids::UserId;
+    #[cfg(feature = "smtp")]
+    use crate::{
+        config::SmtpConfig,
+        testing::{auth_user, test_app_state},
+    };
+    #[cfg(feature = "smtp")]
+    use std::sync::Arc;
+
+    #[cfg(feature = "smtp")]
+    async fn setup_test_state(mailer: Option<Mailer>) -> SharedState {
+        Arc::new(AppState {
+            mailer: mailer.map(Arc::new),
+            ..test_app_state().await
+        })
+    }
+
+    #[cfg(feature = "smtp")]
+    fn digest_request(frequency: &str, hour: i64) -> Json<SetDigestSubscriptionRequest> {
+        Json(SetDigestSubscriptionRequest {
+            frequency: frequency.to_string(),
+            hour,
+            weekday: None,
+            timezone: Some("Europe/Berlin".to_string()),
+        })
+    }
+
+    #[cfg(feature = "smtp")]
+    #[tokio::test]
+    async fn test_digest_subscription_lifecycle() {
+        let result = set_subscription(
+            State(setup_test_state(None).await),
+            auth_user(&UserId::generate()),
+            digest_request("daily", 8),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::NOT_FOUND);
+
+        let smtp = SmtpConfig {
+            url: Some("smtp://localhost:2525".to_string()),
+            from: Some("journal@example.com".to_string()),
+        };
+        let mailer = Mailer::from_config(&smtp, None).unwrap();
+        let state = setup_test_state(mailer).await;
+        let user = crate::models::User::new(
+            "digest@example.com".to_string(),
+            "digest".to_string(),
+            "hash".to_string(),
+            "salt".to_string(),
+        );
+        state.storage.create_user(&user).await.unwrap();
+
+        for request in [digest_request("monthly", 8), digest_request("daily", 24)] {
+            let result = set_subscription(State(state.clone()), auth_user(&user.id), request).await;
+            assert_eq!(result.unwrap_err().status(), StatusCode::BAD_REQUEST);
+        }
+        let mut request = digest_request("weekly", 8);
+        request.timezone = Some("Mars/Olympus_Mons".to_string());
+        let result = set_subscription(State(state.clone()), auth_user(&user.id), request).await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::BAD_REQUEST);
+
+        let mut request = digest_request("weekly", 18);
+        request.weekday = Some("Fri".to_string());
+        let Json(saved) = set_subscription(State(state.clone()), auth_user(&user.id), request)
+            .await
+            .unwrap();
+        assert_eq!(saved.weekday.as_deref(), Some("friday"));
+        let next_run_at = chrono::DateTime::parse_from_rfc3339(&saved.next_run_at).unwrap();
+        assert!(next_run_at > Utc::now());
+
+        let Json(fetched) = get_subscription(State(state.clone()), auth_user(&user.id))
+            .await
+            .unwrap();
+        assert_eq!(fetched.frequency, "weekly");
+        assert_eq!(fetched.hour, 18);
+        assert_eq!(fetched.timezone, "Europe/Berlin");
+
+        let _ = delete_subscription(State(state.clone()), auth_user(&user.id))
+            .await
+            .unwrap();
+        let result = get_subscription(State(state), auth_user(&user.id)).await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_render_digest_lists_entries_or_nudges() {
+        let subscription = DigestSubscription::new(
+            UserId::generate(),
+            DIGEST_FREQUENCY_WEEKLY.to_string(),
+            8,
+            None,
+            "America/New_York".to_string(),
+        )
+        .unwrap();
+
+        let (subject, markdown) =
+            render_digest(&subscription, &[], Some("https://journal.example.com"));
+        assert_eq!(subject, "Nothing written in the past week");
+        assert!(markdown.contains("[Write an entry](https://journal.example.com)"));
+
+        let mut older = Message::new(UserId::generate(), "Went hiking".to_string());
+        older.created_at = "2026-10-12T01:30:00Z".parse().unwrap();
+        let newer = Message::new(UserId::generate(), "Rained all day".to_string());
+        let (subject, markdown) = render_digest(&subscription, &[newer, older], None);
+        assert_eq!(subject, "Your weekly digest: 2 entries");
+        // Dated in the subscriber's time zone, oldest first
+        let hiking = markdown.find("October 11, 2026 at 09:30 PM").unwrap();
+        assert!(hiking < markdown.find("Rained all day").unwrap());
+    }
+}
@@ -0,0 +1,523 @@
+use std::time::Duration;
+
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::{DateTime, Utc};
+use moka::future::Cache;
+use serde_json::json;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    audit::{self, ACTOR_SYSTEM},
+    db::DbError,
+    error::AppError,
+    exports,
+    handlers::{storage_error, AppState, SharedState},
+    ids::UserId,
+    mailer::{EmailAttachment, Mailer},
+    models::{
+        AuditEvent, DormancyPolicy, DormancyPolicyResponse, SetDormancyPolicyRequest,
+        SuccessResponse, User, DORMANCY_MAX_DAYS, DORMANCY_MIN_DAYS,
+    },
+    storage::Storage,
+    AuthUser,
+};
+
+/// How often the worker looks for dormant accounts
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How often one user's activity is written down at most
+const ACTIVITY_RESOLUTION: Duration = Duration::from_secs(60 * 60);
+
+const ACTIVITY_CAPACITY: u64 = 100_000;
+
+/// Writes down when users were last active, for their dormancy policies
+///
+/// Only the first request in each hour is written, so the database isn't
+/// written to on every request.
+#[derive(Clone)]
+pub struct ActivityTracker {
+    recent: Cache<UserId, ()>,
+}
+
+impl Default for ActivityTracker {
+    fn default() -> Self {
+        Self {
+            recent: Cache::builder()
+                .time_to_live(ACTIVITY_RESOLUTION)
+                .max_capacity(ACTIVITY_CAPACITY)
+                .build(),
+        }
+    }
+}
+
+impl ActivityTracker {
+    /// Note that the user is active now; failures are only logged
+    pub async fn record(&self, storage: &dyn Storage, user_id: &UserId) {
+        if self.recent.contains_key(user_id) {
+            return;
+        }
+        self.recent.insert(user_id.clone(), ()).await;
+
+        if let Err(e) = storage.record_user_activity(user_id, Utc::now()).await {
+            tracing::warn!("Failed to record activity of user {}: {}", user_id, e);
+        }
+    }
+}
+
+/// Carry out dormancy policies as accounts fall dormant, until shutdown
+pub fn spawn_worker(state: SharedState, shutdown: CancellationToken) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            check_policies(&state).await;
+
+            tokio::select! {
+                _ = tokio::time::sleep(CHECK_INTERVAL) => {}
+                _ = shutdown.cancelled() => break,
+            }
+        }
+    })
+}
+
+/// Warn the users whose accounts are falling dormant, and carry out the
+/// policies of those that have
+async fn check_policies(state: &AppState) {
+    // Without email nobody could be warned, so nothing is carried out
+    let Some(mailer) = &state.mailer else {
+        return;
+    };
+
+    let policies = match state.storage.list_pending_dormancy_policies().await {
+        Ok(policies) => policies,
+        Err(e) => {
+            tracing::error!("Failed to fetch dormancy policies: {}", e);
+            return;
+        }
+    };
+
+    let now = Utc::now();
+    for policy in policies {
+        if let Err(e) = check_policy(state, mailer, &policy, now).await {
+            tracing::error!(
+                "Failed to carry out dormancy policy of user {}: {}",
+                policy.user_id,
+                e
+            );
+        }
+    }
+}
+
+async fn check_policy(
+    state: &AppState,
+    mailer: &Mailer,
+    policy: &DormancyPolicy,
+    now: DateTime<Utc>,
+) -> Result<(), String> {
+    let Some(user) = state
+        .storage
+        .find_user_by_id(&policy.user_id)
+        .await
+        .map_err(|e| format!("Failed to fetch user: {}", e))?
+    else {
+        return Ok(());
+    };
+    // Frozen accounts are left for the admins to deal with
+    if user.disabled {
+        return Ok(());
+    }
+
+    if let Some(warnings_sent) = policy.warnings_due(now) {
+        // Only counted once sent, so a failing email is retried on the next
+        // check and the account is never carried out without every warning
+        let (subject, body) = render_warning(policy, &user, now, mailer.public_url());
+        mailer.send(&user.email, &subject, body, None).await?;
+        return state
+            .storage
+            .record_dormancy_warning(&user.id, warnings_sent, now)
+            .await
+            .map_err(|e| format!("Failed to record warning: {}", e));
+    }
+    if !policy.is_due(now) {
+        return Ok(());
+    }
+
+    // Not marked carried out until the export is sent, so a failure is
+    // retried and the account is never deleted without it
+    if let Some(address) = &policy.export_email {
+        let archive = exports::account_archive(state.storage.as_ref(), &user.id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let (subject, body) = render_export(policy, &user);
+        let attachment = EmailAttachment {
+            filename: "account.zip".to_string(),
+            content_type: "application/zip".to_string(),
+            body: archive,
+        };
+        mailer
+            .send(address, &subject, body, Some(attachment))
+            .await?;
+    }
+
+    let marked = state
+        .storage
+        .mark_dormancy_triggered(&user.id, policy.last_active_at, now)
+        .await
+        .map_err(|e| format!("Failed to mark policy carried out: {}", e))?;
+    // The user came back in the meantime
+    if !marked {
+        return Ok(());
+    }
+    tracing::info!("Carried out dormancy policy of user {}", user.id);
+    if !policy.purge {
+        return Ok(());
+    }
+
+    state
+        .storage
+        .delete_user(&user.id)
+        .await
+        .map_err(|e| format!("Failed to delete user: {}", e))?;
+    state.users.invalidate(&user.id).await;
+    audit::record(
+        &*state.storage,
+        AuditEvent::new("dormancy.user.delete", ACTOR_SYSTEM)
+            .with_target("user", user.id.as_str())
+            .with_metadata(json!({
+                "email": user.email,
+                "inactive_days": policy.inactive_days,
+            })),
+    )
+    .await;
+
+    let body = format!(
+        "Your Dissipate account ({}) has been deleted after {} days without activity, as you asked in its dormancy settings.\n",
+        user.email, policy.inactive_days
+    );
+    mailer
+        .send(&user.email, "Your account has been deleted", body, None)
+        .await
+}
+
+/// What a policy does, as a clause following "your account will be" and
+/// the like
+fn describe_action(policy: &DormancyPolicy) -> String {
+    match (&policy.export_email, policy.purge) {
+        (Some(address), true) => format!("exported to {} and then deleted", address),
+        (Some(address), false) => format!("exported to {}", address),
+        (None, _) => "deleted".to_string(),
+    }
+}
+
+/// Subject and body of the email warning a user whose account is falling
+/// dormant
+fn render_warning(
+    policy: &DormancyPolicy,
+    user: &User,
+    now: DateTime<Utc>,
+    public_url: Option<&str>,
+) -> (String, String) {
+    // Carried out no sooner than a day after the last warning
+    let carried_out_at = policy.deadline().max(now + chrono::Duration::days(1));
+    let date = carried_out_at.format("%B %-d, %Y at %H:%M UTC");
+
+    let mut body = format!(
+        "You haven't used your Dissipate account ({}) since {}. As you asked in its dormancy settings, after {} days without activity your account will be {}.\n\n\
+         Unless you log in before {}, this happens then. Logging in, or using any app signed in to your account, cancels it.\n",
+        user.email,
+        policy.last_active_at.format("%B %-d, %Y"),
+        policy.inactive_days,
+        describe_action(policy),
+        date
+    );
+    if let Some(url) = public_url {
+        body.push_str(&format!("\n{}\n", url));
+    }
+
+    (
+        format!(
+            "Your account will be {} on {}",
+            describe_action(policy),
+            date
+        ),
+        body,
+    )
+}
+
+/// Subject and body of the email carrying a dormant account's export
+fn render_export(policy: &DormancyPolicy, user: &User) -> (String, String) {
+    let mut body = format!(
+        "{} ({}) asked Dissipate to send you an export of their journal once they had been inactive for {} days, and they haven't used their account since {}.\n\n\
+         The attached ZIP holds their entries and account data as JSON files.\n",
+        user.shown_name(),
+        user.email,
+        policy.inactive_days,
+        policy.last_active_at.format("%B %-d, %Y")
+    );
+    if policy.purge {
+        body.push_str("\nTheir account is deleted after this export.\n");
+    }
+
+    (format!("Journal export of {}", user.shown_name()), body)
+}
+
+// ============ Handlers ============
+
+/// GET /api/user/dormancy
+/// Get what happens to the authenticated user's account once it falls
+/// dormant
+#[utoipa::path(
+    get,
+    path = "/api/user/dormancy",
+    tag = "user",
+    responses(
+        (status = 200, description = "What happens once the account falls dormant", body = DormancyPolicyResponse),
+        (status = 404, description = "No dormancy policy set up", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_policy(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<DormancyPolicyResponse>, AppError> {
+    let policy = state
+        .storage
+        .get_dormancy_policy(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "No dormancy policy set up"))?;
+
+    Ok(Json(policy.to_response()))
+}
+
+/// PUT /api/user/dormancy
+/// Export and/or delete the account once the user has been inactive for a
+/// number of days, replacing any existing policy
+#[utoipa::path(
+    put,
+    path = "/api/user/dormancy",
+    tag = "user",
+    request_body = SetDormancyPolicyRequest,
+    responses(
+        (status = 200, description = "Dormancy policy saved", body = DormancyPolicyResponse),
+        (status = 400, description = "Invalid settings", body = ErrorResponse),
+        (status = 404, description = "Email is not configured", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn set_policy(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Json(payload): Json<SetDormancyPolicyRequest>,
+) -> Result<Json<DormancyPolicyResponse>, AppError> {
+    // Warnings are sent by email, so nothing happens without them
+    if state.mailer.is_none() {
+        return Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            "Email is not configured",
+        ));
+    }
+    let bad_request = |message: String| AppError::new(StatusCode::BAD_REQUEST, message);
+
+    if !(DORMANCY_MIN_DAYS..=DORMANCY_MAX_DAYS).contains(&payload.inactive_days) {
+        return Err(bad_request(format!(
+            "inactive_days must be between {} and {}",
+            DORMANCY_MIN_DAYS, DORMANCY_MAX_DAYS
+        )));
+    }
+    let export_email = payload
+        .export_email
+        .as_deref()
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .map(|address| {
+            address
+                .parse::<lettre::Address>()
+                .map(|_| address.to_string())
+                .map_err(|_| bad_request(format!("Invalid email address: {}", address)))
+        })
+        .transpose()?;
+    if export_email.is_none() && !payload.purge {
+        return Err(bad_request(
+            "Choose an export address, purging, or both".to_string(),
+        ));
+    }
+
+    let policy = DormancyPolicy::new(user_id, payload.inactive_days, export_email, payload.purge);
+    state
+        .storage
+        .set_dormancy_policy(&policy)
+        .await
+        .map_err(|e| storage_error(e, "Failed to save dormancy policy"))?;
+
+    Ok(Json(policy.to_response()))
+}
+
+/// DELETE /api/user/dormancy
+/// Keep the account however long the user is away
+#[utoipa::path(
+    delete,
+    path = "/api/user/dormancy",
+    tag = "user",
+    responses(
+        (status = 200, description = "Dormancy policy removed", body = SuccessResponse),
+        (status = 404, description = "No dormancy policy set up", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn delete_policy(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<SuccessResponse>, AppError> {
+    state
+        .storage
+        .delete_dormancy_policy(&user_id)
+        .await
+        .map_err(|e| match e {
+            DbError::DormancyPolicyNotFound => {
+                AppError::new(StatusCode::NOT_FOUND, "No dormancy policy set up")
+            }
+            _ => storage_error(e, "Failed to remove dormancy policy"),
+        })?;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "smtp")]
+    use crate::{
+        config::SmtpConfig,
+        testing::{auth_user, test_app_state},
+    };
+    #[cfg(feature = "smtp")]
+    use std::sync::Arc;
+
+    #[cfg(feature = "smtp")]
+    async fn setup_test_state(mailer: Option<Mailer>) -> SharedState {
+        Arc::new(AppState {
+            mailer: mailer.map(Arc::new),
+            ..test_app_state().await
+        })
+    }
+
+    #[cfg(feature = "smtp")]
+    fn policy_request(inactive_days: i64, purge: bool) -> Json<SetDormancyPolicyRequest> {
+        Json(SetDormancyPolicyRequest {
+            inactive_days,
+            export_email: None,
+            purge,
+        })
+    }
+
+    #[cfg(feature = "smtp")]
+    #[tokio::test]
+    async fn test_dormant_accounts_are_purged_after_warnings() {
+        let result = set_policy(
+            State(setup_test_state(None).await),
+            auth_user(&UserId::generate()),
+            policy_request(90, true),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::NOT_FOUND);
+
+        // Nothing listens here, so every email fails
+        let smtp = SmtpConfig {
+            url: Some("smtp://localhost:2525".to_string()),
+            from: Some("journal@example.com".to_string()),
+        };
+        let mailer = Mailer::from_config(&smtp, None).unwrap();
+        let state = setup_test_state(mailer).await;
+        let user = User::new(
+            "dormant@example.com".to_string(),
+            "dormant".to_string(),
+            "hash".to_string(),
+            "salt".to_string(),
+        );
+        state.storage.create_user(&user).await.unwrap();
+
+        for request in [policy_request(7, true), policy_request(90, false)] {
+            let result = set_policy(State(state.clone()), auth_user(&user.id), request).await;
+            assert_eq!(result.unwrap_err().status(), StatusCode::BAD_REQUEST);
+        }
+        let Json(saved) = set_policy(
+            State(state.clone()),
+            auth_user(&user.id),
+            policy_request(90, true),
+        )
+        .await
+        .unwrap();
+        assert_eq!(saved.warnings_sent, 0);
+
+        // Back-dated past the deadline, but no warning is delivered, so
+        // none is counted and the account is kept however often it's checked
+        let mut policy = state
+            .storage
+            .get_dormancy_policy(&user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        policy.last_active_at = Utc::now() - chrono::Duration::days(91);
+        state.storage.set_dormancy_policy(&policy).await.unwrap();
+        check_policies(&state).await;
+        check_policies(&state).await;
+        let mut policy = state
+            .storage
+            .get_dormancy_policy(&user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(policy.warnings_sent, 0);
+        assert!(!policy.is_due(Utc::now()));
+        assert!(state
+            .storage
+            .find_user_by_id(&user.id)
+            .await
+            .unwrap()
+            .is_some());
+
+        // Activity starts the count again
+        policy.warnings_sent = 2;
+        state.storage.set_dormancy_policy(&policy).await.unwrap();
+        state
+            .activity
+            .record(state.storage.as_ref(), &user.id)
+            .await;
+        let Json(restarted) = get_policy(State(state.clone()), auth_user(&user.id))
+            .await
+            .unwrap();
+        assert_eq!(restarted.warnings_sent, 0);
+
+        // Once every warning went out, the account is purged
+        policy.last_active_at = Utc::now() - chrono::Duration::days(91);
+        policy.warnings_sent = 3;
+        policy.last_warning_at = Some(Utc::now() - chrono::Duration::days(2));
+        state.storage.set_dormancy_policy(&policy).await.unwrap();
+        check_policies(&state).await;
+        assert!(state
+            .storage
+            .find_user_by_id(&user.id)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_warnings_lead_up_to_deadline() {
+        let mut policy = DormancyPolicy::new(UserId::generate(), 30, None, true);
+        let deadline = policy.deadline();
+        let days = chrono::Duration::days;
+
+        assert_eq!(policy.warnings_due(deadline - days(20)), None);
+        assert_eq!(policy.warnings_due(deadline - days(14)), Some(1));
+        assert_eq!(policy.warnings_due(deadline - days(3)), Some(2));
+        policy.warnings_sent = 2;
+        assert_eq!(policy.warnings_due(deadline - days(3)), None);
+        assert!(!policy.is_due(deadline));
+
+        policy.warnings_sent = 3;
+        policy.last_warning_at = Some(deadline - days(1));
+        assert!(!policy.is_due(deadline - chrono::Duration::hours(1)));
+        assert!(policy.is_due(deadline));
+    }
+}
@@ -0,0 +1,240 @@
+//! The error type returned by handlers
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::{
+    auth::AuthError, db::DbError, handlers::ErrorResponse, models::UsageResponse,
+    utils::PasswordError, validation::ValidationErrors,
+};
+
+/// An error from a handler, sent to the client as an [`ErrorResponse`]
+///
+/// Failures with an underlying cause keep it, so it can be logged when the
+/// response is built. Clients only see a summary of what went wrong.
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    /// A request refused with a message meant for the client
+    #[error("{message}")]
+    Rejected {
+        status: StatusCode,
+        message: String,
+        /// Stable identifier for errors clients handle specially, such as
+        /// `account_disabled`
+        code: Option<&'static str>,
+        /// Current usage, when a write is rejected for exceeding a limit,
+        /// boxed to keep `Result<_, AppError>` small
+        usage: Option<Box<UsageResponse>>,
+    },
+    /// Storage failed while doing what `context` describes
+    #[error("{context}: {source}")]
+    Storage { source: DbError, context: String },
+    /// A request body that broke the rules for its fields
+    #[error(transparent)]
+    Invalid(#[from] ValidationErrors),
+    #[error(transparent)]
+    Auth(#[from] AuthError),
+    #[error(transparent)]
+    Password(#[from] PasswordError),
+}
+
+impl AppError {
+    /// Refuse a request with `message`
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        AppError::Rejected {
+            status,
+            message: message.into(),
+            code: None,
+            usage: None,
+        }
+    }
+
+    /// A storage error, reported to the client as `context` unless it says
+    /// something about the request, such as a missing row
+    pub fn storage(source: DbError, context: impl Into<String>) -> Self {
+        AppError::Storage {
+            source,
+            context: context.into(),
+        }
+    }
+
+    /// Status of the response this error becomes
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::Rejected { status, .. } => *status,
+            AppError::Invalid(_) => StatusCode::BAD_REQUEST,
+            AppError::Storage { source, .. } => {
+                db_error_status(source).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+            AppError::Auth(
+                AuthError::TokenExpired
+                | AuthError::InvalidToken(_)
+                | AuthError::MissingAuthHeader
+                | AuthError::InvalidAuthHeader,
+            ) => StatusCode::UNAUTHORIZED,
+            AppError::Auth(_) | AppError::Password(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The status and body sent to the client, logging the cause of server
+    /// errors
+    pub fn into_parts(self) -> (StatusCode, ErrorResponse) {
+        let status = self.status();
+        if status == StatusCode::INTERNAL_SERVER_ERROR {
+            tracing::error!("{}", self);
+        }
+
+        let fields = match &self {
+            AppError::Invalid(errors) => Some(errors.0.clone()),
+            _ => None,
+        };
+        let (message, code, usage) = match self {
+            AppError::Rejected {
+                message,
+                code,
+                usage,
+                ..
+            } => (message, code, usage.map(|usage| *usage)),
+            AppError::Invalid(errors) => (errors.to_string(), Some("validation_failed"), None),
+            AppError::Storage { source, .. } if source.is_busy() => {
+                ("Database is busy, please retry".to_string(), None, None)
+            }
+            AppError::Storage { source, context } => match db_error_status(&source) {
+                Some(_) => (source.to_string(), None, None),
+                None => (context, None, None),
+            },
+            AppError::Auth(e) => {
+                let message = match e {
+                    AuthError::TokenExpired => "Token expired",
+                    AuthError::InvalidToken(_) => "Invalid token",
+                    AuthError::MissingAuthHeader => "Missing authorization",
+                    AuthError::InvalidAuthHeader => "Invalid authorization header",
+                    AuthError::TokenCreationError(_) => "Failed to create token",
+                    AuthError::CredentialCheckError(_) => "Password verification error",
+                };
+                (message.to_string(), None, None)
+            }
+            AppError::Password(e) => {
+                let message = match e {
+                    PasswordError::HashError(_) => "Failed to hash password",
+                    PasswordError::VerifyError(_) => "Password verification error",
+                };
+                (message.to_string(), None, None)
+            }
+        };
+
+        (
+            status,
+            ErrorResponse {
+                error: message,
+                usage,
+                code: code.map(str::to_string),
+                fields,
+            },
+        )
+    }
+}
+
+/// Status for storage errors about the request rather than the server
+///
+/// A database that stayed locked through every retry is reported as 503 so
+/// clients back off and try again; `middleware::retry_after` adds the
+/// `Retry-After` header.
+fn db_error_status(e: &DbError) -> Option<StatusCode> {
+    if e.is_busy() {
+        return Some(StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    match e {
+        DbError::UserNotFound
+        | DbError::MessageNotFound
+        | DbError::TemplateNotFound
+        | DbError::MemberNotFound
+        | DbError::ExportScheduleNotFound
+        | DbError::WebhookNotFound
+        | DbError::NotificationChannelNotFound
+        | DbError::TelegramNotLinked
+        | DbError::InboundEmailAddressNotFound
+        | DbError::MatrixAccountNotFound
+        | DbError::DormancyPolicyNotFound
+        | DbError::AnnouncementNotFound => Some(StatusCode::NOT_FOUND),
+        DbError::EmailAlreadyExists | DbError::MessageIdConflict => Some(StatusCode::CONFLICT),
+        DbError::JournalAccessDenied => Some(StatusCode::FORBIDDEN),
+        _ => None,
+    }
+}
+
+impl From<DbError> for AppError {
+    fn from(source: DbError) -> Self {
+        AppError::storage(source, "Database error")
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let (status, body) = self.into_parts();
+        (status, Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::Rule;
+
+    #[test]
+    fn test_storage_error_hides_cause() {
+        let error = AppError::storage(DbError::Unsupported("Backing up"), "Failed to back up");
+        let (status, body) = error.into_parts();
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body.error, "Failed to back up");
+    }
+
+    #[test]
+    fn test_storage_error_about_request_keeps_status() {
+        let (status, body) = AppError::from(DbError::MessageNotFound).into_parts();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body.error, "Message not found");
+
+        let error = AppError::storage(DbError::DatabaseBusy, "Failed to create message");
+        assert_eq!(error.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn test_auth_errors() {
+        let (status, body) = AppError::from(AuthError::TokenExpired).into_parts();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body.error, "Token expired");
+
+        let error = AppError::from(PasswordError::HashError("out of memory".to_string()));
+        let (status, body) = error.into_parts();
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body.error, "Failed to hash password");
+    }
+
+    #[test]
+    fn test_rejection_keeps_code() {
+        let error = AppError::Rejected {
+            status: StatusCode::FORBIDDEN,
+            message: "This account has been disabled".to_string(),
+            code: Some("account_disabled"),
+            usage: None,
+        };
+        let (status, body) = error.into_parts();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body.code.as_deref(), Some("account_disabled"));
+    }
+    #[test]
+    fn test_validation_errors_list_fields() {
+        let mut errors = ValidationErrors::default();
+        errors.check("username", "Username", "", &[Rule::NotBlank]);
+        let (status, body) = AppError::from(errors).into_parts();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.error, "Username cannot be empty");
+        assert_eq!(body.code.as_deref(), Some("validation_failed"));
+        assert_eq!(body.fields.unwrap()[0].field, "username");
+    }
+}
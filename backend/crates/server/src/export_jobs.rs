@@ -0,0 +1,1416 @@
+use std::{path::PathBuf, time::Duration};
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::Response,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use tokio::{sync::Notify, task::JoinHandle};
+use tokio_util::{io::ReaderStream, sync::CancellationToken};
+
+use crate::{
+    audit::{self, ACTOR_SYSTEM},
+    config::ExportsConfig,
+    db::DbError,
+    destinations::{webdav_file_name, WebDavClient},
+    error::AppError,
+    export_format::{EncryptTo, ExportFormat},
+    handlers::{storage_error, AppState, SharedState},
+    ids::UserId,
+    mailer::EmailAttachment,
+    models::{
+        next_export_run, AuditEvent, CreateExportRequest, CreateExportScheduleRequest, ExportJob,
+        ExportJobResponse, ExportProgressResponse, ExportSchedule, ExportScheduleResponse,
+        ExportSchedulesResponse, Message, SetWebDavDestinationRequest, SuccessResponse,
+        WebDavDestination, WebDavDestinationResponse, EXPORT_DELIVERY_ATTACHMENT,
+        EXPORT_DELIVERY_LINK, EXPORT_STATUS_COMPLETED,
+    },
+    notifiers::{self, Notification},
+    validation::Validate,
+    AuthUser,
+};
+
+const DEFAULT_EXPORT_DIR: &str = "exports";
+
+/// Finished exports are deleted, with their files, after this long
+const EXPORT_RETENTION_DAYS: i64 = 7;
+
+/// How often an idle worker wakes to prune expired exports and queue
+/// scheduled ones
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Largest scheduled export emailed as an attachment; bigger ones are sent
+/// as a link when `PUBLIC_URL` is set
+const ATTACHMENT_LIMIT_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Messages read per query while exporting; progress is recorded after each
+const EXPORT_PAGE_SIZE: i64 = 500;
+
+/// Where export files are written, the signal that wakes the worker, and the
+/// client copying them to WebDAV
+#[derive(Debug)]
+pub struct ExportJobs {
+    dir: PathBuf,
+    wake: Notify,
+    webdav: WebDavClient,
+}
+
+impl ExportJobs {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            wake: Notify::new(),
+            webdav: WebDavClient::default(),
+        }
+    }
+
+    /// `dir` defaults to `exports` in the working directory
+    pub fn from_config(config: &ExportsConfig) -> Self {
+        let dir = config.dir.as_deref().unwrap_or(DEFAULT_EXPORT_DIR);
+        Self::new(PathBuf::from(dir))
+    }
+
+    fn file_path(&self, job: &ExportJob, format: ExportFormat) -> PathBuf {
+        self.dir
+            .join(format!("{}.{}", job.id, extension(job, format)))
+    }
+}
+
+/// Extension of a job's file: `zip` when split by month, with `.age` added
+/// when it is encrypted
+fn extension(job: &ExportJob, format: ExportFormat) -> String {
+    let extension = if job.split_by_month {
+        "zip"
+    } else {
+        format.extension()
+    };
+    if job.encrypted {
+        format!("{}.age", extension)
+    } else {
+        extension.to_string()
+    }
+}
+
+fn content_type(job: &ExportJob, format: ExportFormat) -> &'static str {
+    if job.encrypted {
+        "application/octet-stream"
+    } else if job.split_by_month {
+        "application/zip"
+    } else {
+        format.content_type()
+    }
+}
+
+impl Default for ExportJobs {
+    fn default() -> Self {
+        Self::new(PathBuf::from(DEFAULT_EXPORT_DIR))
+    }
+}
+
+/// Process queued export jobs in the background, one at a time
+pub fn spawn_worker(state: SharedState, shutdown: CancellationToken) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        // Jobs interrupted by a restart are started over
+        if let Err(e) = state.storage.requeue_running_export_jobs().await {
+            tracing::error!("Failed to requeue export jobs: {}", e);
+        }
+
+        loop {
+            prune_expired(&state).await;
+            queue_due_schedules(&state).await;
+
+            // A job already running is finished; the rest wait for a restart
+            while !shutdown.is_cancelled() {
+                match state.storage.claim_next_export_job().await {
+                    Ok(Some(job)) => run_job(&state, &job).await,
+                    Ok(None) => break,
+                    Err(e) => {
+                        tracing::error!("Failed to claim export job: {}", e);
+                        break;
+                    }
+                }
+            }
+
+            tokio::select! {
+                _ = state.export_jobs.wake.notified() => {}
+                _ = tokio::time::sleep(PRUNE_INTERVAL) => {}
+                _ = shutdown.cancelled() => break,
+            }
+        }
+    })
+}
+
+/// Write one job's file and record the outcome
+pub async fn run_job(state: &AppState, job: &ExportJob) {
+    let error = write_export(state, job).await.err();
+    if let Some(error) = &error {
+        tracing::error!("Export {} failed: {}", job.id, error);
+    }
+
+    if let Err(e) = state
+        .storage
+        .finish_export_job(&job.id, error.as_deref())
+        .await
+    {
+        tracing::error!("Failed to record export {} outcome: {}", job.id, e);
+    }
+
+    if error.is_some() {
+        return;
+    }
+    if let Err(e) = upload(state, job).await {
+        tracing::error!("Failed to upload export {}: {}", job.id, e);
+    }
+    if let Err(e) = upload_to_webdav(state, job).await {
+        tracing::error!("Failed to copy export {} to WebDAV: {}", job.id, e);
+    }
+    if job.schedule_id.is_some() {
+        if let Err(e) = deliver(state, job).await {
+            tracing::error!("Failed to email export {}: {}", job.id, e);
+        }
+    }
+    notifiers::notify_user(
+        state,
+        &job.user_id,
+        Notification {
+            title: "Your export is ready".to_string(),
+            body: format!(
+                "Your {} export can be downloaded for {} days.",
+                job.format, EXPORT_RETENTION_DAYS
+            ),
+            url: None,
+        },
+    );
+}
+
+async fn write_export(state: &AppState, job: &ExportJob) -> Result<(), String> {
+    let format: ExportFormat = job.format.parse()?;
+    let messages = fetch_messages(state, job).await?;
+    let mut contents = if job.split_by_month {
+        tokio::task::spawn_blocking(move || format.render_by_month(&messages))
+            .await
+            .map_err(|e| format!("Archive task failed: {}", e))??
+    } else {
+        format.render(&messages)?.into_bytes()
+    };
+    if let Some(encrypt_to) = &job.encrypt_to {
+        let encrypt_to: EncryptTo = encrypt_to.parse()?;
+        contents = tokio::task::spawn_blocking(move || encrypt_to.encrypt(&contents))
+            .await
+            .map_err(|e| format!("Encryption task failed: {}", e))??;
+    }
+
+    let dir = &state.export_jobs.dir;
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    // Written under a temporary name so a crash never leaves a partial file
+    let path = state.export_jobs.file_path(job, format);
+    let partial = path.with_extension("partial");
+    tokio::fs::write(&partial, contents)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+    tokio::fs::rename(&partial, &path)
+        .await
+        .map_err(|e| format!("Failed to rename {}: {}", partial.display(), e))?;
+
+    Ok(())
+}
+
+/// Read a job's messages, newest first, a page at a time, recording progress
+/// after each page
+async fn fetch_messages(state: &AppState, job: &ExportJob) -> Result<Vec<Message>, String> {
+    let fetch_error = |e: DbError| format!("Failed to fetch messages: {}", e);
+    let progress_error = |e: DbError| format!("Failed to record progress: {}", e);
+
+    let total = state
+        .storage
+        .count_messages_for_user(&job.user_id)
+        .await
+        .map_err(fetch_error)?;
+    state
+        .storage
+        .set_export_job_progress(&job.id, 0, total)
+        .await
+        .map_err(progress_error)?;
+
+    let mut messages: Vec<Message> = Vec::new();
+    loop {
+        let after = messages
+            .last()
+            .map(|message| (message.created_at, &message.id));
+        let page = state
+            .storage
+            .get_messages_page(&job.user_id, after, EXPORT_PAGE_SIZE)
+            .await
+            .map_err(fetch_error)?;
+        let last_page = (page.len() as i64) < EXPORT_PAGE_SIZE;
+        messages.extend(page);
+
+        // Entries published since counting can take the total up
+        let processed = messages.len() as i64;
+        state
+            .storage
+            .set_export_job_progress(&job.id, processed, total.max(processed))
+            .await
+            .map_err(progress_error)?;
+        if last_page {
+            return Ok(messages);
+        }
+    }
+}
+
+/// Copy a finished job's file to the S3 bucket, if one is configured
+async fn upload(state: &AppState, job: &ExportJob) -> Result<(), String> {
+    let Some(s3) = &state.s3 else {
+        return Ok(());
+    };
+
+    let format: ExportFormat = job.format.parse()?;
+    let key = s3.export_key(job, &extension(job, format));
+    s3.upload_file(&state.export_jobs.file_path(job, format), &key)
+        .await
+}
+
+/// Copy a finished job's file to the user's WebDAV folder, if they set one
+async fn upload_to_webdav(state: &AppState, job: &ExportJob) -> Result<(), String> {
+    let Some(destination) = state
+        .storage
+        .get_webdav_destination(&job.user_id)
+        .await
+        .map_err(|e| format!("Failed to fetch WebDAV destination: {}", e))?
+    else {
+        return Ok(());
+    };
+
+    // Checked again, since the host may resolve somewhere else by now
+    state.outbound.check(&destination.url).await?;
+
+    let format: ExportFormat = job.format.parse()?;
+    state
+        .export_jobs
+        .webdav
+        .upload_file(
+            &destination,
+            &state.export_jobs.file_path(job, format),
+            &webdav_file_name(job, &extension(job, format)),
+        )
+        .await
+}
+
+/// Email a scheduled job's file to the address on its schedule
+async fn deliver(state: &AppState, job: &ExportJob) -> Result<(), String> {
+    let (Some(mailer), Some(schedule_id)) = (&state.mailer, &job.schedule_id) else {
+        return Ok(());
+    };
+    let Some(schedule) = state
+        .storage
+        .get_export_schedule(schedule_id, &job.user_id)
+        .await
+        .map_err(|e| format!("Failed to fetch schedule: {}", e))?
+    else {
+        // Deleted since the job was queued
+        return Ok(());
+    };
+
+    let format: ExportFormat = job.format.parse()?;
+    let path = state.export_jobs.file_path(job, format);
+    let size = tokio::fs::metadata(&path)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+        .len();
+    let link = match (mailer.public_url(), &job.download_token) {
+        (Some(base), Some(token)) => Some(format!(
+            "{}/api/exports/{}/download/{}",
+            base, job.id, token
+        )),
+        _ => None,
+    };
+
+    let subject = format!("Your {} journal export", schedule.frequency);
+    let attach = schedule.delivery == EXPORT_DELIVERY_ATTACHMENT
+        && (size <= ATTACHMENT_LIMIT_BYTES || link.is_none());
+    if attach {
+        let body = tokio::fs::read(&path)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let attachment = EmailAttachment {
+            filename: format!("messages.{}", extension(job, format)),
+            content_type: content_type(job, format).to_string(),
+            body,
+        };
+        let text = "Your scheduled journal export is attached.".to_string();
+        mailer
+            .send(&schedule.email, &subject, text, Some(attachment))
+            .await
+    } else {
+        let link = link.ok_or("PUBLIC_URL is required to email download links")?;
+        let text = format!(
+            "Your scheduled journal export is ready. Download it within {} days:\n\n{}",
+            EXPORT_RETENTION_DAYS, link
+        );
+        mailer.send(&schedule.email, &subject, text, None).await
+    }
+}
+
+/// Queue a job for every schedule that is due
+async fn queue_due_schedules(state: &AppState) {
+    // Nothing could deliver the exports
+    if state.mailer.is_none() {
+        return;
+    }
+
+    let now = Utc::now();
+    let schedules = match state.storage.get_due_export_schedules(now).await {
+        Ok(schedules) => schedules,
+        Err(e) => {
+            tracing::error!("Failed to fetch export schedules: {}", e);
+            return;
+        }
+    };
+
+    for schedule in schedules {
+        let Some(next_run_at) = next_run_after(&schedule, now) else {
+            tracing::warn!(
+                "Export schedule {} has unknown frequency {}",
+                schedule.id,
+                schedule.frequency
+            );
+            continue;
+        };
+
+        let job = ExportJob::for_schedule(&schedule);
+        if let Err(e) = state
+            .storage
+            .queue_scheduled_export(&job, next_run_at)
+            .await
+        {
+            tracing::error!("Failed to queue scheduled export {}: {}", schedule.id, e);
+        }
+    }
+}
+
+/// First run of `schedule` after `now`, skipping runs missed while the
+/// server was down rather than sending them all at once
+fn next_run_after(schedule: &ExportSchedule, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let mut next = schedule.next_run_at;
+    while next <= now {
+        next = next_export_run(&schedule.frequency, next)?;
+    }
+    Some(next)
+}
+
+async fn prune_expired(state: &AppState) {
+    let before = Utc::now() - chrono::Duration::days(EXPORT_RETENTION_DAYS);
+    let jobs = match state
+        .storage
+        .delete_export_jobs_finished_before(before)
+        .await
+    {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            tracing::error!("Failed to prune export jobs: {}", e);
+            return;
+        }
+    };
+
+    if jobs.is_empty() {
+        return;
+    }
+    for job in &jobs {
+        if let Ok(format) = job.format.parse() {
+            let _ = tokio::fs::remove_file(state.export_jobs.file_path(job, format)).await;
+        }
+    }
+    audit::record(
+        &*state.storage,
+        AuditEvent::new("exports.prune", ACTOR_SYSTEM).with_metadata(json!({
+            "count": jobs.len(),
+            "retention_days": EXPORT_RETENTION_DAYS,
+        })),
+    )
+    .await;
+}
+
+// ============ Handlers ============
+
+/// POST /api/exports
+/// Queue an export; poll `GET /api/exports/:id` until it completes
+#[utoipa::path(
+    post,
+    path = "/api/exports",
+    tag = "exports",
+    request_body = CreateExportRequest,
+    responses(
+        (status = 202, description = "Export queued", body = ExportJobResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn create_export(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Json(payload): Json<CreateExportRequest>,
+) -> Result<(StatusCode, Json<ExportJobResponse>), AppError> {
+    let format: ExportFormat = payload
+        .format
+        .parse()
+        .map_err(|e: String| AppError::new(StatusCode::BAD_REQUEST, e))?;
+    if let Some(encrypt_to) = &payload.encrypt_to {
+        encrypt_to
+            .parse::<EncryptTo>()
+            .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, e))?;
+    }
+    let split_by_month = match payload.split_by.as_deref() {
+        None => false,
+        Some("month") => true,
+        Some(other) => {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                format!("Unknown split_by: {}", other),
+            ))
+        }
+    };
+
+    let job = ExportJob {
+        split_by_month,
+        ..ExportJob::new(user_id, format.as_str().to_string()).encrypted_to(payload.encrypt_to)
+    };
+    state
+        .storage
+        .create_export_job(&job)
+        .await
+        .map_err(|e| storage_error(e, "Failed to queue export"))?;
+    state.export_jobs.wake.notify_one();
+
+    Ok((StatusCode::ACCEPTED, Json(job.to_response())))
+}
+
+/// GET /api/exports/:id
+/// Report an export's status, with a download URL once it has completed
+#[utoipa::path(
+    get,
+    path = "/api/exports/{id}",
+    tag = "exports",
+    params(("id" = String, Path, description = "Export ID")),
+    responses(
+        (status = 200, description = "Export status", body = ExportJobResponse),
+        (status = 404, description = "Export not found", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_export(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Path(job_id): Path<String>,
+) -> Result<Json<ExportJobResponse>, AppError> {
+    let job = find_job(&state, &job_id, &user_id).await?;
+    Ok(Json(job.to_response()))
+}
+
+/// GET /api/exports/:id/progress
+/// Report how many messages a running export has read, for progress bars
+#[utoipa::path(
+    get,
+    path = "/api/exports/{id}/progress",
+    tag = "exports",
+    params(("id" = String, Path, description = "Export ID")),
+    responses(
+        (status = 200, description = "Messages read so far", body = ExportProgressResponse),
+        (status = 404, description = "Export not found", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_export_progress(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Path(job_id): Path<String>,
+) -> Result<Json<ExportProgressResponse>, AppError> {
+    let job = find_job(&state, &job_id, &user_id).await?;
+    Ok(Json(job.to_progress_response()))
+}
+
+/// GET /api/exports/:id/download
+/// Stream a completed export's file
+#[utoipa::path(
+    get,
+    path = "/api/exports/{id}/download",
+    tag = "exports",
+    params(("id" = String, Path, description = "Export ID")),
+    responses(
+        (status = 200, description = "The export's file", body = String, content_type = "application/octet-stream"),
+        (status = 404, description = "Export not found", body = ErrorResponse),
+        (status = 409, description = "Export is not ready", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn download_export(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Path(job_id): Path<String>,
+) -> Result<Response, AppError> {
+    let job = find_job(&state, &job_id, &user_id).await?;
+    stream_export(&state, &job).await
+}
+
+/// GET /api/exports/:id/download/:token
+/// Stream a completed export's file from an emailed link, without logging in
+#[utoipa::path(
+    get,
+    path = "/api/exports/{id}/download/{token}",
+    tag = "exports",
+    params(
+        ("id" = String, Path, description = "Export ID"),
+        ("token" = String, Path, description = "Token from the emailed link")
+    ),
+    responses(
+        (status = 200, description = "The export's file", body = String, content_type = "application/octet-stream"),
+        (status = 404, description = "Export not found", body = ErrorResponse),
+        (status = 409, description = "Export is not ready", body = ErrorResponse),
+    )
+)]
+pub async fn download_export_by_token(
+    State(state): State<SharedState>,
+    Path((job_id, token)): Path<(String, String)>,
+) -> Result<Response, AppError> {
+    let job = state
+        .storage
+        .get_export_job_by_token(&job_id, &token)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "Export not found"))?;
+    stream_export(&state, &job).await
+}
+
+async fn stream_export(state: &AppState, job: &ExportJob) -> Result<Response, AppError> {
+    if job.status != EXPORT_STATUS_COMPLETED {
+        return Err(AppError::new(StatusCode::CONFLICT, "Export is not ready"));
+    }
+
+    let format: ExportFormat = job
+        .format
+        .parse()
+        .map_err(|e: String| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let file = tokio::fs::File::open(state.export_jobs.file_path(job, format))
+        .await
+        .map_err(|_| AppError::new(StatusCode::GONE, "Export file is missing"))?;
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type(job, format))
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"messages.{}\"",
+                extension(job, format)
+            ),
+        )
+        .body(Body::from_stream(ReaderStream::new(file)))
+        .map_err(|_| {
+            AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to build response",
+            )
+        })?;
+
+    Ok(response)
+}
+
+/// POST /api/export-schedules
+/// Email an export every week or month, starting straight away
+#[utoipa::path(
+    post,
+    path = "/api/export-schedules",
+    tag = "exports",
+    request_body = CreateExportScheduleRequest,
+    responses(
+        (status = 201, description = "Schedule created", body = ExportScheduleResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 404, description = "Email is not configured", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn create_schedule(
+    State(state): State<SharedState>,
+    auth: AuthUser,
+    Json(payload): Json<CreateExportScheduleRequest>,
+) -> Result<(StatusCode, Json<ExportScheduleResponse>), AppError> {
+    let mailer = state
+        .mailer
+        .as_ref()
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "Email is not configured"))?;
+    payload.validate()?;
+
+    let format: ExportFormat = payload
+        .format
+        .parse()
+        .map_err(|e: String| AppError::new(StatusCode::BAD_REQUEST, e))?;
+    if next_export_run(&payload.frequency, Utc::now()).is_none() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Frequency must be weekly or monthly",
+        ));
+    }
+    let delivery = payload
+        .delivery
+        .unwrap_or_else(|| EXPORT_DELIVERY_ATTACHMENT.to_string());
+    match delivery.as_str() {
+        EXPORT_DELIVERY_ATTACHMENT => {}
+        EXPORT_DELIVERY_LINK if mailer.public_url().is_some() => {}
+        EXPORT_DELIVERY_LINK => {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "Download links are not configured",
+            ))
+        }
+        _ => {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "Delivery must be attachment or link",
+            ))
+        }
+    }
+
+    // Schedules are kept, so a passphrase would have to be stored
+    if let Some(encrypt_to) = &payload.encrypt_to {
+        let encrypt_to: EncryptTo = encrypt_to
+            .parse()
+            .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, e))?;
+        if encrypt_to.is_passphrase() {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "Scheduled exports can only be encrypted to an age recipient",
+            ));
+        }
+    }
+
+    let email = match payload.email {
+        Some(email) => email,
+        None => auth.user(&state).await?.email.clone(),
+    };
+
+    let schedule = ExportSchedule::new(
+        auth.id,
+        format.as_str().to_string(),
+        payload.frequency,
+        email,
+        delivery,
+        payload.encrypt_to,
+    );
+    state
+        .storage
+        .create_export_schedule(&schedule)
+        .await
+        .map_err(|e| storage_error(e, "Failed to create schedule"))?;
+    state.export_jobs.wake.notify_one();
+
+    Ok((StatusCode::CREATED, Json(schedule.to_response())))
+}
+
+/// GET /api/export-schedules
+/// List the authenticated user's recurring exports
+#[utoipa::path(
+    get,
+    path = "/api/export-schedules",
+    tag = "exports",
+    responses(
+        (status = 200, description = "Export schedules", body = ExportSchedulesResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn list_schedules(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<ExportSchedulesResponse>, AppError> {
+    let schedules = state
+        .storage
+        .list_export_schedules(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    Ok(Json(ExportSchedulesResponse {
+        schedules: schedules.iter().map(ExportSchedule::to_response).collect(),
+    }))
+}
+
+/// DELETE /api/export-schedules/:id
+/// Stop a recurring export
+#[utoipa::path(
+    delete,
+    path = "/api/export-schedules/{id}",
+    tag = "exports",
+    params(("id" = String, Path, description = "Schedule ID")),
+    responses(
+        (status = 200, description = "Schedule deleted", body = SuccessResponse),
+        (status = 404, description = "Schedule not found", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn delete_schedule(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Path(schedule_id): Path<String>,
+) -> Result<Json<SuccessResponse>, AppError> {
+    state
+        .storage
+        .delete_export_schedule(&schedule_id, &user_id)
+        .await
+        .map_err(|e| match e {
+            DbError::ExportScheduleNotFound => {
+                AppError::new(StatusCode::NOT_FOUND, "Export schedule not found")
+            }
+            _ => storage_error(e, "Failed to delete schedule"),
+        })?;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+/// GET /api/export-destinations/webdav
+/// Get the WebDAV folder the authenticated user's exports are copied to
+#[utoipa::path(
+    get,
+    path = "/api/export-destinations/webdav",
+    tag = "exports",
+    responses(
+        (status = 200, description = "WebDAV folder exports are copied to", body = WebDavDestinationResponse),
+        (status = 404, description = "WebDAV destination not found", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_webdav_destination(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<WebDavDestinationResponse>, AppError> {
+    let destination = state
+        .storage
+        .get_webdav_destination(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "No WebDAV destination set"))?;
+
+    Ok(Json(destination.to_response()))
+}
+
+/// PUT /api/export-destinations/webdav
+/// Copy every finished export to a WebDAV folder, such as one in Nextcloud
+///
+/// The folder must already exist; the credentials are checked before saving.
+#[utoipa::path(
+    put,
+    path = "/api/export-destinations/webdav",
+    tag = "exports",
+    request_body = SetWebDavDestinationRequest,
+    responses(
+        (status = 200, description = "WebDAV folder saved", body = WebDavDestinationResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn set_webdav_destination(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Json(payload): Json<SetWebDavDestinationRequest>,
+) -> Result<Json<WebDavDestinationResponse>, AppError> {
+    state
+        .outbound
+        .check(&payload.url)
+        .await
+        .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, e))?;
+
+    let destination = WebDavDestination {
+        user_id,
+        url: payload.url,
+        username: payload.username,
+        password: payload.password,
+        created_at: Utc::now(),
+    };
+    state
+        .export_jobs
+        .webdav
+        .check(&destination)
+        .await
+        .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, e))?;
+
+    state
+        .storage
+        .set_webdav_destination(&destination)
+        .await
+        .map_err(|e| storage_error(e, "Failed to save WebDAV destination"))?;
+
+    Ok(Json(destination.to_response()))
+}
+
+/// DELETE /api/export-destinations/webdav
+/// Stop copying exports to WebDAV
+#[utoipa::path(
+    delete,
+    path = "/api/export-destinations/webdav",
+    tag = "exports",
+    responses(
+        (status = 200, description = "WebDAV folder removed", body = SuccessResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn delete_webdav_destination(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<SuccessResponse>, AppError> {
+    state
+        .storage
+        .delete_webdav_destination(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Failed to remove WebDAV destination"))?;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+async fn find_job(state: &AppState, job_id: &str, user_id: &UserId) -> Result<ExportJob, AppError> {
+    state
+        .storage
+        .get_export_job(job_id, user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "Export not found"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        handlers::AppState,
+        testing::{auth_user, test_app_state},
+    };
+    use http_body_util::BodyExt;
+    use std::sync::Arc;
+
+    async fn setup_test_state(dir: PathBuf) -> SharedState {
+        Arc::new(AppState {
+            export_jobs: ExportJobs::new(dir),
+            ..test_app_state().await
+        })
+    }
+
+    async fn create_test_user(state: &SharedState, email: &str) -> crate::models::User {
+        let user = crate::models::User::new(
+            email.to_string(),
+            "testuser".to_string(),
+            "hash".to_string(),
+            "salt".to_string(),
+        );
+        state.storage.create_user(&user).await.unwrap();
+        user
+    }
+
+    fn export_request(format: &str) -> Json<CreateExportRequest> {
+        Json(CreateExportRequest {
+            format: format.to_string(),
+            encrypt_to: None,
+            split_by: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_export_job_lifecycle() {
+        let dir = std::env::temp_dir().join(format!("dissipate-exports-{}", uuid::Uuid::new_v4()));
+        let state = setup_test_state(dir.clone()).await;
+        let user = create_test_user(&state, "jobs@example.com").await;
+        let message = Message::new(user.id.clone(), "Exported later".to_string());
+        state.storage.create_message(&message).await.unwrap();
+
+        let (status, Json(queued)) = create_export(
+            State(state.clone()),
+            auth_user(&user.id),
+            export_request("markdown"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(queued.status, "pending");
+        assert!(queued.download_url.is_none());
+
+        let early = download_export(
+            State(state.clone()),
+            auth_user(&user.id),
+            Path(queued.id.clone()),
+        )
+        .await;
+        assert_eq!(early.unwrap_err().status(), StatusCode::CONFLICT);
+
+        // What the background worker does
+        let job = state
+            .storage
+            .claim_next_export_job()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(job.id, queued.id);
+        run_job(&state, &job).await;
+
+        let Json(done) = get_export(
+            State(state.clone()),
+            auth_user(&user.id),
+            Path(queued.id.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(done.status, "completed");
+        assert_eq!(
+            done.download_url.as_deref(),
+            Some(format!("/api/exports/{}/download", queued.id).as_str())
+        );
+
+        let response = download_export(State(state), auth_user(&user.id), Path(queued.id))
+            .await
+            .unwrap();
+        assert!(response
+            .headers()
+            .get(header::CONTENT_DISPOSITION)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .contains("messages.md"));
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let markdown = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(markdown.contains("Exported later"));
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export_jobs_are_private_and_validated() {
+        let state = setup_test_state(std::env::temp_dir()).await;
+        let owner = create_test_user(&state, "owner@example.com").await;
+        let other = create_test_user(&state, "other@example.com").await;
+
+        let invalid = create_export(
+            State(state.clone()),
+            auth_user(&owner.id),
+            export_request("pdf"),
+        );
+        assert_eq!(invalid.await.unwrap_err().status(), StatusCode::BAD_REQUEST);
+
+        let (_, Json(queued)) = create_export(
+            State(state.clone()),
+            auth_user(&owner.id),
+            export_request("json"),
+        )
+        .await
+        .unwrap();
+        let result = get_export(State(state), auth_user(&other.id), Path(queued.id)).await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_export_encrypted_to_recipient() {
+        use std::io::Read;
+
+        let dir = std::env::temp_dir().join(format!("dissipate-exports-{}", uuid::Uuid::new_v4()));
+        let state = setup_test_state(dir.clone()).await;
+        let user = create_test_user(&state, "encrypted@example.com").await;
+        let message = Message::new(user.id.clone(), "For my eyes only".to_string());
+        state.storage.create_message(&message).await.unwrap();
+        let identity = age::x25519::Identity::generate();
+
+        let weak = create_export(
+            State(state.clone()),
+            auth_user(&user.id),
+            Json(CreateExportRequest {
+                format: "json".to_string(),
+                encrypt_to: Some("hunter2".to_string()),
+                split_by: None,
+            }),
+        )
+        .await;
+        assert_eq!(weak.unwrap_err().status(), StatusCode::BAD_REQUEST);
+
+        let (_, Json(queued)) = create_export(
+            State(state.clone()),
+            auth_user(&user.id),
+            Json(CreateExportRequest {
+                format: "json".to_string(),
+                encrypt_to: Some(identity.to_public().to_string()),
+                split_by: None,
+            }),
+        )
+        .await
+        .unwrap();
+        assert!(queued.encrypted);
+
+        let job = state
+            .storage
+            .claim_next_export_job()
+            .await
+            .unwrap()
+            .unwrap();
+        run_job(&state, &job).await;
+        let finished = state
+            .storage
+            .get_export_job(&queued.id, &user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(finished.encrypt_to.is_none());
+
+        let response = download_export(State(state), auth_user(&user.id), Path(queued.id))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "application/octet-stream"
+        );
+        assert!(response
+            .headers()
+            .get(header::CONTENT_DISPOSITION)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .contains("messages.json.age"));
+
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let age::Decryptor::Recipients(decryptor) = age::Decryptor::new(&bytes[..]).unwrap() else {
+            panic!("expected a recipient-encrypted file");
+        };
+        let mut json = String::new();
+        decryptor
+            .decrypt(std::iter::once(&identity as &dyn age::Identity))
+            .unwrap()
+            .read_to_string(&mut json)
+            .unwrap();
+        assert!(json.contains("For my eyes only"));
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export_progress_covers_every_page() {
+        let dir = std::env::temp_dir().join(format!("dissipate-exports-{}", uuid::Uuid::new_v4()));
+        let state = setup_test_state(dir.clone()).await;
+        let user = create_test_user(&state, "progress@example.com").await;
+        // Identical timestamps, so paging has to fall back to IDs
+        let created_at = Utc::now();
+        for i in 0..=EXPORT_PAGE_SIZE {
+            let mut message = Message::new(user.id.clone(), format!("Entry {}", i));
+            message.created_at = created_at;
+            state.storage.create_message(&message).await.unwrap();
+        }
+
+        let (_, Json(queued)) = create_export(
+            State(state.clone()),
+            auth_user(&user.id),
+            export_request("json"),
+        )
+        .await
+        .unwrap();
+        let Json(pending) = get_export_progress(
+            State(state.clone()),
+            auth_user(&user.id),
+            Path(queued.id.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(pending.processed, 0);
+        assert_eq!(pending.total, None);
+
+        let job = state
+            .storage
+            .claim_next_export_job()
+            .await
+            .unwrap()
+            .unwrap();
+        run_job(&state, &job).await;
+
+        let Json(done) = get_export_progress(
+            State(state.clone()),
+            auth_user(&user.id),
+            Path(queued.id.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(done.status, "completed");
+        assert_eq!(done.processed, EXPORT_PAGE_SIZE + 1);
+        assert_eq!(done.total, Some(EXPORT_PAGE_SIZE + 1));
+
+        let response = download_export(State(state), auth_user(&user.id), Path(queued.id))
+            .await
+            .unwrap();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let exported: Vec<serde_json::Value> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(exported.len() as i64, EXPORT_PAGE_SIZE + 1);
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export_split_by_month() {
+        let dir = std::env::temp_dir().join(format!("dissipate-exports-{}", uuid::Uuid::new_v4()));
+        let state = setup_test_state(dir.clone()).await;
+        let user = create_test_user(&state, "monthly@example.com").await;
+        let mut message = Message::new(user.id.clone(), "Spring".to_string());
+        message.created_at = "2024-05-20T09:00:00Z".parse().unwrap();
+        state.storage.create_message(&message).await.unwrap();
+
+        let unknown = create_export(
+            State(state.clone()),
+            auth_user(&user.id),
+            Json(CreateExportRequest {
+                format: "markdown".to_string(),
+                encrypt_to: None,
+                split_by: Some("week".to_string()),
+            }),
+        )
+        .await;
+        assert_eq!(unknown.unwrap_err().status(), StatusCode::BAD_REQUEST);
+
+        let (_, Json(queued)) = create_export(
+            State(state.clone()),
+            auth_user(&user.id),
+            Json(CreateExportRequest {
+                format: "markdown".to_string(),
+                encrypt_to: None,
+                split_by: Some("month".to_string()),
+            }),
+        )
+        .await
+        .unwrap();
+        assert!(queued.split_by_month);
+
+        let job = state
+            .storage
+            .claim_next_export_job()
+            .await
+            .unwrap()
+            .unwrap();
+        run_job(&state, &job).await;
+
+        let response = download_export(State(state), auth_user(&user.id), Path(queued.id))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "application/zip"
+        );
+        assert!(response
+            .headers()
+            .get(header::CONTENT_DISPOSITION)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .contains("messages.zip"));
+
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let archive = zip::ZipArchive::new(std::io::Cursor::new(bytes.to_vec())).unwrap();
+        assert_eq!(archive.file_names().collect::<Vec<_>>(), vec!["2024-05.md"]);
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scheduled_export_downloads_by_token() {
+        let dir = std::env::temp_dir().join(format!("dissipate-exports-{}", uuid::Uuid::new_v4()));
+        let state = setup_test_state(dir.clone()).await;
+        let user = create_test_user(&state, "scheduled@example.com").await;
+        let schedule = ExportSchedule::new(
+            user.id.clone(),
+            "json".to_string(),
+            "weekly".to_string(),
+            user.email.clone(),
+            EXPORT_DELIVERY_LINK.to_string(),
+            None,
+        );
+        state
+            .storage
+            .create_export_schedule(&schedule)
+            .await
+            .unwrap();
+
+        let now = Utc::now();
+        let next_run_at = next_run_after(&schedule, now).unwrap();
+        assert_eq!(
+            next_run_at,
+            schedule.next_run_at + chrono::Duration::weeks(1)
+        );
+        let job = ExportJob::for_schedule(&schedule);
+        state
+            .storage
+            .queue_scheduled_export(&job, next_run_at)
+            .await
+            .unwrap();
+        let due = state.storage.get_due_export_schedules(now).await.unwrap();
+        assert!(due.is_empty());
+
+        let claimed = state
+            .storage
+            .claim_next_export_job()
+            .await
+            .unwrap()
+            .unwrap();
+        run_job(&state, &claimed).await;
+
+        let token = job.download_token.clone().unwrap();
+        let wrong = download_export_by_token(
+            State(state.clone()),
+            Path((job.id.clone(), "wrong".to_string())),
+        )
+        .await;
+        assert_eq!(wrong.unwrap_err().status(), StatusCode::NOT_FOUND);
+
+        let response = download_export_by_token(State(state), Path((job.id, token)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export_schedules_require_email() {
+        let state = setup_test_state(std::env::temp_dir()).await;
+        let user = create_test_user(&state, "noemail@example.com").await;
+
+        let result = create_schedule(
+            State(state),
+            auth_user(&user.id),
+            Json(CreateExportScheduleRequest {
+                format: "json".to_string(),
+                frequency: "weekly".to_string(),
+                email: None,
+                delivery: None,
+                encrypt_to: None,
+            }),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::NOT_FOUND);
+    }
+
+    type Uploads = Arc<std::sync::Mutex<Vec<(String, Vec<u8>)>>>;
+
+    /// Minimal WebDAV server with one folder, recording files PUT into it
+    async fn spawn_webdav_server() -> (String, Uploads) {
+        let uploads = Uploads::default();
+        let recorded = uploads.clone();
+        let app = axum::Router::new().fallback(
+            move |method: axum::http::Method, uri: axum::http::Uri, body: axum::body::Bytes| {
+                let uploads = recorded.clone();
+                async move {
+                    match method.as_str() {
+                        "PROPFIND" if uri.path() == "/dav/journal/" => StatusCode::MULTI_STATUS,
+                        "PUT" if uri.path().starts_with("/dav/journal/") => {
+                            let upload = (uri.path().to_string(), body.to_vec());
+                            uploads.lock().unwrap().push(upload);
+                            StatusCode::CREATED
+                        }
+                        _ => StatusCode::NOT_FOUND,
+                    }
+                }
+            },
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}/dav", addr), uploads)
+    }
+
+    fn webdav_request(url: String) -> Json<SetWebDavDestinationRequest> {
+        Json(SetWebDavDestinationRequest {
+            url,
+            username: "alice".to_string(),
+            password: "app-password".to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_exports_are_copied_to_webdav() {
+        let dir = std::env::temp_dir().join(format!("dissipate-exports-{}", uuid::Uuid::new_v4()));
+        let state = setup_test_state(dir.clone()).await;
+        let user = create_test_user(&state, "webdav@example.com").await;
+        let message = Message::new(user.id.clone(), "Copied to Nextcloud".to_string());
+        state.storage.create_message(&message).await.unwrap();
+        let (base, uploads) = spawn_webdav_server().await;
+
+        let internal = set_webdav_destination(
+            State(state.clone()),
+            auth_user(&user.id),
+            webdav_request("http://192.168.1.10/remote.php/dav/files/alice".to_string()),
+        )
+        .await;
+        assert_eq!(internal.unwrap_err().status(), StatusCode::BAD_REQUEST);
+
+        let missing = set_webdav_destination(
+            State(state.clone()),
+            auth_user(&user.id),
+            webdav_request(format!("{}/missing", base)),
+        )
+        .await;
+        assert_eq!(missing.unwrap_err().status(), StatusCode::BAD_REQUEST);
+
+        let folder = format!("{}/journal/", base);
+        let Json(saved) = set_webdav_destination(
+            State(state.clone()),
+            auth_user(&user.id),
+            webdav_request(folder.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(saved.url, folder);
+
+        let _ = create_export(
+            State(state.clone()),
+            auth_user(&user.id),
+            export_request("markdown"),
+        )
+        .await
+        .unwrap();
+        let job = state
+            .storage
+            .claim_next_export_job()
+            .await
+            .unwrap()
+            .unwrap();
+        run_job(&state, &job).await;
+
+        {
+            let uploads = uploads.lock().unwrap();
+            assert_eq!(uploads.len(), 1);
+            assert_eq!(
+                uploads[0].0,
+                format!("/dav/journal/{}", webdav_file_name(&job, "md"))
+            );
+            assert!(String::from_utf8_lossy(&uploads[0].1).contains("Copied to Nextcloud"));
+        }
+
+        let _ = delete_webdav_destination(State(state.clone()), auth_user(&user.id))
+            .await
+            .unwrap();
+        let result = get_webdav_destination(State(state), auth_user(&user.id)).await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::NOT_FOUND);
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[test]
+    fn test_next_run_after_skips_missed_runs() {
+        let mut schedule = ExportSchedule::new(
+            UserId::generate(),
+            "json".to_string(),
+            "monthly".to_string(),
+            "user@example.com".to_string(),
+            EXPORT_DELIVERY_ATTACHMENT.to_string(),
+            None,
+        );
+        let now = Utc::now();
+        schedule.next_run_at = now - chrono::Duration::days(95);
+
+        let next = next_run_after(&schedule, now).unwrap();
+        assert!(next > now);
+        assert!(next <= now + chrono::Duration::days(31));
+
+        schedule.frequency = "daily".to_string();
+        assert!(next_run_after(&schedule, now).is_none());
+    }
+}
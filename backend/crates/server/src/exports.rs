@@ -0,0 +1,599 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::Response,
+};
+use chrono::Utc;
+use serde_json::json;
+
+use crate::{
+    error::AppError,
+    export_format::{
+        render_html, render_ics, render_json, render_markdown, write_zip, MarkdownLayout,
+    },
+    handlers::{storage_error, SharedState},
+    ids::UserId,
+    models::{MarkdownExportQuery, Message},
+    storage::Storage,
+    utils::hashtags,
+    AuthUser,
+};
+
+/// GET /api/export/json
+/// Export all user messages as JSON
+#[utoipa::path(
+    get,
+    path = "/api/export/json",
+    tag = "exports",
+    responses(
+        (status = 200, description = "All messages as JSON", body = String, content_type = "application/json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn export_json(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Response, AppError> {
+    let messages = state
+        .storage
+        .get_messages_for_user(&user_id, None)
+        .await
+        .map_err(|e| storage_error(e, "Failed to fetch messages"))?;
+
+    let json = render_json(&messages).map_err(|_| {
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to serialize messages",
+        )
+    })?;
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"messages.json\"",
+        )
+        .body(json.into())
+        .map_err(|_| {
+            AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to build response",
+            )
+        })?;
+
+    Ok(response)
+}
+
+/// GET /api/export/markdown
+/// Export all user messages as Markdown
+#[utoipa::path(
+    get,
+    path = "/api/export/markdown",
+    tag = "exports",
+    params(MarkdownExportQuery),
+    responses(
+        (status = 200, description = "All messages as Markdown", body = String, content_type = "text/markdown"),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn export_markdown(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Query(query): Query<MarkdownExportQuery>,
+) -> Result<Response, AppError> {
+    let layout = MarkdownLayout::from_query(&query)
+        .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, e))?;
+    let messages = state
+        .storage
+        .get_messages_for_user(&user_id, None)
+        .await
+        .map_err(|e| storage_error(e, "Failed to fetch messages"))?;
+
+    let markdown = render_markdown(&messages, Utc::now(), layout);
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/markdown; charset=utf-8")
+        .header(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"messages.md\"",
+        )
+        .body(markdown.into())
+        .map_err(|_| {
+            AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to build response",
+            )
+        })?;
+
+    Ok(response)
+}
+
+/// GET /api/export/html
+/// Export all user messages as a single styled HTML page
+#[utoipa::path(
+    get,
+    path = "/api/export/html",
+    tag = "exports",
+    responses(
+        (status = 200, description = "All messages as a styled HTML page", body = String, content_type = "text/html"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn export_html(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Response, AppError> {
+    let messages = state
+        .storage
+        .get_messages_for_user(&user_id, None)
+        .await
+        .map_err(|e| storage_error(e, "Failed to fetch messages"))?;
+
+    let page = render_html(&messages, Utc::now());
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .header(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"messages.html\"",
+        )
+        .body(page.into())
+        .map_err(|_| {
+            AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to build response",
+            )
+        })?;
+
+    Ok(response)
+}
+
+/// GET /api/export/ics
+/// Export an all-day calendar event for each message, to overlay the days
+/// written on onto a calendar app
+#[utoipa::path(
+    get,
+    path = "/api/export/ics",
+    tag = "exports",
+    responses(
+        (status = 200, description = "An all-day calendar event per message", body = String, content_type = "text/calendar"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn export_ics(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Response, AppError> {
+    let messages = state
+        .storage
+        .get_messages_for_user(&user_id, None)
+        .await
+        .map_err(|e| storage_error(e, "Failed to fetch messages"))?;
+
+    let calendar = render_ics(&messages, Utc::now());
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/calendar; charset=utf-8")
+        .header(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"messages.ics\"",
+        )
+        .body(calendar.into())
+        .map_err(|_| {
+            AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to build response",
+            )
+        })?;
+
+    Ok(response)
+}
+
+/// GET /api/export/account
+/// Export everything stored about the account as a ZIP of JSON files
+///
+/// Unlike the message exports, this covers the profile, templates,
+/// reactions, sharing, push subscriptions and export settings too. Secrets
+/// (the password hash and the WebDAV password) are left out.
+#[utoipa::path(
+    get,
+    path = "/api/export/account",
+    tag = "exports",
+    responses(
+        (status = 200, description = "ZIP of JSON files covering the whole account", body = String, content_type = "application/zip"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn export_account(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Response, AppError> {
+    let archive = account_archive(state.storage.as_ref(), &user_id).await?;
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/zip")
+        .header(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"account.zip\"",
+        )
+        .body(archive.into())
+        .map_err(|_| {
+            AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to build response",
+            )
+        })?;
+
+    Ok(response)
+}
+
+/// ZIP of everything stored about the account, as served by
+/// `export_account`
+pub async fn account_archive(storage: &dyn Storage, user_id: &UserId) -> Result<Vec<u8>, AppError> {
+    let fetch_error = |e| storage_error(e, "Failed to fetch account data");
+
+    let user = storage
+        .find_user_by_id(user_id)
+        .await
+        .map_err(fetch_error)?
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "User not found"))?;
+    let messages = storage
+        .get_messages_for_user(user_id, None)
+        .await
+        .map_err(fetch_error)?;
+    let templates = storage
+        .get_templates_for_user(user_id)
+        .await
+        .map_err(fetch_error)?;
+    let reactions = storage
+        .get_reactions_by_user(user_id)
+        .await
+        .map_err(fetch_error)?;
+    let members = storage
+        .list_journal_members(user_id)
+        .await
+        .map_err(fetch_error)?;
+    let shared_journals = storage
+        .list_shared_journals(user_id)
+        .await
+        .map_err(fetch_error)?;
+    let subscriptions = storage
+        .get_push_subscriptions(user_id)
+        .await
+        .map_err(fetch_error)?;
+    let schedules = storage
+        .list_export_schedules(user_id)
+        .await
+        .map_err(fetch_error)?;
+    let webdav = storage
+        .get_webdav_destination(user_id)
+        .await
+        .map_err(fetch_error)?;
+
+    let files = vec![
+        (
+            "account.json",
+            json!({
+                "format_version": 1,
+                "exported_at": Utc::now().to_rfc3339(),
+                "profile": {
+                    "id": user.id,
+                    "email": user.email,
+                    "username": user.username,
+                    "display_name": user.display_name,
+                    "created_at": user.created_at.to_rfc3339(),
+                    "updated_at": user.updated_at.to_rfc3339(),
+                },
+            }),
+        ),
+        (
+            "messages.json",
+            json!(messages
+                .iter()
+                .map(Message::to_response)
+                .collect::<Vec<_>>()),
+        ),
+        ("tags.json", json!(count_hashtags(&messages))),
+        (
+            "templates.json",
+            json!(templates
+                .iter()
+                .map(|t| t.to_response())
+                .collect::<Vec<_>>()),
+        ),
+        ("reactions.json", json!(reactions)),
+        (
+            "sharing.json",
+            json!({ "members": members, "shared_with_me": shared_journals }),
+        ),
+        (
+            "push_subscriptions.json",
+            json!(subscriptions
+                .iter()
+                .map(|s| json!({ "endpoint": s.endpoint, "created_at": s.created_at }))
+                .collect::<Vec<_>>()),
+        ),
+        (
+            "export_settings.json",
+            json!({
+                "schedules": schedules.iter().map(|s| s.to_response()).collect::<Vec<_>>(),
+                "webdav": webdav.map(|w| w.to_response()),
+            }),
+        ),
+    ];
+
+    let archive = tokio::task::spawn_blocking(move || write_json_zip(&files))
+        .await
+        .map_err(|_| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to build archive"))?
+        .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(archive)
+}
+
+/// Write each value as a pretty-printed JSON file in a ZIP archive
+fn write_json_zip(files: &[(&str, serde_json::Value)]) -> Result<Vec<u8>, String> {
+    let files = files
+        .iter()
+        .map(|(name, value)| {
+            serde_json::to_vec_pretty(value)
+                .map(|json| (name.to_string(), json))
+                .map_err(|e| format!("Failed to write {}: {}", name, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    write_zip(&files)
+}
+
+/// Count the `#hashtags` used across messages, which stand in for tags
+fn count_hashtags(messages: &[Message]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for tag in messages.iter().flat_map(|message| hashtags(&message.content)) {
+        *counts.entry(tag).or_insert(0) += 1;
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{Message, MessageResponse},
+        testing::{auth_user, test_app_state},
+        utils::hash_password,
+    };
+    use chrono::DateTime;
+    use http_body_util::BodyExt;
+    use std::sync::Arc;
+
+    async fn setup_test_state() -> SharedState {
+        Arc::new(test_app_state().await)
+    }
+
+    async fn create_test_user(state: &SharedState, email: &str) -> crate::models::User {
+        let (hash, salt) = hash_password("password123").unwrap();
+        let user = crate::models::User::new(email.to_string(), "testuser".to_string(), hash, salt);
+        state.storage.create_user(&user).await.unwrap();
+        user
+    }
+
+    #[tokio::test]
+    async fn test_export_json_empty() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "export@example.com").await;
+
+        let result = export_json(State(state), auth_user(&user.id)).await;
+
+        assert!(result.is_ok());
+        let response = result.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Check content-type header
+        let content_type = response.headers().get(header::CONTENT_TYPE).unwrap();
+        assert_eq!(content_type, "application/json");
+
+        // Check content-disposition header
+        let content_disposition = response.headers().get(header::CONTENT_DISPOSITION).unwrap();
+        assert!(content_disposition
+            .to_str()
+            .unwrap()
+            .contains("messages.json"));
+    }
+
+    #[tokio::test]
+    async fn test_export_json_with_messages() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "jsonexport@example.com").await;
+
+        // Create some messages
+        let msg1 = Message::new(user.id.clone(), "First message".to_string());
+        let msg2 = Message::new(user.id.clone(), "Second message".to_string());
+        state.storage.create_message(&msg1).await.unwrap();
+        state.storage.create_message(&msg2).await.unwrap();
+
+        let result = export_json(State(state), auth_user(&user.id)).await;
+
+        assert!(result.is_ok());
+        let response = result.unwrap();
+
+        // Parse body
+        let body = response.into_body();
+        let bytes = body.collect().await.unwrap().to_bytes();
+        let json_str = String::from_utf8(bytes.to_vec()).unwrap();
+
+        let messages: Vec<MessageResponse> = serde_json::from_str(&json_str).unwrap();
+        assert_eq!(messages.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_export_markdown_empty() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "mdexport@example.com").await;
+
+        let result =
+            export_markdown(State(state), auth_user(&user.id), Query(Default::default())).await;
+
+        assert!(result.is_ok());
+        let response = result.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Check content-type header
+        let content_type = response.headers().get(header::CONTENT_TYPE).unwrap();
+        assert!(content_type.to_str().unwrap().contains("text/markdown"));
+
+        // Check content-disposition header
+        let content_disposition = response.headers().get(header::CONTENT_DISPOSITION).unwrap();
+        assert!(content_disposition
+            .to_str()
+            .unwrap()
+            .contains("messages.md"));
+    }
+
+    #[tokio::test]
+    async fn test_export_markdown_with_messages() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "mdwithmsg@example.com").await;
+
+        let msg = Message::new(user.id.clone(), "Test message content".to_string());
+        state.storage.create_message(&msg).await.unwrap();
+
+        let result =
+            export_markdown(State(state), auth_user(&user.id), Query(Default::default())).await;
+
+        assert!(result.is_ok());
+        let response = result.unwrap();
+
+        let body = response.into_body();
+        let bytes = body.collect().await.unwrap().to_bytes();
+        let markdown = String::from_utf8(bytes.to_vec()).unwrap();
+
+        assert!(markdown.contains("# Messages Export"));
+        assert!(markdown.contains("Test message content"));
+    }
+
+    #[tokio::test]
+    async fn test_export_markdown_format() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "mdformat@example.com").await;
+
+        let msg = Message::new(user.id.clone(), "My test message".to_string());
+        state.storage.create_message(&msg).await.unwrap();
+
+        let result =
+            export_markdown(State(state), auth_user(&user.id), Query(Default::default())).await;
+
+        let response = result.unwrap();
+        let body = response.into_body();
+        let bytes = body.collect().await.unwrap().to_bytes();
+        let markdown = String::from_utf8(bytes.to_vec()).unwrap();
+
+        // Check structure
+        assert!(markdown.starts_with("# Messages Export"));
+        assert!(markdown.contains("Exported:"));
+        assert!(markdown.contains("---"));
+        assert!(markdown.contains("##")); // Date headers
+        assert!(markdown.contains("My test message"));
+    }
+
+    #[tokio::test]
+    async fn test_export_html_is_downloadable_page() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "htmlexport@example.com").await;
+        let msg = Message::new(user.id.clone(), "Some **bold** words".to_string());
+        state.storage.create_message(&msg).await.unwrap();
+
+        let response = export_html(State(state), auth_user(&user.id))
+            .await
+            .unwrap();
+
+        let content_type = response.headers().get(header::CONTENT_TYPE).unwrap();
+        assert!(content_type.to_str().unwrap().starts_with("text/html"));
+        let content_disposition = response.headers().get(header::CONTENT_DISPOSITION).unwrap();
+        assert!(content_disposition
+            .to_str()
+            .unwrap()
+            .contains("messages.html"));
+
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let page = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(page.starts_with("<!DOCTYPE html>"));
+        assert!(page.contains("<style>"));
+        assert!(page.contains("<strong>bold</strong>"));
+    }
+
+    #[tokio::test]
+    async fn test_export_ics_has_an_event_per_message() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "icsexport@example.com").await;
+        let mut msg = Message::new(user.id.clone(), "# Hiking, at last\nLong day".to_string());
+        msg.created_at = DateTime::parse_from_rfc3339("2024-05-31T22:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        state.storage.create_message(&msg).await.unwrap();
+
+        let response = export_ics(State(state), auth_user(&user.id)).await.unwrap();
+
+        let content_type = response.headers().get(header::CONTENT_TYPE).unwrap();
+        assert!(content_type.to_str().unwrap().starts_with("text/calendar"));
+
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let calendar = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(calendar.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(calendar.contains("DTSTART;VALUE=DATE:20240531\r\n"));
+        assert!(calendar.contains("DTEND;VALUE=DATE:20240601\r\n"));
+        assert!(calendar.contains("SUMMARY:Hiking\\, at last\r\n"));
+        assert!(calendar.ends_with("END:VCALENDAR\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_export_account_bundles_everything_but_secrets() {
+        use std::io::Read;
+
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "account@example.com").await;
+        let msg = Message::new(
+            user.id.clone(),
+            "Hiking today #outdoors #Outdoors".to_string(),
+        );
+        state.storage.create_message(&msg).await.unwrap();
+        state
+            .storage
+            .add_reaction(&msg.id, &user.id, "👍")
+            .await
+            .unwrap();
+
+        let response = export_account(State(state), auth_user(&user.id))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "application/zip"
+        );
+
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        let mut read = |name: &str| {
+            let mut json = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut json)
+                .unwrap();
+            serde_json::from_str::<serde_json::Value>(&json).unwrap()
+        };
+
+        let account = read("account.json");
+        assert_eq!(account["profile"]["email"], "account@example.com");
+        assert!(account["profile"].get("password_hash").is_none());
+        assert_eq!(read("messages.json")[0]["content"], msg.content);
+        assert_eq!(read("tags.json")["outdoors"], 2);
+        assert_eq!(read("reactions.json")[0]["emoji"], "👍");
+        assert!(read("export_settings.json")["webdav"].is_null());
+    }
+}
@@ -0,0 +1,321 @@
+use std::{collections::HashMap, str::FromStr, sync::RwLock};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde_json::json;
+
+use crate::{
+    audit::{self, AdminActor},
+    config::FeaturesConfig,
+    db::DbError,
+    error::AppError,
+    handlers::{storage_error, SharedState},
+    models::{
+        AuditEvent, ClientConfigResponse, FeatureFlagResponse, FeatureFlagsResponse,
+        FeaturesResponse, SetFeatureFlagRequest,
+    },
+    storage::Storage,
+};
+
+/// `code` of the 403 returned when a request needs a feature that is off
+pub const FEATURE_DISABLED: &str = "feature_disabled";
+
+/// A part of the app operators can switch off per instance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Feature {
+    /// Creating accounts at `/api/register`
+    Registration,
+    /// Shared journals
+    Sharing,
+    /// Attachments; there are no uploads yet, so only clients act on it
+    Attachments,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 3] = [Self::Registration, Self::Sharing, Self::Attachments];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Registration => "registration",
+            Self::Sharing => "sharing",
+            Self::Attachments => "attachments",
+        }
+    }
+
+    fn disabled_message(self) -> &'static str {
+        match self {
+            Self::Registration => "Registration is closed on this instance",
+            Self::Sharing => "Sharing is disabled on this instance",
+            Self::Attachments => "Attachments are disabled on this instance",
+        }
+    }
+}
+
+impl FromStr for Feature {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|feature| feature.as_str() == value)
+            .ok_or_else(|| format!("Unknown feature: {}", value))
+    }
+}
+
+/// Which features are on: the `[features]` config, overridden by switches
+/// operators save at `/api/admin/features`
+///
+/// The switches are kept in the database so they survive restarts, and
+/// mirrored here so checking a feature doesn't need a query.
+#[derive(Debug)]
+pub struct Features {
+    defaults: FeaturesConfig,
+    overrides: RwLock<HashMap<Feature, bool>>,
+}
+
+impl Features {
+    pub fn from_config(config: &FeaturesConfig) -> Self {
+        Self {
+            defaults: config.clone(),
+            overrides: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Apply the switches saved in the database, at startup
+    pub async fn load(&self, storage: &dyn Storage) -> Result<(), DbError> {
+        let flags = storage.list_feature_flags().await?;
+
+        let mut overrides = self.overrides.write().unwrap();
+        for (name, enabled) in flags {
+            match name.parse() {
+                Ok(feature) => {
+                    overrides.insert(feature, enabled);
+                }
+                // Left by a newer version, or one that dropped a feature
+                Err(_) => tracing::warn!("Ignoring unknown feature flag {}", name),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        self.overrides
+            .read()
+            .unwrap()
+            .get(&feature)
+            .copied()
+            .unwrap_or_else(|| self.default_for(feature))
+    }
+
+    /// Reject a request needing `feature` while it is off
+    pub fn require(&self, feature: Feature) -> Result<(), AppError> {
+        if self.is_enabled(feature) {
+            return Ok(());
+        }
+        Err(AppError::Rejected {
+            status: StatusCode::FORBIDDEN,
+            message: feature.disabled_message().to_string(),
+            code: Some(FEATURE_DISABLED),
+            usage: None,
+        })
+    }
+
+    fn default_for(&self, feature: Feature) -> bool {
+        match feature {
+            Feature::Registration => self.defaults.registration,
+            Feature::Sharing => self.defaults.sharing,
+            Feature::Attachments => self.defaults.attachments,
+        }
+    }
+
+    fn set(&self, feature: Feature, enabled: Option<bool>) {
+        let mut overrides = self.overrides.write().unwrap();
+        match enabled {
+            Some(enabled) => overrides.insert(feature, enabled),
+            None => overrides.remove(&feature),
+        };
+    }
+
+    fn to_response(&self) -> FeaturesResponse {
+        FeaturesResponse {
+            registration: self.is_enabled(Feature::Registration),
+            sharing: self.is_enabled(Feature::Sharing),
+            attachments: self.is_enabled(Feature::Attachments),
+        }
+    }
+
+    fn flag_response(&self, feature: Feature) -> FeatureFlagResponse {
+        FeatureFlagResponse {
+            name: feature.as_str().to_string(),
+            enabled: self.is_enabled(feature),
+            overridden: self.overrides.read().unwrap().contains_key(&feature),
+        }
+    }
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self::from_config(&FeaturesConfig::default())
+    }
+}
+
+/// GET /api/config
+/// Settings clients adapt to, such as which features are on
+#[utoipa::path(
+    get,
+    path = "/api/config",
+    tag = "system",
+    responses(
+        (status = 200, description = "Instance settings", body = ClientConfigResponse),
+    )
+)]
+pub async fn get_config(State(state): State<SharedState>) -> Json<ClientConfigResponse> {
+    Json(ClientConfigResponse {
+        features: state.features.to_response(),
+    })
+}
+
+/// GET /api/admin/features
+/// List every feature, whether it is on and whether that was switched here
+#[utoipa::path(
+    get,
+    path = "/api/admin/features",
+    tag = "admin",
+    responses(
+        (status = 200, description = "All features", body = FeatureFlagsResponse),
+    ),
+    security(("admin_token" = []), ("bearer_auth" = []))
+)]
+pub async fn list_features(State(state): State<SharedState>) -> Json<FeatureFlagsResponse> {
+    Json(FeatureFlagsResponse {
+        features: Feature::ALL
+            .into_iter()
+            .map(|feature| state.features.flag_response(feature))
+            .collect(),
+    })
+}
+
+/// PUT /api/admin/features/{name}
+/// Switch a feature on or off, whatever the config says
+#[utoipa::path(
+    put,
+    path = "/api/admin/features/{name}",
+    tag = "admin",
+    params(("name" = String, Path, description = "`registration`, `sharing` or `attachments`")),
+    request_body = SetFeatureFlagRequest,
+    responses(
+        (status = 200, description = "Updated feature", body = FeatureFlagResponse),
+        (status = 404, description = "Unknown feature", body = ErrorResponse),
+    ),
+    security(("admin_token" = []), ("bearer_auth" = []))
+)]
+pub async fn set_feature(
+    State(state): State<SharedState>,
+    Extension(AdminActor(actor)): Extension<AdminActor>,
+    Path(name): Path<String>,
+    Json(payload): Json<SetFeatureFlagRequest>,
+) -> Result<Json<FeatureFlagResponse>, AppError> {
+    let feature = parse_feature(&name)?;
+
+    state
+        .storage
+        .set_feature_flag(feature.as_str(), payload.enabled)
+        .await
+        .map_err(|e| storage_error(e, "Failed to save feature"))?;
+    state.features.set(feature, Some(payload.enabled));
+    tracing::info!(
+        "Admin turned {} {}",
+        feature.as_str(),
+        if payload.enabled { "on" } else { "off" }
+    );
+    audit::record(
+        &*state.storage,
+        AuditEvent::new("admin.feature.set", &actor)
+            .with_target("feature", feature.as_str())
+            .with_metadata(json!({ "enabled": payload.enabled })),
+    )
+    .await;
+
+    Ok(Json(state.features.flag_response(feature)))
+}
+
+/// DELETE /api/admin/features/{name}
+/// Drop a switch, so the feature follows the config again
+#[utoipa::path(
+    delete,
+    path = "/api/admin/features/{name}",
+    tag = "admin",
+    params(("name" = String, Path, description = "`registration`, `sharing` or `attachments`")),
+    responses(
+        (status = 200, description = "Feature as the config sets it", body = FeatureFlagResponse),
+        (status = 404, description = "Unknown feature", body = ErrorResponse),
+    ),
+    security(("admin_token" = []), ("bearer_auth" = []))
+)]
+pub async fn reset_feature(
+    State(state): State<SharedState>,
+    Extension(AdminActor(actor)): Extension<AdminActor>,
+    Path(name): Path<String>,
+) -> Result<Json<FeatureFlagResponse>, AppError> {
+    let feature = parse_feature(&name)?;
+
+    state
+        .storage
+        .delete_feature_flag(feature.as_str())
+        .await
+        .map_err(|e| storage_error(e, "Failed to reset feature"))?;
+    state.features.set(feature, None);
+    tracing::info!("Admin reset {} to the config", feature.as_str());
+    audit::record(
+        &*state.storage,
+        AuditEvent::new("admin.feature.reset", &actor).with_target("feature", feature.as_str()),
+    )
+    .await;
+
+    Ok(Json(state.features.flag_response(feature)))
+}
+
+fn parse_feature(name: &str) -> Result<Feature, AppError> {
+    name.parse()
+        .map_err(|e: String| AppError::new(StatusCode::NOT_FOUND, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[tokio::test]
+    async fn test_saved_switches_override_the_config() {
+        let storage = MemoryStorage::new();
+        storage.set_feature_flag("sharing", false).await.unwrap();
+        storage
+            .set_feature_flag("teleportation", true)
+            .await
+            .unwrap();
+
+        let features = Features::default();
+        assert!(features.is_enabled(Feature::Sharing));
+        features.load(&storage).await.unwrap();
+        assert!(!features.is_enabled(Feature::Sharing));
+        assert!(!features.is_enabled(Feature::Registration));
+
+        let (status, error) = features.require(Feature::Sharing).unwrap_err().into_parts();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(error.code.as_deref(), Some(FEATURE_DISABLED));
+
+        features.set(Feature::Sharing, None);
+        assert!(features.require(Feature::Sharing).is_ok());
+    }
+
+    #[test]
+    fn test_feature_names_round_trip() {
+        for feature in Feature::ALL {
+            assert_eq!(feature.as_str().parse::<Feature>(), Ok(feature));
+        }
+        assert!("teleportation".parse::<Feature>().is_err());
+    }
+}
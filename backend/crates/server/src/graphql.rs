@@ -0,0 +1,523 @@
+use std::{cmp::Reverse, collections::BTreeMap};
+
+use async_graphql::{
+    Context, EmptySubscription, Error, ErrorExtensions, InputObject, Object, Request, Response,
+    Schema, SimpleObject,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Extension, Json,
+};
+
+use crate::{
+    error::AppError,
+    handlers::{self, SharedState},
+    ids::MessageId,
+    models::{
+        CreateMessageRequest, MessageResponse, MessagesQuery, ReactionRequest,
+        UpdateDisplayNameRequest, UpdateMessageRequest, UpdateUsernameRequest,
+    },
+    pagination::Pagination,
+    utils::{hashtags, parse_timestamp},
+    AuthUser,
+};
+
+/// Deepest selection accepted, to keep queries cheap
+const MAX_DEPTH: usize = 8;
+
+pub type GraphQlSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+/// Build the schema served at `/api/graphql`
+pub fn schema() -> GraphQlSchema {
+    Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .finish()
+}
+
+/// POST /api/graphql
+/// Run a request as the authenticated user
+pub async fn execute(
+    State(state): State<SharedState>,
+    auth: AuthUser,
+    Extension(schema): Extension<GraphQlSchema>,
+    Json(request): Json<Request>,
+) -> Json<Response> {
+    let request = request.data(state).data(CurrentUser(auth));
+    Json(schema.execute(request).await)
+}
+
+/// The authenticated user a request runs as
+struct CurrentUser(AuthUser);
+
+fn request_context(ctx: &Context<'_>) -> (SharedState, AuthUser) {
+    let state = ctx.data_unchecked::<SharedState>().clone();
+    let auth = ctx.data_unchecked::<CurrentUser>().0.clone();
+    (state, auth)
+}
+
+/// Report a REST handler's error as a GraphQL error, keeping its status
+fn handler_error(error: AppError) -> Error {
+    let (status, error) = error.into_parts();
+    Error::new(error.error)
+        .extend_with(|_, extensions| extensions.set("status", i32::from(status.as_u16())))
+}
+
+/// Parse a message ID argument, failing with the status the REST API gives a
+/// malformed one
+fn parse_message_id(id: &str) -> Result<MessageId, Error> {
+    id.parse()
+        .map_err(|e: String| handler_error(AppError::new(StatusCode::BAD_REQUEST, e)))
+}
+
+#[derive(SimpleObject)]
+struct User {
+    id: String,
+    email: String,
+    username: String,
+    /// Name shown to other users, when set
+    display_name: Option<String>,
+}
+
+#[derive(SimpleObject)]
+struct Message {
+    id: String,
+    content: String,
+    created_at: String,
+    updated_at: String,
+    publish_at: Option<String>,
+    /// Set when written by a journal collaborator
+    author_id: Option<String>,
+    /// Set on copies saved from a conflicting edit
+    conflict_of: Option<String>,
+    reactions: Vec<Reaction>,
+    /// `#hashtags` in the content, lowercased
+    tags: Vec<String>,
+}
+
+impl From<MessageResponse> for Message {
+    fn from(message: MessageResponse) -> Self {
+        let mut tags: Vec<String> = Vec::new();
+        for tag in hashtags(&message.content) {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+
+        Self {
+            tags,
+            id: message.id,
+            content: message.content,
+            created_at: message.created_at,
+            updated_at: message.updated_at,
+            publish_at: message.publish_at,
+            author_id: message.author_id,
+            conflict_of: message.conflict_of,
+            reactions: message
+                .reactions
+                .into_iter()
+                .map(|reaction| Reaction {
+                    emoji: reaction.emoji,
+                    count: reaction.count,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct Reaction {
+    emoji: String,
+    count: i64,
+}
+
+/// A `#hashtag`, which stands in for tags
+#[derive(SimpleObject)]
+struct Tag {
+    name: String,
+    /// Messages using it
+    count: i64,
+}
+
+#[derive(SimpleObject)]
+struct Stats {
+    message_count: i64,
+    /// Bytes of message content stored
+    content_bytes: i64,
+    /// Configured per-user limit; null when unlimited
+    quota_bytes: Option<i64>,
+    /// Configured per-user limit on messages; null when unlimited
+    max_messages: Option<i64>,
+    /// Messages created since midnight UTC
+    messages_today: i64,
+    /// Configured per-user limit on messages created per day; null when
+    /// unlimited
+    max_messages_per_day: Option<i64>,
+    first_message_at: Option<String>,
+    last_message_at: Option<String>,
+    tag_count: i64,
+}
+
+/// Narrows `messages`; every field set must match
+#[derive(InputObject, Default)]
+struct MessageFilter {
+    /// Created at or after this time (RFC 3339)
+    from: Option<String>,
+    /// Created before this time (RFC 3339)
+    to: Option<String>,
+    /// Uses this `#hashtag`, with or without the `#`
+    tag: Option<String>,
+    /// Contains this text, ignoring case
+    search: Option<String>,
+}
+
+#[derive(InputObject)]
+struct CreateMessageInput {
+    #[graphql(default)]
+    content: String,
+    /// Client-generated ID, for offline sync
+    id: Option<String>,
+    /// Pre-fills content when content is empty
+    template_id: Option<String>,
+    /// Backdate the entry (RFC 3339)
+    created_at: Option<String>,
+    /// Hide from listings until this time (RFC 3339)
+    publish_at: Option<String>,
+}
+
+/// The user's messages, newest first, with reactions
+async fn all_messages(state: SharedState, auth: AuthUser) -> Result<Vec<Message>, Error> {
+    let Json(response) = handlers::get_messages(
+        State(state),
+        auth,
+        Query(MessagesQuery::default()),
+        Pagination::default(),
+    )
+    .await
+    .map_err(handler_error)?;
+    Ok(response.messages.into_iter().map(Message::from).collect())
+}
+
+fn filter_messages(
+    mut messages: Vec<Message>,
+    filter: MessageFilter,
+) -> Result<Vec<Message>, Error> {
+    let bound = |value: &str, name: &str| {
+        parse_timestamp(value)
+            .map_err(|_| Error::new(format!("Invalid {} timestamp: {}", name, value)))
+    };
+    let created_at = |message: &Message| parse_timestamp(&message.created_at).ok();
+
+    if let Some(from) = filter.from {
+        let from = bound(&from, "from")?;
+        messages.retain(|message| created_at(message).is_some_and(|at| at >= from));
+    }
+    if let Some(to) = filter.to {
+        let to = bound(&to, "to")?;
+        messages.retain(|message| created_at(message).is_some_and(|at| at < to));
+    }
+    if let Some(tag) = filter.tag {
+        let tag = tag.trim_start_matches('#').to_lowercase();
+        messages.retain(|message| message.tags.contains(&tag));
+    }
+    if let Some(search) = filter.search {
+        let search = search.to_lowercase();
+        messages.retain(|message| message.content.to_lowercase().contains(&search));
+    }
+
+    Ok(messages)
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// The authenticated user
+    async fn me(&self, ctx: &Context<'_>) -> Result<User, Error> {
+        let (state, auth) = request_context(ctx);
+        // Not `auth.user`, which would miss changes made by a mutation
+        let user = state
+            .storage
+            .find_user_by_id(&auth.id)
+            .await
+            .map_err(|e| Error::new(format!("Failed to fetch user: {}", e)))?
+            .ok_or_else(|| Error::new("User not found"))?;
+
+        Ok(User {
+            id: user.id.into(),
+            email: user.email,
+            username: user.username,
+            display_name: user.display_name,
+        })
+    }
+
+    /// The user's messages, newest first
+    async fn messages(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: MessageFilter,
+        #[graphql(
+            default = 50,
+            validator(minimum = 0, maximum = 500),
+            desc = "At most 500"
+        )]
+        limit: i32,
+        #[graphql(default, validator(minimum = 0))] offset: i32,
+    ) -> Result<Vec<Message>, Error> {
+        let (state, auth) = request_context(ctx);
+        let messages = filter_messages(all_messages(state, auth).await?, filter)?;
+
+        Ok(messages
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect())
+    }
+
+    /// Hashtags used across the user's messages, most used first
+    async fn tags(&self, ctx: &Context<'_>) -> Result<Vec<Tag>, Error> {
+        let (state, auth) = request_context(ctx);
+        let mut counts: BTreeMap<String, i64> = BTreeMap::new();
+        for message in all_messages(state, auth).await? {
+            for tag in message.tags {
+                *counts.entry(tag).or_insert(0) += 1;
+            }
+        }
+
+        let mut tags: Vec<Tag> = counts
+            .into_iter()
+            .map(|(name, count)| Tag { name, count })
+            .collect();
+        tags.sort_by_key(|tag| Reverse(tag.count));
+        Ok(tags)
+    }
+
+    /// Totals across the user's messages
+    async fn stats(&self, ctx: &Context<'_>) -> Result<Stats, Error> {
+        let (state, auth) = request_context(ctx);
+        let Json(usage) = handlers::get_usage(State(state.clone()), auth.clone())
+            .await
+            .map_err(handler_error)?;
+        let messages = all_messages(state, auth).await?;
+
+        let mut tags: Vec<&String> = messages.iter().flat_map(|m| &m.tags).collect();
+        tags.sort();
+        tags.dedup();
+
+        Ok(Stats {
+            message_count: messages.len() as i64,
+            content_bytes: usage.content_bytes,
+            quota_bytes: usage.quota_bytes,
+            max_messages: usage.max_messages,
+            messages_today: usage.messages_today,
+            max_messages_per_day: usage.max_messages_per_day,
+            first_message_at: messages.last().map(|m| m.created_at.clone()),
+            last_message_at: messages.first().map(|m| m.created_at.clone()),
+            tag_count: tags.len() as i64,
+        })
+    }
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    async fn create_message(
+        &self,
+        ctx: &Context<'_>,
+        input: CreateMessageInput,
+    ) -> Result<Message, Error> {
+        let (state, auth) = request_context(ctx);
+        let request = CreateMessageRequest {
+            content: input.content,
+            id: input.id.as_deref().map(parse_message_id).transpose()?,
+            template_id: input.template_id,
+            created_at: input.created_at,
+            publish_at: input.publish_at,
+        };
+        let (_, Json(message)) = handlers::create_message(State(state), auth, Json(request))
+            .await
+            .map_err(handler_error)?;
+        Ok(message.into())
+    }
+
+    /// Pass the `updatedAt` the edit was based on to detect conflicts
+    async fn update_message(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        content: String,
+        updated_at: Option<String>,
+    ) -> Result<Message, Error> {
+        let (state, auth) = request_context(ctx);
+        let id = parse_message_id(&id)?;
+        let request = UpdateMessageRequest {
+            content,
+            updated_at,
+        };
+        let Json(message) = handlers::update_message(State(state), auth, Path(id), Json(request))
+            .await
+            .map_err(handler_error)?;
+        Ok(message.into())
+    }
+
+    async fn delete_message(&self, ctx: &Context<'_>, id: String) -> Result<bool, Error> {
+        let (state, auth) = request_context(ctx);
+        let Json(_) = handlers::delete_message(State(state), auth, Path(parse_message_id(&id)?))
+            .await
+            .map_err(handler_error)?;
+        Ok(true)
+    }
+
+    async fn add_reaction(
+        &self,
+        ctx: &Context<'_>,
+        message_id: String,
+        emoji: String,
+    ) -> Result<bool, Error> {
+        let (state, auth) = request_context(ctx);
+        let message_id = parse_message_id(&message_id)?;
+        let request = ReactionRequest { emoji };
+        let Json(_) = handlers::add_reaction(State(state), auth, Path(message_id), Json(request))
+            .await
+            .map_err(handler_error)?;
+        Ok(true)
+    }
+
+    async fn remove_reaction(
+        &self,
+        ctx: &Context<'_>,
+        message_id: String,
+        emoji: String,
+    ) -> Result<bool, Error> {
+        let (state, auth) = request_context(ctx);
+        let message_id = parse_message_id(&message_id)?;
+        let Json(_) = handlers::remove_reaction(State(state), auth, Path((message_id, emoji)))
+            .await
+            .map_err(handler_error)?;
+        Ok(true)
+    }
+
+    /// Change the username and return the updated profile
+    async fn update_username(&self, ctx: &Context<'_>, username: String) -> Result<User, Error> {
+        let (state, auth) = request_context(ctx);
+        let request = UpdateUsernameRequest { username };
+        let Json(_) = handlers::update_username(State(state), auth, Json(request))
+            .await
+            .map_err(handler_error)?;
+        QueryRoot.me(ctx).await
+    }
+
+    /// Set or clear the display name and return the updated profile
+    async fn update_display_name(
+        &self,
+        ctx: &Context<'_>,
+        display_name: Option<String>,
+    ) -> Result<User, Error> {
+        let (state, auth) = request_context(ctx);
+        let request = UpdateDisplayNameRequest { display_name };
+        let Json(_) = handlers::update_display_name(State(state), auth, Json(request))
+            .await
+            .map_err(handler_error)?;
+        QueryRoot.me(ctx).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ids::UserId,
+        models::User as UserModel,
+        testing::{auth_user, test_app_state},
+    };
+    use std::sync::Arc;
+
+    async fn setup_test_state() -> SharedState {
+        Arc::new(test_app_state().await)
+    }
+
+    async fn run(state: &SharedState, user_id: &UserId, query: &str) -> serde_json::Value {
+        let request = async_graphql::Request::new(query)
+            .data(state.clone())
+            .data(CurrentUser(auth_user(user_id)));
+        let response = schema().execute(request).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        response.data.into_json().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_mutations_and_filtered_queries() {
+        let state = setup_test_state().await;
+        let user = UserModel::new(
+            "graphql@example.com".to_string(),
+            "testuser".to_string(),
+            "hash".to_string(),
+            "salt".to_string(),
+        );
+        state.storage.create_user(&user).await.unwrap();
+
+        run(
+            &state,
+            &user.id,
+            r#"mutation {
+                a: createMessage(input: { content: "Learning #Rust today" }) { id }
+                b: createMessage(input: { content: "A quiet walk #outdoors" }) { id }
+            }"#,
+        )
+        .await;
+
+        let data = run(
+            &state,
+            &user.id,
+            r##"{
+                me { email }
+                messages(filter: { tag: "#rust" }) { content tags }
+                tags { name count }
+                stats { messageCount tagCount }
+            }"##,
+        )
+        .await;
+
+        assert_eq!(data["me"]["email"], "graphql@example.com");
+        let messages = data["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["content"], "Learning #Rust today");
+        assert_eq!(messages[0]["tags"], serde_json::json!(["rust"]));
+        assert_eq!(data["tags"].as_array().unwrap().len(), 2);
+        assert_eq!(data["stats"]["messageCount"], 2);
+        assert_eq!(data["stats"]["tagCount"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_handler_errors_keep_their_status() {
+        let state = setup_test_state().await;
+        let request = async_graphql::Request::new(format!(
+            r#"mutation {{ updateMessage(id: "{}", content: "Edited") {{ id }} }}"#,
+            MessageId::generate()
+        ))
+        .data(state)
+        .data(CurrentUser(auth_user(&UserId::generate())));
+
+        let response = schema().execute(request).await;
+
+        let error = &response.errors[0];
+        assert_eq!(error.message, "Message not found");
+        let status = error.extensions.as_ref().unwrap().get("status").unwrap();
+        assert_eq!(*status, async_graphql::Value::from(404));
+    }
+    #[tokio::test]
+    async fn test_malformed_message_id_is_a_bad_request() {
+        let state = setup_test_state().await;
+        let request = async_graphql::Request::new(r#"mutation { deleteMessage(id: "missing") }"#)
+            .data(state)
+            .data(CurrentUser(auth_user(&UserId::generate())));
+
+        let response = schema().execute(request).await;
+
+        let error = &response.errors[0];
+        assert_eq!(error.message, "Invalid message ID: missing");
+        let status = error.extensions.as_ref().unwrap().get("status").unwrap();
+        assert_eq!(*status, async_graphql::Value::from(400));
+    }
+}
@@ -0,0 +1,372 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tonic::{Code, Request, Response, Status};
+
+use crate::{
+    auth::extract_token_from_header,
+    error::AppError,
+    handlers::{self, SharedState},
+    ids::{MessageId, UserId},
+    models,
+    pagination::Pagination,
+    AuthUser,
+};
+
+pub mod proto {
+    tonic::include_proto!("dissipate.v1");
+}
+
+use proto::messages_server::{Messages, MessagesServer};
+
+/// Serve the gRPC API on `addr`, next to the HTTP server, until `shutdown`
+/// is cancelled and in-flight calls finish
+pub fn spawn(state: SharedState, addr: SocketAddr, shutdown: CancellationToken) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let service = MessagesServer::new(MessagesService { state });
+        if let Err(e) = tonic::transport::Server::builder()
+            .add_service(service)
+            .serve_with_shutdown(addr, shutdown.cancelled_owned())
+            .await
+        {
+            tracing::error!("gRPC server failed: {}", e);
+        }
+    })
+}
+
+/// Message CRUD and sync, sharing the REST handlers' logic
+pub struct MessagesService {
+    state: SharedState,
+}
+
+impl MessagesService {
+    /// Check the `authorization` metadata and return who the caller is signed in as
+    async fn authenticate<T>(&self, request: &Request<T>) -> Result<AuthUser, Status> {
+        let header = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| Status::unauthenticated("Missing authorization"))?;
+        let token = extract_token_from_header(header)
+            .map_err(|_| Status::unauthenticated("Invalid authorization header"))?;
+        let claims = self
+            .state
+            .auth
+            .validate_token(token)
+            .await
+            .map_err(|_| Status::unauthenticated("Invalid token"))?;
+
+        let id = claims
+            .user_id
+            .parse()
+            .map_err(|_| Status::unauthenticated("Invalid token"))?;
+        Ok(AuthUser::new(id, claims))
+    }
+}
+
+/// Parse a message ID sent by a client, rejecting it as the REST API does a
+/// malformed path
+fn parse_message_id(id: &str) -> Result<MessageId, Status> {
+    id.parse().map_err(Status::invalid_argument)
+}
+
+/// Report a REST handler's error with the closest gRPC code
+fn handler_error(error: AppError) -> Status {
+    let (status, error) = error.into_parts();
+    let code = match status {
+        StatusCode::BAD_REQUEST => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND => Code::NotFound,
+        // Edit conflicts and reused IDs
+        StatusCode::CONFLICT => Code::Aborted,
+        StatusCode::PAYLOAD_TOO_LARGE => Code::ResourceExhausted,
+        StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+        _ => Code::Internal,
+    };
+    Status::new(code, error.error)
+}
+
+impl From<models::MessageResponse> for proto::Message {
+    fn from(message: models::MessageResponse) -> Self {
+        Self {
+            id: message.id,
+            content: message.content,
+            created_at: message.created_at,
+            updated_at: message.updated_at,
+            seq: message.seq,
+            publish_at: message.publish_at,
+            author_id: message.author_id,
+            conflict_of: message.conflict_of,
+            reactions: message
+                .reactions
+                .into_iter()
+                .map(|reaction| proto::ReactionCount {
+                    emoji: reaction.emoji,
+                    count: reaction.count,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<models::SyncResponse> for proto::SyncResponse {
+    fn from(response: models::SyncResponse) -> Self {
+        Self {
+            changed: response.changed.into_iter().map(Into::into).collect(),
+            deleted: response.deleted,
+            server_time: response.server_time,
+            seq: response.seq,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Messages for MessagesService {
+    async fn list_messages(
+        &self,
+        request: Request<proto::ListMessagesRequest>,
+    ) -> Result<Response<proto::ListMessagesResponse>, Status> {
+        let auth = self.authenticate(&request).await?;
+        let query = models::MessagesQuery {
+            since: request.into_inner().since,
+            since_seq: None,
+        };
+
+        let Json(response) = handlers::get_messages(
+            State(self.state.clone()),
+            auth,
+            Query(query),
+            Pagination::default(),
+        )
+        .await
+        .map_err(handler_error)?;
+        Ok(Response::new(proto::ListMessagesResponse {
+            messages: response.messages.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn create_message(
+        &self,
+        request: Request<proto::CreateMessageRequest>,
+    ) -> Result<Response<proto::Message>, Status> {
+        let auth = self.authenticate(&request).await?;
+        let request = request.into_inner();
+        let payload = models::CreateMessageRequest {
+            content: request.content,
+            id: request.id.as_deref().map(parse_message_id).transpose()?,
+            template_id: request.template_id,
+            created_at: request.created_at,
+            publish_at: request.publish_at,
+        };
+
+        let (_, Json(message)) =
+            handlers::create_message(State(self.state.clone()), auth, Json(payload))
+                .await
+                .map_err(handler_error)?;
+        Ok(Response::new(message.into()))
+    }
+
+    async fn update_message(
+        &self,
+        request: Request<proto::UpdateMessageRequest>,
+    ) -> Result<Response<proto::Message>, Status> {
+        let auth = self.authenticate(&request).await?;
+        let request = request.into_inner();
+        let id = parse_message_id(&request.id)?;
+        let payload = models::UpdateMessageRequest {
+            content: request.content,
+            updated_at: request.updated_at,
+        };
+
+        let Json(message) =
+            handlers::update_message(State(self.state.clone()), auth, Path(id), Json(payload))
+                .await
+                .map_err(handler_error)?;
+        Ok(Response::new(message.into()))
+    }
+
+    async fn delete_message(
+        &self,
+        request: Request<proto::DeleteMessageRequest>,
+    ) -> Result<Response<proto::DeleteMessageResponse>, Status> {
+        let auth = self.authenticate(&request).await?;
+        let id = parse_message_id(&request.into_inner().id)?;
+
+        handlers::delete_message(State(self.state.clone()), auth, Path(id))
+            .await
+            .map_err(handler_error)?;
+        Ok(Response::new(proto::DeleteMessageResponse {}))
+    }
+
+    async fn sync(
+        &self,
+        request: Request<proto::SyncRequest>,
+    ) -> Result<Response<proto::SyncResponse>, Status> {
+        let auth = self.authenticate(&request).await?;
+        let request = request.into_inner();
+        let query = models::MessagesQuery {
+            since: request.since,
+            since_seq: request.since_seq,
+        };
+
+        let Json(response) = handlers::sync(State(self.state.clone()), auth, Query(query))
+            .await
+            .map_err(handler_error)?;
+        Ok(Response::new(response.into()))
+    }
+
+    async fn batch_sync(
+        &self,
+        request: Request<proto::BatchSyncRequest>,
+    ) -> Result<Response<proto::SyncResponse>, Status> {
+        let auth = self.authenticate(&request).await?;
+        let request = request.into_inner();
+        let payload = models::BatchSyncRequest {
+            since_seq: request.since_seq,
+            creates: request
+                .creates
+                .into_iter()
+                .map(|create| {
+                    Ok(models::SyncCreate {
+                        id: parse_message_id(&create.id)?,
+                        content: create.content,
+                        created_at: create.created_at,
+                    })
+                })
+                .collect::<Result<_, Status>>()?,
+            updates: request
+                .updates
+                .into_iter()
+                .map(|update| {
+                    Ok(models::SyncUpdate {
+                        id: parse_message_id(&update.id)?,
+                        content: update.content,
+                        updated_at: update.updated_at,
+                    })
+                })
+                .collect::<Result<_, Status>>()?,
+            deletes: request
+                .deletes
+                .iter()
+                .map(|id| parse_message_id(id))
+                .collect::<Result<_, _>>()?,
+        };
+
+        let Json(response) = handlers::batch_sync(State(self.state.clone()), auth, Json(payload))
+            .await
+            .map_err(handler_error)?;
+        Ok(Response::new(response.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{auth::create_token, storage::Storage, testing::test_app_state};
+    use std::sync::Arc;
+
+    async fn service() -> MessagesService {
+        let state = Arc::new(test_app_state().await);
+        MessagesService { state }
+    }
+
+    fn authorized<T>(message: T, user_id: &UserId) -> Request<T> {
+        let token = create_token(user_id, "test-secret").unwrap();
+        let mut request = Request::new(message);
+        request.metadata_mut().insert(
+            "authorization",
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        request
+    }
+
+    #[tokio::test]
+    async fn test_requires_token() {
+        let service = service().await;
+
+        let status = service
+            .list_messages(Request::new(proto::ListMessagesRequest { since: None }))
+            .await
+            .unwrap_err();
+
+        assert_eq!(status.code(), Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn test_create_list_and_sync() {
+        let service = service().await;
+        let user = models::User::new(
+            "grpc@example.com".to_string(),
+            "testuser".to_string(),
+            "hash".to_string(),
+            "salt".to_string(),
+        );
+        service.state.storage.create_user(&user).await.unwrap();
+
+        let created = service
+            .create_message(authorized(
+                proto::CreateMessageRequest {
+                    content: "Sent over gRPC".to_string(),
+                    ..Default::default()
+                },
+                &user.id,
+            ))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(created.content, "Sent over gRPC");
+
+        let listed = service
+            .list_messages(authorized(
+                proto::ListMessagesRequest { since: None },
+                &user.id,
+            ))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(listed.messages.len(), 1);
+
+        let missing = service
+            .delete_message(authorized(
+                proto::DeleteMessageRequest {
+                    id: MessageId::generate().to_string(),
+                },
+                &user.id,
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(missing.code(), Code::NotFound);
+
+        let malformed = service
+            .delete_message(authorized(
+                proto::DeleteMessageRequest {
+                    id: "missing".to_string(),
+                },
+                &user.id,
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(malformed.code(), Code::InvalidArgument);
+
+        let synced = service
+            .sync(authorized(
+                proto::SyncRequest {
+                    since: None,
+                    since_seq: Some(0),
+                },
+                &user.id,
+            ))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(synced.changed.len(), 1);
+        assert_eq!(synced.seq, created.seq);
+    }
+}
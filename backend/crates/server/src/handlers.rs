@@ -0,0 +1,2772 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, NaiveTime, Utc};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+use crate::{
+    audit,
+    auth::{JwtAuth, SharedAuthProvider},
+    backup::BackupStatus,
+    cache::UserCache,
+    config::Config,
+    db::{ConflictPolicy, DbError},
+    destinations::S3Destination,
+    dormancy::ActivityTracker,
+    error::AppError,
+    export_jobs::ExportJobs,
+    features::{Feature, Features},
+    ids::{MessageId, UserId},
+    inbound_email::InboundEmail,
+    mailer::Mailer,
+    maintenance::{CheckpointStatus, MaintenanceMode},
+    models::*,
+    notifiers::{self, Notification, Notifiers},
+    outbound::OutboundPolicy,
+    pagination::{PageSpec, Pagination, SortOrder},
+    push::{notify_user, PushNotifier, PushPayload},
+    ratelimit::RateLimits,
+    realtime::{EventHub, MessageEvent},
+    storage::{SharedStorage, SyncBatch, SyncDelta, SyncEdit},
+    telegram::TelegramBot,
+    utils::{hash_password, parse_timestamp},
+    validation::{FieldError, Validate},
+    webhooks::{self, Webhooks},
+    AuthUser,
+};
+
+/// Application state shared across handlers
+pub struct AppState {
+    pub storage: SharedStorage,
+    /// Checks passwords and issues and checks tokens; `JwtAuth` unless an
+    /// embedding application sets its own
+    pub auth: SharedAuthProvider,
+    pub conflict_policy: ConflictPolicy,
+    pub events: EventHub,
+    /// Web Push sender, if VAPID keys are configured
+    pub push: Option<Arc<PushNotifier>>,
+    /// Email sender, if SMTP is configured
+    pub mailer: Option<Arc<Mailer>>,
+    /// Bucket exports and backups are copied to, if S3 is configured
+    pub s3: Option<Arc<S3Destination>>,
+    pub backups: BackupStatus,
+    pub users: UserCache,
+    /// Per-user limit on stored message content, in bytes
+    pub quota_bytes: Option<i64>,
+    /// Per-user limit on stored messages
+    pub max_messages: Option<i64>,
+    /// Per-user limit on messages created per UTC day
+    pub max_messages_per_day: Option<i64>,
+    /// Bearer token for `/api/admin` routes, which are disabled when unset
+    pub admin_token: Option<String>,
+    pub checkpoints: CheckpointStatus,
+    pub export_jobs: ExportJobs,
+    pub rate_limits: RateLimits,
+    /// Set by operators to answer API requests with 503 during maintenance
+    pub maintenance_mode: MaintenanceMode,
+    pub webhooks: Webhooks,
+    /// Per-instance toggles, such as whether sharing is allowed
+    pub features: Features,
+    /// Sends notifications to users' ntfy and Gotify channels
+    pub notifiers: Notifiers,
+    /// Telegram bot that saves messages as entries, if a bot token is set
+    pub telegram: Option<Arc<TelegramBot>>,
+    /// Domain and provider key for email-in, if configured
+    pub inbound_email: Option<InboundEmail>,
+    /// When users were last active, for their dormancy policies
+    pub activity: ActivityTracker,
+    /// Which addresses user-supplied URLs may point to
+    pub outbound: OutboundPolicy,
+}
+
+pub type SharedState = Arc<AppState>;
+
+impl AppState {
+    /// State for serving `storage` with the settings in `config`
+    ///
+    /// Nothing is read from the environment, so tests and applications
+    /// embedding the server can build state from a `Config` of their own.
+    pub fn new(config: &Config, storage: SharedStorage) -> Result<Self, String> {
+        let conflict_policy = match &config.sync.conflict_policy {
+            Some(policy) => policy.parse()?,
+            None => ConflictPolicy::default(),
+        };
+        let outbound = OutboundPolicy::from_config(&config.outbound);
+        let push = match (
+            &config.push.vapid_public_key,
+            &config.push.vapid_private_key,
+        ) {
+            (Some(public_key), Some(private_key)) => Some(Arc::new(
+                PushNotifier::new(public_key.clone(), private_key.clone(), outbound.clone())
+                    .map_err(|e| format!("Invalid VAPID keys: {}", e))?,
+            )),
+            _ => None,
+        };
+        let mailer =
+            Mailer::from_config(&config.smtp, config.server.public_url.as_deref())?.map(Arc::new);
+        let telegram = TelegramBot::from_config(&config.telegram)?.map(Arc::new);
+        let s3 = S3Destination::from_config(&config.s3)?.map(Arc::new);
+
+        Ok(Self {
+            storage,
+            auth: Arc::new(JwtAuth::new(config.jwt_secret()?)),
+            conflict_policy,
+            events: EventHub::default(),
+            push,
+            mailer,
+            s3,
+            backups: BackupStatus::default(),
+            users: UserCache::from_config(&config.cache),
+            quota_bytes: config.limits.quota_bytes,
+            max_messages: config.limits.max_messages,
+            max_messages_per_day: config.limits.max_messages_per_day,
+            admin_token: config.auth.admin_token.clone(),
+            checkpoints: CheckpointStatus::default(),
+            export_jobs: ExportJobs::from_config(&config.exports),
+            rate_limits: RateLimits::from_config(&config.limits),
+            maintenance_mode: MaintenanceMode::from_config(&config.maintenance),
+            webhooks: Webhooks::default(),
+            features: Features::from_config(&config.features),
+            notifiers: Notifiers::new(outbound.clone()),
+            telegram,
+            inbound_email: InboundEmail::from_config(&config.inbound_email),
+            activity: ActivityTracker::default(),
+            outbound,
+        })
+    }
+}
+
+/// Error response type
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+    /// Current usage, included when a write is rejected for exceeding a limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<UsageResponse>,
+    /// Stable identifier for errors clients handle specially, such as
+    /// `account_disabled`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    /// Each field that failed validation, when `code` is `validation_failed`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<Vec<FieldError>>,
+}
+
+impl ErrorResponse {
+    pub fn new(message: impl Into<String>) -> Json<ErrorResponse> {
+        Json(ErrorResponse {
+            error: message.into(),
+            usage: None,
+            code: None,
+            fields: None,
+        })
+    }
+}
+
+/// `code` of the 403 returned to a user whose account an admin disabled
+pub const ACCOUNT_DISABLED: &str = "account_disabled";
+
+/// Reject a request from a disabled account
+pub fn account_disabled() -> AppError {
+    AppError::Rejected {
+        status: StatusCode::FORBIDDEN,
+        message: "This account has been disabled".to_string(),
+        code: Some(ACCOUNT_DISABLED),
+        usage: None,
+    }
+}
+
+/// `code` of the 403 returned to a user who must change their password first
+pub const PASSWORD_RESET_REQUIRED: &str = "password_reset_required";
+
+/// Reject a request from a user who must change their password first
+pub fn password_reset_required() -> AppError {
+    AppError::Rejected {
+        status: StatusCode::FORBIDDEN,
+        message: "Please choose a new password".to_string(),
+        code: Some(PASSWORD_RESET_REQUIRED),
+        usage: None,
+    }
+}
+
+/// Map an unexpected storage error to a 500 with the given message,
+/// logging the error itself, which clients don't get to see
+///
+/// Errors about the request itself, such as a missing row, and a database
+/// that stayed locked through every retry keep their own status; see
+/// [`AppError::storage`].
+pub fn storage_error(e: DbError, message: &str) -> AppError {
+    AppError::storage(e, message)
+}
+
+/// `code` of the 413 returned to a user who has as many messages as allowed
+pub const MESSAGE_LIMIT_REACHED: &str = "message_limit_reached";
+
+/// `code` of the 429 returned to a user who has created as many messages
+/// today as allowed
+pub const DAILY_MESSAGE_LIMIT_REACHED: &str = "daily_message_limit_reached";
+
+/// Reject a write that would take the user past their storage quota
+///
+/// `added_bytes` is how much the write grows the user's stored content.
+/// Writes that don't grow it are always allowed, so a user over quota can
+/// still trim or delete messages.
+pub async fn ensure_quota(
+    state: &AppState,
+    user_id: &UserId,
+    added_bytes: i64,
+) -> Result<(), AppError> {
+    let Some(quota) = state.quota_bytes else {
+        return Ok(());
+    };
+    if added_bytes <= 0 {
+        return Ok(());
+    }
+
+    let used = state
+        .storage
+        .get_content_bytes(user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    if used + added_bytes > quota {
+        return Err(limit_reached(
+            state,
+            user_id,
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "Storage quota exceeded",
+            None,
+        )
+        .await);
+    }
+
+    Ok(())
+}
+
+/// Reject creating `new_messages` messages that would take the user past
+/// their message limits
+///
+/// The daily limit is only checked when `per_day` is set; imports restore
+/// old entries rather than write new ones, so they are only held to the
+/// total.
+pub async fn ensure_message_limits(
+    state: &AppState,
+    user_id: &UserId,
+    new_messages: i64,
+    per_day: bool,
+) -> Result<(), AppError> {
+    let max_per_day = state.max_messages_per_day.filter(|_| per_day);
+    if new_messages <= 0 || (state.max_messages.is_none() && max_per_day.is_none()) {
+        return Ok(());
+    }
+
+    let (count, today) = state
+        .storage
+        .get_message_counts(user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    if state
+        .max_messages
+        .is_some_and(|max| count + new_messages > max)
+    {
+        return Err(limit_reached(
+            state,
+            user_id,
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "Message limit reached",
+            Some(MESSAGE_LIMIT_REACHED),
+        )
+        .await);
+    }
+    if max_per_day.is_some_and(|max| today + new_messages > max) {
+        return Err(limit_reached(
+            state,
+            user_id,
+            StatusCode::TOO_MANY_REQUESTS,
+            "Daily message limit reached",
+            Some(DAILY_MESSAGE_LIMIT_REACHED),
+        )
+        .await);
+    }
+
+    Ok(())
+}
+
+/// A rejected write, with the user's usage so clients can show how far over
+/// they are
+async fn limit_reached(
+    state: &AppState,
+    user_id: &UserId,
+    status: StatusCode,
+    message: &str,
+    code: Option<&'static str>,
+) -> AppError {
+    match current_usage(state, user_id).await {
+        Ok(usage) => AppError::Rejected {
+            status,
+            message: message.to_string(),
+            code,
+            usage: Some(Box::new(usage)),
+        },
+        Err(error) => error,
+    }
+}
+
+/// The user's stored content and messages, with the configured limits
+async fn current_usage(state: &AppState, user_id: &UserId) -> Result<UsageResponse, AppError> {
+    let content_bytes = state
+        .storage
+        .get_content_bytes(user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+    let (message_count, messages_today) = state
+        .storage
+        .get_message_counts(user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+    let tomorrow = Utc::now().date_naive() + chrono::Days::new(1);
+
+    Ok(UsageResponse {
+        content_bytes,
+        quota_bytes: state.quota_bytes,
+        message_count,
+        max_messages: state.max_messages,
+        messages_today,
+        max_messages_per_day: state.max_messages_per_day,
+        day_resets_at: tomorrow.and_time(NaiveTime::MIN).and_utc().to_rfc3339(),
+    })
+}
+
+/// How many bytes replacing a message's content with `content` adds
+///
+/// Unknown messages count as zero, since the edit will be rejected anyway.
+async fn edit_growth(
+    state: &AppState,
+    message_id: &MessageId,
+    user_id: &UserId,
+    content: &str,
+) -> Result<i64, AppError> {
+    let existing = state
+        .storage
+        .get_message_by_id(message_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    Ok(match existing {
+        Some(message) if message.user_id == *user_id => {
+            content.len() as i64 - message.content.len() as i64
+        }
+        _ => 0,
+    })
+}
+
+// ============ Authentication Handlers ============
+
+/// GET /healthz
+/// Liveness probe: answers as long as the process is serving requests
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "system",
+    responses(
+        (status = 200, description = "Process is up", body = String),
+    )
+)]
+pub async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// GET /readyz
+/// Readiness probe: checks the database answers and is fully migrated
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "system",
+    responses(
+        (status = 200, description = "Ready to serve traffic", body = ReadinessResponse),
+        (status = 503, description = "Database unreachable or migrations pending", body = ErrorResponse),
+    )
+)]
+pub async fn readyz(State(state): State<SharedState>) -> Result<Json<ReadinessResponse>, AppError> {
+    let pending = async {
+        state.storage.ping().await?;
+        state.storage.pending_migrations().await
+    }
+    .await
+    .map_err(|e| {
+        tracing::warn!("Readiness check failed: {}", e);
+        AppError::new(StatusCode::SERVICE_UNAVAILABLE, "Database unavailable")
+    })?;
+
+    if pending > 0 {
+        return Err(AppError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("{} pending migrations", pending),
+        ));
+    }
+
+    Ok(Json(ReadinessResponse {
+        status: "ready".to_string(),
+    }))
+}
+
+/// GET /api/health
+/// Report that the server is up, with the last backup time when enabled
+#[utoipa::path(
+    get,
+    path = "/api/health",
+    tag = "system",
+    responses(
+        (status = 200, description = "Server is up", body = HealthResponse),
+    )
+)]
+pub async fn health(State(state): State<SharedState>) -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: "ok".to_string(),
+        last_backup_at: state.backups.last_success(),
+    })
+}
+
+/// POST /api/login
+/// Authenticate user and return JWT token
+#[utoipa::path(
+    post,
+    path = "/api/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Token for the `Authorization: Bearer` header", body = LoginResponse),
+        (status = 401, description = "Invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Account disabled, with code `account_disabled`", body = ErrorResponse),
+    )
+)]
+pub async fn login(
+    State(state): State<SharedState>,
+    Json(payload): Json<LoginRequest>,
+) -> Result<Json<LoginResponse>, AppError> {
+    // Find user by email
+    let user = state
+        .users
+        .find_by_email(&*state.storage, &payload.email)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?
+        .ok_or_else(|| AppError::new(StatusCode::UNAUTHORIZED, "Invalid email or password"))?;
+
+    // Verify password
+    let is_valid = state
+        .auth
+        .verify_credentials(&user, &payload.password)
+        .await?;
+
+    if !is_valid {
+        return Err(AppError::new(
+            StatusCode::UNAUTHORIZED,
+            "Invalid email or password",
+        ));
+    }
+
+    // Only told to someone who knows the password
+    if user.disabled {
+        return Err(account_disabled());
+    }
+    state.activity.record(&*state.storage, &user.id).await;
+
+    let token = state.auth.issue_token(&user.id).await?;
+
+    Ok(Json(LoginResponse {
+        token,
+        user: user.to_public(),
+        must_reset_password: user.must_reset_password,
+    }))
+}
+
+/// POST /api/register
+/// Create an account and log in, while registration is open
+#[utoipa::path(
+    post,
+    path = "/api/register",
+    tag = "auth",
+    request_body = RegisterRequest,
+    responses(
+        (status = 201, description = "Account created, with a token for the `Authorization: Bearer` header", body = LoginResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 403, description = "Registration closed, with code `feature_disabled`", body = ErrorResponse),
+        (status = 409, description = "Email already exists", body = ErrorResponse),
+    )
+)]
+pub async fn register(
+    State(state): State<SharedState>,
+    Json(payload): Json<RegisterRequest>,
+) -> Result<(StatusCode, Json<LoginResponse>), AppError> {
+    state.features.require(Feature::Registration)?;
+    payload.validate()?;
+
+    let (hash, salt) = hash_password(&payload.password)?;
+    let user = User::new(payload.email, payload.username, hash, salt);
+    state
+        .storage
+        .create_user(&user)
+        .await
+        .map_err(|e| match e {
+            DbError::EmailAlreadyExists => {
+                AppError::new(StatusCode::CONFLICT, "Email already exists")
+            }
+            _ => storage_error(e, "Failed to create user"),
+        })?;
+    tracing::info!("User {} registered", user.id);
+
+    let token = state.auth.issue_token(&user.id).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(LoginResponse {
+            token,
+            user: user.to_public(),
+            must_reset_password: false,
+        }),
+    ))
+}
+
+// ============ Message Handlers ============
+
+/// Messages come newest first and all at once unless `limit` is given, and
+/// can be sorted by `created_at` or `updated_at`
+pub struct MessagePages;
+
+impl PageSpec for MessagePages {
+    const DEFAULT_LIMIT: Option<u32> = None;
+    const MAX_LIMIT: u32 = 1000;
+    const SORTS: &'static [&'static str] = &["created_at", "updated_at"];
+    const DEFAULT_ORDER: SortOrder = SortOrder::Desc;
+    const CURSOR: bool = false;
+}
+
+/// GET /api/messages
+/// Get the authenticated user's messages, a page at a time if asked
+pub async fn get_messages(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Query(query): Query<MessagesQuery>,
+    pagination: Pagination<MessagePages>,
+) -> Result<Json<MessagesResponse>, AppError> {
+    let since = parse_optional_timestamp(query.since.as_deref(), "since")?;
+
+    let messages = state
+        .storage
+        .get_messages_for_user_page(&user_id, since, &pagination.sorted_page())
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    let message_responses = with_reactions(&state, &user_id, &messages).await?;
+
+    Ok(Json(MessagesResponse {
+        messages: message_responses,
+    }))
+}
+
+/// GET /api/sync
+/// Get messages changed and IDs deleted since the given cursor
+///
+/// Clients should prefer the integer `since_seq` cursor; the `since`
+/// timestamp is kept for older clients.
+#[utoipa::path(
+    get,
+    path = "/api/sync",
+    tag = "sync",
+    params(MessagesQuery),
+    responses(
+        (status = 200, description = "Changes since the cursor", body = SyncResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn sync(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Query(query): Query<MessagesQuery>,
+) -> Result<Json<SyncResponse>, AppError> {
+    // Captured before querying so nothing written meanwhile is skipped next time
+    let server_time = chrono::Utc::now().to_rfc3339();
+    let since = parse_optional_timestamp(query.since.as_deref(), "since")?;
+
+    let db_error = |e| storage_error(e, "Database error");
+
+    let seq = state
+        .storage
+        .get_change_seq(&user_id)
+        .await
+        .map_err(db_error)?;
+
+    let (changed, deleted) = match (query.since_seq, since) {
+        (Some(since_seq), _) => (
+            state
+                .storage
+                .get_messages_since_seq(&user_id, since_seq)
+                .await
+                .map_err(db_error)?,
+            state
+                .storage
+                .get_deleted_message_ids_since_seq(&user_id, since_seq)
+                .await
+                .map_err(db_error)?,
+        ),
+        (None, Some(since)) => (
+            state
+                .storage
+                .get_messages_for_user(&user_id, Some(since))
+                .await
+                .map_err(db_error)?,
+            state
+                .storage
+                .get_deleted_message_ids(&user_id, since)
+                .await
+                .map_err(db_error)?,
+        ),
+        (None, None) => (
+            state
+                .storage
+                .get_messages_for_user(&user_id, None)
+                .await
+                .map_err(db_error)?,
+            Vec::new(),
+        ),
+    };
+
+    Ok(Json(SyncResponse {
+        changed: with_reactions(&state, &user_id, &changed).await?,
+        deleted: deleted.into_iter().map(String::from).collect(),
+        server_time,
+        seq,
+    }))
+}
+
+/// POST /api/sync
+/// Apply queued client changes and return the server-side delta, atomically
+#[utoipa::path(
+    post,
+    path = "/api/sync",
+    tag = "sync",
+    request_body = BatchSyncRequest,
+    responses(
+        (status = 200, description = "Changes since `since_seq`, after applying the batch", body = SyncResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 413, description = "Storage quota or message limit exceeded", body = ErrorResponse),
+        (status = 429, description = "Daily message limit reached", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn batch_sync(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Json(payload): Json<BatchSyncRequest>,
+) -> Result<Json<SyncResponse>, AppError> {
+    let server_time = chrono::Utc::now().to_rfc3339();
+
+    let db_error = |e| storage_error(e, "Database error");
+
+    payload.validate()?;
+
+    let mut creates = Vec::with_capacity(payload.creates.len());
+    for create in payload.creates {
+        let mut message = Message::with_id(create.id, user_id.clone(), create.content);
+        if let Some(created_at) =
+            parse_optional_timestamp(create.created_at.as_deref(), "created_at")?
+        {
+            message.created_at = created_at;
+        }
+        creates.push(message);
+    }
+
+    let mut updates = Vec::with_capacity(payload.updates.len());
+    for update in payload.updates {
+        updates.push(SyncEdit {
+            base_updated_at: parse_optional_timestamp(update.updated_at.as_deref(), "updated_at")?,
+            id: update.id,
+            content: update.content,
+        });
+    }
+
+    let mut growth: i64 = creates.iter().map(|m| m.content.len() as i64).sum();
+    for update in &updates {
+        growth += edit_growth(&state, &update.id, &user_id, &update.content).await?;
+    }
+    ensure_quota(&state, &user_id, growth).await?;
+    ensure_message_limits(&state, &user_id, creates.len() as i64, true).await?;
+
+    let delete_count = payload.deletes.len();
+    let batch = SyncBatch {
+        creates,
+        updates,
+        deletes: payload.deletes,
+        since_seq: payload.since_seq.unwrap_or(0),
+    };
+    let SyncDelta {
+        changed,
+        deleted,
+        seq,
+    } = state
+        .storage
+        .apply_sync_batch(&user_id, batch, state.conflict_policy)
+        .await
+        .map_err(|e| match e {
+            DbError::MessageIdConflict => {
+                AppError::new(StatusCode::CONFLICT, "Message ID already in use")
+            }
+            other => db_error(other),
+        })?;
+
+    state.events.publish(&user_id, MessageEvent::Resync { seq });
+    if delete_count > 0 {
+        audit::record(
+            &*state.storage,
+            AuditEvent::new("messages.delete_batch", user_id.as_str())
+                .with_target("user", user_id.as_str())
+                .with_metadata(serde_json::json!({ "count": delete_count })),
+        )
+        .await;
+    }
+
+    Ok(Json(SyncResponse {
+        changed: with_reactions(&state, &user_id, &changed).await?,
+        deleted: deleted.into_iter().map(String::from).collect(),
+        server_time,
+        seq,
+    }))
+}
+
+/// GET /api/messages (conditional)
+/// Wraps `get_messages` with an ETag, answering 304 Not Modified when the
+/// client's `If-None-Match` still matches
+#[utoipa::path(
+    get,
+    path = "/api/messages",
+    tag = "messages",
+    params(MessagesQuery, PageQuery),
+    responses(
+        (status = 200, description = "Messages, newest first by default", body = MessagesResponse),
+        (status = 304, description = "Unchanged since the `If-None-Match` ETag"),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_messages_cached(
+    State(state): State<SharedState>,
+    auth: AuthUser,
+    headers: HeaderMap,
+    Query(query): Query<MessagesQuery>,
+    pagination: Pagination<MessagePages>,
+) -> Result<Response, AppError> {
+    let fingerprint = state
+        .storage
+        .get_messages_fingerprint(&auth.id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+    let since = query.since.as_deref().unwrap_or("");
+    let etag = format!("W/\"{}-{}-{}\"", fingerprint, since, pagination);
+
+    let matches = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|h| h.to_str().ok())
+        .map(|value| {
+            value
+                .split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        })
+        .unwrap_or(false);
+
+    let mut response = if matches {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        get_messages(State(state), auth, Query(query), pagination)
+            .await?
+            .into_response()
+    };
+
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+
+    Ok(response)
+}
+
+/// Parse an optional RFC 3339 timestamp from a request, rejecting bad input
+pub fn parse_optional_timestamp(
+    value: Option<&str>,
+    field: &str,
+) -> Result<Option<DateTime<Utc>>, AppError> {
+    value.map(parse_timestamp).transpose().map_err(|_| {
+        AppError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid {} timestamp", field),
+        )
+    })
+}
+
+/// A message ID that is the same every time `key` is, so an entry delivered
+/// again by an integration is recognised instead of saved twice
+pub fn derived_message_id(key: &str) -> MessageId {
+    let digest = Sha256::digest(key.as_bytes());
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    MessageId::from(uuid::Builder::from_custom_bytes(bytes).into_uuid())
+}
+
+/// Convert messages from an owner's journal to responses with reaction counts attached
+async fn with_reactions(
+    state: &SharedState,
+    owner_id: &UserId,
+    messages: &[Message],
+) -> Result<Vec<MessageResponse>, AppError> {
+    let mut counts = state
+        .storage
+        .get_reaction_counts(owner_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    Ok(messages
+        .iter()
+        .map(|m| {
+            let mut response = m.to_response();
+            response.reactions = counts.remove(&m.id).unwrap_or_default();
+            response
+        })
+        .collect())
+}
+
+/// POST /api/messages
+/// Create a new message
+#[utoipa::path(
+    post,
+    path = "/api/messages",
+    tag = "messages",
+    request_body = CreateMessageRequest,
+    responses(
+        (status = 201, description = "Created message", body = MessageResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 409, description = "Message ID already in use", body = ErrorResponse),
+        (status = 413, description = "Storage quota or message limit exceeded", body = ErrorResponse),
+        (status = 429, description = "Daily message limit reached", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn create_message(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Json(payload): Json<CreateMessageRequest>,
+) -> Result<(StatusCode, Json<MessageResponse>), AppError> {
+    let (status, message) = save_message(&state, user_id, payload).await?;
+    Ok((status, Json(message)))
+}
+
+/// Save a new message for `user_id`, as [`create_message`] does
+///
+/// For entries that arrive some other way than the API, such as by email or
+/// through a chat bot, where there's no signed-in user.
+pub async fn save_message(
+    state: &SharedState,
+    user_id: UserId,
+    mut payload: CreateMessageRequest,
+) -> Result<(StatusCode, MessageResponse), AppError> {
+    // Pre-fill content from a template when none was provided
+    if let Some(template_id) = payload.template_id.as_deref() {
+        if payload.content.trim().is_empty() {
+            let template = state
+                .storage
+                .get_template_for_user(template_id, &user_id)
+                .await
+                .map_err(|e| storage_error(e, "Database error"))?
+                .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "Template not found"))?;
+            payload.content = template.content;
+        }
+    }
+    payload.validate()?;
+
+    // Validate optional backdating and scheduling timestamps
+    let created_at = parse_optional_timestamp(payload.created_at.as_deref(), "created_at")?;
+    let publish_at = parse_optional_timestamp(payload.publish_at.as_deref(), "publish_at")?;
+
+    // Create message (with optional client-provided ID)
+    let mut message = if let Some(id) = payload.id {
+        Message::with_id(id, user_id, payload.content)
+    } else {
+        Message::new(user_id, payload.content)
+    };
+    if let Some(created_at) = created_at {
+        message.created_at = created_at;
+    }
+    message.publish_at = publish_at;
+
+    ensure_quota(state, &message.user_id, message.content.len() as i64).await?;
+    ensure_message_limits(state, &message.user_id, 1, true).await?;
+
+    // Retried creates with the same client ID return the existing row
+    let (stored, created) = state
+        .storage
+        .upsert_message(&message)
+        .await
+        .map_err(|e| match e {
+            DbError::MessageIdConflict => {
+                AppError::new(StatusCode::CONFLICT, "Message ID already in use")
+            }
+            _ => storage_error(e, "Failed to create message"),
+        })?;
+
+    let status = if created {
+        let event = MessageEvent::Created {
+            message: stored.to_response(),
+        };
+        webhooks::dispatch(state, &stored.user_id, &event).await;
+        state.events.publish(&stored.user_id, event);
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+
+    Ok((status, stored.to_response()))
+}
+
+/// PUT /api/messages/:id
+/// Update a message
+#[utoipa::path(
+    put,
+    path = "/api/messages/{id}",
+    tag = "messages",
+    params(("id" = String, Path, description = "Message ID")),
+    request_body = UpdateMessageRequest,
+    responses(
+        (status = 200, description = "Updated message", body = MessageResponse),
+        (status = 404, description = "Message not found", body = ErrorResponse),
+        (status = 409, description = "Edited elsewhere since `updated_at`", body = ErrorResponse),
+        (status = 413, description = "Storage quota exceeded", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn update_message(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Path(message_id): Path<MessageId>,
+    Json(payload): Json<UpdateMessageRequest>,
+) -> Result<Json<MessageResponse>, AppError> {
+    payload.validate()?;
+    let base_updated_at = parse_optional_timestamp(payload.updated_at.as_deref(), "updated_at")?;
+
+    let growth = edit_growth(&state, &message_id, &user_id, &payload.content).await?;
+    ensure_quota(&state, &user_id, growth).await?;
+
+    let updated = state
+        .storage
+        .update_message(
+            &message_id,
+            &user_id,
+            &payload.content,
+            base_updated_at,
+            state.conflict_policy,
+        )
+        .await
+        .map_err(|e| match e {
+            DbError::MessageNotFound => AppError::new(StatusCode::NOT_FOUND, "Message not found"),
+            _ => storage_error(e, "Failed to update message"),
+        })?;
+
+    // A stale edit under the conflict-copy policy produces a new message
+    let event = if updated.conflict_of.is_some() {
+        MessageEvent::Created {
+            message: updated.to_response(),
+        }
+    } else {
+        MessageEvent::Updated {
+            message: updated.to_response(),
+        }
+    };
+    webhooks::dispatch(&state, &user_id, &event).await;
+    state.events.publish(&user_id, event);
+
+    Ok(Json(updated.to_response()))
+}
+
+/// DELETE /api/messages/:id
+/// Delete a message
+#[utoipa::path(
+    delete,
+    path = "/api/messages/{id}",
+    tag = "messages",
+    params(("id" = String, Path, description = "Message ID")),
+    responses(
+        (status = 200, description = "Message deleted", body = SuccessResponse),
+        (status = 404, description = "Message not found", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn delete_message(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Path(message_id): Path<MessageId>,
+) -> Result<Json<SuccessResponse>, AppError> {
+    state
+        .storage
+        .delete_message(&message_id, &user_id)
+        .await
+        .map_err(|e| match e {
+            DbError::MessageNotFound => AppError::new(StatusCode::NOT_FOUND, "Message not found"),
+            _ => storage_error(e, "Failed to delete message"),
+        })?;
+
+    let event = MessageEvent::Deleted {
+        id: message_id.into(),
+    };
+    webhooks::dispatch(&state, &user_id, &event).await;
+    state.events.publish(&user_id, event);
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+// ============ Template Handlers ============
+
+/// GET /api/templates
+/// Get all templates for authenticated user
+#[utoipa::path(
+    get,
+    path = "/api/templates",
+    tag = "templates",
+    responses(
+        (status = 200, description = "Templates", body = TemplatesResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_templates(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<TemplatesResponse>, AppError> {
+    let templates = state
+        .storage
+        .get_templates_for_user(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    Ok(Json(TemplatesResponse {
+        templates: templates.iter().map(|t| t.to_response()).collect(),
+    }))
+}
+
+/// POST /api/templates
+/// Create a new template
+#[utoipa::path(
+    post,
+    path = "/api/templates",
+    tag = "templates",
+    request_body = CreateTemplateRequest,
+    responses(
+        (status = 201, description = "Created template", body = TemplateResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn create_template(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Json(payload): Json<CreateTemplateRequest>,
+) -> Result<(StatusCode, Json<TemplateResponse>), AppError> {
+    payload.validate()?;
+
+    let template = Template::new(user_id, payload.name, payload.content);
+
+    let created = state
+        .storage
+        .create_template(&template)
+        .await
+        .map_err(|e| storage_error(e, "Failed to create template"))?;
+
+    Ok((StatusCode::CREATED, Json(created.to_response())))
+}
+
+/// PUT /api/templates/:id
+/// Update a template
+#[utoipa::path(
+    put,
+    path = "/api/templates/{id}",
+    tag = "templates",
+    params(("id" = String, Path, description = "Template ID")),
+    request_body = UpdateTemplateRequest,
+    responses(
+        (status = 200, description = "Updated template", body = TemplateResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 404, description = "Template not found", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn update_template(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Path(template_id): Path<String>,
+    Json(payload): Json<UpdateTemplateRequest>,
+) -> Result<Json<TemplateResponse>, AppError> {
+    payload.validate()?;
+
+    let updated = state
+        .storage
+        .update_template(&template_id, &user_id, &payload.name, &payload.content)
+        .await
+        .map_err(|e| match e {
+            DbError::TemplateNotFound => AppError::new(StatusCode::NOT_FOUND, "Template not found"),
+            _ => storage_error(e, "Failed to update template"),
+        })?;
+
+    Ok(Json(updated.to_response()))
+}
+
+/// DELETE /api/templates/:id
+/// Delete a template
+#[utoipa::path(
+    delete,
+    path = "/api/templates/{id}",
+    tag = "templates",
+    params(("id" = String, Path, description = "Template ID")),
+    responses(
+        (status = 200, description = "Template deleted", body = SuccessResponse),
+        (status = 404, description = "Template not found", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn delete_template(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Path(template_id): Path<String>,
+) -> Result<Json<SuccessResponse>, AppError> {
+    state
+        .storage
+        .delete_template(&template_id, &user_id)
+        .await
+        .map_err(|e| match e {
+            DbError::TemplateNotFound => AppError::new(StatusCode::NOT_FOUND, "Template not found"),
+            _ => storage_error(e, "Failed to delete template"),
+        })?;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+// ============ Shared Journal Handlers ============
+
+/// GET /api/journal/members
+/// List accounts the authenticated user has shared their journal with
+#[utoipa::path(
+    get,
+    path = "/api/journal/members",
+    tag = "journals",
+    responses(
+        (status = 200, description = "Members of your journal", body = JournalMembersResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn list_journal_members(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<JournalMembersResponse>, AppError> {
+    let members = state
+        .storage
+        .list_journal_members(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    Ok(Json(JournalMembersResponse { members }))
+}
+
+/// POST /api/journal/members
+/// Invite another account to read or write the authenticated user's journal
+#[utoipa::path(
+    post,
+    path = "/api/journal/members",
+    tag = "journals",
+    request_body = InviteMemberRequest,
+    responses(
+        (status = 201, description = "Member added", body = SuccessResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 403, description = "Sharing disabled, with code `feature_disabled`", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn invite_journal_member(
+    State(state): State<SharedState>,
+    auth: AuthUser,
+    Json(payload): Json<InviteMemberRequest>,
+) -> Result<(StatusCode, Json<SuccessResponse>), AppError> {
+    state.features.require(Feature::Sharing)?;
+    payload.validate()?;
+
+    let member = state
+        .users
+        .find_by_email(&*state.storage, &payload.email)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "User not found"))?;
+
+    if member.id == auth.id {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Cannot invite yourself",
+        ));
+    }
+
+    state
+        .storage
+        .add_journal_member(&auth.id, &member.id, &payload.role)
+        .await
+        .map_err(|e| storage_error(e, "Failed to invite member"))?;
+
+    if let Ok(owner) = auth.user(&state).await {
+        let title = "Journal shared with you".to_string();
+        let body = format!("{} shared their journal with you", owner.shown_name());
+        notify_user(
+            &state,
+            &member.id,
+            PushPayload {
+                title: title.clone(),
+                body: body.clone(),
+                url: None,
+            },
+        );
+        notifiers::notify_user(
+            &state,
+            &member.id,
+            Notification {
+                title,
+                body,
+                url: None,
+            },
+        );
+    }
+
+    Ok((StatusCode::CREATED, Json(SuccessResponse::new())))
+}
+
+/// DELETE /api/journal/members/:member_id
+/// Revoke a member's access to the authenticated user's journal
+#[utoipa::path(
+    delete,
+    path = "/api/journal/members/{member_id}",
+    tag = "journals",
+    params(("member_id" = String, Path, description = "User ID of the member")),
+    responses(
+        (status = 200, description = "Member removed", body = SuccessResponse),
+        (status = 404, description = "Journal member not found", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn remove_journal_member(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Path(member_id): Path<UserId>,
+) -> Result<Json<SuccessResponse>, AppError> {
+    state
+        .storage
+        .remove_journal_member(&user_id, &member_id)
+        .await
+        .map_err(|e| match e {
+            DbError::MemberNotFound => {
+                AppError::new(StatusCode::NOT_FOUND, "Journal member not found")
+            }
+            _ => storage_error(e, "Failed to remove member"),
+        })?;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+/// GET /api/journals
+/// List journals shared with the authenticated user
+#[utoipa::path(
+    get,
+    path = "/api/journals",
+    tag = "journals",
+    responses(
+        (status = 200, description = "Journals shared with you", body = SharedJournalsResponse),
+        (status = 403, description = "Sharing disabled, with code `feature_disabled`", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn list_shared_journals(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<SharedJournalsResponse>, AppError> {
+    state.features.require(Feature::Sharing)?;
+
+    let journals = state
+        .storage
+        .list_shared_journals(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    Ok(Json(SharedJournalsResponse { journals }))
+}
+
+/// GET /api/journals/:owner_id/messages
+/// Get messages from a journal shared with the authenticated user
+#[utoipa::path(
+    get,
+    path = "/api/journals/{owner_id}/messages",
+    tag = "journals",
+    params(
+        ("owner_id" = String, Path, description = "User ID of the journal owner"),
+        MessagesQuery
+    ),
+    responses(
+        (status = 200, description = "Messages in the journal, newest first", body = MessagesResponse),
+        (status = 403, description = "Journal access denied, or sharing disabled with code `feature_disabled`", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_journal_messages(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Path(owner_id): Path<UserId>,
+    Query(query): Query<MessagesQuery>,
+) -> Result<Json<MessagesResponse>, AppError> {
+    state.features.require(Feature::Sharing)?;
+    let since = parse_optional_timestamp(query.since.as_deref(), "since")?;
+
+    let messages = state
+        .storage
+        .get_journal_messages(&owner_id, &user_id, since)
+        .await
+        .map_err(|e| match e {
+            DbError::JournalAccessDenied => {
+                AppError::new(StatusCode::FORBIDDEN, "Journal access denied")
+            }
+            _ => storage_error(e, "Database error"),
+        })?;
+
+    Ok(Json(MessagesResponse {
+        messages: with_reactions(&state, &owner_id, &messages).await?,
+    }))
+}
+
+/// POST /api/journals/:owner_id/messages
+/// Post a message to a journal the authenticated user has write access to
+#[utoipa::path(
+    post,
+    path = "/api/journals/{owner_id}/messages",
+    tag = "journals",
+    params(("owner_id" = String, Path, description = "User ID of the journal owner")),
+    request_body = CreateMessageRequest,
+    responses(
+        (status = 201, description = "Created message", body = MessageResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 403, description = "Journal access denied, or sharing disabled with code `feature_disabled`", body = ErrorResponse),
+        (status = 413, description = "Storage quota or message limit exceeded", body = ErrorResponse),
+        (status = 429, description = "Daily message limit reached", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn create_journal_message(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Path(owner_id): Path<UserId>,
+    Json(payload): Json<CreateMessageRequest>,
+) -> Result<(StatusCode, Json<MessageResponse>), AppError> {
+    state.features.require(Feature::Sharing)?;
+    payload.validate()?;
+
+    let mut message = if let Some(id) = payload.id {
+        Message::with_id(id, owner_id, payload.content)
+    } else {
+        Message::new(owner_id, payload.content)
+    };
+    message.author_id = Some(user_id.clone());
+
+    // Entries count against the journal owner's quota and limits, but the
+    // owner's usage isn't shown to members
+    let hide_usage = |error: AppError| match error.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => {
+            AppError::new(StatusCode::PAYLOAD_TOO_LARGE, "Journal storage is full")
+        }
+        StatusCode::TOO_MANY_REQUESTS => AppError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "This journal can't take more entries today",
+        ),
+        _ => error,
+    };
+    ensure_quota(&state, &message.user_id, message.content.len() as i64)
+        .await
+        .map_err(hide_usage)?;
+    ensure_message_limits(&state, &message.user_id, 1, true)
+        .await
+        .map_err(hide_usage)?;
+
+    let created = state
+        .storage
+        .create_journal_message(&user_id, &message)
+        .await
+        .map_err(|e| match e {
+            DbError::JournalAccessDenied => {
+                AppError::new(StatusCode::FORBIDDEN, "Journal access denied")
+            }
+            _ => storage_error(e, "Failed to create message"),
+        })?;
+
+    let event = MessageEvent::Created {
+        message: created.to_response(),
+    };
+    webhooks::dispatch(&state, &created.user_id, &event).await;
+    state.events.publish(&created.user_id, event);
+
+    Ok((StatusCode::CREATED, Json(created.to_response())))
+}
+
+// ============ Reaction Handlers ============
+
+fn reaction_error(e: DbError) -> AppError {
+    match e {
+        DbError::MessageNotFound => AppError::new(StatusCode::NOT_FOUND, "Message not found"),
+        _ => storage_error(e, "Failed to update reaction"),
+    }
+}
+
+/// POST /api/messages/:id/reactions
+/// React to an own or shared message
+#[utoipa::path(
+    post,
+    path = "/api/messages/{id}/reactions",
+    tag = "messages",
+    params(("id" = String, Path, description = "Message ID")),
+    request_body = ReactionRequest,
+    responses(
+        (status = 200, description = "Reaction added", body = SuccessResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 404, description = "Message not found", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn add_reaction(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Path(message_id): Path<MessageId>,
+    Json(payload): Json<ReactionRequest>,
+) -> Result<Json<SuccessResponse>, AppError> {
+    payload.validate()?;
+
+    state
+        .storage
+        .add_reaction(&message_id, &user_id, &payload.emoji)
+        .await
+        .map_err(reaction_error)?;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+/// DELETE /api/messages/:id/reactions/:emoji
+/// Remove the authenticated user's reaction from a message
+#[utoipa::path(
+    delete,
+    path = "/api/messages/{id}/reactions/{emoji}",
+    tag = "messages",
+    params(
+        ("id" = String, Path, description = "Message ID"),
+        ("emoji" = String, Path, description = "Emoji to remove")
+    ),
+    responses(
+        (status = 200, description = "Reaction removed", body = SuccessResponse),
+        (status = 404, description = "Message not found", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn remove_reaction(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Path((message_id, emoji)): Path<(MessageId, String)>,
+) -> Result<Json<SuccessResponse>, AppError> {
+    state
+        .storage
+        .remove_reaction(&message_id, &user_id, &emoji)
+        .await
+        .map_err(reaction_error)?;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+// ============ User Management Handlers ============
+
+/// PUT /api/user/email
+/// Update user email
+#[utoipa::path(
+    put,
+    path = "/api/user/email",
+    tag = "user",
+    request_body = UpdateEmailRequest,
+    responses(
+        (status = 200, description = "Email changed", body = SuccessResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 409, description = "Email already exists", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn update_email(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Json(payload): Json<UpdateEmailRequest>,
+) -> Result<Json<SuccessResponse>, AppError> {
+    payload.validate()?;
+
+    state
+        .storage
+        .update_user_email(&user_id, &payload.email)
+        .await
+        .map_err(|e| match e {
+            DbError::EmailAlreadyExists => {
+                AppError::new(StatusCode::CONFLICT, "Email already exists")
+            }
+            _ => storage_error(e, "Failed to update email"),
+        })?;
+    state.users.invalidate(&user_id).await;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+/// PUT /api/user/username
+/// Update user username
+#[utoipa::path(
+    put,
+    path = "/api/user/username",
+    tag = "user",
+    request_body = UpdateUsernameRequest,
+    responses(
+        (status = 200, description = "Username changed", body = SuccessResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn update_username(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Json(payload): Json<UpdateUsernameRequest>,
+) -> Result<Json<SuccessResponse>, AppError> {
+    payload.validate()?;
+
+    state
+        .storage
+        .update_user_username(&user_id, &payload.username)
+        .await
+        .map_err(|e| storage_error(e, "Failed to update username"))?;
+    state.users.invalidate(&user_id).await;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+/// PUT /api/user/display-name
+/// Set the name shown to other users, or clear it to show the username
+#[utoipa::path(
+    put,
+    path = "/api/user/display-name",
+    tag = "user",
+    request_body = UpdateDisplayNameRequest,
+    responses(
+        (status = 200, description = "Display name changed", body = SuccessResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn update_display_name(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Json(payload): Json<UpdateDisplayNameRequest>,
+) -> Result<Json<SuccessResponse>, AppError> {
+    payload.validate()?;
+
+    let display_name = payload
+        .display_name
+        .as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty());
+    state
+        .storage
+        .update_user_display_name(&user_id, display_name)
+        .await
+        .map_err(|e| storage_error(e, "Failed to update display name"))?;
+    state.users.invalidate(&user_id).await;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+/// PUT /api/user/password
+/// Update user password
+#[utoipa::path(
+    put,
+    path = "/api/user/password",
+    tag = "user",
+    request_body = UpdatePasswordRequest,
+    responses(
+        (status = 200, description = "Password changed", body = SuccessResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Current password is wrong", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn update_password(
+    State(state): State<SharedState>,
+    auth: AuthUser,
+    Json(payload): Json<UpdatePasswordRequest>,
+) -> Result<Json<SuccessResponse>, AppError> {
+    payload.validate()?;
+    let user = auth.user(&state).await?;
+
+    // Verify current password
+    let is_valid = state
+        .auth
+        .verify_credentials(user, &payload.current_password)
+        .await?;
+
+    if !is_valid {
+        return Err(AppError::new(
+            StatusCode::UNAUTHORIZED,
+            "Invalid current password",
+        ));
+    }
+
+    // Hash new password
+    let (new_hash, new_salt) = hash_password(&payload.new_password)?;
+
+    // Update password
+    state
+        .storage
+        .update_user_password(&user.id, &new_hash, &new_salt)
+        .await
+        .map_err(|e| storage_error(e, "Failed to update password"))?;
+    state.users.invalidate(&user.id).await;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+/// GET /api/user/usage
+/// Report the storage and messages used by the user, and their limits
+#[utoipa::path(
+    get,
+    path = "/api/user/usage",
+    tag = "user",
+    responses(
+        (status = 200, description = "Storage and messages used, with limits", body = UsageResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_usage(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<UsageResponse>, AppError> {
+    Ok(Json(current_usage(&state, &user_id).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{auth_user, test_app_state},
+        utils::{hash_password, verify_password},
+    };
+
+    async fn setup_test_state() -> SharedState {
+        setup_test_state_with_quota(None).await
+    }
+
+    async fn setup_test_state_with_quota(quota_bytes: Option<i64>) -> SharedState {
+        Arc::new(AppState {
+            quota_bytes,
+            ..test_app_state().await
+        })
+    }
+
+    async fn setup_test_state_with_message_limits(
+        max_messages: Option<i64>,
+        max_messages_per_day: Option<i64>,
+    ) -> SharedState {
+        Arc::new(AppState {
+            max_messages,
+            max_messages_per_day,
+            ..test_app_state().await
+        })
+    }
+
+    async fn create_test_user(state: &SharedState, email: &str, password: &str) -> User {
+        let (hash, salt) = hash_password(password).unwrap();
+        let user = User::new(email.to_string(), "testuser".to_string(), hash, salt);
+        state.storage.create_user(&user).await.unwrap();
+        user
+    }
+
+    #[tokio::test]
+    async fn test_login_success() {
+        let state = setup_test_state().await;
+        create_test_user(&state, "login@example.com", "password123").await;
+
+        let request = LoginRequest {
+            email: "login@example.com".to_string(),
+            password: "password123".to_string(),
+        };
+
+        let result = login(State(state), Json(request)).await;
+
+        assert!(result.is_ok());
+        let response = result.unwrap().0;
+        assert!(!response.token.is_empty());
+        assert_eq!(response.user.email, "login@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_login_wrong_email() {
+        let state = setup_test_state().await;
+
+        let request = LoginRequest {
+            email: "nonexistent@example.com".to_string(),
+            password: "password123".to_string(),
+        };
+
+        let result = login(State(state), Json(request)).await;
+
+        assert!(result.is_err());
+        let status = result.unwrap_err().status();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_login_wrong_password() {
+        let state = setup_test_state().await;
+        create_test_user(&state, "wrongpw@example.com", "password123").await;
+
+        let request = LoginRequest {
+            email: "wrongpw@example.com".to_string(),
+            password: "wrongpassword".to_string(),
+        };
+
+        let result = login(State(state), Json(request)).await;
+
+        assert!(result.is_err());
+        let status = result.unwrap_err().status();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_get_messages_empty() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "empty@example.com", "password123").await;
+
+        let result = get_messages(
+            State(state),
+            auth_user(&user.id),
+            Query(MessagesQuery::default()),
+            Pagination::default(),
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert!(result.unwrap().0.messages.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_messages_cached_not_modified() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "etag@example.com", "password123").await;
+
+        let first = get_messages_cached(
+            State(state.clone()),
+            auth_user(&user.id),
+            HeaderMap::new(),
+            Query(MessagesQuery::default()),
+            Pagination::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        let etag = first.headers().get(header::ETAG).unwrap().clone();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag.clone());
+        let second = get_messages_cached(
+            State(state.clone()),
+            auth_user(&user.id),
+            headers.clone(),
+            Query(MessagesQuery::default()),
+            Pagination::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(second.status(), StatusCode::NOT_MODIFIED);
+
+        // A new message invalidates the tag
+        let message = Message::new(user.id.clone(), "Changed".to_string());
+        state.storage.create_message(&message).await.unwrap();
+        let third = get_messages_cached(
+            State(state),
+            auth_user(&user.id),
+            headers,
+            Query(MessagesQuery::default()),
+            Pagination::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(third.status(), StatusCode::OK);
+        assert_ne!(third.headers().get(header::ETAG).unwrap(), &etag);
+    }
+
+    #[tokio::test]
+    async fn test_message_changes_publish_events() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "events@example.com", "password123").await;
+        let mut events = state.events.subscribe(&user.id);
+
+        let request = CreateMessageRequest {
+            content: "Live".to_string(),
+            id: None,
+            template_id: None,
+            created_at: None,
+            publish_at: None,
+        };
+        let (_, Json(created)) =
+            create_message(State(state.clone()), auth_user(&user.id), Json(request))
+                .await
+                .unwrap();
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            MessageEvent::Created { message } if message.id == created.id
+        ));
+
+        let _ = delete_message(
+            State(state),
+            auth_user(&user.id),
+            Path(created.id.parse().unwrap()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            events.recv().await.unwrap(),
+            MessageEvent::Deleted { id: created.id }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_message_success() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "create@example.com", "password123").await;
+
+        let request = CreateMessageRequest {
+            content: "Hello, world!".to_string(),
+            id: None,
+            template_id: None,
+            created_at: None,
+            publish_at: None,
+        };
+
+        let result = create_message(State(state), auth_user(&user.id), Json(request)).await;
+
+        assert!(result.is_ok());
+        let (status, response) = result.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(response.0.content, "Hello, world!");
+    }
+
+    #[tokio::test]
+    async fn test_create_message_with_client_id() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "clientid@example.com", "password123").await;
+
+        let client_id = MessageId::generate();
+        let request = CreateMessageRequest {
+            content: "Message with custom ID".to_string(),
+            id: Some(client_id.clone()),
+            template_id: None,
+            created_at: None,
+            publish_at: None,
+        };
+
+        let result = create_message(State(state), auth_user(&user.id), Json(request)).await;
+
+        assert!(result.is_ok());
+        let (_, response) = result.unwrap();
+        assert_eq!(response.0.id, client_id);
+    }
+
+    #[tokio::test]
+    async fn test_create_message_retry_returns_existing() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "retry@example.com", "password123").await;
+
+        let retry_id = MessageId::generate();
+        let request = || CreateMessageRequest {
+            content: "Queued offline".to_string(),
+            id: Some(retry_id.clone()),
+            template_id: None,
+            created_at: None,
+            publish_at: None,
+        };
+
+        let (status, _) =
+            create_message(State(state.clone()), auth_user(&user.id), Json(request()))
+                .await
+                .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (status, response) = create_message(State(state), auth_user(&user.id), Json(request()))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response.0.id, retry_id);
+    }
+
+    #[tokio::test]
+    async fn test_create_message_empty_content_fails() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "emptymsg@example.com", "password123").await;
+
+        let request = CreateMessageRequest {
+            content: "   ".to_string(),
+            id: None,
+            template_id: None,
+            created_at: None,
+            publish_at: None,
+        };
+
+        let result = create_message(State(state), auth_user(&user.id), Json(request)).await;
+
+        assert!(result.is_err());
+        let status = result.unwrap_err().status();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_create_message_from_template() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "fromtemplate@example.com", "password123").await;
+
+        let template = Template::new(
+            user.id.clone(),
+            "Gratitude".to_string(),
+            "Today I am grateful for:".to_string(),
+        );
+        state.storage.create_template(&template).await.unwrap();
+
+        let request = CreateMessageRequest {
+            content: String::new(),
+            id: None,
+            template_id: Some(template.id.clone()),
+            created_at: None,
+            publish_at: None,
+        };
+
+        let result = create_message(State(state), auth_user(&user.id), Json(request)).await;
+
+        assert!(result.is_ok());
+        let (_, response) = result.unwrap();
+        assert_eq!(response.0.content, "Today I am grateful for:");
+    }
+
+    #[tokio::test]
+    async fn test_create_message_unknown_template_fails() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "notemplate@example.com", "password123").await;
+
+        let request = CreateMessageRequest {
+            content: String::new(),
+            id: None,
+            template_id: Some("missing-template".to_string()),
+            created_at: None,
+            publish_at: None,
+        };
+
+        let result = create_message(State(state), auth_user(&user.id), Json(request)).await;
+
+        assert!(result.is_err());
+        let status = result.unwrap_err().status();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_create_scheduled_message_hidden_from_listing() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "future@example.com", "password123").await;
+
+        let request = CreateMessageRequest {
+            content: "Open in a year".to_string(),
+            id: None,
+            template_id: None,
+            created_at: Some("2020-01-01T00:00:00Z".to_string()),
+            publish_at: Some("2999-01-01T00:00:00Z".to_string()),
+        };
+
+        let result = create_message(State(state.clone()), auth_user(&user.id), Json(request)).await;
+
+        assert!(result.is_ok());
+        let (_, response) = result.unwrap();
+        assert_eq!(response.0.created_at, "2020-01-01T00:00:00+00:00");
+
+        let listing = get_messages(
+            State(state),
+            auth_user(&user.id),
+            Query(MessagesQuery::default()),
+            Pagination::default(),
+        )
+        .await
+        .unwrap();
+        assert!(listing.0.messages.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_create_message_invalid_publish_at_fails() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "badschedule@example.com", "password123").await;
+
+        let request = CreateMessageRequest {
+            content: "Whenever".to_string(),
+            id: None,
+            template_id: None,
+            created_at: None,
+            publish_at: Some("someday".to_string()),
+        };
+
+        let result = create_message(State(state), auth_user(&user.id), Json(request)).await;
+
+        assert!(result.is_err());
+        let status = result.unwrap_err().status();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_invite_journal_member_and_read() {
+        let state = setup_test_state().await;
+        let owner = create_test_user(&state, "journal-owner@example.com", "password123").await;
+        let member = create_test_user(&state, "journal-member@example.com", "password123").await;
+
+        let message = Message::new(owner.id.clone(), "Our trip".to_string());
+        state.storage.create_message(&message).await.unwrap();
+
+        let request = InviteMemberRequest {
+            email: "journal-member@example.com".to_string(),
+            role: JOURNAL_ROLE_READ.to_string(),
+        };
+        let result =
+            invite_journal_member(State(state.clone()), auth_user(&owner.id), Json(request)).await;
+        assert!(result.is_ok());
+
+        let result = get_journal_messages(
+            State(state.clone()),
+            auth_user(&member.id),
+            Path(owner.id.clone()),
+            Query(MessagesQuery::default()),
+        )
+        .await;
+        assert_eq!(result.unwrap().0.messages.len(), 1);
+
+        // Read-only members cannot post
+        let request = CreateMessageRequest {
+            content: "Can I write?".to_string(),
+            id: None,
+            template_id: None,
+            created_at: None,
+            publish_at: None,
+        };
+        let result = create_journal_message(
+            State(state),
+            auth_user(&member.id),
+            Path(owner.id),
+            Json(request),
+        )
+        .await;
+        let status = result.unwrap_err().status();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_invite_journal_member_invalid_role() {
+        let state = setup_test_state().await;
+        let owner = create_test_user(&state, "role-owner@example.com", "password123").await;
+
+        let request = InviteMemberRequest {
+            email: "someone@example.com".to_string(),
+            role: "admin".to_string(),
+        };
+        let result = invite_journal_member(State(state), auth_user(&owner.id), Json(request)).await;
+
+        let status = result.unwrap_err().status();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_reactions_included_in_listing() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "reactions@example.com", "password123").await;
+
+        let message = Message::new(user.id.clone(), "React to me".to_string());
+        state.storage.create_message(&message).await.unwrap();
+
+        let request = ReactionRequest {
+            emoji: "🔥".to_string(),
+        };
+        let result = add_reaction(
+            State(state.clone()),
+            auth_user(&user.id),
+            Path(message.id.clone()),
+            Json(request),
+        )
+        .await;
+        assert!(result.is_ok());
+
+        let listing = get_messages(
+            State(state),
+            auth_user(&user.id),
+            Query(MessagesQuery::default()),
+            Pagination::default(),
+        )
+        .await
+        .unwrap();
+        let reactions = &listing.0.messages[0].reactions;
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[0].emoji, "🔥");
+        assert_eq!(reactions[0].count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_add_reaction_rejects_text() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "badreaction@example.com", "password123").await;
+
+        let request = ReactionRequest {
+            emoji: "this is not an emoji".to_string(),
+        };
+        let result = add_reaction(
+            State(state),
+            auth_user(&user.id),
+            Path(MessageId::generate()),
+            Json(request),
+        )
+        .await;
+
+        let status = result.unwrap_err().status();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_sync_reports_changes_and_deletions() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "sync@example.com", "password123").await;
+
+        let kept = Message::new(user.id.clone(), "Kept".to_string());
+        let removed = Message::new(user.id.clone(), "Removed".to_string());
+        state.storage.create_message(&kept).await.unwrap();
+        state.storage.create_message(&removed).await.unwrap();
+
+        let initial = sync(
+            State(state.clone()),
+            auth_user(&user.id),
+            Query(MessagesQuery::default()),
+        )
+        .await
+        .unwrap()
+        .0;
+        assert_eq!(initial.changed.len(), 2);
+        assert!(initial.deleted.is_empty());
+
+        state
+            .storage
+            .delete_message(&removed.id, &user.id)
+            .await
+            .unwrap();
+
+        let delta = sync(
+            State(state),
+            auth_user(&user.id),
+            Query(MessagesQuery {
+                since: Some(initial.server_time),
+                since_seq: None,
+            }),
+        )
+        .await
+        .unwrap()
+        .0;
+        assert!(delta.changed.is_empty());
+        assert_eq!(delta.deleted, vec![removed.id]);
+    }
+
+    #[tokio::test]
+    async fn test_sync_with_seq_cursor() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "syncseq@example.com", "password123").await;
+
+        let first = Message::new(user.id.clone(), "First".to_string());
+        state.storage.create_message(&first).await.unwrap();
+
+        let initial = sync(
+            State(state.clone()),
+            auth_user(&user.id),
+            Query(MessagesQuery::default()),
+        )
+        .await
+        .unwrap()
+        .0;
+        assert_eq!(initial.seq, 1);
+
+        let second = Message::new(user.id.clone(), "Second".to_string());
+        state.storage.create_message(&second).await.unwrap();
+
+        let delta = sync(
+            State(state),
+            auth_user(&user.id),
+            Query(MessagesQuery {
+                since: None,
+                since_seq: Some(initial.seq),
+            }),
+        )
+        .await
+        .unwrap()
+        .0;
+        assert_eq!(delta.changed.len(), 1);
+        assert_eq!(delta.changed[0].content, "Second");
+        assert_eq!(delta.seq, 2);
+    }
+
+    #[tokio::test]
+    async fn test_sync_rejects_invalid_since() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "badsince@example.com", "password123").await;
+
+        let result = sync(
+            State(state),
+            auth_user(&user.id),
+            Query(MessagesQuery {
+                since: Some("last week".to_string()),
+                since_seq: None,
+            }),
+        )
+        .await;
+
+        let status = result.unwrap_err().status();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_batch_sync_applies_changes_and_returns_delta() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "batch@example.com", "password123").await;
+
+        let existing = Message::new(user.id.clone(), "Existing".to_string());
+        let doomed = Message::new(user.id.clone(), "Doomed".to_string());
+        state.storage.create_message(&existing).await.unwrap();
+        state.storage.create_message(&doomed).await.unwrap();
+
+        let request = BatchSyncRequest {
+            since_seq: Some(2),
+            creates: vec![SyncCreate {
+                id: MessageId::generate(),
+                content: "Written on the train".to_string(),
+                created_at: None,
+            }],
+            updates: vec![
+                SyncUpdate {
+                    id: existing.id.clone(),
+                    content: "Existing, edited".to_string(),
+                    updated_at: None,
+                },
+                SyncUpdate {
+                    id: MessageId::generate(),
+                    content: "Ignored".to_string(),
+                    updated_at: None,
+                },
+            ],
+            deletes: vec![doomed.id.clone()],
+        };
+
+        let response = batch_sync(State(state.clone()), auth_user(&user.id), Json(request))
+            .await
+            .unwrap()
+            .0;
+
+        assert_eq!(response.changed.len(), 2);
+        assert_eq!(response.deleted, vec![doomed.id]);
+        assert_eq!(response.seq, 5);
+
+        let messages = state
+            .storage
+            .get_messages_for_user(&user.id, None)
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_batch_sync_rolls_back_on_error() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "batchfail@example.com", "password123").await;
+        let other = create_test_user(&state, "batchother@example.com", "password123").await;
+
+        let taken = Message::with_id(MessageId::generate(), other.id, "Not yours".to_string());
+        state.storage.create_message(&taken).await.unwrap();
+
+        let fresh_id = MessageId::generate();
+        let request = BatchSyncRequest {
+            creates: vec![
+                SyncCreate {
+                    id: fresh_id.clone(),
+                    content: "Should roll back".to_string(),
+                    created_at: None,
+                },
+                SyncCreate {
+                    id: taken.id.clone(),
+                    content: "Conflicts".to_string(),
+                    created_at: None,
+                },
+            ],
+            ..Default::default()
+        };
+
+        let result = batch_sync(State(state.clone()), auth_user(&user.id), Json(request)).await;
+
+        let status = result.unwrap_err().status();
+        assert_eq!(status, StatusCode::CONFLICT);
+        let fresh = state.storage.get_message_by_id(&fresh_id).await.unwrap();
+        assert!(fresh.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_create_template_empty_name_fails() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "emptyname@example.com", "password123").await;
+
+        let request = CreateTemplateRequest {
+            name: "  ".to_string(),
+            content: "Body".to_string(),
+        };
+
+        let result = create_template(State(state), auth_user(&user.id), Json(request)).await;
+
+        assert!(result.is_err());
+        let status = result.unwrap_err().status();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_update_message_success() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "updatemsg@example.com", "password123").await;
+
+        // Create a message first
+        let message = Message::new(user.id.clone(), "Original".to_string());
+        state.storage.create_message(&message).await.unwrap();
+
+        let request = UpdateMessageRequest {
+            content: "Updated content".to_string(),
+            updated_at: None,
+        };
+
+        let result = update_message(
+            State(state),
+            auth_user(&user.id),
+            Path(message.id),
+            Json(request),
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().0.content, "Updated content");
+    }
+
+    #[tokio::test]
+    async fn test_update_message_not_found() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "notfound@example.com", "password123").await;
+
+        let request = UpdateMessageRequest {
+            content: "Update non-existent".to_string(),
+            updated_at: None,
+        };
+
+        let result = update_message(
+            State(state),
+            auth_user(&user.id),
+            Path(MessageId::generate()),
+            Json(request),
+        )
+        .await;
+
+        assert!(result.is_err());
+        let status = result.unwrap_err().status();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_delete_message_success() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "deletemsg@example.com", "password123").await;
+
+        let message = Message::new(user.id.clone(), "Delete me".to_string());
+        state.storage.create_message(&message).await.unwrap();
+
+        let result = delete_message(
+            State(state.clone()),
+            auth_user(&user.id),
+            Path(message.id.clone()),
+        )
+        .await;
+
+        assert!(result.is_ok());
+
+        // Verify message is gone
+        let deleted = state.storage.get_message_by_id(&message.id).await.unwrap();
+        assert!(deleted.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_update_email_success() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "oldemail@example.com", "password123").await;
+
+        let request = UpdateEmailRequest {
+            email: "newemail@example.com".to_string(),
+        };
+
+        let result = update_email(State(state.clone()), auth_user(&user.id), Json(request)).await;
+
+        assert!(result.is_ok());
+
+        // Verify email changed
+        let updated = state
+            .storage
+            .find_user_by_id(&user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.email, "newemail@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_update_email_invalid_format() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "invalid@example.com", "password123").await;
+
+        let request = UpdateEmailRequest {
+            email: "not-an-email".to_string(),
+        };
+
+        let result = update_email(State(state), auth_user(&user.id), Json(request)).await;
+
+        assert!(result.is_err());
+        let status = result.unwrap_err().status();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_update_username_success() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "username@example.com", "password123").await;
+
+        let request = UpdateUsernameRequest {
+            username: "newusername".to_string(),
+        };
+
+        let result =
+            update_username(State(state.clone()), auth_user(&user.id), Json(request)).await;
+
+        assert!(result.is_ok());
+
+        let updated = state
+            .storage
+            .find_user_by_id(&user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.username, "newusername");
+    }
+
+    #[tokio::test]
+    async fn test_update_display_name() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "shown@example.com", "password123").await;
+
+        let request = UpdateDisplayNameRequest {
+            display_name: Some("  Ada Lovelace ".to_string()),
+        };
+        let _ = update_display_name(State(state.clone()), auth_user(&user.id), Json(request))
+            .await
+            .unwrap();
+        let updated = state
+            .storage
+            .find_user_by_id(&user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.display_name.as_deref(), Some("Ada Lovelace"));
+        assert_eq!(updated.username, user.username);
+
+        // Blank clears it
+        let request = UpdateDisplayNameRequest {
+            display_name: Some(" ".to_string()),
+        };
+        let _ = update_display_name(State(state.clone()), auth_user(&user.id), Json(request))
+            .await
+            .unwrap();
+        let updated = state
+            .storage
+            .find_user_by_id(&user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.display_name, None);
+
+        let request = UpdateDisplayNameRequest {
+            display_name: Some("x".repeat(65)),
+        };
+        let result = update_display_name(State(state), auth_user(&user.id), Json(request)).await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_update_password_success() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "changepw@example.com", "oldpassword123").await;
+
+        let request = UpdatePasswordRequest {
+            current_password: "oldpassword123".to_string(),
+            new_password: "newpassword456".to_string(),
+        };
+
+        let result =
+            update_password(State(state.clone()), auth_user(&user.id), Json(request)).await;
+
+        assert!(result.is_ok());
+
+        // Verify new password works
+        let updated = state
+            .storage
+            .find_user_by_id(&user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(verify_password("newpassword456", &updated.password_hash).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_login_after_password_change_uses_new_password() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "relogin@example.com", "oldpassword123").await;
+        let login_with = |password: &str| LoginRequest {
+            email: "relogin@example.com".to_string(),
+            password: password.to_string(),
+        };
+
+        // Caches the user
+        assert!(
+            login(State(state.clone()), Json(login_with("oldpassword123")))
+                .await
+                .is_ok()
+        );
+
+        let request = UpdatePasswordRequest {
+            current_password: "oldpassword123".to_string(),
+            new_password: "newpassword456".to_string(),
+        };
+        let _ = update_password(State(state.clone()), auth_user(&user.id), Json(request))
+            .await
+            .unwrap();
+
+        assert!(
+            login(State(state.clone()), Json(login_with("oldpassword123")))
+                .await
+                .is_err()
+        );
+        assert!(login(State(state), Json(login_with("newpassword456")))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_update_password_wrong_current() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "wrongcurrent@example.com", "password123").await;
+
+        let request = UpdatePasswordRequest {
+            current_password: "wrongpassword".to_string(),
+            new_password: "newpassword456".to_string(),
+        };
+
+        let result = update_password(State(state), auth_user(&user.id), Json(request)).await;
+
+        assert!(result.is_err());
+        let status = result.unwrap_err().status();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_update_password_too_short() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "shortpw@example.com", "password123").await;
+
+        let request = UpdatePasswordRequest {
+            current_password: "password123".to_string(),
+            new_password: "short".to_string(),
+        };
+
+        let result = update_password(State(state), auth_user(&user.id), Json(request)).await;
+
+        assert!(result.is_err());
+        let status = result.unwrap_err().status();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    fn message_request(content: &str) -> CreateMessageRequest {
+        CreateMessageRequest {
+            content: content.to_string(),
+            id: None,
+            template_id: None,
+            created_at: None,
+            publish_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_message_over_quota_rejected_with_usage() {
+        let state = setup_test_state_with_quota(Some(10)).await;
+        let user = create_test_user(&state, "quota@example.com", "password123").await;
+
+        let _ = create_message(
+            State(state.clone()),
+            auth_user(&user.id),
+            Json(message_request("123456")),
+        )
+        .await
+        .unwrap();
+
+        let result = create_message(
+            State(state.clone()),
+            auth_user(&user.id),
+            Json(message_request("12345")),
+        )
+        .await;
+        let (status, error) = result.unwrap_err().into_parts();
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        let usage = error.usage.unwrap();
+        assert_eq!(usage.content_bytes, 6);
+        assert_eq!(usage.quota_bytes, Some(10));
+
+        let Json(usage) = get_usage(State(state), auth_user(&user.id)).await.unwrap();
+        assert_eq!(usage.content_bytes, 6);
+    }
+
+    #[tokio::test]
+    async fn test_shrinking_edit_allowed_over_quota() {
+        let state = setup_test_state_with_quota(Some(100)).await;
+        let user = create_test_user(&state, "shrink@example.com", "password123").await;
+        let message = Message::new(user.id.clone(), "x".repeat(150));
+        state.storage.create_message(&message).await.unwrap();
+
+        let grow = UpdateMessageRequest {
+            content: "x".repeat(151),
+            updated_at: None,
+        };
+        let result = update_message(
+            State(state.clone()),
+            auth_user(&user.id),
+            Path(message.id.clone()),
+            Json(grow),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let shrink = UpdateMessageRequest {
+            content: "short".to_string(),
+            updated_at: None,
+        };
+        let _ = update_message(
+            State(state.clone()),
+            auth_user(&user.id),
+            Path(message.id),
+            Json(shrink),
+        )
+        .await
+        .unwrap();
+
+        let Json(usage) = get_usage(State(state), auth_user(&user.id)).await.unwrap();
+        assert_eq!(usage.content_bytes, 5);
+    }
+
+    #[tokio::test]
+    async fn test_create_message_past_message_limit_rejected() {
+        let state = setup_test_state_with_message_limits(Some(2), None).await;
+        let user = create_test_user(&state, "limit@example.com", "password123").await;
+        // Imported messages count towards the total
+        let imported = Message::new(user.id.clone(), "old".to_string());
+        state.storage.create_message(&imported).await.unwrap();
+
+        let _ = create_message(
+            State(state.clone()),
+            auth_user(&user.id),
+            Json(message_request("second")),
+        )
+        .await
+        .unwrap();
+        let result = create_message(
+            State(state.clone()),
+            auth_user(&user.id),
+            Json(message_request("third")),
+        )
+        .await;
+        let (status, error) = result.unwrap_err().into_parts();
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(error.code.as_deref(), Some(MESSAGE_LIMIT_REACHED));
+        let usage = error.usage.unwrap();
+        assert_eq!(usage.message_count, 2);
+        assert_eq!(usage.max_messages, Some(2));
+        assert_eq!(usage.messages_today, 1);
+
+        // Deleting one makes room again
+        let _ = delete_message(State(state.clone()), auth_user(&user.id), Path(imported.id))
+            .await
+            .unwrap();
+        let _ = create_message(
+            State(state),
+            auth_user(&user.id),
+            Json(message_request("third")),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_daily_message_limit_counts_deleted_messages() {
+        let state = setup_test_state_with_message_limits(None, Some(2)).await;
+        let user = create_test_user(&state, "daily@example.com", "password123").await;
+
+        let mut ids = Vec::new();
+        for content in ["one", "two"] {
+            let (_, Json(message)) = create_message(
+                State(state.clone()),
+                auth_user(&user.id),
+                Json(message_request(content)),
+            )
+            .await
+            .unwrap();
+            ids.push(message.id);
+        }
+        for id in ids {
+            let _ = delete_message(
+                State(state.clone()),
+                auth_user(&user.id),
+                Path(id.parse().unwrap()),
+            )
+            .await
+            .unwrap();
+        }
+
+        let result = create_message(
+            State(state.clone()),
+            auth_user(&user.id),
+            Json(message_request("three")),
+        )
+        .await;
+        let (status, error) = result.unwrap_err().into_parts();
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(error.code.as_deref(), Some(DAILY_MESSAGE_LIMIT_REACHED));
+
+        let Json(usage) = get_usage(State(state), auth_user(&user.id)).await.unwrap();
+        assert_eq!(usage.message_count, 0);
+        assert_eq!(usage.messages_today, 2);
+        assert_eq!(usage.max_messages_per_day, Some(2));
+        assert!(usage.day_resets_at.ends_with("T00:00:00+00:00"));
+    }
+}
+
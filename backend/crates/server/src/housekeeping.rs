@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::{db::DbError, handlers::SharedState, middleware::IDEMPOTENCY_TTL_HOURS};
+
+/// How often expired rows are looked for
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Delete short-lived tokens once they have expired, until shutdown
+///
+/// Expired rows are already ignored where they are read; this only keeps
+/// their tables from growing without bound on long-lived instances.
+pub fn spawn_worker(state: SharedState, shutdown: CancellationToken) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            prune_expired(&state).await;
+
+            tokio::select! {
+                _ = tokio::time::sleep(CHECK_INTERVAL) => {}
+                _ = shutdown.cancelled() => break,
+            }
+        }
+    })
+}
+
+async fn prune_expired(state: &SharedState) {
+    let now = Utc::now();
+
+    let before = now - chrono::Duration::hours(IDEMPOTENCY_TTL_HOURS);
+    log_pruned(
+        "expired idempotency keys",
+        state
+            .storage
+            .delete_idempotent_responses_before(before)
+            .await,
+    );
+    log_pruned(
+        "expired Telegram link codes",
+        state.storage.delete_expired_telegram_link_codes(now).await,
+    );
+}
+
+fn log_pruned(what: &str, result: Result<u64, DbError>) {
+    match result {
+        Ok(0) => {}
+        Ok(count) => tracing::info!("Pruned {} {}", count, what),
+        Err(e) => tracing::error!("Failed to prune {}: {}", what, e),
+    }
+}
@@ -0,0 +1,591 @@
+use std::io::{Cursor, Read};
+
+use axum::{body::Bytes, extract::State, http::StatusCode, Json};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::ToSchema;
+
+use crate::{
+    audit,
+    error::AppError,
+    handlers::{ensure_message_limits, ensure_quota, storage_error, SharedState},
+    ids::UserId,
+    models::{AuditEvent, ImportResponse, Message},
+    realtime::MessageEvent,
+    utils::parse_timestamp,
+    AuthUser,
+};
+
+/// Maximum request body size accepted by the import endpoints (50 MB)
+pub const IMPORT_BODY_LIMIT: usize = 50 * 1024 * 1024;
+
+/// Most Markdown read out of an uploaded ZIP, guarding against zip bombs
+const MARKDOWN_EXTRACT_LIMIT: u64 = 4 * IMPORT_BODY_LIMIT as u64;
+
+// ============ Day One ============
+
+/// Top-level Day One JSON export (`Journal.json` inside the export zip)
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DayOneExport {
+    pub entries: Vec<DayOneEntry>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DayOneEntry {
+    #[serde(default)]
+    pub text: String,
+    pub creation_date: String,
+    #[serde(default)]
+    pub modified_date: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Convert a Day One export into messages owned by `user_id`
+pub fn parse_dayone(export: DayOneExport, user_id: &UserId) -> Result<Vec<Message>, String> {
+    let mut messages = Vec::with_capacity(export.entries.len());
+
+    for entry in export.entries {
+        if entry.text.trim().is_empty() {
+            continue;
+        }
+
+        let created_at = parse_timestamp(&entry.creation_date)
+            .map_err(|_| format!("Invalid creationDate: {}", entry.creation_date))?;
+        let updated_at = match entry.modified_date.as_deref() {
+            Some(modified) => parse_timestamp(modified)
+                .map_err(|_| format!("Invalid modifiedDate: {}", modified))?,
+            None => created_at,
+        };
+
+        let mut message = Message::new(user_id.clone(), with_tags(entry.text, &entry.tags));
+        message.created_at = created_at;
+        message.updated_at = updated_at;
+        messages.push(message);
+    }
+
+    Ok(messages)
+}
+
+// ============ Journey ============
+
+/// A single Journey entry (one JSON file per entry in the export zip)
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct JourneyEntry {
+    #[serde(default)]
+    pub text: String,
+    /// Milliseconds since the Unix epoch
+    pub date_journal: i64,
+    #[serde(default)]
+    pub date_modified: Option<i64>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Convert Journey entries into messages owned by `user_id`
+pub fn parse_journey(entries: Vec<JourneyEntry>, user_id: &UserId) -> Result<Vec<Message>, String> {
+    let mut messages = Vec::with_capacity(entries.len());
+
+    for entry in entries {
+        if entry.text.trim().is_empty() {
+            continue;
+        }
+
+        let created_at = DateTime::<Utc>::from_timestamp_millis(entry.date_journal)
+            .ok_or_else(|| format!("Invalid date_journal: {}", entry.date_journal))?;
+        let updated_at = match entry.date_modified {
+            Some(modified) => DateTime::<Utc>::from_timestamp_millis(modified)
+                .ok_or_else(|| format!("Invalid date_modified: {}", modified))?,
+            None => created_at,
+        };
+
+        let mut message = Message::new(user_id.clone(), with_tags(entry.text, &entry.tags));
+        message.created_at = created_at;
+        message.updated_at = updated_at;
+        messages.push(message);
+    }
+
+    Ok(messages)
+}
+
+// ============ Markdown ============
+
+/// Messages parsed from a ZIP of Markdown files, and the files skipped
+/// because no date could be found for them
+#[derive(Debug, Default)]
+pub struct MarkdownImport {
+    pub messages: Vec<Message>,
+    pub skipped: Vec<String>,
+}
+
+/// Convert a ZIP of `.md` files (an Obsidian vault, a folder of notes, ...)
+/// into messages owned by `user_id`, one per file
+///
+/// Each file's date comes from a `date` or `created` front-matter field, or
+/// else from a `YYYY-MM-DD` in its name, as in daily notes. `tags` in the
+/// front matter are appended as hashtags.
+pub fn parse_markdown_zip(zip: &[u8], user_id: &UserId) -> Result<MarkdownImport, String> {
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(zip)).map_err(|e| format!("Invalid ZIP file: {}", e))?;
+    let mut import = MarkdownImport::default();
+    let mut extracted = 0;
+
+    for index in 0..archive.len() {
+        let mut file = archive
+            .by_index(index)
+            .map_err(|e| format!("Invalid ZIP file: {}", e))?;
+        let name = file.name().to_string();
+        if file.is_dir() || !is_markdown_file(&name) {
+            continue;
+        }
+
+        let mut text = String::new();
+        (&mut file)
+            .take(MARKDOWN_EXTRACT_LIMIT - extracted + 1)
+            .read_to_string(&mut text)
+            .map_err(|_| format!("{} is not valid UTF-8", name))?;
+        extracted += text.len() as u64;
+        if extracted > MARKDOWN_EXTRACT_LIMIT {
+            return Err("ZIP file is too large once extracted".to_string());
+        }
+
+        let (front_matter, body) = split_front_matter(&text);
+        if body.trim().is_empty() {
+            continue;
+        }
+
+        let created_at = front_matter
+            .iter()
+            .find(|(key, _)| key == "date" || key == "created")
+            .and_then(|(_, value)| parse_markdown_date(value))
+            .or_else(|| date_in_file_name(&name));
+        let Some(created_at) = created_at else {
+            import.skipped.push(name);
+            continue;
+        };
+        let updated_at = front_matter
+            .iter()
+            .find(|(key, _)| key == "updated" || key == "modified")
+            .and_then(|(_, value)| parse_markdown_date(value))
+            .filter(|updated_at| *updated_at >= created_at)
+            .unwrap_or(created_at);
+        let tags: Vec<String> = front_matter
+            .iter()
+            .filter(|(key, _)| key == "tags")
+            .flat_map(|(_, value)| parse_tag_list(value))
+            .collect();
+
+        let mut message = Message::new(user_id.clone(), with_tags(body.to_string(), &tags));
+        message.created_at = created_at;
+        message.updated_at = updated_at;
+        import.messages.push(message);
+    }
+
+    Ok(import)
+}
+
+fn is_markdown_file(name: &str) -> bool {
+    let lower = name.to_lowercase();
+    let hidden = name
+        .split('/')
+        .any(|part| part.starts_with('.') || part == "__MACOSX");
+    !hidden && (lower.ends_with(".md") || lower.ends_with(".markdown"))
+}
+
+/// Split simple YAML front matter into `key: value` pairs and the body
+///
+/// Block lists (`tags:` followed by `- item` lines) are joined into one
+/// comma-separated value. Text without front matter is returned unchanged.
+fn split_front_matter(text: &str) -> (Vec<(String, String)>, &str) {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let Some(rest) = text
+        .strip_prefix("---\n")
+        .or_else(|| text.strip_prefix("---\r\n"))
+    else {
+        return (Vec::new(), text);
+    };
+    let Some(end) = rest
+        .match_indices("\n---")
+        .map(|(i, _)| i)
+        .find(|&i| matches!(rest[i + 4..].chars().next(), None | Some('\n' | '\r')))
+    else {
+        return (Vec::new(), text);
+    };
+
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for line in rest[..end].lines() {
+        let trimmed = line.trim();
+        if let Some(item) = trimmed.strip_prefix("- ") {
+            if let Some((_, value)) = fields.last_mut() {
+                if !value.is_empty() {
+                    value.push_str(", ");
+                }
+                value.push_str(item.trim());
+            }
+        } else if let Some((key, value)) = trimmed.split_once(':') {
+            fields.push((key.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    let body = rest[end + 4..].trim_start_matches(['\r', '\n']);
+    (fields, body)
+}
+
+/// Parse an RFC 3339 timestamp, `YYYY-MM-DD HH:MM[:SS]` or `YYYY-MM-DD`,
+/// taking times without an offset as UTC
+fn parse_markdown_date(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim().trim_matches(['"', '\'']);
+    if let Ok(at) = parse_timestamp(value) {
+        return Some(at);
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(at) = NaiveDateTime::parse_from_str(value, format) {
+            return Some(at.and_utc());
+        }
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc())
+}
+
+/// First `YYYY-MM-DD` in a file's name, at midnight UTC
+fn date_in_file_name(path: &str) -> Option<DateTime<Utc>> {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.char_indices()
+        .filter_map(|(i, _)| name.get(i..i + 10))
+        .find_map(|candidate| NaiveDate::parse_from_str(candidate, "%Y-%m-%d").ok())
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc())
+}
+
+/// Tags written as `[a, b]` or `a, b`
+fn parse_tag_list(value: &str) -> Vec<String> {
+    value
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+        .map(|tag| tag.trim().trim_matches(['"', '\'']).trim_start_matches('#'))
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Append tags as a trailing line of hashtags, since messages have no tag field
+fn with_tags(text: String, tags: &[String]) -> String {
+    let hashtags: Vec<String> = tags
+        .iter()
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty())
+        .map(|t| format!("#{}", t.replace(' ', "_")))
+        .collect();
+
+    if hashtags.is_empty() {
+        text
+    } else {
+        format!("{}\n\n{}", text.trim_end(), hashtags.join(" "))
+    }
+}
+
+// ============ Handlers ============
+
+/// POST /api/import/dayone
+/// Import entries from a Day One JSON export
+#[utoipa::path(
+    post,
+    path = "/api/import/dayone",
+    tag = "imports",
+    request_body = DayOneExport,
+    responses(
+        (status = 200, description = "Entries imported", body = ImportResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 413, description = "Storage quota or message limit exceeded", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn import_dayone(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Json(payload): Json<DayOneExport>,
+) -> Result<Json<ImportResponse>, AppError> {
+    let messages =
+        parse_dayone(payload, &user_id).map_err(|e| AppError::new(StatusCode::BAD_REQUEST, e))?;
+
+    store_messages(&state, &user_id, "dayone", messages).await
+}
+
+/// POST /api/import/journey
+/// Import entries from a Journey export (array of entry objects)
+#[utoipa::path(
+    post,
+    path = "/api/import/journey",
+    tag = "imports",
+    request_body = Vec<JourneyEntry>,
+    responses(
+        (status = 200, description = "Entries imported", body = ImportResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 413, description = "Storage quota or message limit exceeded", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn import_journey(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Json(payload): Json<Vec<JourneyEntry>>,
+) -> Result<Json<ImportResponse>, AppError> {
+    let messages =
+        parse_journey(payload, &user_id).map_err(|e| AppError::new(StatusCode::BAD_REQUEST, e))?;
+
+    store_messages(&state, &user_id, "journey", messages).await
+}
+
+/// POST /api/import/markdown
+/// Import a ZIP of Markdown files, one message per dated file
+#[utoipa::path(
+    post,
+    path = "/api/import/markdown",
+    tag = "imports",
+    request_body(content = String, description = "ZIP of Markdown files", content_type = "application/zip"),
+    responses(
+        (status = 200, description = "Entries imported, with any files skipped", body = ImportResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 413, description = "Storage quota or message limit exceeded", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn import_markdown(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    body: Bytes,
+) -> Result<Json<ImportResponse>, AppError> {
+    let owner = user_id.clone();
+    let import = tokio::task::spawn_blocking(move || parse_markdown_zip(&body, &owner))
+        .await
+        .map_err(|_| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read ZIP file"))?
+        .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, e))?;
+
+    let Json(mut response) = store_messages(&state, &user_id, "markdown", import.messages).await?;
+    response.skipped = import.skipped;
+    Ok(Json(response))
+}
+
+async fn store_messages(
+    state: &SharedState,
+    user_id: &UserId,
+    source: &str,
+    messages: Vec<Message>,
+) -> Result<Json<ImportResponse>, AppError> {
+    if let Some(first) = messages.first() {
+        let bytes = messages.iter().map(|m| m.content.len() as i64).sum();
+        ensure_quota(state, &first.user_id, bytes).await?;
+        ensure_message_limits(state, &first.user_id, messages.len() as i64, false).await?;
+    }
+
+    for message in &messages {
+        state
+            .storage
+            .create_message(message)
+            .await
+            .map_err(|e| storage_error(e, "Failed to import messages"))?;
+    }
+
+    if let Some(last) = messages.last() {
+        let seq = state
+            .storage
+            .get_change_seq(&last.user_id)
+            .await
+            .unwrap_or_default();
+        state
+            .events
+            .publish(&last.user_id, MessageEvent::Resync { seq });
+    }
+    audit::record(
+        &*state.storage,
+        AuditEvent::new("messages.import", user_id.as_str())
+            .with_target("user", user_id.as_str())
+            .with_metadata(json!({ "source": source, "imported": messages.len() })),
+    )
+    .await;
+
+    Ok(Json(ImportResponse {
+        imported: messages.len(),
+        skipped: Vec::new(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::User,
+        testing::{auth_user, test_app_state},
+        utils::hash_password,
+    };
+    use std::sync::Arc;
+
+    async fn setup_test_state() -> SharedState {
+        Arc::new(test_app_state().await)
+    }
+
+    async fn create_test_user(state: &SharedState, email: &str) -> User {
+        let (hash, salt) = hash_password("password123").unwrap();
+        let user = User::new(email.to_string(), "testuser".to_string(), hash, salt);
+        state.storage.create_user(&user).await.unwrap();
+        user
+    }
+
+    #[test]
+    fn test_parse_dayone_maps_fields() {
+        let json = r#"{
+            "metadata": {"version": "1.0"},
+            "entries": [
+                {
+                    "uuid": "ABC123",
+                    "creationDate": "2023-05-01T08:30:00Z",
+                    "modifiedDate": "2023-05-02T09:00:00Z",
+                    "text": "Morning walk",
+                    "tags": ["outdoors", "daily log"]
+                },
+                {"creationDate": "2023-05-03T08:30:00Z", "text": "   "}
+            ]
+        }"#;
+        let export: DayOneExport = serde_json::from_str(json).unwrap();
+
+        let user_id = UserId::generate();
+        let messages = parse_dayone(export, &user_id).unwrap();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, "Morning walk\n\n#outdoors #daily_log");
+        assert_eq!(
+            messages[0].created_at.to_rfc3339(),
+            "2023-05-01T08:30:00+00:00"
+        );
+        assert_eq!(
+            messages[0].updated_at.to_rfc3339(),
+            "2023-05-02T09:00:00+00:00"
+        );
+        assert_eq!(messages[0].user_id, user_id);
+    }
+
+    #[test]
+    fn test_parse_dayone_rejects_bad_date() {
+        let json = r#"{"entries": [{"creationDate": "yesterday", "text": "Hi"}]}"#;
+        let export: DayOneExport = serde_json::from_str(json).unwrap();
+
+        assert!(parse_dayone(export, &UserId::generate()).is_err());
+    }
+
+    #[test]
+    fn test_parse_journey_converts_millis() {
+        let json = r#"[{"id": "1", "text": "Rainy day", "date_journal": 1700000000000}]"#;
+        let entries: Vec<JourneyEntry> = serde_json::from_str(json).unwrap();
+
+        let messages = parse_journey(entries, &UserId::generate()).unwrap();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, "Rainy day");
+        assert_eq!(
+            messages[0].created_at.to_rfc3339(),
+            "2023-11-14T22:13:20+00:00"
+        );
+    }
+
+    fn zip_of(files: &[(&str, &str)]) -> Vec<u8> {
+        use std::io::Write;
+
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            writer
+                .start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_parse_markdown_zip_reads_front_matter_and_file_names() {
+        let zip = zip_of(&[
+            (
+                "notes/Trip.md",
+                "---\ntitle: Trip\ndate: 2023-06-01 14:30\ntags:\n  - travel\n  - \"road trip\"\n---\n\nDrove to the coast\n",
+            ),
+            ("daily/2023-06-02.md", "Quiet day"),
+            ("daily/README.md", "How I use this vault"),
+            (".obsidian/workspace.md", "ignored"),
+            ("notes/picture.png", "not markdown"),
+        ]);
+
+        let import = parse_markdown_zip(&zip, &UserId::generate()).unwrap();
+
+        assert_eq!(import.messages.len(), 2);
+        assert_eq!(
+            import.messages[0].content,
+            "Drove to the coast\n\n#travel #road_trip"
+        );
+        assert_eq!(
+            import.messages[0].created_at.to_rfc3339(),
+            "2023-06-01T14:30:00+00:00"
+        );
+        assert_eq!(import.messages[1].content, "Quiet day");
+        assert_eq!(
+            import.messages[1].created_at.to_rfc3339(),
+            "2023-06-02T00:00:00+00:00"
+        );
+        assert_eq!(import.skipped, vec!["daily/README.md".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_markdown_zip_rejects_non_zip() {
+        assert!(parse_markdown_zip(b"# Not a zip", &UserId::generate()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_markdown_stores_messages() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "markdown@example.com").await;
+        let zip = zip_of(&[
+            ("2021-12-31.md", "Last entry of the year"),
+            ("undated.md", "No date here"),
+        ]);
+
+        let Json(response) = import_markdown(State(state.clone()), auth_user(&user.id), zip.into())
+            .await
+            .unwrap();
+
+        assert_eq!(response.imported, 1);
+        assert_eq!(response.skipped, vec!["undated.md".to_string()]);
+        let messages = state
+            .storage
+            .get_messages_for_user(&user.id, None)
+            .await
+            .unwrap();
+        assert_eq!(messages[0].content, "Last entry of the year");
+    }
+
+    #[tokio::test]
+    async fn test_import_dayone_stores_messages() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "dayone@example.com").await;
+
+        let export = DayOneExport {
+            entries: vec![DayOneEntry {
+                text: "Imported entry".to_string(),
+                creation_date: "2022-01-01T00:00:00Z".to_string(),
+                modified_date: None,
+                tags: vec![],
+            }],
+        };
+
+        let result = import_dayone(State(state.clone()), auth_user(&user.id), Json(export)).await;
+
+        assert_eq!(result.unwrap().0.imported, 1);
+        let messages = state
+            .storage
+            .get_messages_for_user(&user.id, None)
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, "Imported entry");
+    }
+}
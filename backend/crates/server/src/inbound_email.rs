@@ -0,0 +1,639 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{FromRequest, Multipart, Request, State},
+    http::{header, StatusCode},
+    Form, Json,
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{
+    config::InboundEmailConfig,
+    db::DbError,
+    error::AppError,
+    handlers::{self, storage_error, SharedState},
+    ids::UserId,
+    mailer::EmailAttachment,
+    models::{
+        CreateMessageRequest, InboundEmailAddress, InboundEmailResponse, SetInboundEmailRequest,
+        SuccessResponse,
+    },
+    AuthUser,
+};
+
+/// Largest forwarded email accepted, attachments included
+pub const INBOUND_BODY_LIMIT: usize = 25 * 1024 * 1024;
+
+/// Most addresses a user can accept mail from besides their own
+pub const MAX_ALLOWED_SENDERS: usize = 5;
+
+/// Largest text attachment copied into an entry
+const MAX_TEXT_ATTACHMENT_BYTES: usize = 100 * 1024;
+
+/// How old a provider's signature may be, so captured requests can't be
+/// replayed later
+const SIGNATURE_MAX_AGE_SECS: i64 = 15 * 60;
+
+/// Where email-in mail arrives, and the key the provider signs it with
+#[derive(Debug, Clone)]
+pub struct InboundEmail {
+    domain: String,
+    signing_key: String,
+}
+
+impl InboundEmail {
+    /// Build from the `[inbound_email]` settings; email-in is off unless a
+    /// domain is set
+    pub fn from_config(config: &InboundEmailConfig) -> Option<Self> {
+        Some(Self {
+            domain: config.domain.clone()?.to_lowercase(),
+            signing_key: config.mailgun_signing_key.clone()?,
+        })
+    }
+}
+
+/// Form fields and file attachments of an email forwarded by Mailgun
+#[derive(Debug, Default)]
+struct ForwardedEmail {
+    fields: HashMap<String, String>,
+    attachments: Vec<EmailAttachment>,
+}
+
+impl ForwardedEmail {
+    fn field(&self, name: &str) -> &str {
+        self.fields
+            .get(name)
+            .map(String::as_str)
+            .unwrap_or_default()
+    }
+}
+
+/// Read a forwarded email, which Mailgun sends as multipart when it has
+/// attachments and as a plain form otherwise
+async fn read_email(request: Request) -> Result<ForwardedEmail, AppError> {
+    let multipart = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("multipart/form-data"));
+
+    if !multipart {
+        let Form(fields) = Form::<HashMap<String, String>>::from_request(request, &())
+            .await
+            .map_err(|e| AppError::new(e.status(), e.body_text()))?;
+        return Ok(ForwardedEmail {
+            fields,
+            attachments: Vec::new(),
+        });
+    }
+
+    let mut multipart = Multipart::from_request(request, &())
+        .await
+        .map_err(|e| AppError::new(e.status(), e.body_text()))?;
+    let invalid = |e: axum::extract::multipart::MultipartError| {
+        AppError::new(StatusCode::BAD_REQUEST, e.body_text())
+    };
+
+    let mut email = ForwardedEmail::default();
+    while let Some(field) = multipart.next_field().await.map_err(invalid)? {
+        let name = field.name().unwrap_or_default().to_string();
+        match field.file_name() {
+            Some(filename) => {
+                let filename = filename.to_string();
+                let content_type = field
+                    .content_type()
+                    .unwrap_or("application/octet-stream")
+                    .to_string();
+                let body = field.bytes().await.map_err(invalid)?;
+                email.attachments.push(EmailAttachment {
+                    filename,
+                    content_type,
+                    body: body.to_vec(),
+                });
+            }
+            None => {
+                let value = field.text().await.map_err(invalid)?;
+                email.fields.insert(name, value);
+            }
+        }
+    }
+
+    Ok(email)
+}
+
+/// Whether Mailgun signed `timestamp` and `token` with `key` recently
+fn verify_signature(
+    key: &str,
+    timestamp: &str,
+    token: &str,
+    signature: &str,
+    now: DateTime<Utc>,
+) -> bool {
+    let Ok(signed_at) = timestamp.parse::<i64>() else {
+        return false;
+    };
+    if (now.timestamp() - signed_at).abs() > SIGNATURE_MAX_AGE_SECS {
+        return false;
+    }
+    let Some(signature) = decode_hex(signature) else {
+        return false;
+    };
+
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.as_bytes());
+    mac.update(token.as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// The bare address in a header value such as `Ada <ada@example.com>`,
+/// lowercased
+fn bare_address(value: &str) -> String {
+    let value = value.trim();
+    let address = match (value.rfind('<'), value.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &value[start + 1..end],
+        _ => value,
+    };
+    address.trim().to_lowercase()
+}
+
+/// The token of the first recipient at `domain`, without any `+tag`
+fn recipient_token(recipients: &str, domain: &str) -> Option<String> {
+    recipients.split(',').find_map(|recipient| {
+        let address = bare_address(recipient);
+        let (local, recipient_domain) = address.rsplit_once('@')?;
+        if recipient_domain != domain {
+            return None;
+        }
+        let token = local.split('+').next().unwrap_or(local);
+        Some(token.to_string())
+    })
+}
+
+/// The entry an email becomes: its subject as a heading, the reply without
+/// quoted text or signature, and any short text attachments. Other
+/// attachments can't be stored yet, so they are only named.
+fn entry_content(email: &ForwardedEmail) -> String {
+    let mut content = String::new();
+    let subject = email.field("subject").trim();
+    if !subject.is_empty() {
+        content.push_str(&format!("# {}\n\n", subject));
+    }
+    let body = match email.field("stripped-text").trim() {
+        "" => email.field("body-plain").trim(),
+        stripped => stripped,
+    };
+    content.push_str(body);
+
+    let mut skipped = Vec::new();
+    for attachment in &email.attachments {
+        let text = std::str::from_utf8(&attachment.body)
+            .ok()
+            .filter(|_| attachment.content_type.starts_with("text/"))
+            .filter(|text| text.len() <= MAX_TEXT_ATTACHMENT_BYTES);
+        match text {
+            Some(text) => content.push_str(&format!(
+                "\n\n## {}\n\n{}",
+                attachment.filename,
+                text.trim()
+            )),
+            None => skipped.push(attachment.filename.as_str()),
+        }
+    }
+    if !skipped.is_empty() {
+        content.push_str(&format!(
+            "\n\n_Attachments not saved: {}_",
+            skipped.join(", ")
+        ));
+    }
+
+    content.trim().to_string()
+}
+
+/// POST /api/inbound/mailgun
+/// Save an email Mailgun forwarded to a user's secret address as an entry
+///
+/// Mail that can't be saved, such as from a sender who isn't allowed, is
+/// answered with 406, which tells Mailgun not to retry it.
+#[utoipa::path(
+    post,
+    path = "/api/inbound/mailgun",
+    tag = "messages",
+    request_body(
+        content = String,
+        content_type = "multipart/form-data",
+        description = "Mailgun's forwarded message, with its `timestamp`, `token` and `signature`"
+    ),
+    responses(
+        (status = 200, description = "Saved as an entry"),
+        (status = 401, description = "Invalid signature", body = ErrorResponse),
+        (status = 404, description = "Email-in is not configured", body = ErrorResponse),
+        (status = 406, description = "Unknown recipient, sender not allowed, or the entry was rejected", body = ErrorResponse),
+    )
+)]
+pub async fn receive_mailgun(
+    State(state): State<SharedState>,
+    request: Request,
+) -> Result<StatusCode, AppError> {
+    let Some(config) = state.inbound_email.clone() else {
+        return Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            "Email-in is not configured",
+        ));
+    };
+    let email = read_email(request).await?;
+
+    if !verify_signature(
+        &config.signing_key,
+        email.field("timestamp"),
+        email.field("token"),
+        email.field("signature"),
+        Utc::now(),
+    ) {
+        return Err(AppError::new(StatusCode::UNAUTHORIZED, "Invalid signature"));
+    }
+    let rejected = |message: &str| AppError::new(StatusCode::NOT_ACCEPTABLE, message);
+
+    let Some(token) = recipient_token(email.field("recipient"), &config.domain) else {
+        return Err(rejected("Unknown recipient"));
+    };
+    let address = state
+        .storage
+        .get_inbound_email_address_by_token(&token)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?
+        .ok_or_else(|| rejected("Unknown recipient"))?;
+    let user = state
+        .storage
+        .find_user_by_id(&address.user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?
+        .ok_or_else(|| rejected("Unknown recipient"))?;
+
+    // Anyone who learns the address could otherwise write to the journal
+    let sender = bare_address(email.field("from"));
+    if sender != user.email.to_lowercase() && !address.allowed_senders().contains(&sender) {
+        tracing::info!("Rejected email from {} for user {}", sender, user.id);
+        return Err(rejected("Sender not allowed"));
+    }
+
+    // The same email forwarded again is the same entry, not a new one
+    let id = match email.field("Message-Id") {
+        "" => None,
+        message_id => Some(handlers::derived_message_id(&format!(
+            "email:{}:{}",
+            user.id, message_id
+        ))),
+    };
+    let request = CreateMessageRequest {
+        content: entry_content(&email),
+        id,
+        template_id: None,
+        created_at: None,
+        publish_at: None,
+    };
+    handlers::save_message(&state, user.id, request)
+        .await
+        .map_err(|error| {
+            if error.status().is_client_error() {
+                AppError::new(StatusCode::NOT_ACCEPTABLE, error.into_parts().1.error)
+            } else {
+                error
+            }
+        })?;
+
+    Ok(StatusCode::OK)
+}
+
+// ============ Handlers ============
+
+fn email_in_config(state: &SharedState) -> Result<&InboundEmail, AppError> {
+    state
+        .inbound_email
+        .as_ref()
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "Email-in is not configured"))
+}
+
+async fn find_address(
+    state: &SharedState,
+    user_id: &UserId,
+) -> Result<InboundEmailAddress, AppError> {
+    state
+        .storage
+        .get_inbound_email_address(user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "No inbound email address set up"))
+}
+
+/// GET /api/user/inbound-email
+/// The authenticated user's secret address for emailing in entries
+#[utoipa::path(
+    get,
+    path = "/api/user/inbound-email",
+    tag = "user",
+    responses(
+        (status = 200, description = "Address to email entries to", body = InboundEmailResponse),
+        (status = 404, description = "Email-in is not configured, or no address set up", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_address(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<InboundEmailResponse>, AppError> {
+    let config = email_in_config(&state)?;
+    let address = find_address(&state, &user_id).await?;
+
+    Ok(Json(address.to_response(&config.domain)))
+}
+
+/// PUT /api/user/inbound-email
+/// Set up the user's address, or change who may send to it
+#[utoipa::path(
+    put,
+    path = "/api/user/inbound-email",
+    tag = "user",
+    request_body = SetInboundEmailRequest,
+    responses(
+        (status = 200, description = "Address to email entries to", body = InboundEmailResponse),
+        (status = 400, description = "Invalid or too many senders", body = ErrorResponse),
+        (status = 404, description = "Email-in is not configured", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn set_address(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Json(payload): Json<SetInboundEmailRequest>,
+) -> Result<Json<InboundEmailResponse>, AppError> {
+    let config = email_in_config(&state)?;
+
+    if payload.allowed_senders.len() > MAX_ALLOWED_SENDERS {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "At most {} other senders can be allowed",
+                MAX_ALLOWED_SENDERS
+            ),
+        ));
+    }
+    let mut senders = Vec::new();
+    for sender in &payload.allowed_senders {
+        let sender = sender.trim().to_lowercase();
+        if sender.parse::<lettre::Address>().is_err() {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid sender address: {}", sender),
+            ));
+        }
+        if !senders.contains(&sender) {
+            senders.push(sender);
+        }
+    }
+
+    let existing = state
+        .storage
+        .get_inbound_email_address(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+    let address = match existing {
+        Some(mut address) => {
+            address.allowed_senders = senders.join(",");
+            address
+        }
+        None => InboundEmailAddress::new(user_id, &senders),
+    };
+    state
+        .storage
+        .save_inbound_email_address(&address)
+        .await
+        .map_err(|e| storage_error(e, "Failed to save inbound email address"))?;
+
+    Ok(Json(address.to_response(&config.domain)))
+}
+
+/// POST /api/user/inbound-email/rotate
+/// Replace the user's address, e.g. after it leaked
+#[utoipa::path(
+    post,
+    path = "/api/user/inbound-email/rotate",
+    tag = "user",
+    responses(
+        (status = 200, description = "New address; the old one stops working", body = InboundEmailResponse),
+        (status = 404, description = "Email-in is not configured, or no address set up", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn rotate_address(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<InboundEmailResponse>, AppError> {
+    let config = email_in_config(&state)?;
+    let mut address = find_address(&state, &user_id).await?;
+
+    address.rotate();
+    state
+        .storage
+        .save_inbound_email_address(&address)
+        .await
+        .map_err(|e| storage_error(e, "Failed to save inbound email address"))?;
+
+    Ok(Json(address.to_response(&config.domain)))
+}
+
+/// DELETE /api/user/inbound-email
+/// Stop accepting mail for the user
+#[utoipa::path(
+    delete,
+    path = "/api/user/inbound-email",
+    tag = "user",
+    responses(
+        (status = 200, description = "Address removed", body = SuccessResponse),
+        (status = 404, description = "No address set up", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn delete_address(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<SuccessResponse>, AppError> {
+    state
+        .storage
+        .delete_inbound_email_address(&user_id)
+        .await
+        .map_err(|e| match e {
+            DbError::InboundEmailAddressNotFound => {
+                AppError::new(StatusCode::NOT_FOUND, "No inbound email address set up")
+            }
+            _ => storage_error(e, "Failed to remove inbound email address"),
+        })?;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        handlers::AppState,
+        models::User,
+        testing::{auth_user, test_app_state},
+    };
+    use axum::body::Body;
+    use std::sync::Arc;
+
+    const SIGNING_KEY: &str = "mailgun-key";
+    const BOUNDARY: &str = "forwarded-email";
+
+    async fn setup_test_state() -> SharedState {
+        Arc::new(AppState {
+            inbound_email: InboundEmail::from_config(&InboundEmailConfig {
+                domain: Some("in.example.com".to_string()),
+                mailgun_signing_key: Some(SIGNING_KEY.to_string()),
+            }),
+            ..test_app_state().await
+        })
+    }
+
+    fn sign(timestamp: &str, token: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(SIGNING_KEY.as_bytes()).unwrap();
+        mac.update(timestamp.as_bytes());
+        mac.update(token.as_bytes());
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// A Mailgun forward of an email with a text and an image attachment
+    fn forwarded(recipient: &str, from: &str, signature: Option<&str>) -> Request {
+        let timestamp = Utc::now().timestamp().to_string();
+        let signature = signature
+            .map(str::to_string)
+            .unwrap_or_else(|| sign(&timestamp, "nonce"));
+        let fields = [
+            ("recipient", recipient),
+            ("from", from),
+            ("subject", "On the train"),
+            ("body-plain", "Wrote this on my phone.\n\n> quoted reply"),
+            ("stripped-text", "Wrote this on my phone."),
+            ("Message-Id", "<1234@mail.example.com>"),
+            ("timestamp", &timestamp),
+            ("token", "nonce"),
+            ("signature", &signature),
+        ];
+
+        let mut body = String::new();
+        for (name, value) in fields {
+            body.push_str(&format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                BOUNDARY, name, value
+            ));
+        }
+        for (name, filename, content_type, content) in [
+            ("attachment-1", "notes.txt", "text/plain", "Buy milk"),
+            ("attachment-2", "view.jpg", "image/jpeg", "\u{1}\u{2}"),
+        ] {
+            body.push_str(&format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\
+                 Content-Type: {}\r\n\r\n{}\r\n",
+                BOUNDARY, name, filename, content_type, content
+            ));
+        }
+        body.push_str(&format!("--{}--\r\n", BOUNDARY));
+
+        Request::builder()
+            .method("POST")
+            .uri("/api/inbound/mailgun")
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_mail_from_allowed_senders_becomes_an_entry() {
+        let state = setup_test_state().await;
+        let user = User::new(
+            "writer@example.com".to_string(),
+            "writer".to_string(),
+            "hash".to_string(),
+            "salt".to_string(),
+        );
+        state.storage.create_user(&user).await.unwrap();
+
+        let request = Json(SetInboundEmailRequest {
+            allowed_senders: vec!["Phone@Example.com".to_string()],
+        });
+        let Json(address) = set_address(State(state.clone()), auth_user(&user.id), request)
+            .await
+            .unwrap();
+        assert!(address.address.ends_with("@in.example.com"));
+        assert_eq!(address.allowed_senders, vec!["phone@example.com"]);
+        let recipient = address.address.replace('@', "+journal@");
+
+        let result = receive_mailgun(
+            State(state.clone()),
+            forwarded(&recipient, "Me <writer@example.com>", Some("00")),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::UNAUTHORIZED);
+        let result = receive_mailgun(
+            State(state.clone()),
+            forwarded(&recipient, "Someone <someone@example.com>", None),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::NOT_ACCEPTABLE);
+        let result = receive_mailgun(
+            State(state.clone()),
+            forwarded("nobody@in.example.com", "writer@example.com", None),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::NOT_ACCEPTABLE);
+
+        // Forwarded twice, saved once
+        for from in ["Me <writer@example.com>", "phone@example.com"] {
+            let result = receive_mailgun(State(state.clone()), forwarded(&recipient, from, None));
+            assert_eq!(result.await.unwrap(), StatusCode::OK);
+        }
+        let messages = state
+            .storage
+            .get_messages_for_user(&user.id, None)
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            messages[0].content,
+            "# On the train\n\nWrote this on my phone.\n\n## notes.txt\n\nBuy milk\n\n\
+             _Attachments not saved: view.jpg_"
+        );
+
+        // The old address stops working once rotated
+        let Json(rotated) = rotate_address(State(state.clone()), auth_user(&user.id))
+            .await
+            .unwrap();
+        assert_ne!(rotated.address, address.address);
+        let result = receive_mailgun(
+            State(state),
+            forwarded(&recipient, "writer@example.com", None),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::NOT_ACCEPTABLE);
+    }
+}
@@ -0,0 +1,1502 @@
+//! Dissipate's server as a library
+//!
+//! [`DissipateApp`] builds the same [`Router`] the `dissipate-backend` binary
+//! serves, so it can be nested under a path in another axum application:
+//!
+//! ```no_run
+//! # async fn run(config: dissipate_server::config::Config) -> anyhow::Result<()> {
+//! use dissipate_server::DissipateApp;
+//!
+//! let state = DissipateApp::state_from_config(&config).await?;
+//! let app = axum::Router::new().nest("/journal", DissipateApp::builder(state).build());
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod accesslog;
+pub mod admin;
+pub mod announcements;
+#[cfg(feature = "embed-frontend")]
+pub mod assets;
+pub mod audit;
+pub mod auth;
+pub mod avatars;
+pub mod backup;
+pub mod cache;
+pub mod destinations;
+pub mod digests;
+pub mod dormancy;
+pub mod error;
+pub mod export_jobs;
+pub mod exports;
+pub mod features;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod handlers;
+pub mod housekeeping;
+pub mod imports;
+pub mod inbound_email;
+pub mod mailer;
+pub mod maintenance;
+pub mod matrix;
+pub mod middleware;
+pub mod notifiers;
+pub mod openapi;
+pub mod outbound;
+pub mod pagination;
+pub mod proxy;
+pub mod push;
+pub mod ratelimit;
+pub mod realtime;
+pub mod reload;
+pub mod reminders;
+#[cfg(feature = "sentry")]
+pub mod reporting;
+pub mod shutdown;
+pub mod storage;
+pub mod telegram;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+pub mod tls;
+pub mod validation;
+pub mod webhooks;
+
+pub use dissipate_core::{config, db, export_format, ids, models, utils};
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{DefaultBodyLimit, FromRequestParts},
+    http::{request::Parts, StatusCode},
+    middleware::{from_fn_with_state, map_response},
+    routing::{delete, get, post, put},
+    Router,
+};
+use error::AppError;
+use handlers::{AppState, SharedState};
+use ids::UserId;
+use models::{Claims, User};
+use tokio::sync::OnceCell;
+use tower_http::{catch_panic::CatchPanicLayer, trace::TraceLayer};
+
+/// Authenticated user extractor
+///
+/// Set by `auth_middleware` with the claims of the request's token and the
+/// user's record it loaded to check the account, which [`AuthUser::user`]
+/// hands out without another lookup. Callers with only the claims leave the
+/// record to be looked up when it is first needed, and then shared by every
+/// clone.
+#[derive(Clone)]
+pub struct AuthUser {
+    pub id: UserId,
+    pub claims: Claims,
+    user: Arc<OnceCell<User>>,
+}
+
+impl AuthUser {
+    /// `id` signed in with a token carrying `claims`
+    pub fn new(id: UserId, claims: Claims) -> Self {
+        Self {
+            id,
+            claims,
+            user: Arc::default(),
+        }
+    }
+
+    /// `user` signed in with a token carrying `claims`, their record already
+    /// loaded
+    pub fn with_user(user: User, claims: Claims) -> Self {
+        Self {
+            id: user.id.clone(),
+            claims,
+            user: Arc::new(OnceCell::new_with(Some(user))),
+        }
+    }
+
+    /// The user's record, through the user cache
+    pub async fn user(&self, state: &AppState) -> Result<&User, AppError> {
+        self.user
+            .get_or_try_init(|| async {
+                state
+                    .users
+                    .find_by_id(&*state.storage, &self.id)
+                    .await
+                    .map_err(|e| handlers::storage_error(e, "Database error"))?
+                    .ok_or_else(|| AppError::new(StatusCode::UNAUTHORIZED, "Not authenticated"))
+            })
+            .await
+    }
+}
+
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for AuthUser
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<AuthUser>()
+            .cloned()
+            .ok_or_else(|| AppError::new(StatusCode::UNAUTHORIZED, "Not authenticated"))
+    }
+}
+
+/// Builds Dissipate's HTTP API and web app as a [`Router`]
+///
+/// Background work such as backups, reminders and webhook deliveries is
+/// started by the `dissipate-backend` binary, not by the router.
+pub struct DissipateApp;
+
+impl DissipateApp {
+    /// Start building the router around `state`
+    pub fn builder(state: SharedState) -> DissipateAppBuilder {
+        DissipateAppBuilder {
+            state,
+            cors: middleware::ReloadableCors::new(&middleware::CorsConfig::default()),
+            proxies: proxy::TrustedProxies::default(),
+            access_log: None,
+            reloader: None,
+            timeouts: middleware::Timeouts::default(),
+        }
+    }
+
+    /// Open the database in `config` and set up shared state as the server
+    /// does
+    pub async fn state_from_config(config: &config::Config) -> anyhow::Result<SharedState> {
+        let db_options =
+            db::DbOptions::from_config(&config.database).map_err(anyhow::Error::msg)?;
+        let pools = db::init_pools_with(&config.database.url, &db_options).await?;
+        let storage = Arc::new(storage::SqliteStorage::from_pools(pools));
+
+        Self::state_with_storage(config, storage).await
+    }
+
+    /// Set up shared state as [`Self::state_from_config`] does, over in-memory
+    /// storage instead of the database
+    ///
+    /// Nothing is written to disk, so everything is lost when the state is
+    /// dropped.
+    pub async fn ephemeral_state(config: &config::Config) -> anyhow::Result<SharedState> {
+        Self::state_with_storage(config, Arc::new(storage::MemoryStorage::new())).await
+    }
+
+    async fn state_with_storage(
+        config: &config::Config,
+        storage: storage::SharedStorage,
+    ) -> anyhow::Result<SharedState> {
+        let state = Arc::new(AppState::new(config, storage).map_err(anyhow::Error::msg)?);
+        state.features.load(&*state.storage).await?;
+
+        Ok(state)
+    }
+}
+
+/// Options for the router built by [`DissipateApp::builder`]
+pub struct DissipateAppBuilder {
+    state: SharedState,
+    cors: middleware::ReloadableCors,
+    proxies: proxy::TrustedProxies,
+    access_log: Option<accesslog::AccessLog>,
+    reloader: Option<reload::Reloader>,
+    timeouts: middleware::Timeouts,
+}
+
+impl DissipateAppBuilder {
+    /// CORS settings; by default any origin may call the API without
+    /// credentials
+    pub fn cors(mut self, cors: middleware::ReloadableCors) -> Self {
+        self.cors = cors;
+        self
+    }
+
+    /// Proxies whose `X-Forwarded-For` header is trusted; none by default
+    pub fn trusted_proxies(mut self, proxies: proxy::TrustedProxies) -> Self {
+        self.proxies = proxies;
+        self
+    }
+
+    /// Write a line per request to `access_log`
+    pub fn access_log(mut self, access_log: accesslog::AccessLog) -> Self {
+        self.access_log = Some(access_log);
+        self
+    }
+
+    /// Serve `POST /api/admin/reload`, applying changes through `reloader`
+    pub fn reloader(mut self, reloader: reload::Reloader) -> Self {
+        self.reloader = Some(reloader);
+        self
+    }
+
+    /// How long requests may take before they are abandoned
+    pub fn timeouts(mut self, timeouts: middleware::Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Build the router, ready to serve or to nest under a path
+    pub fn build(self) -> Router {
+        create_router(
+            self.state,
+            self.cors,
+            self.proxies,
+            self.access_log,
+            self.reloader,
+            self.timeouts,
+        )
+    }
+}
+
+/// Create the application router
+fn create_router(
+    state: SharedState,
+    cors: middleware::ReloadableCors,
+    proxies: proxy::TrustedProxies,
+    access_log: Option<accesslog::AccessLog>,
+    reloader: Option<reload::Reloader>,
+    timeouts: middleware::Timeouts,
+) -> Router {
+    // Public routes (no auth required)
+    let public_routes = Router::new()
+        .route("/api/health", get(handlers::health))
+        // Probes for Docker healthchecks and Kubernetes
+        .route("/healthz", get(handlers::healthz))
+        .route("/readyz", get(handlers::readyz))
+        .route("/api/openapi.json", get(openapi::openapi_json))
+        .route("/api/login", post(handlers::login))
+        .route("/api/register", post(handlers::register))
+        .route("/api/config", get(features::get_config))
+        .route("/api/announcements", get(announcements::list_active))
+        // The token in emailed links stands in for a login
+        .route(
+            "/api/exports/:id/download/:token",
+            get(export_jobs::download_export_by_token),
+        )
+        // Authenticates itself, since browsers can't send headers on upgrade
+        .route("/api/ws", get(realtime::ws_handler))
+        // Signed by the email provider
+        .route(
+            "/api/inbound/mailgun",
+            post(inbound_email::receive_mailgun)
+                .layer(DefaultBodyLimit::max(inbound_email::INBOUND_BODY_LIMIT)),
+        )
+        .layer(from_fn_with_state(
+            state.clone(),
+            middleware::rate_limit_middleware,
+        ));
+
+    // Protected routes (auth required)
+    let protected_routes = Router::new()
+        // Messages
+        .route("/api/messages", get(handlers::get_messages_cached))
+        .route("/api/messages", post(handlers::create_message))
+        .route("/api/messages/:id", put(handlers::update_message))
+        .route("/api/messages/:id", delete(handlers::delete_message))
+        .route("/api/messages/:id/reactions", post(handlers::add_reaction))
+        .route(
+            "/api/messages/:id/reactions/:emoji",
+            delete(handlers::remove_reaction),
+        )
+        // Push notifications
+        .route("/api/push/vapid-public-key", get(push::vapid_public_key))
+        .route(
+            "/api/push/subscriptions",
+            post(push::subscribe).delete(push::unsubscribe),
+        )
+        // Sync
+        .route("/api/sync", get(handlers::sync).post(handlers::batch_sync))
+        // Templates
+        .route("/api/templates", get(handlers::get_templates))
+        .route("/api/templates", post(handlers::create_template))
+        .route("/api/templates/:id", put(handlers::update_template))
+        .route("/api/templates/:id", delete(handlers::delete_template))
+        // Shared journals
+        .route("/api/journal/members", get(handlers::list_journal_members))
+        .route(
+            "/api/journal/members",
+            post(handlers::invite_journal_member),
+        )
+        .route(
+            "/api/journal/members/:member_id",
+            delete(handlers::remove_journal_member),
+        )
+        .route("/api/journals", get(handlers::list_shared_journals))
+        .route(
+            "/api/journals/:owner_id/messages",
+            get(handlers::get_journal_messages).post(handlers::create_journal_message),
+        )
+        // User management
+        .route("/api/user/email", put(handlers::update_email))
+        .route("/api/user/username", put(handlers::update_username))
+        .route("/api/user/display-name", put(handlers::update_display_name))
+        .route("/api/user/password", put(handlers::update_password))
+        .route("/api/user/usage", get(handlers::get_usage))
+        .route(
+            "/api/user/avatar",
+            put(avatars::upload_avatar).layer(DefaultBodyLimit::max(avatars::AVATAR_BODY_LIMIT)),
+        )
+        .route("/api/user/:id/avatar", get(avatars::get_avatar))
+        .route(
+            "/api/user/webhooks",
+            get(webhooks::list_webhooks).post(webhooks::create_webhook),
+        )
+        .route("/api/user/webhooks/:id", delete(webhooks::delete_webhook))
+        .route(
+            "/api/user/webhooks/:id/deliveries",
+            get(webhooks::list_deliveries),
+        )
+        .route("/api/user/announcements", get(announcements::list_for_user))
+        .route(
+            "/api/user/announcements/:id/dismiss",
+            post(announcements::dismiss),
+        )
+        .route(
+            "/api/user/digest",
+            get(digests::get_subscription)
+                .put(digests::set_subscription)
+                .delete(digests::delete_subscription),
+        )
+        .route(
+            "/api/user/reminder",
+            get(reminders::get_reminder)
+                .put(reminders::set_reminder)
+                .delete(reminders::delete_reminder),
+        )
+        .route(
+            "/api/user/notification-channels",
+            get(notifiers::list_channels).post(notifiers::create_channel),
+        )
+        .route(
+            "/api/user/notification-channels/:id",
+            delete(notifiers::delete_channel),
+        )
+        .route(
+            "/api/user/notification-channels/:id/test",
+            post(notifiers::test_channel),
+        )
+        .route(
+            "/api/user/telegram",
+            get(telegram::get_link).delete(telegram::unlink),
+        )
+        .route("/api/user/telegram/code", post(telegram::create_link_code))
+        .route(
+            "/api/user/inbound-email",
+            get(inbound_email::get_address)
+                .put(inbound_email::set_address)
+                .delete(inbound_email::delete_address),
+        )
+        .route(
+            "/api/user/inbound-email/rotate",
+            post(inbound_email::rotate_address),
+        )
+        .route(
+            "/api/user/dormancy",
+            get(dormancy::get_policy)
+                .put(dormancy::set_policy)
+                .delete(dormancy::delete_policy),
+        )
+        .route(
+            "/api/user/matrix",
+            get(matrix::get_account)
+                .put(matrix::set_account)
+                .delete(matrix::delete_account),
+        )
+        // Exports
+        .route("/api/export/json", get(exports::export_json))
+        .route("/api/export/markdown", get(exports::export_markdown))
+        .route("/api/export/html", get(exports::export_html))
+        .route("/api/export/ics", get(exports::export_ics))
+        .route("/api/export/account", get(exports::export_account))
+        .route("/api/exports", post(export_jobs::create_export))
+        .route("/api/exports/:id", get(export_jobs::get_export))
+        .route(
+            "/api/exports/:id/progress",
+            get(export_jobs::get_export_progress),
+        )
+        .route(
+            "/api/exports/:id/download",
+            get(export_jobs::download_export),
+        )
+        .route(
+            "/api/export-schedules",
+            get(export_jobs::list_schedules).post(export_jobs::create_schedule),
+        )
+        .route(
+            "/api/export-schedules/:id",
+            delete(export_jobs::delete_schedule),
+        )
+        .route(
+            "/api/export-destinations/webdav",
+            get(export_jobs::get_webdav_destination)
+                .put(export_jobs::set_webdav_destination)
+                .delete(export_jobs::delete_webdav_destination),
+        )
+        // Imports
+        .route(
+            "/api/import/dayone",
+            post(imports::import_dayone).layer(DefaultBodyLimit::max(imports::IMPORT_BODY_LIMIT)),
+        )
+        .route(
+            "/api/import/journey",
+            post(imports::import_journey).layer(DefaultBodyLimit::max(imports::IMPORT_BODY_LIMIT)),
+        )
+        .route(
+            "/api/import/markdown",
+            post(imports::import_markdown).layer(DefaultBodyLimit::max(imports::IMPORT_BODY_LIMIT)),
+        );
+
+    #[cfg(feature = "graphql")]
+    let protected_routes = protected_routes.route(
+        "/api/graphql",
+        post(graphql::execute).layer(axum::Extension(graphql::schema())),
+    );
+
+    let protected_routes = protected_routes
+        .layer(from_fn_with_state(
+            state.clone(),
+            middleware::idempotency_middleware,
+        ))
+        // Inside auth, so authenticated requests are limited per user
+        .layer(from_fn_with_state(
+            state.clone(),
+            middleware::rate_limit_middleware,
+        ))
+        .layer(from_fn_with_state(state.clone(), middleware::auth_middleware));
+
+    // Operator routes, authenticated with ADMIN_TOKEN or an admin user's JWT
+    let mut admin_routes = Router::new()
+        .route("/api/admin/checkpoint", post(maintenance::checkpoint))
+        .route(
+            "/api/admin/maintenance",
+            get(maintenance::get_maintenance)
+                .put(maintenance::enable_maintenance)
+                .delete(maintenance::disable_maintenance),
+        )
+        .route("/api/admin/wal", get(maintenance::wal_status))
+        .route("/api/admin/integrity", get(maintenance::integrity))
+        .route("/api/admin/stats", get(maintenance::stats))
+        .route("/api/admin/audit", get(audit::list_audit_events))
+        .route("/api/admin/features", get(features::list_features))
+        .route(
+            "/api/admin/features/:name",
+            put(features::set_feature).delete(features::reset_feature),
+        )
+        .route(
+            "/api/admin/announcements",
+            get(announcements::list_all).post(announcements::create),
+        )
+        .route(
+            "/api/admin/announcements/:id",
+            put(announcements::update).delete(announcements::delete),
+        )
+        .route(
+            "/api/admin/users",
+            get(admin::list_users).post(admin::create_user),
+        )
+        .route("/api/admin/users/:id", delete(admin::delete_user))
+        .route("/api/admin/users/:id/password", put(admin::reset_password))
+        .route("/api/admin/users/:id/role", put(admin::set_role))
+        .route("/api/admin/users/:id/disabled", put(admin::set_disabled));
+    if let Some(reloader) = reloader {
+        admin_routes = admin_routes.route(
+            "/api/admin/reload",
+            post(reload::reload_config).with_state(reloader),
+        );
+    }
+    let admin_routes = admin_routes.layer(from_fn_with_state(
+        state.clone(),
+        middleware::admin_middleware,
+    ));
+
+    let app = Router::new()
+        .merge(public_routes)
+        .merge(protected_routes)
+        .merge(admin_routes);
+
+    // Interactive docs for the spec served at /api/openapi.json
+    #[cfg(feature = "swagger-ui")]
+    let app = app.merge(
+        utoipa_swagger_ui::SwaggerUi::new("/api/docs")
+            .config(utoipa_swagger_ui::Config::from("/api/openapi.json")),
+    );
+
+    // The frontend, from inside the binary or from ./dist next to it
+    #[cfg(feature = "embed-frontend")]
+    let app = app.fallback(assets::serve);
+    #[cfg(not(feature = "embed-frontend"))]
+    let app = app.fallback_service(tower_http::services::ServeDir::new("dist"));
+
+    let app = app
+        .layer(from_fn_with_state(
+            state.clone(),
+            middleware::maintenance_middleware,
+        ))
+        .layer(map_response(middleware::retry_after))
+        .layer(axum::middleware::from_fn(middleware::json_errors))
+        .layer(middleware::compression_layer())
+        .layer(from_fn_with_state(cors, middleware::cors_middleware))
+        .layer(TraceLayer::new_for_http())
+        .layer(from_fn_with_state(timeouts, middleware::timeout_middleware));
+
+    // Panics become JSON 500s, after Sentry's panic hook has reported them
+    // with the request hub's details; other server errors are reported as
+    // they pass back out
+    #[cfg(feature = "sentry")]
+    let app = app.layer(axum::middleware::from_fn(reporting::capture_server_errors));
+    let app = app.layer(CatchPanicLayer::custom(middleware::panic_response));
+    #[cfg(feature = "sentry")]
+    let app = app
+        .layer(sentry::integrations::tower::SentryHttpLayer::new())
+        .layer(sentry::integrations::tower::NewSentryLayer::<
+            axum::extract::Request,
+        >::new_from_top());
+
+    app.layer(from_fn_with_state(
+        access_log,
+        middleware::access_log_middleware,
+    ))
+    // Outermost, so every layer and handler sees the real client address
+    .layer(from_fn_with_state(
+        proxies,
+        middleware::client_ip_middleware,
+    ))
+    .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        authed_json_request, authed_request, body_json, create_test_user,
+        create_test_user_and_login, test_app,
+    };
+    use axum::{
+        body::Body,
+        http::{header, Request, StatusCode},
+    };
+    use http_body_util::BodyExt;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_login_endpoint() {
+        let (app, state) = test_app().await;
+
+        create_test_user(&state, "login@example.com", "loginuser").await;
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/login")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                json!({
+                    "email": "login@example.com",
+                    "password": "password123"
+                })
+                .to_string(),
+            ))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let json = body_json(response).await;
+        assert!(json.get("token").is_some());
+        assert_eq!(json["user"]["email"], "login@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_login_disabled_user() {
+        let (app, state) = test_app().await;
+        let (user_id, _) = create_test_user_and_login(&state).await;
+        state
+            .storage
+            .set_user_disabled(&user_id, true)
+            .await
+            .unwrap();
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/login")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                json!({
+                    "email": "test@example.com",
+                    "password": "password123"
+                })
+                .to_string(),
+            ))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let json = body_json(response).await;
+        assert_eq!(json["code"], "account_disabled");
+    }
+
+    #[tokio::test]
+    async fn test_openapi_spec_is_public() {
+        let (app, _) = test_app().await;
+
+        let request = Request::builder()
+            .method("GET")
+            .uri("/api/openapi.json")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let spec = body_json(response).await;
+        assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+        assert!(spec["paths"]["/api/messages/{id}"]["put"].is_object());
+        assert!(spec["components"]["schemas"]["MessageResponse"].is_object());
+        assert!(spec["components"]["securitySchemes"]["bearer_auth"].is_object());
+        assert!(spec["paths"]["/api/login"]["post"]["security"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_get_messages_requires_auth() {
+        let (app, _) = test_app().await;
+
+        let request = Request::builder()
+            .method("GET")
+            .uri("/api/messages")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_get_messages_with_auth() {
+        let (app, state) = test_app().await;
+        let (_, token) = create_test_user_and_login(&state).await;
+
+        let request = authed_request("GET", "/api/messages", &token)
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let json = body_json(response).await;
+        assert!(json["messages"].is_array());
+    }
+
+    #[tokio::test]
+    async fn test_get_messages_etag() {
+        let (app, state) = test_app().await;
+        let (_, token) = create_test_user_and_login(&state).await;
+
+        let request = authed_request("GET", "/api/messages", &token)
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers().get(header::ETAG).unwrap().clone();
+
+        let request = authed_request("GET", "/api/messages", &token)
+            .header(header::IF_NONE_MATCH, etag)
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[tokio::test]
+    async fn test_large_responses_are_compressed() {
+        let (app, state) = test_app().await;
+        let (user_id, token) = create_test_user_and_login(&state).await;
+
+        for i in 0..50 {
+            let msg = models::Message::new(user_id.clone(), format!("Compressible entry {}", i));
+            state.storage.create_message(&msg).await.unwrap();
+        }
+
+        let request = authed_request("GET", "/api/messages", &token)
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_ENCODING).unwrap(),
+            "gzip"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_admin_routes_disabled_without_token() {
+        let (app, _state) = test_app().await;
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/admin/checkpoint")
+            .header(header::AUTHORIZATION, "Bearer anything")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_admin_user_management() {
+        let (app, state) = test_app().await;
+        let (admin_id, token) = create_test_user_and_login(&state).await;
+        let admin_request = |method: &str, uri: &str, body: serde_json::Value| {
+            authed_json_request(method, uri, &token, &body)
+        };
+
+        let request = admin_request("GET", "/api/admin/users", json!({}));
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        state
+            .storage
+            .update_user_role(&admin_id, models::ROLE_ADMIN)
+            .await
+            .unwrap();
+        state.users.invalidate(&admin_id).await;
+
+        let request = admin_request(
+            "POST",
+            "/api/admin/users",
+            json!({"email": "new@example.com", "username": "new", "password": "password123"}),
+        );
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let json = body_json(response).await;
+        assert_eq!(json["role"], "user");
+        let user_id: UserId = json["id"].as_str().unwrap().parse().unwrap();
+
+        let request = admin_request("GET", "/api/admin/users", json!({}));
+        let response = app.clone().oneshot(request).await.unwrap();
+        let json = body_json(response).await;
+        assert_eq!(json["users"].as_array().unwrap().len(), 2);
+        assert_eq!(json["users"][0]["role"], "admin");
+
+        let request = admin_request("GET", "/api/admin/users?sort=email&limit=1", json!({}));
+        let response = app.clone().oneshot(request).await.unwrap();
+        let json = body_json(response).await;
+        assert_eq!(json["users"].as_array().unwrap().len(), 1);
+        assert_eq!(json["users"][0]["email"], "new@example.com");
+        let request = admin_request("GET", "/api/admin/users?sort=password", json!({}));
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let json = body_json(response).await;
+        assert_eq!(json["fields"][0]["field"], "sort");
+
+        let uri = format!("/api/admin/users/{}/role", user_id);
+        let request = admin_request("PUT", &uri, json!({"role": "owner"}));
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let request = admin_request("PUT", &uri, json!({"role": "admin"}));
+        let response = app.clone().oneshot(request).await.unwrap();
+        let json = body_json(response).await;
+        assert_eq!(json["role"], "admin");
+
+        let uri = format!("/api/admin/users/{}/password", user_id);
+        let request = admin_request("PUT", &uri, json!({"password": "changed-password"}));
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let user = state
+            .storage
+            .find_user_by_id(&user_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(utils::verify_password("changed-password", &user.password_hash).unwrap());
+
+        let uri = format!("/api/admin/users/{}", user_id);
+        let request = admin_request("DELETE", &uri, json!({}));
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let request = admin_request("DELETE", &uri, json!({}));
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let uri = format!("/api/admin/audit?target_id={}&limit=2", user_id);
+        let request = admin_request("GET", &uri, json!({}));
+        let response = app.clone().oneshot(request).await.unwrap();
+        let json = body_json(response).await;
+        let events = json["events"].as_array().unwrap();
+        assert_eq!(events[0]["action"], "admin.user.delete");
+        assert_eq!(events[0]["actor"], admin_id.as_str());
+        assert_eq!(events[0]["metadata"]["email"], "new@example.com");
+        assert_eq!(events[1]["action"], "admin.user.reset_password");
+        let cursor = json["next_before"].as_i64().unwrap();
+
+        let uri = format!("/api/admin/audit?target_id={}&cursor={}", user_id, cursor);
+        let request = admin_request("GET", &uri, json!({}));
+        let response = app.oneshot(request).await.unwrap();
+        let json = body_json(response).await;
+        let events = json["events"].as_array().unwrap();
+        assert!(events.iter().all(|e| e["id"].as_i64().unwrap() < cursor));
+        assert_eq!(events.last().unwrap()["action"], "admin.user.create");
+    }
+
+    #[tokio::test]
+    async fn test_announcements() {
+        let (app, state) = test_app().await;
+        let (admin_id, token) = create_test_user_and_login(&state).await;
+        state
+            .storage
+            .update_user_role(&admin_id, models::ROLE_ADMIN)
+            .await
+            .unwrap();
+        state.users.invalidate(&admin_id).await;
+        let request = |method: &str, uri: &str, body: serde_json::Value| {
+            authed_json_request(method, uri, &token, &body)
+        };
+        let public = || {
+            Request::builder()
+                .uri("/api/announcements")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request(
+                "POST",
+                "/api/admin/announcements",
+                json!({"message": "Down for upgrades", "level": "urgent"}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .clone()
+            .oneshot(request(
+                "POST",
+                "/api/admin/announcements",
+                json!({"message": "Down for upgrades", "level": "warning"}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let json = body_json(response).await;
+        let id = json["id"].as_str().unwrap().to_string();
+
+        // Shown without logging in, and to users until they dismiss it
+        let response = app.clone().oneshot(public()).await.unwrap();
+        let json = body_json(response).await;
+        assert_eq!(json["announcements"][0]["message"], "Down for upgrades");
+
+        let uri = format!("/api/user/announcements/{}/dismiss", id);
+        let response = app
+            .clone()
+            .oneshot(request("POST", &uri, json!({})))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app
+            .clone()
+            .oneshot(request("GET", "/api/user/announcements", json!({})))
+            .await
+            .unwrap();
+        let json = body_json(response).await;
+        assert_eq!(json["announcements"], json!([]));
+
+        let uri = format!("/api/admin/announcements/{}", id);
+        let response = app
+            .clone()
+            .oneshot(request("DELETE", &uri, json!({})))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = app.clone().oneshot(public()).await.unwrap();
+        let json = body_json(response).await;
+        assert_eq!(json["announcements"], json!([]));
+
+        let response = app
+            .oneshot(request("DELETE", &uri, json!({})))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_feature_flags() {
+        let (app, state) = test_app().await;
+        let (admin_id, token) = create_test_user_and_login(&state).await;
+        state
+            .storage
+            .update_user_role(&admin_id, models::ROLE_ADMIN)
+            .await
+            .unwrap();
+        state.users.invalidate(&admin_id).await;
+        let request = |method: &str, uri: &str, body: serde_json::Value| {
+            authed_json_request(method, uri, &token, &body)
+        };
+        let register = || {
+            Request::builder()
+                .method("POST")
+                .uri("/api/register")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    json!({"email": "new@example.com", "username": "new", "password": "password123"})
+                        .to_string(),
+                ))
+                .unwrap()
+        };
+
+        // Registration is closed unless the config or an admin opens it
+        let response = app.clone().oneshot(register()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let json = body_json(response).await;
+        assert_eq!(json["code"], "feature_disabled");
+
+        let response = app
+            .clone()
+            .oneshot(request(
+                "PUT",
+                "/api/admin/features/registration",
+                json!({"enabled": true}),
+            ))
+            .await
+            .unwrap();
+        let json = body_json(response).await;
+        assert_eq!(
+            json,
+            json!({"name": "registration", "enabled": true, "overridden": true})
+        );
+
+        let response = app.clone().oneshot(register()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let json = body_json(response).await;
+        assert!(json["token"].is_string());
+        let response = app.clone().oneshot(register()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        // Turning sharing off closes shared journals
+        app.clone()
+            .oneshot(request(
+                "PUT",
+                "/api/admin/features/sharing",
+                json!({"enabled": false}),
+            ))
+            .await
+            .unwrap();
+        let response = app
+            .clone()
+            .oneshot(request(
+                "POST",
+                "/api/journal/members",
+                json!({"email": "new@example.com", "role": "read"}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let config = Request::builder()
+            .uri("/api/config")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(config).await.unwrap();
+        let json = body_json(response).await;
+        assert_eq!(
+            json["features"],
+            json!({"registration": true, "sharing": false, "attachments": false})
+        );
+
+        // Resetting goes back to the config
+        let response = app
+            .clone()
+            .oneshot(request("DELETE", "/api/admin/features/sharing", json!({})))
+            .await
+            .unwrap();
+        let json = body_json(response).await;
+        assert_eq!(
+            json,
+            json!({"name": "sharing", "enabled": true, "overridden": false})
+        );
+
+        let response = app
+            .oneshot(request(
+                "PUT",
+                "/api/admin/features/teleportation",
+                json!({"enabled": true}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_admin_stats() {
+        let (app, state) = test_app().await;
+        let (admin_id, token) = create_test_user_and_login(&state).await;
+        state
+            .storage
+            .update_user_role(&admin_id, models::ROLE_ADMIN)
+            .await
+            .unwrap();
+        let stats_request = |uri: &str| {
+            authed_request("GET", uri, &token)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let request = stats_request("/api/admin/stats?days=7");
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let json = body_json(response).await;
+        assert_eq!(json["user_count"], 1);
+        assert_eq!(json["messages_per_day"].as_object().unwrap().len(), 7);
+        assert_eq!(json["active_sessions"], 0);
+        assert!(json["last_backup_at"].is_null());
+        assert!(json["last_optimize"].is_null());
+
+        let request = stats_request("/api/admin/stats?days=0");
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_health() {
+        let (app, _) = test_app().await;
+
+        let request = Request::builder()
+            .uri("/api/health")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let json = body_json(response).await;
+        assert_eq!(json, json!({"status": "ok"}));
+    }
+
+    #[tokio::test]
+    async fn test_builder_router_can_be_nested() {
+        let (_, state) = test_app().await;
+        let app = Router::new().nest("/journal", DissipateApp::builder(state).build());
+
+        let request = Request::builder()
+            .uri("/journal/api/health")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_liveness_and_readiness_probes() {
+        let (app, _) = test_app().await;
+
+        let request = Request::builder()
+            .uri("/healthz")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let request = Request::builder()
+            .uri("/readyz")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let json = body_json(response).await;
+        assert_eq!(json, json!({"status": "ready"}));
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_mode() {
+        let (app, state) = test_app().await;
+        state
+            .maintenance_mode
+            .enable(Some("Migrating, back soon".to_string()), Some(120));
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/login")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"email":"a@example.com","password":"x"}"#))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "120");
+        let json = body_json(response).await;
+        assert_eq!(json["error"], "Migrating, back soon");
+
+        for uri in ["/api/health", "/healthz", "/readyz"] {
+            let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+        }
+
+        state.maintenance_mode.disable();
+        let request = Request::builder()
+            .uri("/api/messages")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_create_message() {
+        let (app, state) = test_app().await;
+        let (_, token) = create_test_user_and_login(&state).await;
+
+        let request = authed_json_request(
+            "POST",
+            "/api/messages",
+            &token,
+            &json!({"content": "Hello, world!"}),
+        );
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let json = body_json(response).await;
+        assert_eq!(json["content"], "Hello, world!");
+    }
+
+    #[tokio::test]
+    async fn test_create_message_idempotency_key() {
+        let (app, state) = test_app().await;
+        let (user_id, token) = create_test_user_and_login(&state).await;
+
+        let request = || {
+            authed_request("POST", "/api/messages", &token)
+                .header(header::CONTENT_TYPE, "application/json")
+                .header("Idempotency-Key", "retry-1")
+                .body(Body::from(json!({"content": "Once"}).to_string()))
+                .unwrap()
+        };
+
+        let first = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(first.status(), StatusCode::CREATED);
+        let retry = app.oneshot(request()).await.unwrap();
+        assert_eq!(retry.status(), StatusCode::CREATED);
+
+        let first = first.into_body().collect().await.unwrap().to_bytes();
+        let retry = retry.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(first, retry);
+        let messages = state
+            .storage
+            .get_messages_for_user(&user_id, None)
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_update_message() {
+        let (app, state) = test_app().await;
+        let (user_id, token) = create_test_user_and_login(&state).await;
+
+        // Create a message first
+        let msg = models::Message::new(user_id, "Original".to_string());
+        let msg_id = msg.id.clone();
+        state.storage.create_message(&msg).await.unwrap();
+
+        let request = authed_json_request(
+            "PUT",
+            &format!("/api/messages/{}", msg_id),
+            &token,
+            &json!({"content": "Updated"}),
+        );
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let json = body_json(response).await;
+        assert_eq!(json["content"], "Updated");
+    }
+
+    #[tokio::test]
+    async fn test_delete_message() {
+        let (app, state) = test_app().await;
+        let (user_id, token) = create_test_user_and_login(&state).await;
+
+        let msg = models::Message::new(user_id, "Delete me".to_string());
+        let msg_id = msg.id.clone();
+        state.storage.create_message(&msg).await.unwrap();
+
+        let request = authed_request("DELETE", &format!("/api/messages/{}", msg_id), &token)
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Verify deleted
+        let deleted = state.storage.get_message_by_id(&msg_id).await.unwrap();
+        assert!(deleted.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_template_crud() {
+        let (app, state) = test_app().await;
+        let (_, token) = create_test_user_and_login(&state).await;
+
+        let request = authed_request("POST", "/api/templates", &token)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                json!({"name": "Daily", "content": "Wins today:"}).to_string(),
+            ))
+            .unwrap();
+
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let json = body_json(response).await;
+        let template_id = json["id"].as_str().unwrap().to_string();
+
+        let request = authed_json_request(
+            "POST",
+            "/api/messages",
+            &token,
+            &json!({"template_id": template_id}),
+        );
+
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let json = body_json(response).await;
+        assert_eq!(json["content"], "Wins today:");
+
+        let request = authed_request("DELETE", &format!("/api/templates/{}", template_id), &token)
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_update_email() {
+        let (app, state) = test_app().await;
+        let (user_id, token) = create_test_user_and_login(&state).await;
+
+        let request = authed_json_request(
+            "PUT",
+            "/api/user/email",
+            &token,
+            &json!({"email": "new@example.com"}),
+        );
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let user = state
+            .storage
+            .find_user_by_id(&user_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.email, "new@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_update_username() {
+        let (app, state) = test_app().await;
+        let (user_id, token) = create_test_user_and_login(&state).await;
+
+        let request = authed_json_request(
+            "PUT",
+            "/api/user/username",
+            &token,
+            &json!({"username": "newname"}),
+        );
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let user = state
+            .storage
+            .find_user_by_id(&user_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.username, "newname");
+    }
+
+    #[tokio::test]
+    async fn test_update_password() {
+        let (app, state) = test_app().await;
+        let (_, token) = create_test_user_and_login(&state).await;
+
+        let request = authed_request("PUT", "/api/user/password", &token)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                json!({
+                    "current_password": "password123",
+                    "new_password": "newpassword456"
+                })
+                .to_string(),
+            ))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_avatar_upload() {
+        let (app, state) = test_app().await;
+        let (user_id, token) = create_test_user_and_login(&state).await;
+        let uri = format!("/api/user/{}/avatar", user_id);
+
+        let request = authed_request("GET", &uri, &token)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let mut png = Vec::new();
+        image::RgbaImage::new(600, 400)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let request = authed_request("PUT", "/api/user/avatar", &token)
+            .header(header::CONTENT_TYPE, "image/gif")
+            .body(Body::from(png.clone()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let request = authed_request("PUT", "/api/user/avatar", &token)
+            .header(header::CONTENT_TYPE, "image/png")
+            .body(Body::from(png))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let request = authed_request("GET", &uri, &token)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let avatar = image::load_from_memory(&body).unwrap();
+        assert_eq!((avatar.width(), avatar.height()), (256, 171));
+    }
+
+    #[tokio::test]
+    async fn test_export_json() {
+        let (app, state) = test_app().await;
+        let (_, token) = create_test_user_and_login(&state).await;
+
+        let request = authed_request("GET", "/api/export/json", &token)
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let content_type = response.headers().get(header::CONTENT_TYPE).unwrap();
+        assert_eq!(content_type, "application/json");
+    }
+
+    #[tokio::test]
+    async fn test_export_markdown() {
+        let (app, state) = test_app().await;
+        let (_, token) = create_test_user_and_login(&state).await;
+
+        let request = authed_request("GET", "/api/export/markdown", &token)
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let content_type = response.headers().get(header::CONTENT_TYPE).unwrap();
+        assert!(content_type.to_str().unwrap().contains("text/markdown"));
+    }
+
+    #[tokio::test]
+    async fn test_import_dayone() {
+        let (app, state) = test_app().await;
+        let (_, token) = create_test_user_and_login(&state).await;
+
+        let request = authed_request("POST", "/api/import/dayone", &token)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                json!({
+                    "entries": [
+                        {"creationDate": "2021-06-01T12:00:00Z", "text": "From Day One"}
+                    ]
+                })
+                .to_string(),
+            ))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let json = body_json(response).await;
+        assert_eq!(json["imported"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_cors_headers() {
+        let (app, _) = test_app().await;
+
+        let request = Request::builder()
+            .method("OPTIONS")
+            .uri("/api/login")
+            .header(header::ORIGIN, "http://localhost:5173")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        
+        // CORS should allow the request
+        assert!(response.headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    #[tokio::test]
+    async fn test_user_isolation() {
+        let (app, state) = test_app().await;
+
+        // Create two users
+        let user1 = create_test_user(&state, "user1@example.com", "user1").await;
+        let token1 = state.auth.issue_token(&user1.id).await.unwrap();
+        let user2 = create_test_user(&state, "user2@example.com", "user2").await;
+        let token2 = state.auth.issue_token(&user2.id).await.unwrap();
+
+        // User1 creates a message
+        let msg = models::Message::new(user1.id.clone(), "User 1's secret".to_string());
+        state.storage.create_message(&msg).await.unwrap();
+
+        // User2 should not see User1's messages
+        let request = authed_request("GET", "/api/messages", &token2)
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.clone().oneshot(request).await.unwrap();
+        let json = body_json(response).await;
+
+        assert_eq!(json["messages"].as_array().unwrap().len(), 0);
+
+        // User1 should see their own messages
+        let request = authed_request("GET", "/api/messages", &token1)
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        let json = body_json(response).await;
+
+        assert_eq!(json["messages"].as_array().unwrap().len(), 1);
+    }
+}
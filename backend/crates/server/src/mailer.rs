@@ -0,0 +1,171 @@
+#[cfg(feature = "smtp")]
+use lettre::{
+    message::{header::ContentType, Attachment, Mailbox, MultiPart, SinglePart},
+    AsyncSmtpTransport, AsyncTransport, Message as Email, Tokio1Executor,
+};
+
+use crate::config::SmtpConfig;
+
+/// File attached to an email
+#[derive(Debug, Clone)]
+pub struct EmailAttachment {
+    pub filename: String,
+    pub content_type: String,
+    pub body: Vec<u8>,
+}
+
+/// Sends email through an SMTP relay
+#[cfg(feature = "smtp")]
+pub struct Mailer {
+    from: Mailbox,
+    /// Base URL of the app, used for links in emails
+    public_url: Option<String>,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+#[cfg(feature = "smtp")]
+impl Mailer {
+    /// Build from the `[smtp]` settings; email is disabled unless a URL is set
+    ///
+    /// `public_url` is the address the app is served from; emails can only
+    /// contain download links when it is set.
+    pub fn from_config(
+        smtp: &SmtpConfig,
+        public_url: Option<&str>,
+    ) -> Result<Option<Self>, String> {
+        let Some(url) = &smtp.url else {
+            return Ok(None);
+        };
+        let from = smtp
+            .from
+            .as_deref()
+            .ok_or_else(|| "SMTP_FROM is required when SMTP_URL is set".to_string())?;
+        let public_url = public_url
+            .map(|url| url.trim_end_matches('/').to_string())
+            .filter(|url| !url.is_empty());
+
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(url)
+            .map_err(|e| format!("Invalid SMTP_URL: {}", e))?
+            .build();
+
+        Ok(Some(Self {
+            from: from
+                .parse()
+                .map_err(|e| format!("Invalid SMTP_FROM: {}", e))?,
+            public_url,
+            transport,
+        }))
+    }
+
+    pub fn public_url(&self) -> Option<&str> {
+        self.public_url.as_deref()
+    }
+
+    /// Send a plain text email, with an optional attachment
+    pub async fn send(
+        &self,
+        to: &str,
+        subject: &str,
+        body: String,
+        attachment: Option<EmailAttachment>,
+    ) -> Result<(), String> {
+        let to: Mailbox = to
+            .parse()
+            .map_err(|e| format!("Invalid recipient {}: {}", to, e))?;
+        let builder = Email::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject);
+
+        let email = match attachment {
+            None => builder.singlepart(SinglePart::plain(body)),
+            Some(attachment) => {
+                let content_type = ContentType::parse(&attachment.content_type)
+                    .map_err(|e| format!("Invalid attachment type: {}", e))?;
+                builder.multipart(
+                    MultiPart::mixed()
+                        .singlepart(SinglePart::plain(body))
+                        .singlepart(
+                            Attachment::new(attachment.filename)
+                                .body(attachment.body, content_type),
+                        ),
+                )
+            }
+        }
+        .map_err(|e| format!("Failed to build email: {}", e))?;
+
+        self.deliver(email).await
+    }
+
+    /// Send an email with HTML and plain text versions of the same body
+    pub async fn send_html(
+        &self,
+        to: &str,
+        subject: &str,
+        text: String,
+        html: String,
+    ) -> Result<(), String> {
+        let to: Mailbox = to
+            .parse()
+            .map_err(|e| format!("Invalid recipient {}: {}", to, e))?;
+        let email = Email::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .multipart(MultiPart::alternative_plain_html(text, html))
+            .map_err(|e| format!("Failed to build email: {}", e))?;
+
+        self.deliver(email).await
+    }
+
+    async fn deliver(&self, email: Email) -> Result<(), String> {
+        self.transport
+            .send(email)
+            .await
+            .map_err(|e| format!("Failed to send email: {}", e))?;
+
+        Ok(())
+    }
+}
+
+/// Stands in for the mailer in builds without the `smtp` feature, where
+/// email can never be configured
+#[cfg(not(feature = "smtp"))]
+pub enum Mailer {}
+
+#[cfg(not(feature = "smtp"))]
+impl Mailer {
+    pub fn from_config(
+        smtp: &SmtpConfig,
+        _public_url: Option<&str>,
+    ) -> Result<Option<Self>, String> {
+        if smtp.url.is_some() {
+            return Err("Email (SMTP_URL) requires building with the `smtp` feature".to_string());
+        }
+        Ok(None)
+    }
+
+    pub fn public_url(&self) -> Option<&str> {
+        match *self {}
+    }
+
+    pub async fn send(
+        &self,
+        _to: &str,
+        _subject: &str,
+        _body: String,
+        _attachment: Option<EmailAttachment>,
+    ) -> Result<(), String> {
+        match *self {}
+    }
+
+    pub async fn send_html(
+        &self,
+        _to: &str,
+        _subject: &str,
+        _text: String,
+        _html: String,
+    ) -> Result<(), String> {
+        match *self {}
+    }
+}
@@ -0,0 +1,318 @@
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use clap::{Parser, Subcommand};
+#[cfg(feature = "grpc")]
+use dissipate_server::grpc;
+#[cfg(feature = "sentry")]
+use dissipate_server::reporting;
+#[cfg(feature = "telegram")]
+use dissipate_server::telegram;
+use dissipate_server::{
+    accesslog, backup, config, db, digests, dormancy, export_jobs, housekeeping, maintenance,
+    matrix, middleware, proxy, reload, reminders, shutdown, tls, webhooks, DissipateApp,
+};
+use tokio_util::sync::CancellationToken;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Dissipate journal server
+///
+/// Flags take precedence over environment variables, which take precedence
+/// over the config file.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// Config file to read instead of `dissipate.toml`
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Address to listen on, such as `127.0.0.1:8080`; repeat to listen on
+    /// several
+    #[arg(long, value_name = "ADDR")]
+    bind: Vec<SocketAddr>,
+    /// SQLite database URL, such as `sqlite:/var/lib/dissipate/dissipate.db`
+    #[arg(long, value_name = "URL")]
+    database_url: Option<String>,
+    /// Apply pending database migrations and exit
+    #[arg(long)]
+    migrate_only: bool,
+    /// Keep all data in memory instead of the database, for demos; it is lost
+    /// when the server stops
+    #[arg(long, conflicts_with = "migrate_only")]
+    ephemeral: bool,
+    /// Print the effective configuration, with secrets redacted, and exit
+    #[arg(long)]
+    print_config: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Apply pending database migrations and exit (same as `--migrate-only`)
+    Migrate,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // The only place the environment is read; everything else gets `config`
+    let env = |name: &str| std::env::var(name).ok();
+    let mut config =
+        config::Config::load(cli.config.as_deref(), env).map_err(anyhow::Error::msg)?;
+    if !cli.bind.is_empty() {
+        config.server.bind = cli.bind;
+    }
+    if let Some(url) = cli.database_url {
+        config.database.url = url;
+    }
+
+    if cli.print_config {
+        print!("{}", toml::to_string_pretty(&config.redacted())?);
+        return Ok(());
+    }
+
+    // Initialize tracing, behind a reload layer so the filter can change
+    // without a restart
+    let (log_filter, log_filter_handle) = tracing_subscriber::reload::Layer::new(
+        tracing_subscriber::EnvFilter::try_new(config.logging.filter())?,
+    );
+    tracing_subscriber::registry()
+        .with(log_filter)
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+
+    // Held until exit, so queued reports are sent
+    #[cfg(feature = "sentry")]
+    let _reporting = reporting::init(&config.reporting).map_err(anyhow::Error::msg)?;
+    #[cfg(not(feature = "sentry"))]
+    if config.reporting.dsn.is_some() {
+        anyhow::bail!("Error reporting (SENTRY_DSN) requires building with the `sentry` feature");
+    }
+
+    let database_url = config.database.url.clone();
+    let db_options = db::DbOptions::from_config(&config.database).map_err(anyhow::Error::msg)?;
+
+    if cli.migrate_only || matches!(cli.command, Some(Command::Migrate)) {
+        db::init_pool_with(&database_url, &db_options).await?;
+        tracing::info!("Database migrations applied");
+        return Ok(());
+    }
+
+    // Backups and database maintenance have no file to work on when
+    // ephemeral
+    let backup_config = backup::BackupConfig::from_config(&config.backup)
+        .map_err(anyhow::Error::msg)?
+        .filter(|_| !cli.ephemeral);
+    let checkpoint_config = maintenance::CheckpointConfig::from_config(&config.database)
+        .map_err(anyhow::Error::msg)?
+        .filter(|_| !cli.ephemeral);
+    let optimize_config = maintenance::OptimizeConfig::from_config(&config.database)
+        .map_err(anyhow::Error::msg)?
+        .filter(|_| !cli.ephemeral);
+    let cors = middleware::CorsConfig::from_settings(&config.cors).map_err(anyhow::Error::msg)?;
+    let tls = match tls::TlsConfig::from_config(&config.server).map_err(anyhow::Error::msg)? {
+        Some(tls_config) => {
+            let rustls = tls_config
+                .load()
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to load TLS certificate: {}", e))?;
+            Some((tls_config, rustls))
+        }
+        None => None,
+    };
+    let access_log =
+        accesslog::AccessLog::from_config(&config.logging).map_err(anyhow::Error::msg)?;
+
+    #[cfg(not(feature = "grpc"))]
+    if config.server.grpc_addr.is_some() {
+        anyhow::bail!("The gRPC server (GRPC_ADDR) requires building with the `grpc` feature");
+    }
+
+    // Initialize storage
+    let state = if cli.ephemeral {
+        tracing::warn!("Running ephemeral: data is kept in memory and lost on exit");
+        DissipateApp::ephemeral_state(&config).await?
+    } else {
+        DissipateApp::state_from_config(&config).await?
+    };
+
+    let shutdown = CancellationToken::new();
+    shutdown::cancel_on_signal(shutdown.clone());
+    let mut workers = Vec::new();
+
+    if let Some(config) = backup_config {
+        tracing::info!(
+            "Backing up to {} every {:?}",
+            config.dir.display(),
+            config.interval
+        );
+        workers.push(backup::spawn(state.clone(), config, shutdown.clone()));
+    }
+
+    if let Some(config) = checkpoint_config {
+        tracing::info!(
+            "Checkpointing the WAL ({:?}) every {:?}",
+            config.mode,
+            config.interval
+        );
+        workers.push(maintenance::spawn(state.clone(), config, shutdown.clone()));
+    }
+
+    if let Some(config) = optimize_config {
+        tracing::info!(
+            "Optimizing the database daily between {} and {} UTC",
+            config.start.format("%H:%M"),
+            config.end.format("%H:%M")
+        );
+        workers.push(maintenance::spawn_optimizer(
+            state.clone(),
+            config,
+            shutdown.clone(),
+        ));
+    }
+
+    workers.push(export_jobs::spawn_worker(state.clone(), shutdown.clone()));
+    workers.push(webhooks::spawn_worker(state.clone(), shutdown.clone()));
+    workers.push(digests::spawn_worker(state.clone(), shutdown.clone()));
+    workers.push(reminders::spawn_worker(state.clone(), shutdown.clone()));
+    workers.push(matrix::spawn_worker(state.clone(), shutdown.clone()));
+    workers.push(dormancy::spawn_worker(state.clone(), shutdown.clone()));
+    workers.push(housekeeping::spawn_worker(state.clone(), shutdown.clone()));
+
+    #[cfg(feature = "telegram")]
+    if let Some(bot) = state.telegram.clone() {
+        tracing::info!("Saving messages sent to the Telegram bot");
+        workers.push(telegram::spawn_worker(state.clone(), bot, shutdown.clone()));
+    }
+
+    #[cfg(feature = "grpc")]
+    if let Some(addr) = config.server.grpc_addr {
+        tracing::info!("Serving gRPC at {}", addr);
+        workers.push(grpc::spawn(state.clone(), addr, shutdown.clone()));
+    }
+
+    let proxies = proxy::TrustedProxies::new(config.proxy.trusted.clone());
+    let cors = middleware::ReloadableCors::new(&cors);
+    let config_path = cli.config.clone();
+    let reloader = reload::Reloader {
+        load_config: Arc::new(move || config::Config::load(config_path.as_deref(), env)),
+        cors: cors.clone(),
+        rate_limits: state.rate_limits.clone(),
+        log_filter: Some(log_filter_handle),
+        tls: tls.clone(),
+    };
+    reload::spawn_reload_on_sighup(reloader.clone());
+
+    let timeouts = middleware::Timeouts::from_config(&config.server);
+    let mut app = DissipateApp::builder(state.clone())
+        .cors(cors)
+        .trusted_proxies(proxies)
+        .reloader(reloader)
+        .timeouts(timeouts);
+    if let Some(access_log) = access_log {
+        app = app.access_log(access_log);
+    }
+    let app = app.build();
+    let rustls = tls.map(|(_, rustls)| rustls);
+    let mut server = tokio::spawn(serve(
+        app,
+        config.server.bind.clone(),
+        rustls,
+        shutdown.clone(),
+    ));
+
+    tokio::select! {
+        // Only finishes early on errors such as the port being taken
+        result = &mut server => return result?,
+        _ = shutdown.cancelled() => {}
+    }
+
+    // In-flight requests and background work share one deadline
+    let drained = tokio::time::timeout(shutdown::DRAIN_TIMEOUT, async {
+        let result = server.await;
+        shutdown::join_workers(workers).await;
+        result
+    })
+    .await;
+    match drained {
+        Ok(result) => result??,
+        Err(_) => tracing::warn!(
+            "Stopped waiting for requests and background tasks after {:?}",
+            shutdown::DRAIN_TIMEOUT
+        ),
+    }
+
+    state.storage.close().await;
+    tracing::info!("Shut down");
+
+    Ok(())
+}
+
+/// Serve HTTP, or HTTPS when configured, on every address in `addrs` until
+/// `shutdown` is cancelled and open connections have finished
+async fn serve(
+    app: Router,
+    addrs: Vec<SocketAddr>,
+    rustls: Option<RustlsConfig>,
+    shutdown: CancellationToken,
+) -> anyhow::Result<()> {
+    // Peer addresses are needed for per-IP rate limits
+    let service = app.into_make_service_with_connect_info::<SocketAddr>();
+    let mut servers = tokio::task::JoinSet::new();
+
+    match rustls {
+        Some(rustls) => {
+            for addr in addrs {
+                let handle = axum_server::Handle::new();
+                let draining = handle.clone();
+                let shutdown = shutdown.clone();
+                tokio::spawn(async move {
+                    shutdown.cancelled().await;
+                    // The caller enforces the deadline
+                    draining.graceful_shutdown(None);
+                });
+
+                let server = axum_server::bind_rustls(addr, rustls.clone())
+                    .handle(handle.clone())
+                    .serve(service.clone());
+                servers.spawn(async move {
+                    server
+                        .await
+                        .map_err(|e| anyhow::anyhow!("Server on {} failed: {}", addr, e))
+                });
+                // `None` if binding failed, which the server task reports
+                if let Some(local) = handle.listening().await {
+                    tracing::info!("Listening on https://{}", local);
+                }
+            }
+        }
+        None => {
+            for addr in addrs {
+                let listener = tokio::net::TcpListener::bind(addr)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Failed to bind {}: {}", addr, e))?;
+                tracing::info!("Listening on http://{}", listener.local_addr()?);
+
+                let server = axum::serve(listener, service.clone())
+                    .with_graceful_shutdown(shutdown.clone().cancelled_owned());
+                servers.spawn(async move {
+                    server
+                        .await
+                        .map_err(|e| anyhow::anyhow!("Server on {} failed: {}", addr, e))
+                });
+            }
+        }
+    }
+
+    // The first listener to fail takes the others down with it
+    while let Some(result) = servers.join_next().await {
+        result??;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,543 @@
+use std::{sync::RwLock, time::Duration};
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use chrono::{DateTime, NaiveTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use utoipa::IntoParams;
+
+use crate::{
+    audit::{self, AdminActor},
+    config::{DatabaseConfig, MaintenanceConfig},
+    db::{CheckpointMode, CheckpointResult, DbError},
+    error::AppError,
+    handlers::{storage_error, AppState, SharedState},
+    models::{
+        AuditEvent, CheckpointResponse, IntegrityResponse, MaintenanceRequest, MaintenanceResponse,
+        OptimizeResponse, StatsResponse, WalStatusResponse,
+    },
+};
+
+/// Scheduled WAL checkpoint settings
+#[derive(Debug, Clone)]
+pub struct CheckpointConfig {
+    pub interval: Duration,
+    pub mode: CheckpointMode,
+}
+
+impl CheckpointConfig {
+    /// Scheduled checkpoints are disabled unless `checkpoint_interval_secs`
+    /// is set
+    ///
+    /// `checkpoint_mode` defaults to `passive`.
+    pub fn from_config(config: &DatabaseConfig) -> Result<Option<Self>, String> {
+        let Some(interval_secs) = config.checkpoint_interval_secs else {
+            return Ok(None);
+        };
+
+        if interval_secs == 0 {
+            return Err(
+                "[database] checkpoint_interval_secs (CHECKPOINT_INTERVAL_SECS) must be positive"
+                    .to_string(),
+            );
+        }
+        let mode = match &config.checkpoint_mode {
+            Some(mode) => mode.parse()?,
+            None => CheckpointMode::default(),
+        };
+
+        Ok(Some(Self {
+            interval: Duration::from_secs(interval_secs),
+            mode,
+        }))
+    }
+}
+
+/// How often the optimizer checks whether its window has come
+const OPTIMIZE_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Scheduled database optimization settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptimizeConfig {
+    /// Start of the daily quiet window, in UTC
+    pub start: NaiveTime,
+    /// End of the window, which is past midnight when before `start`
+    pub end: NaiveTime,
+}
+
+impl OptimizeConfig {
+    /// Use `optimize_window`, such as `03:00-05:00` in UTC; scheduled
+    /// optimization is disabled when it is unset
+    pub fn from_config(config: &DatabaseConfig) -> Result<Option<Self>, String> {
+        config
+            .optimize_window
+            .as_deref()
+            .map(Self::parse)
+            .transpose()
+    }
+
+    fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || {
+            format!(
+                "Invalid DB_OPTIMIZE_WINDOW (expected HH:MM-HH:MM): {}",
+                value
+            )
+        };
+        let (start, end) = value.trim().split_once('-').ok_or_else(invalid)?;
+        let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").map_err(|_| invalid())?;
+        let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").map_err(|_| invalid())?;
+        if start == end {
+            return Err(invalid());
+        }
+
+        Ok(Self { start, end })
+    }
+
+    /// Whether `time` falls in the window
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// Time of the most recent checkpoint, reported by `GET /api/admin/wal`,
+/// and the outcome of the last optimization, reported by
+/// `GET /api/admin/stats`
+#[derive(Debug, Default)]
+pub struct CheckpointStatus {
+    last_at: RwLock<Option<String>>,
+    last_optimize: RwLock<Option<OptimizeResponse>>,
+}
+
+impl CheckpointStatus {
+    pub fn last_at(&self) -> Option<String> {
+        self.last_at.read().unwrap().clone()
+    }
+
+    fn record(&self, at: String) {
+        *self.last_at.write().unwrap() = Some(at);
+    }
+
+    pub fn last_optimize(&self) -> Option<OptimizeResponse> {
+        self.last_optimize.read().unwrap().clone()
+    }
+
+    fn record_optimize(&self, response: OptimizeResponse) {
+        *self.last_optimize.write().unwrap() = Some(response);
+    }
+}
+
+const DEFAULT_MAINTENANCE_MESSAGE: &str = "Down for maintenance, please try again shortly";
+const DEFAULT_MAINTENANCE_RETRY_AFTER_SECS: u32 = 60;
+
+/// Whether the API is down for maintenance, such as a backup or migration
+/// that needs the database to itself
+///
+/// While on, API requests other than health checks and operator endpoints
+/// get a 503 with the notice; see `middleware::maintenance_middleware`.
+#[derive(Debug, Default)]
+pub struct MaintenanceMode {
+    notice: RwLock<Option<MaintenanceNotice>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MaintenanceNotice {
+    pub message: String,
+    pub retry_after_secs: u32,
+}
+
+impl MaintenanceMode {
+    /// Start in maintenance mode if `[maintenance] enabled` is set
+    pub fn from_config(config: &MaintenanceConfig) -> Self {
+        let mode = Self::default();
+        if config.enabled {
+            mode.enable(config.message.clone(), config.retry_after_secs);
+        }
+        mode
+    }
+
+    pub fn enable(&self, message: Option<String>, retry_after_secs: Option<u32>) {
+        let notice = MaintenanceNotice {
+            message: message
+                .filter(|message| !message.trim().is_empty())
+                .unwrap_or_else(|| DEFAULT_MAINTENANCE_MESSAGE.to_string()),
+            retry_after_secs: retry_after_secs.unwrap_or(DEFAULT_MAINTENANCE_RETRY_AFTER_SECS),
+        };
+        tracing::warn!("Maintenance mode on: {}", notice.message);
+        *self.notice.write().unwrap() = Some(notice);
+    }
+
+    pub fn disable(&self) {
+        if self.notice.write().unwrap().take().is_some() {
+            tracing::warn!("Maintenance mode off");
+        }
+    }
+
+    /// The notice to answer with, if in maintenance mode
+    pub fn notice(&self) -> Option<MaintenanceNotice> {
+        self.notice.read().unwrap().clone()
+    }
+
+    fn response(&self) -> MaintenanceResponse {
+        let notice = self.notice();
+        MaintenanceResponse {
+            enabled: notice.is_some(),
+            message: notice.as_ref().map(|notice| notice.message.clone()),
+            retry_after_secs: notice.map(|notice| notice.retry_after_secs),
+        }
+    }
+}
+
+/// Checkpoint every `config.interval` in the background
+pub fn spawn(
+    state: SharedState,
+    config: CheckpointConfig,
+    shutdown: CancellationToken,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let start = tokio::time::Instant::now() + config.interval;
+        let mut interval = tokio::time::interval_at(start, config.interval);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = shutdown.cancelled() => break,
+            }
+            match run_checkpoint(&state, config.mode).await {
+                Ok(result) if result.busy => {
+                    tracing::debug!("Checkpoint incomplete, database busy")
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!("Scheduled checkpoint failed: {}", e),
+            }
+        }
+    })
+}
+
+/// Optimize the database once a day, during `config`'s quiet window
+pub fn spawn_optimizer(
+    state: SharedState,
+    config: OptimizeConfig,
+    shutdown: CancellationToken,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut last_run: Option<DateTime<Utc>> = None;
+
+        loop {
+            let now = Utc::now();
+            // Once per window, however often it is checked during it
+            let ran_recently = last_run.is_some_and(|at| now - at < chrono::Duration::hours(20));
+            if config.contains(now.time()) && !ran_recently {
+                last_run = Some(now);
+                if let Err(e) = run_optimize(&state).await {
+                    tracing::error!("Scheduled database optimization failed: {}", e);
+                }
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(OPTIMIZE_CHECK_INTERVAL) => {}
+                _ = shutdown.cancelled() => break,
+            }
+        }
+    })
+}
+
+async fn run_optimize(state: &AppState) -> Result<OptimizeResponse, DbError> {
+    let started = std::time::Instant::now();
+    let result = state.storage.optimize().await?;
+    let finished_at = Utc::now().to_rfc3339();
+    if !result.checkpoint.busy {
+        state.checkpoints.record(finished_at.clone());
+    }
+
+    let response = OptimizeResponse {
+        finished_at,
+        duration_ms: started.elapsed().as_millis() as u64,
+        vacuumed_bytes: result.vacuumed_bytes,
+        checkpoint_busy: result.checkpoint.busy,
+    };
+    tracing::info!(
+        "Optimized database in {} ms, reclaiming {} bytes{}",
+        response.duration_ms,
+        response.vacuumed_bytes.unwrap_or(0),
+        if response.checkpoint_busy {
+            "; WAL checkpoint incomplete, database busy"
+        } else {
+            ""
+        }
+    );
+    state.checkpoints.record_optimize(response.clone());
+    Ok(response)
+}
+
+async fn run_checkpoint(
+    state: &AppState,
+    mode: CheckpointMode,
+) -> Result<CheckpointResult, DbError> {
+    let result = state.storage.checkpoint(mode).await?;
+    if !result.busy {
+        state.checkpoints.record(Utc::now().to_rfc3339());
+    }
+    Ok(result)
+}
+
+// ============ Admin Handlers ============
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CheckpointQuery {
+    /// `passive` (default), `full`, `restart` or `truncate`
+    pub mode: Option<String>,
+}
+
+/// POST /api/admin/checkpoint
+/// Checkpoint the WAL now, e.g. before snapshotting the database file
+#[utoipa::path(
+    post,
+    path = "/api/admin/checkpoint",
+    tag = "admin",
+    params(CheckpointQuery),
+    responses(
+        (status = 200, description = "Checkpoint result", body = CheckpointResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn checkpoint(
+    State(state): State<SharedState>,
+    Query(query): Query<CheckpointQuery>,
+) -> Result<Json<CheckpointResponse>, AppError> {
+    let mode = match query.mode.as_deref() {
+        Some(mode) => mode
+            .parse()
+            .map_err(|e: String| AppError::new(StatusCode::BAD_REQUEST, e))?,
+        None => CheckpointMode::default(),
+    };
+
+    let result = run_checkpoint(&state, mode)
+        .await
+        .map_err(|e| storage_error(e, "Checkpoint failed"))?;
+    let wal_bytes = state
+        .storage
+        .wal_size_bytes()
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    Ok(Json(CheckpointResponse {
+        busy: result.busy,
+        log_frames: result.log_frames,
+        checkpointed_frames: result.checkpointed_frames,
+        wal_bytes,
+    }))
+}
+
+/// GET /api/admin/wal
+/// Report the WAL size and when it was last fully checkpointed
+#[utoipa::path(
+    get,
+    path = "/api/admin/wal",
+    tag = "admin",
+    responses(
+        (status = 200, description = "WAL metrics", body = WalStatusResponse),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn wal_status(
+    State(state): State<SharedState>,
+) -> Result<Json<WalStatusResponse>, AppError> {
+    let wal_bytes = state
+        .storage
+        .wal_size_bytes()
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    Ok(Json(WalStatusResponse {
+        wal_bytes,
+        last_checkpoint_at: state.checkpoints.last_at(),
+    }))
+}
+
+/// GET /api/admin/integrity
+/// Check the database for corruption and orphaned rows, with row counts
+#[utoipa::path(
+    get,
+    path = "/api/admin/integrity",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Integrity report", body = IntegrityResponse),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn integrity(
+    State(state): State<SharedState>,
+) -> Result<Json<IntegrityResponse>, AppError> {
+    let report = state
+        .storage
+        .check_integrity()
+        .await
+        .map_err(|e| storage_error(e, "Integrity check failed"))?;
+
+    Ok(Json(IntegrityResponse {
+        ok: report.is_ok(),
+        integrity_errors: report.integrity_errors,
+        orphaned_rows: report.orphaned_rows,
+        row_counts: report.row_counts,
+    }))
+}
+
+const DEFAULT_STATS_DAYS: u32 = 30;
+const MAX_STATS_DAYS: u32 = 366;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatsQuery {
+    /// Days of message counts to report, up to 366; 30 by default
+    pub days: Option<u32>,
+}
+
+/// GET /api/admin/stats
+/// Report users, message volume, database size, connected clients and the
+/// last backup, for capacity planning
+#[utoipa::path(
+    get,
+    path = "/api/admin/stats",
+    tag = "admin",
+    params(StatsQuery),
+    responses(
+        (status = 200, description = "Instance statistics", body = StatsResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+    ),
+    security(("admin_token" = []), ("bearer_auth" = []))
+)]
+pub async fn stats(
+    State(state): State<SharedState>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<StatsResponse>, AppError> {
+    let days = query.days.unwrap_or(DEFAULT_STATS_DAYS);
+    if days == 0 || days > MAX_STATS_DAYS {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            format!("days must be between 1 and {}", MAX_STATS_DAYS),
+        ));
+    }
+
+    let stats = state
+        .storage
+        .instance_stats(days)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    Ok(Json(StatsResponse {
+        user_count: stats.user_count,
+        messages_per_day: stats.messages_per_day,
+        database_bytes: stats.database_bytes,
+        active_sessions: state.events.connection_count(),
+        last_backup_at: state.backups.last_success(),
+        last_optimize: state.checkpoints.last_optimize(),
+    }))
+}
+
+/// GET /api/admin/maintenance
+/// Report whether maintenance mode is on
+#[utoipa::path(
+    get,
+    path = "/api/admin/maintenance",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Maintenance mode state", body = MaintenanceResponse),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn get_maintenance(State(state): State<SharedState>) -> Json<MaintenanceResponse> {
+    Json(state.maintenance_mode.response())
+}
+
+/// PUT /api/admin/maintenance
+/// Turn maintenance mode on, or change its message
+#[utoipa::path(
+    put,
+    path = "/api/admin/maintenance",
+    tag = "admin",
+    request_body = MaintenanceRequest,
+    responses(
+        (status = 200, description = "Maintenance mode on", body = MaintenanceResponse),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn enable_maintenance(
+    State(state): State<SharedState>,
+    Extension(AdminActor(actor)): Extension<AdminActor>,
+    Json(request): Json<MaintenanceRequest>,
+) -> Json<MaintenanceResponse> {
+    state
+        .maintenance_mode
+        .enable(request.message, request.retry_after_secs);
+    let response = state.maintenance_mode.response();
+    audit::record(
+        &*state.storage,
+        AuditEvent::new("admin.maintenance.enable", &actor).with_metadata(json!({
+            "message": response.message,
+            "retry_after_secs": response.retry_after_secs,
+        })),
+    )
+    .await;
+    Json(response)
+}
+
+/// DELETE /api/admin/maintenance
+/// Turn maintenance mode off
+#[utoipa::path(
+    delete,
+    path = "/api/admin/maintenance",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Maintenance mode off", body = MaintenanceResponse),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn disable_maintenance(
+    State(state): State<SharedState>,
+    Extension(AdminActor(actor)): Extension<AdminActor>,
+) -> Json<MaintenanceResponse> {
+    state.maintenance_mode.disable();
+    audit::record(
+        &*state.storage,
+        AuditEvent::new("admin.maintenance.disable", &actor),
+    )
+    .await;
+    Json(state.maintenance_mode.response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_optimize_window_may_span_midnight() {
+        let time = |s: &str| NaiveTime::parse_from_str(s, "%H:%M").unwrap();
+
+        let window = OptimizeConfig::parse("03:00-05:00").unwrap();
+        assert!(window.contains(time("03:00")));
+        assert!(window.contains(time("04:59")));
+        assert!(!window.contains(time("05:00")));
+        assert!(!window.contains(time("23:30")));
+
+        let window = OptimizeConfig::parse(" 23:00 - 01:30 ").unwrap();
+        assert!(window.contains(time("23:30")));
+        assert!(window.contains(time("00:15")));
+        assert!(!window.contains(time("12:00")));
+
+        for value in ["03:00", "3am-5am", "04:00-04:00"] {
+            assert!(OptimizeConfig::parse(value).is_err());
+        }
+    }
+}
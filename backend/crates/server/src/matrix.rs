@@ -0,0 +1,636 @@
+use std::{collections::HashMap, time::Duration};
+
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    db::DbError,
+    error::AppError,
+    handlers::{self, storage_error, SharedState},
+    models::{
+        CreateMessageRequest, MatrixAccount, MatrixAccountResponse, SetMatrixAccountRequest,
+        SuccessResponse,
+    },
+    notifiers::{Notification, Notifier},
+    AuthUser,
+};
+
+/// How often each connected room is checked for new messages
+const SYNC_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Most messages saved from one room per sync
+const SYNC_EVENT_LIMIT: u32 = 50;
+
+/// A user's DM room with their Matrix bot, reached with the bot's token
+pub struct MatrixRoom {
+    homeserver_url: String,
+    access_token: String,
+    room_id: String,
+}
+
+/// Error body of the Matrix client-server API
+#[derive(Debug, Deserialize)]
+struct MatrixError {
+    error: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct WhoAmI {
+    user_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct JoinedMembers {
+    joined: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SyncResponse {
+    next_batch: String,
+    #[serde(default)]
+    rooms: SyncRooms,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SyncRooms {
+    #[serde(default)]
+    join: HashMap<String, JoinedRoom>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JoinedRoom {
+    #[serde(default)]
+    timeline: Timeline,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Timeline {
+    #[serde(default)]
+    events: Vec<RoomEvent>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RoomEvent {
+    event_id: String,
+    sender: String,
+    #[serde(rename = "type")]
+    kind: String,
+    /// Milliseconds since the Unix epoch
+    origin_server_ts: i64,
+    #[serde(default)]
+    content: serde_json::Value,
+}
+
+impl MatrixRoom {
+    pub fn new(homeserver_url: &str, access_token: &str, room_id: &str) -> Self {
+        Self {
+            homeserver_url: homeserver_url.trim_end_matches('/').to_string(),
+            access_token: access_token.to_string(),
+            room_id: room_id.to_string(),
+        }
+    }
+
+    pub fn for_account(account: &MatrixAccount) -> Self {
+        Self::new(
+            &account.homeserver_url,
+            &account.access_token,
+            &account.room_id,
+        )
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/_matrix/client/v3{}", self.homeserver_url, path)
+    }
+
+    fn room_url(&self, path: &str) -> String {
+        self.url(&format!("/rooms/{}{}", path_segment(&self.room_id), path))
+    }
+
+    /// The bot's own Matrix ID
+    async fn whoami(&self, client: &reqwest::Client) -> Result<String, String> {
+        let request = client
+            .get(self.url("/account/whoami"))
+            .bearer_auth(&self.access_token);
+        let whoami: WhoAmI = request_json(request).await?;
+        Ok(whoami.user_id)
+    }
+
+    async fn joined_members(&self, client: &reqwest::Client) -> Result<Vec<String>, String> {
+        let request = client
+            .get(self.room_url("/joined_members"))
+            .bearer_auth(&self.access_token);
+        let members: JoinedMembers = request_json(request).await?;
+        Ok(members.joined.into_keys().collect())
+    }
+
+    /// Events in the room since `since`, or none with just the token to
+    /// continue from when `since` is unset
+    async fn sync(
+        &self,
+        client: &reqwest::Client,
+        since: Option<&str>,
+    ) -> Result<(Vec<RoomEvent>, String), String> {
+        let filter = json!({
+            "room": {
+                "rooms": [self.room_id],
+                "timeline": { "types": ["m.room.message"], "limit": SYNC_EVENT_LIMIT },
+                "state": { "types": [] },
+                "ephemeral": { "types": [] },
+                "account_data": { "types": [] },
+            },
+            "presence": { "types": [] },
+            "account_data": { "types": [] },
+        });
+        let mut request = client
+            .get(self.url("/sync"))
+            .bearer_auth(&self.access_token)
+            .query(&[("timeout", "0"), ("filter", &filter.to_string())]);
+        if let Some(since) = since {
+            request = request.query(&[("since", since)]);
+        }
+
+        let mut sync: SyncResponse = request_json(request).await?;
+        let events = match (since, sync.rooms.join.remove(&self.room_id)) {
+            (Some(_), Some(room)) => room.timeline.events,
+            _ => Vec::new(),
+        };
+        Ok((events, sync.next_batch))
+    }
+
+    async fn send_event(
+        &self,
+        client: &reqwest::Client,
+        event_type: &str,
+        content: serde_json::Value,
+    ) -> Result<(), String> {
+        let txn_id = uuid::Uuid::new_v4();
+        let request = client
+            .put(self.room_url(&format!("/send/{}/{}", event_type, txn_id)))
+            .bearer_auth(&self.access_token)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(content.to_string());
+        request_json::<serde_json::Value>(request).await?;
+        Ok(())
+    }
+}
+
+#[axum::async_trait]
+impl Notifier for MatrixRoom {
+    async fn send(
+        &self,
+        client: &reqwest::Client,
+        notification: &Notification,
+    ) -> Result<(), String> {
+        let mut body = format!("{}\n\n{}", notification.title, notification.body);
+        if let Some(url) = &notification.url {
+            body.push_str(&format!("\n\n{}", url));
+        }
+
+        self.send_event(
+            client,
+            "m.room.message",
+            json!({ "msgtype": "m.text", "body": body }),
+        )
+        .await
+        .map_err(|e| format!("Failed to post to Matrix: {}", e))
+    }
+}
+
+/// Percent-encode everything but unreserved characters, for room IDs such as
+/// `!abc:example.org` in URL paths
+fn path_segment(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Send a request and parse its JSON response, or the homeserver's error
+async fn request_json<T: DeserializeOwned>(request: reqwest::RequestBuilder) -> Result<T, String> {
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("Failed to reach homeserver: {}", e))?;
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .map_err(|e| format!("Failed to read homeserver response: {}", e))?;
+
+    if !status.is_success() {
+        let error = serde_json::from_str::<MatrixError>(&body)
+            .map(|e| e.error)
+            .unwrap_or(body);
+        return Err(format!("Homeserver answered {}: {}", status, error));
+    }
+    serde_json::from_str(&body).map_err(|e| format!("Invalid homeserver response: {}", e))
+}
+
+/// Save what users write in their Matrix rooms, until shutdown
+pub fn spawn_worker(state: SharedState, shutdown: CancellationToken) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            sync_accounts(&state).await;
+
+            tokio::select! {
+                _ = tokio::time::sleep(SYNC_INTERVAL) => {}
+                _ = shutdown.cancelled() => break,
+            }
+        }
+    })
+}
+
+/// Check every connected room once
+async fn sync_accounts(state: &SharedState) {
+    let accounts = match state.storage.list_matrix_accounts().await {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            tracing::error!("Failed to fetch Matrix accounts: {}", e);
+            return;
+        }
+    };
+
+    for account in accounts {
+        if let Err(e) = sync_account(state, &account).await {
+            tracing::warn!(
+                "Failed to sync Matrix room of user {}: {}",
+                account.user_id,
+                e
+            );
+        }
+    }
+}
+
+/// Save the user's new messages in their room as entries, reacting to each
+/// one saved and explaining any that couldn't be
+async fn sync_account(state: &SharedState, account: &MatrixAccount) -> Result<(), String> {
+    let disabled = state
+        .storage
+        .find_user_by_id(&account.user_id)
+        .await
+        .map_err(|e| format!("Failed to fetch user: {}", e))?
+        .is_none_or(|user| user.disabled);
+    if disabled {
+        return Ok(());
+    }
+
+    // Checked again, since the host may resolve somewhere else by now
+    state.outbound.check(&account.homeserver_url).await?;
+
+    let client = state.notifiers.client();
+    let room = MatrixRoom::for_account(account);
+    let (events, next_batch) = room.sync(client, account.sync_token.as_deref()).await?;
+
+    for event in events {
+        let body = event.content["body"].as_str().unwrap_or_default();
+        if event.kind != "m.room.message"
+            || event.sender == account.bot_user_id
+            || event.content["msgtype"] != "m.text"
+        {
+            continue;
+        }
+
+        let request = CreateMessageRequest {
+            content: body.to_string(),
+            // An event synced again is the same entry, not a new one
+            id: Some(handlers::derived_message_id(&format!(
+                "matrix:{}",
+                event.event_id
+            ))),
+            template_id: None,
+            created_at: DateTime::<Utc>::from_timestamp_millis(event.origin_server_ts)
+                .map(|t| t.to_rfc3339()),
+            publish_at: None,
+        };
+        let reply = match handlers::save_message(state, account.user_id.clone(), request).await {
+            Ok(_) => room.send_event(
+                client,
+                "m.reaction",
+                json!({
+                    "m.relates_to": {
+                        "rel_type": "m.annotation",
+                        "event_id": event.event_id,
+                        "key": "✅",
+                    },
+                }),
+            ),
+            Err(e) => room.send_event(
+                client,
+                "m.room.message",
+                json!({
+                    "msgtype": "m.notice",
+                    "body": format!("Couldn't save that: {}", e.into_parts().1.error),
+                }),
+            ),
+        };
+        if let Err(e) = reply.await {
+            tracing::warn!("Failed to reply in Matrix room: {}", e);
+        }
+    }
+
+    state
+        .storage
+        .set_matrix_sync_token(&account.user_id, &next_batch)
+        .await
+        .map_err(|e| format!("Failed to save sync token: {}", e))
+}
+
+// ============ Handlers ============
+
+/// GET /api/user/matrix
+/// The authenticated user's connected Matrix bot and room
+#[utoipa::path(
+    get,
+    path = "/api/user/matrix",
+    tag = "user",
+    responses(
+        (status = 200, description = "Connected Matrix bot and room", body = MatrixAccountResponse),
+        (status = 404, description = "Matrix is not connected", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_account(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<MatrixAccountResponse>, AppError> {
+    let account = state
+        .storage
+        .get_matrix_account(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "Matrix is not connected"))?;
+
+    Ok(Json(account.to_response()))
+}
+
+/// PUT /api/user/matrix
+/// Connect a Matrix bot and its DM room with the user, checking the token
+/// and room with the homeserver first
+#[utoipa::path(
+    put,
+    path = "/api/user/matrix",
+    tag = "user",
+    request_body = SetMatrixAccountRequest,
+    responses(
+        (status = 200, description = "Matrix connected", body = MatrixAccountResponse),
+        (status = 400, description = "Invalid homeserver, token or room", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn set_account(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Json(payload): Json<SetMatrixAccountRequest>,
+) -> Result<Json<MatrixAccountResponse>, AppError> {
+    let bad_request = |message: String| AppError::new(StatusCode::BAD_REQUEST, message);
+
+    state
+        .outbound
+        .check(&payload.homeserver_url)
+        .await
+        .map_err(|e| bad_request(format!("Homeserver {}", e)))?;
+    if !payload.room_id.starts_with('!') || !payload.room_id.contains(':') {
+        return Err(bad_request(
+            "Room ID must look like !abc123:example.org".to_string(),
+        ));
+    }
+
+    let client = state.notifiers.client();
+    let room = MatrixRoom::new(
+        &payload.homeserver_url,
+        &payload.access_token,
+        &payload.room_id,
+    );
+    let bot_user_id = room
+        .whoami(client)
+        .await
+        .map_err(|e| bad_request(format!("Matrix rejected the access token: {}", e)))?;
+    let members = room
+        .joined_members(client)
+        .await
+        .map_err(|e| bad_request(format!("The bot can't see the room: {}", e)))?;
+    // Anyone else in the room could otherwise write to the journal
+    if members.len() != 2 || !members.contains(&bot_user_id) {
+        return Err(bad_request(
+            "The room must be a direct chat between you and the bot".to_string(),
+        ));
+    }
+
+    let account = MatrixAccount {
+        user_id,
+        homeserver_url: room.homeserver_url,
+        access_token: payload.access_token,
+        bot_user_id,
+        room_id: payload.room_id,
+        sync_token: None,
+        created_at: Utc::now(),
+    };
+    state
+        .storage
+        .set_matrix_account(&account)
+        .await
+        .map_err(|e| storage_error(e, "Failed to connect Matrix"))?;
+
+    Ok(Json(account.to_response()))
+}
+
+/// DELETE /api/user/matrix
+/// Disconnect the user's Matrix bot
+#[utoipa::path(
+    delete,
+    path = "/api/user/matrix",
+    tag = "user",
+    responses(
+        (status = 200, description = "Matrix disconnected", body = SuccessResponse),
+        (status = 404, description = "Matrix is not connected", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn delete_account(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<SuccessResponse>, AppError> {
+    state
+        .storage
+        .delete_matrix_account(&user_id)
+        .await
+        .map_err(|e| match e {
+            DbError::MatrixAccountNotFound => {
+                AppError::new(StatusCode::NOT_FOUND, "Matrix is not connected")
+            }
+            _ => storage_error(e, "Failed to disconnect Matrix"),
+        })?;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{Message, User},
+        testing::{auth_user, test_app_state},
+    };
+    use serde_json::Value;
+    use std::sync::{Arc, Mutex};
+
+    const ROOM_ID: &str = "!journal:example.org";
+    const BOT_USER_ID: &str = "@bot:example.org";
+
+    type Queue = Arc<Mutex<Vec<Value>>>;
+
+    /// Minimal homeserver with one DM room, handing out queued timeline
+    /// events once and recording each event the bot sends
+    async fn spawn_homeserver(timeline: Queue, sent: Queue) -> String {
+        let app = axum::Router::new().fallback(move |uri: axum::http::Uri, body: String| {
+            let timeline = timeline.clone();
+            let sent = sent.clone();
+            async move {
+                let path = uri.path().trim_start_matches("/_matrix/client/v3");
+                let since = uri.query().is_some_and(|q| q.contains("since="));
+                let response = match path {
+                    "/account/whoami" => json!({ "user_id": BOT_USER_ID }),
+                    "/rooms/%21journal%3Aexample.org/joined_members" => json!({
+                        "joined": { BOT_USER_ID: {}, "@me:example.org": {} },
+                    }),
+                    "/sync" if !since => json!({ "next_batch": "s1" }),
+                    "/sync" => json!({
+                        "next_batch": "s2",
+                        "rooms": { "join": { ROOM_ID: { "timeline": {
+                            "events": std::mem::take(&mut *timeline.lock().unwrap()),
+                        } } } },
+                    }),
+                    _ => {
+                        let event_type = path.split('/').nth(4).unwrap_or_default();
+                        let mut event: Value = serde_json::from_str(&body).unwrap();
+                        event["type"] = json!(event_type);
+                        sent.lock().unwrap().push(event);
+                        json!({ "event_id": "$sent" })
+                    }
+                };
+                Json(response)
+            }
+        });
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    async fn setup_test_state() -> SharedState {
+        Arc::new(test_app_state().await)
+    }
+
+    fn text_event(event_id: &str, sender: &str, body: &str) -> Value {
+        json!({
+            "event_id": event_id,
+            "sender": sender,
+            "type": "m.room.message",
+            "origin_server_ts": 1_700_000_000_000i64,
+            "content": { "msgtype": "m.text", "body": body },
+        })
+    }
+
+    #[tokio::test]
+    async fn test_room_messages_become_entries() {
+        let timeline = Queue::default();
+        let sent = Queue::default();
+        let homeserver_url = spawn_homeserver(timeline.clone(), sent.clone()).await;
+        let state = setup_test_state().await;
+
+        let user = User::new(
+            "matrix@example.org".to_string(),
+            "matrix".to_string(),
+            "hash".to_string(),
+            "salt".to_string(),
+        );
+        state.storage.create_user(&user).await.unwrap();
+
+        let request = SetMatrixAccountRequest {
+            homeserver_url: homeserver_url.clone(),
+            access_token: "syt_test".to_string(),
+            room_id: "#journal:example.org".to_string(),
+        };
+        let result = set_account(State(state.clone()), auth_user(&user.id), Json(request)).await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::BAD_REQUEST);
+
+        let request = SetMatrixAccountRequest {
+            homeserver_url: "http://10.0.0.8:8008".to_string(),
+            access_token: "syt_test".to_string(),
+            room_id: ROOM_ID.to_string(),
+        };
+        let result = set_account(State(state.clone()), auth_user(&user.id), Json(request)).await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::BAD_REQUEST);
+
+        let request = SetMatrixAccountRequest {
+            homeserver_url,
+            access_token: "syt_test".to_string(),
+            room_id: ROOM_ID.to_string(),
+        };
+        let Json(account) = set_account(State(state.clone()), auth_user(&user.id), Json(request))
+            .await
+            .unwrap();
+        assert_eq!(account.bot_user_id, BOT_USER_ID);
+
+        // The first sync only marks where the room's history ends
+        sync_accounts(&state).await;
+        let account = state
+            .storage
+            .get_matrix_account(&user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(account.sync_token.as_deref(), Some("s1"));
+
+        timeline.lock().unwrap().extend([
+            text_event("$1", "@me:example.org", "Dear diary"),
+            text_event("$2", BOT_USER_ID, "Daily reminder"),
+        ]);
+        sync_accounts(&state).await;
+
+        let messages: Vec<Message> = state
+            .storage
+            .get_messages_for_user(&user.id, None)
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].id, handlers::derived_message_id("matrix:$1"));
+        assert_eq!(messages[0].content, "Dear diary");
+        assert_eq!(messages[0].created_at.timestamp(), 1_700_000_000);
+
+        let reaction = sent.lock().unwrap().pop().unwrap();
+        assert_eq!(reaction["type"], "m.reaction");
+        assert_eq!(reaction["m.relates_to"]["event_id"], "$1");
+
+        // Notifications are posted to the room too
+        let notification = Notification {
+            title: "Time to write".to_string(),
+            body: "Keep your streak going".to_string(),
+            url: None,
+        };
+        state
+            .notifiers
+            .notify(state.storage.as_ref(), &user.id, &notification)
+            .await
+            .unwrap();
+        let message = sent.lock().unwrap().pop().unwrap();
+        assert_eq!(message["type"], "m.room.message");
+        assert_eq!(message["body"], "Time to write\n\nKeep your streak going");
+
+        let _ = delete_account(State(state.clone()), auth_user(&user.id))
+            .await
+            .unwrap();
+        let result = get_account(State(state), auth_user(&user.id)).await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,1621 @@
+use std::{
+    any::Any,
+    net::SocketAddr,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{
+        header, Extensions, HeaderMap, HeaderName, HeaderValue, Method, Request, StatusCode,
+        Version,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tower::{Layer, ServiceExt};
+use tower_http::{
+    compression::{predicate::SizeAbove, CompressionLayer, Predicate},
+    cors::{self, AllowMethods, AllowOrigin, CorsLayer},
+};
+use tracing::Instrument;
+
+use crate::{
+    accesslog::{latency_ms, AccessLog, AccessLogEntry, LoggedUser},
+    audit::{AdminActor, ACTOR_ADMIN_TOKEN},
+    auth::extract_token_from_header,
+    config::{CorsSettings, ServerConfig},
+    handlers::{account_disabled, password_reset_required, ErrorResponse, SharedState},
+    ids::UserId,
+    models::IdempotentResponse,
+    proxy::{ClientIp, TrustedProxies},
+    ratelimit::RouteClass,
+    AuthUser,
+};
+
+/// The only route open to users who must change their password
+const PASSWORD_CHANGE_PATH: &str = "/api/user/password";
+
+/// Responses smaller than this are not worth compressing
+const MIN_COMPRESSION_SIZE: u16 = 1024;
+
+/// Text content types that compress well; images and archives are sent as-is
+const COMPRESSIBLE_CONTENT_TYPES: &[&str] = &[
+    "application/json",
+    "application/javascript",
+    "image/svg+xml",
+    "text/",
+];
+
+/// Header clients set to make retried mutations safe
+pub const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Header marking a response as a replay of a stored one
+pub const IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// Header carrying the ID a request is logged under, taken from the client
+/// or a proxy when sent and echoed on the response
+pub const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// How long stored responses are replayed
+pub const IDEMPOTENCY_TTL_HOURS: i64 = 24;
+
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+/// Plain-text error bodies from axum, such as JSON rejections, are short
+const MAX_TEXT_ERROR_BYTES: usize = 4096;
+
+/// Seconds clients are asked to wait after a 503 before retrying
+const RETRY_AFTER_SECONDS: u32 = 1;
+
+/// Which cross-origin requests browsers may make
+///
+/// The default allows any origin and method without credentials.
+#[derive(Debug, Clone, Default)]
+pub struct CorsConfig {
+    /// Allowed origins, such as `https://journal.example.com`; `None` allows any
+    pub origins: Option<Vec<HeaderValue>>,
+    /// Allowed methods; `None` allows any
+    pub methods: Option<Vec<Method>>,
+    /// Let browsers send cookies and read responses to credentialed requests
+    pub allow_credentials: bool,
+}
+
+impl CorsConfig {
+    /// Parse and check the `[cors]` settings
+    pub fn from_settings(settings: &CorsSettings) -> Result<Self, String> {
+        let origins = match &settings.allowed_origins {
+            Some(origins) => Some(
+                origins
+                    .iter()
+                    .map(|origin| parse_origin(origin))
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            None => None,
+        };
+        let methods = match &settings.allowed_methods {
+            Some(methods) => Some(
+                methods
+                    .iter()
+                    .map(|method| {
+                        method
+                            .to_ascii_uppercase()
+                            .parse::<Method>()
+                            .map_err(|_| format!("Invalid CORS method: {}", method))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            None => None,
+        };
+
+        let config = Self {
+            origins,
+            methods,
+            allow_credentials: settings.allow_credentials,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Browsers ignore credentialed responses that allow every origin
+    pub fn validate(&self) -> Result<(), String> {
+        if self.allow_credentials && self.origins.is_none() {
+            return Err("[cors] allow_credentials (CORS_ALLOW_CREDENTIALS) needs \
+                        allowed_origins (CORS_ALLOWED_ORIGINS)"
+                .to_string());
+        }
+        Ok(())
+    }
+}
+
+/// An origin is a scheme, host and optional port, with no path
+fn parse_origin(origin: &str) -> Result<HeaderValue, String> {
+    let invalid = || format!("Invalid CORS origin: {}", origin);
+    let host = origin
+        .strip_prefix("https://")
+        .or_else(|| origin.strip_prefix("http://"))
+        .ok_or_else(invalid)?;
+    if host.is_empty() || host.contains('/') {
+        return Err(invalid());
+    }
+    HeaderValue::from_str(origin).map_err(|_| invalid())
+}
+
+/// CORS layer configuration
+pub fn cors_layer(config: &CorsConfig) -> CorsLayer {
+    let origins = match &config.origins {
+        Some(origins) => AllowOrigin::list(origins.clone()),
+        None => AllowOrigin::from(cors::Any),
+    };
+    let methods = match &config.methods {
+        Some(methods) => AllowMethods::list(methods.clone()),
+        // `*` is not allowed alongside credentials
+        None if config.allow_credentials => AllowMethods::mirror_request(),
+        None => AllowMethods::from(cors::Any),
+    };
+
+    CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .allow_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            header::ACCEPT,
+            header::ORIGIN,
+            header::ACCEPT_ENCODING,
+            header::ACCEPT_LANGUAGE,
+            header::CACHE_CONTROL,
+            header::PRAGMA,
+            header::USER_AGENT,
+            IDEMPOTENCY_KEY,
+        ])
+        .allow_credentials(config.allow_credentials)
+}
+
+/// CORS settings that a config reload can swap while the server runs
+#[derive(Clone)]
+pub struct ReloadableCors(Arc<RwLock<CorsLayer>>);
+
+impl ReloadableCors {
+    pub fn new(config: &CorsConfig) -> Self {
+        Self(Arc::new(RwLock::new(cors_layer(config))))
+    }
+
+    /// Apply `config` to requests from now on
+    pub fn update(&self, config: &CorsConfig) {
+        *self.0.write().unwrap() = cors_layer(config);
+    }
+}
+
+/// CORS middleware - answers preflights and adds CORS headers with the
+/// current `ReloadableCors` settings
+pub async fn cors_middleware(
+    State(cors): State<ReloadableCors>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let layer = cors.0.read().unwrap().clone();
+    match layer.layer(next).oneshot(request).await {
+        Ok(response) => response,
+        Err(never) => match never {},
+    }
+}
+
+/// Compression layer configuration - gzip/brotli for large text responses
+pub fn compression_layer() -> CompressionLayer<impl Predicate> {
+    CompressionLayer::new()
+        .compress_when(SizeAbove::new(MIN_COMPRESSION_SIZE).and(is_compressible_content_type))
+}
+
+fn is_compressible_content_type(
+    _status: StatusCode,
+    _version: Version,
+    headers: &HeaderMap,
+    _extensions: &Extensions,
+) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .map(|content_type| {
+            COMPRESSIBLE_CONTENT_TYPES
+                .iter()
+                .any(|allowed| content_type.starts_with(allowed))
+        })
+        .unwrap_or(false)
+}
+
+/// Auth middleware - validates JWT and injects user_id into request extensions
+pub async fn auth_middleware(
+    State(state): State<SharedState>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    // Get Authorization header
+    let auth_header = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    // Extract and validate token
+    let token = extract_token_from_header(auth_header).map_err(|_| StatusCode::UNAUTHORIZED)?;
+
+    let claims = state
+        .auth
+        .validate_token(token)
+        .await
+        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    let user_id: UserId = claims
+        .user_id
+        .parse()
+        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+
+    // Tokens issued before the account was deleted or an admin disabled it
+    // stop working, and users told to pick a new password can do nothing
+    // else until they do
+    let user = state
+        .users
+        .find_by_id(&*state.storage, &user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    if user.disabled {
+        return Ok(account_disabled().into_response());
+    }
+    if user.must_reset_password && request.uri().path() != PASSWORD_CHANGE_PATH {
+        return Ok(password_reset_required().into_response());
+    }
+    state.activity.record(&*state.storage, &user_id).await;
+
+    // The bare ID is for the middleware further in, and AuthUser for
+    // handlers, keeping the record just loaded
+    request.extensions_mut().insert(user_id.clone());
+    let logged_user = LoggedUser(claims.user_id.clone());
+    request
+        .extensions_mut()
+        .insert(AuthUser::with_user(user, claims));
+
+    let mut response = next.run(request).await;
+    response.extensions_mut().insert(logged_user);
+    Ok(response)
+}
+
+/// Admin middleware - requires `Authorization: Bearer <ADMIN_TOKEN>`, or
+/// the JWT of a user with the `admin` role
+///
+/// Other users get 403. Without an admin token configured, requests that
+/// aren't from an admin user get 404.
+pub async fn admin_middleware(
+    State(state): State<SharedState>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| extract_token_from_header(h).ok())
+        .map(str::to_string);
+
+    if let (Some(token), Some(expected)) = (&token, &state.admin_token) {
+        if constant_time_eq(token.as_bytes(), expected.as_bytes()) {
+            request
+                .extensions_mut()
+                .insert(AdminActor(ACTOR_ADMIN_TOKEN.to_string()));
+            return Ok(next.run(request).await);
+        }
+    }
+
+    let claims = match token {
+        Some(token) => state.auth.validate_token(&token).await.ok(),
+        None => None,
+    };
+    let Some(claims) = claims else {
+        return Err(match state.admin_token {
+            Some(_) => StatusCode::UNAUTHORIZED,
+            None => StatusCode::NOT_FOUND,
+        });
+    };
+    let user_id: UserId = claims
+        .user_id
+        .parse()
+        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    let user = state
+        .users
+        .find_by_id(&*state.storage, &user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    if !user.is_admin() || user.disabled {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    request
+        .extensions_mut()
+        .insert(AdminActor(user.id.to_string()));
+
+    let mut response = next.run(request).await;
+    response.extensions_mut().insert(LoggedUser(user.id.into()));
+    Ok(response)
+}
+
+/// Compare secrets without exiting early on the first differing byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Idempotency middleware - replays the first response to a repeated
+/// `Idempotency-Key` on POST/PUT/DELETE
+///
+/// Must run after `auth_middleware`, since keys are scoped per user. Server
+/// errors are not stored so the client can retry them.
+pub async fn idempotency_middleware(
+    State(state): State<SharedState>,
+    request: Request<Body>,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let method = request.method().clone();
+    if !matches!(method, Method::POST | Method::PUT | Method::DELETE) {
+        return Ok(next.run(request).await);
+    }
+
+    let Some(key) = request.headers().get(&IDEMPOTENCY_KEY) else {
+        return Ok(next.run(request).await);
+    };
+    let key = key
+        .to_str()
+        .ok()
+        .filter(|k| !k.is_empty() && k.len() <= MAX_IDEMPOTENCY_KEY_LENGTH)
+        .ok_or(StatusCode::BAD_REQUEST)?
+        .to_string();
+
+    let user_id = request
+        .extensions()
+        .get::<UserId>()
+        .cloned()
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let path = request.uri().path().to_string();
+
+    let not_before =
+        (chrono::Utc::now() - chrono::Duration::hours(IDEMPOTENCY_TTL_HOURS)).to_rfc3339();
+    let stored = state
+        .storage
+        .get_idempotent_response(&user_id, &key, &not_before)
+        .await
+        .map_err(|e| {
+            if e.is_busy() {
+                StatusCode::SERVICE_UNAVAILABLE
+            } else {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    if let Some(stored) = stored {
+        // The same key must not be reused for a different request
+        if stored.method != method.as_str() || stored.path != path {
+            return Err(StatusCode::UNPROCESSABLE_ENTITY);
+        }
+        return Ok(replay(stored));
+    }
+
+    let response = next.run(request).await;
+    if response.status().is_server_error() {
+        return Ok(response);
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let record = IdempotentResponse {
+        method: method.to_string(),
+        path,
+        status: parts.status.as_u16() as i64,
+        content_type: parts
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|h| h.to_str().ok())
+            .map(String::from),
+        body: body.to_vec(),
+    };
+    // Failing to record only loses replay protection for this request
+    let _ = state
+        .storage
+        .save_idempotent_response(&user_id, &key, &record)
+        .await;
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+fn replay(stored: IdempotentResponse) -> Response {
+    let mut response = Response::new(Body::from(stored.body));
+    *response.status_mut() = StatusCode::from_u16(stored.status as u16).unwrap_or(StatusCode::OK);
+
+    let headers = response.headers_mut();
+    if let Some(value) = stored
+        .content_type
+        .and_then(|c| HeaderValue::from_str(&c).ok())
+    {
+        headers.insert(header::CONTENT_TYPE, value);
+    }
+    headers.insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+
+    response
+}
+
+/// Rate limiting middleware - takes each request from a token bucket per
+/// user, or per client IP for requests without one
+///
+/// Must run after `auth_middleware` on protected routes to see the user.
+/// Rejected requests get `429` with `Retry-After`.
+pub async fn rate_limit_middleware(
+    State(state): State<SharedState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let limits = &state.rate_limits;
+    if !limits.is_enabled() {
+        return next.run(request).await;
+    }
+    let Some(class) = RouteClass::of(request.method(), request.uri().path()) else {
+        return next.run(request).await;
+    };
+
+    let key = match request.extensions().get::<UserId>() {
+        Some(user_id) => format!("user:{}", user_id),
+        None => match request.extensions().get::<ClientIp>() {
+            Some(ClientIp(ip)) => format!("ip:{}", ip),
+            None => "ip:unknown".to_string(),
+        },
+    };
+
+    if let Err(wait) = limits.check(class, &key).await {
+        // Round up, so clients that wait exactly this long get through
+        let seconds = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, HeaderValue::from(seconds))],
+            ErrorResponse::new("Too many requests, please slow down"),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Client address middleware - records the real client address, looking
+/// through trusted proxies, as a `ClientIp` extension for rate limiting and
+/// logging
+///
+/// Must run before anything that reads `ClientIp`. Requests without a peer
+/// address, such as in tests, get none.
+pub async fn client_ip_middleware(
+    State(proxies): State<TrustedProxies>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    if let Some(ConnectInfo(peer)) = request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        let client = proxies.client_ip(peer.ip(), request.headers());
+        request.extensions_mut().insert(ClientIp(client));
+    }
+
+    next.run(request).await
+}
+
+/// Access log middleware - gives each request an `X-Request-Id`, which
+/// everything logged while handling it carries, and, when an access log is
+/// configured, writes a JSON line for it once it is answered
+///
+/// Runs inside `client_ip_middleware` and outside everything else, so the
+/// latency covers the whole stack.
+pub async fn access_log_middleware(
+    State(log): State<Option<AccessLog>>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    let request_id = match request.headers().get(REQUEST_ID) {
+        Some(id) if is_valid_request_id(id) => id.clone(),
+        _ => {
+            let id = HeaderValue::from_str(&uuid::Uuid::new_v4().to_string())
+                .expect("UUIDs are valid header values");
+            request.headers_mut().insert(REQUEST_ID, id.clone());
+            id
+        }
+    };
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = request_id.to_str().unwrap_or_default()
+    );
+
+    let Some(log) = log else {
+        let mut response = next.run(request).instrument(span).await;
+        response.headers_mut().insert(REQUEST_ID, request_id);
+        return response;
+    };
+
+    let started = Instant::now();
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let client_ip = request
+        .extensions()
+        .get::<ClientIp>()
+        .map(|ClientIp(ip)| *ip);
+
+    let mut response = next.run(request).instrument(span).await;
+
+    log.write(&AccessLogEntry {
+        timestamp: chrono::Utc::now(),
+        request_id: request_id.to_str().unwrap_or_default(),
+        method: method.as_str(),
+        path: &path,
+        status: response.status().as_u16(),
+        latency_ms: latency_ms(started.elapsed()),
+        user_id: response
+            .extensions()
+            .get::<LoggedUser>()
+            .map(|LoggedUser(id)| id.as_str()),
+        client_ip,
+    });
+    response.headers_mut().insert(REQUEST_ID, request_id);
+    response
+}
+
+/// Answer a request whose handler panicked with a JSON 500, for
+/// `CatchPanicLayer`
+///
+/// The panic is logged inside the request's span, so with its request ID.
+pub fn panic_response(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let detail = panic
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| panic.downcast_ref::<&str>().copied())
+        .unwrap_or("unknown panic");
+    tracing::error!("Handler panicked: {}", detail);
+
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        ErrorResponse::new("Internal server error"),
+    )
+        .into_response()
+}
+
+/// Incoming IDs end up in logs, so only short printable ones are kept
+fn is_valid_request_id(id: &HeaderValue) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id.as_bytes().iter().all(|b| b.is_ascii_graphic())
+}
+
+/// Maintenance middleware - answers API requests with 503, `Retry-After`
+/// and the operator's message while maintenance mode is on
+///
+/// Health checks keep answering, so orchestrators don't restart the server,
+/// as do operator endpoints, so maintenance mode can be switched off. The
+/// frontend is still served, so it can show the message.
+pub async fn maintenance_middleware(
+    State(state): State<SharedState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    let exempt =
+        !path.starts_with("/api/") || path == "/api/health" || path.starts_with("/api/admin/");
+    let notice = if exempt {
+        None
+    } else {
+        state.maintenance_mode.notice()
+    };
+    let Some(notice) = notice else {
+        return next.run(request).await;
+    };
+
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(
+            header::RETRY_AFTER,
+            HeaderValue::from(notice.retry_after_secs),
+        )],
+        ErrorResponse::new(notice.message),
+    )
+        .into_response()
+}
+
+/// Add `Retry-After` to 503 responses, which handlers return when the
+/// database stayed locked through every retry
+pub async fn retry_after(mut response: Response) -> Response {
+    if response.status() == StatusCode::SERVICE_UNAVAILABLE
+        && !response.headers().contains_key(header::RETRY_AFTER)
+    {
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(RETRY_AFTER_SECONDS));
+    }
+
+    response
+}
+
+/// How long requests may take before they are abandoned with a 504
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    pub default: Duration,
+    /// For exports and imports, which go through every message
+    pub long: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self::from_config(&ServerConfig::default())
+    }
+}
+
+impl Timeouts {
+    pub fn from_config(server: &ServerConfig) -> Self {
+        Self {
+            default: Duration::from_secs(server.request_timeout_secs),
+            long: Duration::from_secs(server.long_request_timeout_secs),
+        }
+    }
+
+    fn for_path(&self, path: &str) -> Duration {
+        if path.starts_with("/api/export/") || path.starts_with("/api/import/") {
+            self.long
+        } else {
+            self.default
+        }
+    }
+}
+
+/// Timeout middleware - abandons requests that take longer than their
+/// budget, so a hung database write can't hold a client connection forever
+///
+/// Only the time to a response counts; streamed downloads and WebSockets
+/// carry on past it.
+pub async fn timeout_middleware(
+    State(timeouts): State<Timeouts>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let budget = timeouts.for_path(request.uri().path());
+
+    match tokio::time::timeout(budget, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            tracing::warn!("Request timed out after {:?}", budget);
+            (
+                StatusCode::GATEWAY_TIMEOUT,
+                ErrorResponse::new("Request timed out"),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// JSON error middleware - gives API errors that aren't already JSON, such
+/// as unknown routes, wrong methods and malformed JSON bodies, the
+/// `ErrorResponse` shape
+///
+/// axum's plain-text message is kept as the error; bodiless errors get the
+/// status's reason, such as `Not Found`. Other headers, like `Allow` on
+/// 405s, are kept.
+pub async fn json_errors(request: Request<Body>, next: Next) -> Response {
+    let is_api = request.uri().path().starts_with("/api/");
+    let response = next.run(request).await;
+
+    let status = response.status();
+    if !is_api || !(status.is_client_error() || status.is_server_error()) {
+        return response;
+    }
+    let is_text = match response.headers().get(header::CONTENT_TYPE) {
+        None => false,
+        Some(content_type) if content_type.as_bytes().starts_with(b"text/plain") => true,
+        // Already JSON, or a file
+        Some(_) => return response,
+    };
+
+    let (mut parts, body) = response.into_parts();
+    let mut message = String::new();
+    if is_text {
+        if let Ok(bytes) = axum::body::to_bytes(body, MAX_TEXT_ERROR_BYTES).await {
+            message = String::from_utf8_lossy(&bytes).trim().to_string();
+        }
+    }
+    if message.is_empty() {
+        message = status.canonical_reason().unwrap_or("Error").to_string();
+    }
+
+    // These describe the old body, and would override the JSON one's
+    parts.headers.remove(header::CONTENT_TYPE);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    (parts, ErrorResponse::new(message)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        auth::create_token,
+        models::{Claims, User},
+        testing::test_app_state,
+        utils::hash_password,
+    };
+    use axum::{
+        body::Body,
+        http::{header, Request, StatusCode},
+        middleware::from_fn_with_state,
+        response::IntoResponse,
+        routing::{get, post, put},
+        Router,
+    };
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tower::ServiceExt;
+
+    async fn setup_test_state() -> SharedState {
+        Arc::new(test_app_state().await)
+    }
+
+    /// Only tokens of existing accounts are accepted
+    async fn create_test_user(state: &SharedState, email: &str) -> UserId {
+        let (hash, salt) = hash_password("password123").unwrap();
+        let user = User::new(email.to_string(), email.to_string(), hash, salt);
+        state.storage.create_user(&user).await.unwrap();
+        user.id
+    }
+
+    async fn test_handler(request: Request<Body>) -> impl IntoResponse {
+        let user_id = request
+            .extensions()
+            .get::<UserId>()
+            .map(UserId::to_string)
+            .unwrap_or_default();
+        (StatusCode::OK, user_id)
+    }
+
+    fn create_test_router(state: SharedState) -> Router {
+        Router::new()
+            .route("/protected", get(test_handler))
+            .layer(from_fn_with_state(state.clone(), auth_middleware))
+            .with_state(state)
+    }
+
+    #[tokio::test]
+    async fn test_auth_middleware_valid_token() {
+        let state = setup_test_state().await;
+        let user_id = create_test_user(&state, "valid@example.com").await;
+        let token = create_token(&user_id, "test-secret").unwrap();
+
+        let app = create_test_router(state);
+
+        let request = Request::builder()
+            .uri("/protected")
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_auth_middleware_missing_header() {
+        let state = setup_test_state().await;
+        let app = create_test_router(state);
+
+        let request = Request::builder()
+            .uri("/protected")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_auth_middleware_invalid_token() {
+        let state = setup_test_state().await;
+        let app = create_test_router(state);
+
+        let request = Request::builder()
+            .uri("/protected")
+            .header(header::AUTHORIZATION, "Bearer invalid-token")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_auth_middleware_wrong_secret() {
+        let state = setup_test_state().await;
+        let token = create_token(&UserId::generate(), "wrong-secret").unwrap();
+
+        let app = create_test_router(state);
+
+        let request = Request::builder()
+            .uri("/protected")
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_auth_middleware_basic_auth_rejected() {
+        let state = setup_test_state().await;
+        let app = create_test_router(state);
+
+        let request = Request::builder()
+            .uri("/protected")
+            .header(header::AUTHORIZATION, "Basic dXNlcjpwYXNz")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_cors_layer_configuration() {
+        let _cors = cors_layer(&CorsConfig::default());
+        // Just verify it builds without error
+        assert!(true);
+    }
+
+    #[tokio::test]
+    async fn test_cors_allow_list() {
+        let config = CorsConfig {
+            origins: Some(vec![parse_origin("https://journal.example.com").unwrap()]),
+            methods: None,
+            allow_credentials: true,
+        };
+        config.validate().unwrap();
+        let app: Router = Router::new()
+            .route("/ok", get(|| async { StatusCode::OK }))
+            .layer(cors_layer(&config));
+
+        let preflight = |origin: &str| {
+            Request::builder()
+                .method("OPTIONS")
+                .uri("/ok")
+                .header(header::ORIGIN, origin)
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "PUT")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let allowed = app
+            .clone()
+            .oneshot(preflight("https://journal.example.com"))
+            .await
+            .unwrap();
+        let headers = allowed.headers();
+        assert_eq!(
+            headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "https://journal.example.com"
+        );
+        assert_eq!(
+            headers
+                .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+                .unwrap(),
+            "true"
+        );
+        assert_eq!(
+            headers.get(header::ACCESS_CONTROL_ALLOW_METHODS).unwrap(),
+            "PUT"
+        );
+
+        let other = app
+            .oneshot(preflight("https://evil.example.com"))
+            .await
+            .unwrap();
+        assert!(other
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cors_reload() {
+        let cors = ReloadableCors::new(&CorsConfig {
+            origins: Some(vec![parse_origin("https://old.example.com").unwrap()]),
+            ..Default::default()
+        });
+        let app: Router = Router::new()
+            .route("/ok", get(|| async { StatusCode::OK }))
+            .layer(from_fn_with_state(cors.clone(), cors_middleware));
+        let allowed_origin = |app: Router| async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/ok")
+                        .header(header::ORIGIN, "https://new.example.com")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            response
+                .headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .cloned()
+        };
+
+        assert!(allowed_origin(app.clone()).await.is_none());
+
+        cors.update(&CorsConfig {
+            origins: Some(vec![parse_origin("https://new.example.com").unwrap()]),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            allowed_origin(app).await.unwrap(),
+            "https://new.example.com"
+        );
+    }
+
+    #[test]
+    fn test_cors_config_validation() {
+        assert!(parse_origin("https://journal.example.com:8443").is_ok());
+        assert!(parse_origin("https://journal.example.com/").is_err());
+        assert!(parse_origin("journal.example.com").is_err());
+
+        let credentials_with_any_origin = CorsConfig {
+            allow_credentials: true,
+            ..Default::default()
+        };
+        assert!(credentials_with_any_origin.validate().is_err());
+    }
+
+    #[test]
+    fn test_compressible_content_types() {
+        let check = |content_type: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_str(content_type).unwrap(),
+            );
+            is_compressible_content_type(
+                StatusCode::OK,
+                Version::HTTP_11,
+                &headers,
+                &Extensions::new(),
+            )
+        };
+
+        assert!(check("application/json"));
+        assert!(check("text/markdown; charset=utf-8"));
+        assert!(!check("image/png"));
+        assert!(!check("application/zip"));
+    }
+
+    #[tokio::test]
+    async fn test_auth_middleware_injects_user_id() {
+        let state = setup_test_state().await;
+        let user_id = create_test_user(&state, "injected@example.com").await;
+        let token = create_token(&user_id, "test-secret").unwrap();
+
+        let app = create_test_router(state);
+
+        let request = Request::builder()
+            .uri("/protected")
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // The handler returns the user_id in the body
+        let body = axum::body::to_bytes(response.into_body(), 1024)
+            .await
+            .unwrap();
+        assert_eq!(String::from_utf8(body.to_vec()).unwrap(), user_id);
+    }
+
+    #[tokio::test]
+    async fn test_auth_user_carries_claims_and_loads_user() {
+        async fn whoami(
+            State(state): State<SharedState>,
+            auth: AuthUser,
+        ) -> Result<String, crate::error::AppError> {
+            let user = auth.user(&state).await?;
+            Ok(format!("{} {}", auth.claims.user_id, user.username))
+        }
+
+        let state = setup_test_state().await;
+        let (hash, salt) = hash_password("password123").unwrap();
+        let user = User::new(
+            "claims@example.com".to_string(),
+            "claims".to_string(),
+            hash,
+            salt,
+        );
+        state.storage.create_user(&user).await.unwrap();
+        let token = create_token(&user.id, "test-secret").unwrap();
+        let app = Router::new()
+            .route("/whoami", get(whoami))
+            .layer(from_fn_with_state(state.clone(), auth_middleware))
+            .with_state(state);
+
+        let request = Request::builder()
+            .uri("/whoami")
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), 1024)
+            .await
+            .unwrap();
+        assert_eq!(body, format!("{} claims", user.id));
+    }
+
+    #[tokio::test]
+    async fn test_auth_middleware_rejects_tokens_not_naming_a_user() {
+        let state = setup_test_state().await;
+        let claims = Claims {
+            user_id: "not-a-uuid".to_string(),
+            exp: (chrono::Utc::now().timestamp() + 3600) as usize,
+        };
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &claims,
+            &jsonwebtoken::EncodingKey::from_secret(b"test-secret"),
+        )
+        .unwrap();
+
+        let request = Request::builder()
+            .uri("/protected")
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+        let response = create_test_router(state).oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_auth_middleware_rejects_disabled_users() {
+        let state = setup_test_state().await;
+        let (hash, salt) = hash_password("password123").unwrap();
+        let user = User::new(
+            "disabled@example.com".to_string(),
+            "disabled".to_string(),
+            hash,
+            salt,
+        );
+        state.storage.create_user(&user).await.unwrap();
+        let token = create_token(&user.id, "test-secret").unwrap();
+        let app = create_test_router(state.clone());
+        let request = || {
+            Request::builder()
+                .uri("/protected")
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        state
+            .storage
+            .set_user_disabled(&user.id, true)
+            .await
+            .unwrap();
+        state.users.invalidate(&user.id).await;
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = axum::body::to_bytes(response.into_body(), 1024)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["code"], "account_disabled");
+    }
+
+    #[tokio::test]
+    async fn test_auth_middleware_rejects_deleted_users() {
+        let state = setup_test_state().await;
+        let (hash, salt) = hash_password("password123").unwrap();
+        let user = User::new(
+            "deleted@example.com".to_string(),
+            "deleted".to_string(),
+            hash,
+            salt,
+        );
+        state.storage.create_user(&user).await.unwrap();
+        let token = create_token(&user.id, "test-secret").unwrap();
+        let app = create_test_router(state.clone());
+        let request = || {
+            Request::builder()
+                .uri("/protected")
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        state.storage.delete_user(&user.id).await.unwrap();
+        state.users.invalidate(&user.id).await;
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_auth_middleware_requires_password_reset() {
+        let state = setup_test_state().await;
+        let (hash, salt) = hash_password("password123").unwrap();
+        let mut user = User::new(
+            "reset@example.com".to_string(),
+            "reset".to_string(),
+            hash,
+            salt,
+        );
+        user.must_reset_password = true;
+        state.storage.create_user(&user).await.unwrap();
+        let token = create_token(&user.id, "test-secret").unwrap();
+        let app = Router::new()
+            .route("/protected", get(test_handler))
+            .route(PASSWORD_CHANGE_PATH, put(test_handler))
+            .layer(from_fn_with_state(state.clone(), auth_middleware))
+            .with_state(state);
+
+        for (method, uri, expected) in [
+            ("GET", "/protected", StatusCode::FORBIDDEN),
+            ("PUT", PASSWORD_CHANGE_PATH, StatusCode::OK),
+        ] {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), expected, "{}", uri);
+        }
+    }
+
+    fn admin_request(token: Option<&str>) -> Request<Body> {
+        let mut builder = Request::builder().uri("/admin");
+        if let Some(token) = token {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_admin_middleware_requires_admin_token() {
+        let mut state = setup_test_state().await;
+        let user_token = create_token(&UserId::generate(), "test-secret").unwrap();
+
+        let disabled = Router::new()
+            .route("/admin", get(test_handler))
+            .layer(from_fn_with_state(state.clone(), admin_middleware))
+            .with_state(state.clone());
+        let response = disabled
+            .oneshot(admin_request(Some("anything")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        Arc::get_mut(&mut state).unwrap().admin_token = Some("admin-secret".to_string());
+        let app = Router::new()
+            .route("/admin", get(test_handler))
+            .layer(from_fn_with_state(state.clone(), admin_middleware))
+            .with_state(state);
+
+        for (token, expected) in [
+            (None, StatusCode::UNAUTHORIZED),
+            (Some(user_token.as_str()), StatusCode::UNAUTHORIZED),
+            (Some("admin-secre"), StatusCode::UNAUTHORIZED),
+            (Some("admin-secret"), StatusCode::OK),
+        ] {
+            let response = app.clone().oneshot(admin_request(token)).await.unwrap();
+            assert_eq!(response.status(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_admin_middleware_accepts_admin_users() {
+        let state = setup_test_state().await;
+        let (hash, salt) = hash_password("password123").unwrap();
+        let user = User::new(
+            "user@example.com".to_string(),
+            "user".to_string(),
+            hash.clone(),
+            salt.clone(),
+        );
+        let mut admin = User::new(
+            "admin@example.com".to_string(),
+            "admin".to_string(),
+            hash,
+            salt,
+        );
+        admin.role = crate::models::ROLE_ADMIN.to_string();
+        state.storage.create_user(&user).await.unwrap();
+        state.storage.create_user(&admin).await.unwrap();
+
+        // Admin users don't need ADMIN_TOKEN to be configured
+        let app = Router::new()
+            .route("/admin", get(test_handler))
+            .layer(from_fn_with_state(state.clone(), admin_middleware))
+            .with_state(state.clone());
+
+        for (user_id, expected) in [
+            (&user.id, StatusCode::FORBIDDEN),
+            (&admin.id, StatusCode::OK),
+        ] {
+            let token = create_token(user_id, "test-secret").unwrap();
+            let response = app
+                .clone()
+                .oneshot(admin_request(Some(&token)))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected);
+        }
+    }
+
+    fn create_idempotency_test_router(state: SharedState, counter: Arc<AtomicUsize>) -> Router {
+        let handler = move || {
+            let counter = counter.clone();
+            async move {
+                let count = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                (StatusCode::CREATED, count.to_string())
+            }
+        };
+
+        Router::new()
+            .route("/items", post(handler.clone()))
+            .route("/other", post(handler))
+            .layer(from_fn_with_state(state.clone(), idempotency_middleware))
+            .layer(from_fn_with_state(state.clone(), auth_middleware))
+            .with_state(state)
+    }
+
+    fn idempotent_request(uri: &str, token: &str, key: &str) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .header(IDEMPOTENCY_KEY, key)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_middleware_replays_response() {
+        let state = setup_test_state().await;
+        let (hash, salt) = hash_password("password123").unwrap();
+        let user = User::new(
+            "idem@example.com".to_string(),
+            "idem".to_string(),
+            hash,
+            salt,
+        );
+        state.storage.create_user(&user).await.unwrap();
+        let token = create_token(&user.id, "test-secret").unwrap();
+
+        let counter = Arc::new(AtomicUsize::new(0));
+        let app = create_idempotency_test_router(state, counter.clone());
+
+        let first = app
+            .clone()
+            .oneshot(idempotent_request("/items", &token, "key-1"))
+            .await
+            .unwrap();
+        assert_eq!(first.status(), StatusCode::CREATED);
+
+        let retry = app
+            .clone()
+            .oneshot(idempotent_request("/items", &token, "key-1"))
+            .await
+            .unwrap();
+        assert_eq!(retry.status(), StatusCode::CREATED);
+        assert_eq!(retry.headers().get(IDEMPOTENT_REPLAYED).unwrap(), "true");
+        let body = axum::body::to_bytes(retry.into_body(), 1024).await.unwrap();
+        assert_eq!(&body[..], b"1");
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+        // Reusing the key for another request is rejected
+        let mismatched = app
+            .clone()
+            .oneshot(idempotent_request("/other", &token, "key-1"))
+            .await
+            .unwrap();
+        assert_eq!(mismatched.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        // A new key runs the handler again
+        let fresh = app
+            .oneshot(idempotent_request("/items", &token, "key-2"))
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(fresh.into_body(), 1024).await.unwrap();
+        assert_eq!(&body[..], b"2");
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_added_to_unavailable_responses() {
+        let app: Router = Router::new()
+            .route("/busy", get(|| async { StatusCode::SERVICE_UNAVAILABLE }))
+            .route("/ok", get(|| async { StatusCode::OK }))
+            .layer(axum::middleware::map_response(retry_after));
+
+        let busy = app
+            .clone()
+            .oneshot(Request::builder().uri("/busy").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(busy.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(busy.headers().get(header::RETRY_AFTER).unwrap(), "1");
+
+        let ok = app
+            .oneshot(Request::builder().uri("/ok").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert!(ok.headers().get(header::RETRY_AFTER).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_per_user_and_ip() {
+        let mut state = Arc::into_inner(setup_test_state().await).unwrap();
+        state.rate_limits = crate::ratelimit::RateLimits::new(None, Some(1), None);
+        let state = Arc::new(state);
+
+        let protected = Router::new()
+            .route("/protected", get(test_handler))
+            .layer(from_fn_with_state(state.clone(), rate_limit_middleware))
+            .layer(from_fn_with_state(state.clone(), auth_middleware));
+        let public = Router::new()
+            .route("/public", get(|| async { StatusCode::OK }))
+            .layer(from_fn_with_state(state.clone(), rate_limit_middleware))
+            .layer(from_fn_with_state(
+                TrustedProxies::new(vec!["10.0.0.0/8".parse().unwrap()]),
+                client_ip_middleware,
+            ));
+        let app = protected.merge(public).with_state(state.clone());
+
+        let as_user = |user_id: &UserId| {
+            let token = create_token(user_id, "test-secret").unwrap();
+            Request::builder()
+                .uri("/protected")
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap()
+        };
+        // Through a trusted proxy, which passes on the client address
+        let from_ip = |ip: &str| {
+            Request::builder()
+                .uri("/public")
+                .extension(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 443))))
+                .header("x-forwarded-for", format!("198.51.100.1, {}", ip))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let user_id = create_test_user(&state, "limited@example.com").await;
+        let first = app.clone().oneshot(as_user(&user_id)).await.unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        let second = app.clone().oneshot(as_user(&user_id)).await.unwrap();
+        assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(second.headers().get(header::RETRY_AFTER).unwrap(), "60");
+        let other_id = create_test_user(&state, "other@example.com").await;
+        let other = app.clone().oneshot(as_user(&other_id)).await.unwrap();
+        assert_eq!(other.status(), StatusCode::OK);
+
+        let first = app.clone().oneshot(from_ip("192.0.2.1")).await.unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        let second = app.clone().oneshot(from_ip("192.0.2.1")).await.unwrap();
+        assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+        let other = app.oneshot(from_ip("192.0.2.2")).await.unwrap();
+        assert_eq!(other.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_panics_become_json_500s() {
+        async fn panics() -> StatusCode {
+            panic!("boom")
+        }
+        let app: Router = Router::new().route("/panic", get(panics)).layer(
+            tower_http::catch_panic::CatchPanicLayer::custom(panic_response),
+        );
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/panic")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = axum::body::to_bytes(response.into_body(), 1024)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "Internal server error");
+    }
+
+    #[tokio::test]
+    async fn test_timeouts_per_route() {
+        async fn slow() -> StatusCode {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            StatusCode::OK
+        }
+        let timeouts = Timeouts {
+            default: Duration::from_millis(50),
+            long: Duration::from_secs(5),
+        };
+        let app: Router = Router::new()
+            .route("/api/messages", get(slow))
+            .route("/api/export/json", get(slow))
+            .layer(from_fn_with_state(timeouts, timeout_middleware));
+        let get_path = |path: &str| {
+            app.clone()
+                .oneshot(Request::builder().uri(path).body(Body::empty()).unwrap())
+        };
+
+        let timed_out = get_path("/api/messages").await.unwrap();
+        assert_eq!(timed_out.status(), StatusCode::GATEWAY_TIMEOUT);
+        let body = axum::body::to_bytes(timed_out.into_body(), 1024)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "Request timed out");
+
+        let export = get_path("/api/export/json").await.unwrap();
+        assert_eq!(export.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_api_errors_are_json() {
+        async fn create(axum::Json(_): axum::Json<serde_json::Value>) -> StatusCode {
+            StatusCode::CREATED
+        }
+        let app: Router = Router::new()
+            .route("/api/items", post(create))
+            .route("/page", get(|| async { StatusCode::OK }))
+            .layer(axum::middleware::from_fn(json_errors));
+        let send = |method: &str, uri: &str, body: &'static str| {
+            app.clone().oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+        };
+        let error = |response: Response| async move {
+            assert_eq!(
+                response.headers().get(header::CONTENT_TYPE).unwrap(),
+                "application/json"
+            );
+            let body = axum::body::to_bytes(response.into_body(), 1024)
+                .await
+                .unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            body["error"].as_str().unwrap().to_string()
+        };
+
+        let missing = send("GET", "/api/missing", "").await.unwrap();
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+        assert_eq!(error(missing).await, "Not Found");
+
+        let wrong_method = send("DELETE", "/api/items", "").await.unwrap();
+        assert_eq!(wrong_method.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert!(wrong_method.headers().contains_key(header::ALLOW));
+        assert_eq!(error(wrong_method).await, "Method Not Allowed");
+
+        let malformed = send("POST", "/api/items", "{").await.unwrap();
+        assert_eq!(malformed.status(), StatusCode::BAD_REQUEST);
+        assert!(error(malformed)
+            .await
+            .contains("Failed to parse the request body as JSON"));
+
+        let created = send("POST", "/api/items", "{}").await.unwrap();
+        assert_eq!(created.status(), StatusCode::CREATED);
+
+        // Only the API; the frontend keeps its own 404s
+        let page = send("GET", "/missing", "").await.unwrap();
+        assert_eq!(page.status(), StatusCode::NOT_FOUND);
+        assert!(page.headers().get(header::CONTENT_TYPE).is_none());
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_access_log() {
+        let state = setup_test_state().await;
+        let buffer = SharedBuffer::default();
+        let log = AccessLog::new(Box::new(buffer.clone()));
+        let app = create_test_router(state.clone())
+            .layer(from_fn_with_state(Some(log), access_log_middleware))
+            .layer(from_fn_with_state(
+                TrustedProxies::default(),
+                client_ip_middleware,
+            ));
+
+        let user_id = create_test_user(&state, "logged@example.com").await;
+        let token = create_token(&user_id, "test-secret").unwrap();
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/protected?token=secret")
+                    .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                    .header(REQUEST_ID, "req-1")
+                    .extension(ConnectInfo(SocketAddr::from(([192, 0, 2, 1], 50000))))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.headers().get(REQUEST_ID).unwrap(), "req-1");
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/protected")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let generated = response
+            .headers()
+            .get(REQUEST_ID)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(uuid::Uuid::parse_str(generated).is_ok());
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["request_id"], "req-1");
+        assert_eq!(lines[0]["method"], "GET");
+        assert_eq!(lines[0]["path"], "/protected");
+        assert_eq!(lines[0]["status"], 200);
+        assert_eq!(lines[0]["user_id"], user_id.as_str());
+        assert_eq!(lines[0]["client_ip"], "192.0.2.1");
+        assert!(lines[0]["latency_ms"].is_number());
+        assert_eq!(lines[1]["request_id"], generated);
+        assert_eq!(lines[1]["status"], 401);
+        assert!(lines[1]["user_id"].is_null());
+    }
+}
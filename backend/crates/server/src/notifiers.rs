@@ -0,0 +1,535 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use reqwest::redirect;
+use serde_json::json;
+
+use crate::{
+    db::DbError,
+    error::AppError,
+    handlers::{storage_error, AppState, SharedState},
+    ids::UserId,
+    matrix::MatrixRoom,
+    models::{
+        CreateNotificationChannelRequest, NotificationChannel, NotificationChannelResponse,
+        NotificationChannelsResponse, SuccessResponse, NOTIFICATION_CHANNEL_GOTIFY,
+        NOTIFICATION_CHANNEL_NTFY,
+    },
+    outbound::OutboundPolicy,
+    storage::Storage,
+    AuthUser,
+};
+
+/// Most notification channels one user can add
+pub const MAX_CHANNELS_PER_USER: usize = 5;
+
+/// How long a channel's server gets to accept a notification
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Notification shown by a user's ntfy or Gotify app
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+    /// Page to open when the notification is clicked
+    pub url: Option<String>,
+}
+
+/// A service that can show a user notifications
+#[axum::async_trait]
+pub trait Notifier: Send + Sync {
+    async fn send(
+        &self,
+        client: &reqwest::Client,
+        notification: &Notification,
+    ) -> Result<(), String>;
+}
+
+/// Publishes to an ntfy topic, on ntfy.sh or a self-hosted server
+pub struct Ntfy {
+    topic_url: String,
+    /// Access token for protected topics
+    token: Option<String>,
+}
+
+#[axum::async_trait]
+impl Notifier for Ntfy {
+    async fn send(
+        &self,
+        client: &reqwest::Client,
+        notification: &Notification,
+    ) -> Result<(), String> {
+        let mut request = client
+            .post(&self.topic_url)
+            .header("Title", &notification.title)
+            .body(notification.body.clone());
+        if let Some(url) = &notification.url {
+            request = request.header("Click", url);
+        }
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Failed to post to ntfy: {}", e))?;
+        Ok(())
+    }
+}
+
+/// Posts to a Gotify server as one of its applications
+pub struct Gotify {
+    server_url: String,
+    /// Application token
+    token: String,
+}
+
+#[axum::async_trait]
+impl Notifier for Gotify {
+    async fn send(
+        &self,
+        client: &reqwest::Client,
+        notification: &Notification,
+    ) -> Result<(), String> {
+        let mut body = json!({
+            "title": notification.title,
+            "message": notification.body,
+        });
+        if let Some(url) = &notification.url {
+            body["extras"] = json!({ "client::notification": { "click": { "url": url } } });
+        }
+
+        client
+            .post(format!("{}/message", self.server_url.trim_end_matches('/')))
+            .header("X-Gotify-Key", &self.token)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Failed to post to Gotify: {}", e))?;
+        Ok(())
+    }
+}
+
+/// The backend for a channel, or `None` for an unknown kind or a Gotify
+/// channel without a token
+pub fn notifier_for(channel: &NotificationChannel) -> Option<Box<dyn Notifier>> {
+    match channel.kind.as_str() {
+        NOTIFICATION_CHANNEL_NTFY => Some(Box::new(Ntfy {
+            topic_url: channel.url.clone(),
+            token: channel.token.clone(),
+        })),
+        NOTIFICATION_CHANNEL_GOTIFY => Some(Box::new(Gotify {
+            server_url: channel.url.clone(),
+            token: channel.token.clone()?,
+        })),
+        _ => None,
+    }
+}
+
+/// The client notifications are sent with, and where it may send them
+#[derive(Debug, Clone)]
+pub struct Notifiers {
+    client: reqwest::Client,
+    outbound: OutboundPolicy,
+}
+
+impl Default for Notifiers {
+    fn default() -> Self {
+        Self::new(OutboundPolicy::default())
+    }
+}
+
+impl Notifiers {
+    pub fn new(outbound: OutboundPolicy) -> Self {
+        // A redirect could lead to an address the URL check would refuse
+        let client = reqwest::Client::builder()
+            .redirect(redirect::Policy::none())
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("valid HTTP client configuration");
+
+        Self { client, outbound }
+    }
+
+    /// The shared HTTP client, for other integrations' API calls
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// Send a notification to every channel of a user, and to their Matrix
+    /// room if one is connected, returning what went wrong if any failed
+    pub async fn notify(
+        &self,
+        storage: &dyn Storage,
+        user_id: &UserId,
+        notification: &Notification,
+    ) -> Result<(), String> {
+        let channels = storage
+            .list_notification_channels(user_id)
+            .await
+            .map_err(|e| format!("Failed to load notification channels: {}", e))?;
+
+        let mut errors = Vec::new();
+        for channel in &channels {
+            let result = match notifier_for(channel) {
+                // Checked again, since the host may resolve somewhere else by now
+                Some(notifier) => match self.outbound.check(&channel.url).await {
+                    Ok(()) => notifier.send(&self.client, notification).await,
+                    Err(e) => Err(e),
+                },
+                None => Err(format!("Unusable {} channel {}", channel.kind, channel.id)),
+            };
+            if let Err(e) = result {
+                errors.push(e);
+            }
+        }
+
+        let matrix = storage
+            .get_matrix_account(user_id)
+            .await
+            .map_err(|e| format!("Failed to load Matrix account: {}", e))?;
+        if let Some(account) = matrix {
+            let result = match self.outbound.check(&account.homeserver_url).await {
+                Ok(()) => {
+                    MatrixRoom::for_account(&account)
+                        .send(&self.client, notification)
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                errors.push(e);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+}
+
+/// Notify a user's channels in the background; failures are only logged
+pub fn notify_user(state: &AppState, user_id: &UserId, notification: Notification) {
+    let notifiers = state.notifiers.clone();
+    let storage = state.storage.clone();
+    let user_id = user_id.clone();
+
+    tokio::spawn(async move {
+        if let Err(e) = notifiers
+            .notify(storage.as_ref(), &user_id, &notification)
+            .await
+        {
+            tracing::warn!("Failed to notify user {}: {}", user_id, e);
+        }
+    });
+}
+
+// ============ Handlers ============
+
+/// GET /api/user/notification-channels
+/// List the services the authenticated user's notifications are sent to
+#[utoipa::path(
+    get,
+    path = "/api/user/notification-channels",
+    tag = "user",
+    responses(
+        (status = 200, description = "ntfy and Gotify channels", body = NotificationChannelsResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn list_channels(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<NotificationChannelsResponse>, AppError> {
+    let channels = state
+        .storage
+        .list_notification_channels(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    Ok(Json(NotificationChannelsResponse {
+        channels: channels
+            .iter()
+            .map(NotificationChannel::to_response)
+            .collect(),
+    }))
+}
+
+/// POST /api/user/notification-channels
+/// Send reminders, shares and finished exports to an ntfy topic or a Gotify
+/// server
+#[utoipa::path(
+    post,
+    path = "/api/user/notification-channels",
+    tag = "user",
+    request_body = CreateNotificationChannelRequest,
+    responses(
+        (status = 201, description = "Channel added", body = NotificationChannelResponse),
+        (status = 400, description = "Invalid request or too many channels", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn create_channel(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Json(payload): Json<CreateNotificationChannelRequest>,
+) -> Result<(StatusCode, Json<NotificationChannelResponse>), AppError> {
+    if payload.kind != NOTIFICATION_CHANNEL_NTFY && payload.kind != NOTIFICATION_CHANNEL_GOTIFY {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Kind must be ntfy or gotify",
+        ));
+    }
+    state
+        .outbound
+        .check(&payload.url)
+        .await
+        .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, e))?;
+    let token = payload.token.filter(|token| !token.trim().is_empty());
+    if payload.kind == NOTIFICATION_CHANNEL_GOTIFY && token.is_none() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Gotify channels need an application token",
+        ));
+    }
+
+    let existing = state
+        .storage
+        .list_notification_channels(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+    if existing.len() >= MAX_CHANNELS_PER_USER {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "At most {} notification channels can be added",
+                MAX_CHANNELS_PER_USER
+            ),
+        ));
+    }
+
+    let channel = NotificationChannel::new(user_id, payload.kind, payload.url, token);
+    state
+        .storage
+        .create_notification_channel(&channel)
+        .await
+        .map_err(|e| storage_error(e, "Failed to add notification channel"))?;
+
+    Ok((StatusCode::CREATED, Json(channel.to_response())))
+}
+
+/// DELETE /api/user/notification-channels/:id
+/// Stop sending notifications to a channel
+#[utoipa::path(
+    delete,
+    path = "/api/user/notification-channels/{id}",
+    tag = "user",
+    params(("id" = String, Path, description = "Channel ID")),
+    responses(
+        (status = 200, description = "Channel removed", body = SuccessResponse),
+        (status = 404, description = "Channel not found", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn delete_channel(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Path(channel_id): Path<String>,
+) -> Result<Json<SuccessResponse>, AppError> {
+    state
+        .storage
+        .delete_notification_channel(&channel_id, &user_id)
+        .await
+        .map_err(|e| match e {
+            DbError::NotificationChannelNotFound => {
+                AppError::new(StatusCode::NOT_FOUND, "Notification channel not found")
+            }
+            _ => storage_error(e, "Failed to remove notification channel"),
+        })?;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+/// POST /api/user/notification-channels/:id/test
+/// Send a test notification, reporting the channel's error if it fails
+#[utoipa::path(
+    post,
+    path = "/api/user/notification-channels/{id}/test",
+    tag = "user",
+    params(("id" = String, Path, description = "Channel ID")),
+    responses(
+        (status = 200, description = "Test notification sent", body = SuccessResponse),
+        (status = 404, description = "Channel not found", body = ErrorResponse),
+        (status = 502, description = "The channel's server rejected the notification", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn test_channel(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Path(channel_id): Path<String>,
+) -> Result<Json<SuccessResponse>, AppError> {
+    let channel = state
+        .storage
+        .get_notification_channel(&channel_id, &user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "Notification channel not found"))?;
+
+    let notifier = notifier_for(&channel).ok_or_else(|| {
+        AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Notification channel is not usable",
+        )
+    })?;
+    state
+        .outbound
+        .check(&channel.url)
+        .await
+        .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, e))?;
+    let notification = Notification {
+        title: "Test notification".to_string(),
+        body: "Notifications from your journal will show up here.".to_string(),
+        url: None,
+    };
+    notifier
+        .send(&state.notifiers.client, &notification)
+        .await
+        .map_err(|e| AppError::new(StatusCode::BAD_GATEWAY, e))?;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{storage::MemoryStorage, testing::auth_user};
+    use axum::http::HeaderMap;
+    use std::sync::{Arc, Mutex};
+
+    type Received = Arc<Mutex<Vec<(String, HeaderMap, String)>>>;
+
+    /// Minimal ntfy and Gotify server recording each request's path,
+    /// headers and body
+    async fn spawn_notification_server() -> (String, Received) {
+        let received = Received::default();
+        let recorded = received.clone();
+        let app = axum::Router::new().fallback(
+            move |uri: axum::http::Uri, headers: HeaderMap, body: String| {
+                let received = recorded.clone();
+                async move {
+                    let path = uri.path().to_string();
+                    received.lock().unwrap().push((path, headers, body));
+                    StatusCode::OK
+                }
+            },
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}", addr), received)
+    }
+
+    #[tokio::test]
+    async fn test_notifications_reach_every_channel() {
+        let storage = MemoryStorage::new();
+        let user = crate::models::User::new(
+            "notified@example.com".to_string(),
+            "notified".to_string(),
+            "hash".to_string(),
+            "salt".to_string(),
+        );
+        storage.create_user(&user).await.unwrap();
+
+        let (base, received) = spawn_notification_server().await;
+        let ntfy = NotificationChannel::new(
+            user.id.clone(),
+            NOTIFICATION_CHANNEL_NTFY.to_string(),
+            format!("{}/journal", base),
+            None,
+        );
+        let gotify = NotificationChannel::new(
+            user.id.clone(),
+            NOTIFICATION_CHANNEL_GOTIFY.to_string(),
+            format!("{}/", base),
+            Some("app-token".to_string()),
+        );
+        storage.create_notification_channel(&ntfy).await.unwrap();
+        storage.create_notification_channel(&gotify).await.unwrap();
+
+        let notification = Notification {
+            title: "Your export is ready".to_string(),
+            body: "Download it within 7 days.".to_string(),
+            url: Some("https://journal.example.com".to_string()),
+        };
+        // Nothing is sent to loopback unless it is allowed
+        let refused = Notifiers::default()
+            .notify(&storage, &user.id, &notification)
+            .await
+            .unwrap_err();
+        assert!(refused.contains("URL must not point to a private or local address"));
+        assert!(received.lock().unwrap().is_empty());
+
+        Notifiers::new(OutboundPolicy::new(vec!["127.0.0.0/8".parse().unwrap()]))
+            .notify(&storage, &user.id, &notification)
+            .await
+            .unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        let (path, headers, body) = &received[0];
+        assert_eq!(path, "/journal");
+        assert_eq!(headers["title"], "Your export is ready");
+        assert_eq!(headers["click"], "https://journal.example.com");
+        assert_eq!(body, "Download it within 7 days.");
+
+        let (path, headers, body) = &received[1];
+        assert_eq!(path, "/message");
+        assert_eq!(headers["x-gotify-key"], "app-token");
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["title"], "Your export is ready");
+        assert_eq!(body["message"], "Download it within 7 days.");
+    }
+
+    #[test]
+    fn test_gotify_channels_need_a_token() {
+        let mut channel = NotificationChannel::new(
+            UserId::generate(),
+            NOTIFICATION_CHANNEL_GOTIFY.to_string(),
+            "https://gotify.example.com".to_string(),
+            None,
+        );
+        assert!(notifier_for(&channel).is_none());
+
+        channel.kind = NOTIFICATION_CHANNEL_NTFY.to_string();
+        assert!(notifier_for(&channel).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_create_channel_refuses_internal_urls() {
+        let state = Arc::new(crate::testing::test_app_state().await);
+        let user = crate::testing::create_test_user(&state, "lan@example.com", "lan").await;
+
+        let request = CreateNotificationChannelRequest {
+            kind: NOTIFICATION_CHANNEL_NTFY.to_string(),
+            url: "http://10.0.0.5/journal".to_string(),
+            token: None,
+        };
+        let status = create_channel(State(state), auth_user(&user.id), Json(request))
+            .await
+            .unwrap_err()
+            .status();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,270 @@
+use axum::Json;
+use utoipa::{
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use crate::{
+    admin, announcements, audit, avatars, digests, dormancy, export_jobs, exports, features,
+    handlers, imports, inbound_email, maintenance, matrix, models, notifiers, push, reload,
+    reminders, telegram, validation, webhooks,
+};
+
+/// Description of the HTTP API, served at `/api/openapi.json`
+///
+/// The WebSocket at `/api/ws` is left out, as OpenAPI can't describe it.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Dissipate API",
+        description = "Journal messages, sync, sharing, exports and imports"
+    ),
+    paths(
+        handlers::health,
+        handlers::healthz,
+        handlers::readyz,
+        handlers::login,
+        handlers::register,
+        features::get_config,
+        announcements::list_active,
+        handlers::get_messages_cached,
+        handlers::create_message,
+        handlers::update_message,
+        handlers::delete_message,
+        handlers::add_reaction,
+        handlers::remove_reaction,
+        push::vapid_public_key,
+        push::subscribe,
+        push::unsubscribe,
+        handlers::sync,
+        handlers::batch_sync,
+        handlers::get_templates,
+        handlers::create_template,
+        handlers::update_template,
+        handlers::delete_template,
+        handlers::list_journal_members,
+        handlers::invite_journal_member,
+        handlers::remove_journal_member,
+        handlers::list_shared_journals,
+        handlers::get_journal_messages,
+        handlers::create_journal_message,
+        handlers::update_email,
+        handlers::update_username,
+        handlers::update_display_name,
+        handlers::update_password,
+        handlers::get_usage,
+        avatars::upload_avatar,
+        avatars::get_avatar,
+        exports::export_json,
+        exports::export_markdown,
+        exports::export_html,
+        exports::export_ics,
+        exports::export_account,
+        export_jobs::create_export,
+        export_jobs::get_export,
+        export_jobs::get_export_progress,
+        export_jobs::download_export,
+        export_jobs::download_export_by_token,
+        export_jobs::list_schedules,
+        export_jobs::create_schedule,
+        export_jobs::delete_schedule,
+        export_jobs::get_webdav_destination,
+        export_jobs::set_webdav_destination,
+        export_jobs::delete_webdav_destination,
+        webhooks::list_webhooks,
+        webhooks::create_webhook,
+        webhooks::delete_webhook,
+        webhooks::list_deliveries,
+        announcements::list_for_user,
+        announcements::dismiss,
+        digests::get_subscription,
+        digests::set_subscription,
+        digests::delete_subscription,
+        reminders::get_reminder,
+        reminders::set_reminder,
+        reminders::delete_reminder,
+        notifiers::list_channels,
+        notifiers::create_channel,
+        notifiers::delete_channel,
+        notifiers::test_channel,
+        telegram::get_link,
+        telegram::create_link_code,
+        telegram::unlink,
+        inbound_email::receive_mailgun,
+        inbound_email::get_address,
+        inbound_email::set_address,
+        inbound_email::rotate_address,
+        inbound_email::delete_address,
+        matrix::get_account,
+        matrix::set_account,
+        matrix::delete_account,
+        dormancy::get_policy,
+        dormancy::set_policy,
+        dormancy::delete_policy,
+        imports::import_dayone,
+        imports::import_journey,
+        imports::import_markdown,
+        maintenance::checkpoint,
+        maintenance::wal_status,
+        maintenance::integrity,
+        maintenance::stats,
+        audit::list_audit_events,
+        features::list_features,
+        features::set_feature,
+        features::reset_feature,
+        announcements::list_all,
+        announcements::create,
+        announcements::update,
+        announcements::delete,
+        maintenance::get_maintenance,
+        maintenance::enable_maintenance,
+        maintenance::disable_maintenance,
+        reload::reload_config,
+        admin::list_users,
+        admin::create_user,
+        admin::delete_user,
+        admin::reset_password,
+        admin::set_role,
+        admin::set_disabled,
+    ),
+    components(schemas(
+        handlers::ErrorResponse,
+        validation::FieldError,
+        models::UserResponse,
+        models::MessageResponse,
+        models::ReactionCount,
+        models::TemplateResponse,
+        models::JournalMemberResponse,
+        models::SharedJournalResponse,
+        models::LoginRequest,
+        models::RegisterRequest,
+        models::CreateMessageRequest,
+        models::UpdateMessageRequest,
+        models::UpdateEmailRequest,
+        models::UpdateUsernameRequest,
+        models::UpdateDisplayNameRequest,
+        models::UpdatePasswordRequest,
+        models::CreateTemplateRequest,
+        models::UpdateTemplateRequest,
+        models::InviteMemberRequest,
+        models::ReactionRequest,
+        models::PushSubscriptionRequest,
+        models::PushSubscriptionKeys,
+        models::PushUnsubscribeRequest,
+        models::BatchSyncRequest,
+        models::SyncCreate,
+        models::SyncUpdate,
+        models::CreateExportRequest,
+        models::CreateExportScheduleRequest,
+        models::SetWebDavDestinationRequest,
+        models::SetDigestSubscriptionRequest,
+        models::SetReminderRequest,
+        models::CreateNotificationChannelRequest,
+        models::SetInboundEmailRequest,
+        models::SetMatrixAccountRequest,
+        models::SetDormancyPolicyRequest,
+        models::CreateWebhookRequest,
+        models::AnnouncementRequest,
+        models::HealthResponse,
+        models::ReadinessResponse,
+        models::LoginResponse,
+        models::MessagesResponse,
+        models::TemplatesResponse,
+        models::JournalMembersResponse,
+        models::SharedJournalsResponse,
+        models::SyncResponse,
+        models::UsageResponse,
+        models::CheckpointResponse,
+        models::WalStatusResponse,
+        models::IntegrityResponse,
+        models::StatsResponse,
+        models::OptimizeResponse,
+        models::AuditEventResponse,
+        models::AuditEventsResponse,
+        models::MaintenanceRequest,
+        models::MaintenanceResponse,
+        models::AdminUserResponse,
+        models::AdminUsersResponse,
+        models::AdminCreateUserRequest,
+        models::AdminResetPasswordRequest,
+        models::AdminSetRoleRequest,
+        models::AdminSetDisabledRequest,
+        models::ClientConfigResponse,
+        models::FeaturesResponse,
+        models::FeatureFlagResponse,
+        models::FeatureFlagsResponse,
+        models::SetFeatureFlagRequest,
+        models::ExportJobResponse,
+        models::ExportProgressResponse,
+        models::ExportScheduleResponse,
+        models::ExportSchedulesResponse,
+        models::WebDavDestinationResponse,
+        models::DigestSubscriptionResponse,
+        models::ReminderResponse,
+        models::NotificationChannelResponse,
+        models::NotificationChannelsResponse,
+        models::TelegramLinkResponse,
+        models::TelegramLinkCodeResponse,
+        models::InboundEmailResponse,
+        models::MatrixAccountResponse,
+        models::DormancyPolicyResponse,
+        models::WebhookResponse,
+        models::WebhooksResponse,
+        models::WebhookDeliveryResponse,
+        models::WebhookDeliveriesResponse,
+        models::AnnouncementResponse,
+        models::AnnouncementsResponse,
+        models::ImportResponse,
+        models::VapidKeyResponse,
+        models::SuccessResponse,
+        imports::DayOneExport,
+        imports::DayOneEntry,
+        imports::JourneyEntry,
+    )),
+    modifiers(&SecuritySchemes),
+    tags(
+        (name = "system", description = "Server status"),
+        (name = "auth", description = "Logging in"),
+        (name = "messages", description = "Journal messages and reactions"),
+        (name = "sync", description = "Offline sync"),
+        (name = "push", description = "Web Push notifications"),
+        (name = "templates", description = "Message templates"),
+        (name = "journals", description = "Shared journals"),
+        (name = "user", description = "Account settings"),
+        (name = "exports", description = "Exports, scheduled exports and destinations"),
+        (name = "imports", description = "Imports from other journaling apps"),
+        (name = "webhooks", description = "Signed HTTP callbacks on message changes"),
+        (name = "announcements", description = "Notices from the operators, such as maintenance windows"),
+        (name = "admin", description = "Operator maintenance and user management, authenticated with ADMIN_TOKEN or an admin user's JWT"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Declares the two bearer schemes: user JWTs from `/api/login`, and the
+/// operator's `ADMIN_TOKEN`
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer_auth",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "admin_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+/// GET /api/openapi.json
+/// Serve the OpenAPI description of the API
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
@@ -0,0 +1,140 @@
+//! Checks on URLs users give the server to send requests to
+//!
+//! Webhooks, notifications, WebDAV uploads and Matrix requests are sent from
+//! inside the server's network, and what comes back is shown to the user.
+//! [`OutboundPolicy::check`] resolves the host and refuses loopback, private,
+//! link-local and unspecified addresses, so these URLs can't reach services
+//! only meant to be reachable from inside, such as a cloud provider's
+//! metadata endpoint at 169.254.169.254.
+
+use std::{
+    net::{IpAddr, Ipv6Addr},
+    sync::Arc,
+};
+
+use ipnet::IpNet;
+use reqwest::Url;
+
+use crate::config::OutboundConfig;
+
+/// Private networks that user-supplied URLs may point to anyway
+///
+/// Without any, only public addresses are allowed.
+#[derive(Debug, Clone, Default)]
+pub struct OutboundPolicy(Arc<Vec<IpNet>>);
+
+impl OutboundPolicy {
+    pub fn new(allowed: Vec<IpNet>) -> Self {
+        Self(Arc::new(allowed))
+    }
+
+    pub fn from_config(config: &OutboundConfig) -> Self {
+        Self::new(config.allowed_networks.clone())
+    }
+
+    fn allows(&self, ip: &IpAddr) -> bool {
+        !is_internal(ip) || self.0.iter().any(|network| network.contains(ip))
+    }
+
+    /// Check that `url` is an http or https URL whose host only resolves to
+    /// addresses requests may be sent to
+    ///
+    /// Called when a URL is saved and again before each request, since what
+    /// a host name resolves to can change in between.
+    pub async fn check(&self, url: &str) -> Result<(), String> {
+        let url = Url::parse(url)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .ok_or_else(|| "URL must be an http or https URL".to_string())?;
+        let host = url
+            .host_str()
+            .ok_or_else(|| "URL must have a host".to_string())?;
+        // IPv6 hosts keep their brackets in URLs
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let port = url.port_or_known_default().unwrap_or(80);
+
+        let addrs = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|_| format!("Could not resolve {}", host))?;
+        for addr in addrs {
+            if !self.allows(&addr.ip()) {
+                return Err("URL must not point to a private or local address".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether `ip` is a loopback, private, link-local or unspecified address
+pub fn is_internal(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_internal(&IpAddr::V4(ip)),
+            None => {
+                ip.is_loopback() || ip.is_unspecified() || is_unique_local(ip) || is_link_local(ip)
+            }
+        },
+    }
+}
+
+/// `fc00::/7`, IPv6's private networks
+fn is_unique_local(ip: &Ipv6Addr) -> bool {
+    ip.segments()[0] & 0xfe00 == 0xfc00
+}
+
+/// `fe80::/10`
+fn is_link_local(ip: &Ipv6Addr) -> bool {
+    ip.segments()[0] & 0xffc0 == 0xfe80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rejects_internal_addresses() {
+        let policy = OutboundPolicy::default();
+        for url in [
+            "http://127.0.0.1:8080/hook",
+            "http://localhost/hook",
+            "http://10.1.2.3/",
+            "http://192.168.0.10/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://0.0.0.0/",
+            "http://2130706433/",
+            "http://[::1]/",
+            "http://[fd00::1]/",
+            "http://[fe80::1]/",
+            "http://[::ffff:10.0.0.1]/",
+        ] {
+            assert_eq!(
+                policy.check(url).await.unwrap_err(),
+                "URL must not point to a private or local address",
+                "{}",
+                url
+            );
+        }
+        assert_eq!(
+            policy.check("ftp://203.0.113.10/").await.unwrap_err(),
+            "URL must be an http or https URL"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_allows_public_and_allowed_addresses() {
+        let policy = OutboundPolicy::default();
+        assert!(policy.check("https://203.0.113.10/hook").await.is_ok());
+        assert!(policy.check("http://[2001:db8::1]:8080/").await.is_ok());
+
+        let policy = OutboundPolicy::new(vec!["192.168.1.0/24".parse().unwrap()]);
+        assert!(policy.check("http://192.168.1.20/").await.is_ok());
+        assert!(policy.check("http://192.168.2.20/").await.is_err());
+    }
+}
@@ -0,0 +1,248 @@
+//! Paging and sorting for listing endpoints
+//!
+//! [`Pagination`] reads `limit`, `offset` or `cursor`, `sort` and `order`
+//! from the query string and checks them against the endpoint's
+//! [`PageSpec`], so handlers only see values they can use. Bad values are
+//! rejected like a request body that breaks its rules, with each parameter in
+//! `fields`.
+
+use std::{fmt, marker::PhantomData};
+
+use axum::{
+    extract::{FromRequestParts, Query},
+    http::{request::Parts, StatusCode},
+};
+
+use crate::{
+    db::SortedPage,
+    error::AppError,
+    models::PageQuery,
+    validation::{Rule, ValidationErrors},
+};
+
+/// Direction of a sorted list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        }
+    }
+}
+
+/// How one endpoint's list can be paged and sorted
+pub trait PageSpec {
+    /// Page size when no `limit` is given; `None` lists everything
+    const DEFAULT_LIMIT: Option<u32>;
+    /// Largest `limit` accepted
+    const MAX_LIMIT: u32;
+    /// Fields `sort` accepts, the first being the default; empty when the
+    /// list has a fixed order
+    const SORTS: &'static [&'static str];
+    /// Direction when no `order` is given
+    const DEFAULT_ORDER: SortOrder;
+    /// Whether pages follow on from a `cursor` rather than an `offset`
+    const CURSOR: bool;
+}
+
+/// Checked paging and sorting parameters for the list described by `S`
+pub struct Pagination<S> {
+    /// Most items to return; `None` for all of them
+    pub limit: Option<u32>,
+    /// Items to skip, always `0` for lists paged by cursor
+    pub offset: u32,
+    /// Where the previous page ended, for lists paged by cursor
+    pub cursor: Option<String>,
+    /// One of `S::SORTS`, or empty for lists with a fixed order
+    pub sort: &'static str,
+    pub order: SortOrder,
+    spec: PhantomData<fn() -> S>,
+}
+
+impl<S: PageSpec> Pagination<S> {
+    /// Check `query` against `S`, filling in its defaults
+    pub fn from_query(query: PageQuery) -> Result<Self, ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+
+        if let Some(limit) = query.limit {
+            if limit == 0 || limit > S::MAX_LIMIT {
+                errors.reject(
+                    "limit",
+                    "out_of_range",
+                    format!("limit must be between 1 and {}", S::MAX_LIMIT),
+                );
+            }
+        }
+        if S::CURSOR && query.offset.is_some() {
+            errors.reject("offset", "not_supported", "This list is paged by cursor");
+        }
+        if !S::CURSOR && query.cursor.is_some() {
+            errors.reject("cursor", "not_supported", "This list is paged by offset");
+        }
+        if S::SORTS.is_empty() {
+            for (field, value) in [("sort", &query.sort), ("order", &query.order)] {
+                if value.is_some() {
+                    errors.reject(field, "not_supported", "This list has a fixed order");
+                }
+            }
+        } else {
+            let sort = query.sort.as_deref();
+            errors.check_optional("sort", "sort", sort, &[Rule::OneOf(S::SORTS)]);
+            let order = query.order.as_deref();
+            errors.check_optional("order", "order", order, &[Rule::OneOf(&["asc", "desc"])]);
+        }
+        errors.into_result()?;
+
+        let sort = S::SORTS
+            .iter()
+            .copied()
+            .find(|sort| query.sort.as_deref() == Some(*sort));
+        let order = match query.order.as_deref() {
+            Some("asc") => SortOrder::Asc,
+            Some("desc") => SortOrder::Desc,
+            _ => S::DEFAULT_ORDER,
+        };
+        Ok(Self {
+            limit: query.limit.or(S::DEFAULT_LIMIT),
+            offset: query.offset.unwrap_or(0),
+            cursor: query.cursor,
+            sort: sort.or(S::SORTS.first().copied()).unwrap_or_default(),
+            order,
+            spec: PhantomData,
+        })
+    }
+
+    /// The page to ask storage for, so only its rows are loaded
+    pub fn sorted_page(&self) -> SortedPage<'static> {
+        SortedPage {
+            sort: self.sort,
+            descending: self.order == SortOrder::Desc,
+            limit: self.limit.map(i64::from),
+            offset: i64::from(self.offset),
+        }
+    }
+}
+
+/// The first page, in the default order
+impl<S: PageSpec> Default for Pagination<S> {
+    fn default() -> Self {
+        Self {
+            limit: S::DEFAULT_LIMIT,
+            offset: 0,
+            cursor: None,
+            sort: S::SORTS.first().copied().unwrap_or_default(),
+            order: S::DEFAULT_ORDER,
+            spec: PhantomData,
+        }
+    }
+}
+
+/// The parameters in a stable form, for telling pages apart in ETags
+impl<S> fmt::Display for Pagination<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.limit {
+            Some(limit) => write!(f, "{}", limit)?,
+            None => f.write_str("all")?,
+        }
+        write!(
+            f,
+            ".{}.{}.{}.{}",
+            self.offset,
+            self.cursor.as_deref().unwrap_or(""),
+            self.sort,
+            self.order.as_str()
+        )
+    }
+}
+
+#[axum::async_trait]
+impl<S, St> FromRequestParts<St> for Pagination<S>
+where
+    S: PageSpec,
+    St: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &St) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<PageQuery>::try_from_uri(&parts.uri)
+            .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, e.body_text()))?;
+        Ok(Self::from_query(query)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Sorted;
+
+    impl PageSpec for Sorted {
+        const DEFAULT_LIMIT: Option<u32> = None;
+        const MAX_LIMIT: u32 = 10;
+        const SORTS: &'static [&'static str] = &["created_at", "name"];
+        const DEFAULT_ORDER: SortOrder = SortOrder::Desc;
+        const CURSOR: bool = false;
+    }
+
+    struct Fixed;
+
+    impl PageSpec for Fixed {
+        const DEFAULT_LIMIT: Option<u32> = Some(5);
+        const MAX_LIMIT: u32 = 10;
+        const SORTS: &'static [&'static str] = &[];
+        const DEFAULT_ORDER: SortOrder = SortOrder::Desc;
+        const CURSOR: bool = true;
+    }
+
+    fn query(pairs: &str) -> PageQuery {
+        let uri = format!("/items?{}", pairs).parse().unwrap();
+        Query::try_from_uri(&uri).unwrap().0
+    }
+
+    #[test]
+    fn test_defaults() {
+        let pagination = Pagination::<Sorted>::from_query(query("")).unwrap();
+        assert_eq!(pagination.limit, None);
+        assert_eq!(pagination.sort, "created_at");
+        assert_eq!(pagination.order, SortOrder::Desc);
+
+        let pagination = Pagination::<Fixed>::from_query(query("")).unwrap();
+        assert_eq!(pagination.limit, Some(5));
+        assert_eq!(pagination.sort, "");
+    }
+
+    #[test]
+    fn test_page() {
+        let pagination =
+            Pagination::<Sorted>::from_query(query("limit=2&offset=1&sort=name&order=asc"))
+                .unwrap();
+        assert_eq!(pagination.sort, "name");
+        assert_eq!(pagination.order, SortOrder::Asc);
+        let page = pagination.sorted_page();
+        assert_eq!((page.sort, page.descending), ("name", false));
+        assert_eq!((page.limit, page.offset), (Some(2), 1));
+    }
+
+    #[test]
+    fn test_rejects_each_bad_parameter() {
+        let errors = Pagination::<Sorted>::from_query(query("limit=11&sort=size&cursor=x"))
+            .err()
+            .unwrap();
+        let fields: Vec<&str> = errors.0.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["limit", "cursor", "sort"]);
+        assert_eq!(errors.0[0].message, "limit must be between 1 and 10");
+        assert_eq!(errors.0[2].message, "sort must be 'created_at' or 'name'");
+
+        let errors = Pagination::<Fixed>::from_query(query("offset=5&order=asc"))
+            .err()
+            .unwrap();
+        let fields: Vec<&str> = errors.0.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["offset", "order"]);
+    }
+}
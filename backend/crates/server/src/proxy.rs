@@ -0,0 +1,105 @@
+use std::{net::IpAddr, sync::Arc};
+
+use axum::http::HeaderMap;
+use ipnet::IpNet;
+
+/// Address of the client a request came from, after looking through any
+/// trusted proxies; set on every request by
+/// `middleware::client_ip_middleware`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+/// Reverse proxies whose `X-Forwarded-For` entries are believed
+///
+/// Without any, the peer address is the client, so clients can't spoof
+/// their address with the header.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(Arc<Vec<IpNet>>);
+
+impl TrustedProxies {
+    pub fn new(networks: Vec<IpNet>) -> Self {
+        Self(Arc::new(networks))
+    }
+
+    fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.iter().any(|network| network.contains(ip))
+    }
+
+    /// The client behind `peer`
+    ///
+    /// Walks `X-Forwarded-For` from the right, since each proxy appends the
+    /// address it received the request from, and stops at the first address
+    /// that isn't a trusted proxy. Anything left of that was sent by the
+    /// client and can't be believed.
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let mut client = peer;
+        if !self.contains(&client) {
+            return client;
+        }
+
+        let hops = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect::<Vec<_>>();
+        for hop in hops.into_iter().rev() {
+            let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+                break;
+            };
+            client = ip;
+            if !self.contains(&client) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn forwarded(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_untrusted_peer_is_the_client() {
+        let proxies = TrustedProxies::default();
+
+        let client = proxies.client_ip(ip("203.0.113.7"), &forwarded("198.51.100.1"));
+
+        assert_eq!(client, ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn test_stops_at_first_untrusted_hop() {
+        let proxies = TrustedProxies::new(vec![
+            "10.0.0.0/8".parse().unwrap(),
+            "192.0.2.10/32".parse().unwrap(),
+        ]);
+
+        // A spoofed entry, the real client, then a second trusted proxy
+        let headers = forwarded("1.2.3.4, 203.0.113.7, 192.0.2.10");
+        let client = proxies.client_ip(ip("10.0.0.2"), &headers);
+
+        assert_eq!(client, ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn test_unparseable_hop_ends_the_walk() {
+        let proxies = TrustedProxies::new(vec!["10.0.0.0/8".parse().unwrap()]);
+
+        let client = proxies.client_ip(ip("10.0.0.2"), &forwarded("unknown, 10.0.0.3"));
+
+        assert_eq!(client, ip("10.0.0.3"));
+    }
+}
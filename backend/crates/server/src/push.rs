@@ -0,0 +1,342 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+use web_push::{
+    ContentEncoding, IsahcWebPushClient, SubscriptionInfo, VapidSignatureBuilder, WebPushClient,
+    WebPushError, WebPushMessageBuilder, URL_SAFE_NO_PAD,
+};
+
+use crate::{
+    error::AppError,
+    handlers::{storage_error, SharedState},
+    ids::UserId,
+    models::{
+        PushSubscription, PushSubscriptionRequest, PushUnsubscribeRequest, SuccessResponse,
+        VapidKeyResponse,
+    },
+    outbound::OutboundPolicy,
+    storage::Storage,
+    AuthUser,
+};
+
+/// Notification shown by the service worker
+#[derive(Debug, Clone, Serialize)]
+pub struct PushPayload {
+    pub title: String,
+    pub body: String,
+    /// Page to open when the notification is clicked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// Sends VAPID-signed Web Push messages to a user's registered browsers
+pub struct PushNotifier {
+    public_key: String,
+    private_key: String,
+    client: IsahcWebPushClient,
+    outbound: OutboundPolicy,
+}
+
+impl PushNotifier {
+    /// Create a notifier from URL-safe base64 VAPID keys
+    pub fn new(
+        public_key: String,
+        private_key: String,
+        outbound: OutboundPolicy,
+    ) -> Result<Self, WebPushError> {
+        // Fail at startup rather than on the first notification
+        VapidSignatureBuilder::from_base64_no_sub(&private_key, URL_SAFE_NO_PAD)?;
+
+        Ok(Self {
+            public_key,
+            private_key,
+            client: IsahcWebPushClient::new()?,
+            outbound,
+        })
+    }
+
+    /// Send a notification to every subscription of a user
+    ///
+    /// Subscriptions the push service reports as gone are removed. Other
+    /// failures are logged, since notifications are best-effort.
+    pub async fn notify(&self, storage: &dyn Storage, user_id: &UserId, payload: &PushPayload) {
+        let subscriptions = match storage.get_push_subscriptions(user_id).await {
+            Ok(subscriptions) => subscriptions,
+            Err(e) => {
+                tracing::warn!("Failed to load push subscriptions: {}", e);
+                return;
+            }
+        };
+        let Ok(body) = serde_json::to_vec(payload) else {
+            return;
+        };
+
+        for subscription in subscriptions {
+            // Checked again, since the host may resolve somewhere else by now
+            if let Err(e) = self.outbound.check(&subscription.endpoint).await {
+                tracing::warn!("Not sending push notification: {}", e);
+                continue;
+            }
+            match self.send(&subscription, &body).await {
+                Ok(()) => {}
+                Err(WebPushError::EndpointNotValid | WebPushError::EndpointNotFound) => {
+                    let _ = storage
+                        .delete_push_subscription(&subscription.user_id, &subscription.endpoint)
+                        .await;
+                }
+                Err(e) => tracing::warn!("Failed to send push notification: {}", e),
+            }
+        }
+    }
+
+    async fn send(&self, subscription: &PushSubscription, body: &[u8]) -> Result<(), WebPushError> {
+        let info = SubscriptionInfo::new(
+            &subscription.endpoint,
+            &subscription.p256dh,
+            &subscription.auth,
+        );
+        let signature =
+            VapidSignatureBuilder::from_base64(&self.private_key, URL_SAFE_NO_PAD, &info)?
+                .build()?;
+
+        let mut builder = WebPushMessageBuilder::new(&info);
+        builder.set_payload(ContentEncoding::Aes128Gcm, body);
+        builder.set_vapid_signature(signature);
+
+        self.client.send(builder.build()?).await
+    }
+}
+
+/// Notify a user in the background, if push is configured
+pub fn notify_user(state: &SharedState, user_id: &UserId, payload: PushPayload) {
+    let Some(notifier) = state.push.clone() else {
+        return;
+    };
+    let storage = state.storage.clone();
+    let user_id = user_id.clone();
+
+    tokio::spawn(async move {
+        notifier.notify(storage.as_ref(), &user_id, &payload).await;
+    });
+}
+
+// ============ Handlers ============
+
+/// GET /api/push/vapid-public-key
+/// Get the application server key used to subscribe
+#[utoipa::path(
+    get,
+    path = "/api/push/vapid-public-key",
+    tag = "push",
+    responses(
+        (status = 200, description = "Application server key", body = VapidKeyResponse),
+        (status = 404, description = "Push notifications are not configured", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn vapid_public_key(
+    State(state): State<SharedState>,
+) -> Result<Json<VapidKeyResponse>, AppError> {
+    let notifier = state.push.as_ref().ok_or_else(|| {
+        AppError::new(
+            StatusCode::NOT_FOUND,
+            "Push notifications are not configured",
+        )
+    })?;
+
+    Ok(Json(VapidKeyResponse {
+        public_key: notifier.public_key.clone(),
+    }))
+}
+
+/// POST /api/push/subscriptions
+/// Register a browser push subscription for the authenticated user
+#[utoipa::path(
+    post,
+    path = "/api/push/subscriptions",
+    tag = "push",
+    request_body = PushSubscriptionRequest,
+    responses(
+        (status = 201, description = "Subscription saved", body = SuccessResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn subscribe(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Json(payload): Json<PushSubscriptionRequest>,
+) -> Result<(StatusCode, Json<SuccessResponse>), AppError> {
+    if !payload.endpoint.starts_with("https://") {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Endpoint must be an https URL",
+        ));
+    }
+    state
+        .outbound
+        .check(&payload.endpoint)
+        .await
+        .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, e))?;
+    if payload.keys.p256dh.is_empty() || payload.keys.auth.is_empty() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Subscription keys are required",
+        ));
+    }
+
+    state
+        .storage
+        .save_push_subscription(
+            &user_id,
+            &payload.endpoint,
+            &payload.keys.p256dh,
+            &payload.keys.auth,
+        )
+        .await
+        .map_err(|e| storage_error(e, "Failed to save subscription"))?;
+
+    Ok((StatusCode::CREATED, Json(SuccessResponse::new())))
+}
+
+/// DELETE /api/push/subscriptions
+/// Remove a browser push subscription
+#[utoipa::path(
+    delete,
+    path = "/api/push/subscriptions",
+    tag = "push",
+    request_body = PushUnsubscribeRequest,
+    responses(
+        (status = 200, description = "Subscription removed", body = SuccessResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn unsubscribe(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Json(payload): Json<PushUnsubscribeRequest>,
+) -> Result<Json<SuccessResponse>, AppError> {
+    state
+        .storage
+        .delete_push_subscription(&user_id, &payload.endpoint)
+        .await
+        .map_err(|e| storage_error(e, "Failed to remove subscription"))?;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{PushSubscriptionKeys, User},
+        testing::{auth_user, test_app_state},
+        utils::hash_password,
+    };
+    use std::sync::Arc;
+
+    async fn setup_test_state() -> SharedState {
+        Arc::new(test_app_state().await)
+    }
+
+    async fn create_test_user(state: &SharedState, email: &str) -> User {
+        let (hash, salt) = hash_password("password123").unwrap();
+        let user = User::new(email.to_string(), "testuser".to_string(), hash, salt);
+        state.storage.create_user(&user).await.unwrap();
+        user
+    }
+
+    fn subscription(endpoint: &str) -> PushSubscriptionRequest {
+        PushSubscriptionRequest {
+            endpoint: endpoint.to_string(),
+            keys: PushSubscriptionKeys {
+                p256dh: "BNcRdreALRFXTkOOUHK1EtK2wtaz5Ry4YfYCA_0QTpQtUbVlUls0VJXg7A8u-Ts1XbjhazAkj7I99e8QcYP7DkM".to_string(),
+                auth: "tBHItJI5svbpez7KI4CCXg".to_string(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_and_unsubscribe() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "push@example.com").await;
+
+        let (status, _) = subscribe(
+            State(state.clone()),
+            auth_user(&user.id),
+            Json(subscription("https://127.0.0.1/push/abc")),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(
+            state
+                .storage
+                .get_push_subscriptions(&user.id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let _ = unsubscribe(
+            State(state.clone()),
+            auth_user(&user.id),
+            Json(PushUnsubscribeRequest {
+                endpoint: "https://127.0.0.1/push/abc".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+        assert!(state
+            .storage
+            .get_push_subscriptions(&user.id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_rejects_insecure_endpoint() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "insecure@example.com").await;
+
+        let result = subscribe(
+            State(state),
+            auth_user(&user.id),
+            Json(subscription("http://push.example.com/abc")),
+        )
+        .await;
+
+        assert_eq!(result.unwrap_err().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_rejects_private_endpoint() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "private@example.com").await;
+
+        let result = subscribe(
+            State(state.clone()),
+            auth_user(&user.id),
+            Json(subscription("https://169.254.169.254/latest/meta-data/")),
+        )
+        .await;
+
+        assert_eq!(result.unwrap_err().status(), StatusCode::BAD_REQUEST);
+        assert!(state
+            .storage
+            .get_push_subscriptions(&user.id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_vapid_public_key_requires_configuration() {
+        let state = setup_test_state().await;
+
+        let result = vapid_public_key(State(state)).await;
+
+        assert_eq!(result.unwrap_err().status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use axum::http::Method;
+use moka::future::Cache;
+
+use crate::config::LimitsConfig;
+
+/// Buckets untouched for this long are dropped; they would be full again
+const BUCKET_IDLE: Duration = Duration::from_secs(600);
+const MAX_BUCKETS: u64 = 100_000;
+
+/// Which budget a request draws from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteClass {
+    /// Logging in and registering, limited per IP to slow down password
+    /// guessing and mass sign-ups
+    Auth,
+    Read,
+    Write,
+}
+
+impl RouteClass {
+    /// Classify a request; `None` for requests that are never limited
+    pub fn of(method: &Method, path: &str) -> Option<Self> {
+        match path {
+            // Health probes must keep answering under load
+            "/api/health" | "/healthz" | "/readyz" => None,
+            "/api/login" | "/api/register" => Some(Self::Auth),
+            _ if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) => {
+                Some(Self::Read)
+            }
+            _ => Some(Self::Write),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Auth => "auth",
+            Self::Read => "read",
+            Self::Write => "write",
+        }
+    }
+}
+
+/// Token buckets per user, or per client IP for anonymous requests
+///
+/// Each bucket holds a minute's worth of requests and refills continuously,
+/// so clients can burst up to their budget but not sustain more than it.
+/// Limits live in memory and reset on restart. Clones share their buckets
+/// and budgets, so `update` applies everywhere.
+#[derive(Clone)]
+pub struct RateLimits {
+    budgets: Arc<RwLock<Budgets>>,
+    buckets: Cache<String, Arc<Mutex<Bucket>>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Budgets {
+    auth_per_minute: Option<u32>,
+    read_per_minute: Option<u32>,
+    write_per_minute: Option<u32>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Default for RateLimits {
+    /// No limits
+    fn default() -> Self {
+        Self::new(None, None, None)
+    }
+}
+
+impl RateLimits {
+    pub fn new(
+        auth_per_minute: Option<u32>,
+        read_per_minute: Option<u32>,
+        write_per_minute: Option<u32>,
+    ) -> Self {
+        Self {
+            budgets: Arc::new(RwLock::new(Budgets {
+                auth_per_minute,
+                read_per_minute,
+                write_per_minute,
+            })),
+            buckets: Cache::builder()
+                .time_to_idle(BUCKET_IDLE)
+                .max_capacity(MAX_BUCKETS)
+                .build(),
+        }
+    }
+
+    /// Budgets from the `[limits]` settings
+    pub fn from_config(limits: &LimitsConfig) -> Self {
+        Self::new(
+            limits.auth_per_minute,
+            limits.read_per_minute,
+            limits.write_per_minute,
+        )
+    }
+
+    /// Switch to the budgets in `limits`, as on a config reload
+    ///
+    /// Clients keep their buckets; lowered budgets cap them at the next
+    /// request and raised ones refill at the new rate.
+    pub fn update(&self, limits: &LimitsConfig) {
+        *self.budgets.write().unwrap() = Budgets {
+            auth_per_minute: limits.auth_per_minute,
+            read_per_minute: limits.read_per_minute,
+            write_per_minute: limits.write_per_minute,
+        };
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        let budgets = self.budgets.read().unwrap();
+        budgets.auth_per_minute.is_some()
+            || budgets.read_per_minute.is_some()
+            || budgets.write_per_minute.is_some()
+    }
+
+    fn per_minute(&self, class: RouteClass) -> Option<u32> {
+        let budgets = self.budgets.read().unwrap();
+        match class {
+            RouteClass::Auth => budgets.auth_per_minute,
+            RouteClass::Read => budgets.read_per_minute,
+            RouteClass::Write => budgets.write_per_minute,
+        }
+    }
+
+    /// Take one request from `key`'s bucket for `class`
+    ///
+    /// Returns how long to wait when the bucket is empty.
+    pub async fn check(&self, class: RouteClass, key: &str) -> Result<(), Duration> {
+        let Some(per_minute) = self.per_minute(class) else {
+            return Ok(());
+        };
+        let capacity = f64::from(per_minute);
+
+        let bucket = self
+            .buckets
+            .get_with(format!("{}:{}", class.name(), key), async move {
+                Arc::new(Mutex::new(Bucket {
+                    tokens: capacity,
+                    updated: Instant::now(),
+                }))
+            })
+            .await;
+        let mut bucket = bucket.lock().unwrap();
+
+        let now = Instant::now();
+        let per_second = capacity / 60.0;
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_second))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route_classes() {
+        assert_eq!(RouteClass::of(&Method::GET, "/readyz"), None);
+        assert_eq!(
+            RouteClass::of(&Method::POST, "/api/login"),
+            Some(RouteClass::Auth)
+        );
+        assert_eq!(
+            RouteClass::of(&Method::POST, "/api/register"),
+            Some(RouteClass::Auth)
+        );
+        assert_eq!(
+            RouteClass::of(&Method::GET, "/api/messages"),
+            Some(RouteClass::Read)
+        );
+        assert_eq!(
+            RouteClass::of(&Method::POST, "/api/sync"),
+            Some(RouteClass::Write)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_buckets_are_per_key_and_class() {
+        let limits = RateLimits::new(None, Some(100), Some(2));
+
+        assert!(limits.check(RouteClass::Write, "user-1").await.is_ok());
+        assert!(limits.check(RouteClass::Write, "user-1").await.is_ok());
+        let wait = limits.check(RouteClass::Write, "user-1").await.unwrap_err();
+        // Two per minute refill one every 30 seconds
+        assert!(wait > Duration::from_secs(29) && wait <= Duration::from_secs(30));
+
+        // Other users and other budgets are untouched
+        assert!(limits.check(RouteClass::Write, "user-2").await.is_ok());
+        assert!(limits.check(RouteClass::Read, "user-1").await.is_ok());
+        // Unset budgets never limit
+        for _ in 0..10 {
+            assert!(limits.check(RouteClass::Auth, "user-1").await.is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_update_applies_to_clones() {
+        let limits = RateLimits::default();
+        let shared = limits.clone();
+        assert!(!shared.is_enabled());
+
+        limits.update(&LimitsConfig {
+            auth_per_minute: Some(1),
+            ..Default::default()
+        });
+
+        assert!(shared.is_enabled());
+        assert!(shared.check(RouteClass::Auth, "ip:1").await.is_ok());
+        assert!(shared.check(RouteClass::Auth, "ip:1").await.is_err());
+    }
+}
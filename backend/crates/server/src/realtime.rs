@@ -0,0 +1,198 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use axum::{
+    extract::{
+        ws::{Message as WsMessage, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::{
+    auth::extract_token_from_header, handlers::SharedState, ids::UserId, models::MessageResponse,
+};
+
+/// Number of events buffered per user before slow sockets start lagging
+const CHANNEL_CAPACITY: usize = 64;
+
+/// A change to one of a user's messages, pushed to their open sockets
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MessageEvent {
+    Created {
+        message: MessageResponse,
+    },
+    Updated {
+        message: MessageResponse,
+    },
+    Deleted {
+        id: String,
+    },
+    /// Many changes were applied at once (batch sync, import); clients
+    /// should pull from `/api/sync`
+    Resync {
+        seq: i64,
+    },
+}
+
+/// Per-user broadcast channels for real-time message events
+#[derive(Default)]
+pub struct EventHub {
+    channels: Mutex<HashMap<UserId, broadcast::Sender<MessageEvent>>>,
+}
+
+impl EventHub {
+    /// Subscribe to events for a user, creating their channel if needed
+    pub fn subscribe(&self, user_id: &UserId) -> broadcast::Receiver<MessageEvent> {
+        let mut channels = self.channels.lock().unwrap();
+        channels
+            .entry(user_id.clone())
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+
+    /// Send an event to every open socket of a user
+    ///
+    /// Channels without receivers are dropped so the registry only holds
+    /// users who are currently connected.
+    pub fn publish(&self, user_id: &UserId, event: MessageEvent) {
+        let mut channels = self.channels.lock().unwrap();
+        if let Some(sender) = channels.get(user_id) {
+            if sender.send(event).is_err() {
+                channels.remove(user_id);
+            }
+        }
+    }
+
+    /// Number of sockets currently open, across all users
+    pub fn connection_count(&self) -> usize {
+        let channels = self.channels.lock().unwrap();
+        channels
+            .values()
+            .map(|sender| sender.receiver_count())
+            .sum()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WsQuery {
+    /// Browsers cannot set headers on WebSocket requests, so the token may
+    /// also be passed as a query parameter
+    pub token: Option<String>,
+}
+
+/// GET /api/ws
+/// Upgrade to a WebSocket that streams message events for the user
+pub async fn ws_handler(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Query(query): Query<WsQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, StatusCode> {
+    let token = match query.token {
+        Some(token) => token,
+        None => {
+            let auth_header = headers
+                .get(header::AUTHORIZATION)
+                .and_then(|h| h.to_str().ok())
+                .ok_or(StatusCode::UNAUTHORIZED)?;
+            extract_token_from_header(auth_header)
+                .map_err(|_| StatusCode::UNAUTHORIZED)?
+                .to_string()
+        }
+    };
+
+    let claims = state
+        .auth
+        .validate_token(&token)
+        .await
+        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    let user_id: UserId = claims
+        .user_id
+        .parse()
+        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    let events = state.events.subscribe(&user_id);
+
+    Ok(ws.on_upgrade(move |socket| forward_events(socket, events)))
+}
+
+async fn forward_events(mut socket: WebSocket, mut events: broadcast::Receiver<MessageEvent>) {
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    // Missed events cannot be replayed; ask the client to resync
+                    Err(broadcast::error::RecvError::Lagged(_)) => MessageEvent::Resync { seq: 0 },
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                let Ok(text) = serde_json::to_string(&event) else {
+                    continue;
+                };
+                if socket.send(WsMessage::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => {
+                match incoming {
+                    Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                    // Pings are answered by axum; other client messages are ignored
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_publish_reaches_subscribers() {
+        let hub = EventHub::default();
+        let user_id = UserId::generate();
+        let mut first = hub.subscribe(&user_id);
+        let mut second = hub.subscribe(&user_id);
+        let mut other = hub.subscribe(&UserId::generate());
+        assert_eq!(hub.connection_count(), 3);
+
+        hub.publish(
+            &user_id,
+            MessageEvent::Deleted {
+                id: "msg-1".to_string(),
+            },
+        );
+
+        let expected = MessageEvent::Deleted {
+            id: "msg-1".to_string(),
+        };
+        assert_eq!(first.recv().await.unwrap(), expected);
+        assert_eq!(second.recv().await.unwrap(), expected);
+        assert!(other.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_publish_drops_abandoned_channels() {
+        let hub = EventHub::default();
+        let user_id = UserId::generate();
+        drop(hub.subscribe(&user_id));
+
+        hub.publish(&user_id, MessageEvent::Resync { seq: 1 });
+
+        assert!(hub.channels.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_event_serialization() {
+        let json = serde_json::to_value(MessageEvent::Deleted {
+            id: "msg-1".to_string(),
+        })
+        .unwrap();
+
+        assert_eq!(json, serde_json::json!({"type": "deleted", "id": "msg-1"}));
+    }
+}
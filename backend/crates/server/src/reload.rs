@@ -0,0 +1,151 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode};
+use axum_server::tls_rustls::RustlsConfig;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+use crate::{
+    config::Config,
+    error::AppError,
+    middleware::{CorsConfig, ReloadableCors},
+    ratelimit::RateLimits,
+    tls::TlsConfig,
+};
+
+/// Handle for swapping the log filter installed in `main`
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
+
+/// Reads the config afresh, as it was found at startup
+pub type ConfigLoader = Arc<dyn Fn() -> Result<Config, String> + Send + Sync>;
+
+/// Settings that can change without a restart, so in-flight requests and
+/// open WebSockets survive: CORS, rate limits, the log filter and the TLS
+/// certificate
+///
+/// Everything else in the config is only read at startup.
+#[derive(Clone)]
+pub struct Reloader {
+    pub load_config: ConfigLoader,
+    pub cors: ReloadableCors,
+    pub rate_limits: RateLimits,
+    pub log_filter: Option<LogFilterHandle>,
+    pub tls: Option<(TlsConfig, RustlsConfig)>,
+}
+
+impl Reloader {
+    /// Re-read the config file and environment and apply the reloadable
+    /// settings
+    ///
+    /// Everything is checked before anything is applied, so an invalid
+    /// config leaves the running settings untouched.
+    pub async fn reload(&self) -> Result<(), String> {
+        let config = (self.load_config)()?;
+        let cors = CorsConfig::from_settings(&config.cors)?;
+        let filter = EnvFilter::try_new(config.logging.filter())
+            .map_err(|e| format!("Invalid log filter: {}", e))?;
+
+        if let Some((tls, rustls)) = &self.tls {
+            rustls
+                .reload_from_pem_file(&tls.cert_path, &tls.key_path)
+                .await
+                .map_err(|e| format!("Failed to reload TLS certificate: {}", e))?;
+        }
+        if let Some(log_filter) = &self.log_filter {
+            log_filter
+                .reload(filter)
+                .map_err(|e| format!("Failed to reload log filter: {}", e))?;
+        }
+        self.cors.update(&cors);
+        self.rate_limits.update(&config.limits);
+
+        tracing::info!("Reloaded configuration");
+        Ok(())
+    }
+}
+
+/// Reload on every SIGHUP until the server stops
+///
+/// A reload that fails is logged and keeps the previous settings.
+#[cfg(unix)]
+pub fn spawn_reload_on_sighup(reloader: Reloader) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(e) => {
+                tracing::error!("Can't listen for SIGHUP, config reload disabled: {}", e);
+                return;
+            }
+        };
+
+        while hangups.recv().await.is_some() {
+            if let Err(e) = reloader.reload().await {
+                tracing::error!("Config reload failed: {}", e);
+            }
+        }
+    });
+}
+
+/// SIGHUP doesn't exist here; use `POST /api/admin/reload` instead
+#[cfg(not(unix))]
+pub fn spawn_reload_on_sighup(_reloader: Reloader) {}
+
+/// POST /api/admin/reload
+/// Apply changed CORS, rate limit, log filter and TLS settings, as SIGHUP does
+#[utoipa::path(
+    post,
+    path = "/api/admin/reload",
+    tag = "admin",
+    responses(
+        (status = 204, description = "Settings reloaded"),
+        (status = 400, description = "Invalid configuration; nothing was changed", body = ErrorResponse),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn reload_config(State(reloader): State<Reloader>) -> Result<StatusCode, AppError> {
+    reloader
+        .reload()
+        .await
+        .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, e))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ratelimit::RouteClass;
+
+    #[tokio::test]
+    async fn test_reload_applies_config_file() {
+        let path = std::env::temp_dir().join(format!("dissipate-{}.toml", uuid::Uuid::new_v4()));
+        let config_path = path.clone();
+        let reloader = Reloader {
+            load_config: Arc::new(move || Config::load(Some(&config_path), |_| None)),
+            cors: ReloadableCors::new(&CorsConfig::default()),
+            rate_limits: RateLimits::default(),
+            log_filter: None,
+            tls: None,
+        };
+
+        std::fs::write(&path, "[limits]\nauth_per_minute = 1\n").unwrap();
+        reloader.reload().await.unwrap();
+        assert!(reloader
+            .rate_limits
+            .check(RouteClass::Auth, "ip:1")
+            .await
+            .is_ok());
+        assert!(reloader
+            .rate_limits
+            .check(RouteClass::Auth, "ip:1")
+            .await
+            .is_err());
+
+        // Invalid settings change nothing
+        std::fs::write(&path, "[limits]\nauth_per_minute = 0\n").unwrap();
+        assert!(reloader.reload().await.is_err());
+        assert!(reloader.rate_limits.is_enabled());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
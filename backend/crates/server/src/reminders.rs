@@ -0,0 +1,499 @@
+use std::{collections::HashSet, time::Duration};
+
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::{NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    error::AppError,
+    handlers::{storage_error, AppState, SharedState},
+    models::{weekday_name, Reminder, ReminderResponse, SetReminderRequest, SuccessResponse},
+    notifiers::Notification,
+    push::PushPayload,
+    AuthUser,
+};
+
+/// How often the worker looks for reminders that are due
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Longest streak counted, so a reminder never reads a user's whole history
+const STREAK_LOOKBACK_DAYS: i64 = 366;
+
+/// Send reminders as they fall due, until shutdown
+pub fn spawn_worker(state: SharedState, shutdown: CancellationToken) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            send_due_reminders(&state).await;
+
+            tokio::select! {
+                _ = tokio::time::sleep(CHECK_INTERVAL) => {}
+                _ = shutdown.cancelled() => break,
+            }
+        }
+    })
+}
+
+/// Send every reminder that is due and move each on to its next run
+async fn send_due_reminders(state: &AppState) {
+    let now = Utc::now();
+    let reminders = match state.storage.get_due_reminders(now).await {
+        Ok(reminders) => reminders,
+        Err(e) => {
+            tracing::error!("Failed to fetch reminders: {}", e);
+            return;
+        }
+    };
+
+    for reminder in reminders {
+        // Runs missed while the server was down are skipped, not all sent
+        let Some(next_run_at) = reminder.run_after(now) else {
+            tracing::warn!(
+                "Reminder for user {} has invalid settings",
+                reminder.user_id
+            );
+            continue;
+        };
+        if let Err(e) = state
+            .storage
+            .set_reminder_next_run(&reminder.user_id, next_run_at)
+            .await
+        {
+            tracing::error!(
+                "Failed to schedule reminder for user {}: {}",
+                reminder.user_id,
+                e
+            );
+            continue;
+        }
+
+        if let Err(e) = send_reminder(state, &reminder).await {
+            tracing::error!("Failed to remind user {} to write: {}", reminder.user_id, e);
+        }
+    }
+}
+
+/// Nudge a user on every channel they chose, unless they already wrote
+/// today in their time zone
+async fn send_reminder(state: &AppState, reminder: &Reminder) -> Result<(), String> {
+    let Some(user) = state
+        .storage
+        .find_user_by_id(&reminder.user_id)
+        .await
+        .map_err(|e| format!("Failed to fetch user: {}", e))?
+    else {
+        return Ok(());
+    };
+    if user.disabled {
+        return Ok(());
+    }
+
+    let tz: Tz = reminder.timezone.parse().unwrap_or(Tz::UTC);
+    let now = Utc::now();
+    let today = now.with_timezone(&tz).date_naive();
+    let since = now - chrono::Duration::days(STREAK_LOOKBACK_DAYS + 1);
+    let days: HashSet<NaiveDate> = state
+        .storage
+        .get_message_times(&user.id, since)
+        .await
+        .map_err(|e| format!("Failed to fetch messages: {}", e))?
+        .into_iter()
+        .map(|created_at| created_at.with_timezone(&tz).date_naive())
+        .collect();
+    if days.contains(&today) {
+        return Ok(());
+    }
+
+    let (title, body) = reminder_text(streak_before(&days, today));
+    let mut errors = Vec::new();
+    if reminder.email {
+        if let Some(mailer) = &state.mailer {
+            let text = match mailer.public_url() {
+                Some(url) => format!("{}\n\n{}", body, url),
+                None => body.clone(),
+            };
+            if let Err(e) = mailer.send(&user.email, &title, text, None).await {
+                errors.push(e);
+            }
+        }
+    }
+    if reminder.push {
+        if let Some(notifier) = &state.push {
+            let payload = PushPayload {
+                title: title.clone(),
+                body: body.clone(),
+                url: None,
+            };
+            notifier
+                .notify(state.storage.as_ref(), &user.id, &payload)
+                .await;
+        }
+    }
+    if reminder.channels {
+        let notification = Notification {
+            title,
+            body,
+            url: None,
+        };
+        if let Err(e) = state
+            .notifiers
+            .notify(state.storage.as_ref(), &user.id, &notification)
+            .await
+        {
+            errors.push(e);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+/// Days in a row the user wrote, ending the day before `today`
+fn streak_before(days: &HashSet<NaiveDate>, today: NaiveDate) -> i64 {
+    let mut streak = 0;
+    let mut day = today.pred_opt();
+    while let Some(date) = day.filter(|date| days.contains(date)) {
+        streak += 1;
+        day = date.pred_opt();
+    }
+    streak
+}
+
+/// Title and body of a reminder, encouraging the user to keep a streak alive
+fn reminder_text(streak: i64) -> (String, String) {
+    match streak {
+        0 => (
+            "Time to write".to_string(),
+            "You haven't written anything today. A few lines are enough.".to_string(),
+        ),
+        1 => (
+            "Keep your streak going".to_string(),
+            "You wrote yesterday. Write today to make it two days in a row.".to_string(),
+        ),
+        streak => (
+            "Keep your streak going".to_string(),
+            format!(
+                "You've written {} days in a row. Write today to keep your streak alive.",
+                streak
+            ),
+        ),
+    }
+}
+
+/// GET /api/user/reminder
+/// Get when the authenticated user is reminded to write
+#[utoipa::path(
+    get,
+    path = "/api/user/reminder",
+    tag = "user",
+    responses(
+        (status = 200, description = "When the user is reminded to write", body = ReminderResponse),
+        (status = 404, description = "No reminder set up", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_reminder(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<ReminderResponse>, AppError> {
+    let reminder = state
+        .storage
+        .get_reminder(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "No reminder set up"))?;
+
+    Ok(Json(reminder.to_response()))
+}
+
+/// PUT /api/user/reminder
+/// Remind the user to write at a local time on the chosen days, unless they
+/// already have, replacing any existing reminder
+#[utoipa::path(
+    put,
+    path = "/api/user/reminder",
+    tag = "user",
+    request_body = SetReminderRequest,
+    responses(
+        (status = 200, description = "Reminder saved", body = ReminderResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn set_reminder(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Json(payload): Json<SetReminderRequest>,
+) -> Result<Json<ReminderResponse>, AppError> {
+    let bad_request = |message: String| AppError::new(StatusCode::BAD_REQUEST, message);
+
+    let time = NaiveTime::parse_from_str(&payload.time, "%H:%M")
+        .map_err(|_| bad_request("Time must be HH:MM".to_string()))?;
+
+    let mut weekdays = match &payload.days {
+        None => vec![
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+            Weekday::Sat,
+            Weekday::Sun,
+        ],
+        Some(days) => days
+            .iter()
+            .map(|day| {
+                day.parse::<Weekday>()
+                    .map_err(|_| bad_request(format!("Unknown weekday: {}", day)))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+    if weekdays.is_empty() {
+        return Err(bad_request("Choose at least one day".to_string()));
+    }
+    weekdays.sort_by_key(Weekday::num_days_from_monday);
+    weekdays.dedup();
+
+    let timezone = match payload.timezone.as_deref() {
+        None => Tz::UTC,
+        Some(timezone) => timezone
+            .parse()
+            .map_err(|_| bad_request(format!("Unknown time zone: {}", timezone)))?,
+    };
+
+    if !payload.email && !payload.push && !payload.channels {
+        return Err(bad_request(
+            "Choose at least one of email, push or channels".to_string(),
+        ));
+    }
+    if payload.email && state.mailer.is_none() {
+        return Err(bad_request("Email is not configured".to_string()));
+    }
+    if payload.push && state.push.is_none() {
+        return Err(bad_request(
+            "Push notifications are not configured".to_string(),
+        ));
+    }
+    if payload.channels {
+        let channels = state
+            .storage
+            .list_notification_channels(&user_id)
+            .await
+            .map_err(|e| storage_error(e, "Database error"))?;
+        let matrix = state
+            .storage
+            .get_matrix_account(&user_id)
+            .await
+            .map_err(|e| storage_error(e, "Database error"))?;
+        if channels.is_empty() && matrix.is_none() {
+            return Err(bad_request(
+                "Add a notification channel or connect Matrix first".to_string(),
+            ));
+        }
+    }
+
+    let days: Vec<&str> = weekdays.into_iter().map(weekday_name).collect();
+    let reminder = Reminder::new(
+        user_id,
+        time.format("%H:%M").to_string(),
+        days.join(","),
+        timezone.name().to_string(),
+        payload.email,
+        payload.push,
+        payload.channels,
+    )
+    .ok_or_else(|| bad_request("Invalid reminder settings".to_string()))?;
+    state
+        .storage
+        .set_reminder(&reminder)
+        .await
+        .map_err(|e| storage_error(e, "Failed to save reminder"))?;
+
+    Ok(Json(reminder.to_response()))
+}
+
+/// DELETE /api/user/reminder
+/// Stop reminding the user to write
+#[utoipa::path(
+    delete,
+    path = "/api/user/reminder",
+    tag = "user",
+    responses(
+        (status = 200, description = "Reminder stopped", body = SuccessResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn delete_reminder(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<SuccessResponse>, AppError> {
+    state
+        .storage
+        .delete_reminder(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Failed to remove reminder"))?;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ids::UserId,
+        models::{Message, NotificationChannel, NOTIFICATION_CHANNEL_NTFY},
+        testing::{auth_user, test_app_state},
+    };
+    use std::sync::{Arc, Mutex};
+
+    async fn setup_test_state() -> SharedState {
+        Arc::new(test_app_state().await)
+    }
+
+    type Posts = Arc<Mutex<Vec<(String, String)>>>;
+
+    /// Minimal ntfy server recording the title and body of each post
+    async fn spawn_ntfy_server() -> (String, Posts) {
+        let posts = Posts::default();
+        let recorded = posts.clone();
+        let app = axum::Router::new().route(
+            "/journal",
+            axum::routing::post(move |headers: axum::http::HeaderMap, body: String| {
+                let posts = recorded.clone();
+                async move {
+                    let title = headers["title"].to_str().unwrap().to_string();
+                    posts.lock().unwrap().push((title, body));
+                    StatusCode::OK
+                }
+            }),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}/journal", addr), posts)
+    }
+
+    /// Bring a reminder forward, to every day, so it is due now
+    async fn make_due(state: &AppState, user_id: &UserId) {
+        let mut reminder = state.storage.get_reminder(user_id).await.unwrap().unwrap();
+        reminder.days = "monday,tuesday,wednesday,thursday,friday,saturday,sunday".to_string();
+        reminder.next_run_at = Utc::now() - chrono::Duration::minutes(1);
+        state.storage.set_reminder(&reminder).await.unwrap();
+    }
+
+    fn reminder_request() -> Json<SetReminderRequest> {
+        Json(SetReminderRequest {
+            time: "21:30".to_string(),
+            days: None,
+            timezone: None,
+            email: false,
+            push: false,
+            channels: true,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_reminder_settings_are_validated() {
+        let state = setup_test_state().await;
+        let auth = auth_user(&UserId::generate());
+
+        let mut request = reminder_request();
+        request.time = "9pm".to_string();
+        let result = set_reminder(State(state.clone()), auth.clone(), request).await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::BAD_REQUEST);
+
+        let mut request = reminder_request();
+        request.channels = false;
+        request.email = true;
+        let result = set_reminder(State(state.clone()), auth.clone(), request).await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::BAD_REQUEST);
+
+        let mut request = reminder_request();
+        request.days = Some(vec!["someday".to_string()]);
+        let result = set_reminder(State(state.clone()), auth.clone(), request).await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::BAD_REQUEST);
+
+        // No channels to send it to
+        let result = set_reminder(State(state.clone()), auth.clone(), reminder_request()).await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::BAD_REQUEST);
+
+        let result = get_reminder(State(state), auth).await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_reminders_skip_days_already_written() {
+        let state = setup_test_state().await;
+        let (ntfy_url, posts) = spawn_ntfy_server().await;
+        let user = crate::models::User::new(
+            "reminded@example.com".to_string(),
+            "reminded".to_string(),
+            "hash".to_string(),
+            "salt".to_string(),
+        );
+        state.storage.create_user(&user).await.unwrap();
+
+        for days_ago in [1, 2] {
+            let mut message = Message::new(user.id.clone(), "Earlier".to_string());
+            message.created_at = Utc::now() - chrono::Duration::days(days_ago);
+            state.storage.create_message(&message).await.unwrap();
+        }
+
+        let channel = NotificationChannel::new(
+            user.id.clone(),
+            NOTIFICATION_CHANNEL_NTFY.to_string(),
+            ntfy_url,
+            None,
+        );
+        state
+            .storage
+            .create_notification_channel(&channel)
+            .await
+            .unwrap();
+
+        let mut request = reminder_request();
+        request.days = Some(vec![
+            "Sunday".to_string(),
+            "sat".to_string(),
+            "sun".to_string(),
+        ]);
+        let Json(saved) = set_reminder(State(state.clone()), auth_user(&user.id), request)
+            .await
+            .unwrap();
+        assert_eq!(saved.days, vec!["saturday", "sunday"]);
+
+        make_due(&state, &user.id).await;
+        send_due_reminders(&state).await;
+        {
+            let posts = posts.lock().unwrap();
+            assert_eq!(posts.len(), 1);
+            assert_eq!(posts[0].0, "Keep your streak going");
+            assert!(posts[0].1.contains("2 days in a row"));
+        }
+        let reminder = state.storage.get_reminder(&user.id).await.unwrap().unwrap();
+        assert!(reminder.next_run_at > Utc::now());
+
+        let message = Message::new(user.id.clone(), "Today".to_string());
+        state.storage.create_message(&message).await.unwrap();
+        make_due(&state, &user.id).await;
+        send_due_reminders(&state).await;
+        assert_eq!(posts.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_streak_counts_consecutive_days_before_today() {
+        let day = |d: u32| NaiveDate::from_ymd_opt(2026, 10, d).unwrap();
+        let today = day(16);
+
+        let days: HashSet<NaiveDate> = [day(15), day(14), day(12)].into();
+        assert_eq!(streak_before(&days, today), 2);
+
+        let days: HashSet<NaiveDate> = [day(16), day(14)].into();
+        assert_eq!(streak_before(&days, today), 0);
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use axum::{extract::Request, http::StatusCode, middleware::Next, response::Response};
+use sentry::protocol::Event;
+
+use crate::{accesslog::LoggedUser, config::ReportingConfig, middleware::REQUEST_ID};
+
+/// Start sending panics and server errors to the Sentry-compatible DSN in
+/// `[reporting]`; `None` when unset
+///
+/// Keep the guard until exit, so queued reports are flushed. Reports carry
+/// the method, path, request ID and user ID, but no message content, query
+/// strings, cookies or credentials.
+pub fn init(config: &ReportingConfig) -> Result<Option<sentry::ClientInitGuard>, String> {
+    let Some(dsn) = config.dsn.as_deref() else {
+        return Ok(None);
+    };
+    let dsn = dsn
+        .parse()
+        .map_err(|e| format!("Invalid SENTRY_DSN: {}", e))?;
+
+    Ok(Some(sentry::init(sentry::ClientOptions {
+        dsn: Some(dsn),
+        release: sentry::release_name!(),
+        environment: config.environment.clone().map(Into::into),
+        // No client IPs or sensitive headers
+        send_default_pii: false,
+        before_send: Some(Arc::new(scrub)),
+        ..Default::default()
+    })))
+}
+
+/// Query strings can carry the tokens from emailed download links
+fn scrub(mut event: Event<'static>) -> Option<Event<'static>> {
+    if let Some(request) = &mut event.request {
+        if let Some(url) = &mut request.url {
+            url.set_query(None);
+        }
+        request.query_string = None;
+        request.cookies = None;
+    }
+    Some(event)
+}
+
+/// Report responses that failed on our side
+///
+/// Runs inside the per-request Sentry hub and the access log, so reports get
+/// the request details and `X-Request-Id` to find the matching log lines.
+/// Panics are reported by Sentry's panic hook instead.
+pub async fn capture_server_errors(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID)
+        .and_then(|id| id.to_str().ok())
+        .map(str::to_string);
+
+    let response = next.run(request).await;
+
+    let status = response.status();
+    // 503s mean the database was busy and the client was asked to retry
+    if !status.is_server_error() || status == StatusCode::SERVICE_UNAVAILABLE {
+        return response;
+    }
+    let user_id = response
+        .extensions()
+        .get::<LoggedUser>()
+        .map(|LoggedUser(id)| id.clone());
+
+    sentry::with_scope(
+        |scope| {
+            scope.set_tag("method", &method);
+            scope.set_tag("path", &path);
+            if let Some(request_id) = &request_id {
+                scope.set_tag("request_id", request_id);
+            }
+            if let Some(id) = user_id {
+                scope.set_user(Some(sentry::User {
+                    id: Some(id),
+                    ..Default::default()
+                }));
+            }
+        },
+        || {
+            sentry::capture_message(
+                &format!("Server error {}", status.as_u16()),
+                sentry::Level::Error,
+            )
+        },
+    );
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sentry::protocol::Request as EventRequest;
+
+    #[test]
+    fn test_scrub_removes_query_strings() {
+        let event = Event {
+            request: Some(EventRequest {
+                url: Some(
+                    "https://journal.example.com/api/exports/download?token=secret"
+                        .parse()
+                        .unwrap(),
+                ),
+                query_string: Some("token=secret".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let request = scrub(event).unwrap().request.unwrap();
+
+        assert_eq!(
+            request.url.unwrap().as_str(),
+            "https://journal.example.com/api/exports/download"
+        );
+        assert!(request.query_string.is_none());
+    }
+}
@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// How long in-flight requests and background work get to finish once a
+/// shutdown starts; open WebSockets would otherwise hold it up forever.
+/// Kept under the 30 s grace period Kubernetes gives before SIGKILL.
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(25);
+
+/// Cancel `token` on the first SIGTERM or Ctrl-C
+pub fn cancel_on_signal(token: CancellationToken) {
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        tracing::info!("Shutting down, draining connections");
+        token.cancel();
+    });
+}
+
+async fn wait_for_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Can't listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    // What `docker stop` and Kubernetes send
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Can't listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Wait for background tasks to notice the shutdown and finish their
+/// current piece of work
+pub async fn join_workers(workers: Vec<JoinHandle<()>>) {
+    for worker in workers {
+        if let Err(e) = worker.await {
+            tracing::error!("Background task failed: {}", e);
+        }
+    }
+}
@@ -0,0 +1,1505 @@
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    db::{
+        self, AuditFilter, CheckpointMode, CheckpointResult, ConflictPolicy, DbError, DbPool,
+        DbPools, InstanceStats, IntegrityReport, OptimizeResult, SortedPage,
+    },
+    ids::{MessageId, UserId},
+    models::{
+        Announcement, AuditEvent, Avatar, DigestSubscription, DormancyPolicy, DueWebhookDelivery,
+        ExportJob, ExportSchedule, IdempotentResponse, InboundEmailAddress, JournalMemberResponse,
+        MatrixAccount, Message, NotificationChannel, PushSubscription, ReactionCount, Reminder,
+        SharedJournalResponse, TelegramLink, TelegramLinkCode, Template, User, UserReaction,
+        WebDavDestination, Webhook, WebhookDelivery,
+    },
+};
+
+mod memory;
+
+pub use memory::MemoryStorage;
+
+/// Shared handle to the configured storage backend
+pub type SharedStorage = Arc<dyn Storage>;
+
+/// Client changes applied atomically by `Storage::apply_sync_batch`
+#[derive(Debug, Default)]
+pub struct SyncBatch {
+    pub creates: Vec<Message>,
+    pub updates: Vec<SyncEdit>,
+    pub deletes: Vec<MessageId>,
+    /// Cursor the returned delta is computed from
+    pub since_seq: i64,
+}
+
+/// A queued edit to an existing message
+#[derive(Debug)]
+pub struct SyncEdit {
+    pub id: MessageId,
+    pub content: String,
+    /// Version the edit was based on, for conflict detection
+    pub base_updated_at: Option<DateTime<Utc>>,
+}
+
+/// Server-side changes since a sequence cursor
+#[derive(Debug)]
+pub struct SyncDelta {
+    pub changed: Vec<Message>,
+    pub deleted: Vec<MessageId>,
+    pub seq: i64,
+}
+
+/// Persistence operations used by the HTTP layer
+///
+/// Method semantics, including which `DbError` each returns, follow the
+/// functions of the same name in `db`.
+#[axum::async_trait]
+pub trait Storage: Send + Sync {
+    /// Write a consistent snapshot of all data to a new file at `path`
+    async fn backup_to(&self, path: &str) -> Result<(), DbError>;
+    /// Checkpoint the write-ahead log into the main database file
+    async fn checkpoint(&self, mode: CheckpointMode) -> Result<CheckpointResult, DbError>;
+    /// Refresh planner statistics, reclaim free pages and truncate the WAL
+    async fn optimize(&self) -> Result<OptimizeResult, DbError>;
+    /// Current size of the write-ahead log, if the database has one
+    async fn wal_size_bytes(&self) -> Result<Option<u64>, DbError>;
+    /// Check the database for corruption and orphaned rows
+    async fn check_integrity(&self) -> Result<IntegrityReport, DbError>;
+    /// User and message counts for the last `days` days, and database size
+    async fn instance_stats(&self, days: u32) -> Result<InstanceStats, DbError>;
+    /// Check the database answers queries
+    async fn ping(&self) -> Result<(), DbError>;
+    /// Number of schema migrations not yet applied
+    async fn pending_migrations(&self) -> Result<usize, DbError>;
+    /// Wait for checked-out connections to be returned, then close them all
+    async fn close(&self);
+
+    // Users
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, DbError>;
+    async fn find_user_by_id(&self, id: &UserId) -> Result<Option<User>, DbError>;
+    async fn create_user(&self, user: &User) -> Result<(), DbError>;
+    async fn list_users_page(&self, page: &SortedPage<'_>) -> Result<Vec<User>, DbError>;
+    async fn delete_user(&self, user_id: &UserId) -> Result<(), DbError>;
+    async fn update_user_role(&self, user_id: &UserId, role: &str) -> Result<(), DbError>;
+    async fn set_user_disabled(&self, user_id: &UserId, disabled: bool) -> Result<(), DbError>;
+    async fn update_user_email(&self, user_id: &UserId, email: &str) -> Result<(), DbError>;
+    async fn update_user_username(&self, user_id: &UserId, username: &str) -> Result<(), DbError>;
+    async fn update_user_display_name(
+        &self,
+        user_id: &UserId,
+        display_name: Option<&str>,
+    ) -> Result<(), DbError>;
+    async fn update_user_password(
+        &self,
+        user_id: &UserId,
+        password_hash: &str,
+        salt: &str,
+    ) -> Result<(), DbError>;
+
+    // Messages
+    async fn get_change_seq(&self, user_id: &UserId) -> Result<i64, DbError>;
+    async fn get_messages_since_seq(
+        &self,
+        user_id: &UserId,
+        since_seq: i64,
+    ) -> Result<Vec<Message>, DbError>;
+    async fn get_messages_fingerprint(&self, user_id: &UserId) -> Result<String, DbError>;
+    async fn get_content_bytes(&self, user_id: &UserId) -> Result<i64, DbError>;
+    async fn get_message_counts(&self, user_id: &UserId) -> Result<(i64, i64), DbError>;
+    async fn get_messages_for_user(
+        &self,
+        user_id: &UserId,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Message>, DbError>;
+    async fn get_messages_for_user_page(
+        &self,
+        user_id: &UserId,
+        since: Option<DateTime<Utc>>,
+        page: &SortedPage<'_>,
+    ) -> Result<Vec<Message>, DbError>;
+    async fn count_messages_for_user(&self, user_id: &UserId) -> Result<i64, DbError>;
+    async fn get_messages_page(
+        &self,
+        user_id: &UserId,
+        after: Option<(DateTime<Utc>, &MessageId)>,
+        limit: i64,
+    ) -> Result<Vec<Message>, DbError>;
+    async fn get_messages_created_between(
+        &self,
+        user_id: &UserId,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<Message>, DbError>;
+    async fn get_message_times(
+        &self,
+        user_id: &UserId,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<DateTime<Utc>>, DbError>;
+    async fn get_message_by_id(&self, id: &MessageId) -> Result<Option<Message>, DbError>;
+    async fn create_message(&self, message: &Message) -> Result<Message, DbError>;
+    async fn upsert_message(&self, message: &Message) -> Result<(Message, bool), DbError>;
+    async fn update_message(
+        &self,
+        id: &MessageId,
+        user_id: &UserId,
+        content: &str,
+        base_updated_at: Option<DateTime<Utc>>,
+        policy: ConflictPolicy,
+    ) -> Result<Message, DbError>;
+    async fn delete_message(&self, id: &MessageId, user_id: &UserId) -> Result<(), DbError>;
+    async fn get_deleted_message_ids(
+        &self,
+        user_id: &UserId,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<MessageId>, DbError>;
+    async fn get_deleted_message_ids_since_seq(
+        &self,
+        user_id: &UserId,
+        since_seq: i64,
+    ) -> Result<Vec<MessageId>, DbError>;
+    /// Apply queued client changes and read back the delta in one transaction
+    ///
+    /// Updates and deletes of messages that no longer exist are skipped, since
+    /// the returned tombstones already tell the client about the deletion.
+    async fn apply_sync_batch(
+        &self,
+        user_id: &UserId,
+        batch: SyncBatch,
+        policy: ConflictPolicy,
+    ) -> Result<SyncDelta, DbError>;
+
+    // Templates
+    async fn get_templates_for_user(&self, user_id: &UserId) -> Result<Vec<Template>, DbError>;
+    async fn get_template_for_user(
+        &self,
+        id: &str,
+        user_id: &UserId,
+    ) -> Result<Option<Template>, DbError>;
+    async fn create_template(&self, template: &Template) -> Result<Template, DbError>;
+    async fn update_template(
+        &self,
+        id: &str,
+        user_id: &UserId,
+        name: &str,
+        content: &str,
+    ) -> Result<Template, DbError>;
+    async fn delete_template(&self, id: &str, user_id: &UserId) -> Result<(), DbError>;
+
+    // Shared journals
+    async fn add_journal_member(
+        &self,
+        owner_id: &UserId,
+        member_id: &UserId,
+        role: &str,
+    ) -> Result<(), DbError>;
+    async fn remove_journal_member(
+        &self,
+        owner_id: &UserId,
+        member_id: &UserId,
+    ) -> Result<(), DbError>;
+    async fn list_journal_members(
+        &self,
+        owner_id: &UserId,
+    ) -> Result<Vec<JournalMemberResponse>, DbError>;
+    async fn list_shared_journals(
+        &self,
+        member_id: &UserId,
+    ) -> Result<Vec<SharedJournalResponse>, DbError>;
+    async fn get_journal_messages(
+        &self,
+        owner_id: &UserId,
+        member_id: &UserId,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Message>, DbError>;
+    async fn create_journal_message(
+        &self,
+        member_id: &UserId,
+        message: &Message,
+    ) -> Result<Message, DbError>;
+
+    // Reactions
+    async fn add_reaction(
+        &self,
+        message_id: &MessageId,
+        user_id: &UserId,
+        emoji: &str,
+    ) -> Result<(), DbError>;
+    async fn remove_reaction(
+        &self,
+        message_id: &MessageId,
+        user_id: &UserId,
+        emoji: &str,
+    ) -> Result<(), DbError>;
+    async fn get_reaction_counts(
+        &self,
+        owner_id: &UserId,
+    ) -> Result<HashMap<MessageId, Vec<ReactionCount>>, DbError>;
+    async fn get_reactions_by_user(&self, user_id: &UserId) -> Result<Vec<UserReaction>, DbError>;
+
+    // Push subscriptions
+    async fn save_push_subscription(
+        &self,
+        user_id: &UserId,
+        endpoint: &str,
+        p256dh: &str,
+        auth: &str,
+    ) -> Result<(), DbError>;
+    async fn get_push_subscriptions(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<PushSubscription>, DbError>;
+    async fn delete_push_subscription(
+        &self,
+        user_id: &UserId,
+        endpoint: &str,
+    ) -> Result<(), DbError>;
+
+    // Export jobs
+    async fn create_export_job(&self, job: &ExportJob) -> Result<(), DbError>;
+    async fn get_export_job(
+        &self,
+        id: &str,
+        user_id: &UserId,
+    ) -> Result<Option<ExportJob>, DbError>;
+    async fn get_export_job_by_token(
+        &self,
+        id: &str,
+        token: &str,
+    ) -> Result<Option<ExportJob>, DbError>;
+    async fn claim_next_export_job(&self) -> Result<Option<ExportJob>, DbError>;
+    async fn set_export_job_progress(
+        &self,
+        id: &str,
+        processed: i64,
+        total: i64,
+    ) -> Result<(), DbError>;
+    async fn finish_export_job(&self, id: &str, error: Option<&str>) -> Result<(), DbError>;
+    async fn requeue_running_export_jobs(&self) -> Result<u64, DbError>;
+    async fn delete_export_jobs_finished_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<ExportJob>, DbError>;
+
+    // Export schedules
+    async fn create_export_schedule(&self, schedule: &ExportSchedule) -> Result<(), DbError>;
+    async fn get_export_schedule(
+        &self,
+        id: &str,
+        user_id: &UserId,
+    ) -> Result<Option<ExportSchedule>, DbError>;
+    async fn list_export_schedules(&self, user_id: &UserId)
+        -> Result<Vec<ExportSchedule>, DbError>;
+    async fn delete_export_schedule(&self, id: &str, user_id: &UserId) -> Result<(), DbError>;
+    async fn get_due_export_schedules(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<ExportSchedule>, DbError>;
+    async fn queue_scheduled_export(
+        &self,
+        job: &ExportJob,
+        next_run_at: DateTime<Utc>,
+    ) -> Result<(), DbError>;
+
+    // WebDAV destinations
+    async fn set_webdav_destination(&self, destination: &WebDavDestination) -> Result<(), DbError>;
+    async fn get_webdav_destination(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<WebDavDestination>, DbError>;
+    async fn delete_webdav_destination(&self, user_id: &UserId) -> Result<(), DbError>;
+
+    // Digest subscriptions
+    async fn set_digest_subscription(
+        &self,
+        subscription: &DigestSubscription,
+    ) -> Result<(), DbError>;
+    async fn get_digest_subscription(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<DigestSubscription>, DbError>;
+    async fn delete_digest_subscription(&self, user_id: &UserId) -> Result<(), DbError>;
+    async fn get_due_digest_subscriptions(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<DigestSubscription>, DbError>;
+    async fn set_digest_next_run(
+        &self,
+        user_id: &UserId,
+        next_run_at: DateTime<Utc>,
+    ) -> Result<(), DbError>;
+
+    // Reminders
+    async fn set_reminder(&self, reminder: &Reminder) -> Result<(), DbError>;
+    async fn get_reminder(&self, user_id: &UserId) -> Result<Option<Reminder>, DbError>;
+    async fn delete_reminder(&self, user_id: &UserId) -> Result<(), DbError>;
+    async fn get_due_reminders(&self, now: DateTime<Utc>) -> Result<Vec<Reminder>, DbError>;
+    async fn set_reminder_next_run(
+        &self,
+        user_id: &UserId,
+        next_run_at: DateTime<Utc>,
+    ) -> Result<(), DbError>;
+
+    // Notification channels
+    async fn create_notification_channel(
+        &self,
+        channel: &NotificationChannel,
+    ) -> Result<(), DbError>;
+    async fn get_notification_channel(
+        &self,
+        id: &str,
+        user_id: &UserId,
+    ) -> Result<Option<NotificationChannel>, DbError>;
+    async fn list_notification_channels(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<NotificationChannel>, DbError>;
+    async fn delete_notification_channel(&self, id: &str, user_id: &UserId) -> Result<(), DbError>;
+
+    // Telegram
+    async fn create_telegram_link_code(&self, link_code: &TelegramLinkCode) -> Result<(), DbError>;
+    async fn link_telegram_chat(
+        &self,
+        code: &str,
+        chat_id: i64,
+        now: DateTime<Utc>,
+    ) -> Result<Option<UserId>, DbError>;
+    async fn get_telegram_link(&self, user_id: &UserId) -> Result<Option<TelegramLink>, DbError>;
+    async fn get_telegram_link_by_chat(
+        &self,
+        chat_id: i64,
+    ) -> Result<Option<TelegramLink>, DbError>;
+    async fn delete_telegram_link(&self, user_id: &UserId) -> Result<(), DbError>;
+    async fn delete_expired_telegram_link_codes(&self, now: DateTime<Utc>) -> Result<u64, DbError>;
+
+    // Inbound email
+    async fn save_inbound_email_address(
+        &self,
+        address: &InboundEmailAddress,
+    ) -> Result<(), DbError>;
+    async fn get_inbound_email_address(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<InboundEmailAddress>, DbError>;
+    async fn get_inbound_email_address_by_token(
+        &self,
+        token: &str,
+    ) -> Result<Option<InboundEmailAddress>, DbError>;
+    async fn delete_inbound_email_address(&self, user_id: &UserId) -> Result<(), DbError>;
+
+    // Matrix
+    async fn set_matrix_account(&self, account: &MatrixAccount) -> Result<(), DbError>;
+    async fn get_matrix_account(&self, user_id: &UserId) -> Result<Option<MatrixAccount>, DbError>;
+    async fn list_matrix_accounts(&self) -> Result<Vec<MatrixAccount>, DbError>;
+    async fn set_matrix_sync_token(
+        &self,
+        user_id: &UserId,
+        sync_token: &str,
+    ) -> Result<(), DbError>;
+    async fn delete_matrix_account(&self, user_id: &UserId) -> Result<(), DbError>;
+
+    // Dormancy
+    async fn set_dormancy_policy(&self, policy: &DormancyPolicy) -> Result<(), DbError>;
+    async fn get_dormancy_policy(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<DormancyPolicy>, DbError>;
+    async fn list_pending_dormancy_policies(&self) -> Result<Vec<DormancyPolicy>, DbError>;
+    async fn record_user_activity(
+        &self,
+        user_id: &UserId,
+        at: DateTime<Utc>,
+    ) -> Result<(), DbError>;
+    async fn record_dormancy_warning(
+        &self,
+        user_id: &UserId,
+        warnings_sent: i64,
+        at: DateTime<Utc>,
+    ) -> Result<(), DbError>;
+    async fn mark_dormancy_triggered(
+        &self,
+        user_id: &UserId,
+        last_active_at: DateTime<Utc>,
+        at: DateTime<Utc>,
+    ) -> Result<bool, DbError>;
+    async fn delete_dormancy_policy(&self, user_id: &UserId) -> Result<(), DbError>;
+
+    // Avatars
+    async fn set_user_avatar(&self, avatar: &Avatar) -> Result<(), DbError>;
+    async fn get_user_avatar(&self, user_id: &UserId) -> Result<Option<Avatar>, DbError>;
+
+    // Webhooks
+    async fn create_webhook(&self, webhook: &Webhook) -> Result<(), DbError>;
+    async fn get_webhook(&self, id: &str, user_id: &UserId) -> Result<Option<Webhook>, DbError>;
+    async fn list_webhooks(&self, user_id: &UserId) -> Result<Vec<Webhook>, DbError>;
+    async fn delete_webhook(&self, id: &str, user_id: &UserId) -> Result<(), DbError>;
+    async fn queue_webhook_deliveries(
+        &self,
+        user_id: &UserId,
+        event: &str,
+        payload: &str,
+    ) -> Result<u64, DbError>;
+    async fn get_due_webhook_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<DueWebhookDelivery>, DbError>;
+    async fn record_webhook_attempt(
+        &self,
+        id: &str,
+        status: &str,
+        response_status: Option<i64>,
+        error: Option<&str>,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<(), DbError>;
+    async fn list_webhook_deliveries(
+        &self,
+        webhook_id: &str,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, DbError>;
+    async fn delete_webhook_deliveries_before(&self, before: DateTime<Utc>)
+        -> Result<u64, DbError>;
+
+    // Idempotency keys
+    async fn get_idempotent_response(
+        &self,
+        user_id: &UserId,
+        key: &str,
+        not_before: &str,
+    ) -> Result<Option<IdempotentResponse>, DbError>;
+    async fn save_idempotent_response(
+        &self,
+        user_id: &UserId,
+        key: &str,
+        response: &IdempotentResponse,
+    ) -> Result<(), DbError>;
+    async fn delete_idempotent_responses_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<u64, DbError>;
+
+    // Feature flags
+    async fn list_feature_flags(&self) -> Result<Vec<(String, bool)>, DbError>;
+    async fn set_feature_flag(&self, name: &str, enabled: bool) -> Result<(), DbError>;
+    async fn delete_feature_flag(&self, name: &str) -> Result<(), DbError>;
+
+    // Announcements
+    async fn create_announcement(&self, announcement: &Announcement) -> Result<(), DbError>;
+    async fn update_announcement(
+        &self,
+        id: &str,
+        message: &str,
+        level: &str,
+        starts_at: Option<DateTime<Utc>>,
+        ends_at: Option<DateTime<Utc>>,
+    ) -> Result<Announcement, DbError>;
+    async fn delete_announcement(&self, id: &str) -> Result<(), DbError>;
+    async fn list_announcements(&self) -> Result<Vec<Announcement>, DbError>;
+    async fn list_active_announcements(
+        &self,
+        now: DateTime<Utc>,
+        user_id: Option<&UserId>,
+    ) -> Result<Vec<Announcement>, DbError>;
+    async fn dismiss_announcement(&self, id: &str, user_id: &UserId) -> Result<(), DbError>;
+
+    // Audit log
+    async fn record_audit_event(&self, event: &AuditEvent) -> Result<(), DbError>;
+    async fn list_audit_events(
+        &self,
+        filter: &AuditFilter,
+        limit: i64,
+    ) -> Result<Vec<AuditEvent>, DbError>;
+}
+
+/// SQLite storage that sends reads and writes to separate pools
+pub struct SqliteStorage {
+    read: DbPool,
+    write: DbPool,
+}
+
+impl SqliteStorage {
+    pub fn new(pool: DbPool) -> Self {
+        Self::from_pools(DbPools::single(pool))
+    }
+
+    pub fn from_pools(pools: DbPools) -> Self {
+        Self {
+            read: pools.read,
+            write: pools.write,
+        }
+    }
+
+    /// Apply a sync batch in one transaction, without retrying
+    async fn try_apply_sync_batch(
+        &self,
+        user_id: &UserId,
+        batch: &SyncBatch,
+        policy: ConflictPolicy,
+    ) -> Result<SyncDelta, DbError> {
+        let mut tx = self.write.begin().await?;
+
+        for message in &batch.creates {
+            db::upsert_message_in(&mut tx, message).await?;
+        }
+
+        for update in &batch.updates {
+            match db::update_message_in(
+                &mut tx,
+                &update.id,
+                user_id,
+                &update.content,
+                update.base_updated_at,
+                policy,
+            )
+            .await
+            {
+                Ok(_) | Err(DbError::MessageNotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        for id in &batch.deletes {
+            match db::delete_message_in(&mut tx, id, user_id).await {
+                Ok(()) | Err(DbError::MessageNotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let changed = db::get_messages_since_seq(&mut *tx, user_id, batch.since_seq).await?;
+        let deleted =
+            db::get_deleted_message_ids_since_seq(&mut *tx, user_id, batch.since_seq).await?;
+        let seq = db::get_change_seq(&mut *tx, user_id).await?;
+
+        tx.commit().await?;
+
+        Ok(SyncDelta {
+            changed,
+            deleted,
+            seq,
+        })
+    }
+}
+
+#[axum::async_trait]
+impl Storage for SqliteStorage {
+    async fn backup_to(&self, path: &str) -> Result<(), DbError> {
+        db::backup_database(&self.read, path).await
+    }
+
+    async fn checkpoint(&self, mode: CheckpointMode) -> Result<CheckpointResult, DbError> {
+        db::wal_checkpoint(&self.write, mode).await
+    }
+
+    async fn optimize(&self) -> Result<OptimizeResult, DbError> {
+        db::optimize(&self.write).await
+    }
+
+    async fn wal_size_bytes(&self) -> Result<Option<u64>, DbError> {
+        db::wal_size_bytes(&self.read).await
+    }
+
+    async fn check_integrity(&self) -> Result<IntegrityReport, DbError> {
+        db::check_database(&self.read).await
+    }
+
+    async fn instance_stats(&self, days: u32) -> Result<InstanceStats, DbError> {
+        db::instance_stats(&self.read, days).await
+    }
+
+    async fn ping(&self) -> Result<(), DbError> {
+        db::ping(&self.read).await
+    }
+
+    async fn pending_migrations(&self) -> Result<usize, DbError> {
+        db::pending_migrations(&self.read).await
+    }
+
+    async fn close(&self) {
+        // Closing the writer last lets SQLite checkpoint and remove the WAL
+        self.read.close().await;
+        self.write.close().await;
+    }
+
+    // Users
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, DbError> {
+        db::find_user_by_email(&self.read, email).await
+    }
+
+    async fn find_user_by_id(&self, id: &UserId) -> Result<Option<User>, DbError> {
+        db::find_user_by_id(&self.read, id).await
+    }
+
+    async fn create_user(&self, user: &User) -> Result<(), DbError> {
+        db::create_user(&self.write, user).await
+    }
+
+    async fn list_users_page(&self, page: &SortedPage<'_>) -> Result<Vec<User>, DbError> {
+        db::list_users_page(&self.read, page).await
+    }
+
+    async fn delete_user(&self, user_id: &UserId) -> Result<(), DbError> {
+        db::delete_user(&self.write, user_id).await
+    }
+
+    async fn update_user_role(&self, user_id: &UserId, role: &str) -> Result<(), DbError> {
+        db::update_user_role(&self.write, user_id, role).await
+    }
+
+    async fn set_user_disabled(&self, user_id: &UserId, disabled: bool) -> Result<(), DbError> {
+        db::set_user_disabled(&self.write, user_id, disabled).await
+    }
+
+    async fn update_user_email(&self, user_id: &UserId, email: &str) -> Result<(), DbError> {
+        db::update_user_email(&self.write, user_id, email).await
+    }
+
+    async fn update_user_username(&self, user_id: &UserId, username: &str) -> Result<(), DbError> {
+        db::update_user_username(&self.write, user_id, username).await
+    }
+
+    async fn update_user_display_name(
+        &self,
+        user_id: &UserId,
+        display_name: Option<&str>,
+    ) -> Result<(), DbError> {
+        db::update_user_display_name(&self.write, user_id, display_name).await
+    }
+
+    async fn update_user_password(
+        &self,
+        user_id: &UserId,
+        password_hash: &str,
+        salt: &str,
+    ) -> Result<(), DbError> {
+        db::update_user_password(&self.write, user_id, password_hash, salt).await
+    }
+
+    // Messages
+    async fn get_change_seq(&self, user_id: &UserId) -> Result<i64, DbError> {
+        db::get_change_seq(&self.read, user_id).await
+    }
+
+    async fn get_messages_since_seq(
+        &self,
+        user_id: &UserId,
+        since_seq: i64,
+    ) -> Result<Vec<Message>, DbError> {
+        db::get_messages_since_seq(&self.read, user_id, since_seq).await
+    }
+
+    async fn get_messages_fingerprint(&self, user_id: &UserId) -> Result<String, DbError> {
+        db::get_messages_fingerprint(&self.read, user_id).await
+    }
+
+    async fn get_content_bytes(&self, user_id: &UserId) -> Result<i64, DbError> {
+        db::get_content_bytes(&self.read, user_id).await
+    }
+
+    async fn get_message_counts(&self, user_id: &UserId) -> Result<(i64, i64), DbError> {
+        db::get_message_counts(&self.read, user_id).await
+    }
+
+    async fn get_messages_for_user(
+        &self,
+        user_id: &UserId,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Message>, DbError> {
+        db::get_messages_for_user(&self.read, user_id, since).await
+    }
+
+    async fn get_messages_for_user_page(
+        &self,
+        user_id: &UserId,
+        since: Option<DateTime<Utc>>,
+        page: &SortedPage<'_>,
+    ) -> Result<Vec<Message>, DbError> {
+        db::get_messages_for_user_page(&self.read, user_id, since, page).await
+    }
+
+    async fn count_messages_for_user(&self, user_id: &UserId) -> Result<i64, DbError> {
+        db::count_messages_for_user(&self.read, user_id).await
+    }
+
+    async fn get_messages_page(
+        &self,
+        user_id: &UserId,
+        after: Option<(DateTime<Utc>, &MessageId)>,
+        limit: i64,
+    ) -> Result<Vec<Message>, DbError> {
+        db::get_messages_page(&self.read, user_id, after, limit).await
+    }
+
+    async fn get_messages_created_between(
+        &self,
+        user_id: &UserId,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<Message>, DbError> {
+        db::get_messages_created_between(&self.read, user_id, since, until).await
+    }
+
+    async fn get_message_times(
+        &self,
+        user_id: &UserId,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<DateTime<Utc>>, DbError> {
+        db::get_message_times(&self.read, user_id, since).await
+    }
+
+    async fn get_message_by_id(&self, id: &MessageId) -> Result<Option<Message>, DbError> {
+        db::get_message_by_id(&self.read, id).await
+    }
+
+    async fn create_message(&self, message: &Message) -> Result<Message, DbError> {
+        db::create_message(&self.write, message).await
+    }
+
+    async fn upsert_message(&self, message: &Message) -> Result<(Message, bool), DbError> {
+        db::upsert_message(&self.write, message).await
+    }
+
+    async fn update_message(
+        &self,
+        id: &MessageId,
+        user_id: &UserId,
+        content: &str,
+        base_updated_at: Option<DateTime<Utc>>,
+        policy: ConflictPolicy,
+    ) -> Result<Message, DbError> {
+        db::update_message(&self.write, id, user_id, content, base_updated_at, policy).await
+    }
+
+    async fn delete_message(&self, id: &MessageId, user_id: &UserId) -> Result<(), DbError> {
+        db::delete_message(&self.write, id, user_id).await
+    }
+
+    async fn get_deleted_message_ids(
+        &self,
+        user_id: &UserId,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<MessageId>, DbError> {
+        db::get_deleted_message_ids(&self.read, user_id, since).await
+    }
+
+    async fn get_deleted_message_ids_since_seq(
+        &self,
+        user_id: &UserId,
+        since_seq: i64,
+    ) -> Result<Vec<MessageId>, DbError> {
+        db::get_deleted_message_ids_since_seq(&self.read, user_id, since_seq).await
+    }
+
+    async fn apply_sync_batch(
+        &self,
+        user_id: &UserId,
+        batch: SyncBatch,
+        policy: ConflictPolicy,
+    ) -> Result<SyncDelta, DbError> {
+        db::retry_busy(|| self.try_apply_sync_batch(user_id, &batch, policy)).await
+    }
+
+    // Templates
+    async fn get_templates_for_user(&self, user_id: &UserId) -> Result<Vec<Template>, DbError> {
+        db::get_templates_for_user(&self.read, user_id).await
+    }
+
+    async fn get_template_for_user(
+        &self,
+        id: &str,
+        user_id: &UserId,
+    ) -> Result<Option<Template>, DbError> {
+        db::get_template_for_user(&self.read, id, user_id).await
+    }
+
+    async fn create_template(&self, template: &Template) -> Result<Template, DbError> {
+        db::create_template(&self.write, template).await
+    }
+
+    async fn update_template(
+        &self,
+        id: &str,
+        user_id: &UserId,
+        name: &str,
+        content: &str,
+    ) -> Result<Template, DbError> {
+        db::update_template(&self.write, id, user_id, name, content).await
+    }
+
+    async fn delete_template(&self, id: &str, user_id: &UserId) -> Result<(), DbError> {
+        db::delete_template(&self.write, id, user_id).await
+    }
+
+    // Shared journals
+    async fn add_journal_member(
+        &self,
+        owner_id: &UserId,
+        member_id: &UserId,
+        role: &str,
+    ) -> Result<(), DbError> {
+        db::add_journal_member(&self.write, owner_id, member_id, role).await
+    }
+
+    async fn remove_journal_member(
+        &self,
+        owner_id: &UserId,
+        member_id: &UserId,
+    ) -> Result<(), DbError> {
+        db::remove_journal_member(&self.write, owner_id, member_id).await
+    }
+
+    async fn list_journal_members(
+        &self,
+        owner_id: &UserId,
+    ) -> Result<Vec<JournalMemberResponse>, DbError> {
+        db::list_journal_members(&self.read, owner_id).await
+    }
+
+    async fn list_shared_journals(
+        &self,
+        member_id: &UserId,
+    ) -> Result<Vec<SharedJournalResponse>, DbError> {
+        db::list_shared_journals(&self.read, member_id).await
+    }
+
+    async fn get_journal_messages(
+        &self,
+        owner_id: &UserId,
+        member_id: &UserId,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Message>, DbError> {
+        db::get_journal_messages(&self.read, owner_id, member_id, since).await
+    }
+
+    async fn create_journal_message(
+        &self,
+        member_id: &UserId,
+        message: &Message,
+    ) -> Result<Message, DbError> {
+        db::create_journal_message(&self.write, member_id, message).await
+    }
+
+    // Reactions
+    async fn add_reaction(
+        &self,
+        message_id: &MessageId,
+        user_id: &UserId,
+        emoji: &str,
+    ) -> Result<(), DbError> {
+        db::add_reaction(&self.write, message_id, user_id, emoji).await
+    }
+
+    async fn remove_reaction(
+        &self,
+        message_id: &MessageId,
+        user_id: &UserId,
+        emoji: &str,
+    ) -> Result<(), DbError> {
+        db::remove_reaction(&self.write, message_id, user_id, emoji).await
+    }
+
+    async fn get_reaction_counts(
+        &self,
+        owner_id: &UserId,
+    ) -> Result<HashMap<MessageId, Vec<ReactionCount>>, DbError> {
+        db::get_reaction_counts(&self.read, owner_id).await
+    }
+
+    async fn get_reactions_by_user(&self, user_id: &UserId) -> Result<Vec<UserReaction>, DbError> {
+        db::get_reactions_by_user(&self.read, user_id).await
+    }
+
+    // Push subscriptions
+    async fn save_push_subscription(
+        &self,
+        user_id: &UserId,
+        endpoint: &str,
+        p256dh: &str,
+        auth: &str,
+    ) -> Result<(), DbError> {
+        db::save_push_subscription(&self.write, user_id, endpoint, p256dh, auth).await
+    }
+
+    async fn get_push_subscriptions(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<PushSubscription>, DbError> {
+        db::get_push_subscriptions(&self.read, user_id).await
+    }
+
+    async fn delete_push_subscription(
+        &self,
+        user_id: &UserId,
+        endpoint: &str,
+    ) -> Result<(), DbError> {
+        db::delete_push_subscription(&self.write, user_id, endpoint).await
+    }
+
+    // Export jobs
+    async fn create_export_job(&self, job: &ExportJob) -> Result<(), DbError> {
+        db::create_export_job(&self.write, job).await
+    }
+
+    async fn get_export_job(
+        &self,
+        id: &str,
+        user_id: &UserId,
+    ) -> Result<Option<ExportJob>, DbError> {
+        db::get_export_job(&self.read, id, user_id).await
+    }
+
+    async fn get_export_job_by_token(
+        &self,
+        id: &str,
+        token: &str,
+    ) -> Result<Option<ExportJob>, DbError> {
+        db::get_export_job_by_token(&self.read, id, token).await
+    }
+
+    async fn claim_next_export_job(&self) -> Result<Option<ExportJob>, DbError> {
+        db::claim_next_export_job(&self.write).await
+    }
+
+    async fn set_export_job_progress(
+        &self,
+        id: &str,
+        processed: i64,
+        total: i64,
+    ) -> Result<(), DbError> {
+        db::set_export_job_progress(&self.write, id, processed, total).await
+    }
+
+    async fn finish_export_job(&self, id: &str, error: Option<&str>) -> Result<(), DbError> {
+        db::finish_export_job(&self.write, id, error).await
+    }
+
+    async fn requeue_running_export_jobs(&self) -> Result<u64, DbError> {
+        db::requeue_running_export_jobs(&self.write).await
+    }
+
+    async fn delete_export_jobs_finished_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<ExportJob>, DbError> {
+        db::delete_export_jobs_finished_before(&self.write, before).await
+    }
+
+    // Export schedules
+    async fn create_export_schedule(&self, schedule: &ExportSchedule) -> Result<(), DbError> {
+        db::create_export_schedule(&self.write, schedule).await
+    }
+
+    async fn get_export_schedule(
+        &self,
+        id: &str,
+        user_id: &UserId,
+    ) -> Result<Option<ExportSchedule>, DbError> {
+        db::get_export_schedule(&self.read, id, user_id).await
+    }
+
+    async fn list_export_schedules(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<ExportSchedule>, DbError> {
+        db::list_export_schedules(&self.read, user_id).await
+    }
+
+    async fn delete_export_schedule(&self, id: &str, user_id: &UserId) -> Result<(), DbError> {
+        db::delete_export_schedule(&self.write, id, user_id).await
+    }
+
+    async fn get_due_export_schedules(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<ExportSchedule>, DbError> {
+        db::get_due_export_schedules(&self.read, now).await
+    }
+
+    async fn queue_scheduled_export(
+        &self,
+        job: &ExportJob,
+        next_run_at: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        db::queue_scheduled_export(&self.write, job, next_run_at).await
+    }
+
+    // WebDAV destinations
+    async fn set_webdav_destination(&self, destination: &WebDavDestination) -> Result<(), DbError> {
+        db::set_webdav_destination(&self.write, destination).await
+    }
+
+    async fn get_webdav_destination(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<WebDavDestination>, DbError> {
+        db::get_webdav_destination(&self.read, user_id).await
+    }
+
+    async fn delete_webdav_destination(&self, user_id: &UserId) -> Result<(), DbError> {
+        db::delete_webdav_destination(&self.write, user_id).await
+    }
+
+    // Digest subscriptions
+    async fn set_digest_subscription(
+        &self,
+        subscription: &DigestSubscription,
+    ) -> Result<(), DbError> {
+        db::set_digest_subscription(&self.write, subscription).await
+    }
+
+    async fn get_digest_subscription(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<DigestSubscription>, DbError> {
+        db::get_digest_subscription(&self.read, user_id).await
+    }
+
+    async fn delete_digest_subscription(&self, user_id: &UserId) -> Result<(), DbError> {
+        db::delete_digest_subscription(&self.write, user_id).await
+    }
+
+    async fn get_due_digest_subscriptions(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<DigestSubscription>, DbError> {
+        db::get_due_digest_subscriptions(&self.read, now).await
+    }
+
+    async fn set_digest_next_run(
+        &self,
+        user_id: &UserId,
+        next_run_at: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        db::set_digest_next_run(&self.write, user_id, next_run_at).await
+    }
+
+    // Reminders
+    async fn set_reminder(&self, reminder: &Reminder) -> Result<(), DbError> {
+        db::set_reminder(&self.write, reminder).await
+    }
+
+    async fn get_reminder(&self, user_id: &UserId) -> Result<Option<Reminder>, DbError> {
+        db::get_reminder(&self.read, user_id).await
+    }
+
+    async fn delete_reminder(&self, user_id: &UserId) -> Result<(), DbError> {
+        db::delete_reminder(&self.write, user_id).await
+    }
+
+    async fn get_due_reminders(&self, now: DateTime<Utc>) -> Result<Vec<Reminder>, DbError> {
+        db::get_due_reminders(&self.read, now).await
+    }
+
+    async fn set_reminder_next_run(
+        &self,
+        user_id: &UserId,
+        next_run_at: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        db::set_reminder_next_run(&self.write, user_id, next_run_at).await
+    }
+
+    // Notification channels
+    async fn create_notification_channel(
+        &self,
+        channel: &NotificationChannel,
+    ) -> Result<(), DbError> {
+        db::create_notification_channel(&self.write, channel).await
+    }
+
+    async fn get_notification_channel(
+        &self,
+        id: &str,
+        user_id: &UserId,
+    ) -> Result<Option<NotificationChannel>, DbError> {
+        db::get_notification_channel(&self.read, id, user_id).await
+    }
+
+    async fn list_notification_channels(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<NotificationChannel>, DbError> {
+        db::list_notification_channels(&self.read, user_id).await
+    }
+
+    async fn delete_notification_channel(&self, id: &str, user_id: &UserId) -> Result<(), DbError> {
+        db::delete_notification_channel(&self.write, id, user_id).await
+    }
+
+    // Telegram
+    async fn create_telegram_link_code(&self, link_code: &TelegramLinkCode) -> Result<(), DbError> {
+        db::create_telegram_link_code(&self.write, link_code).await
+    }
+
+    async fn link_telegram_chat(
+        &self,
+        code: &str,
+        chat_id: i64,
+        now: DateTime<Utc>,
+    ) -> Result<Option<UserId>, DbError> {
+        db::link_telegram_chat(&self.write, code, chat_id, now).await
+    }
+
+    async fn get_telegram_link(&self, user_id: &UserId) -> Result<Option<TelegramLink>, DbError> {
+        db::get_telegram_link(&self.read, user_id).await
+    }
+
+    async fn get_telegram_link_by_chat(
+        &self,
+        chat_id: i64,
+    ) -> Result<Option<TelegramLink>, DbError> {
+        db::get_telegram_link_by_chat(&self.read, chat_id).await
+    }
+
+    async fn delete_telegram_link(&self, user_id: &UserId) -> Result<(), DbError> {
+        db::delete_telegram_link(&self.write, user_id).await
+    }
+
+    async fn delete_expired_telegram_link_codes(&self, now: DateTime<Utc>) -> Result<u64, DbError> {
+        db::delete_expired_telegram_link_codes(&self.write, now).await
+    }
+
+    // Inbound email
+    async fn save_inbound_email_address(
+        &self,
+        address: &InboundEmailAddress,
+    ) -> Result<(), DbError> {
+        db::save_inbound_email_address(&self.write, address).await
+    }
+
+    async fn get_inbound_email_address(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<InboundEmailAddress>, DbError> {
+        db::get_inbound_email_address(&self.read, user_id).await
+    }
+
+    async fn get_inbound_email_address_by_token(
+        &self,
+        token: &str,
+    ) -> Result<Option<InboundEmailAddress>, DbError> {
+        db::get_inbound_email_address_by_token(&self.read, token).await
+    }
+
+    async fn delete_inbound_email_address(&self, user_id: &UserId) -> Result<(), DbError> {
+        db::delete_inbound_email_address(&self.write, user_id).await
+    }
+
+    // Matrix
+    async fn set_matrix_account(&self, account: &MatrixAccount) -> Result<(), DbError> {
+        db::set_matrix_account(&self.write, account).await
+    }
+
+    async fn get_matrix_account(&self, user_id: &UserId) -> Result<Option<MatrixAccount>, DbError> {
+        db::get_matrix_account(&self.read, user_id).await
+    }
+
+    async fn list_matrix_accounts(&self) -> Result<Vec<MatrixAccount>, DbError> {
+        db::list_matrix_accounts(&self.read).await
+    }
+
+    async fn set_matrix_sync_token(
+        &self,
+        user_id: &UserId,
+        sync_token: &str,
+    ) -> Result<(), DbError> {
+        db::set_matrix_sync_token(&self.write, user_id, sync_token).await
+    }
+
+    async fn delete_matrix_account(&self, user_id: &UserId) -> Result<(), DbError> {
+        db::delete_matrix_account(&self.write, user_id).await
+    }
+
+    // Dormancy
+    async fn set_dormancy_policy(&self, policy: &DormancyPolicy) -> Result<(), DbError> {
+        db::set_dormancy_policy(&self.write, policy).await
+    }
+
+    async fn get_dormancy_policy(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<DormancyPolicy>, DbError> {
+        db::get_dormancy_policy(&self.read, user_id).await
+    }
+
+    async fn list_pending_dormancy_policies(&self) -> Result<Vec<DormancyPolicy>, DbError> {
+        db::list_pending_dormancy_policies(&self.read).await
+    }
+
+    async fn record_user_activity(
+        &self,
+        user_id: &UserId,
+        at: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        db::record_user_activity(&self.write, user_id, at).await
+    }
+
+    async fn record_dormancy_warning(
+        &self,
+        user_id: &UserId,
+        warnings_sent: i64,
+        at: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        db::record_dormancy_warning(&self.write, user_id, warnings_sent, at).await
+    }
+
+    async fn mark_dormancy_triggered(
+        &self,
+        user_id: &UserId,
+        last_active_at: DateTime<Utc>,
+        at: DateTime<Utc>,
+    ) -> Result<bool, DbError> {
+        db::mark_dormancy_triggered(&self.write, user_id, last_active_at, at).await
+    }
+
+    async fn delete_dormancy_policy(&self, user_id: &UserId) -> Result<(), DbError> {
+        db::delete_dormancy_policy(&self.write, user_id).await
+    }
+
+    // Avatars
+    async fn set_user_avatar(&self, avatar: &Avatar) -> Result<(), DbError> {
+        db::set_user_avatar(&self.write, avatar).await
+    }
+
+    async fn get_user_avatar(&self, user_id: &UserId) -> Result<Option<Avatar>, DbError> {
+        db::get_user_avatar(&self.read, user_id).await
+    }
+
+    // Webhooks
+    async fn create_webhook(&self, webhook: &Webhook) -> Result<(), DbError> {
+        db::create_webhook(&self.write, webhook).await
+    }
+
+    async fn get_webhook(&self, id: &str, user_id: &UserId) -> Result<Option<Webhook>, DbError> {
+        db::get_webhook(&self.read, id, user_id).await
+    }
+
+    async fn list_webhooks(&self, user_id: &UserId) -> Result<Vec<Webhook>, DbError> {
+        db::list_webhooks(&self.read, user_id).await
+    }
+
+    async fn delete_webhook(&self, id: &str, user_id: &UserId) -> Result<(), DbError> {
+        db::delete_webhook(&self.write, id, user_id).await
+    }
+
+    async fn queue_webhook_deliveries(
+        &self,
+        user_id: &UserId,
+        event: &str,
+        payload: &str,
+    ) -> Result<u64, DbError> {
+        db::queue_webhook_deliveries(&self.write, user_id, event, payload).await
+    }
+
+    async fn get_due_webhook_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<DueWebhookDelivery>, DbError> {
+        db::get_due_webhook_deliveries(&self.read, now, limit).await
+    }
+
+    async fn record_webhook_attempt(
+        &self,
+        id: &str,
+        status: &str,
+        response_status: Option<i64>,
+        error: Option<&str>,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<(), DbError> {
+        db::record_webhook_attempt(
+            &self.write,
+            id,
+            status,
+            response_status,
+            error,
+            next_attempt_at,
+        )
+        .await
+    }
+
+    async fn list_webhook_deliveries(
+        &self,
+        webhook_id: &str,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, DbError> {
+        db::list_webhook_deliveries(&self.read, webhook_id, limit).await
+    }
+
+    async fn delete_webhook_deliveries_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<u64, DbError> {
+        db::delete_webhook_deliveries_before(&self.write, before).await
+    }
+
+    // Idempotency keys
+    async fn get_idempotent_response(
+        &self,
+        user_id: &UserId,
+        key: &str,
+        not_before: &str,
+    ) -> Result<Option<IdempotentResponse>, DbError> {
+        db::get_idempotent_response(&self.read, user_id, key, not_before).await
+    }
+
+    async fn save_idempotent_response(
+        &self,
+        user_id: &UserId,
+        key: &str,
+        response: &IdempotentResponse,
+    ) -> Result<(), DbError> {
+        db::save_idempotent_response(&self.write, user_id, key, response).await
+    }
+
+    async fn delete_idempotent_responses_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<u64, DbError> {
+        db::delete_idempotent_responses_before(&self.write, before).await
+    }
+
+    // Feature flags
+    async fn list_feature_flags(&self) -> Result<Vec<(String, bool)>, DbError> {
+        db::list_feature_flags(&self.read).await
+    }
+
+    async fn set_feature_flag(&self, name: &str, enabled: bool) -> Result<(), DbError> {
+        db::set_feature_flag(&self.write, name, enabled).await
+    }
+
+    async fn delete_feature_flag(&self, name: &str) -> Result<(), DbError> {
+        db::delete_feature_flag(&self.write, name).await
+    }
+
+    // Announcements
+    async fn create_announcement(&self, announcement: &Announcement) -> Result<(), DbError> {
+        db::create_announcement(&self.write, announcement).await
+    }
+
+    async fn update_announcement(
+        &self,
+        id: &str,
+        message: &str,
+        level: &str,
+        starts_at: Option<DateTime<Utc>>,
+        ends_at: Option<DateTime<Utc>>,
+    ) -> Result<Announcement, DbError> {
+        db::update_announcement(&self.write, id, message, level, starts_at, ends_at).await
+    }
+
+    async fn delete_announcement(&self, id: &str) -> Result<(), DbError> {
+        db::delete_announcement(&self.write, id).await
+    }
+
+    async fn list_announcements(&self) -> Result<Vec<Announcement>, DbError> {
+        db::list_announcements(&self.read).await
+    }
+
+    async fn list_active_announcements(
+        &self,
+        now: DateTime<Utc>,
+        user_id: Option<&UserId>,
+    ) -> Result<Vec<Announcement>, DbError> {
+        db::list_active_announcements(&self.read, now, user_id).await
+    }
+
+    async fn dismiss_announcement(&self, id: &str, user_id: &UserId) -> Result<(), DbError> {
+        db::dismiss_announcement(&self.write, id, user_id).await
+    }
+
+    // Audit log
+    async fn record_audit_event(&self, event: &AuditEvent) -> Result<(), DbError> {
+        db::insert_audit_event(&self.write, event).await
+    }
+
+    async fn list_audit_events(
+        &self,
+        filter: &AuditFilter,
+        limit: i64,
+    ) -> Result<Vec<AuditEvent>, DbError> {
+        db::list_audit_events(&self.read, filter, limit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::hash_password;
+
+    async fn setup_test_storage() -> (SharedStorage, User) {
+        let pool = db::init_pool("sqlite::memory:").await.unwrap();
+        let storage: SharedStorage = Arc::new(SqliteStorage::new(pool));
+
+        let (hash, salt) = hash_password("password123").unwrap();
+        let user = User::new(
+            "storage@example.com".to_string(),
+            "storage".to_string(),
+            hash,
+            salt,
+        );
+        storage.create_user(&user).await.unwrap();
+
+        (storage, user)
+    }
+
+    #[tokio::test]
+    async fn test_apply_sync_batch_returns_delta() {
+        let (storage, user) = setup_test_storage().await;
+        let existing = Message::new(user.id.clone(), "Existing".to_string());
+        storage.create_message(&existing).await.unwrap();
+
+        let new_id = MessageId::generate();
+        let batch = SyncBatch {
+            creates: vec![Message::with_id(
+                new_id.clone(),
+                user.id.clone(),
+                "New".to_string(),
+            )],
+            deletes: vec![existing.id.clone(), MessageId::generate()],
+            since_seq: 1,
+            ..Default::default()
+        };
+        let delta = storage
+            .apply_sync_batch(&user.id, batch, ConflictPolicy::LastWriterWins)
+            .await
+            .unwrap();
+
+        assert_eq!(delta.changed.len(), 1);
+        assert_eq!(delta.changed[0].id, new_id);
+        assert_eq!(delta.deleted, vec![existing.id]);
+        assert_eq!(delta.seq, 3);
+    }
+
+    #[tokio::test]
+    async fn test_apply_sync_batch_rolls_back_on_conflict() {
+        let (storage, user) = setup_test_storage().await;
+        let (hash, salt) = hash_password("password123").unwrap();
+        let other = User::new(
+            "other@example.com".to_string(),
+            "other".to_string(),
+            hash,
+            salt,
+        );
+        storage.create_user(&other).await.unwrap();
+        let taken = Message::with_id(MessageId::generate(), other.id, "Theirs".to_string());
+        storage.create_message(&taken).await.unwrap();
+
+        let batch = SyncBatch {
+            creates: vec![
+                Message::new(user.id.clone(), "First".to_string()),
+                Message::with_id(taken.id.clone(), user.id.clone(), "Mine".to_string()),
+            ],
+            ..Default::default()
+        };
+        let result = storage
+            .apply_sync_batch(&user.id, batch, ConflictPolicy::LastWriterWins)
+            .await;
+
+        assert!(matches!(result, Err(DbError::MessageIdConflict)));
+        assert!(storage
+            .get_messages_for_user(&user.id, None)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
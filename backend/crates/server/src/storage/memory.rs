@@ -0,0 +1,2512 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Mutex, MutexGuard},
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use super::{Storage, SyncBatch, SyncDelta};
+use crate::{
+    db::{
+        AuditFilter, CheckpointMode, CheckpointResult, ConflictPolicy, DbError, InstanceStats,
+        IntegrityReport, OptimizeResult, SortedPage,
+    },
+    ids::{MessageId, UserId},
+    models::{
+        Announcement, AuditEvent, Avatar, DigestSubscription, DormancyPolicy, DueWebhookDelivery,
+        ExportJob, ExportSchedule, IdempotentResponse, InboundEmailAddress, JournalMemberResponse,
+        MatrixAccount, Message, NotificationChannel, PushSubscription, ReactionCount, Reminder,
+        SharedJournalResponse, TelegramLink, TelegramLinkCode, Template, User, UserReaction,
+        WebDavDestination, Webhook, WebhookDelivery, EXPORT_STATUS_COMPLETED, EXPORT_STATUS_FAILED,
+        EXPORT_STATUS_PENDING, EXPORT_STATUS_RUNNING, JOURNAL_ROLE_WRITE, WEBHOOK_DELIVERY_PENDING,
+    },
+};
+
+/// Storage that keeps everything in process memory, for tests and demos
+///
+/// Behaves like `SqliteStorage`: the same errors, the same ordering, and
+/// deleting a row removes what references it as the foreign keys would.
+/// Everything is lost when it is dropped, and there is no file to back up.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    tables: Mutex<Tables>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn tables(&self) -> MutexGuard<'_, Tables> {
+        self.tables.lock().unwrap()
+    }
+}
+
+/// Rows of each table, in insertion order
+#[derive(Debug, Default, Clone)]
+struct Tables {
+    users: Vec<UserRow>,
+    messages: Vec<Message>,
+    tombstones: Vec<Tombstone>,
+    message_creations: Vec<MessageCreations>,
+    templates: Vec<Template>,
+    journal_members: Vec<JournalMember>,
+    reactions: Vec<Reaction>,
+    push_subscriptions: Vec<PushSubscription>,
+    export_jobs: Vec<ExportJob>,
+    export_schedules: Vec<ExportSchedule>,
+    webdav_destinations: Vec<WebDavDestination>,
+    digest_subscriptions: Vec<DigestSubscription>,
+    reminders: Vec<Reminder>,
+    notification_channels: Vec<NotificationChannel>,
+    telegram_links: Vec<TelegramLink>,
+    telegram_link_codes: Vec<TelegramLinkCode>,
+    inbound_email_addresses: Vec<InboundEmailAddress>,
+    matrix_accounts: Vec<MatrixAccount>,
+    dormancy_policies: Vec<DormancyPolicy>,
+    user_avatars: Vec<Avatar>,
+    webhooks: Vec<Webhook>,
+    webhook_deliveries: Vec<WebhookDelivery>,
+    idempotency_keys: Vec<IdempotencyKey>,
+    feature_flags: BTreeMap<String, bool>,
+    announcements: Vec<Announcement>,
+    /// Announcement and user IDs
+    announcement_dismissals: Vec<(String, UserId)>,
+    audit_events: Vec<AuditEvent>,
+}
+
+#[derive(Debug, Clone)]
+struct UserRow {
+    user: User,
+    /// Last sequence number handed out for the user
+    change_seq: i64,
+}
+
+#[derive(Debug, Clone)]
+struct Tombstone {
+    message_id: MessageId,
+    user_id: UserId,
+    deleted_at: DateTime<Utc>,
+    seq: i64,
+}
+
+#[derive(Debug, Clone)]
+struct MessageCreations {
+    user_id: UserId,
+    day: NaiveDate,
+    count: i64,
+}
+
+#[derive(Debug, Clone)]
+struct JournalMember {
+    owner_id: UserId,
+    member_id: UserId,
+    role: String,
+    created_at: String,
+}
+
+#[derive(Debug, Clone)]
+struct Reaction {
+    message_id: MessageId,
+    user_id: UserId,
+    emoji: String,
+    created_at: String,
+}
+
+#[derive(Debug, Clone)]
+struct IdempotencyKey {
+    user_id: UserId,
+    key: String,
+    response: IdempotentResponse,
+    /// RFC 3339, compared as a string like the SQLite column
+    created_at: String,
+}
+
+/// A scheduled message stays hidden until its publish time
+fn is_visible(message: &Message, now: DateTime<Utc>) -> bool {
+    message.publish_at.is_none_or(|at| at <= now)
+}
+
+/// Rows a SQL `LIMIT` lets through; negative limits don't limit
+fn row_limit(limit: i64) -> usize {
+    usize::try_from(limit).unwrap_or(usize::MAX)
+}
+
+/// The rows `page` selects once sorted by `ascending`, which should break
+/// ties by ID like the SQL does
+fn sorted_page<T>(
+    mut rows: Vec<T>,
+    page: &SortedPage<'_>,
+    ascending: impl Fn(&T, &T) -> Ordering,
+) -> Vec<T> {
+    rows.sort_by(|a, b| {
+        let ordering = ascending(a, b);
+        if page.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+    rows.into_iter()
+        .skip(usize::try_from(page.offset).unwrap_or(0))
+        .take(page.limit.map_or(usize::MAX, row_limit))
+        .collect()
+}
+
+/// Remove the rows matching `remove`, returning how many there were
+fn remove_where<T>(rows: &mut Vec<T>, mut remove: impl FnMut(&T) -> bool) -> u64 {
+    let before = rows.len();
+    rows.retain(|row| !remove(row));
+    (before - rows.len()) as u64
+}
+
+/// Replace the row matching `matches` in place, or append `row`
+fn upsert<T>(rows: &mut Vec<T>, row: T, matches: impl Fn(&T) -> bool) {
+    match rows.iter_mut().find(|existing| matches(existing)) {
+        Some(existing) => *existing = row,
+        None => rows.push(row),
+    }
+}
+
+impl Tables {
+    fn user(&self, id: &UserId) -> Option<&User> {
+        self.users
+            .iter()
+            .map(|row| &row.user)
+            .find(|user| user.id == *id)
+    }
+
+    fn user_mut(&mut self, id: &UserId) -> Result<&mut User, DbError> {
+        self.users
+            .iter_mut()
+            .map(|row| &mut row.user)
+            .find(|user| user.id == *id)
+            .ok_or(DbError::UserNotFound)
+    }
+
+    /// Reject rows owned by a user that doesn't exist
+    fn ensure_user(&self, id: &UserId) -> Result<(), DbError> {
+        match self.user(id) {
+            Some(_) => Ok(()),
+            None => Err(DbError::ConstraintViolation("FOREIGN KEY users.id")),
+        }
+    }
+
+    fn change_seq(&self, user_id: &UserId) -> Result<i64, DbError> {
+        self.users
+            .iter()
+            .find(|row| row.user.id == *user_id)
+            .map(|row| row.change_seq)
+            .ok_or(DbError::UserNotFound)
+    }
+
+    /// Bump and return the user's change sequence number
+    fn next_seq(&mut self, user_id: &UserId) -> Result<i64, DbError> {
+        let row = self
+            .users
+            .iter_mut()
+            .find(|row| row.user.id == *user_id)
+            .ok_or(DbError::UserNotFound)?;
+        row.change_seq += 1;
+        Ok(row.change_seq)
+    }
+
+    fn message(&self, id: &MessageId) -> Option<&Message> {
+        self.messages.iter().find(|message| message.id == *id)
+    }
+
+    fn journal_role(&self, owner_id: &UserId, member_id: &UserId) -> Option<&str> {
+        self.journal_members
+            .iter()
+            .find(|member| member.owner_id == *owner_id && member.member_id == *member_id)
+            .map(|member| member.role.as_str())
+    }
+
+    fn messages_for_user(&self, user_id: &UserId, since: Option<DateTime<Utc>>) -> Vec<Message> {
+        let now = Utc::now();
+        let mut messages: Vec<Message> = self
+            .messages
+            .iter()
+            .filter(|m| m.user_id == *user_id && is_visible(m, now))
+            .filter(|m| {
+                since.is_none_or(|since| {
+                    m.created_at > since
+                        || m.updated_at > since
+                        || m.publish_at.is_some_and(|at| at > since)
+                })
+            })
+            .cloned()
+            .collect();
+        messages.sort_by_key(|message| Reverse(message.created_at));
+        messages
+    }
+
+    fn messages_since_seq(&self, user_id: &UserId, since_seq: i64) -> Vec<Message> {
+        let now = Utc::now();
+        let mut messages: Vec<Message> = self
+            .messages
+            .iter()
+            .filter(|m| m.user_id == *user_id && m.seq > since_seq && is_visible(m, now))
+            .cloned()
+            .collect();
+        messages.sort_by_key(|m| m.seq);
+        messages
+    }
+
+    fn deleted_ids_since_seq(&self, user_id: &UserId, since_seq: i64) -> Vec<MessageId> {
+        let mut tombstones: Vec<&Tombstone> = self
+            .tombstones
+            .iter()
+            .filter(|t| t.user_id == *user_id && t.seq > since_seq)
+            .collect();
+        tombstones.sort_by_key(|t| t.seq);
+        tombstones
+            .into_iter()
+            .map(|t| t.message_id.clone())
+            .collect()
+    }
+
+    fn create_message(&mut self, message: &Message) -> Result<Message, DbError> {
+        if self.message(&message.id).is_some() {
+            return Err(DbError::ConstraintViolation("UNIQUE messages.id"));
+        }
+
+        let mut created = message.clone();
+        created.seq = self.next_seq(&message.user_id)?;
+        self.messages.push(created.clone());
+
+        // A re-created ID is no longer deleted
+        remove_where(&mut self.tombstones, |t| t.message_id == message.id);
+
+        Ok(created)
+    }
+
+    fn upsert_message(&mut self, message: &Message) -> Result<(Message, bool), DbError> {
+        match self.message(&message.id).cloned() {
+            None => {
+                let created = self.create_message(message)?;
+                self.record_message_created(&message.user_id);
+                Ok((created, true))
+            }
+            Some(existing) if existing.user_id != message.user_id => {
+                Err(DbError::MessageIdConflict)
+            }
+            Some(existing) if existing.content == message.content => Ok((existing, false)),
+            Some(existing) => {
+                let updated = self.update_message(
+                    &existing.id,
+                    &existing.user_id,
+                    &message.content,
+                    None,
+                    ConflictPolicy::LastWriterWins,
+                )?;
+                Ok((updated, false))
+            }
+        }
+    }
+
+    /// Count a message written by a client towards its owner's messages today
+    fn record_message_created(&mut self, user_id: &UserId) {
+        let today = Utc::now().date_naive();
+
+        remove_where(&mut self.message_creations, |c| {
+            c.user_id == *user_id && c.day < today
+        });
+        match self
+            .message_creations
+            .iter_mut()
+            .find(|c| c.user_id == *user_id && c.day == today)
+        {
+            Some(creations) => creations.count += 1,
+            None => self.message_creations.push(MessageCreations {
+                user_id: user_id.clone(),
+                day: today,
+                count: 1,
+            }),
+        }
+    }
+
+    fn update_message(
+        &mut self,
+        id: &MessageId,
+        user_id: &UserId,
+        content: &str,
+        base_updated_at: Option<DateTime<Utc>>,
+        policy: ConflictPolicy,
+    ) -> Result<Message, DbError> {
+        let required_base = match policy {
+            ConflictPolicy::ConflictCopy => base_updated_at,
+            ConflictPolicy::LastWriterWins => None,
+        };
+        let position = self.messages.iter().position(|m| {
+            m.id == *id
+                && m.user_id == *user_id
+                && required_base.is_none_or(|base| m.updated_at == base)
+        });
+
+        match position {
+            Some(index) => {
+                let seq = self.next_seq(user_id)?;
+                let message = &mut self.messages[index];
+                message.content = content.to_string();
+                message.updated_at = Utc::now();
+                message.seq = seq;
+                Ok(message.clone())
+            }
+            None if self.message(id).is_some_and(|m| m.user_id == *user_id) => {
+                // Stale edit: keep the stored version and save the edit alongside it
+                let mut copy = Message::new(user_id.clone(), content.to_string());
+                copy.conflict_of = Some(id.clone());
+                self.create_message(&copy)
+            }
+            None => Err(DbError::MessageNotFound),
+        }
+    }
+
+    fn delete_message(&mut self, id: &MessageId, user_id: &UserId) -> Result<(), DbError> {
+        if remove_where(&mut self.messages, |m| m.id == *id && m.user_id == *user_id) == 0 {
+            return Err(DbError::MessageNotFound);
+        }
+        remove_where(&mut self.reactions, |r| r.message_id == *id);
+
+        // Record a tombstone so syncing clients learn about the deletion
+        let deleted_at = Utc::now();
+        let seq = self.next_seq(user_id)?;
+        match self.tombstones.iter_mut().find(|t| t.message_id == *id) {
+            Some(tombstone) => {
+                tombstone.deleted_at = deleted_at;
+                tombstone.seq = seq;
+            }
+            None => self.tombstones.push(Tombstone {
+                message_id: id.clone(),
+                user_id: user_id.clone(),
+                deleted_at,
+                seq,
+            }),
+        }
+
+        Ok(())
+    }
+
+    fn apply_sync_batch(
+        &mut self,
+        user_id: &UserId,
+        batch: &SyncBatch,
+        policy: ConflictPolicy,
+    ) -> Result<SyncDelta, DbError> {
+        for message in &batch.creates {
+            self.upsert_message(message)?;
+        }
+
+        for update in &batch.updates {
+            match self.update_message(
+                &update.id,
+                user_id,
+                &update.content,
+                update.base_updated_at,
+                policy,
+            ) {
+                Ok(_) | Err(DbError::MessageNotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        for id in &batch.deletes {
+            match self.delete_message(id, user_id) {
+                Ok(()) | Err(DbError::MessageNotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(SyncDelta {
+            changed: self.messages_since_seq(user_id, batch.since_seq),
+            deleted: self.deleted_ids_since_seq(user_id, batch.since_seq),
+            seq: self.change_seq(user_id)?,
+        })
+    }
+
+    /// Check that a user may react to a message: they must own it or be a member
+    /// of the owner's journal
+    fn ensure_can_react(
+        &self,
+        message_id: &MessageId,
+        user_id: &UserId,
+    ) -> Result<UserId, DbError> {
+        let message = self.message(message_id).ok_or(DbError::MessageNotFound)?;
+
+        let is_member = self.journal_role(&message.user_id, user_id).is_some();
+        if message.user_id != *user_id && !is_member {
+            return Err(DbError::MessageNotFound);
+        }
+
+        Ok(message.user_id.clone())
+    }
+
+    fn create_export_job(&mut self, job: &ExportJob) -> Result<(), DbError> {
+        if self.export_jobs.iter().any(|j| j.id == job.id) {
+            return Err(DbError::ConstraintViolation("UNIQUE export_jobs.id"));
+        }
+        self.ensure_user(&job.user_id)?;
+        if let Some(schedule_id) = &job.schedule_id {
+            if !self.export_schedules.iter().any(|s| s.id == *schedule_id) {
+                return Err(DbError::ConstraintViolation(
+                    "FOREIGN KEY export_schedules.id",
+                ));
+            }
+        }
+
+        self.export_jobs.push(job.clone());
+        Ok(())
+    }
+
+    /// Delete a user and everything that references them
+    fn delete_user(&mut self, user_id: &UserId) -> Result<(), DbError> {
+        if remove_where(&mut self.users, |row| row.user.id == *user_id) == 0 {
+            return Err(DbError::UserNotFound);
+        }
+
+        let message_ids: HashSet<MessageId> = self
+            .messages
+            .iter()
+            .filter(|m| m.user_id == *user_id)
+            .map(|m| m.id.clone())
+            .collect();
+        remove_where(&mut self.messages, |m| m.user_id == *user_id);
+        remove_where(&mut self.reactions, |r| {
+            r.user_id == *user_id || message_ids.contains(&r.message_id)
+        });
+
+        let webhook_ids: HashSet<String> = self
+            .webhooks
+            .iter()
+            .filter(|w| w.user_id == *user_id)
+            .map(|w| w.id.clone())
+            .collect();
+        remove_where(&mut self.webhooks, |w| w.user_id == *user_id);
+        remove_where(&mut self.webhook_deliveries, |d| {
+            webhook_ids.contains(&d.webhook_id)
+        });
+
+        remove_where(&mut self.tombstones, |t| t.user_id == *user_id);
+        remove_where(&mut self.message_creations, |c| c.user_id == *user_id);
+        remove_where(&mut self.templates, |t| t.user_id == *user_id);
+        remove_where(&mut self.journal_members, |m| {
+            m.owner_id == *user_id || m.member_id == *user_id
+        });
+        remove_where(&mut self.push_subscriptions, |s| s.user_id == *user_id);
+        remove_where(&mut self.export_jobs, |j| j.user_id == *user_id);
+        remove_where(&mut self.export_schedules, |s| s.user_id == *user_id);
+        remove_where(&mut self.webdav_destinations, |d| d.user_id == *user_id);
+        remove_where(&mut self.digest_subscriptions, |s| s.user_id == *user_id);
+        remove_where(&mut self.reminders, |r| r.user_id == *user_id);
+        remove_where(&mut self.notification_channels, |c| c.user_id == *user_id);
+        remove_where(&mut self.telegram_links, |l| l.user_id == *user_id);
+        remove_where(&mut self.telegram_link_codes, |c| c.user_id == *user_id);
+        remove_where(&mut self.inbound_email_addresses, |a| a.user_id == *user_id);
+        remove_where(&mut self.matrix_accounts, |a| a.user_id == *user_id);
+        remove_where(&mut self.dormancy_policies, |p| p.user_id == *user_id);
+        remove_where(&mut self.user_avatars, |a| a.user_id == *user_id);
+        remove_where(&mut self.idempotency_keys, |k| k.user_id == *user_id);
+        remove_where(&mut self.announcement_dismissals, |(_, dismissed_by)| {
+            dismissed_by == user_id
+        });
+
+        Ok(())
+    }
+}
+
+#[axum::async_trait]
+impl Storage for MemoryStorage {
+    async fn backup_to(&self, _path: &str) -> Result<(), DbError> {
+        Err(DbError::Unsupported("Backing up"))
+    }
+
+    async fn checkpoint(&self, _mode: CheckpointMode) -> Result<CheckpointResult, DbError> {
+        // There is no write-ahead log to copy back
+        Ok(CheckpointResult {
+            busy: false,
+            log_frames: 0,
+            checkpointed_frames: 0,
+        })
+    }
+
+    async fn optimize(&self) -> Result<OptimizeResult, DbError> {
+        Ok(OptimizeResult {
+            vacuumed_bytes: None,
+            checkpoint: self.checkpoint(CheckpointMode::Truncate).await?,
+        })
+    }
+
+    async fn wal_size_bytes(&self) -> Result<Option<u64>, DbError> {
+        Ok(None)
+    }
+
+    async fn check_integrity(&self) -> Result<IntegrityReport, DbError> {
+        let t = self.tables();
+
+        // Deletes remove what references the deleted row, so nothing is
+        // ever orphaned
+        let row_counts = [
+            ("users", t.users.len()),
+            ("messages", t.messages.len()),
+            ("templates", t.templates.len()),
+            ("journal_members", t.journal_members.len()),
+            ("reactions", t.reactions.len()),
+            ("tombstones", t.tombstones.len()),
+            ("push_subscriptions", t.push_subscriptions.len()),
+            ("idempotency_keys", t.idempotency_keys.len()),
+            ("audit_events", t.audit_events.len()),
+        ]
+        .into_iter()
+        .map(|(table, count)| (table.to_string(), count as i64))
+        .collect();
+
+        Ok(IntegrityReport {
+            row_counts,
+            ..IntegrityReport::default()
+        })
+    }
+
+    async fn instance_stats(&self, days: u32) -> Result<InstanceStats, DbError> {
+        let t = self.tables();
+
+        let today = Utc::now().date_naive();
+        let first_day = today - chrono::Duration::days(i64::from(days.max(1)) - 1);
+        let mut messages_per_day: BTreeMap<String, i64> = first_day
+            .iter_days()
+            .take_while(|day| *day <= today)
+            .map(|day| (day.to_string(), 0))
+            .collect();
+        for message in &t.messages {
+            let day = message.created_at.date_naive().to_string();
+            if let Some(total) = messages_per_day.get_mut(&day) {
+                *total += 1;
+            }
+        }
+
+        Ok(InstanceStats {
+            user_count: t.users.len() as i64,
+            messages_per_day,
+            database_bytes: 0,
+        })
+    }
+
+    async fn ping(&self) -> Result<(), DbError> {
+        Ok(())
+    }
+
+    async fn pending_migrations(&self) -> Result<usize, DbError> {
+        Ok(0)
+    }
+
+    async fn close(&self) {}
+
+    // Users
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, DbError> {
+        let t = self.tables();
+        Ok(t.users
+            .iter()
+            .map(|row| &row.user)
+            .find(|user| user.email == email)
+            .cloned())
+    }
+
+    async fn find_user_by_id(&self, id: &UserId) -> Result<Option<User>, DbError> {
+        Ok(self.tables().user(id).cloned())
+    }
+
+    async fn create_user(&self, user: &User) -> Result<(), DbError> {
+        let mut t = self.tables();
+        let taken = t
+            .users
+            .iter()
+            .any(|row| row.user.id == user.id || row.user.email == user.email);
+        if taken {
+            return Err(DbError::EmailAlreadyExists);
+        }
+
+        t.users.push(UserRow {
+            user: user.clone(),
+            change_seq: 0,
+        });
+        Ok(())
+    }
+
+    async fn list_users_page(&self, page: &SortedPage<'_>) -> Result<Vec<User>, DbError> {
+        let users: Vec<User> = self
+            .tables()
+            .users
+            .iter()
+            .map(|row| row.user.clone())
+            .collect();
+        Ok(sorted_page(users, page, |a, b| {
+            match page.sort {
+                "email" => a.email.cmp(&b.email),
+                "username" => a.username.cmp(&b.username),
+                _ => a.created_at.cmp(&b.created_at),
+            }
+            .then_with(|| a.id.cmp(&b.id))
+        }))
+    }
+
+    async fn delete_user(&self, user_id: &UserId) -> Result<(), DbError> {
+        self.tables().delete_user(user_id)
+    }
+
+    async fn update_user_role(&self, user_id: &UserId, role: &str) -> Result<(), DbError> {
+        let mut t = self.tables();
+        let user = t.user_mut(user_id)?;
+        user.role = role.to_string();
+        user.updated_at = Utc::now();
+        Ok(())
+    }
+
+    async fn set_user_disabled(&self, user_id: &UserId, disabled: bool) -> Result<(), DbError> {
+        let mut t = self.tables();
+        let user = t.user_mut(user_id)?;
+        user.disabled = disabled;
+        user.updated_at = Utc::now();
+        Ok(())
+    }
+
+    async fn update_user_email(&self, user_id: &UserId, email: &str) -> Result<(), DbError> {
+        let mut t = self.tables();
+        let taken = t
+            .users
+            .iter()
+            .any(|row| row.user.id != *user_id && row.user.email == email);
+        if taken {
+            return Err(DbError::EmailAlreadyExists);
+        }
+
+        let user = t.user_mut(user_id)?;
+        user.email = email.to_string();
+        user.updated_at = Utc::now();
+        Ok(())
+    }
+
+    async fn update_user_username(&self, user_id: &UserId, username: &str) -> Result<(), DbError> {
+        let mut t = self.tables();
+        let user = t.user_mut(user_id)?;
+        user.username = username.to_string();
+        user.updated_at = Utc::now();
+        Ok(())
+    }
+
+    async fn update_user_display_name(
+        &self,
+        user_id: &UserId,
+        display_name: Option<&str>,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        let user = t.user_mut(user_id)?;
+        user.display_name = display_name.map(str::to_string);
+        user.updated_at = Utc::now();
+        Ok(())
+    }
+
+    async fn update_user_password(
+        &self,
+        user_id: &UserId,
+        password_hash: &str,
+        salt: &str,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        let user = t.user_mut(user_id)?;
+        user.password_hash = password_hash.to_string();
+        user.salt = salt.to_string();
+        user.must_reset_password = false;
+        user.updated_at = Utc::now();
+        Ok(())
+    }
+
+    // Messages
+    async fn get_change_seq(&self, user_id: &UserId) -> Result<i64, DbError> {
+        self.tables().change_seq(user_id)
+    }
+
+    async fn get_messages_since_seq(
+        &self,
+        user_id: &UserId,
+        since_seq: i64,
+    ) -> Result<Vec<Message>, DbError> {
+        Ok(self.tables().messages_since_seq(user_id, since_seq))
+    }
+
+    async fn get_messages_fingerprint(&self, user_id: &UserId) -> Result<String, DbError> {
+        let t = self.tables();
+        let change_seq = t.change_seq(user_id)?;
+
+        let now = Utc::now();
+        let visible = t
+            .messages
+            .iter()
+            .filter(|m| m.user_id == *user_id && is_visible(m, now))
+            .count();
+
+        Ok(format!("{}-{}", change_seq, visible))
+    }
+
+    async fn get_content_bytes(&self, user_id: &UserId) -> Result<i64, DbError> {
+        let t = self.tables();
+        Ok(t.messages
+            .iter()
+            .filter(|m| m.user_id == *user_id)
+            .map(|m| m.content.len() as i64)
+            .sum())
+    }
+
+    async fn get_message_counts(&self, user_id: &UserId) -> Result<(i64, i64), DbError> {
+        let t = self.tables();
+        let today = Utc::now().date_naive();
+
+        let total = t.messages.iter().filter(|m| m.user_id == *user_id).count() as i64;
+        let created_today = t
+            .message_creations
+            .iter()
+            .find(|c| c.user_id == *user_id && c.day == today)
+            .map_or(0, |c| c.count);
+
+        Ok((total, created_today))
+    }
+
+    async fn get_messages_for_user(
+        &self,
+        user_id: &UserId,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Message>, DbError> {
+        Ok(self.tables().messages_for_user(user_id, since))
+    }
+
+    async fn get_messages_for_user_page(
+        &self,
+        user_id: &UserId,
+        since: Option<DateTime<Utc>>,
+        page: &SortedPage<'_>,
+    ) -> Result<Vec<Message>, DbError> {
+        let messages = self.tables().messages_for_user(user_id, since);
+        Ok(sorted_page(messages, page, |a, b| {
+            match page.sort {
+                "updated_at" => a.updated_at.cmp(&b.updated_at),
+                _ => a.created_at.cmp(&b.created_at),
+            }
+            .then_with(|| a.id.cmp(&b.id))
+        }))
+    }
+
+    async fn count_messages_for_user(&self, user_id: &UserId) -> Result<i64, DbError> {
+        let t = self.tables();
+        let now = Utc::now();
+        Ok(t.messages
+            .iter()
+            .filter(|m| m.user_id == *user_id && is_visible(m, now))
+            .count() as i64)
+    }
+
+    async fn get_messages_page(
+        &self,
+        user_id: &UserId,
+        after: Option<(DateTime<Utc>, &MessageId)>,
+        limit: i64,
+    ) -> Result<Vec<Message>, DbError> {
+        let t = self.tables();
+        let now = Utc::now();
+
+        let mut messages: Vec<Message> = t
+            .messages
+            .iter()
+            .filter(|m| m.user_id == *user_id && is_visible(m, now))
+            .filter(|m| {
+                after.is_none_or(|(created_at, id)| {
+                    m.created_at < created_at || (m.created_at == created_at && m.id < *id)
+                })
+            })
+            .cloned()
+            .collect();
+        messages.sort_by(|a, b| {
+            b.created_at
+                .cmp(&a.created_at)
+                .then_with(|| b.id.cmp(&a.id))
+        });
+        messages.truncate(row_limit(limit));
+
+        Ok(messages)
+    }
+
+    async fn get_messages_created_between(
+        &self,
+        user_id: &UserId,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<Message>, DbError> {
+        let t = self.tables();
+        let now = Utc::now();
+
+        let mut messages: Vec<Message> = t
+            .messages
+            .iter()
+            .filter(|m| m.user_id == *user_id && is_visible(m, now))
+            .filter(|m| m.created_at >= since && m.created_at < until)
+            .cloned()
+            .collect();
+        messages.sort_by(|a, b| {
+            b.created_at
+                .cmp(&a.created_at)
+                .then_with(|| b.id.cmp(&a.id))
+        });
+
+        Ok(messages)
+    }
+
+    async fn get_message_times(
+        &self,
+        user_id: &UserId,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<DateTime<Utc>>, DbError> {
+        let t = self.tables();
+        let mut times: Vec<DateTime<Utc>> = t
+            .messages
+            .iter()
+            .filter(|m| m.user_id == *user_id && m.created_at >= since)
+            .map(|m| m.created_at)
+            .collect();
+        times.sort_by(|a, b| b.cmp(a));
+        Ok(times)
+    }
+
+    async fn get_message_by_id(&self, id: &MessageId) -> Result<Option<Message>, DbError> {
+        Ok(self.tables().message(id).cloned())
+    }
+
+    async fn create_message(&self, message: &Message) -> Result<Message, DbError> {
+        self.tables().create_message(message)
+    }
+
+    async fn upsert_message(&self, message: &Message) -> Result<(Message, bool), DbError> {
+        self.tables().upsert_message(message)
+    }
+
+    async fn update_message(
+        &self,
+        id: &MessageId,
+        user_id: &UserId,
+        content: &str,
+        base_updated_at: Option<DateTime<Utc>>,
+        policy: ConflictPolicy,
+    ) -> Result<Message, DbError> {
+        self.tables()
+            .update_message(id, user_id, content, base_updated_at, policy)
+    }
+
+    async fn delete_message(&self, id: &MessageId, user_id: &UserId) -> Result<(), DbError> {
+        self.tables().delete_message(id, user_id)
+    }
+
+    async fn get_deleted_message_ids(
+        &self,
+        user_id: &UserId,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<MessageId>, DbError> {
+        let t = self.tables();
+        let mut tombstones: Vec<&Tombstone> = t
+            .tombstones
+            .iter()
+            .filter(|tombstone| tombstone.user_id == *user_id && tombstone.deleted_at > since)
+            .collect();
+        tombstones.sort_by_key(|tombstone| tombstone.deleted_at);
+        Ok(tombstones
+            .into_iter()
+            .map(|tombstone| tombstone.message_id.clone())
+            .collect())
+    }
+
+    async fn get_deleted_message_ids_since_seq(
+        &self,
+        user_id: &UserId,
+        since_seq: i64,
+    ) -> Result<Vec<MessageId>, DbError> {
+        Ok(self.tables().deleted_ids_since_seq(user_id, since_seq))
+    }
+
+    async fn apply_sync_batch(
+        &self,
+        user_id: &UserId,
+        batch: SyncBatch,
+        policy: ConflictPolicy,
+    ) -> Result<SyncDelta, DbError> {
+        let mut t = self.tables();
+        // Restored if any change fails, like a rolled back transaction
+        let snapshot = t.clone();
+
+        let result = t.apply_sync_batch(user_id, &batch, policy);
+        if result.is_err() {
+            *t = snapshot;
+        }
+        result
+    }
+
+    // Templates
+    async fn get_templates_for_user(&self, user_id: &UserId) -> Result<Vec<Template>, DbError> {
+        let t = self.tables();
+        let mut templates: Vec<Template> = t
+            .templates
+            .iter()
+            .filter(|template| template.user_id == *user_id)
+            .cloned()
+            .collect();
+        templates.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(templates)
+    }
+
+    async fn get_template_for_user(
+        &self,
+        id: &str,
+        user_id: &UserId,
+    ) -> Result<Option<Template>, DbError> {
+        let t = self.tables();
+        Ok(t.templates
+            .iter()
+            .find(|template| template.id == *id && template.user_id == *user_id)
+            .cloned())
+    }
+
+    async fn create_template(&self, template: &Template) -> Result<Template, DbError> {
+        let mut t = self.tables();
+        if t.templates
+            .iter()
+            .any(|existing| existing.id == template.id)
+        {
+            return Err(DbError::ConstraintViolation("UNIQUE templates.id"));
+        }
+        t.ensure_user(&template.user_id)?;
+
+        t.templates.push(template.clone());
+        Ok(template.clone())
+    }
+
+    async fn update_template(
+        &self,
+        id: &str,
+        user_id: &UserId,
+        name: &str,
+        content: &str,
+    ) -> Result<Template, DbError> {
+        let mut t = self.tables();
+        let template = t
+            .templates
+            .iter_mut()
+            .find(|template| template.id == *id && template.user_id == *user_id)
+            .ok_or(DbError::TemplateNotFound)?;
+
+        template.name = name.to_string();
+        template.content = content.to_string();
+        template.updated_at = Utc::now();
+        Ok(template.clone())
+    }
+
+    async fn delete_template(&self, id: &str, user_id: &UserId) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if remove_where(&mut t.templates, |template| {
+            template.id == *id && template.user_id == *user_id
+        }) == 0
+        {
+            return Err(DbError::TemplateNotFound);
+        }
+        Ok(())
+    }
+
+    // Shared journals
+    async fn add_journal_member(
+        &self,
+        owner_id: &UserId,
+        member_id: &UserId,
+        role: &str,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        t.ensure_user(owner_id)?;
+        t.ensure_user(member_id)?;
+
+        match t
+            .journal_members
+            .iter_mut()
+            .find(|m| m.owner_id == *owner_id && m.member_id == *member_id)
+        {
+            Some(member) => member.role = role.to_string(),
+            None => t.journal_members.push(JournalMember {
+                owner_id: owner_id.clone(),
+                member_id: member_id.clone(),
+                role: role.to_string(),
+                created_at: Utc::now().to_rfc3339(),
+            }),
+        }
+        Ok(())
+    }
+
+    async fn remove_journal_member(
+        &self,
+        owner_id: &UserId,
+        member_id: &UserId,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if remove_where(&mut t.journal_members, |m| {
+            m.owner_id == *owner_id && m.member_id == *member_id
+        }) == 0
+        {
+            return Err(DbError::MemberNotFound);
+        }
+        Ok(())
+    }
+
+    async fn list_journal_members(
+        &self,
+        owner_id: &UserId,
+    ) -> Result<Vec<JournalMemberResponse>, DbError> {
+        let t = self.tables();
+        let mut members: Vec<&JournalMember> = t
+            .journal_members
+            .iter()
+            .filter(|m| m.owner_id == *owner_id)
+            .collect();
+        members.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+
+        Ok(members
+            .into_iter()
+            .filter_map(|member| {
+                let user = t.user(&member.member_id)?;
+                Some(JournalMemberResponse {
+                    user_id: user.id.to_string(),
+                    email: user.email.clone(),
+                    username: user.username.clone(),
+                    display_name: user.shown_name().to_string(),
+                    role: member.role.clone(),
+                    created_at: member.created_at.clone(),
+                })
+            })
+            .collect())
+    }
+
+    async fn list_shared_journals(
+        &self,
+        member_id: &UserId,
+    ) -> Result<Vec<SharedJournalResponse>, DbError> {
+        let t = self.tables();
+        let mut journals: Vec<SharedJournalResponse> = t
+            .journal_members
+            .iter()
+            .filter(|m| m.member_id == *member_id)
+            .filter_map(|member| {
+                let owner = t.user(&member.owner_id)?;
+                Some(SharedJournalResponse {
+                    owner_id: owner.id.to_string(),
+                    username: owner.username.clone(),
+                    display_name: owner.shown_name().to_string(),
+                    role: member.role.clone(),
+                })
+            })
+            .collect();
+        journals.sort_by(|a, b| a.username.cmp(&b.username));
+        Ok(journals)
+    }
+
+    async fn get_journal_messages(
+        &self,
+        owner_id: &UserId,
+        member_id: &UserId,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Message>, DbError> {
+        let t = self.tables();
+        if t.journal_role(owner_id, member_id).is_none() {
+            return Err(DbError::JournalAccessDenied);
+        }
+        Ok(t.messages_for_user(owner_id, since))
+    }
+
+    async fn create_journal_message(
+        &self,
+        member_id: &UserId,
+        message: &Message,
+    ) -> Result<Message, DbError> {
+        let mut t = self.tables();
+        if t.journal_role(&message.user_id, member_id) != Some(JOURNAL_ROLE_WRITE) {
+            return Err(DbError::JournalAccessDenied);
+        }
+
+        let created = t.create_message(message)?;
+        t.record_message_created(&message.user_id);
+        Ok(created)
+    }
+
+    // Reactions
+    async fn add_reaction(
+        &self,
+        message_id: &MessageId,
+        user_id: &UserId,
+        emoji: &str,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        let owner_id = t.ensure_can_react(message_id, user_id)?;
+        t.ensure_user(user_id)?;
+
+        let exists = t
+            .reactions
+            .iter()
+            .any(|r| r.message_id == *message_id && r.user_id == *user_id && r.emoji == emoji);
+        if !exists {
+            t.reactions.push(Reaction {
+                message_id: message_id.clone(),
+                user_id: user_id.clone(),
+                emoji: emoji.to_string(),
+                created_at: Utc::now().to_rfc3339(),
+            });
+            t.next_seq(&owner_id)?;
+        }
+        Ok(())
+    }
+
+    async fn remove_reaction(
+        &self,
+        message_id: &MessageId,
+        user_id: &UserId,
+        emoji: &str,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        let owner_id = t.ensure_can_react(message_id, user_id)?;
+
+        let removed = remove_where(&mut t.reactions, |r| {
+            r.message_id == *message_id && r.user_id == *user_id && r.emoji == emoji
+        });
+        if removed > 0 {
+            t.next_seq(&owner_id)?;
+        }
+        Ok(())
+    }
+
+    async fn get_reaction_counts(
+        &self,
+        owner_id: &UserId,
+    ) -> Result<HashMap<MessageId, Vec<ReactionCount>>, DbError> {
+        let t = self.tables();
+
+        // Message ID, emoji, count and when the emoji was first used
+        let mut groups: Vec<(MessageId, String, i64, String)> = Vec::new();
+        for reaction in &t.reactions {
+            if t.message(&reaction.message_id)
+                .is_none_or(|m| m.user_id != *owner_id)
+            {
+                continue;
+            }
+            match groups.iter_mut().find(|(message_id, emoji, ..)| {
+                *message_id == reaction.message_id && *emoji == reaction.emoji
+            }) {
+                Some((.., count, first)) => {
+                    *count += 1;
+                    if reaction.created_at < *first {
+                        first.clone_from(&reaction.created_at);
+                    }
+                }
+                None => groups.push((
+                    reaction.message_id.clone(),
+                    reaction.emoji.clone(),
+                    1,
+                    reaction.created_at.clone(),
+                )),
+            }
+        }
+        groups.sort_by(|a, b| a.3.cmp(&b.3));
+
+        let mut counts: HashMap<MessageId, Vec<ReactionCount>> = HashMap::new();
+        for (message_id, emoji, count, _) in groups {
+            counts
+                .entry(message_id)
+                .or_default()
+                .push(ReactionCount { emoji, count });
+        }
+        Ok(counts)
+    }
+
+    async fn get_reactions_by_user(&self, user_id: &UserId) -> Result<Vec<UserReaction>, DbError> {
+        let t = self.tables();
+        let mut reactions: Vec<UserReaction> = t
+            .reactions
+            .iter()
+            .filter(|r| r.user_id == *user_id)
+            .map(|r| UserReaction {
+                message_id: r.message_id.clone(),
+                emoji: r.emoji.clone(),
+                created_at: r.created_at.clone(),
+            })
+            .collect();
+        reactions.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        Ok(reactions)
+    }
+
+    // Push subscriptions
+    async fn save_push_subscription(
+        &self,
+        user_id: &UserId,
+        endpoint: &str,
+        p256dh: &str,
+        auth: &str,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        t.ensure_user(user_id)?;
+
+        match t
+            .push_subscriptions
+            .iter_mut()
+            .find(|s| s.endpoint == endpoint)
+        {
+            Some(subscription) => {
+                subscription.user_id = user_id.clone();
+                subscription.p256dh = p256dh.to_string();
+                subscription.auth = auth.to_string();
+            }
+            None => t.push_subscriptions.push(PushSubscription {
+                endpoint: endpoint.to_string(),
+                user_id: user_id.clone(),
+                p256dh: p256dh.to_string(),
+                auth: auth.to_string(),
+                created_at: Utc::now().to_rfc3339(),
+            }),
+        }
+        Ok(())
+    }
+
+    async fn get_push_subscriptions(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<PushSubscription>, DbError> {
+        let t = self.tables();
+        let mut subscriptions: Vec<PushSubscription> = t
+            .push_subscriptions
+            .iter()
+            .filter(|s| s.user_id == *user_id)
+            .cloned()
+            .collect();
+        subscriptions.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        Ok(subscriptions)
+    }
+
+    async fn delete_push_subscription(
+        &self,
+        user_id: &UserId,
+        endpoint: &str,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        remove_where(&mut t.push_subscriptions, |s| {
+            s.endpoint == endpoint && s.user_id == *user_id
+        });
+        Ok(())
+    }
+
+    // Export jobs
+    async fn create_export_job(&self, job: &ExportJob) -> Result<(), DbError> {
+        self.tables().create_export_job(job)
+    }
+
+    async fn get_export_job(
+        &self,
+        id: &str,
+        user_id: &UserId,
+    ) -> Result<Option<ExportJob>, DbError> {
+        let t = self.tables();
+        Ok(t.export_jobs
+            .iter()
+            .find(|j| j.id == *id && j.user_id == *user_id)
+            .cloned())
+    }
+
+    async fn get_export_job_by_token(
+        &self,
+        id: &str,
+        token: &str,
+    ) -> Result<Option<ExportJob>, DbError> {
+        let t = self.tables();
+        Ok(t.export_jobs
+            .iter()
+            .find(|j| j.id == *id && j.download_token.as_deref() == Some(token))
+            .cloned())
+    }
+
+    async fn claim_next_export_job(&self) -> Result<Option<ExportJob>, DbError> {
+        let mut t = self.tables();
+        let next = t
+            .export_jobs
+            .iter_mut()
+            .filter(|j| j.status == EXPORT_STATUS_PENDING)
+            .min_by_key(|j| j.created_at);
+
+        Ok(next.map(|job| {
+            job.status = EXPORT_STATUS_RUNNING.to_string();
+            job.clone()
+        }))
+    }
+
+    async fn set_export_job_progress(
+        &self,
+        id: &str,
+        processed: i64,
+        total: i64,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if let Some(job) = t.export_jobs.iter_mut().find(|j| j.id == *id) {
+            job.rows_processed = processed;
+            job.rows_total = Some(total);
+        }
+        Ok(())
+    }
+
+    async fn finish_export_job(&self, id: &str, error: Option<&str>) -> Result<(), DbError> {
+        let status = match error {
+            None => EXPORT_STATUS_COMPLETED,
+            Some(_) => EXPORT_STATUS_FAILED,
+        };
+
+        let mut t = self.tables();
+        if let Some(job) = t.export_jobs.iter_mut().find(|j| j.id == *id) {
+            job.status = status.to_string();
+            job.error = error.map(str::to_string);
+            job.completed_at = Some(Utc::now());
+            // The passphrase, if any, is no longer needed
+            job.encrypt_to = None;
+        }
+        Ok(())
+    }
+
+    async fn requeue_running_export_jobs(&self) -> Result<u64, DbError> {
+        let mut t = self.tables();
+        let mut requeued = 0;
+        for job in t
+            .export_jobs
+            .iter_mut()
+            .filter(|j| j.status == EXPORT_STATUS_RUNNING)
+        {
+            job.status = EXPORT_STATUS_PENDING.to_string();
+            requeued += 1;
+        }
+        Ok(requeued)
+    }
+
+    async fn delete_export_jobs_finished_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<ExportJob>, DbError> {
+        let mut t = self.tables();
+        let (finished, kept): (Vec<ExportJob>, Vec<ExportJob>) = std::mem::take(&mut t.export_jobs)
+            .into_iter()
+            .partition(|j| j.completed_at.is_some_and(|at| at < before));
+        t.export_jobs = kept;
+        Ok(finished)
+    }
+
+    // Export schedules
+    async fn create_export_schedule(&self, schedule: &ExportSchedule) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if t.export_schedules.iter().any(|s| s.id == schedule.id) {
+            return Err(DbError::ConstraintViolation("UNIQUE export_schedules.id"));
+        }
+        t.ensure_user(&schedule.user_id)?;
+
+        t.export_schedules.push(schedule.clone());
+        Ok(())
+    }
+
+    async fn get_export_schedule(
+        &self,
+        id: &str,
+        user_id: &UserId,
+    ) -> Result<Option<ExportSchedule>, DbError> {
+        let t = self.tables();
+        Ok(t.export_schedules
+            .iter()
+            .find(|s| s.id == *id && s.user_id == *user_id)
+            .cloned())
+    }
+
+    async fn list_export_schedules(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<ExportSchedule>, DbError> {
+        let t = self.tables();
+        let mut schedules: Vec<ExportSchedule> = t
+            .export_schedules
+            .iter()
+            .filter(|s| s.user_id == *user_id)
+            .cloned()
+            .collect();
+        schedules.sort_by_key(|s| s.created_at);
+        Ok(schedules)
+    }
+
+    async fn delete_export_schedule(&self, id: &str, user_id: &UserId) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if remove_where(&mut t.export_schedules, |s| {
+            s.id == *id && s.user_id == *user_id
+        }) == 0
+        {
+            return Err(DbError::ExportScheduleNotFound);
+        }
+
+        // Jobs it queued are kept, without the schedule
+        for job in t
+            .export_jobs
+            .iter_mut()
+            .filter(|j| j.schedule_id.as_deref() == Some(id))
+        {
+            job.schedule_id = None;
+        }
+        Ok(())
+    }
+
+    async fn get_due_export_schedules(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<ExportSchedule>, DbError> {
+        let t = self.tables();
+        let mut schedules: Vec<ExportSchedule> = t
+            .export_schedules
+            .iter()
+            .filter(|s| s.next_run_at <= now)
+            .cloned()
+            .collect();
+        schedules.sort_by_key(|s| s.next_run_at);
+        Ok(schedules)
+    }
+
+    async fn queue_scheduled_export(
+        &self,
+        job: &ExportJob,
+        next_run_at: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        t.create_export_job(job)?;
+
+        if let Some(schedule) = t
+            .export_schedules
+            .iter_mut()
+            .find(|s| job.schedule_id.as_deref() == Some(s.id.as_str()))
+        {
+            schedule.next_run_at = next_run_at;
+        }
+        Ok(())
+    }
+
+    // WebDAV destinations
+    async fn set_webdav_destination(&self, destination: &WebDavDestination) -> Result<(), DbError> {
+        let mut t = self.tables();
+        t.ensure_user(&destination.user_id)?;
+
+        upsert(&mut t.webdav_destinations, destination.clone(), |d| {
+            d.user_id == destination.user_id
+        });
+        Ok(())
+    }
+
+    async fn get_webdav_destination(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<WebDavDestination>, DbError> {
+        let t = self.tables();
+        Ok(t.webdav_destinations
+            .iter()
+            .find(|d| d.user_id == *user_id)
+            .cloned())
+    }
+
+    async fn delete_webdav_destination(&self, user_id: &UserId) -> Result<(), DbError> {
+        let mut t = self.tables();
+        remove_where(&mut t.webdav_destinations, |d| d.user_id == *user_id);
+        Ok(())
+    }
+
+    // Digest subscriptions
+    async fn set_digest_subscription(
+        &self,
+        subscription: &DigestSubscription,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        t.ensure_user(&subscription.user_id)?;
+
+        upsert(&mut t.digest_subscriptions, subscription.clone(), |s| {
+            s.user_id == subscription.user_id
+        });
+        Ok(())
+    }
+
+    async fn get_digest_subscription(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<DigestSubscription>, DbError> {
+        let t = self.tables();
+        Ok(t.digest_subscriptions
+            .iter()
+            .find(|s| s.user_id == *user_id)
+            .cloned())
+    }
+
+    async fn delete_digest_subscription(&self, user_id: &UserId) -> Result<(), DbError> {
+        let mut t = self.tables();
+        remove_where(&mut t.digest_subscriptions, |s| s.user_id == *user_id);
+        Ok(())
+    }
+
+    async fn get_due_digest_subscriptions(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<DigestSubscription>, DbError> {
+        let t = self.tables();
+        let mut subscriptions: Vec<DigestSubscription> = t
+            .digest_subscriptions
+            .iter()
+            .filter(|s| s.next_run_at <= now)
+            .cloned()
+            .collect();
+        subscriptions.sort_by_key(|s| s.next_run_at);
+        Ok(subscriptions)
+    }
+
+    async fn set_digest_next_run(
+        &self,
+        user_id: &UserId,
+        next_run_at: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if let Some(subscription) = t
+            .digest_subscriptions
+            .iter_mut()
+            .find(|s| s.user_id == *user_id)
+        {
+            subscription.next_run_at = next_run_at;
+        }
+        Ok(())
+    }
+
+    // Reminders
+    async fn set_reminder(&self, reminder: &Reminder) -> Result<(), DbError> {
+        let mut t = self.tables();
+        t.ensure_user(&reminder.user_id)?;
+
+        upsert(&mut t.reminders, reminder.clone(), |r| {
+            r.user_id == reminder.user_id
+        });
+        Ok(())
+    }
+
+    async fn get_reminder(&self, user_id: &UserId) -> Result<Option<Reminder>, DbError> {
+        let t = self.tables();
+        Ok(t.reminders.iter().find(|r| r.user_id == *user_id).cloned())
+    }
+
+    async fn delete_reminder(&self, user_id: &UserId) -> Result<(), DbError> {
+        let mut t = self.tables();
+        remove_where(&mut t.reminders, |r| r.user_id == *user_id);
+        Ok(())
+    }
+
+    async fn get_due_reminders(&self, now: DateTime<Utc>) -> Result<Vec<Reminder>, DbError> {
+        let t = self.tables();
+        let mut reminders: Vec<Reminder> = t
+            .reminders
+            .iter()
+            .filter(|r| r.next_run_at <= now)
+            .cloned()
+            .collect();
+        reminders.sort_by_key(|r| r.next_run_at);
+        Ok(reminders)
+    }
+
+    async fn set_reminder_next_run(
+        &self,
+        user_id: &UserId,
+        next_run_at: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if let Some(reminder) = t.reminders.iter_mut().find(|r| r.user_id == *user_id) {
+            reminder.next_run_at = next_run_at;
+        }
+        Ok(())
+    }
+
+    // Notification channels
+    async fn create_notification_channel(
+        &self,
+        channel: &NotificationChannel,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if t.notification_channels.iter().any(|c| c.id == channel.id) {
+            return Err(DbError::ConstraintViolation(
+                "UNIQUE notification_channels.id",
+            ));
+        }
+        t.ensure_user(&channel.user_id)?;
+
+        t.notification_channels.push(channel.clone());
+        Ok(())
+    }
+
+    async fn get_notification_channel(
+        &self,
+        id: &str,
+        user_id: &UserId,
+    ) -> Result<Option<NotificationChannel>, DbError> {
+        let t = self.tables();
+        Ok(t.notification_channels
+            .iter()
+            .find(|c| c.id == *id && c.user_id == *user_id)
+            .cloned())
+    }
+
+    async fn list_notification_channels(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<NotificationChannel>, DbError> {
+        let t = self.tables();
+        let mut channels: Vec<NotificationChannel> = t
+            .notification_channels
+            .iter()
+            .filter(|c| c.user_id == *user_id)
+            .cloned()
+            .collect();
+        channels.sort_by_key(|c| c.created_at);
+        Ok(channels)
+    }
+
+    async fn delete_notification_channel(&self, id: &str, user_id: &UserId) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if remove_where(&mut t.notification_channels, |c| {
+            c.id == *id && c.user_id == *user_id
+        }) == 0
+        {
+            return Err(DbError::NotificationChannelNotFound);
+        }
+        Ok(())
+    }
+
+    // Telegram
+    async fn create_telegram_link_code(&self, link_code: &TelegramLinkCode) -> Result<(), DbError> {
+        let mut t = self.tables();
+        let taken = t
+            .telegram_link_codes
+            .iter()
+            .any(|c| c.code == link_code.code && c.user_id != link_code.user_id);
+        if taken {
+            return Err(DbError::ConstraintViolation(
+                "UNIQUE telegram_link_codes.code",
+            ));
+        }
+        t.ensure_user(&link_code.user_id)?;
+
+        upsert(&mut t.telegram_link_codes, link_code.clone(), |c| {
+            c.user_id == link_code.user_id
+        });
+        Ok(())
+    }
+
+    async fn link_telegram_chat(
+        &self,
+        code: &str,
+        chat_id: i64,
+        now: DateTime<Utc>,
+    ) -> Result<Option<UserId>, DbError> {
+        let mut t = self.tables();
+        let Some(index) = t
+            .telegram_link_codes
+            .iter()
+            .position(|c| c.code == code && c.expires_at > now)
+        else {
+            return Ok(None);
+        };
+        let user_id = t.telegram_link_codes.remove(index).user_id;
+
+        remove_where(&mut t.telegram_links, |l| {
+            l.chat_id == chat_id || l.user_id == user_id
+        });
+        t.telegram_links.push(TelegramLink {
+            user_id: user_id.clone(),
+            chat_id,
+            created_at: now,
+        });
+        Ok(Some(user_id))
+    }
+
+    async fn get_telegram_link(&self, user_id: &UserId) -> Result<Option<TelegramLink>, DbError> {
+        let t = self.tables();
+        Ok(t.telegram_links
+            .iter()
+            .find(|l| l.user_id == *user_id)
+            .cloned())
+    }
+
+    async fn get_telegram_link_by_chat(
+        &self,
+        chat_id: i64,
+    ) -> Result<Option<TelegramLink>, DbError> {
+        let t = self.tables();
+        Ok(t.telegram_links
+            .iter()
+            .find(|l| l.chat_id == chat_id)
+            .cloned())
+    }
+
+    async fn delete_telegram_link(&self, user_id: &UserId) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if remove_where(&mut t.telegram_links, |l| l.user_id == *user_id) == 0 {
+            return Err(DbError::TelegramNotLinked);
+        }
+        Ok(())
+    }
+
+    async fn delete_expired_telegram_link_codes(&self, now: DateTime<Utc>) -> Result<u64, DbError> {
+        let mut t = self.tables();
+        Ok(remove_where(&mut t.telegram_link_codes, |c| {
+            c.expires_at <= now
+        }))
+    }
+
+    // Inbound email
+    async fn save_inbound_email_address(
+        &self,
+        address: &InboundEmailAddress,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        let taken = t
+            .inbound_email_addresses
+            .iter()
+            .any(|a| a.token == address.token && a.user_id != address.user_id);
+        if taken {
+            return Err(DbError::ConstraintViolation(
+                "UNIQUE inbound_email_addresses.token",
+            ));
+        }
+        t.ensure_user(&address.user_id)?;
+
+        match t
+            .inbound_email_addresses
+            .iter_mut()
+            .find(|a| a.user_id == address.user_id)
+        {
+            Some(existing) => {
+                existing.token.clone_from(&address.token);
+                existing
+                    .allowed_senders
+                    .clone_from(&address.allowed_senders);
+            }
+            None => t.inbound_email_addresses.push(address.clone()),
+        }
+        Ok(())
+    }
+
+    async fn get_inbound_email_address(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<InboundEmailAddress>, DbError> {
+        let t = self.tables();
+        Ok(t.inbound_email_addresses
+            .iter()
+            .find(|a| a.user_id == *user_id)
+            .cloned())
+    }
+
+    async fn get_inbound_email_address_by_token(
+        &self,
+        token: &str,
+    ) -> Result<Option<InboundEmailAddress>, DbError> {
+        let t = self.tables();
+        Ok(t.inbound_email_addresses
+            .iter()
+            .find(|a| a.token == token)
+            .cloned())
+    }
+
+    async fn delete_inbound_email_address(&self, user_id: &UserId) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if remove_where(&mut t.inbound_email_addresses, |a| a.user_id == *user_id) == 0 {
+            return Err(DbError::InboundEmailAddressNotFound);
+        }
+        Ok(())
+    }
+
+    // Matrix
+    async fn set_matrix_account(&self, account: &MatrixAccount) -> Result<(), DbError> {
+        let mut t = self.tables();
+        t.ensure_user(&account.user_id)?;
+
+        upsert(&mut t.matrix_accounts, account.clone(), |a| {
+            a.user_id == account.user_id
+        });
+        Ok(())
+    }
+
+    async fn get_matrix_account(&self, user_id: &UserId) -> Result<Option<MatrixAccount>, DbError> {
+        let t = self.tables();
+        Ok(t.matrix_accounts
+            .iter()
+            .find(|a| a.user_id == *user_id)
+            .cloned())
+    }
+
+    async fn list_matrix_accounts(&self) -> Result<Vec<MatrixAccount>, DbError> {
+        Ok(self.tables().matrix_accounts.clone())
+    }
+
+    async fn set_matrix_sync_token(
+        &self,
+        user_id: &UserId,
+        sync_token: &str,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if let Some(account) = t.matrix_accounts.iter_mut().find(|a| a.user_id == *user_id) {
+            account.sync_token = Some(sync_token.to_string());
+        }
+        Ok(())
+    }
+
+    async fn delete_matrix_account(&self, user_id: &UserId) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if remove_where(&mut t.matrix_accounts, |a| a.user_id == *user_id) == 0 {
+            return Err(DbError::MatrixAccountNotFound);
+        }
+        Ok(())
+    }
+
+    // Dormancy
+    async fn set_dormancy_policy(&self, policy: &DormancyPolicy) -> Result<(), DbError> {
+        let mut t = self.tables();
+        t.ensure_user(&policy.user_id)?;
+
+        upsert(&mut t.dormancy_policies, policy.clone(), |p| {
+            p.user_id == policy.user_id
+        });
+        Ok(())
+    }
+
+    async fn get_dormancy_policy(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<DormancyPolicy>, DbError> {
+        let t = self.tables();
+        Ok(t.dormancy_policies
+            .iter()
+            .find(|p| p.user_id == *user_id)
+            .cloned())
+    }
+
+    async fn list_pending_dormancy_policies(&self) -> Result<Vec<DormancyPolicy>, DbError> {
+        let t = self.tables();
+        Ok(t.dormancy_policies
+            .iter()
+            .filter(|p| p.triggered_at.is_none())
+            .cloned()
+            .collect())
+    }
+
+    async fn record_user_activity(
+        &self,
+        user_id: &UserId,
+        at: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if let Some(policy) = t
+            .dormancy_policies
+            .iter_mut()
+            .find(|p| p.user_id == *user_id)
+        {
+            policy.last_active_at = at;
+            policy.warnings_sent = 0;
+            policy.last_warning_at = None;
+            policy.triggered_at = None;
+        }
+        Ok(())
+    }
+
+    async fn record_dormancy_warning(
+        &self,
+        user_id: &UserId,
+        warnings_sent: i64,
+        at: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if let Some(policy) = t
+            .dormancy_policies
+            .iter_mut()
+            .find(|p| p.user_id == *user_id)
+        {
+            policy.warnings_sent = warnings_sent;
+            policy.last_warning_at = Some(at);
+        }
+        Ok(())
+    }
+
+    async fn mark_dormancy_triggered(
+        &self,
+        user_id: &UserId,
+        last_active_at: DateTime<Utc>,
+        at: DateTime<Utc>,
+    ) -> Result<bool, DbError> {
+        let mut t = self.tables();
+        let policy = t.dormancy_policies.iter_mut().find(|p| {
+            p.user_id == *user_id && p.last_active_at == last_active_at && p.triggered_at.is_none()
+        });
+
+        Ok(match policy {
+            Some(policy) => {
+                policy.triggered_at = Some(at);
+                true
+            }
+            None => false,
+        })
+    }
+
+    async fn delete_dormancy_policy(&self, user_id: &UserId) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if remove_where(&mut t.dormancy_policies, |p| p.user_id == *user_id) == 0 {
+            return Err(DbError::DormancyPolicyNotFound);
+        }
+        Ok(())
+    }
+
+    // Avatars
+    async fn set_user_avatar(&self, avatar: &Avatar) -> Result<(), DbError> {
+        let mut t = self.tables();
+        t.ensure_user(&avatar.user_id)?;
+
+        upsert(&mut t.user_avatars, avatar.clone(), |a| {
+            a.user_id == avatar.user_id
+        });
+        Ok(())
+    }
+
+    async fn get_user_avatar(&self, user_id: &UserId) -> Result<Option<Avatar>, DbError> {
+        let t = self.tables();
+        Ok(t.user_avatars
+            .iter()
+            .find(|a| a.user_id == *user_id)
+            .cloned())
+    }
+
+    // Webhooks
+    async fn create_webhook(&self, webhook: &Webhook) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if t.webhooks.iter().any(|w| w.id == webhook.id) {
+            return Err(DbError::ConstraintViolation("UNIQUE webhooks.id"));
+        }
+        t.ensure_user(&webhook.user_id)?;
+
+        t.webhooks.push(webhook.clone());
+        Ok(())
+    }
+
+    async fn get_webhook(&self, id: &str, user_id: &UserId) -> Result<Option<Webhook>, DbError> {
+        let t = self.tables();
+        Ok(t.webhooks
+            .iter()
+            .find(|w| w.id == *id && w.user_id == *user_id)
+            .cloned())
+    }
+
+    async fn list_webhooks(&self, user_id: &UserId) -> Result<Vec<Webhook>, DbError> {
+        let t = self.tables();
+        let mut webhooks: Vec<Webhook> = t
+            .webhooks
+            .iter()
+            .filter(|w| w.user_id == *user_id)
+            .cloned()
+            .collect();
+        webhooks.sort_by_key(|w| w.created_at);
+        Ok(webhooks)
+    }
+
+    async fn delete_webhook(&self, id: &str, user_id: &UserId) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if remove_where(&mut t.webhooks, |w| w.id == *id && w.user_id == *user_id) == 0 {
+            return Err(DbError::WebhookNotFound);
+        }
+        remove_where(&mut t.webhook_deliveries, |d| d.webhook_id == id);
+        Ok(())
+    }
+
+    async fn queue_webhook_deliveries(
+        &self,
+        user_id: &UserId,
+        event: &str,
+        payload: &str,
+    ) -> Result<u64, DbError> {
+        let mut t = self.tables();
+        let now = Utc::now();
+
+        let webhook_ids: Vec<String> = t
+            .webhooks
+            .iter()
+            .filter(|w| w.user_id == *user_id)
+            .map(|w| w.id.clone())
+            .collect();
+        for webhook_id in &webhook_ids {
+            t.webhook_deliveries.push(WebhookDelivery {
+                id: uuid::Uuid::new_v4().to_string(),
+                webhook_id: webhook_id.clone(),
+                event: event.to_string(),
+                payload: payload.to_string(),
+                status: WEBHOOK_DELIVERY_PENDING.to_string(),
+                attempts: 0,
+                next_attempt_at: Some(now),
+                response_status: None,
+                error: None,
+                created_at: now,
+                updated_at: now,
+            });
+        }
+
+        Ok(webhook_ids.len() as u64)
+    }
+
+    async fn get_due_webhook_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<DueWebhookDelivery>, DbError> {
+        let t = self.tables();
+        let mut due: Vec<(DateTime<Utc>, DueWebhookDelivery)> = t
+            .webhook_deliveries
+            .iter()
+            .filter(|d| d.status == WEBHOOK_DELIVERY_PENDING)
+            .filter_map(|d| {
+                let next_attempt_at = d.next_attempt_at.filter(|at| *at <= now)?;
+                let webhook = t.webhooks.iter().find(|w| w.id == d.webhook_id)?;
+                Some((
+                    next_attempt_at,
+                    DueWebhookDelivery {
+                        id: d.id.clone(),
+                        event: d.event.clone(),
+                        payload: d.payload.clone(),
+                        attempts: d.attempts,
+                        url: webhook.url.clone(),
+                        secret: webhook.secret.clone(),
+                    },
+                ))
+            })
+            .collect();
+        due.sort_by_key(|(next_attempt_at, _)| *next_attempt_at);
+
+        Ok(due
+            .into_iter()
+            .take(row_limit(limit))
+            .map(|(_, delivery)| delivery)
+            .collect())
+    }
+
+    async fn record_webhook_attempt(
+        &self,
+        id: &str,
+        status: &str,
+        response_status: Option<i64>,
+        error: Option<&str>,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if let Some(delivery) = t.webhook_deliveries.iter_mut().find(|d| d.id == *id) {
+            delivery.status = status.to_string();
+            delivery.attempts += 1;
+            delivery.response_status = response_status;
+            delivery.error = error.map(str::to_string);
+            delivery.next_attempt_at = next_attempt_at;
+            delivery.updated_at = Utc::now();
+        }
+        Ok(())
+    }
+
+    async fn list_webhook_deliveries(
+        &self,
+        webhook_id: &str,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, DbError> {
+        let t = self.tables();
+        let mut deliveries: Vec<WebhookDelivery> = t
+            .webhook_deliveries
+            .iter()
+            .filter(|d| d.webhook_id == webhook_id)
+            .cloned()
+            .collect();
+        deliveries.sort_by(|a, b| {
+            b.created_at
+                .cmp(&a.created_at)
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        deliveries.truncate(row_limit(limit));
+        Ok(deliveries)
+    }
+
+    async fn delete_webhook_deliveries_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<u64, DbError> {
+        let mut t = self.tables();
+        Ok(remove_where(&mut t.webhook_deliveries, |d| {
+            d.created_at < before
+        }))
+    }
+
+    // Idempotency keys
+    async fn get_idempotent_response(
+        &self,
+        user_id: &UserId,
+        key: &str,
+        not_before: &str,
+    ) -> Result<Option<IdempotentResponse>, DbError> {
+        let t = self.tables();
+        Ok(t.idempotency_keys
+            .iter()
+            .find(|k| k.user_id == *user_id && k.key == key && k.created_at.as_str() >= not_before)
+            .map(|k| k.response.clone()))
+    }
+
+    async fn save_idempotent_response(
+        &self,
+        user_id: &UserId,
+        key: &str,
+        response: &IdempotentResponse,
+    ) -> Result<(), DbError> {
+        let mut t = self.tables();
+        t.ensure_user(user_id)?;
+
+        remove_where(&mut t.idempotency_keys, |k| {
+            k.user_id == *user_id && k.key == key
+        });
+        t.idempotency_keys.push(IdempotencyKey {
+            user_id: user_id.clone(),
+            key: key.to_string(),
+            response: response.clone(),
+            created_at: Utc::now().to_rfc3339(),
+        });
+        Ok(())
+    }
+
+    async fn delete_idempotent_responses_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<u64, DbError> {
+        let before = before.to_rfc3339();
+        let mut t = self.tables();
+        Ok(remove_where(&mut t.idempotency_keys, |k| {
+            k.created_at < before
+        }))
+    }
+
+    // Feature flags
+    async fn list_feature_flags(&self) -> Result<Vec<(String, bool)>, DbError> {
+        let t = self.tables();
+        Ok(t.feature_flags
+            .iter()
+            .map(|(name, enabled)| (name.clone(), *enabled))
+            .collect())
+    }
+
+    async fn set_feature_flag(&self, name: &str, enabled: bool) -> Result<(), DbError> {
+        self.tables()
+            .feature_flags
+            .insert(name.to_string(), enabled);
+        Ok(())
+    }
+
+    async fn delete_feature_flag(&self, name: &str) -> Result<(), DbError> {
+        self.tables().feature_flags.remove(name);
+        Ok(())
+    }
+
+    // Announcements
+    async fn create_announcement(&self, announcement: &Announcement) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if t.announcements.iter().any(|a| a.id == announcement.id) {
+            return Err(DbError::ConstraintViolation("UNIQUE announcements.id"));
+        }
+
+        t.announcements.push(announcement.clone());
+        Ok(())
+    }
+
+    async fn update_announcement(
+        &self,
+        id: &str,
+        message: &str,
+        level: &str,
+        starts_at: Option<DateTime<Utc>>,
+        ends_at: Option<DateTime<Utc>>,
+    ) -> Result<Announcement, DbError> {
+        let mut t = self.tables();
+        let announcement = t
+            .announcements
+            .iter_mut()
+            .find(|a| a.id == *id)
+            .ok_or(DbError::AnnouncementNotFound)?;
+
+        announcement.message = message.to_string();
+        announcement.level = level.to_string();
+        announcement.starts_at = starts_at;
+        announcement.ends_at = ends_at;
+        announcement.updated_at = Utc::now();
+        Ok(announcement.clone())
+    }
+
+    async fn delete_announcement(&self, id: &str) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if remove_where(&mut t.announcements, |a| a.id == *id) == 0 {
+            return Err(DbError::AnnouncementNotFound);
+        }
+        remove_where(&mut t.announcement_dismissals, |(announcement_id, _)| {
+            announcement_id == id
+        });
+        Ok(())
+    }
+
+    async fn list_announcements(&self) -> Result<Vec<Announcement>, DbError> {
+        let mut announcements = self.tables().announcements.clone();
+        announcements.sort_by_key(|announcement| Reverse(announcement.created_at));
+        Ok(announcements)
+    }
+
+    async fn list_active_announcements(
+        &self,
+        now: DateTime<Utc>,
+        user_id: Option<&UserId>,
+    ) -> Result<Vec<Announcement>, DbError> {
+        let t = self.tables();
+        let dismissed = |id: &str| {
+            user_id.is_some_and(|user_id| {
+                t.announcement_dismissals
+                    .iter()
+                    .any(|(announcement_id, dismissed_by)| {
+                        announcement_id == id && dismissed_by == user_id
+                    })
+            })
+        };
+
+        let mut announcements: Vec<Announcement> = t
+            .announcements
+            .iter()
+            .filter(|a| a.starts_at.is_none_or(|starts_at| starts_at <= now))
+            .filter(|a| a.ends_at.is_none_or(|ends_at| ends_at > now))
+            .filter(|a| !dismissed(&a.id))
+            .cloned()
+            .collect();
+        announcements.sort_by_key(|announcement| Reverse(announcement.created_at));
+        Ok(announcements)
+    }
+
+    async fn dismiss_announcement(&self, id: &str, user_id: &UserId) -> Result<(), DbError> {
+        let mut t = self.tables();
+        if !t.announcements.iter().any(|a| a.id == *id) {
+            return Err(DbError::AnnouncementNotFound);
+        }
+        t.ensure_user(user_id)?;
+
+        let dismissal = (id.to_string(), user_id.clone());
+        if !t.announcement_dismissals.contains(&dismissal) {
+            t.announcement_dismissals.push(dismissal);
+        }
+        Ok(())
+    }
+
+    // Audit log
+    async fn record_audit_event(&self, event: &AuditEvent) -> Result<(), DbError> {
+        let mut t = self.tables();
+        let mut event = event.clone();
+        event.id = t.audit_events.last().map_or(0, |last| last.id) + 1;
+        t.audit_events.push(event);
+        Ok(())
+    }
+
+    async fn list_audit_events(
+        &self,
+        filter: &AuditFilter,
+        limit: i64,
+    ) -> Result<Vec<AuditEvent>, DbError> {
+        let action_prefix = filter.action.as_ref().map(|action| format!("{}.", action));
+
+        let t = self.tables();
+        Ok(t.audit_events
+            .iter()
+            .rev()
+            .filter(|e| {
+                filter.action.as_ref().is_none_or(|action| {
+                    e.action == *action
+                        || action_prefix
+                            .as_ref()
+                            .is_some_and(|prefix| e.action.starts_with(prefix.as_str()))
+                })
+            })
+            .filter(|e| filter.actor.as_ref().is_none_or(|actor| e.actor == *actor))
+            .filter(|e| {
+                filter
+                    .target_id
+                    .as_ref()
+                    .is_none_or(|target_id| e.target_id.as_ref() == Some(target_id))
+            })
+            .filter(|e| filter.since.is_none_or(|since| e.created_at >= since))
+            .filter(|e| filter.until.is_none_or(|until| e.created_at < until))
+            .filter(|e| filter.before_id.is_none_or(|before_id| e.id < before_id))
+            .take(row_limit(limit))
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SharedStorage;
+    use std::sync::Arc;
+
+    async fn setup() -> (SharedStorage, User) {
+        let storage: SharedStorage = Arc::new(MemoryStorage::new());
+        let user = User::new(
+            "memory@example.com".to_string(),
+            "memory".to_string(),
+            "hash".to_string(),
+            "salt".to_string(),
+        );
+        storage.create_user(&user).await.unwrap();
+        (storage, user)
+    }
+
+    #[tokio::test]
+    async fn test_sequence_numbers_track_changes() {
+        let (storage, user) = setup().await;
+
+        let message = storage
+            .create_message(&Message::new(user.id.clone(), "First".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(message.seq, 1);
+
+        let edited = storage
+            .update_message(
+                &message.id,
+                &user.id,
+                "Edited",
+                None,
+                ConflictPolicy::LastWriterWins,
+            )
+            .await
+            .unwrap();
+        assert_eq!(edited.seq, 2);
+
+        storage.delete_message(&message.id, &user.id).await.unwrap();
+        assert_eq!(storage.get_change_seq(&user.id).await.unwrap(), 3);
+        assert!(storage
+            .get_messages_since_seq(&user.id, 0)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            storage
+                .get_deleted_message_ids_since_seq(&user.id, 2)
+                .await
+                .unwrap(),
+            vec![message.id]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stale_edit_is_saved_as_conflict_copy() {
+        let (storage, user) = setup().await;
+        let message = storage
+            .create_message(&Message::new(user.id.clone(), "Original".to_string()))
+            .await
+            .unwrap();
+        let base = message.updated_at;
+        storage
+            .update_message(
+                &message.id,
+                &user.id,
+                "From laptop",
+                Some(base),
+                ConflictPolicy::ConflictCopy,
+            )
+            .await
+            .unwrap();
+
+        let copy = storage
+            .update_message(
+                &message.id,
+                &user.id,
+                "From phone",
+                Some(base),
+                ConflictPolicy::ConflictCopy,
+            )
+            .await
+            .unwrap();
+
+        assert_ne!(copy.id, message.id);
+        assert_eq!(copy.conflict_of, Some(message.id.clone()));
+        let stored = storage
+            .get_message_by_id(&message.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.content, "From laptop");
+    }
+
+    #[tokio::test]
+    async fn test_apply_sync_batch_rolls_back_on_conflict() {
+        let (storage, user) = setup().await;
+        let other = User::new(
+            "other@example.com".to_string(),
+            "other".to_string(),
+            "hash".to_string(),
+            "salt".to_string(),
+        );
+        storage.create_user(&other).await.unwrap();
+        let taken = Message::with_id(MessageId::generate(), other.id, "Theirs".to_string());
+        storage.create_message(&taken).await.unwrap();
+
+        let batch = SyncBatch {
+            creates: vec![
+                Message::new(user.id.clone(), "First".to_string()),
+                Message::with_id(taken.id.clone(), user.id.clone(), "Mine".to_string()),
+            ],
+            ..Default::default()
+        };
+        let result = storage
+            .apply_sync_batch(&user.id, batch, ConflictPolicy::LastWriterWins)
+            .await;
+
+        assert!(matches!(result, Err(DbError::MessageIdConflict)));
+        assert!(storage
+            .get_messages_for_user(&user.id, None)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(storage.get_change_seq(&user.id).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_deleting_user_removes_what_references_them() {
+        let (storage, user) = setup().await;
+        let message = storage
+            .create_message(&Message::new(user.id.clone(), "Mine".to_string()))
+            .await
+            .unwrap();
+        storage
+            .add_reaction(&message.id, &user.id, "👍")
+            .await
+            .unwrap();
+        storage
+            .create_template(&Template::new(
+                user.id.clone(),
+                "Daily".to_string(),
+                "Today I...".to_string(),
+            ))
+            .await
+            .unwrap();
+
+        storage.delete_user(&user.id).await.unwrap();
+
+        let report = storage.check_integrity().await.unwrap();
+        assert!(report.is_ok());
+        assert!(report.row_counts.values().all(|count| *count == 0));
+        assert!(matches!(
+            storage.delete_user(&user.id).await,
+            Err(DbError::UserNotFound)
+        ));
+    }
+}
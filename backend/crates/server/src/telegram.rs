@@ -0,0 +1,136 @@
+use axum::{extract::State, http::StatusCode, Json};
+
+use crate::{
+    db::DbError,
+    error::AppError,
+    handlers::{storage_error, SharedState},
+    models::{SuccessResponse, TelegramLinkCode, TelegramLinkCodeResponse, TelegramLinkResponse},
+    AuthUser,
+};
+
+#[cfg(feature = "telegram")]
+mod bot;
+
+#[cfg(feature = "telegram")]
+pub use bot::{spawn_worker, TelegramBot};
+
+/// Stands in for the bot in builds without the `telegram` feature, where it
+/// can never be configured
+#[cfg(not(feature = "telegram"))]
+pub enum TelegramBot {}
+
+#[cfg(not(feature = "telegram"))]
+impl TelegramBot {
+    pub fn from_config(config: &crate::config::TelegramConfig) -> Result<Option<Self>, String> {
+        if config.bot_token.is_some() {
+            return Err(
+                "The Telegram bot (TELEGRAM_BOT_TOKEN) requires building with the `telegram` feature"
+                    .to_string(),
+            );
+        }
+        Ok(None)
+    }
+
+    pub fn link_url(&self, _code: &str) -> Option<String> {
+        match *self {}
+    }
+}
+
+// ============ Handlers ============
+
+/// GET /api/user/telegram
+/// Whether the authenticated user has linked a Telegram chat
+#[utoipa::path(
+    get,
+    path = "/api/user/telegram",
+    tag = "user",
+    responses(
+        (status = 200, description = "Whether a Telegram chat is linked", body = TelegramLinkResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_link(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<TelegramLinkResponse>, AppError> {
+    let link = state
+        .storage
+        .get_telegram_link(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    Ok(Json(TelegramLinkResponse {
+        linked: link.is_some(),
+        linked_at: link.map(|link| link.created_at.to_rfc3339()),
+    }))
+}
+
+/// POST /api/user/telegram/code
+/// Issue a one-time code that links the chat it is sent from
+#[utoipa::path(
+    post,
+    path = "/api/user/telegram/code",
+    tag = "user",
+    responses(
+        (status = 201, description = "Code to send the bot", body = TelegramLinkCodeResponse),
+        (status = 404, description = "Telegram is not configured", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn create_link_code(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<(StatusCode, Json<TelegramLinkCodeResponse>), AppError> {
+    let Some(bot) = &state.telegram else {
+        return Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            "Telegram is not configured",
+        ));
+    };
+
+    let link_code = TelegramLinkCode::new(user_id);
+    state
+        .storage
+        .create_telegram_link_code(&link_code)
+        .await
+        .map_err(|e| storage_error(e, "Failed to create link code"))?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(TelegramLinkCodeResponse {
+            url: bot.link_url(&link_code.code),
+            code: link_code.code,
+            expires_at: link_code.expires_at.to_rfc3339(),
+        }),
+    ))
+}
+
+/// DELETE /api/user/telegram
+/// Stop saving messages from the user's Telegram chat
+#[utoipa::path(
+    delete,
+    path = "/api/user/telegram",
+    tag = "user",
+    responses(
+        (status = 200, description = "Telegram chat unlinked", body = SuccessResponse),
+        (status = 404, description = "Telegram is not linked", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn unlink(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<SuccessResponse>, AppError> {
+    state
+        .storage
+        .delete_telegram_link(&user_id)
+        .await
+        .map_err(|e| match e {
+            DbError::TelegramNotLinked => {
+                AppError::new(StatusCode::NOT_FOUND, "Telegram is not linked")
+            }
+            _ => storage_error(e, "Failed to unlink Telegram"),
+        })?;
+
+    Ok(Json(SuccessResponse::new()))
+}
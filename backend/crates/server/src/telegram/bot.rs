@@ -0,0 +1,440 @@
+use std::{
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::TelegramConfig,
+    handlers::{self, SharedState},
+    models::CreateMessageRequest,
+};
+
+const DEFAULT_API_URL: &str = "https://api.telegram.org";
+
+/// How long each `getUpdates` call waits for new messages
+const POLL_TIMEOUT_SECS: u64 = 30;
+
+/// Pause after a failed poll, so an outage isn't hammered
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Sent to chats that aren't linked to a journal yet
+const LINK_INSTRUCTIONS: &str = "This chat isn't linked to a journal yet. \
+     Get a code from Dissipate's Telegram settings and send it here as /start CODE.";
+
+/// Bot that saves the messages users send it as entries in their journal
+///
+/// Updates are fetched by long polling, so the server doesn't need to be
+/// reachable from Telegram.
+pub struct TelegramBot {
+    client: reqwest::Client,
+    /// Bot API URL including the token, e.g. `https://api.telegram.org/bot123:abc`
+    base_url: String,
+    /// The bot's `@username`, once Telegram has told us
+    username: OnceLock<String>,
+}
+
+/// Bot API envelope around every result
+#[derive(Debug, Deserialize)]
+struct ApiResponse<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<TelegramMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TelegramMessage {
+    message_id: i64,
+    chat: Chat,
+    /// Unix time the message was sent
+    date: i64,
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Chat {
+    id: i64,
+    /// `private`, `group`, `supergroup` or `channel`
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct BotUser {
+    username: Option<String>,
+}
+
+impl TelegramBot {
+    /// Build from the `[telegram]` settings; the bot is off unless a token is set
+    pub fn from_config(config: &TelegramConfig) -> Result<Option<Self>, String> {
+        let Some(token) = &config.bot_token else {
+            return Ok(None);
+        };
+        let api_url = config.api_url.as_deref().unwrap_or(DEFAULT_API_URL);
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(POLL_TIMEOUT_SECS + 10))
+            .build()
+            .map_err(|e| format!("Failed to build Telegram client: {}", e))?;
+
+        Ok(Some(Self {
+            client,
+            base_url: format!("{}/bot{}", api_url.trim_end_matches('/'), token),
+            username: OnceLock::new(),
+        }))
+    }
+
+    /// Link that opens a chat with the bot and sends it `/start <code>`
+    pub fn link_url(&self, code: &str) -> Option<String> {
+        self.username
+            .get()
+            .map(|username| format!("https://t.me/{}?start={}", username, code))
+    }
+
+    /// Call a Bot API method with JSON parameters
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<T, String> {
+        let body = self
+            .client
+            .post(format!("{}/{}", self.base_url, method))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(params.to_string())
+            .send()
+            .await
+            .map_err(|e| format!("Failed to call {}: {}", method, e.without_url()))?
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read {} response: {}", method, e.without_url()))?;
+
+        let response: ApiResponse<T> = serde_json::from_str(&body)
+            .map_err(|e| format!("Invalid {} response: {}", method, e))?;
+        match response.result {
+            Some(result) if response.ok => Ok(result),
+            _ => Err(format!(
+                "{} failed: {}",
+                method,
+                response.description.unwrap_or_default()
+            )),
+        }
+    }
+
+    async fn load_username(&self) -> Result<(), String> {
+        let me: BotUser = self.call("getMe", json!({})).await?;
+        if let Some(username) = me.username {
+            let _ = self.username.set(username);
+        }
+        Ok(())
+    }
+
+    async fn send_message(&self, chat_id: i64, text: &str) -> Result<(), String> {
+        self.call::<serde_json::Value>("sendMessage", json!({ "chat_id": chat_id, "text": text }))
+            .await?;
+        Ok(())
+    }
+}
+
+/// Save messages sent to the bot until shutdown
+pub fn spawn_worker(
+    state: SharedState,
+    bot: Arc<TelegramBot>,
+    shutdown: CancellationToken,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut offset = 0;
+        loop {
+            let polled = tokio::select! {
+                polled = poll(&state, &bot, offset) => polled,
+                _ = shutdown.cancelled() => break,
+            };
+
+            match polled {
+                Ok(next_offset) => offset = next_offset,
+                Err(e) => {
+                    tracing::error!("Failed to fetch Telegram updates: {}", e);
+                    tokio::select! {
+                        _ = tokio::time::sleep(RETRY_DELAY) => {}
+                        _ = shutdown.cancelled() => break,
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Wait for messages after `offset`, answer each, and return the offset to
+/// poll from next
+async fn poll(state: &SharedState, bot: &TelegramBot, offset: i64) -> Result<i64, String> {
+    if bot.username.get().is_none() {
+        bot.load_username().await?;
+    }
+
+    // Asking from an offset confirms every update before it, so Telegram
+    // only sends an update again if the server stopped before answering it
+    let updates: Vec<Update> = bot
+        .call(
+            "getUpdates",
+            json!({
+                "offset": offset,
+                "timeout": POLL_TIMEOUT_SECS,
+                "allowed_updates": ["message"],
+            }),
+        )
+        .await?;
+
+    let mut next_offset = offset;
+    for update in updates {
+        next_offset = next_offset.max(update.update_id + 1);
+        let Some(message) = update.message else {
+            continue;
+        };
+        // Only one-to-one chats, so nobody else's messages end up in a journal
+        if message.chat.kind != "private" {
+            continue;
+        }
+
+        let reply = reply_to(state, &message).await;
+        if let Err(e) = bot.send_message(message.chat.id, &reply).await {
+            tracing::warn!("Failed to reply to Telegram chat: {}", e);
+        }
+    }
+
+    Ok(next_offset)
+}
+
+/// Act on a message to the bot: link or unlink the chat, or save the
+/// message to the linked journal. Returns the bot's answer.
+async fn reply_to(state: &SharedState, message: &TelegramMessage) -> String {
+    let chat_id = message.chat.id;
+    let Some(text) = message.text.as_deref().map(str::trim) else {
+        return "Only text messages can be saved for now.".to_string();
+    };
+
+    if let Some(code) = command_argument(text, "/start") {
+        if code.is_empty() {
+            return LINK_INSTRUCTIONS.to_string();
+        }
+        return match state
+            .storage
+            .link_telegram_chat(&code.to_uppercase(), chat_id, Utc::now())
+            .await
+        {
+            Ok(Some(user_id)) => {
+                tracing::info!("User {} linked a Telegram chat", user_id);
+                "Linked! Anything you send here is saved to your journal.".to_string()
+            }
+            Ok(None) => {
+                "That code is wrong or has expired. Get a new one and try again.".to_string()
+            }
+            Err(e) => {
+                tracing::error!("Failed to link Telegram chat: {}", e);
+                "Something went wrong, please try again.".to_string()
+            }
+        };
+    }
+
+    let link = match state.storage.get_telegram_link_by_chat(chat_id).await {
+        Ok(Some(link)) => link,
+        Ok(None) => return LINK_INSTRUCTIONS.to_string(),
+        Err(e) => {
+            tracing::error!("Failed to look up Telegram chat: {}", e);
+            return "Something went wrong, please try again.".to_string();
+        }
+    };
+
+    if command_argument(text, "/unlink").is_some() {
+        return match state.storage.delete_telegram_link(&link.user_id).await {
+            Ok(()) => "Unlinked. Messages here are no longer saved.".to_string(),
+            Err(e) => {
+                tracing::error!("Failed to unlink Telegram chat: {}", e);
+                "Something went wrong, please try again.".to_string()
+            }
+        };
+    }
+
+    let created_at = DateTime::<Utc>::from_timestamp(message.date, 0).map(|t| t.to_rfc3339());
+    let request = CreateMessageRequest {
+        content: text.to_string(),
+        // A message Telegram sends again is the same entry, not a new one
+        id: Some(handlers::derived_message_id(&format!(
+            "telegram:{}:{}",
+            chat_id, message.message_id
+        ))),
+        template_id: None,
+        created_at,
+        publish_at: None,
+    };
+    match handlers::save_message(state, link.user_id, request).await {
+        Ok(_) => "Saved.".to_string(),
+        Err(e) => format!("Couldn't save that: {}", e.into_parts().1.error),
+    }
+}
+
+/// The text after `command` (or `command@bot`), or `None` for other messages
+fn command_argument<'a>(text: &'a str, command: &str) -> Option<&'a str> {
+    let (first, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let name = first.split('@').next().unwrap_or(first);
+    (name == command).then(|| rest.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        handlers::AppState,
+        models::{Message, User},
+        telegram::{create_link_code, get_link, unlink},
+        testing::{auth_user, test_app_state},
+    };
+    use axum::{extract::State, http::StatusCode, Json};
+    use serde_json::Value;
+    use std::sync::Mutex;
+
+    type Queue = Arc<Mutex<Vec<Value>>>;
+
+    /// Minimal Bot API server handing out queued updates once and recording
+    /// each reply
+    async fn spawn_bot_api(updates: Queue, replies: Queue) -> String {
+        let app = axum::Router::new().fallback(move |uri: axum::http::Uri, body: String| {
+            let updates = updates.clone();
+            let replies = replies.clone();
+            async move {
+                let result = match uri.path().rsplit('/').next().unwrap() {
+                    "getMe" => json!({ "id": 1, "username": "dissipate_bot" }),
+                    "getUpdates" => Value::Array(std::mem::take(&mut *updates.lock().unwrap())),
+                    _ => {
+                        replies
+                            .lock()
+                            .unwrap()
+                            .push(serde_json::from_str(&body).unwrap());
+                        json!({})
+                    }
+                };
+                Json(json!({ "ok": true, "result": result }))
+            }
+        });
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    async fn setup_test_state(api_url: String) -> SharedState {
+        let bot = TelegramBot::from_config(&TelegramConfig {
+            bot_token: Some("123456:test".to_string()),
+            api_url: Some(api_url),
+        })
+        .unwrap();
+
+        Arc::new(AppState {
+            telegram: bot.map(Arc::new),
+            ..test_app_state().await
+        })
+    }
+
+    fn update(update_id: i64, chat_type: &str, text: &str) -> Value {
+        json!({
+            "update_id": update_id,
+            "message": {
+                "message_id": update_id,
+                "chat": { "id": 42, "type": chat_type },
+                "date": 1_700_000_000,
+                "text": text,
+            },
+        })
+    }
+
+    #[tokio::test]
+    async fn test_linked_chats_save_messages() {
+        let updates = Queue::default();
+        let replies = Queue::default();
+        let api_url = spawn_bot_api(updates.clone(), replies.clone()).await;
+        let state = setup_test_state(api_url).await;
+        let bot = state.telegram.clone().unwrap();
+
+        let user = User::new(
+            "capture@example.com".to_string(),
+            "capture".to_string(),
+            "hash".to_string(),
+            "salt".to_string(),
+        );
+        state.storage.create_user(&user).await.unwrap();
+        let (status, Json(code)) = create_link_code(State(state.clone()), auth_user(&user.id))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+
+        updates.lock().unwrap().extend([
+            update(1, "private", "Before linking"),
+            update(
+                2,
+                "private",
+                &format!("/start {}", code.code.to_lowercase()),
+            ),
+            update(3, "group", "Someone else's message"),
+            update(4, "private", "Dear diary"),
+        ]);
+        let offset = poll(&state, &bot, 0).await.unwrap();
+        assert_eq!(offset, 5);
+
+        let texts: Vec<Value> = replies
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|reply| reply["text"].clone())
+            .collect();
+        assert_eq!(texts.len(), 3);
+        assert_eq!(texts[0], LINK_INSTRUCTIONS);
+        assert!(texts[1].as_str().unwrap().starts_with("Linked!"));
+        assert_eq!(texts[2], "Saved.");
+
+        // Updates Telegram sends again aren't saved twice
+        updates
+            .lock()
+            .unwrap()
+            .push(update(4, "private", "Dear diary"));
+        poll(&state, &bot, 4).await.unwrap();
+
+        let messages: Vec<Message> = state
+            .storage
+            .get_messages_for_user(&user.id, None)
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, "Dear diary");
+        assert_eq!(messages[0].created_at.timestamp(), 1_700_000_000);
+
+        // The code is used up, and the bot's username is known by now
+        let Json(link) = get_link(State(state.clone()), auth_user(&user.id))
+            .await
+            .unwrap();
+        assert!(link.linked);
+        let (_, Json(code)) = create_link_code(State(state.clone()), auth_user(&user.id))
+            .await
+            .unwrap();
+        assert_eq!(
+            code.url.unwrap(),
+            format!("https://t.me/dissipate_bot?start={}", code.code)
+        );
+
+        let _ = unlink(State(state.clone()), auth_user(&user.id))
+            .await
+            .unwrap();
+        let result = unlink(State(state), auth_user(&user.id)).await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::NOT_FOUND);
+    }
+}
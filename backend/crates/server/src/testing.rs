@@ -0,0 +1,135 @@
+//! Setup shared by the server's tests and by applications testing against it
+//!
+//! Compiled for this crate's own tests, and for other crates with the
+//! `test-util` feature.
+
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    http::{header, request, Request},
+    response::Response,
+    Router,
+};
+use http_body_util::BodyExt;
+
+use crate::{
+    auth::JwtAuth,
+    backup::BackupStatus,
+    cache::UserCache,
+    db::ConflictPolicy,
+    dormancy::ActivityTracker,
+    export_jobs::ExportJobs,
+    features::Features,
+    handlers::{AppState, SharedState},
+    ids::UserId,
+    maintenance::{CheckpointStatus, MaintenanceMode},
+    models::{Claims, User},
+    notifiers::Notifiers,
+    outbound::OutboundPolicy,
+    ratelimit::RateLimits,
+    realtime::EventHub,
+    storage::MemoryStorage,
+    utils::hash_password,
+    webhooks::Webhooks,
+    AuthUser, DissipateApp,
+};
+
+/// Secret tokens in the test state are signed with
+pub const TEST_JWT_SECRET: &str = "test-secret";
+
+/// Password of users made by [`create_test_user`]
+pub const TEST_PASSWORD: &str = "password123";
+
+/// State over fresh in-memory storage, with no limits and every optional
+/// subsystem off
+///
+/// Fields can be replaced with struct update syntax before wrapping the state
+/// in an `Arc`.
+pub async fn test_app_state() -> AppState {
+    // Test servers listen on loopback
+    let outbound = OutboundPolicy::new(vec!["127.0.0.0/8".parse().unwrap()]);
+    AppState {
+        storage: Arc::new(MemoryStorage::new()),
+        auth: Arc::new(JwtAuth::new(TEST_JWT_SECRET)),
+        conflict_policy: ConflictPolicy::default(),
+        events: EventHub::default(),
+        push: None,
+        mailer: None,
+        s3: None,
+        backups: BackupStatus::default(),
+        users: UserCache::default(),
+        quota_bytes: None,
+        max_messages: None,
+        max_messages_per_day: None,
+        admin_token: None,
+        checkpoints: CheckpointStatus::default(),
+        export_jobs: ExportJobs::default(),
+        rate_limits: RateLimits::default(),
+        maintenance_mode: MaintenanceMode::default(),
+        webhooks: Webhooks::default(),
+        features: Features::default(),
+        notifiers: Notifiers::new(outbound.clone()),
+        telegram: None,
+        inbound_email: None,
+        activity: ActivityTracker::default(),
+        outbound,
+    }
+}
+
+/// The router with default options around [`test_app_state`]
+pub async fn test_app() -> (Router, SharedState) {
+    let state = Arc::new(test_app_state().await);
+    (DissipateApp::builder(state.clone()).build(), state)
+}
+
+/// Store a user with [`TEST_PASSWORD`]
+pub async fn create_test_user(state: &SharedState, email: &str, username: &str) -> User {
+    let (hash, salt) = hash_password(TEST_PASSWORD).unwrap();
+    let user = User::new(email.to_string(), username.to_string(), hash, salt);
+    state.storage.create_user(&user).await.unwrap();
+    user
+}
+
+/// Store `test@example.com` and return their ID and a token for them
+pub async fn create_test_user_and_login(state: &SharedState) -> (UserId, String) {
+    let user = create_test_user(state, "test@example.com", "testuser").await;
+    let token = state.auth.issue_token(&user.id).await.unwrap();
+    (user.id, token)
+}
+
+/// `user_id` signed in, for calling handlers directly
+pub fn auth_user(user_id: &UserId) -> AuthUser {
+    let claims = Claims {
+        user_id: user_id.to_string(),
+        exp: usize::MAX,
+    };
+    AuthUser::new(user_id.clone(), claims)
+}
+
+/// Start a request that carries `token` as a bearer token
+pub fn authed_request(method: &str, uri: &str, token: &str) -> request::Builder {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+}
+
+/// Request with `body` as JSON, carrying `token` as a bearer token
+pub fn authed_json_request(
+    method: &str,
+    uri: &str,
+    token: &str,
+    body: &serde_json::Value,
+) -> Request<Body> {
+    authed_request(method, uri, token)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// Read a response's body as JSON
+pub async fn body_json(response: Response) -> serde_json::Value {
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
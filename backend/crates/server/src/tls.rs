@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+
+use axum_server::tls_rustls::RustlsConfig;
+
+use crate::config::ServerConfig;
+
+/// Certificate and key for serving HTTPS directly
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf first
+    pub cert_path: PathBuf,
+    /// PEM private key
+    pub key_path: PathBuf,
+}
+
+impl TlsConfig {
+    /// Use `tls_cert_file` and `tls_key_file`; TLS is off unless both are set
+    pub fn from_config(server: &ServerConfig) -> Result<Option<Self>, String> {
+        match (&server.tls_cert_file, &server.tls_key_file) {
+            (Some(cert), Some(key)) => Ok(Some(Self {
+                cert_path: PathBuf::from(cert),
+                key_path: PathBuf::from(key),
+            })),
+            (None, None) => Ok(None),
+            _ => Err("TLS_CERT_FILE and TLS_KEY_FILE must be set together".to_string()),
+        }
+    }
+
+    /// Load the certificate and key, failing on missing or malformed files
+    pub async fn load(&self) -> std::io::Result<RustlsConfig> {
+        // Other dependencies also enable rustls' ring backend, so pick it
+        // explicitly rather than let rustls guess. Fails harmlessly if a
+        // provider is already installed.
+        let _ = rustls::crypto::ring::default_provider().install_default();
+
+        RustlsConfig::from_pem_file(&self.cert_path, &self.key_path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_load_rejects_missing_files() {
+        let config = TlsConfig {
+            cert_path: PathBuf::from("/nonexistent/cert.pem"),
+            key_path: PathBuf::from("/nonexistent/key.pem"),
+        };
+
+        assert!(config.load().await.is_err());
+    }
+}
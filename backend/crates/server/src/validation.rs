@@ -0,0 +1,489 @@
+//! Declarative checks on request bodies
+//!
+//! Each request type lists the [`Rule`]s its fields must pass in a
+//! [`Validate`] impl, and handlers call `payload.validate()?` before doing
+//! anything else. The GraphQL and gRPC APIs go through the same handlers, so
+//! they check the same things.
+//!
+//! Every field that fails is reported in the error's `fields`, with the
+//! first one's message as the error message.
+
+use std::fmt;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    models::{
+        AdminCreateUserRequest, AdminResetPasswordRequest, BatchSyncRequest,
+        CreateExportScheduleRequest, CreateMessageRequest, CreateTemplateRequest,
+        InviteMemberRequest, ReactionRequest, RegisterRequest, UpdateDisplayNameRequest,
+        UpdateEmailRequest, UpdateMessageRequest, UpdatePasswordRequest, UpdateTemplateRequest,
+        UpdateUsernameRequest, JOURNAL_ROLE_READ, JOURNAL_ROLE_WRITE,
+    },
+    utils::hashtags,
+};
+
+/// Largest message or template content accepted, in bytes
+pub const MAX_CONTENT_BYTES: usize = 1024 * 1024;
+
+/// Most distinct hashtags in one message or template
+pub const MAX_HASHTAGS: usize = 50;
+
+/// Shortest password accepted, in characters
+pub const MIN_PASSWORD_CHARS: usize = 8;
+
+/// Longest username accepted, in characters
+pub const MAX_USERNAME_CHARS: usize = 32;
+
+/// Longest display name accepted, in characters
+pub const MAX_DISPLAY_NAME_CHARS: usize = 64;
+
+/// Longest template name accepted, in characters
+pub const MAX_TEMPLATE_NAME_CHARS: usize = 100;
+
+/// Longest reaction emoji sequence accepted, in characters
+pub const MAX_EMOJI_CHARS: usize = 16;
+
+/// Longest email address that can be delivered to, in characters
+const MAX_EMAIL_CHARS: usize = 254;
+
+const EMAIL: &[Rule] = &[Rule::Email];
+const USERNAME: &[Rule] = &[
+    Rule::NotBlank,
+    Rule::MaxChars(MAX_USERNAME_CHARS),
+    Rule::UsernameChars,
+];
+// Blank clears the display name, so only the length is checked
+const DISPLAY_NAME: &[Rule] = &[Rule::MaxChars(MAX_DISPLAY_NAME_CHARS)];
+const PASSWORD: &[Rule] = &[Rule::MinChars(MIN_PASSWORD_CHARS)];
+const CONTENT: &[Rule] = &[
+    Rule::NotBlank,
+    Rule::MaxBytes(MAX_CONTENT_BYTES),
+    Rule::MaxHashtags(MAX_HASHTAGS),
+];
+const TEMPLATE_NAME: &[Rule] = &[Rule::NotBlank, Rule::MaxChars(MAX_TEMPLATE_NAME_CHARS)];
+// A template can leave the content to be written from scratch
+const TEMPLATE_CONTENT: &[Rule] = &[
+    Rule::MaxBytes(MAX_CONTENT_BYTES),
+    Rule::MaxHashtags(MAX_HASHTAGS),
+];
+const EMOJI: &[Rule] = &[
+    Rule::NotBlank,
+    Rule::MaxChars(MAX_EMOJI_CHARS),
+    Rule::NoWhitespace,
+];
+const JOURNAL_ROLE: &[Rule] = &[Rule::OneOf(&[JOURNAL_ROLE_READ, JOURNAL_ROLE_WRITE])];
+
+/// A check on a text field
+#[derive(Debug, Clone, Copy)]
+pub enum Rule {
+    /// Not empty or only whitespace
+    NotBlank,
+    /// At least this many characters
+    MinChars(usize),
+    /// At most this many characters
+    MaxChars(usize),
+    /// At most this many bytes of UTF-8
+    MaxBytes(usize),
+    /// No whitespace anywhere
+    NoWhitespace,
+    /// A single `@` between a local part and a domain, without whitespace
+    Email,
+    /// Only letters, digits, `.`, `_` and `-`
+    UsernameChars,
+    /// At most this many distinct `#hashtags`
+    MaxHashtags(usize),
+    /// Exactly one of these values
+    OneOf(&'static [&'static str]),
+}
+
+impl Rule {
+    /// The code and message for `value` breaking this rule, if it does, with
+    /// `label` naming the field
+    fn check(self, label: &str, value: &str) -> Option<(&'static str, String)> {
+        match self {
+            Rule::NotBlank if value.trim().is_empty() => {
+                Some(("required", format!("{} cannot be empty", label)))
+            }
+            Rule::MinChars(min) if value.chars().count() < min => Some((
+                "too_short",
+                format!("{} must be at least {} characters", label, min),
+            )),
+            Rule::MaxChars(max) if value.chars().count() > max => Some((
+                "too_long",
+                format!("{} must be at most {} characters", label, max),
+            )),
+            Rule::MaxBytes(max) if value.len() > max => Some((
+                "too_large",
+                format!("{} must be at most {} bytes", label, max),
+            )),
+            Rule::NoWhitespace if value.chars().any(char::is_whitespace) => {
+                Some(("whitespace", format!("{} cannot contain whitespace", label)))
+            }
+            Rule::Email if !is_email(value) => {
+                Some(("invalid_email", "Invalid email format".to_string()))
+            }
+            Rule::UsernameChars
+                if !value
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, '.' | '_' | '-')) =>
+            {
+                Some((
+                    "invalid_characters",
+                    format!(
+                        "{} can only contain letters, digits, '.', '_' and '-'",
+                        label
+                    ),
+                ))
+            }
+            Rule::MaxHashtags(max) if count_hashtags(value) > max => Some((
+                "too_many_hashtags",
+                format!("{} can have at most {} hashtags", label, max),
+            )),
+            Rule::OneOf(allowed) if !allowed.contains(&value) => {
+                let allowed: Vec<String> = allowed.iter().map(|a| format!("'{}'", a)).collect();
+                Some((
+                    "not_allowed",
+                    format!("{} must be {}", label, allowed.join(" or ")),
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn is_email(value: &str) -> bool {
+    value.chars().count() <= MAX_EMAIL_CHARS
+        && !value.chars().any(char::is_whitespace)
+        && value.split_once('@').is_some_and(|(local, domain)| {
+            !local.is_empty() && !domain.is_empty() && !domain.contains('@')
+        })
+}
+
+fn count_hashtags(content: &str) -> usize {
+    let mut tags: Vec<String> = hashtags(content).collect();
+    tags.sort();
+    tags.dedup();
+    tags.len()
+}
+
+/// A field that broke one of its rules
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct FieldError {
+    /// Path of the field in the request body, such as `creates[0].content`
+    pub field: String,
+    /// Stable identifier of the broken rule, such as `required` or `too_long`
+    pub code: String,
+    pub message: String,
+}
+
+/// Every field of a request that broke a rule, in the order checked
+#[derive(Debug, Default)]
+pub struct ValidationErrors(pub Vec<FieldError>);
+
+impl ValidationErrors {
+    /// Check `value` against `rules` in order, recording the first one it
+    /// breaks under `field`
+    pub fn check(&mut self, field: impl Into<String>, label: &str, value: &str, rules: &[Rule]) {
+        if let Some((code, message)) = rules.iter().find_map(|rule| rule.check(label, value)) {
+            self.reject(field, code, message);
+        }
+    }
+
+    /// Record that `field` broke a rule checked without a [`Rule`], such as
+    /// a range on a number
+    pub fn reject(&mut self, field: impl Into<String>, code: &str, message: impl Into<String>) {
+        self.0.push(FieldError {
+            field: field.into(),
+            code: code.to_string(),
+            message: message.into(),
+        });
+    }
+
+    /// Like [`check`](Self::check), for a field that may be left out
+    pub fn check_optional(
+        &mut self,
+        field: impl Into<String>,
+        label: &str,
+        value: Option<&str>,
+        rules: &[Rule],
+    ) {
+        if let Some(value) = value {
+            self.check(field, label, value, rules);
+        }
+    }
+
+    /// `Ok` when every check passed
+    pub fn into_result(self) -> Result<(), ValidationErrors> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.first() {
+            Some(error) => f.write_str(&error.message),
+            None => f.write_str("Invalid request"),
+        }
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// A request body with rules for its fields
+pub trait Validate {
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
+
+impl Validate for RegisterRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.check("email", "Email", &self.email, EMAIL);
+        errors.check("username", "Username", &self.username, USERNAME);
+        errors.check("password", "Password", &self.password, PASSWORD);
+        errors.into_result()
+    }
+}
+
+impl Validate for AdminCreateUserRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.check("email", "Email", &self.email, EMAIL);
+        errors.check("username", "Username", &self.username, USERNAME);
+        errors.check("password", "Password", &self.password, PASSWORD);
+        errors.into_result()
+    }
+}
+
+impl Validate for AdminResetPasswordRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.check("password", "Password", &self.password, PASSWORD);
+        errors.into_result()
+    }
+}
+
+impl Validate for UpdateEmailRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.check("email", "Email", &self.email, EMAIL);
+        errors.into_result()
+    }
+}
+
+impl Validate for UpdateUsernameRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.check("username", "Username", &self.username, USERNAME);
+        errors.into_result()
+    }
+}
+
+impl Validate for UpdateDisplayNameRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        let display_name = self.display_name.as_deref();
+        errors.check_optional("display_name", "Display name", display_name, DISPLAY_NAME);
+        errors.into_result()
+    }
+}
+
+impl Validate for UpdatePasswordRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.check("new_password", "Password", &self.new_password, PASSWORD);
+        errors.into_result()
+    }
+}
+
+/// Checked after any template has filled in the content
+impl Validate for CreateMessageRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.check("content", "Content", &self.content, CONTENT);
+        errors.into_result()
+    }
+}
+
+impl Validate for UpdateMessageRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.check("content", "Content", &self.content, CONTENT);
+        errors.into_result()
+    }
+}
+
+impl Validate for BatchSyncRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        for (i, create) in self.creates.iter().enumerate() {
+            let field = format!("creates[{}].content", i);
+            errors.check(field, "Content", &create.content, CONTENT);
+        }
+        for (i, update) in self.updates.iter().enumerate() {
+            let field = format!("updates[{}].content", i);
+            errors.check(field, "Content", &update.content, CONTENT);
+        }
+        errors.into_result()
+    }
+}
+
+impl Validate for CreateTemplateRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.check("name", "Template name", &self.name, TEMPLATE_NAME);
+        errors.check(
+            "content",
+            "Template content",
+            &self.content,
+            TEMPLATE_CONTENT,
+        );
+        errors.into_result()
+    }
+}
+
+impl Validate for UpdateTemplateRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.check("name", "Template name", &self.name, TEMPLATE_NAME);
+        errors.check(
+            "content",
+            "Template content",
+            &self.content,
+            TEMPLATE_CONTENT,
+        );
+        errors.into_result()
+    }
+}
+
+impl Validate for InviteMemberRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.check("email", "Email", &self.email, EMAIL);
+        errors.check("role", "Role", &self.role, JOURNAL_ROLE);
+        errors.into_result()
+    }
+}
+
+impl Validate for ReactionRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.check("emoji", "Reaction emoji", &self.emoji, EMOJI);
+        errors.into_result()
+    }
+}
+
+impl Validate for CreateExportScheduleRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.check_optional("email", "Email", self.email.as_deref(), EMAIL);
+        errors.into_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ids::MessageId, models::SyncUpdate};
+
+    fn register(email: &str, username: &str, password: &str) -> RegisterRequest {
+        RegisterRequest {
+            email: email.to_string(),
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_reports_every_failing_field() {
+        let errors = register("not-an-email", "two words", "short")
+            .validate()
+            .unwrap_err();
+
+        let fields: Vec<(&str, &str)> = errors
+            .0
+            .iter()
+            .map(|e| (e.field.as_str(), e.code.as_str()))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("email", "invalid_email"),
+                ("username", "invalid_characters"),
+                ("password", "too_short"),
+            ]
+        );
+        assert_eq!(errors.to_string(), "Invalid email format");
+    }
+
+    #[test]
+    fn test_first_broken_rule_wins() {
+        let errors = register("me@example.com", " ", "password123")
+            .validate()
+            .unwrap_err();
+        assert_eq!(errors.0.len(), 1);
+        assert_eq!(errors.0[0].message, "Username cannot be empty");
+
+        assert!(register("me@example.com", "jo.doe-2", "password123")
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_email() {
+        assert!(is_email("me@example.com"));
+        assert!(!is_email("@example.com"));
+        assert!(!is_email("me@"));
+        assert!(!is_email("me@a@example.com"));
+        assert!(!is_email("me @example.com"));
+    }
+
+    #[test]
+    fn test_batch_fields_are_indexed() {
+        let update = |content: &str| SyncUpdate {
+            id: MessageId::generate(),
+            content: content.to_string(),
+            updated_at: None,
+        };
+        let request = BatchSyncRequest {
+            updates: vec![update("Fine"), update("")],
+            ..Default::default()
+        };
+
+        let errors = request.validate().unwrap_err();
+        assert_eq!(errors.0[0].field, "updates[1].content");
+        assert_eq!(errors.0[0].message, "Content cannot be empty");
+    }
+
+    #[test]
+    fn test_content_limits() {
+        let request = |content: String| UpdateMessageRequest {
+            content,
+            updated_at: None,
+        };
+
+        let hashtags: Vec<String> = (0..=MAX_HASHTAGS).map(|i| format!("#tag{}", i)).collect();
+        let errors = request(hashtags.join(" ")).validate().unwrap_err();
+        assert_eq!(errors.0[0].code, "too_many_hashtags");
+
+        let errors = request("a".repeat(MAX_CONTENT_BYTES + 1))
+            .validate()
+            .unwrap_err();
+        assert_eq!(errors.0[0].code, "too_large");
+    }
+
+    #[test]
+    fn test_one_of_lists_allowed_values() {
+        let request = InviteMemberRequest {
+            email: "friend@example.com".to_string(),
+            role: "owner".to_string(),
+        };
+        let errors = request.validate().unwrap_err();
+        assert_eq!(errors.to_string(), "Role must be 'read' or 'write'");
+    }
+}
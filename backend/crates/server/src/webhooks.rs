@@ -0,0 +1,720 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::{header, redirect};
+use serde_json::json;
+use sha2::Sha256;
+use tokio::{sync::Notify, task::JoinHandle, time::Instant};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    db::DbError,
+    error::AppError,
+    handlers::{storage_error, AppState, SharedState},
+    ids::UserId,
+    models::{
+        CreateWebhookRequest, DueWebhookDelivery, SuccessResponse, Webhook,
+        WebhookDeliveriesResponse, WebhookDelivery, WebhookResponse, WebhooksResponse,
+        WEBHOOK_DELIVERY_DELIVERED, WEBHOOK_DELIVERY_FAILED, WEBHOOK_DELIVERY_PENDING,
+    },
+    realtime::MessageEvent,
+    AuthUser,
+};
+
+pub const EVENT_MESSAGE_CREATED: &str = "message.created";
+pub const EVENT_MESSAGE_UPDATED: &str = "message.updated";
+pub const EVENT_MESSAGE_DELETED: &str = "message.deleted";
+
+/// Header carrying `sha256=` and the hex HMAC-SHA256 of the body, keyed
+/// with the webhook's secret
+pub const SIGNATURE_HEADER: &str = "X-Dissipate-Signature";
+/// Header carrying the event name, e.g. `message.created`
+pub const EVENT_HEADER: &str = "X-Dissipate-Event";
+/// Header carrying the delivery ID, the same on every retry
+pub const DELIVERY_HEADER: &str = "X-Dissipate-Delivery";
+
+/// Webhooks a user can register, so one account can't fan each change out
+/// to an unbounded number of URLs
+const MAX_WEBHOOKS_PER_USER: usize = 10;
+
+/// Attempts at a delivery before it is marked failed
+const MAX_ATTEMPTS: i64 = 8;
+
+/// Longest wait between retries; with `MAX_ATTEMPTS` a delivery is given up
+/// on about a day and a half after the event
+const MAX_RETRY_DELAY_MINUTES: i64 = 12 * 60;
+
+/// How long a receiver has to answer before the attempt counts as failed
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often an idle worker wakes to retry deliveries that have come due
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often old deliveries are pruned from the log
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Deliveries are kept in the log for this long, whatever their outcome
+const DELIVERY_RETENTION_DAYS: i64 = 30;
+
+/// Deliveries attempted per query
+const DELIVERY_BATCH_SIZE: i64 = 50;
+
+/// Deliveries returned by the delivery log endpoint
+const DELIVERY_LOG_LIMIT: i64 = 100;
+
+/// The client POSTing webhooks and the signal that wakes its worker
+#[derive(Debug)]
+pub struct Webhooks {
+    client: reqwest::Client,
+    wake: Notify,
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        // A redirect would turn the POST into a GET, losing the payload
+        let client = reqwest::Client::builder()
+            .redirect(redirect::Policy::none())
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("valid HTTP client configuration");
+
+        Self {
+            client,
+            wake: Notify::new(),
+        }
+    }
+}
+
+impl Webhooks {
+    /// POST a delivery's payload, returning the receiver's HTTP status
+    async fn send(&self, delivery: &DueWebhookDelivery) -> Result<u16, String> {
+        let response = self
+            .client
+            .post(&delivery.url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, &delivery.event)
+            .header(DELIVERY_HEADER, &delivery.id)
+            .header(
+                SIGNATURE_HEADER,
+                sign(&delivery.secret, delivery.payload.as_bytes()),
+            )
+            .body(delivery.payload.clone())
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach receiver: {}", e))?;
+
+        Ok(response.status().as_u16())
+    }
+}
+
+/// Signature of `body` sent in `SIGNATURE_HEADER`
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    let digest: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("sha256={}", digest)
+}
+
+/// Event name and JSON body sent for a message event
+///
+/// `Resync` has no webhook equivalent: batch syncs and imports change too
+/// many messages at once to describe, so they aren't sent.
+fn payload(event: &MessageEvent) -> Option<(&'static str, serde_json::Value)> {
+    let (name, message) = match event {
+        MessageEvent::Created { message } => (EVENT_MESSAGE_CREATED, json!(message)),
+        MessageEvent::Updated { message } => (EVENT_MESSAGE_UPDATED, json!(message)),
+        MessageEvent::Deleted { id } => (EVENT_MESSAGE_DELETED, json!({ "id": id })),
+        MessageEvent::Resync { .. } => return None,
+    };
+
+    Some((
+        name,
+        json!({
+            "event": name,
+            "occurred_at": Utc::now().to_rfc3339(),
+            "message": message,
+        }),
+    ))
+}
+
+/// Queue a message event for each of the user's webhooks
+///
+/// Called once the change has been made, so a failure is logged rather than
+/// failing the request.
+pub async fn dispatch(state: &AppState, user_id: &UserId, event: &MessageEvent) {
+    let Some((name, body)) = payload(event) else {
+        return;
+    };
+
+    match state
+        .storage
+        .queue_webhook_deliveries(user_id, name, &body.to_string())
+        .await
+    {
+        Ok(0) => {}
+        Ok(_) => state.webhooks.wake.notify_one(),
+        Err(e) => tracing::error!("Failed to queue {} webhooks: {}", name, e),
+    }
+}
+
+/// Send queued webhook deliveries in the background, retrying failures with
+/// backoff
+///
+/// Deliveries are sent one at a time, so a slow receiver holds up the rest
+/// for at most `REQUEST_TIMEOUT`.
+pub fn spawn_worker(state: SharedState, shutdown: CancellationToken) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut next_prune = Instant::now();
+
+        loop {
+            if Instant::now() >= next_prune {
+                prune_deliveries(&state).await;
+                next_prune = Instant::now() + PRUNE_INTERVAL;
+            }
+
+            while !shutdown.is_cancelled() {
+                if deliver_due(&state, &shutdown).await < DELIVERY_BATCH_SIZE as usize {
+                    break;
+                }
+            }
+
+            tokio::select! {
+                _ = state.webhooks.wake.notified() => {}
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                _ = shutdown.cancelled() => break,
+            }
+        }
+    })
+}
+
+/// Attempt a batch of due deliveries, returning how many there were
+///
+/// A delivery already being sent is finished on shutdown; the rest wait for
+/// a restart.
+async fn deliver_due(state: &AppState, shutdown: &CancellationToken) -> usize {
+    let deliveries = match state
+        .storage
+        .get_due_webhook_deliveries(Utc::now(), DELIVERY_BATCH_SIZE)
+        .await
+    {
+        Ok(deliveries) => deliveries,
+        Err(e) => {
+            tracing::error!("Failed to load webhook deliveries: {}", e);
+            return 0;
+        }
+    };
+
+    for delivery in &deliveries {
+        if shutdown.is_cancelled() {
+            break;
+        }
+        attempt(state, delivery).await;
+    }
+    deliveries.len()
+}
+
+/// Send one delivery and record the outcome, scheduling a retry on failure
+async fn attempt(state: &AppState, delivery: &DueWebhookDelivery) {
+    // Checked again, since the host may resolve somewhere else by now
+    let sent = match state.outbound.check(&delivery.url).await {
+        Ok(()) => state.webhooks.send(delivery).await,
+        Err(e) => Err(e),
+    };
+    let (response_status, error) = match sent {
+        Ok(status) if (200..300).contains(&status) => (Some(i64::from(status)), None),
+        Ok(status) => (
+            Some(i64::from(status)),
+            Some(format!("Receiver returned {}", status)),
+        ),
+        Err(e) => (None, Some(e)),
+    };
+
+    let attempts = delivery.attempts + 1;
+    let (status, next_attempt_at) = match &error {
+        None => (WEBHOOK_DELIVERY_DELIVERED, None),
+        Some(_) if attempts >= MAX_ATTEMPTS => (WEBHOOK_DELIVERY_FAILED, None),
+        Some(_) => (
+            WEBHOOK_DELIVERY_PENDING,
+            Some(Utc::now() + retry_delay(attempts)),
+        ),
+    };
+    if let Some(error) = &error {
+        tracing::warn!(
+            "Webhook delivery {} attempt {} failed: {}",
+            delivery.id,
+            attempts,
+            error
+        );
+    }
+
+    if let Err(e) = state
+        .storage
+        .record_webhook_attempt(
+            &delivery.id,
+            status,
+            response_status,
+            error.as_deref(),
+            next_attempt_at,
+        )
+        .await
+    {
+        tracing::error!(
+            "Failed to record webhook delivery {} outcome: {}",
+            delivery.id,
+            e
+        );
+    }
+}
+
+/// Wait before the next attempt after `attempts` failed ones: a minute,
+/// then four times longer each time, up to `MAX_RETRY_DELAY_MINUTES`
+fn retry_delay(attempts: i64) -> chrono::Duration {
+    let exponent = (attempts - 1).clamp(0, 10) as u32;
+    chrono::Duration::minutes(4_i64.pow(exponent).min(MAX_RETRY_DELAY_MINUTES))
+}
+
+async fn prune_deliveries(state: &AppState) {
+    let before = Utc::now() - chrono::Duration::days(DELIVERY_RETENTION_DAYS);
+    match state.storage.delete_webhook_deliveries_before(before).await {
+        Ok(0) => {}
+        Ok(count) => tracing::info!("Pruned {} old webhook deliveries", count),
+        Err(e) => tracing::error!("Failed to prune webhook deliveries: {}", e),
+    }
+}
+
+/// GET /api/user/webhooks
+/// List the authenticated user's webhooks
+#[utoipa::path(
+    get,
+    path = "/api/user/webhooks",
+    tag = "webhooks",
+    responses(
+        (status = 200, description = "Registered webhooks", body = WebhooksResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn list_webhooks(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+) -> Result<Json<WebhooksResponse>, AppError> {
+    let webhooks = state
+        .storage
+        .list_webhooks(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    Ok(Json(WebhooksResponse {
+        webhooks: webhooks.iter().map(Webhook::to_response).collect(),
+    }))
+}
+
+/// POST /api/user/webhooks
+/// POST every message change to a URL, signed with a secret
+///
+/// The secret is only returned here, so a generated one must be saved now.
+#[utoipa::path(
+    post,
+    path = "/api/user/webhooks",
+    tag = "webhooks",
+    request_body = CreateWebhookRequest,
+    responses(
+        (status = 201, description = "Webhook created, with its secret", body = WebhookResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn create_webhook(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Json(payload): Json<CreateWebhookRequest>,
+) -> Result<(StatusCode, Json<WebhookResponse>), AppError> {
+    state
+        .outbound
+        .check(&payload.url)
+        .await
+        .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, e))?;
+    if payload
+        .secret
+        .as_deref()
+        .is_some_and(|secret| secret.trim().is_empty())
+    {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Secret cannot be empty",
+        ));
+    }
+
+    let existing = state
+        .storage
+        .list_webhooks(&user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+    if existing.len() >= MAX_WEBHOOKS_PER_USER {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "At most {} webhooks can be registered",
+                MAX_WEBHOOKS_PER_USER
+            ),
+        ));
+    }
+
+    let webhook = Webhook::new(user_id, payload.url, payload.secret);
+    state
+        .storage
+        .create_webhook(&webhook)
+        .await
+        .map_err(|e| storage_error(e, "Failed to create webhook"))?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(WebhookResponse {
+            secret: Some(webhook.secret.clone()),
+            ..webhook.to_response()
+        }),
+    ))
+}
+
+/// DELETE /api/user/webhooks/:id
+/// Stop sending events to a webhook and forget its deliveries
+#[utoipa::path(
+    delete,
+    path = "/api/user/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = String, Path, description = "Webhook ID")),
+    responses(
+        (status = 200, description = "Webhook deleted", body = SuccessResponse),
+        (status = 404, description = "Webhook not found", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn delete_webhook(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Path(webhook_id): Path<String>,
+) -> Result<Json<SuccessResponse>, AppError> {
+    state
+        .storage
+        .delete_webhook(&webhook_id, &user_id)
+        .await
+        .map_err(|e| match e {
+            DbError::WebhookNotFound => AppError::new(StatusCode::NOT_FOUND, "Webhook not found"),
+            _ => storage_error(e, "Failed to delete webhook"),
+        })?;
+
+    Ok(Json(SuccessResponse::new()))
+}
+
+/// GET /api/user/webhooks/:id/deliveries
+/// List a webhook's most recent deliveries, newest first
+#[utoipa::path(
+    get,
+    path = "/api/user/webhooks/{id}/deliveries",
+    tag = "webhooks",
+    params(("id" = String, Path, description = "Webhook ID")),
+    responses(
+        (status = 200, description = "Most recent deliveries, newest first", body = WebhookDeliveriesResponse),
+        (status = 404, description = "Webhook not found", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn list_deliveries(
+    State(state): State<SharedState>,
+    AuthUser { id: user_id, .. }: AuthUser,
+    Path(webhook_id): Path<String>,
+) -> Result<Json<WebhookDeliveriesResponse>, AppError> {
+    state
+        .storage
+        .get_webhook(&webhook_id, &user_id)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "Webhook not found"))?;
+
+    let deliveries = state
+        .storage
+        .list_webhook_deliveries(&webhook_id, DELIVERY_LOG_LIMIT)
+        .await
+        .map_err(|e| storage_error(e, "Database error"))?;
+
+    Ok(Json(WebhookDeliveriesResponse {
+        deliveries: deliveries
+            .iter()
+            .map(WebhookDelivery::to_response)
+            .collect(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::Message,
+        outbound::OutboundPolicy,
+        testing::{auth_user, test_app_state},
+    };
+    use axum::{body::Bytes, http::HeaderMap, routing::post, Router};
+    use std::sync::{Arc, Mutex};
+
+    type Received = Arc<Mutex<Vec<(HeaderMap, Bytes)>>>;
+
+    async fn setup_test_state() -> SharedState {
+        Arc::new(test_app_state().await)
+    }
+
+    async fn create_test_user(state: &SharedState, email: &str) -> crate::models::User {
+        let user = crate::models::User::new(
+            email.to_string(),
+            "testuser".to_string(),
+            "hash".to_string(),
+            "salt".to_string(),
+        );
+        state.storage.create_user(&user).await.unwrap();
+        user
+    }
+
+    /// Serve a receiver answering every POST with `status`, returning its
+    /// URL and the requests it got
+    async fn spawn_receiver(status: StatusCode) -> (String, Received) {
+        let received = Received::default();
+        let app = Router::new().route(
+            "/hook",
+            post({
+                let received = received.clone();
+                move |headers: HeaderMap, body: Bytes| {
+                    received.lock().unwrap().push((headers, body));
+                    async move { status }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}/hook", addr), received)
+    }
+
+    fn webhook_request(url: String, secret: Option<&str>) -> Json<CreateWebhookRequest> {
+        Json(CreateWebhookRequest {
+            url,
+            secret: secret.map(str::to_string),
+        })
+    }
+
+    #[test]
+    fn test_sign() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_retry_delay_backs_off_up_to_a_limit() {
+        assert_eq!(retry_delay(1), chrono::Duration::minutes(1));
+        assert_eq!(retry_delay(2), chrono::Duration::minutes(4));
+        assert_eq!(retry_delay(3), chrono::Duration::minutes(16));
+        assert_eq!(
+            retry_delay(MAX_ATTEMPTS),
+            chrono::Duration::minutes(MAX_RETRY_DELAY_MINUTES)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_message_events_are_delivered_signed() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "hooks@example.com").await;
+        let (url, received) = spawn_receiver(StatusCode::OK).await;
+
+        let (status, Json(webhook)) = create_webhook(
+            State(state.clone()),
+            auth_user(&user.id),
+            webhook_request(url, Some("s3cret")),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(webhook.secret.as_deref(), Some("s3cret"));
+
+        let message = Message::new(user.id.clone(), "Hello".to_string());
+        let created = MessageEvent::Created {
+            message: message.to_response(),
+        };
+        dispatch(&state, &user.id, &created).await;
+        // Too coarse to describe, so not sent
+        dispatch(&state, &user.id, &MessageEvent::Resync { seq: 1 }).await;
+        assert_eq!(deliver_due(&state, &CancellationToken::new()).await, 1);
+
+        {
+            let received = received.lock().unwrap();
+            assert_eq!(received.len(), 1);
+            let (headers, body) = &received[0];
+            assert_eq!(headers[EVENT_HEADER], EVENT_MESSAGE_CREATED);
+            assert_eq!(headers[SIGNATURE_HEADER], sign("s3cret", body));
+            let body: serde_json::Value = serde_json::from_slice(body).unwrap();
+            assert_eq!(body["event"], EVENT_MESSAGE_CREATED);
+            assert_eq!(body["message"]["id"], message.id.as_str());
+        }
+
+        let Json(log) =
+            list_deliveries(State(state.clone()), auth_user(&user.id), Path(webhook.id))
+                .await
+                .unwrap();
+        assert_eq!(log.deliveries.len(), 1);
+        assert_eq!(log.deliveries[0].status, WEBHOOK_DELIVERY_DELIVERED);
+        assert_eq!(log.deliveries[0].attempts, 1);
+        assert_eq!(log.deliveries[0].response_status, Some(200));
+        assert!(log.deliveries[0].next_attempt_at.is_none());
+
+        // The secret is only shown once
+        let Json(listed) = list_webhooks(State(state.clone()), auth_user(&user.id))
+            .await
+            .unwrap();
+        assert_eq!(listed.webhooks.len(), 1);
+        assert!(listed.webhooks[0].secret.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_failed_deliveries_are_retried_later() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "retry@example.com").await;
+        let other = create_test_user(&state, "other@example.com").await;
+        let (url, received) = spawn_receiver(StatusCode::INTERNAL_SERVER_ERROR).await;
+
+        let (_, Json(webhook)) = create_webhook(
+            State(state.clone()),
+            auth_user(&user.id),
+            webhook_request(url, None),
+        )
+        .await
+        .unwrap();
+        assert_eq!(webhook.secret.as_ref().map(String::len), Some(64));
+
+        let deleted = MessageEvent::Deleted {
+            id: "message-1".to_string(),
+        };
+        dispatch(&state, &other.id, &deleted).await;
+        dispatch(&state, &user.id, &deleted).await;
+        assert_eq!(deliver_due(&state, &CancellationToken::new()).await, 1);
+        assert_eq!(received.lock().unwrap().len(), 1);
+
+        // Not due again until the backoff has passed
+        assert_eq!(deliver_due(&state, &CancellationToken::new()).await, 0);
+
+        let Json(log) = list_deliveries(
+            State(state.clone()),
+            auth_user(&user.id),
+            Path(webhook.id.clone()),
+        )
+        .await
+        .unwrap();
+        let delivery = &log.deliveries[0];
+        assert_eq!(delivery.event, EVENT_MESSAGE_DELETED);
+        assert_eq!(delivery.status, WEBHOOK_DELIVERY_PENDING);
+        assert_eq!(delivery.attempts, 1);
+        assert_eq!(delivery.response_status, Some(500));
+        assert!(delivery.error.is_some());
+        assert!(delivery.next_attempt_at.is_some());
+
+        // Other users can neither read nor delete it
+        let status = list_deliveries(
+            State(state.clone()),
+            auth_user(&other.id),
+            Path(webhook.id.clone()),
+        )
+        .await
+        .unwrap_err()
+        .status();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let status = delete_webhook(
+            State(state.clone()),
+            auth_user(&other.id),
+            Path(webhook.id.clone()),
+        )
+        .await
+        .unwrap_err()
+        .status();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let _ = delete_webhook(State(state.clone()), auth_user(&user.id), Path(webhook.id))
+            .await
+            .unwrap();
+        let Json(listed) = list_webhooks(State(state), auth_user(&user.id))
+            .await
+            .unwrap();
+        assert!(listed.webhooks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_create_webhook_rejects_invalid_urls() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "invalid@example.com").await;
+
+        let status = create_webhook(
+            State(state),
+            auth_user(&user.id),
+            webhook_request("ftp://example.com/hook".to_string(), None),
+        )
+        .await
+        .unwrap_err()
+        .status();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_internal_urls_are_not_requested() {
+        let state = setup_test_state().await;
+        let user = create_test_user(&state, "internal@example.com").await;
+
+        let status = create_webhook(
+            State(state.clone()),
+            auth_user(&user.id),
+            webhook_request("http://169.254.169.254/latest".to_string(), None),
+        )
+        .await
+        .unwrap_err()
+        .status();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // A URL allowed when saved is checked again before each delivery
+        let (url, received) = spawn_receiver(StatusCode::OK).await;
+        let (_, Json(webhook)) = create_webhook(
+            State(state.clone()),
+            auth_user(&user.id),
+            webhook_request(url, None),
+        )
+        .await
+        .unwrap();
+        let state = Arc::new(AppState {
+            storage: state.storage.clone(),
+            outbound: OutboundPolicy::default(),
+            ..test_app_state().await
+        });
+        let message = Message::new(user.id.clone(), "Hello".to_string());
+        let created = MessageEvent::Created {
+            message: message.to_response(),
+        };
+        dispatch(&state, &user.id, &created).await;
+        assert_eq!(deliver_due(&state, &CancellationToken::new()).await, 1);
+        assert!(received.lock().unwrap().is_empty());
+
+        let Json(log) = list_deliveries(State(state), auth_user(&user.id), Path(webhook.id))
+            .await
+            .unwrap();
+        assert_eq!(
+            log.deliveries[0].error.as_deref(),
+            Some("URL must not point to a private or local address")
+        );
+    }
+}
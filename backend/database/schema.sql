@@ -1,29 +0,0 @@
--- Enable WAL mode for better concurrency
-PRAGMA journal_mode = WAL;
-
--- Users table
-CREATE TABLE IF NOT EXISTS users (
-    id TEXT PRIMARY KEY,  -- UUID
-    email TEXT UNIQUE NOT NULL,
-    username TEXT NOT NULL,
-    password_hash TEXT NOT NULL,
-    salt TEXT NOT NULL,
-    created_at TEXT NOT NULL,  -- ISO 8601 datetime
-    updated_at TEXT NOT NULL
-);
-
-CREATE INDEX IF NOT EXISTS idx_users_email ON users(email);
-CREATE INDEX IF NOT EXISTS idx_users_username ON users(username);
-
--- Messages table
-CREATE TABLE IF NOT EXISTS messages (
-    id TEXT PRIMARY KEY,  -- UUID
-    user_id TEXT NOT NULL,
-    content TEXT NOT NULL,
-    created_at TEXT NOT NULL,
-    updated_at TEXT NOT NULL,
-    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
-);
-
-CREATE INDEX IF NOT EXISTS idx_messages_user_id ON messages(user_id);
-CREATE INDEX IF NOT EXISTS idx_messages_created_at ON messages(created_at DESC);
@@ -1,3 +0,0 @@
-pub mod db;
-pub mod models;
-pub mod utils;
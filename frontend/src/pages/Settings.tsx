@@ -111,6 +111,22 @@ export const Settings: Component = () => {
         }
     };
 
+    const handleExportHtml = async () => {
+        try {
+            const html = await api.exportHtml();
+            const blob = new Blob([html], { type: 'text/html' });
+            const url = URL.createObjectURL(blob);
+            const a = document.createElement('a');
+            a.href = url;
+            a.download = 'dissipate-export.html';
+            a.click();
+            URL.revokeObjectURL(url);
+            showToast('Export downloaded', 'success');
+        } catch (err) {
+            showToast('Failed to export', 'error');
+        }
+    };
+
     return (
         <div class="settings-page">
             <header class="settings-header">
@@ -208,6 +224,9 @@ export const Settings: Component = () => {
                         <button class="form-button" onClick={handleExportMarkdown}>
                             Export as Markdown
                         </button>
+                        <button class="form-button" onClick={handleExportHtml}>
+                            Export as HTML
+                        </button>
                     </div>
                 </section>
             </main>
@@ -141,4 +141,18 @@ export const api = {
         }
         return response.text();
     },
+
+    async exportHtml(): Promise<string> {
+        const token = getToken();
+        const headers: HeadersInit = {};
+        if (token) {
+            headers['Authorization'] = `Bearer ${token}`;
+        }
+
+        const response = await fetch(`${API_URL}/export/html`, { headers });
+        if (!response.ok) {
+            throw new ApiError(response.statusText, response.status);
+        }
+        return response.text();
+    },
 };